@@ -0,0 +1,39 @@
+/* C API of the raytracing crate, built as a shared library with the `ffi` feature. */
+
+#ifndef RAYTRACING_H
+#define RAYTRACING_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct RtRenderer RtRenderer;
+
+/* Creates a renderer on the highest performance adapter, NULL if there's none. */
+RtRenderer *rt_create(void);
+
+/* Loads a .ron, .json or .pbrt scene and its render settings. Returns 0 on success, -1 on
+ * failure, keeping the previous scene. */
+int rt_load_scene(RtRenderer *renderer, const char *path);
+
+/* Renders width * height tightly packed RGBA8 pixels into out, which has to be exactly
+ * width * height * 4 bytes long. Blocks until done, returns 0 on success and -1 on failure. */
+int rt_render(RtRenderer *renderer, uint32_t width, uint32_t height, uint8_t *out, size_t out_len);
+
+/* Renders width * height tightly packed depths into out for a 0 to 1 depth buffer from near to
+ * far, which can be INFINITY, reversed if reverse_z isn't 0. Blocks until done, returns 0 on
+ * success and -1 on failure. */
+int rt_render_depth(RtRenderer *renderer, uint32_t width, uint32_t height, float near, float far,
+                    int reverse_z, float *out, size_t out_len);
+
+/* Frees a renderer, NULL is ignored. */
+void rt_destroy(RtRenderer *renderer);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,153 @@
+//! Exposure analysis of HDR renders, to judge how bright they are and what clips before they're
+//! tonemapped or quantized: a histogram of the luminance of the pixels in stops and a false
+//! color image of those stops.
+//!
+//! [`time_heatmap`] shows where renders spend their time instead.
+//!
+//! Stops are counted from [middle grey](MIDDLE_GREY), so a well exposed image centers around
+//! zero, and RGBA8 outputs clip at a little less than 2.5 stops above it.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::tile::TileRect;
+
+/// Linear luminance of middle grey, zero stops.
+pub const MIDDLE_GREY: f32 = 0.18;
+
+/// Colors of the stops of false color images below the upper stops, from the bottom up. Stops
+/// past the last are white and pixels with a channel clipping red.
+const FALSE_COLOR_BANDS: [(f32, [u8; 3]); 8] = [
+    (-6.0, [48, 0, 64]),
+    (-4.0, [32, 32, 224]),
+    (-2.0, [0, 144, 224]),
+    (-0.5, [96, 96, 96]),
+    (0.5, [32, 192, 32]),
+    (1.5, [160, 160, 160]),
+    (2.0, [240, 176, 160]),
+    (2.5, [240, 224, 0]),
+];
+
+const CLIPPED_COLOR: [u8; 3] = [224, 0, 0];
+
+/// Luminance of linear Rec. 709 RGB.
+pub fn luminance(rgb: [f32; 3]) -> f32 {
+    0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2]
+}
+
+/// Stops of `luminance` above middle grey, negative below it.
+pub fn stops(luminance: f32) -> f32 {
+    (luminance / MIDDLE_GREY).log2()
+}
+
+/// Luminance of the pixels of an image in stops.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    /// Stops at the bottom of the first bin.
+    pub min_stops: f32,
+    /// Width of every bin in stops.
+    pub bin_stops: f32,
+    /// Pixels in every bin. Ones darker than the first bin are counted in it and brighter
+    /// ones than the last in that.
+    pub counts: Vec<u32>,
+    /// Pixels that are black, and in no bin.
+    pub black: u32,
+    /// Pixels with a color channel at or above one, which clip in RGBA8 images.
+    pub clipped: u32,
+    /// Mean of the stops of the pixels that aren't black, the log-average luminance.
+    pub mean_stops: f32,
+}
+
+impl Histogram {
+    /// Histogram of the tightly packed linear RGBA32F `pixels` in `bins` bins from `min_stops`
+    /// to `max_stops`.
+    pub fn new(pixels: &[f32], min_stops: f32, max_stops: f32, bins: u32) -> Self {
+        let bins = bins.max(1);
+        let bin_stops = (max_stops - min_stops) / bins as f32;
+        let mut histogram = Self {
+            min_stops,
+            bin_stops,
+            counts: vec![0; bins as usize],
+            black: 0,
+            clipped: 0,
+            mean_stops: 0.0,
+        };
+
+        let mut total_stops = 0.0;
+        for pixel in pixels.chunks_exact(4) {
+            let rgb = [pixel[0], pixel[1], pixel[2]];
+            if rgb.iter().any(|&channel| channel >= 1.0) {
+                histogram.clipped += 1;
+            }
+            let luminance = luminance(rgb);
+            if luminance <= 0.0 || luminance.is_nan() {
+                histogram.black += 1;
+                continue;
+            }
+
+            let stops = stops(luminance);
+            total_stops += stops as f64;
+            let bin = ((stops - min_stops) / bin_stops).floor().max(0.0) as usize;
+            histogram.counts[bin.min(bins as usize - 1)] += 1;
+        }
+
+        let lit = pixels.len() / 4 - histogram.black as usize;
+        if lit > 0 {
+            histogram.mean_stops = (total_stops / lit as f64) as f32;
+        }
+        histogram
+    }
+}
+
+/// False color image of the tightly packed linear RGBA32F `pixels` as RGBA8, coloring the
+/// pixels by the band of stops their luminance falls in: purple and blues for the shadows,
+/// green around middle grey, greys, pink and yellow for the highlights and white above them.
+/// Pixels with a color channel that clips in RGBA8 images are red.
+pub fn false_color(pixels: &[f32]) -> Vec<u8> {
+    pixels
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let rgb = [pixel[0], pixel[1], pixel[2]];
+            let stops = stops(luminance(rgb));
+            let [r, g, b] = if rgb.iter().any(|&channel| channel >= 1.0) {
+                CLIPPED_COLOR
+            } else {
+                FALSE_COLOR_BANDS
+                    .iter()
+                    .find(|(upper, _)| stops < *upper)
+                    .map_or([255; 3], |&(_, color)| color)
+            };
+            [r, g, b, 255]
+        })
+        .collect()
+}
+
+/// Heatmap of the `times` tiles of an image `width` by `height` pixels took to render, see
+/// [`RaytracingRenderer::time_tiles`](crate::renderer::RaytracingRenderer::time_tiles), as
+/// RGBA8 pixels. The time per pixel of every tile goes from blue for none through green and
+/// yellow to red for the slowest tile, like the traversal heatmap debug view.
+pub fn time_heatmap(times: &[(TileRect, Duration)], width: u32, height: u32) -> Vec<u8> {
+    let per_pixel =
+        |(rect, time): &(TileRect, Duration)| time.as_secs_f32() / rect.pixel_count().max(1) as f32;
+    let slowest = times.iter().map(per_pixel).fold(0.0, f32::max);
+
+    let mut image = vec![0; width as usize * height as usize * 4];
+    for tile in times {
+        let [r, g, b] = heat(per_pixel(tile) / slowest.max(f32::MIN_POSITIVE));
+        let rect = tile.0;
+        for y in rect.y..rect.y + rect.height {
+            let offset = (y as usize * width as usize + rect.x as usize) * 4;
+            for pixel in image[offset..offset + rect.width as usize * 4].chunks_exact_mut(4) {
+                pixel.copy_from_slice(&[r, g, b, 255]);
+            }
+        }
+    }
+    image
+}
+
+/// Blue through green and yellow to red as `t` goes from 0 to 1, like `heat` in the shaders.
+fn heat(t: f32) -> [u8; 3] {
+    let x = t.clamp(0.0, 1.0) * 3.0;
+    [x - 1.0, x.min(3.0 - x), 1.0 - x].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
@@ -0,0 +1,329 @@
+//! Keyframed animation of cameras and object transforms, and [camera paths](CameraPath) made
+//! into keyframes.
+//!
+//! Keyframes ease into the next one along an [`Easing`] curve. Transforms can also be animated
+//! by [channels](TransformChannels) keyframing their translation, rotation and scale
+//! separately, the way glTF animations are, with rotations [interpolated](Orientation) along
+//! the shortest arc.
+
+#[cfg(feature = "fs")]
+use std::{fs, path::Path};
+
+use cgmath::{InnerSpace, Matrix3, Quaternion, Rad, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::scene::{self, Camera, Transform};
+#[cfg(feature = "fs")]
+use crate::scene::{SceneError, SceneFormat};
+
+/// Value of an animated property at a point in time, in seconds.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe<T> {
+    pub time: f32,
+    pub value: T,
+    /// How the value moves from this keyframe to the next.
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+/// Curves values follow from one keyframe to the next, mapping the fraction of the time between
+/// them that passed to the fraction of the way to the next value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Easing {
+    /// Constant speed.
+    #[default]
+    Linear,
+    /// Holds the value until the next keyframe, like glTF's `STEP` interpolation.
+    Step,
+    /// Starts slowly and speeds up.
+    EaseIn,
+    /// Starts fast and slows down.
+    EaseOut,
+    /// Starts and ends slowly.
+    EaseInOut,
+    /// CSS style cubic Bézier curve from (0, 0) to (1, 1) with the control points `[x1, y1, x2,
+    /// y2]`, the x coordinates clamped to [0, 1].
+    CubicBezier([f32; 4]),
+}
+
+impl Easing {
+    /// Fraction of the way to the next value after a fraction `t` of the time.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => t,
+            Self::Step => 0.0,
+            Self::EaseIn => t * t * t,
+            Self::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Self::EaseInOut => t * t * (3.0 - 2.0 * t),
+            Self::CubicBezier([x1, y1, x2, y2]) => {
+                let bezier = |a: f32, b: f32, s: f32| {
+                    3.0 * (1.0 - s) * (1.0 - s) * s * a + 3.0 * (1.0 - s) * s * s * b + s * s * s
+                };
+                let (x1, x2) = (x1.clamp(0.0, 1.0), x2.clamp(0.0, 1.0));
+                // x grows monotonically with clamped control points, bisect for the s at t.
+                let (mut low, mut high) = (0.0, 1.0);
+                for _ in 0..24 {
+                    let s = (low + high) / 2.0;
+                    if bezier(x1, x2, s) < t {
+                        low = s;
+                    } else {
+                        high = s;
+                    }
+                }
+                bezier(y1, y2, (low + high) / 2.0)
+            }
+        }
+    }
+}
+
+/// Values that can be blended between keyframes.
+pub trait Interpolate: Clone {
+    /// Value a fraction `t` of the way from `self` to `other`.
+    fn interpolate(&self, other: &Self, t: f32) -> Self;
+}
+
+/// Value of the animation at `time`, interpolated between the surrounding keyframes along the
+/// [easing](Keyframe::easing) of the earlier one and held before the first and after the last
+/// one.
+///
+/// `keyframes` don't have to be sorted. Returns `None` if there aren't any.
+pub fn sample<T: Interpolate>(keyframes: &[Keyframe<T>], time: f32) -> Option<T> {
+    let before = keyframes
+        .iter()
+        .filter(|keyframe| keyframe.time <= time)
+        .max_by(|a, b| a.time.total_cmp(&b.time));
+    let after = keyframes
+        .iter()
+        .filter(|keyframe| keyframe.time > time)
+        .min_by(|a, b| a.time.total_cmp(&b.time));
+
+    match (before, after) {
+        (Some(before), Some(after)) => {
+            let t = before
+                .easing
+                .apply((time - before.time) / (after.time - before.time));
+            Some(before.value.interpolate(&after.value, t))
+        }
+        (Some(keyframe), None) | (None, Some(keyframe)) => Some(keyframe.value.clone()),
+        (None, None) => None,
+    }
+}
+
+/// Rotation as a unit quaternion `[x, y, z, w]`, the way glTF stores them, interpolated by
+/// spherical linear interpolation along the shortest arc.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Orientation(pub [f32; 4]);
+
+impl Orientation {
+    pub fn quaternion(self) -> Quaternion<f32> {
+        let [x, y, z, w] = self.0;
+        Quaternion::new(w, x, y, z)
+    }
+}
+
+impl From<Quaternion<f32>> for Orientation {
+    fn from(quaternion: Quaternion<f32>) -> Self {
+        let Quaternion { s, v } = quaternion;
+        Self([v.x, v.y, v.z, s])
+    }
+}
+
+/// Translation, rotation and scale of a transform keyframed separately, like the channels of
+/// glTF animations, each with keyframes at times of its own. The parts with keyframes replace
+/// those of the transform, see [`Scene::at`](scene::Scene::at).
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransformChannels {
+    pub translation: Vec<Keyframe<[f32; 3]>>,
+    pub rotation: Vec<Keyframe<Orientation>>,
+    pub scale: Vec<Keyframe<[f32; 3]>>,
+}
+
+impl TransformChannels {
+    pub fn is_empty(&self) -> bool {
+        self.translation.is_empty() && self.rotation.is_empty() && self.scale.is_empty()
+    }
+
+    /// Replaces the parts of `transform` that have keyframes with their values at `time`.
+    pub fn apply(&self, transform: &mut Transform, time: f32) {
+        if let Some(translation) = sample(&self.translation, time) {
+            transform.translation = translation;
+        }
+        if let Some(orientation) = sample(&self.rotation, time) {
+            transform.orientation = Some(orientation.0);
+        }
+        if let Some(scale) = sample(&self.scale, time) {
+            transform.scale = scale;
+        }
+    }
+}
+
+/// Times of the frames of an animation running from `start` up to, but excluding, `end` at
+/// `fps` frames per second.
+pub fn frame_times(start: f32, end: f32, fps: f32) -> impl Iterator<Item = f32> {
+    let count = ((end - start) * fps).ceil().max(0.0) as u32;
+    (0..count).map(move |frame| start + frame as f32 / fps)
+}
+
+/// Common camera moves, made into keyframes of a camera by [`CameraPath::keyframes`], e.g. for
+/// the turntables of product visualization.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CameraPath {
+    /// Orbit of the camera around the point it looks at, turning counterclockwise around its
+    /// up direction and keeping its distance and height.
+    Turntable {
+        /// Seconds the orbit takes.
+        duration: f32,
+        /// Times the camera goes around, once for a turntable that loops.
+        #[serde(default = "scene::one")]
+        turns: f32,
+    },
+    /// Straight move of the camera along `offset`, looking the same way all along.
+    Dolly {
+        /// Seconds the move takes.
+        duration: f32,
+        offset: [f32; 3],
+    },
+}
+
+impl CameraPath {
+    /// Seconds the path takes.
+    pub fn duration(&self) -> f32 {
+        match *self {
+            Self::Turntable { duration, .. } | Self::Dolly { duration, .. } => duration,
+        }
+    }
+
+    /// Keyframes of `camera` following the path from `start` seconds on. Orbits get one at
+    /// every frame at `fps` frames per second rendered from `start`, so that the frames stay on
+    /// the circle rather than on the chords between the keyframes.
+    pub fn keyframes(&self, camera: &Camera, start: f32, fps: f32) -> Vec<Keyframe<Camera>> {
+        let duration = self.duration().max(0.0);
+        let keyframe = |time: f32, camera| Keyframe {
+            time: start + time,
+            value: camera,
+            easing: Easing::Linear,
+        };
+        match *self {
+            Self::Turntable { turns, .. } => {
+                let up = Vector3::from(camera.up).normalize();
+                let target = Vector3::from(camera.look_at);
+                let offset = Vector3::from(camera.position) - target;
+                let frames = if fps > 0.0 {
+                    (duration * fps).ceil().max(1.0) as u32
+                } else {
+                    1
+                };
+                (0..=frames)
+                    .map(|frame| {
+                        let time = match frame {
+                            0 => 0.0,
+                            _ if frame == frames => duration,
+                            _ => frame as f32 / fps,
+                        };
+                        let fraction = if duration > 0.0 { time / duration } else { 1.0 };
+                        let angle = Rad(std::f32::consts::TAU * turns * fraction);
+                        let position = target + Matrix3::from_axis_angle(up, angle) * offset;
+                        let camera = Camera {
+                            position: position.into(),
+                            ..camera.clone()
+                        };
+                        keyframe(time, camera)
+                    })
+                    .collect()
+            }
+            Self::Dolly { offset, .. } => {
+                let moved = Camera {
+                    position: [0, 1, 2].map(|i| camera.position[i] + offset[i]),
+                    look_at: [0, 1, 2].map(|i| camera.look_at[i] + offset[i]),
+                    ..camera.clone()
+                };
+                vec![keyframe(0.0, camera.clone()), keyframe(duration, moved)]
+            }
+        }
+    }
+}
+
+/// Reads camera keyframes from a file in the format implied by its extension, e.g. one written
+/// by [`save_camera_keyframes`] to render the same camera move in other scenes.
+#[cfg(feature = "fs")]
+pub fn load_camera_keyframes(path: &Path) -> Result<Vec<Keyframe<Camera>>, SceneError> {
+    let format =
+        SceneFormat::from_path(path).ok_or_else(|| SceneError::UnknownFormat(path.to_owned()))?;
+    let source = fs::read_to_string(path)?;
+    Ok(match format {
+        SceneFormat::Ron => ron::from_str(&source)?,
+        SceneFormat::Json => serde_json::from_str(&source)?,
+    })
+}
+
+/// Writes camera keyframes to a file in the format implied by its extension.
+#[cfg(feature = "fs")]
+pub fn save_camera_keyframes(
+    path: &Path,
+    keyframes: &[Keyframe<Camera>],
+) -> Result<(), SceneError> {
+    let format =
+        SceneFormat::from_path(path).ok_or_else(|| SceneError::UnknownFormat(path.to_owned()))?;
+    let source = match format {
+        SceneFormat::Ron => ron::ser::to_string_pretty(keyframes, Default::default())?,
+        SceneFormat::Json => serde_json::to_string_pretty(keyframes)?,
+    };
+    fs::write(path, source)?;
+    Ok(())
+}
+
+impl Interpolate for f32 {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for [f32; 3] {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        [0, 1, 2].map(|i| self[i].interpolate(&other[i], t))
+    }
+}
+
+impl Interpolate for Camera {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        Self {
+            position: self.position.interpolate(&other.position, t),
+            look_at: self.look_at.interpolate(&other.look_at, t),
+            up: self.up.interpolate(&other.up, t),
+            fov: self.fov.interpolate(&other.fov, t),
+            shift: [0, 1].map(|i| self.shift[i].interpolate(&other.shift[i], t)),
+        }
+    }
+}
+
+impl Interpolate for Orientation {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        let (from, to) = (self.quaternion(), other.quaternion());
+        from.normalize().slerp(to.normalize(), t).into()
+    }
+}
+
+/// Rotations in Euler angles are interpolated angle by angle, so that they can turn more than
+/// half a turn between keyframes, and along the shortest arc if either keyframe has an
+/// [orientation](Transform::orientation).
+impl Interpolate for Transform {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        let (rotation, orientation) = match (self.orientation, other.orientation) {
+            (None, None) => (self.rotation.interpolate(&other.rotation, t), None),
+            _ => {
+                let from = Orientation::from(self.quaternion());
+                let to = Orientation::from(other.quaternion());
+                (self.rotation, Some(from.interpolate(&to, t).0))
+            }
+        };
+        Self {
+            translation: self.translation.interpolate(&other.translation, t),
+            rotation,
+            orientation,
+            scale: self.scale.interpolate(&other.scale, t),
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! Benchmarks of the renderer on procedural scenes stressing different parts of it, to compare
+//! adapters, settings and changes to the shaders.
+
+use std::f32::consts::TAU;
+
+use instant::{Duration, Instant};
+
+use crate::{
+    renderer::RaytracingRenderer,
+    scene::{Camera, Material, Object, Scene, Shape},
+    scenes::{self, hash},
+    settings::RenderSettings,
+};
+
+/// Built-in scene rendered by [`run`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BenchScene {
+    /// [`scenes::cornell_box`]: few primitives with long, incoherent paths bouncing around the
+    /// room.
+    CornellBox,
+    /// A thousand spheres of assorted materials on a plane, for deep BVHs over analytic
+    /// primitives and divergent shading.
+    SphereField,
+    /// A single torus knot of half a million smooth triangles, for traversal of a dense mesh.
+    HighPolyMesh,
+}
+
+impl BenchScene {
+    pub const ALL: [Self; 3] = [Self::CornellBox, Self::SphereField, Self::HighPolyMesh];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::CornellBox => "cornell-box",
+            Self::SphereField => "sphere-field",
+            Self::HighPolyMesh => "high-poly-mesh",
+        }
+    }
+
+    pub fn scene(self) -> Scene {
+        match self {
+            Self::CornellBox => scenes::cornell_box(),
+            Self::SphereField => sphere_field(),
+            Self::HighPolyMesh => high_poly_mesh(),
+        }
+    }
+}
+
+/// Settings the scenes are benchmarked with unless others are given.
+pub fn settings() -> RenderSettings {
+    RenderSettings {
+        width: 512,
+        height: 512,
+        samples_per_pixel: 16,
+        max_bounces: 8,
+        ..Default::default()
+    }
+}
+
+/// Times of the renders of a scene.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BenchResult {
+    pub scene: BenchScene,
+    /// Time of every timed render, in the order they were made.
+    pub frame_times: Vec<Duration>,
+    /// Camera rays of every render, one per sample of every pixel.
+    pub camera_rays: u64,
+}
+
+impl BenchResult {
+    pub fn mean(&self) -> Duration {
+        self.frame_times.iter().sum::<Duration>() / self.frame_times.len().max(1) as u32
+    }
+
+    pub fn fastest(&self) -> Duration {
+        self.frame_times.iter().copied().min().unwrap_or_default()
+    }
+
+    pub fn slowest(&self) -> Duration {
+        self.frame_times.iter().copied().max().unwrap_or_default()
+    }
+
+    /// Millions of camera rays traced per second on average. Each of them starts a path, whose
+    /// bounces and shadow rays aren't counted, so scenes with longer paths score lower.
+    pub fn mrays_per_second(&self) -> f64 {
+        self.camera_rays as f64 / self.mean().as_secs_f64().max(f64::MIN_POSITIVE) / 1.0e6
+    }
+}
+
+/// Renders `scene` with `settings` `warmup` times untimed, to compile pipelines and fill caches,
+/// then `iterations` times timed. The scene of `renderer` is replaced.
+pub async fn run(
+    renderer: &mut RaytracingRenderer,
+    scene: BenchScene,
+    settings: &RenderSettings,
+    warmup: u32,
+    iterations: u32,
+) -> BenchResult {
+    renderer
+        .set_scene(&scene.scene())
+        .expect("Built-in scenes are valid");
+    for _ in 0..warmup {
+        renderer.render(settings).await;
+    }
+
+    let mut frame_times = Vec::with_capacity(iterations as usize);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        renderer.render(settings).await;
+        frame_times.push(start.elapsed());
+    }
+    let result = BenchResult {
+        scene,
+        frame_times,
+        camera_rays: settings.width as u64
+            * settings.height as u64
+            * settings.samples_per_pixel as u64,
+    };
+    tracing::info!(
+        scene = scene.name(),
+        mean = ?result.mean(),
+        mrays_per_second = result.mrays_per_second(),
+        "Benchmarked scene"
+    );
+    result
+}
+
+fn sphere_field() -> Scene {
+    const SIDE: i32 = 32;
+    let materials = vec![
+        Material {
+            name: "diffuse".to_owned(),
+            base_color: [0.6, 0.3, 0.2],
+            ..Default::default()
+        },
+        Material {
+            name: "metal".to_owned(),
+            base_color: [0.8, 0.8, 0.7],
+            metallic: 1.0,
+            roughness: 0.2,
+            ..Default::default()
+        },
+        Material {
+            name: "glass".to_owned(),
+            base_color: [1.0, 1.0, 1.0],
+            roughness: 0.0,
+            transmission: 1.0,
+            ..Default::default()
+        },
+        Material {
+            name: "ground".to_owned(),
+            base_color: [0.5, 0.5, 0.5],
+            ..Default::default()
+        },
+    ];
+
+    let mut objects = Vec::new();
+    for z in 0..SIDE {
+        for x in 0..SIDE {
+            let i = (z * SIDE + x) as u32;
+            let radius = 0.15 + 0.15 * hash(i * 4);
+            let center = [
+                (x - SIDE / 2) as f32 + 0.5 * hash(i * 4 + 1),
+                radius,
+                -(z as f32) - 0.5 * hash(i * 4 + 2),
+            ];
+            let material = &materials[(hash(i * 4 + 3) * 3.0) as usize % 3];
+            objects.push(Object {
+                material: Some(material.name.clone()),
+                ..Object::new(Shape::Sphere { center, radius })
+            });
+        }
+    }
+    objects.push(Object {
+        name: "ground".to_owned(),
+        material: Some("ground".to_owned()),
+        ..Object::new(Shape::Sphere {
+            center: [0.0, -1000.0, 0.0],
+            radius: 1000.0,
+        })
+    });
+
+    Scene {
+        camera: Camera {
+            position: [0.0, 3.0, 4.0],
+            look_at: [0.0, 0.0, -8.0],
+            fov: 60.0,
+            ..Default::default()
+        },
+        materials,
+        objects,
+        ..Default::default()
+    }
+}
+
+fn high_poly_mesh() -> Scene {
+    // A (3, 2) torus knot swept by a tube, RINGS rings of SEGMENTS vertices.
+    const RINGS: u32 = 2048;
+    const SEGMENTS: u32 = 128;
+    const TUBE: f32 = 0.15;
+    let knot = |t: f32| {
+        let r = 2.0 + (2.0 * t).cos();
+        [r * (3.0 * t).cos(), r * (3.0 * t).sin(), -(2.0 * t).sin()]
+    };
+    let sub = |a: [f32; 3], b: [f32; 3]| [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+    let cross = |a: [f32; 3], b: [f32; 3]| {
+        [
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ]
+    };
+    let normalize = |a: [f32; 3]| {
+        let length = (a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt();
+        a.map(|c| c / length)
+    };
+
+    let mut positions = Vec::with_capacity((RINGS * SEGMENTS) as usize);
+    let mut normals = Vec::with_capacity((RINGS * SEGMENTS) as usize);
+    for ring in 0..RINGS {
+        let t = ring as f32 / RINGS as f32 * TAU;
+        let center = knot(t);
+        let tangent = normalize(sub(knot(t + 1.0e-3), center));
+        let side = normalize(cross(tangent, [0.0, 0.0, 1.0]));
+        let up = cross(side, tangent);
+        for segment in 0..SEGMENTS {
+            let angle = segment as f32 / SEGMENTS as f32 * TAU;
+            let normal = [0, 1, 2].map(|c| angle.cos() * side[c] + angle.sin() * up[c]);
+            positions.push([0, 1, 2].map(|c| center[c] + TUBE * normal[c]));
+            normals.push(normal);
+        }
+    }
+    let mut indices = Vec::with_capacity((RINGS * SEGMENTS * 6) as usize);
+    for ring in 0..RINGS {
+        for segment in 0..SEGMENTS {
+            let vertex = |r: u32, s: u32| (r % RINGS) * SEGMENTS + s % SEGMENTS;
+            let [a, b] = [vertex(ring, segment), vertex(ring, segment + 1)];
+            let [c, d] = [vertex(ring + 1, segment), vertex(ring + 1, segment + 1)];
+            indices.extend([a, c, b, b, c, d]);
+        }
+    }
+
+    Scene {
+        camera: Camera {
+            position: [0.0, 0.0, 8.0],
+            look_at: [0.0, 0.0, 0.0],
+            fov: 50.0,
+            ..Default::default()
+        },
+        materials: vec![Material {
+            name: "copper".to_owned(),
+            base_color: [0.95, 0.64, 0.54],
+            metallic: 1.0,
+            roughness: 0.3,
+            ..Default::default()
+        }],
+        objects: vec![Object {
+            name: "knot".to_owned(),
+            material: Some("copper".to_owned()),
+            ..Object::new(Shape::Triangles {
+                positions,
+                normals,
+                texcoords: Vec::new(),
+                indices,
+            })
+        }],
+        ..Default::default()
+    }
+}
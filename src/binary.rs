@@ -0,0 +1,45 @@
+//! Helpers for the little-endian binary formats of checkpoints and the [render farm](crate::farm)
+//! protocol.
+
+use std::io::{self, Read, Write};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+pub(crate) fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub(crate) fn read_bytes(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+
+    let len = u64::from_le_bytes(len);
+
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+/// Writes `bytes` prefixed by their length, as read by [`read_bytes`].
+pub(crate) fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+/// Writes `value` as length prefixed JSON, as read by [`read_json`].
+pub(crate) fn write_json(writer: &mut impl Write, value: &impl Serialize) -> io::Result<()> {
+    write_bytes(writer, &serde_json::to_vec(value)?)
+}
+
+pub(crate) fn read_json<T: DeserializeOwned>(reader: &mut impl Read) -> io::Result<T> {
+    serde_json::from_slice(&read_bytes(reader)?).map_err(|err| invalid_data(err.to_string()))
+}
+
+pub(crate) fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
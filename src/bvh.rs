@@ -0,0 +1,259 @@
+//! Bounding volume hierarchy over the primitives of a scene, built on the host and traversed by
+//! the shaders.
+
+use zerocopy::AsBytes;
+
+/// Primitives a leaf holds at most, unless they can't be told apart by their centroids.
+const MAX_LEAF_SIZE: usize = 4;
+
+/// Depth below which nodes are never split, keeping traversal within the shaders' fixed size
+/// stack.
+pub(crate) const MAX_DEPTH: usize = 60;
+
+/// Buckets centroids are sorted into when looking for the cheapest split of a node.
+const SAH_BINS: usize = 12;
+
+/// Axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Aabb {
+    /// Box containing nothing, the identity of [`union`](Self::union).
+    pub const EMPTY: Self = Self {
+        min: [f32::INFINITY; 3],
+        max: [f32::NEG_INFINITY; 3],
+    };
+
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a [f32; 3]>) -> Self {
+        points
+            .into_iter()
+            .fold(Self::EMPTY, |aabb, point| aabb.union(&Self::point(*point)))
+    }
+
+    fn point(point: [f32; 3]) -> Self {
+        Self {
+            min: point,
+            max: point,
+        }
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: [0, 1, 2].map(|i| self.min[i].min(other.min[i])),
+            max: [0, 1, 2].map(|i| self.max[i].max(other.max[i])),
+        }
+    }
+
+    pub fn centroid(&self) -> [f32; 3] {
+        [0, 1, 2].map(|i| (self.min[i] + self.max[i]) * 0.5)
+    }
+
+    fn is_empty(&self) -> bool {
+        (0..3).any(|i| self.min[i] > self.max[i])
+    }
+
+    fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let [x, y, z] = [0, 1, 2].map(|i| self.max[i] - self.min[i]);
+        2.0 * (x * y + y * z + z * x)
+    }
+}
+
+/// Mirrors `BvhNode` in the shaders.
+///
+/// Leaves hold `count` primitives starting at `left_or_first`, inner nodes have a zero count
+/// and their children at `left_or_first` and the index right after it.
+#[derive(AsBytes, Clone, Copy, Debug)]
+#[repr(C)]
+pub(crate) struct BvhNode {
+    pub min: [f32; 3],
+    pub left_or_first: u32,
+    pub max: [f32; 3],
+    pub count: u32,
+}
+
+impl BvhNode {
+    fn new(aabb: Aabb, left_or_first: u32, count: u32) -> Self {
+        Self {
+            min: aabb.min,
+            left_or_first,
+            max: aabb.max,
+            count,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Bvh {
+    /// Nodes in depth-first order, the root first.
+    pub nodes: Vec<BvhNode>,
+    /// Order primitives have to be stored in for the ranges of the leaves to refer to them.
+    pub order: Vec<u32>,
+}
+
+impl Bvh {
+    /// Builds a hierarchy over primitives with the bounds in `aabbs`, splitting nodes where the
+    /// surface area heuristic estimates traversal to be cheapest.
+    pub fn build(aabbs: &[Aabb]) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(aabbs.len().max(1) * 2),
+            order: (0..aabbs.len() as u32).collect(),
+        };
+
+        let bounds = aabbs.iter().fold(Aabb::EMPTY, |sum, aabb| sum.union(aabb));
+        bvh.nodes.push(BvhNode::new(bounds, 0, aabbs.len() as u32));
+        if !aabbs.is_empty() {
+            bvh.subdivide(0, aabbs, 0);
+        }
+
+        bvh
+    }
+
+    /// Recomputes the bounds of the nodes for primitives that moved to `aabbs`, keeping which
+    /// primitives every node holds. Much faster than building a new hierarchy, but traversal
+    /// slows down the further the primitives get from where they were when it was built.
+    pub fn refit(&mut self, aabbs: &[Aabb]) {
+        if aabbs.is_empty() {
+            return;
+        }
+
+        // Children always come after their parents, so going backwards visits them first.
+        for index in (0..self.nodes.len()).rev() {
+            let node = self.nodes[index];
+            let first = node.left_or_first as usize;
+            let bounds = if node.count > 0 {
+                self.order[first..first + node.count as usize]
+                    .iter()
+                    .fold(Aabb::EMPTY, |sum, &index| sum.union(&aabbs[index as usize]))
+            } else {
+                let [left, right] = [first, first + 1].map(|child| Aabb {
+                    min: self.nodes[child].min,
+                    max: self.nodes[child].max,
+                });
+                left.union(&right)
+            };
+            self.nodes[index].min = bounds.min;
+            self.nodes[index].max = bounds.max;
+        }
+    }
+
+    /// Surface area of all the nodes relative to the root's, proportional to the expected
+    /// number of nodes a ray visits.
+    pub fn cost(&self) -> f32 {
+        let area = |node: &BvhNode| {
+            Aabb {
+                min: node.min,
+                max: node.max,
+            }
+            .surface_area()
+        };
+        let root = area(&self.nodes[0]);
+        if root == 0.0 {
+            return 0.0;
+        }
+        self.nodes.iter().map(area).sum::<f32>() / root
+    }
+
+    fn subdivide(&mut self, node: usize, aabbs: &[Aabb], depth: usize) {
+        let first = self.nodes[node].left_or_first as usize;
+        let count = self.nodes[node].count as usize;
+        if count <= MAX_LEAF_SIZE || depth >= MAX_DEPTH {
+            return;
+        }
+
+        let primitives = &mut self.order[first..first + count];
+        let Some((axis, split)) = find_split(primitives, aabbs) else {
+            return;
+        };
+
+        // Partition the primitives around the split plane.
+        let mut left_count = 0;
+        for i in 0..primitives.len() {
+            if aabbs[primitives[i] as usize].centroid()[axis] < split {
+                primitives.swap(i, left_count);
+                left_count += 1;
+            }
+        }
+        if left_count == 0 || left_count == count {
+            return;
+        }
+
+        let left = self.nodes.len();
+        for (start, len) in [
+            (first, left_count),
+            (first + left_count, count - left_count),
+        ] {
+            let bounds = self.order[start..start + len]
+                .iter()
+                .fold(Aabb::EMPTY, |sum, &index| sum.union(&aabbs[index as usize]));
+            self.nodes
+                .push(BvhNode::new(bounds, start as u32, len as u32));
+        }
+
+        self.nodes[node].left_or_first = left as u32;
+        self.nodes[node].count = 0;
+
+        self.subdivide(left, aabbs, depth + 1);
+        self.subdivide(left + 1, aabbs, depth + 1);
+    }
+}
+
+/// Axis and position of the split plane with the lowest surface area heuristic cost, if it's
+/// cheaper than keeping `primitives` in a single leaf.
+fn find_split(primitives: &[u32], aabbs: &[Aabb]) -> Option<(usize, f32)> {
+    let centroids = primitives
+        .iter()
+        .map(|&index| Aabb::point(aabbs[index as usize].centroid()))
+        .fold(Aabb::EMPTY, |sum, centroid| sum.union(&centroid));
+
+    let leaf_cost = primitives
+        .iter()
+        .fold(Aabb::EMPTY, |sum, &index| sum.union(&aabbs[index as usize]))
+        .surface_area()
+        * primitives.len() as f32;
+
+    let mut best: Option<(usize, f32, f32)> = None;
+
+    for axis in 0..3 {
+        let (low, high) = (centroids.min[axis], centroids.max[axis]);
+        if high <= low {
+            continue;
+        }
+
+        let mut bins = [(Aabb::EMPTY, 0usize); SAH_BINS];
+        let scale = SAH_BINS as f32 / (high - low);
+        for &index in primitives {
+            let aabb = &aabbs[index as usize];
+            let bin = (((aabb.centroid()[axis] - low) * scale) as usize).min(SAH_BINS - 1);
+            bins[bin].0 = bins[bin].0.union(aabb);
+            bins[bin].1 += 1;
+        }
+
+        // Costs of putting the first `i + 1` bins on the left of the plane.
+        let mut left_costs = [0.0; SAH_BINS - 1];
+        let (mut bounds, mut count) = (Aabb::EMPTY, 0);
+        for (i, cost) in left_costs.iter_mut().enumerate() {
+            bounds = bounds.union(&bins[i].0);
+            count += bins[i].1;
+            *cost = bounds.surface_area() * count as f32;
+        }
+
+        let (mut bounds, mut count) = (Aabb::EMPTY, 0);
+        for i in (1..SAH_BINS).rev() {
+            bounds = bounds.union(&bins[i].0);
+            count += bins[i].1;
+            let cost = left_costs[i - 1] + bounds.surface_area() * count as f32;
+            if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                best = Some((axis, low + i as f32 / scale, cost));
+            }
+        }
+    }
+
+    best.filter(|&(_, _, cost)| cost < leaf_cost)
+        .map(|(axis, split, _)| (axis, split))
+}
@@ -0,0 +1,41 @@
+use std::sync::Mutex;
+
+use renderdoc::{RenderDoc, V141};
+
+/// Handle to the RenderDoc in-application API.
+///
+/// RenderDoc has to be loaded before the device is created for its hooks to see it, so this is
+/// obtained once when the renderer is constructed and kept around for its whole lifetime.
+pub(crate) struct RenderDocCapture {
+    api: Mutex<RenderDoc<V141>>,
+}
+
+impl RenderDocCapture {
+    /// Loads the RenderDoc API, returning `None` when the application isn't running under
+    /// RenderDoc (or the library can't be found).
+    pub(crate) fn load() -> Option<Self> {
+        match RenderDoc::new() {
+            Ok(api) => Some(Self {
+                api: Mutex::new(api),
+            }),
+            Err(err) => {
+                tracing::debug!(%err, "RenderDoc API unavailable, frame captures are disabled");
+                None
+            }
+        }
+    }
+
+    pub(crate) fn start(&self) {
+        self.api
+            .lock()
+            .unwrap()
+            .start_frame_capture(std::ptr::null(), std::ptr::null());
+    }
+
+    pub(crate) fn end(&self) {
+        self.api
+            .lock()
+            .unwrap()
+            .end_frame_capture(std::ptr::null(), std::ptr::null());
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    binary::{invalid_data, read_bytes, read_json, read_u32, write_bytes, write_json},
+    renderer::{accumulation_size, half_accumulation},
+    settings::RenderSettings,
+    tile,
+};
+
+const MAGIC: &[u8; 8] = b"RTCKPT\0\0";
+const VERSION: u32 = 1;
+
+/// State of an interrupted render, enough to pick it up where it stopped.
+///
+/// Tiles are rendered in order, so the checkpoint keeps the image of the tiles that are already
+/// finished plus the raw accumulation of the one in flight. The random number generators are
+/// stateless hashes of the seed, pixel and sample index, so the seed in `settings` and
+/// `samples_done` are all the RNG state a resumed render needs to continue the exact same
+/// sample sequence.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    pub settings: RenderSettings,
+    /// Tiles, in render order, whose samples have all been accumulated and resolved.
+    pub tiles_done: u32,
+    /// Samples per pixel already accumulated in the tile following the finished ones.
+    pub samples_done: u32,
+    /// Sums and weights of the pixels of the tile in flight as the shaders accumulate them, in
+    /// half precision when the settings ask for it, empty when `samples_done` is zero.
+    pub accumulation: Vec<u8>,
+    /// Tightly packed RGBA8 rows of the whole image, only valid in the finished tiles.
+    pub image: Vec<u8>,
+}
+
+impl Checkpoint {
+    pub(crate) fn new(settings: &RenderSettings) -> Self {
+        Self {
+            settings: settings.clone(),
+            tiles_done: 0,
+            samples_done: 0,
+            accumulation: Vec::new(),
+            image: vec![0; settings.width as usize * settings.height as usize * 4],
+        }
+    }
+
+    /// Writes the checkpoint to `path`, going through a temporary file so that an interruption
+    /// while saving doesn't destroy the previous checkpoint.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("tmp");
+
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        write_json(&mut writer, &self.settings)?;
+        writer.write_all(&self.tiles_done.to_le_bytes())?;
+        writer.write_all(&self.samples_done.to_le_bytes())?;
+        write_bytes(&mut writer, &self.accumulation)?;
+        write_bytes(&mut writer, &self.image)?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+
+        fs::rename(tmp_path, path)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("Not a render checkpoint"));
+        }
+
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "Unsupported checkpoint version {version}"
+            )));
+        }
+
+        let settings: RenderSettings = read_json(&mut reader)?;
+        let tiles_done = read_u32(&mut reader)?;
+        let samples_done = read_u32(&mut reader)?;
+        let accumulation = read_bytes(&mut reader)?;
+        let image = read_bytes(&mut reader)?;
+
+        if image.len() != settings.width as usize * settings.height as usize * 4 {
+            return Err(invalid_data(
+                "Checkpoint image doesn't match its dimensions",
+            ));
+        }
+        if settings.tile_size == 0 {
+            return Err(invalid_data("Checkpoint tiles have no pixels"));
+        }
+        let tiles = || tile::tiles(settings.width, settings.height, settings.tile_size);
+        if tiles_done as usize > tiles().count() {
+            return Err(invalid_data(
+                "Checkpoint has more tiles done than its image",
+            ));
+        }
+        // The accumulation is uploaded as is to the tile in flight.
+        let accumulation_len = match tiles().nth(tiles_done as usize) {
+            Some(rect) if samples_done > 0 => {
+                rect.pixel_count() * accumulation_size(half_accumulation(&settings))
+            }
+            _ => 0,
+        };
+        if accumulation.len() as u64 != accumulation_len {
+            return Err(invalid_data(
+                "Checkpoint accumulation doesn't match its tile",
+            ));
+        }
+
+        Ok(Self {
+            settings,
+            tiles_done,
+            samples_done,
+            accumulation,
+            image,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Saves `checkpoint` to a file of its own and loads it back.
+    fn round_trip(test: &str, checkpoint: &Checkpoint) -> io::Result<Checkpoint> {
+        let path =
+            std::env::temp_dir().join(format!("checkpoint-{test}-{}.ckpt", std::process::id()));
+        checkpoint.save(&path)?;
+        let loaded = Checkpoint::load(&path);
+        fs::remove_file(&path)?;
+        loaded
+    }
+
+    fn checkpoint() -> Checkpoint {
+        let settings = RenderSettings {
+            width: 24,
+            height: 16,
+            tile_size: 16,
+            ..RenderSettings::default()
+        };
+        Checkpoint {
+            tiles_done: 1,
+            samples_done: 3,
+            // The second tile is 8 by 16 pixels.
+            accumulation: vec![7; 8 * 16 * accumulation_size(false) as usize],
+            ..Checkpoint::new(&settings)
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let checkpoint = checkpoint();
+        assert_eq!(round_trip("round-trip", &checkpoint).unwrap(), checkpoint);
+
+        let mut half = checkpoint;
+        half.settings.half_precision_accumulation = true;
+        half.accumulation.truncate(8 * 16 * accumulation_size(true) as usize);
+        assert_eq!(round_trip("round-trip-half", &half).unwrap(), half);
+    }
+
+    #[test]
+    fn rejects_accumulations_not_matching_their_tile() {
+        let load = |test: &str, checkpoint: Checkpoint| {
+            let err = round_trip(test, &checkpoint).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            err.to_string()
+        };
+        let mut short = checkpoint();
+        short.accumulation.pop();
+        assert_eq!(
+            load("short", short),
+            "Checkpoint accumulation doesn't match its tile"
+        );
+        let unsampled = Checkpoint {
+            samples_done: 0,
+            ..checkpoint()
+        };
+        assert_eq!(
+            load("unsampled", unsampled),
+            "Checkpoint accumulation doesn't match its tile"
+        );
+        let finished = Checkpoint {
+            tiles_done: 3,
+            ..checkpoint()
+        };
+        assert_eq!(
+            load("finished", finished),
+            "Checkpoint has more tiles done than its image"
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! Displacement of the surface of meshes by height maps, tessellated when the scene is built
+//! rather than while tracing it, so that detailed surfaces don't need high poly meshes.
+
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Vector2, Vector3, Zero};
+
+use crate::{scene::MeshData, texture::Heightmap};
+
+/// Splits every triangle of a mesh into `4^subdivisions` and moves their vertices out along the
+/// normals by `scale` times the height of `heightmap` at their texture coordinates, which repeats
+/// outside the unit square like textures do. The mesh has to have texture coordinates, normals
+/// are taken from its triangles if it has none, and the displaced mesh gets smooth normals of
+/// its own surface.
+pub(crate) fn displace(
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    texcoords: &[[f32; 2]],
+    indices: &[u32],
+    heightmap: &Heightmap,
+    scale: f32,
+    subdivisions: u32,
+) -> MeshData {
+    let triangles: Vec<[u32; 3]> = if indices.is_empty() {
+        (0..positions.len() as u32 / 3)
+            .map(|i| [i * 3, i * 3 + 1, i * 3 + 2])
+            .collect()
+    } else {
+        indices
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect()
+    };
+    let normals = if normals.len() == positions.len() {
+        normals.to_vec()
+    } else {
+        vertex_normals(positions, &triangles)
+    };
+
+    // Vertices on the edges of the triangles are shared with their neighbours so that the
+    // displaced surface has no cracks, keyed by the weights of the original vertices.
+    let steps = 1 << subdivisions;
+    let mut mesh = MeshData {
+        positions: Vec::new(),
+        normals: Vec::new(),
+        texcoords: Vec::new(),
+        indices: Vec::new(),
+    };
+    let mut vertices = HashMap::new();
+    for triangle in triangles {
+        let mut vertex = |i: u32, j: u32| {
+            let weights = [steps - i - j, i, j];
+            let mut key = [(u32::MAX, 0); 3];
+            for (slot, (&index, &weight)) in key.iter_mut().zip(triangle.iter().zip(&weights)) {
+                if weight > 0 {
+                    *slot = (index, weight);
+                }
+            }
+            key.sort_unstable();
+            *vertices.entry(key).or_insert_with(|| {
+                let mut position = Vector3::zero();
+                let mut normal = Vector3::zero();
+                let mut texcoord = Vector2::zero();
+                for (&index, &weight) in triangle.iter().zip(&weights) {
+                    let weight = weight as f32 / steps as f32;
+                    position += Vector3::from(positions[index as usize]) * weight;
+                    normal += Vector3::from(normals[index as usize]) * weight;
+                    texcoord += Vector2::from(texcoords[index as usize]) * weight;
+                }
+                if normal.magnitude2() > 0.0 {
+                    position += normal.normalize() * scale * height(heightmap, texcoord.into());
+                }
+                mesh.positions.push(position.into());
+                mesh.texcoords.push(texcoord.into());
+                mesh.positions.len() as u32 - 1
+            })
+        };
+
+        for j in 0..steps {
+            for i in 0..steps - j {
+                let [a, b, c] = [vertex(i, j), vertex(i + 1, j), vertex(i, j + 1)];
+                mesh.indices.extend([a, b, c]);
+                if i + j + 1 < steps {
+                    mesh.indices.extend([b, vertex(i + 1, j + 1), c]);
+                }
+            }
+        }
+    }
+
+    let triangles: Vec<[u32; 3]> = mesh
+        .indices
+        .chunks_exact(3)
+        .map(|t| [t[0], t[1], t[2]])
+        .collect();
+    mesh.normals = vertex_normals(&mesh.positions, &triangles);
+    mesh
+}
+
+/// Normals of the vertices of `triangles`, the sum of the normals of the triangles around them
+/// weighted by their areas.
+fn vertex_normals(positions: &[[f32; 3]], triangles: &[[u32; 3]]) -> Vec<[f32; 3]> {
+    let mut normals = vec![Vector3::zero(); positions.len()];
+    for triangle in triangles {
+        let [a, b, c] = triangle.map(|index| Vector3::from(positions[index as usize]));
+        let normal = (b - a).cross(c - a);
+        for &index in triangle {
+            normals[index as usize] += normal;
+        }
+    }
+    normals
+        .into_iter()
+        .map(|normal| {
+            if normal.magnitude2() > 0.0 {
+                normal.normalize().into()
+            } else {
+                [0.0; 3]
+            }
+        })
+        .collect()
+}
+
+/// Bilinearly filtered height of `heightmap` at texture coordinates `[u, v]`, with (0, 0) at the
+/// bottom left of the image.
+fn height(heightmap: &Heightmap, [u, v]: [f32; 2]) -> f32 {
+    let x = u * heightmap.width as f32 - 0.5;
+    let y = (1.0 - v) * heightmap.height as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let at = |x: f32, y: f32| {
+        let x = (x as i64).rem_euclid(heightmap.width as i64) as u32;
+        let y = (y as i64).rem_euclid(heightmap.height as i64) as u32;
+        heightmap.at(x, y)
+    };
+    let top = at(x0, y0) * (1.0 - fx) + at(x0 + 1.0, y0) * fx;
+    let bottom = at(x0, y0 + 1.0) * (1.0 - fx) + at(x0 + 1.0, y0 + 1.0) * fx;
+    top * (1.0 - fy) + bottom * fy
+}
@@ -0,0 +1,323 @@
+//! Distributed rendering over TCP.
+//!
+//! Machines taking part in a render run a worker with [`serve`], listening for connections. A
+//! [`Coordinator`] connects to every worker, hands out tiles one at a time and assembles the
+//! image from the tiles they send back. Tiles of a worker that drops out are handed to the
+//! remaining ones.
+//!
+//! All messages are little-endian and start with a one byte tag:
+//!
+//! - `Scene`: length prefixed JSON [`Scene`] to render the following jobs with, meshes included.
+//! - `Job`: length prefixed JSON [`RenderSettings`] of the render and the rectangle of the tile
+//!   to render.
+//! - `Tile`: rectangle and tightly packed RGBA8 rows of a rendered tile.
+//! - `Error`: length prefixed UTF-8 message of a worker that can't render a job, in place of
+//!   its tile.
+//!
+//! Connections start with the coordinator sending [`MAGIC`], followed by the protocol version.
+
+use std::{
+    collections::VecDeque,
+    io::{self, BufReader, BufWriter, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use tracing::{info_span, instrument};
+
+use crate::{
+    binary::{invalid_data, read_bytes, read_json, read_u32, write_bytes, write_json},
+    renderer::RaytracingRenderer,
+    scene::{Scene, SceneError},
+    settings::RenderSettings,
+    tile::{self, Tile, TileRect},
+};
+
+/// Bytes every connection starts with.
+pub const MAGIC: &[u8; 8] = b"RTFARM\0\0";
+const VERSION: u32 = 1;
+
+const JOB_TAG: u8 = 1;
+const TILE_TAG: u8 = 2;
+const SCENE_TAG: u8 = 3;
+const ERROR_TAG: u8 = 4;
+
+/// How long a coordinator thread waits before looking for work again when the queue is empty
+/// but tiles are still in flight on other workers.
+const REQUEUE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Accepts coordinator connections on `listener` forever, rendering the tiles they ask for
+/// with `renderer`.
+///
+/// Connections are served one at a time, a failing connection is logged and dropped.
+pub fn serve(renderer: &RaytracingRenderer, listener: TcpListener) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let peer = stream.peer_addr()?;
+
+        let _span = info_span!("farm_connection", %peer).entered();
+        tracing::info!("Coordinator connected");
+
+        match handle_connection(renderer, stream) {
+            Ok(()) => tracing::info!("Coordinator disconnected"),
+            Err(err) => tracing::warn!(%err, "Connection failed"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Serves the jobs of a single coordinator until it closes the connection. Jobs the renderer
+/// can't render are answered with an error.
+pub fn handle_connection(renderer: &RaytracingRenderer, stream: TcpStream) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("Not a render farm connection"));
+    }
+    let version = read_u32(&mut reader)?;
+    if version != VERSION {
+        return Err(invalid_data(format!(
+            "Unsupported render farm protocol version {version}"
+        )));
+    }
+
+    loop {
+        let mut tag = [0; 1];
+        match reader.read_exact(&mut tag) {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            result => result?,
+        }
+        match tag[0] {
+            JOB_TAG => {}
+            SCENE_TAG => {
+                let scene: Scene = read_json(&mut reader)?;
+                renderer
+                    .set_scene(&scene)
+                    .map_err(|err| invalid_data(err.to_string()))?;
+                continue;
+            }
+            tag => return Err(invalid_data(format!("Unexpected message tag {tag}"))),
+        }
+
+        let settings: RenderSettings = read_json(&mut reader)?;
+        let rect = read_rect(&mut reader)?;
+        if let Err(message) = check_job(renderer, &settings, rect) {
+            tracing::warn!(message, "Rejected job");
+            writer.write_all(&[ERROR_TAG])?;
+            write_bytes(&mut writer, message.as_bytes())?;
+            writer.flush()?;
+            continue;
+        }
+
+        let mut tiles = Vec::with_capacity(1);
+        async_std::task::block_on(
+            renderer.render_tile_rects(&settings, [rect], |tile| tiles.push(tile)),
+        );
+
+        for tile in tiles {
+            writer.write_all(&[TILE_TAG])?;
+            write_rect(&mut writer, tile.rect)?;
+            write_bytes(&mut writer, &tile.data)?;
+        }
+        writer.flush()?;
+    }
+}
+
+/// Checks that `renderer` can render the tile `rect` of the image described by `settings`.
+fn check_job(
+    renderer: &RaytracingRenderer,
+    settings: &RenderSettings,
+    rect: TileRect,
+) -> Result<(), String> {
+    renderer
+        .validate_settings(settings)
+        .map_err(|err| err.to_string())?;
+    let inside = |start: u32, size: u32, image: u32| {
+        size > 0 && size <= settings.tile_size && start.checked_add(size) <= Some(image)
+    };
+    if !inside(rect.x, rect.width, settings.width) || !inside(rect.y, rect.height, settings.height)
+    {
+        return Err(format!("The tile {rect:?} isn't a tile of the image"));
+    }
+    Ok(())
+}
+
+/// Splits renders between the workers of a render farm.
+pub struct Coordinator {
+    workers: Vec<SocketAddr>,
+    /// JSON of the scene sent to every worker, they keep their own one without it.
+    scene: Option<Vec<u8>>,
+}
+
+impl Coordinator {
+    pub fn new(workers: impl IntoIterator<Item = SocketAddr>) -> Self {
+        Self {
+            workers: workers.into_iter().collect(),
+            scene: None,
+        }
+    }
+
+    /// Makes workers render `scene`, reading its meshes so that workers don't need the files.
+    /// Textures aren't sent along, workers read them from the paths of the materials.
+    pub fn set_scene(&mut self, scene: &Scene) -> Result<(), SceneError> {
+        let mut scene = scene.clone();
+        scene.inline_meshes()?;
+        self.scene = Some(serde_json::to_vec(&scene)?);
+        Ok(())
+    }
+
+    /// Renders an image as tightly packed RGBA8 rows on the workers.
+    ///
+    /// Fails only if every worker dropped out before the image was complete.
+    #[instrument(skip(self))]
+    pub fn render(&self, settings: &RenderSettings) -> io::Result<Vec<u8>> {
+        let queue: Mutex<VecDeque<TileRect>> =
+            Mutex::new(tile::tiles(settings.width, settings.height, settings.tile_size).collect());
+        let tiles_total = queue.lock().unwrap().len();
+        let tiles_left = AtomicUsize::new(tiles_total);
+
+        let mut image = vec![0; settings.width as usize * settings.height as usize * 4];
+        let mut tiles_received = 0;
+
+        thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+
+            for &worker in &self.workers {
+                let sender = sender.clone();
+                let (queue, tiles_left) = (&queue, &tiles_left);
+                let scene = self.scene.as_deref();
+
+                scope.spawn(move || {
+                    let _span = info_span!("farm_worker", %worker).entered();
+                    let result = drive_worker(worker, scene, settings, queue, tiles_left, &sender);
+                    if let Err(err) = result {
+                        tracing::warn!(%err, "Worker dropped out");
+                    }
+                });
+            }
+            drop(sender);
+
+            for tile in receiver {
+                tile.copy_into(&mut image, settings.width);
+                tiles_received += 1;
+            }
+        });
+
+        if tiles_received < tiles_total {
+            return Err(io::Error::other(format!(
+                "Workers dropped out with {} tiles left",
+                tiles_total - tiles_received
+            )));
+        }
+
+        Ok(image)
+    }
+}
+
+/// Feeds tiles from `queue` to a single worker until none are left, putting the tile in
+/// flight back in the queue if the worker fails.
+fn drive_worker(
+    worker: SocketAddr,
+    scene: Option<&[u8]>,
+    settings: &RenderSettings,
+    queue: &Mutex<VecDeque<TileRect>>,
+    tiles_left: &AtomicUsize,
+    sender: &mpsc::Sender<Tile>,
+) -> io::Result<()> {
+    let stream = TcpStream::connect(worker)?;
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+
+    if let Some(scene) = scene {
+        writer.write_all(&[SCENE_TAG])?;
+        write_bytes(&mut writer, scene)?;
+    }
+
+    while tiles_left.load(Ordering::Acquire) > 0 {
+        let Some(rect) = queue.lock().unwrap().pop_front() else {
+            // Other workers still have tiles in flight, which come back if they fail.
+            thread::sleep(REQUEUE_POLL_INTERVAL);
+            continue;
+        };
+
+        match render_on_worker(&mut reader, &mut writer, settings, rect) {
+            Ok(tile) => {
+                tiles_left.fetch_sub(1, Ordering::AcqRel);
+                // The receiver outlives all the workers.
+                sender.send(tile).unwrap();
+            }
+            Err(err) => {
+                queue.lock().unwrap().push_back(rect);
+                return Err(err);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn render_on_worker(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    settings: &RenderSettings,
+    rect: TileRect,
+) -> io::Result<Tile> {
+    writer.write_all(&[JOB_TAG])?;
+    write_json(writer, settings)?;
+    write_rect(writer, rect)?;
+    writer.flush()?;
+
+    let mut tag = [0; 1];
+    reader.read_exact(&mut tag)?;
+    match tag[0] {
+        TILE_TAG => {}
+        ERROR_TAG => {
+            let message = read_bytes(reader)?;
+            return Err(io::Error::other(format!(
+                "Worker can't render the job: {}",
+                String::from_utf8_lossy(&message)
+            )));
+        }
+        tag => return Err(invalid_data(format!("Unexpected message tag {tag}"))),
+    }
+
+    let tile = Tile {
+        rect: read_rect(reader)?,
+        data: read_bytes(reader)?,
+    };
+    if tile.rect != rect || tile.data.len() as u64 != rect.pixel_count() * 4 {
+        return Err(invalid_data("Worker sent back a different tile"));
+    }
+
+    Ok(tile)
+}
+
+fn write_rect(writer: &mut impl Write, rect: TileRect) -> io::Result<()> {
+    for value in [rect.x, rect.y, rect.width, rect.height] {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_rect(reader: &mut impl Read) -> io::Result<TileRect> {
+    Ok(TileRect {
+        x: read_u32(reader)?,
+        y: read_u32(reader)?,
+        width: read_u32(reader)?,
+        height: read_u32(reader)?,
+    })
+}
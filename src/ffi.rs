@@ -0,0 +1,168 @@
+//! C API for embedding the renderer in applications written in other languages.
+//!
+//! Enabled by the `ffi` feature, `include/raytracing.h` declares the functions for C and C++.
+//! Errors are reported through the return values and logged with `tracing`. Panics abort the
+//! process, as they can't unwind into the caller.
+
+use std::{
+    ffi::{c_char, c_int, CStr},
+    ptr, slice,
+};
+
+use crate::{
+    renderer::RaytracingRenderer,
+    scene::Scene,
+    settings::{DepthBuffer, RenderSettings},
+};
+
+/// Renderer handed out to C as an opaque pointer.
+pub struct RtRenderer {
+    renderer: RaytracingRenderer,
+    settings: RenderSettings,
+}
+
+/// Creates a renderer on the highest performance adapter available, rendering the demo scene
+/// until [`rt_load_scene`] replaces it.
+///
+/// Returns null if there's no adapter. The renderer has to be freed with [`rt_destroy`].
+#[no_mangle]
+pub extern "C" fn rt_create() -> *mut RtRenderer {
+    match async_std::task::block_on(RaytracingRenderer::try_new()) {
+        Some(renderer) => Box::into_raw(Box::new(RtRenderer {
+            renderer,
+            settings: RenderSettings::default(),
+        })),
+        None => {
+            tracing::error!("No suitable adapter found");
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Loads the scene file at `path` and renders it from now on, with its render settings.
+///
+/// Returns 0 on success and -1 if the scene can't be loaded, in which case the previous one is
+/// kept.
+///
+/// # Safety
+///
+/// `renderer` has to come from [`rt_create`] and `path` has to be a nul-terminated UTF-8
+/// string.
+#[no_mangle]
+pub unsafe extern "C" fn rt_load_scene(renderer: *mut RtRenderer, path: *const c_char) -> c_int {
+    let renderer = &mut *renderer;
+    let Ok(path) = CStr::from_ptr(path).to_str() else {
+        tracing::error!("Scene path is not valid UTF-8");
+        return -1;
+    };
+
+    let result = Scene::load(path).and_then(|scene| {
+        renderer.renderer.set_scene(&scene)?;
+        renderer.settings = scene.settings;
+        Ok(())
+    });
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            tracing::error!(path, %err, "Could not load scene");
+            -1
+        }
+    }
+}
+
+/// Renders the scene `width` by `height` pixels large into `out`, as tightly packed RGBA8
+/// rows, blocking until the image is done.
+///
+/// Returns 0 on success and -1 if the settings of the scene aren't valid with that size, see
+/// [`RaytracingRenderer::validate_settings`], or `out_len` isn't exactly `width * height * 4`.
+///
+/// # Safety
+///
+/// `renderer` has to come from [`rt_create`] and `out` has to point to `out_len` writable
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rt_render(
+    renderer: *mut RtRenderer,
+    width: u32,
+    height: u32,
+    out: *mut u8,
+    out_len: usize,
+) -> c_int {
+    let renderer = &*renderer;
+    let settings = RenderSettings {
+        width,
+        height,
+        ..renderer.settings.clone()
+    };
+    if let Err(err) = renderer.renderer.validate_settings(&settings) {
+        tracing::error!(%err, "Invalid render settings");
+        return -1;
+    }
+    if out_len != width as usize * height as usize * 4 {
+        tracing::error!(width, height, out_len, "Output doesn't fit the image");
+        return -1;
+    }
+
+    let out = slice::from_raw_parts_mut(out, out_len);
+    async_std::task::block_on(renderer.renderer.render_into(&settings, out));
+    0
+}
+
+/// Renders the depths of the scene `width` by `height` pixels large into `out`, as tightly
+/// packed rows of floats for a zero to one depth buffer from `near` to `far`, which can be
+/// infinite, reversed if `reverse_z` isn't 0. See [`RaytracingRenderer::render_depth`].
+///
+/// Returns 0 on success and -1 if the settings of the scene aren't valid with that size, see
+/// [`RaytracingRenderer::validate_settings`], or `out_len` isn't exactly `width * height`.
+///
+/// # Safety
+///
+/// `renderer` has to come from [`rt_create`] and `out` has to point to `out_len` writable
+/// floats.
+#[no_mangle]
+pub unsafe extern "C" fn rt_render_depth(
+    renderer: *mut RtRenderer,
+    width: u32,
+    height: u32,
+    near: f32,
+    far: f32,
+    reverse_z: c_int,
+    out: *mut f32,
+    out_len: usize,
+) -> c_int {
+    let renderer = &*renderer;
+    let settings = RenderSettings {
+        width,
+        height,
+        ..renderer.settings.clone()
+    };
+    if let Err(err) = renderer.renderer.validate_settings(&settings) {
+        tracing::error!(%err, "Invalid render settings");
+        return -1;
+    }
+    if out_len != width as usize * height as usize {
+        tracing::error!(width, height, out_len, "Output doesn't fit the depths");
+        return -1;
+    }
+
+    let depth_buffer = DepthBuffer {
+        near,
+        far,
+        reverse_z: reverse_z != 0,
+    };
+    let depths = async_std::task::block_on(renderer.renderer.render_depth(&settings, depth_buffer));
+    slice::from_raw_parts_mut(out, out_len).copy_from_slice(&depths);
+    0
+}
+
+/// Frees a renderer created by [`rt_create`]. Null is ignored.
+///
+/// # Safety
+///
+/// `renderer` has to come from [`rt_create`] and can't be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rt_destroy(renderer: *mut RtRenderer) {
+    if !renderer.is_null() {
+        drop(Box::from_raw(renderer));
+    }
+}
@@ -0,0 +1,2291 @@
+//! Scenes flattened into the storage buffers the shaders trace rays against.
+
+use std::{
+    num::NonZeroU64,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix, Matrix3, Matrix4, Point3, SquareMatrix, Transform as _,
+    Vector3,
+};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages,
+    Device, Queue, SamplerBindingType, ShaderStages, TextureSampleType, TextureView,
+    TextureViewDimension,
+};
+use zerocopy::AsBytes;
+
+use crate::{
+    bvh::{Aabb, Bvh, BvhNode},
+    displacement,
+    materials::MaterialLibrary,
+    scene::{
+        self, Camera, ClipPlane, ColorSpace, Csg, CurveBasis, DensityGrid, Fog, Light, Material,
+        MaterialOverrides, MeshData, PhysicalSky, Portal, Scene, SceneError, Sdf, Shape, Sky, Sun,
+    },
+    settings::{Eye, Stereo},
+    texture::{
+        DensityTexture, HeightfieldArray, Heightmap, LightProfileArray, TextureCache,
+        PROFILE_HEIGHT, PROFILE_WIDTH,
+    },
+};
+
+const PRIMITIVE_TRIANGLE: u32 = 0;
+const PRIMITIVE_SPHERE: u32 = 1;
+const PRIMITIVE_CUSTOM: u32 = 2;
+const PRIMITIVE_SMOOTH_TRIANGLE: u32 = 3;
+const PRIMITIVE_SDF: u32 = 4;
+const PRIMITIVE_HEIGHTFIELD: u32 = 5;
+const PRIMITIVE_CURVE: u32 = 6;
+const PRIMITIVE_POINT: u32 = 7;
+const PRIMITIVE_QUAD: u32 = 8;
+const PRIMITIVE_BOX: u32 = 9;
+const PRIMITIVE_CSG: u32 = 10;
+
+/// Straight pieces every cubic segment of curves is split into, as primitives of their own.
+const CURVE_PIECES: u32 = 8;
+
+/// Cells along either side of the tiles heightfields are split into, `HEIGHTFIELD_TILE` in the
+/// shaders. Every tile is a primitive of its own, so the BVH skips most of a terrain.
+const HEIGHTFIELD_TILE: u32 = 32;
+
+const SDF_SPHERE: u32 = 0;
+const SDF_CUBOID: u32 = 1;
+const SDF_TORUS: u32 = 2;
+const SDF_CYLINDER: u32 = 3;
+const SDF_UNION: u32 = 4;
+const SDF_INTERSECTION: u32 = 5;
+const SDF_SUBTRACTION: u32 = 6;
+/// Distances the shaders keep while evaluating a signed distance field, `SDF_STACK` there.
+const SDF_STACK: usize = 8;
+
+const CSG_SPHERE: u32 = 0;
+const CSG_CUBOID: u32 = 1;
+const CSG_CYLINDER: u32 = 2;
+const CSG_UNION: u32 = 3;
+const CSG_INTERSECTION: u32 = 4;
+const CSG_DIFFERENCE: u32 = 5;
+
+/// `custom` of materials shaded by the built-in lobes.
+const MATERIAL_BUILTIN: u32 = u32::MAX;
+
+/// Texture of materials without a texture.
+const NO_TEXTURE: u32 = u32::MAX;
+
+/// Texture coordinates of triangles without any, making them the barycentric coordinates.
+const BARYCENTRIC_TEXCOORDS: [[f32; 2]; 3] = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]];
+
+/// Objects only camera rays can't see, through which they see the footage the render goes
+/// into.
+const OBJECT_HOLDOUT: u32 = 1;
+/// Objects the rays of a kind, see [`RayVisibility`](scene::RayVisibility), pass through.
+const OBJECT_HIDDEN_CAMERA: u32 = 2;
+const OBJECT_HIDDEN_SHADOW: u32 = 4;
+const OBJECT_HIDDEN_REFLECTION: u32 = 8;
+const OBJECT_HIDDEN_INDIRECT: u32 = 16;
+/// First bit of the flags of objects with the bits of the [light links](scene::LightLink) of
+/// the lights that don't illuminate them, one for each link.
+const OBJECT_LIGHT_LINKS_SHIFT: u32 = 8;
+/// Links that fit in the flags of objects, which the shaders keep shifted up by one.
+const MAX_LIGHT_LINKS: usize = 16;
+
+const LIGHT_POINT: u32 = 0;
+const LIGHT_DIRECTIONAL: u32 = 1;
+const LIGHT_PORTAL: u32 = 2;
+const LIGHT_CLIP_PLANE: u32 = 3;
+
+/// Material of the caps of clipping planes that leave surfaces open.
+const NO_CAP: u32 = u32::MAX;
+
+const PROJECTION_PERSPECTIVE: u32 = 0;
+const PROJECTION_OCTAHEDRAL: u32 = 1;
+
+const SKY_GRADIENT: u32 = 0;
+const SKY_PHYSICAL: u32 = 1;
+const SKY_TEXTURE: u32 = 2;
+/// Background of camera rays that's the sky itself.
+const BACKGROUND_SKY: u32 = 3;
+
+/// Bytes of the pieces buffers are compared in when a scene is [updated](GpuScene::update),
+/// only the ones that changed are written.
+const UPDATE_CHUNK_SIZE: usize = 256;
+
+/// Mirrors `Primitive` in the shaders.
+///
+/// Triangles store their world space vertices in `v0`, `v1` and `v2`, smooth shaded ones the
+/// [encoded](encode_normal) normals of the vertices in their `w`, spheres their center and
+/// radius in `v0`, custom shapes their bounds in `v0` and `v1` and their data in `v2`.
+/// Signed distance fields have their bounds in `v0` and `v1` too, and in `v2` the index of
+/// their program of [SDF nodes](sdf_node) after the primitives, the length of it, and the
+/// smallest scale of their transform. Tiles of heightfields have their bounds in `v0` and `v1`
+/// as well, and in `v2` the index of the [header](heightfield_header) of their heightfield
+/// after the primitives, the column and row of the tile in the low and high half of the bits,
+/// and the lowest and highest height in it. Pieces of curves have the centers and radii of
+/// their ends in `v0` and `v1`, and in `v2` the parameters along their strand at those ends
+/// and whether they're flat. Points have their center and radius in `v0`, the normal they
+/// face, zero for the ray, and whether they're Gaussian in `v1` and their color in `v2`.
+#[derive(AsBytes, Clone, Copy)]
+#[repr(C)]
+pub(crate) struct PrimitiveRaw {
+    v0: [f32; 4],
+    v1: [f32; 4],
+    v2: [f32; 4],
+    kind: u32,
+    material: u32,
+    object: u32,
+    /// `OBJECT` flags of the object.
+    flags: u32,
+    /// Texture coordinates of the vertices of triangles.
+    texcoords: [[f32; 2]; 3],
+    _padding1: [u32; 2],
+}
+
+/// Mirrors `Material` in the shaders.
+#[derive(AsBytes, Clone, Copy)]
+#[repr(C)]
+pub(crate) struct MaterialRaw {
+    base_color: [f32; 3],
+    metallic: f32,
+    emission: [f32; 3],
+    roughness: f32,
+    ior: f32,
+    transmission: f32,
+    custom: u32,
+    opacity: f32,
+    /// Index of the texture in the scene's texture cache, [`NO_TEXTURE`] if there's none.
+    base_color_texture: u32,
+    specular: f32,
+    sheen: f32,
+    sheen_tint: f32,
+    anisotropic: f32,
+    anisotropic_rotation: f32,
+    clearcoat: f32,
+    clearcoat_roughness: f32,
+    clearcoat_ior: f32,
+    thin_film_thickness: f32,
+    thin_film_ior: f32,
+    subsurface: f32,
+    subsurface_scattering: [f32; 3],
+    abbe_number: f32,
+    subsurface_absorption: [f32; 3],
+    hair: f32,
+    hair_azimuthal_roughness: f32,
+    hair_scale_angle: f32,
+    shadow_catcher: u32,
+    priority: u32,
+    /// Columns of the affine transform of the texture coordinates of the base color texture.
+    texture_transform: [[f32; 2]; 3],
+    _padding: [u32; 2],
+}
+
+/// Mirrors `Light` in the shaders, `position` is the direction of directional lights.
+#[derive(AsBytes, Clone, Copy)]
+#[repr(C)]
+pub(crate) struct LightRaw {
+    position: [f32; 3],
+    kind: u32,
+    intensity: [f32; 3],
+    cos_half_angle: f32,
+    profile_axis: [f32; 3],
+    profile: u32,
+    profile_reference: [f32; 3],
+    power_cdf: f32,
+    /// Bits of the links of the light among the light link bits of the flags of objects.
+    link: u32,
+    _padding1: [u32; 3],
+}
+
+/// Mirrors `SceneUniforms` in the shaders.
+#[derive(AsBytes, Clone, Copy)]
+#[repr(C)]
+pub(crate) struct SceneUniforms {
+    camera_position: [f32; 3],
+    tan_half_fov: f32,
+    camera_forward: [f32; 3],
+    light_count: u32,
+    camera_right: [f32; 3],
+    camera_projection: u32,
+    camera_up: [f32; 3],
+    portal_count: u32,
+    fog_scattering: [f32; 3],
+    fog_anisotropy: f32,
+    fog_absorption: [f32; 3],
+    fog_density_grid: u32,
+    fog_min: [f32; 3],
+    clip_plane_count: u32,
+    fog_max: [f32; 3],
+    sky_kind: u32,
+    sky_a: [f32; 3],
+    sky_ground_albedo: f32,
+    sky_b: [f32; 3],
+    sky_texture: u32,
+    sky_c: [f32; 3],
+    sky_intensity: f32,
+    sky_d: [f32; 3],
+    sky_rotation: f32,
+    sky_e: [f32; 3],
+    background_kind: u32,
+    sky_zenith: [f32; 3],
+    background_texture: u32,
+    sun_direction: [f32; 3],
+    background_intensity: f32,
+    sky_bottom: [f32; 3],
+    background_rotation: f32,
+    sky_top: [f32; 3],
+    camera_shift_x: f32,
+    background_bottom: [f32; 3],
+    camera_shift_y: f32,
+    background_top: [f32; 3],
+    _padding7: u32,
+}
+
+impl SceneUniforms {
+    /// Shift of the lens of the camera, see [`Camera::shift`].
+    pub fn camera_shift(&self) -> [f32; 2] {
+        [self.camera_shift_x, self.camera_shift_y]
+    }
+}
+
+/// A scene as the shaders see it, with all the objects transformed to world space.
+pub(crate) struct SceneData {
+    /// Primitives in the order the leaves of the BVH refer to them, followed by the programs
+    /// of their signed distance fields.
+    pub primitives: Vec<PrimitiveRaw>,
+    pub bvh: Bvh,
+    /// [Cost](Bvh::cost) of the BVH when it was built, before any refitting.
+    built_cost: f32,
+    pub materials: Vec<MaterialRaw>,
+    /// Materials of the scene the first of `materials` were made from, and the index, base
+    /// material and overrides of the ones objects override the parameters of, to
+    /// [update](Self::material_updates) them in place.
+    scene_materials: Vec<Material>,
+    overridden_materials: Vec<(u32, usize, MaterialOverrides)>,
+    /// Files of the textures and how they're encoded, in the order materials refer to them.
+    pub textures: Vec<(PathBuf, ColorSpace)>,
+    pub density_grid: Option<DensityGrid>,
+    pub lights: Vec<LightRaw>,
+    /// IES files of the layers of the light profile array, in the order lights refer to them.
+    pub light_profiles: Vec<PathBuf>,
+    /// Layers of the heightfield array, in the order heightfields refer to them.
+    pub heightmaps: Vec<Heightmap>,
+    pub uniforms: SceneUniforms,
+    /// [Origin](Scene::origin) of the scene, which everything in the buffers is relative to.
+    pub origin: [f64; 3],
+}
+
+/// How much more expensive than when it was built refitting may make a BVH before it's
+/// rebuilt instead.
+const MAX_REFIT_COST: f32 = 2.0;
+
+impl SceneData {
+    pub fn build(scene: &Scene) -> Result<Self, SceneError> {
+        Self::build_refitting(scene, None)
+    }
+
+    /// Like [`build`](Self::build), but if `scene` has the same primitives as `previous`, only
+    /// moved, the BVH of `previous` is refitted to them unless that makes it too slow to
+    /// traverse.
+    pub fn refit(scene: &Scene, previous: &Self) -> Result<Self, SceneError> {
+        Self::build_refitting(scene, Some(previous))
+    }
+
+    fn build_refitting(scene: &Scene, previous: Option<&Self>) -> Result<Self, SceneError> {
+        let instances = scene.instances();
+        let _span = tracing::info_span!("build_scene", instances = instances.len()).entered();
+
+        // Objects without a material use a default one appended after the scene's, objects
+        // overriding theirs get a copy of it with the overrides applied after that.
+        let default_material = scene.materials.len() as u32;
+        let mut textures = Vec::new();
+        let mut materials: Vec<_> = scene
+            .materials
+            .iter()
+            .map(|material| material_raw(material, &mut textures))
+            .collect();
+        materials.push(material_raw(&Material::default(), &mut textures));
+        let mut overridden_materials = Vec::new();
+
+        let mut primitives = Vec::new();
+        // Records the primitives refer to by index, stored after them: the programs of signed
+        // distance fields and the headers of heightfields.
+        let mut nodes = Vec::new();
+        let mut heightmaps: Vec<Heightmap> = Vec::new();
+        let mut displacement_maps: Vec<Heightmap> = Vec::new();
+        for (instance_index, instance) in instances.iter().enumerate() {
+            let object = instance.object;
+            let mut material = match &object.material {
+                Some(name) => scene.material_index(name)? as u32,
+                None => default_material,
+            };
+            let displacement = scene.materials.get(material as usize).and_then(|material| {
+                let path = material.displacement_texture.as_ref()?;
+                Some((
+                    path,
+                    material.displacement_scale,
+                    material.displacement_subdivisions,
+                ))
+            });
+            if !object.overrides.is_empty() {
+                let base = match scene.materials.get(material as usize) {
+                    Some(base) => base.clone(),
+                    None => Material::default(),
+                };
+                if (material as usize) < scene.materials.len() {
+                    overridden_materials.push((
+                        materials.len() as u32,
+                        material as usize,
+                        object.overrides.clone(),
+                    ));
+                }
+                material = materials.len() as u32;
+                materials.push(material_raw(&object.overrides.apply(base), &mut textures));
+            }
+            let matrix = instance.matrix;
+
+            let unlinked = scene
+                .light_links
+                .iter()
+                .enumerate()
+                .filter(|(_, link)| !link.illuminates(&object.name))
+                .fold(0, |bits, (index, _)| bits | 1 << index);
+            let visibility = object.visibility;
+            let flags = [
+                (object.holdout, OBJECT_HOLDOUT),
+                (!visibility.camera, OBJECT_HIDDEN_CAMERA),
+                (!visibility.shadow, OBJECT_HIDDEN_SHADOW),
+                (!visibility.reflection, OBJECT_HIDDEN_REFLECTION),
+                (!visibility.indirect, OBJECT_HIDDEN_INDIRECT),
+            ]
+            .into_iter()
+            .filter(|(set, _)| *set)
+            .fold(unlinked << OBJECT_LIGHT_LINKS_SHIFT, |flags, (_, flag)| {
+                flags | flag
+            });
+            let primitive = |kind, [v0, v1, v2]: [[f32; 4]; 3], texcoords| PrimitiveRaw {
+                v0,
+                v1,
+                v2,
+                kind,
+                material,
+                object: instance_index as u32,
+                flags,
+                texcoords,
+                _padding1: [0; 2],
+            };
+
+            match &object.shape {
+                Shape::Sphere { center, radius } => {
+                    let center = matrix.transform_point(Point3::from(*center));
+                    let scale =
+                        [matrix.x, matrix.y, matrix.z].map(|axis| axis.truncate().magnitude());
+                    let radius = radius * scale.into_iter().fold(0.0, f32::max);
+                    primitives.push(primitive(
+                        PRIMITIVE_SPHERE,
+                        [[center.x, center.y, center.z, radius], [0.0; 4], [0.0; 4]],
+                        BARYCENTRIC_TEXCOORDS,
+                    ));
+                }
+                Shape::Quad { corner, edges } => {
+                    let corner = Point3::from(*corner);
+                    let [v0, v1, v2] = [
+                        corner,
+                        corner + Vector3::from(edges[0]),
+                        corner + Vector3::from(edges[1]),
+                    ]
+                    .map(|vertex| {
+                        let vertex = matrix.transform_point(vertex);
+                        [vertex.x, vertex.y, vertex.z, 0.0]
+                    });
+                    primitives.push(primitive(
+                        PRIMITIVE_QUAD,
+                        [v0, v1, v2],
+                        BARYCENTRIC_TEXCOORDS,
+                    ));
+                }
+                Shape::Cuboid {
+                    center,
+                    half_extents,
+                } => {
+                    // The box spans the unit cube in the space of the primitive.
+                    let [x, y, z] = *half_extents;
+                    let unit = matrix
+                        * Matrix4::from_translation(Vector3::from(*center) - Vector3::new(x, y, z))
+                        * Matrix4::from_nonuniform_scale(2.0 * x, 2.0 * y, 2.0 * z);
+                    let Some(inverse) = unit.invert() else {
+                        continue;
+                    };
+                    let row = |i| [inverse.x[i], inverse.y[i], inverse.z[i], inverse.w[i]];
+                    primitives.push(primitive(
+                        PRIMITIVE_BOX,
+                        [row(0), row(1), row(2)],
+                        BARYCENTRIC_TEXCOORDS,
+                    ));
+                }
+                Shape::Mesh { path } => {
+                    let mut mesh = scene::load_mesh(path)?;
+                    check_triangles(&mesh.positions, &mesh.indices).map_err(|message| {
+                        SceneError::Geometry {
+                            object: object.name.clone(),
+                            message,
+                        }
+                    })?;
+                    if let Some(displacement) = displacement {
+                        mesh = displace(
+                            &object.name,
+                            &mesh.positions,
+                            &mesh.normals,
+                            &mesh.texcoords,
+                            &mesh.indices,
+                            displacement,
+                            &mut displacement_maps,
+                        )?;
+                    }
+                    let normals = if object.flat_shading {
+                        &[][..]
+                    } else {
+                        &mesh.normals
+                    };
+                    push_triangles(
+                        &mesh.positions,
+                        normals,
+                        &mesh.texcoords,
+                        &mesh.indices,
+                        &matrix,
+                        |kind, vertices, texcoords| {
+                            primitives.push(primitive(kind, vertices, texcoords))
+                        },
+                    );
+                }
+                Shape::Triangles {
+                    positions,
+                    normals,
+                    texcoords,
+                    indices,
+                } => {
+                    check_triangles(positions, indices).map_err(|message| {
+                        SceneError::Geometry {
+                            object: object.name.clone(),
+                            message,
+                        }
+                    })?;
+                    let displaced;
+                    let (positions, normals, texcoords, indices) = match displacement {
+                        Some(displacement) => {
+                            displaced = displace(
+                                &object.name,
+                                positions,
+                                normals,
+                                texcoords,
+                                indices,
+                                displacement,
+                                &mut displacement_maps,
+                            )?;
+                            (
+                                &displaced.positions,
+                                &displaced.normals,
+                                &displaced.texcoords,
+                                &displaced.indices,
+                            )
+                        }
+                        None => (positions, normals, texcoords, indices),
+                    };
+                    let normals = if object.flat_shading {
+                        &[][..]
+                    } else {
+                        normals
+                    };
+                    push_triangles(
+                        positions,
+                        normals,
+                        texcoords,
+                        indices,
+                        &matrix,
+                        |kind, vertices, texcoords| {
+                            primitives.push(primitive(kind, vertices, texcoords))
+                        },
+                    );
+                }
+                Shape::Custom { min, max, data } => {
+                    let [x0, y0, z0] = *min;
+                    let [x1, y1, z1] = *max;
+                    primitives.push(primitive(
+                        PRIMITIVE_CUSTOM,
+                        [[x0, y0, z0, 0.0], [x1, y1, z1, 0.0], *data],
+                        BARYCENTRIC_TEXCOORDS,
+                    ));
+                }
+                Shape::Sdf { expression } => {
+                    let [min, max] = expression.bounds();
+                    let Some(inverse) = matrix.invert() else {
+                        continue;
+                    };
+                    if (0..3).any(|axis| min[axis] > max[axis]) {
+                        continue;
+                    }
+                    if sdf_stack_depth(expression) > SDF_STACK {
+                        return Err(SceneError::Sdf {
+                            object: object.name.clone(),
+                            message: format!(
+                                "the expression needs more than {SDF_STACK} distances at once"
+                            ),
+                        });
+                    }
+
+                    // The program starts with the transform from world space to the one of
+                    // the object, by rows.
+                    let first = nodes.len() as u32;
+                    let row = |i| [inverse.x[i], inverse.y[i], inverse.z[i], inverse.w[i]];
+                    nodes.push(sdf_node(0, [row(0), row(1), row(2)]));
+                    push_sdf(expression, &mut nodes);
+                    let length = nodes.len() as u32 - first;
+
+                    let aabb = transformed_aabb(&matrix, [min, max]);
+                    let scale = [matrix.x, matrix.y, matrix.z]
+                        .map(|axis| axis.truncate().magnitude())
+                        .into_iter()
+                        .fold(f32::MAX, f32::min);
+                    let [x0, y0, z0] = aabb.min;
+                    let [x1, y1, z1] = aabb.max;
+                    primitives.push(primitive(
+                        PRIMITIVE_SDF,
+                        [
+                            [x0, y0, z0, 0.0],
+                            [x1, y1, z1, 0.0],
+                            [f32::from_bits(first), f32::from_bits(length), scale, 0.0],
+                        ],
+                        BARYCENTRIC_TEXCOORDS,
+                    ));
+                }
+                Shape::Csg { expression } => {
+                    let [min, max] = expression.bounds();
+                    let Some(inverse) = matrix.invert() else {
+                        continue;
+                    };
+                    if (0..3).any(|axis| min[axis] > max[axis]) {
+                        continue;
+                    }
+                    if expression.solids() > Csg::MAX_SOLIDS {
+                        return Err(SceneError::Csg {
+                            object: object.name.clone(),
+                            message: format!(
+                                "the expression combines more than {} solids",
+                                Csg::MAX_SOLIDS
+                            ),
+                        });
+                    }
+
+                    // Like the programs of signed distance fields, after the transform to the
+                    // space of the object.
+                    let first = nodes.len() as u32;
+                    let row = |i| [inverse.x[i], inverse.y[i], inverse.z[i], inverse.w[i]];
+                    nodes.push(sdf_node(0, [row(0), row(1), row(2)]));
+                    push_csg(expression, &mut nodes);
+                    let length = nodes.len() as u32 - first;
+
+                    let aabb = transformed_aabb(&matrix, [min, max]);
+                    let [x0, y0, z0] = aabb.min;
+                    let [x1, y1, z1] = aabb.max;
+                    primitives.push(primitive(
+                        PRIMITIVE_CSG,
+                        [
+                            [x0, y0, z0, 0.0],
+                            [x1, y1, z1, 0.0],
+                            [f32::from_bits(first), f32::from_bits(length), 0.0, 0.0],
+                        ],
+                        BARYCENTRIC_TEXCOORDS,
+                    ));
+                }
+                Shape::Curves {
+                    points,
+                    counts,
+                    widths,
+                    basis,
+                    flat,
+                } => {
+                    let flat = if *flat { 1.0 } else { 0.0 };
+                    push_curves(
+                        points,
+                        counts,
+                        widths,
+                        *basis,
+                        &matrix,
+                        |[v0, v1], [u0, u1]| {
+                            primitives.push(primitive(
+                                PRIMITIVE_CURVE,
+                                [v0, v1, [u0, u1, flat, 0.0]],
+                                BARYCENTRIC_TEXCOORDS,
+                            ))
+                        },
+                    )
+                    .map_err(|message| SceneError::Geometry {
+                        object: object.name.clone(),
+                        message,
+                    })?;
+                }
+                Shape::Points {
+                    positions,
+                    radii,
+                    normals,
+                    colors,
+                    gaussian,
+                } => {
+                    let error = |attribute: &str, len: usize| SceneError::Geometry {
+                        object: object.name.clone(),
+                        message: format!("{len} {attribute} for {} points", positions.len()),
+                    };
+                    if radii.len() != 1 && radii.len() != positions.len() {
+                        return Err(error("radii", radii.len()));
+                    }
+                    if !normals.is_empty() && normals.len() != positions.len() {
+                        return Err(error("normals", normals.len()));
+                    }
+                    if !colors.is_empty() && colors.len() != positions.len() {
+                        return Err(error("colors", colors.len()));
+                    }
+
+                    let scale = [matrix.x, matrix.y, matrix.z]
+                        .map(|axis| axis.truncate().magnitude())
+                        .into_iter()
+                        .fold(0.0, f32::max);
+                    // Normals transform with the inverse transpose to stay perpendicular to
+                    // the disks.
+                    let normal_matrix = Matrix3::from_cols(
+                        matrix.x.truncate(),
+                        matrix.y.truncate(),
+                        matrix.z.truncate(),
+                    )
+                    .invert()
+                    .map(|inverse| inverse.transpose())
+                    .unwrap_or_else(Matrix3::identity);
+                    let gaussian = if *gaussian { 1.0 } else { 0.0 };
+                    for (i, position) in positions.iter().enumerate() {
+                        let center = matrix.transform_point(Point3::from(*position));
+                        let radius = radii.get(i).unwrap_or(&radii[0]) * scale;
+                        let normal = normals.get(i).map_or([0.0; 3], |normal| {
+                            let normal = normal_matrix * Vector3::from(*normal);
+                            if normal.magnitude2() > 0.0 {
+                                normal.normalize().into()
+                            } else {
+                                [0.0; 3]
+                            }
+                        });
+                        let [r, g, b] = colors.get(i).copied().unwrap_or([1.0; 3]);
+                        let [nx, ny, nz] = normal;
+                        primitives.push(primitive(
+                            PRIMITIVE_POINT,
+                            [
+                                [center.x, center.y, center.z, radius],
+                                [nx, ny, nz, gaussian],
+                                [r, g, b, 0.0],
+                            ],
+                            BARYCENTRIC_TEXCOORDS,
+                        ));
+                    }
+                }
+                Shape::Heightfield { path, size } => {
+                    let layer = match heightmaps.iter().position(|h| h.path == *path) {
+                        Some(layer) => layer,
+                        None => {
+                            heightmaps.push(Heightmap::read(path)?);
+                            heightmaps.len() - 1
+                        }
+                    };
+                    let heightmap = &heightmaps[layer];
+
+                    // The grid spans the unit square in the space of the header, heights going
+                    // from 0 to 1.
+                    let [width, height, depth] = *size;
+                    let unit = matrix
+                        * Matrix4::from_translation(Vector3::new(-width / 2.0, 0.0, -depth / 2.0))
+                        * Matrix4::from_nonuniform_scale(width, height, depth);
+                    let Some(inverse) = unit.invert() else {
+                        continue;
+                    };
+                    let header = nodes.len() as u32;
+                    let row = |i| [inverse.x[i], inverse.y[i], inverse.z[i], inverse.w[i]];
+                    let [columns, rows] = [heightmap.width - 1, heightmap.height - 1];
+                    nodes.push(heightfield_header(
+                        layer as u32,
+                        [row(0), row(1), row(2)],
+                        [columns, rows],
+                        &unit,
+                    ));
+
+                    for tile_row in 0..rows.div_ceil(HEIGHTFIELD_TILE) {
+                        for tile_column in 0..columns.div_ceil(HEIGHTFIELD_TILE) {
+                            let x = tile_column * HEIGHTFIELD_TILE
+                                ..=((tile_column + 1) * HEIGHTFIELD_TILE).min(columns);
+                            let y = tile_row * HEIGHTFIELD_TILE
+                                ..=((tile_row + 1) * HEIGHTFIELD_TILE).min(rows);
+                            let (low, high) = y
+                                .clone()
+                                .flat_map(|y| x.clone().map(move |x| heightmap.at(x, y)))
+                                .fold((f32::MAX, f32::MIN), |(low, high), h| {
+                                    (low.min(h), high.max(h))
+                                });
+
+                            let corners: Vec<[f32; 3]> = (0..8)
+                                .map(|corner| {
+                                    let u = if corner & 1 == 0 { x.start() } else { x.end() };
+                                    let v = if corner & 2 == 0 { y.start() } else { y.end() };
+                                    let h = if corner & 4 == 0 { low } else { high };
+                                    let point = Point3::new(
+                                        *u as f32 / columns as f32,
+                                        h,
+                                        *v as f32 / rows as f32,
+                                    );
+                                    unit.transform_point(point).into()
+                                })
+                                .collect();
+                            let aabb = Aabb::from_points(&corners);
+                            let [x0, y0, z0] = aabb.min;
+                            let [x1, y1, z1] = aabb.max;
+                            let tile = tile_column | tile_row << 16;
+                            primitives.push(primitive(
+                                PRIMITIVE_HEIGHTFIELD,
+                                [
+                                    [x0, y0, z0, 0.0],
+                                    [x1, y1, z1, 0.0],
+                                    [f32::from_bits(header), f32::from_bits(tile), low, high],
+                                ],
+                                BARYCENTRIC_TEXCOORDS,
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+
+        let aabbs: Vec<_> = primitives.iter().map(primitive_aabb).collect();
+        let refitted = previous
+            .filter(|previous| previous.has_primitives(&primitives))
+            .map(|previous| {
+                let mut bvh = previous.bvh.clone();
+                bvh.refit(&aabbs);
+                (bvh, previous.built_cost)
+            })
+            .filter(|(bvh, built_cost)| bvh.cost() <= built_cost * MAX_REFIT_COST);
+        let (bvh, built_cost) = refitted.unwrap_or_else(|| {
+            let bvh = Bvh::build(&aabbs);
+            let cost = bvh.cost();
+            (bvh, cost)
+        });
+
+        let mut primitives: Vec<_> = bvh
+            .order
+            .iter()
+            .map(|&index| primitives[index as usize])
+            .collect();
+        let nodes_start = primitives.len() as u32;
+        for primitive in &mut primitives {
+            if matches!(
+                primitive.kind,
+                PRIMITIVE_SDF | PRIMITIVE_CSG | PRIMITIVE_HEIGHTFIELD
+            ) {
+                primitive.v2[0] = f32::from_bits(primitive.v2[0].to_bits() + nodes_start);
+            }
+        }
+        primitives.extend(nodes);
+
+        let origin = scene.origin();
+        let mut light_profiles = Vec::new();
+        let mut lights = scene
+            .lights
+            .iter()
+            .cloned()
+            .chain(scene.sun.as_ref().map(Sun::light))
+            .map(|light| light_raw(&light, origin, &mut light_profiles))
+            .collect::<Vec<_>>();
+        if scene.light_links.len() > MAX_LIGHT_LINKS {
+            return Err(SceneError::LightLink {
+                light: scene.light_links[MAX_LIGHT_LINKS].light,
+                message: format!("More than {MAX_LIGHT_LINKS} light links"),
+            });
+        }
+        for (index, link) in scene.light_links.iter().enumerate() {
+            // The sun follows the lights of the scene.
+            let light = lights[..scene.lights.len()]
+                .get_mut(link.light)
+                .ok_or_else(|| SceneError::LightLink {
+                    light: link.light,
+                    message: "No such light".to_owned(),
+                })?;
+            light.link |= 1 << index;
+        }
+        let light_count = lights.len() as u32;
+        let scene_radius = bvh.nodes.first().map_or(0.0, |root| {
+            0.5 * (Vector3::from(root.max) - Vector3::from(root.min)).magnitude()
+        });
+        set_power_cdf(&mut lights, scene_radius);
+        lights.extend(
+            scene
+                .portals
+                .iter()
+                .map(|portal| portal_raw(portal, origin)),
+        );
+        for plane in &scene.clip_planes {
+            lights.push(clip_plane_raw(scene, plane)?);
+        }
+        let with_sun = |sky: &Sky| match (sky, &scene.sun) {
+            (Sky::Physical(sky), Some(sun)) => Sky::Physical(PhysicalSky {
+                sun_direction: sun.direction(),
+                ..*sky
+            }),
+            (sky, _) => sky.clone(),
+        };
+        let sky = with_sun(&scene.sky);
+        let background = scene.background.as_ref().map(with_sun);
+        // Physical backgrounds share the atmosphere of a physical sky.
+        let atmosphere = match (&sky, &background) {
+            (Sky::Physical(_), _) => &sky,
+            (_, Some(background)) => background,
+            (_, None) => &sky,
+        };
+        let uniforms = fog_uniforms(
+            scene.fog.as_ref(),
+            origin,
+            &bvh,
+            camera_uniforms(&scene.camera, origin),
+        );
+        let uniforms = SceneUniforms {
+            light_count,
+            portal_count: scene.portals.len() as u32,
+            clip_plane_count: scene.clip_planes.len() as u32,
+            ..background_uniforms(
+                &sky,
+                background.as_ref(),
+                &mut textures,
+                physical_sky_uniforms(atmosphere, uniforms),
+            )
+        };
+
+        Ok(Self {
+            primitives,
+            bvh,
+            built_cost,
+            materials,
+            scene_materials: scene.materials.clone(),
+            overridden_materials,
+            textures,
+            density_grid: scene.fog.as_ref().and_then(|fog| fog.density.clone()),
+            uniforms,
+            origin,
+            lights,
+            light_profiles,
+            heightmaps,
+        })
+    }
+}
+
+impl SceneData {
+    /// Indices and new contents of the materials made from the ones of `library` with the
+    /// names of materials of the scene, including the copies objects override parameters of.
+    /// Fails if one of them is displaced differently or refers to a texture the scene doesn't
+    /// have.
+    pub fn material_updates(
+        &self,
+        library: &MaterialLibrary,
+    ) -> Result<Vec<(u32, MaterialRaw)>, SceneError> {
+        let mut updates = Vec::new();
+        for (index, material) in self.scene_materials.iter().enumerate() {
+            let Some(updated) = library.get(&material.name) else {
+                continue;
+            };
+            let error = |message: &str| SceneError::MaterialUpdate {
+                material: material.name.clone(),
+                message: message.to_owned(),
+            };
+            if updated.displacement_texture != material.displacement_texture
+                || updated.displacement_scale != material.displacement_scale
+                || updated.displacement_subdivisions != material.displacement_subdivisions
+            {
+                return Err(error("Displacement only changes with the scene"));
+            }
+
+            let mut textures = self.textures.clone();
+            updates.push((index as u32, material_raw(updated, &mut textures)));
+            for (overridden, _, overrides) in self
+                .overridden_materials
+                .iter()
+                .filter(|(_, base, _)| *base == index)
+            {
+                let material = overrides.apply(updated.clone());
+                updates.push((*overridden, material_raw(&material, &mut textures)));
+            }
+            if textures.len() > self.textures.len() {
+                return Err(error(
+                    "Textures the scene doesn't have only come with the scene",
+                ));
+            }
+        }
+        Ok(updates)
+    }
+
+    /// Primitives the leaves of the BVH refer to, before the records after them.
+    pub fn primitive_count(&self) -> u32 {
+        self.bvh.order.len() as u32
+    }
+
+    /// Whether every primitive is a triangle, leaving nothing out of rasterized visibility.
+    pub fn triangles_only(&self) -> bool {
+        self.primitives[..self.bvh.order.len()]
+            .iter()
+            .all(|primitive| {
+                primitive.kind == PRIMITIVE_TRIANGLE || primitive.kind == PRIMITIVE_SMOOTH_TRIANGLE
+            })
+    }
+
+    /// Whether `primitives`, in the order they were gathered from the scene, are the ones of
+    /// these data, possibly moved.
+    fn has_primitives(&self, primitives: &[PrimitiveRaw]) -> bool {
+        self.bvh.order.len() == primitives.len()
+            && self
+                .primitives
+                .iter()
+                .zip(&self.bvh.order)
+                .all(|(old, &index)| {
+                    let new = &primitives[index as usize];
+                    (old.kind, old.object) == (new.kind, new.object)
+                })
+    }
+}
+
+/// Ranges of the pieces of `contents` that differ from `previous`, merged where they touch.
+fn changed_ranges(previous: &[u8], contents: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (index, chunk) in contents.chunks(UPDATE_CHUNK_SIZE).enumerate() {
+        let start = index * UPDATE_CHUNK_SIZE;
+        let end = start + chunk.len();
+        if previous.get(start..end) == Some(chunk) {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(last) if last.end == start => last.end = end,
+            _ => ranges.push(start..end),
+        }
+    }
+    ranges
+}
+
+/// Calls `push` with the ends of the straight pieces of the strands of curves, transformed by
+/// `matrix`, and their parameters along their strand, or fails with why the control points or
+/// widths don't make up whole strands.
+fn push_curves(
+    points: &[[f32; 3]],
+    counts: &[u32],
+    widths: &[f32],
+    basis: CurveBasis,
+    matrix: &Matrix4<f32>,
+    mut push: impl FnMut([[f32; 4]; 2], [f32; 2]),
+) -> Result<(), String> {
+    if widths.len() != 1 && widths.len() != points.len() {
+        return Err(format!(
+            "{} widths for {} control points",
+            widths.len(),
+            points.len()
+        ));
+    }
+    if points.is_empty() {
+        return Ok(());
+    }
+    let all = [points.len() as u32];
+    let counts = if counts.is_empty() { &all[..] } else { counts };
+    if counts.iter().map(|&count| count as usize).sum::<usize>() != points.len() {
+        return Err("the counts of the strands don't add up to the control points".to_owned());
+    }
+
+    let scale = [matrix.x, matrix.y, matrix.z]
+        .map(|axis| axis.truncate().magnitude())
+        .into_iter()
+        .fold(0.0, f32::max);
+    // Weights of the four control points of a segment at `t`.
+    let weights = |t: f32| {
+        let s = 1.0 - t;
+        match basis {
+            CurveBasis::Bezier => [s * s * s, 3.0 * t * s * s, 3.0 * t * t * s, t * t * t],
+            CurveBasis::BSpline => [
+                s * s * s / 6.0,
+                (3.0 * t * t * t - 6.0 * t * t + 4.0) / 6.0,
+                (-3.0 * t * t * t + 3.0 * t * t + 3.0 * t + 1.0) / 6.0,
+                t * t * t / 6.0,
+            ],
+        }
+    };
+
+    let mut first = 0;
+    for &count in counts {
+        let count = count as usize;
+        let segments = match basis {
+            CurveBasis::Bezier if count >= 4 && (count - 1).is_multiple_of(3) => (count - 1) / 3,
+            CurveBasis::BSpline if count >= 4 => count - 3,
+            CurveBasis::Bezier => {
+                return Err(format!(
+                    "Bézier strands need 3n + 1 control points, not {count}"
+                ))
+            }
+            CurveBasis::BSpline => {
+                return Err(format!(
+                    "B-spline strands need at least 4 control points, not {count}"
+                ))
+            }
+        };
+
+        for segment in 0..segments {
+            let start = first
+                + match basis {
+                    CurveBasis::Bezier => segment * 3,
+                    CurveBasis::BSpline => segment,
+                };
+            let at = |t: f32| {
+                let weights = weights(t);
+                let mut point = Vector3::new(0.0, 0.0, 0.0);
+                let mut width = 0.0;
+                for (i, weight) in weights.into_iter().enumerate() {
+                    point += Vector3::from(points[start + i]) * weight;
+                    width += widths.get(start + i).unwrap_or(&widths[0]) * weight;
+                }
+                let point = matrix.transform_point(Point3::from_vec(point));
+                [point.x, point.y, point.z, 0.5 * width * scale]
+            };
+            for piece in 0..CURVE_PIECES {
+                let [t0, t1] = [piece, piece + 1].map(|i| i as f32 / CURVE_PIECES as f32);
+                let along = [t0, t1].map(|t| (segment as f32 + t) / segments as f32);
+                push([at(t0), at(t1)], along);
+            }
+        }
+        first += count;
+    }
+    Ok(())
+}
+
+/// Calls `push` with the kind and the transformed vertices of every triangle of a mesh, along
+/// with the normals of its vertices if there's one for every vertex, and its texture
+/// coordinates.
+/// Triangles of an object tessellated and displaced by the `(path, scale, subdivisions)` of its
+/// material, reading the map at `path` into `maps` unless it's there already.
+fn displace(
+    object: &str,
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    texcoords: &[[f32; 2]],
+    indices: &[u32],
+    (path, scale, subdivisions): (&PathBuf, f32, u32),
+    maps: &mut Vec<Heightmap>,
+) -> Result<MeshData, SceneError> {
+    if texcoords.len() != positions.len() {
+        return Err(SceneError::Geometry {
+            object: object.to_owned(),
+            message: "displacement needs texture coordinates".to_owned(),
+        });
+    }
+    let map = match maps.iter().position(|map| map.path == *path) {
+        Some(index) => index,
+        None => {
+            maps.push(Heightmap::read(path)?);
+            maps.len() - 1
+        }
+    };
+    Ok(displacement::displace(
+        positions,
+        normals,
+        texcoords,
+        indices,
+        &maps[map],
+        scale,
+        subdivisions,
+    ))
+}
+
+/// Checks that `indices` make whole triangles of the vertices of `positions`.
+fn check_triangles(positions: &[[f32; 3]], indices: &[u32]) -> Result<(), String> {
+    if !indices.len().is_multiple_of(3) {
+        return Err(format!(
+            "{} indices don't make whole triangles",
+            indices.len()
+        ));
+    }
+    match indices
+        .iter()
+        .find(|&&index| index as usize >= positions.len())
+    {
+        Some(index) => Err(format!(
+            "index {index} refers to a missing vertex, there are {}",
+            positions.len()
+        )),
+        None => Ok(()),
+    }
+}
+
+fn push_triangles(
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    texcoords: &[[f32; 2]],
+    indices: &[u32],
+    matrix: &Matrix4<f32>,
+    mut push: impl FnMut(u32, [[f32; 4]; 3], [[f32; 2]; 3]),
+) {
+    // Normals transform with the inverse transpose to stay perpendicular to the surface.
+    let normal_matrix = Matrix3::from_cols(
+        matrix.x.truncate(),
+        matrix.y.truncate(),
+        matrix.z.truncate(),
+    )
+    .invert()
+    .map(|inverse| inverse.transpose());
+    let smooth = normals.len() == positions.len() && normal_matrix.is_some();
+    let kind = if smooth {
+        PRIMITIVE_SMOOTH_TRIANGLE
+    } else {
+        PRIMITIVE_TRIANGLE
+    };
+
+    let vertex = |index: u32| {
+        let p = matrix.transform_point(Point3::from(positions[index as usize]));
+        let w = match normal_matrix {
+            Some(normal_matrix) if smooth => {
+                encode_normal(normal_matrix * Vector3::from(normals[index as usize]))
+            }
+            _ => 0.0,
+        };
+        [p.x, p.y, p.z, w]
+    };
+
+    let texcoord = |index: u32| texcoords[index as usize];
+    let textured = texcoords.len() == positions.len();
+    let mut push = |triangle: [u32; 3]| {
+        let texcoords = if textured {
+            triangle.map(texcoord)
+        } else {
+            BARYCENTRIC_TEXCOORDS
+        };
+        push(kind, triangle.map(vertex), texcoords);
+    };
+
+    if indices.is_empty() {
+        for i in (0..positions.len() as u32 / 3).map(|i| i * 3) {
+            push([i, i + 1, i + 2]);
+        }
+    } else {
+        for triangle in indices.chunks_exact(3) {
+            push([triangle[0], triangle[1], triangle[2]]);
+        }
+    }
+}
+
+/// Octahedral encoding of the direction of `normal` into two 16 bit snorms, stored in the bits of
+/// an `f32` and decoded by `decode_normal` in the shaders.
+fn encode_normal(normal: Vector3<f32>) -> f32 {
+    let sign = |x: f32| if x >= 0.0 { 1.0 } else { -1.0 };
+    let n = normal / (normal.x.abs() + normal.y.abs() + normal.z.abs()).max(f32::MIN_POSITIVE);
+    let [x, y] = if n.z >= 0.0 {
+        [n.x, n.y]
+    } else {
+        [(1.0 - n.y.abs()) * sign(n.x), (1.0 - n.x.abs()) * sign(n.y)]
+    };
+    let snorm = |x: f32| (x.clamp(-1.0, 1.0) * 32767.0).round() as i16 as u16 as u32;
+    f32::from_bits(snorm(x) | snorm(y) << 16)
+}
+
+/// Instruction of the program evaluating a signed distance field in the shaders, laid out like
+/// a primitive: the leaves push their distance with their center in `v0` and size in `v1`, the
+/// operations pop two and push their combination with the smoothness in `v0`.
+fn sdf_node(kind: u32, [v0, v1, v2]: [[f32; 4]; 3]) -> PrimitiveRaw {
+    PrimitiveRaw {
+        v0,
+        v1,
+        v2,
+        kind,
+        material: 0,
+        object: 0,
+        flags: 0,
+        texcoords: [[0.0; 2]; 3],
+        _padding1: [0; 2],
+    }
+}
+
+/// Header of a heightfield in `layer` of the heightfield array, `columns` by `rows` cells, with
+/// the rows of the transform from world space to the one of the grid in `v0`, `v1` and `v2`.
+/// The texture coordinates hold the derivative of the position along the first one, how many
+/// texels of a texture the terrain covers per unit of length over its area, and the cells.
+fn heightfield_header(
+    layer: u32,
+    [v0, v1, v2]: [[f32; 4]; 3],
+    [columns, rows]: [u32; 2],
+    unit: &Matrix4<f32>,
+) -> PrimitiveRaw {
+    let tangent = unit.x.truncate();
+    let area = tangent.cross(unit.z.truncate()).magnitude();
+    PrimitiveRaw {
+        v0,
+        v1,
+        v2,
+        kind: layer,
+        material: 0,
+        object: 0,
+        flags: 0,
+        texcoords: [
+            [tangent.x, tangent.y],
+            [tangent.z, 1.0 / area.max(f32::MIN_POSITIVE).sqrt()],
+            [columns as f32, rows as f32],
+        ],
+        _padding1: [0; 2],
+    }
+}
+
+/// Appends the program of `sdf` to `nodes` in postfix order, the deeper operand of operations
+/// where order doesn't matter evaluated first to keep fewer distances at once.
+fn push_sdf(sdf: &Sdf, nodes: &mut Vec<PrimitiveRaw>) {
+    let leaf = |kind, [x, y, z]: [f32; 3], v1| sdf_node(kind, [[x, y, z, 0.0], v1, [0.0; 4]]);
+    let (kind, a, b, smoothness) = match sdf {
+        Sdf::Sphere { center, radius } => {
+            return nodes.push(leaf(SDF_SPHERE, *center, [*radius, 0.0, 0.0, 0.0]));
+        }
+        Sdf::Cuboid {
+            center,
+            half_extents: [x, y, z],
+            rounding,
+        } => return nodes.push(leaf(SDF_CUBOID, *center, [*x, *y, *z, *rounding])),
+        Sdf::Torus {
+            center,
+            major_radius,
+            minor_radius,
+        } => {
+            let size = [*major_radius, *minor_radius, 0.0, 0.0];
+            return nodes.push(leaf(SDF_TORUS, *center, size));
+        }
+        Sdf::Cylinder {
+            center,
+            radius,
+            half_height,
+        } => {
+            let size = [*radius, *half_height, 0.0, 0.0];
+            return nodes.push(leaf(SDF_CYLINDER, *center, size));
+        }
+        Sdf::Union { a, b, smoothness } => (SDF_UNION, a, b, smoothness),
+        Sdf::Intersection { a, b, smoothness } => (SDF_INTERSECTION, a, b, smoothness),
+        Sdf::Subtraction { a, b, smoothness } => (SDF_SUBTRACTION, a, b, smoothness),
+    };
+
+    let (a, b) = if kind != SDF_SUBTRACTION && sdf_stack_depth(b) > sdf_stack_depth(a) {
+        (b, a)
+    } else {
+        (a, b)
+    };
+    push_sdf(a, nodes);
+    push_sdf(b, nodes);
+    nodes.push(sdf_node(
+        kind,
+        [[smoothness.max(0.0), 0.0, 0.0, 0.0], [0.0; 4], [0.0; 4]],
+    ));
+}
+
+/// Appends the solids and operations of `csg` to `nodes` in postfix order.
+fn push_csg(csg: &Csg, nodes: &mut Vec<PrimitiveRaw>) {
+    let leaf = |kind, [x, y, z]: [f32; 3], v1| sdf_node(kind, [[x, y, z, 0.0], v1, [0.0; 4]]);
+    let (kind, a, b) = match csg {
+        Csg::Sphere { center, radius } => {
+            return nodes.push(leaf(CSG_SPHERE, *center, [*radius, 0.0, 0.0, 0.0]));
+        }
+        Csg::Cuboid {
+            center,
+            half_extents: [x, y, z],
+        } => return nodes.push(leaf(CSG_CUBOID, *center, [*x, *y, *z, 0.0])),
+        Csg::Cylinder {
+            center,
+            radius,
+            half_height,
+        } => {
+            let size = [*radius, *half_height, 0.0, 0.0];
+            return nodes.push(leaf(CSG_CYLINDER, *center, size));
+        }
+        Csg::Union { a, b } => (CSG_UNION, a, b),
+        Csg::Intersection { a, b } => (CSG_INTERSECTION, a, b),
+        Csg::Difference { a, b } => (CSG_DIFFERENCE, a, b),
+    };
+
+    push_csg(a, nodes);
+    push_csg(b, nodes);
+    nodes.push(sdf_node(kind, [[0.0; 4]; 3]));
+}
+
+/// Box containing the one from `min` to `max` transformed by `matrix`.
+fn transformed_aabb(matrix: &Matrix4<f32>, [min, max]: [[f32; 3]; 2]) -> Aabb {
+    let corners = (0..8).map(|corner| {
+        let pick = |axis: usize| {
+            if corner >> axis & 1 == 0 {
+                min[axis]
+            } else {
+                max[axis]
+            }
+        };
+        matrix
+            .transform_point(Point3::new(pick(0), pick(1), pick(2)))
+            .into()
+    });
+    Aabb::from_points(&corners.collect::<Vec<_>>())
+}
+
+/// Distances the program [`push_sdf`] makes of `sdf` keeps at once.
+fn sdf_stack_depth(sdf: &Sdf) -> usize {
+    match sdf {
+        Sdf::Sphere { .. } | Sdf::Cuboid { .. } | Sdf::Torus { .. } | Sdf::Cylinder { .. } => 1,
+        Sdf::Union { a, b, .. } | Sdf::Intersection { a, b, .. } => {
+            let (a, b) = (sdf_stack_depth(a), sdf_stack_depth(b));
+            a.max(b).max(a.min(b) + 1)
+        }
+        Sdf::Subtraction { a, b, .. } => sdf_stack_depth(a).max(sdf_stack_depth(b) + 1),
+    }
+}
+
+fn primitive_aabb(primitive: &PrimitiveRaw) -> Aabb {
+    let xyz = |v: [f32; 4]| [v[0], v[1], v[2]];
+    match primitive.kind {
+        PRIMITIVE_SPHERE | PRIMITIVE_POINT => {
+            let [x, y, z, radius] = primitive.v0;
+            Aabb {
+                min: [x - radius, y - radius, z - radius],
+                max: [x + radius, y + radius, z + radius],
+            }
+        }
+        PRIMITIVE_CURVE => {
+            let radius = primitive.v0[3].max(primitive.v1[3]);
+            let aabb = Aabb::from_points(&[xyz(primitive.v0), xyz(primitive.v1)]);
+            Aabb {
+                min: aabb.min.map(|c| c - radius),
+                max: aabb.max.map(|c| c + radius),
+            }
+        }
+        PRIMITIVE_CUSTOM | PRIMITIVE_SDF | PRIMITIVE_CSG | PRIMITIVE_HEIGHTFIELD => {
+            Aabb::from_points(&[xyz(primitive.v0), xyz(primitive.v1)])
+        }
+        PRIMITIVE_QUAD => {
+            let [v0, v1, v2] = [primitive.v0, primitive.v1, primitive.v2].map(xyz);
+            let v3 = [0, 1, 2].map(|axis| v1[axis] + v2[axis] - v0[axis]);
+            Aabb::from_points(&[v0, v1, v2, v3])
+        }
+        PRIMITIVE_BOX => {
+            // The rows of the transform from world space to the unit cube.
+            let inverse = Matrix4::from_cols(
+                primitive.v0.into(),
+                primitive.v1.into(),
+                primitive.v2.into(),
+                [0.0, 0.0, 0.0, 1.0].into(),
+            )
+            .transpose();
+            let unit = inverse.invert().unwrap_or_else(Matrix4::identity);
+            transformed_aabb(&unit, [[0.0; 3], [1.0; 3]])
+        }
+        _ => Aabb::from_points(&[xyz(primitive.v0), xyz(primitive.v1), xyz(primitive.v2)]),
+    }
+}
+
+/// Index of the texture at `path` encoded in `space` in `textures`, added to them if it isn't
+/// there yet. The same image in different spaces is uploaded once for each.
+fn texture_index(path: &Path, space: ColorSpace, textures: &mut Vec<(PathBuf, ColorSpace)>) -> u32 {
+    let layer = textures
+        .iter()
+        .position(|texture| texture.0 == path && texture.1 == space);
+    layer.unwrap_or_else(|| {
+        textures.push((path.to_owned(), space));
+        textures.len() - 1
+    }) as u32
+}
+
+/// The material as laid out in the shaders, adding its base color texture to `textures` unless
+/// another material shares it.
+fn material_raw(material: &Material, textures: &mut Vec<(PathBuf, ColorSpace)>) -> MaterialRaw {
+    let base_color_texture = material
+        .base_color_texture
+        .as_ref()
+        .map_or(NO_TEXTURE, |path| {
+            texture_index(path, material.base_color_texture_space, textures)
+        });
+    MaterialRaw {
+        base_color: material.base_color,
+        metallic: material.metallic,
+        emission: material.emission,
+        roughness: material.roughness,
+        ior: material.ior,
+        transmission: material.transmission,
+        custom: material.custom.unwrap_or(MATERIAL_BUILTIN),
+        opacity: material.opacity,
+        base_color_texture,
+        specular: material.specular,
+        sheen: material.sheen,
+        sheen_tint: material.sheen_tint,
+        anisotropic: material.anisotropic,
+        anisotropic_rotation: material.anisotropic_rotation,
+        clearcoat: material.clearcoat,
+        clearcoat_roughness: material.clearcoat_roughness,
+        clearcoat_ior: material.clearcoat_ior,
+        thin_film_thickness: material.thin_film_thickness,
+        thin_film_ior: material.thin_film_ior,
+        subsurface: material.subsurface,
+        subsurface_scattering: material.subsurface_scattering,
+        abbe_number: material.abbe_number.max(0.0),
+        subsurface_absorption: material.subsurface_absorption,
+        hair: material.hair,
+        hair_azimuthal_roughness: material.hair_azimuthal_roughness.clamp(0.0, 1.0),
+        hair_scale_angle: material.hair_scale_angle,
+        shadow_catcher: material.shadow_catcher as u32,
+        priority: material.priority,
+        texture_transform: texture_transform(material.texture_offset, material.texture_rotation),
+        _padding: [0; 2],
+    }
+}
+
+/// Columns of the transform rotating texture coordinates by `rotation` turns around the center
+/// of the texture, then moving them by `offset`.
+fn texture_transform(offset: [f32; 2], rotation: f32) -> [[f32; 2]; 3] {
+    let (sin, cos) = (rotation * std::f32::consts::TAU).sin_cos();
+    let center = [0.5, 0.5];
+    [
+        [cos, sin],
+        [-sin, cos],
+        [
+            center[0] - (cos * center[0] - sin * center[1]) + offset[0],
+            center[1] - (sin * center[0] + cos * center[1]) + offset[1],
+        ],
+    ]
+}
+
+/// `position` in the world relative to `origin`, subtracted in double precision to keep the
+/// precision of positions close to it, see [`Scene::origin`].
+fn rebase(position: [f32; 3], origin: [f64; 3]) -> [f32; 3] {
+    [0, 1, 2].map(|axis| (position[axis] as f64 - origin[axis]) as f32)
+}
+
+/// The light around `origin` as laid out in the shaders, adding the file of its profile to
+/// `profiles` unless it's there already.
+fn light_raw(light: &Light, origin: [f64; 3], profiles: &mut Vec<PathBuf>) -> LightRaw {
+    let mut profile_frame = (NO_TEXTURE, [0.0; 3], [0.0; 3]);
+    let (kind, position, color, intensity, angular_diameter) = match *light {
+        Light::Point {
+            position,
+            color,
+            intensity,
+            ref profile,
+        } => {
+            if let Some(profile) = profile {
+                let layer = profiles.iter().position(|path| *path == profile.path);
+                let layer = layer.unwrap_or_else(|| {
+                    profiles.push(profile.path.clone());
+                    profiles.len() - 1
+                });
+                let axis = Vector3::from(profile.axis).normalize();
+                let reference = Vector3::from(profile.reference);
+                let reference = (reference - axis * axis.dot(reference)).normalize();
+                profile_frame = (layer as u32, axis.into(), reference.into());
+            }
+            (LIGHT_POINT, rebase(position, origin), color, intensity, 0.0)
+        }
+        Light::Directional {
+            direction,
+            color,
+            intensity,
+            angular_diameter,
+        } => (
+            LIGHT_DIRECTIONAL,
+            Vector3::from(direction).normalize().into(),
+            color,
+            intensity,
+            angular_diameter,
+        ),
+    };
+
+    LightRaw {
+        position,
+        kind,
+        intensity: color.map(|c| c * intensity),
+        cos_half_angle: (angular_diameter.clamp(0.0, 180.0) / 2.0)
+            .to_radians()
+            .cos(),
+        profile_axis: profile_frame.1,
+        profile: profile_frame.0,
+        profile_reference: profile_frame.2,
+        power_cdf: 0.0,
+        link: 0,
+        _padding1: [0; 3],
+    }
+}
+
+/// Sets the `power_cdf` of `lights` to the running fraction of their total power, or of their
+/// number if none has any, which the shaders pick them by. Directional lights emit their
+/// power through the disk of `scene_radius` facing them, as they do when tracing light from
+/// the lights.
+fn set_power_cdf(lights: &mut [LightRaw], scene_radius: f32) {
+    let powers: Vec<f32> = lights
+        .iter()
+        .map(|light| {
+            let [r, g, b] = light.intensity;
+            let luminance = (0.2126 * r + 0.7152 * g + 0.0722 * b).max(0.0);
+            match light.kind {
+                LIGHT_DIRECTIONAL => luminance * std::f32::consts::PI * scene_radius * scene_radius,
+                _ => luminance * 4.0 * std::f32::consts::PI,
+            }
+        })
+        .collect();
+    let total: f32 = powers.iter().sum();
+    let count = lights.len() as f32;
+
+    let mut sum = 0.0;
+    for (light, power) in lights.iter_mut().zip(powers) {
+        sum += if total > 0.0 {
+            power / total
+        } else {
+            1.0 / count
+        };
+        light.power_cdf = sum;
+    }
+    // Rounding can't leave the last one out.
+    if let Some(last) = lights.last_mut() {
+        last.power_cdf = 1.0;
+    }
+}
+
+/// `portal` around `origin` as laid out in the shaders, its edges in place of the directions of
+/// light profiles.
+fn portal_raw(portal: &Portal, origin: [f64; 3]) -> LightRaw {
+    let [u, v] = portal.edges;
+    LightRaw {
+        position: rebase(portal.corner, origin),
+        kind: LIGHT_PORTAL,
+        intensity: [0.0; 3],
+        cos_half_angle: 1.0,
+        profile_axis: u,
+        profile: NO_TEXTURE,
+        profile_reference: v,
+        power_cdf: 1.0,
+        link: 0,
+        _padding1: [0; 3],
+    }
+}
+
+/// `plane` as laid out in the shaders, its normal in place of the axis of light profiles and the
+/// material of its caps in place of the profile.
+fn clip_plane_raw(scene: &Scene, plane: &ClipPlane) -> Result<LightRaw, SceneError> {
+    let cap_material = match &plane.cap_material {
+        Some(name) => scene.material_index(name)? as u32,
+        None => NO_CAP,
+    };
+    Ok(LightRaw {
+        position: rebase(plane.point, scene.origin()),
+        kind: LIGHT_CLIP_PLANE,
+        intensity: [0.0; 3],
+        cos_half_angle: 1.0,
+        profile_axis: Vector3::from(plane.normal).normalize().into(),
+        profile: cap_material,
+        profile_reference: [0.0; 3],
+        power_cdf: 1.0,
+        link: 0,
+        _padding1: [0; 3],
+    })
+}
+
+/// Uniforms with `camera` placed around `origin`, everything else zero.
+fn camera_uniforms(camera: &Camera, origin: [f64; 3]) -> SceneUniforms {
+    let position = Point3::from(rebase(camera.position, origin));
+    let forward = (Point3::from(rebase(camera.look_at, origin)) - position).normalize();
+    let right = forward.cross(Vector3::from(camera.up)).normalize();
+    let up = right.cross(forward);
+
+    SceneUniforms {
+        camera_position: position.to_vec().into(),
+        tan_half_fov: (camera.fov.to_radians() * 0.5).tan(),
+        camera_forward: forward.into(),
+        light_count: 0,
+        camera_right: right.into(),
+        camera_projection: PROJECTION_PERSPECTIVE,
+        camera_up: up.into(),
+        portal_count: 0,
+        fog_scattering: [0.0; 3],
+        fog_anisotropy: 0.0,
+        fog_absorption: [0.0; 3],
+        fog_density_grid: 0,
+        fog_min: [0.0; 3],
+        clip_plane_count: 0,
+        fog_max: [0.0; 3],
+        sky_kind: SKY_GRADIENT,
+        sky_a: [0.0; 3],
+        sky_ground_albedo: 0.0,
+        sky_b: [0.0; 3],
+        sky_texture: NO_TEXTURE,
+        sky_c: [0.0; 3],
+        sky_intensity: 0.0,
+        sky_d: [0.0; 3],
+        sky_rotation: 0.0,
+        sky_e: [0.0; 3],
+        background_kind: BACKGROUND_SKY,
+        sky_zenith: [0.0; 3],
+        background_texture: NO_TEXTURE,
+        sun_direction: [0.0; 3],
+        background_intensity: 0.0,
+        sky_bottom: [0.0; 3],
+        background_rotation: 0.0,
+        sky_top: [0.0; 3],
+        camera_shift_x: camera.shift[0],
+        background_bottom: [0.0; 3],
+        camera_shift_y: camera.shift[1],
+        background_top: [0.0; 3],
+        _padding7: 0,
+    }
+}
+
+/// `uniforms` with the coefficients and box of `fog` around `origin`, which fills the root of
+/// `bvh` without bounds of its own. Without fog, or any space for it to fill, the coefficients
+/// stay zero.
+fn fog_uniforms(
+    fog: Option<&Fog>,
+    origin: [f64; 3],
+    bvh: &Bvh,
+    uniforms: SceneUniforms,
+) -> SceneUniforms {
+    let Some(fog) = fog else {
+        return uniforms;
+    };
+    let [min, max] = match (fog.bounds, bvh.nodes.first()) {
+        (Some(bounds), _) => bounds.map(|corner| rebase(corner, origin)),
+        (None, Some(root)) => [root.min, root.max],
+        (None, None) => return uniforms,
+    };
+    if (0..3).any(|axis| min[axis] >= max[axis]) {
+        return uniforms;
+    }
+
+    SceneUniforms {
+        fog_scattering: fog.scattering.map(|c| c.max(0.0)),
+        fog_anisotropy: fog.anisotropy.clamp(-0.99, 0.99),
+        fog_absorption: fog.absorption.map(|c| c.max(0.0)),
+        fog_density_grid: fog.density.is_some() as u32,
+        fog_min: min,
+        fog_max: max,
+        ..uniforms
+    }
+}
+
+/// `uniforms` with the kinds and parameters of `sky` and the `background` of camera rays, whose
+/// textures are added to `textures`. Physical ones need the coefficients of
+/// [`physical_sky_uniforms`] already in `uniforms`.
+fn background_uniforms(
+    sky: &Sky,
+    background: Option<&Sky>,
+    textures: &mut Vec<(PathBuf, ColorSpace)>,
+    uniforms: SceneUniforms,
+) -> SceneUniforms {
+    // Kind, bottom and top of the gradient, texture, intensity and turns of the rotation.
+    let mut parameters = |sky: &Sky| match sky {
+        Sky::Gradient => (
+            SKY_GRADIENT,
+            [1.0; 3],
+            [0.5, 0.7, 1.0],
+            NO_TEXTURE,
+            0.0,
+            0.0,
+        ),
+        Sky::Physical(_) => (SKY_PHYSICAL, [0.0; 3], [0.0; 3], NO_TEXTURE, 0.0, 0.0),
+        Sky::Color(color) => (SKY_GRADIENT, *color, *color, NO_TEXTURE, 0.0, 0.0),
+        Sky::VerticalGradient { bottom, top } => {
+            (SKY_GRADIENT, *bottom, *top, NO_TEXTURE, 0.0, 0.0)
+        }
+        Sky::Texture(texture) => (
+            SKY_TEXTURE,
+            [0.0; 3],
+            [0.0; 3],
+            texture_index(&texture.path, texture.color_space, textures),
+            texture.intensity.max(0.0),
+            texture.rotation / 360.0,
+        ),
+    };
+    let (sky_kind, sky_bottom, sky_top, sky_texture, sky_intensity, sky_rotation) = parameters(sky);
+    let uniforms = SceneUniforms {
+        sky_kind,
+        sky_bottom,
+        sky_top,
+        sky_texture,
+        sky_intensity,
+        sky_rotation,
+        ..uniforms
+    };
+    let Some(background) = background else {
+        return uniforms;
+    };
+    let (kind, bottom, top, texture, intensity, rotation) = parameters(background);
+    SceneUniforms {
+        background_kind: kind,
+        background_bottom: bottom,
+        background_top: top,
+        background_texture: texture,
+        background_intensity: intensity,
+        background_rotation: rotation,
+        ..uniforms
+    }
+}
+
+/// `uniforms` with the coefficients of the physical model of `sky`: the five of the Perez
+/// formula of the luminance and the two chromaticity coordinates, and their values at the
+/// zenith over the formula there.
+fn physical_sky_uniforms(sky: &Sky, uniforms: SceneUniforms) -> SceneUniforms {
+    let Sky::Physical(sky) = sky else {
+        return uniforms;
+    };
+    let t = sky.turbidity.clamp(1.0, 20.0);
+    let sun = Vector3::from(sky.sun_direction);
+    let sun = if sun.magnitude2() > 0.0 {
+        sun.normalize()
+    } else {
+        Vector3::unit_y()
+    };
+    let theta = sun.y.clamp(0.0, 1.0).acos();
+
+    // Columns for the luminance Y and the x and y chromaticity, in that order.
+    let coefficients = [
+        [
+            0.1787 * t - 1.4630,
+            -0.0193 * t - 0.2592,
+            -0.0167 * t - 0.2608,
+        ],
+        [
+            -0.3554 * t + 0.4275,
+            -0.0665 * t + 0.0008,
+            -0.0950 * t + 0.0092,
+        ],
+        [
+            -0.0227 * t + 5.3251,
+            -0.0004 * t + 0.2125,
+            -0.0079 * t + 0.2102,
+        ],
+        [
+            0.1206 * t - 2.5771,
+            -0.0641 * t - 0.8989,
+            -0.0441 * t - 1.6537,
+        ],
+        [
+            -0.0670 * t + 0.3703,
+            -0.0033 * t + 0.0452,
+            -0.0109 * t + 0.0529,
+        ],
+    ];
+    let [a, b, c, d, e] = coefficients;
+
+    let chi = (4.0 / 9.0 - t / 120.0) * (std::f32::consts::PI - 2.0 * theta);
+    let luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+    let chromaticity = |[t2, t1, t0]: [[f32; 4]; 3]| {
+        let cubic = |[c3, c2, c1, c0]: [f32; 4]| ((c3 * theta + c2) * theta + c1) * theta + c0;
+        t * t * cubic(t2) + t * cubic(t1) + cubic(t0)
+    };
+    let x = chromaticity([
+        [0.00166, -0.00375, 0.00209, 0.0],
+        [-0.02903, 0.06377, -0.03202, 0.00394],
+        [0.11693, -0.21196, 0.06052, 0.25886],
+    ]);
+    let y = chromaticity([
+        [0.00275, -0.00610, 0.00317, 0.0],
+        [-0.04214, 0.08970, -0.04153, 0.00516],
+        [0.15346, -0.26756, 0.06670, 0.26688],
+    ]);
+
+    // The formula at the zenith, which is `theta` away from the sun.
+    let zenith = [luminance * sky.intensity.max(0.0), x, y];
+    let zenith = std::array::from_fn(|i| {
+        let perez = (1.0 + a[i] * b[i].exp())
+            * (1.0 + c[i] * (d[i] * theta).exp() + e[i] * theta.cos().powi(2));
+        zenith[i] / perez
+    });
+
+    SceneUniforms {
+        sky_a: a,
+        sky_ground_albedo: sky.ground_albedo.clamp(0.0, 1.0),
+        sky_b: b,
+        sky_c: c,
+        sky_d: d,
+        sky_e: e,
+        sky_zenith: zenith,
+        sun_direction: sun.into(),
+        ..uniforms
+    }
+}
+
+/// Scene uploaded to the GPU.
+pub(crate) struct GpuScene {
+    /// Shared with the scenes [updated](Self::update) from this one.
+    resources: Arc<SceneResources>,
+    /// What the buffers hold, kept to refit the BVH of the next scene.
+    pub data: SceneData,
+}
+
+/// What a [`GpuScene`] allocated on the GPU.
+struct SceneResources {
+    /// Buffers in binding order.
+    buffers: Vec<Buffer>,
+    textures: TextureCache,
+    density: DensityTexture,
+    light_profiles: LightProfileArray,
+    heightfields: HeightfieldArray,
+    bind_group: BindGroup,
+}
+
+/// Bind group of the scene with the uniforms and storage `buffers` in binding order, then the
+/// cache of `textures` and the views of the density, light profile and heightfield textures.
+fn create_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    buffers: &[&Buffer],
+    textures: &TextureCache,
+    views: [&TextureView; 3],
+) -> BindGroup {
+    let mut entries: Vec<_> = buffers
+        .iter()
+        .enumerate()
+        .map(|(binding, buffer)| BindGroupEntry {
+            binding: binding as u32,
+            resource: buffer.as_entire_binding(),
+        })
+        .collect();
+    entries.push(BindGroupEntry {
+        binding: 5,
+        resource: BindingResource::TextureView(&textures.view),
+    });
+    entries.push(BindGroupEntry {
+        binding: 6,
+        resource: BindingResource::Sampler(&textures.sampler),
+    });
+    for (binding, view) in (7..).zip(views) {
+        entries.push(BindGroupEntry {
+            binding,
+            resource: BindingResource::TextureView(view),
+        });
+    }
+    entries.push(BindGroupEntry {
+        binding: 10,
+        resource: BindingResource::TextureView(&textures.page_table_view),
+    });
+
+    device.create_bind_group(&BindGroupDescriptor {
+        label: Some("Scene bind group"),
+        layout,
+        entries: &entries,
+    })
+}
+
+impl GpuScene {
+    pub fn bind_group_layout(device: &Device) -> BindGroupLayout {
+        let storage = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Scene bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(
+                            std::mem::size_of::<SceneUniforms>() as u64
+                        ),
+                    },
+                    count: None,
+                },
+                storage(1),
+                storage(2),
+                storage(3),
+                storage(4),
+                BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 10,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Uint,
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    /// Fails if a texture, the density grid, a light profile or a heightfield of the scene can't
+    /// be loaded. The textures are streamed through a cache of `texture_budget` bytes if there's
+    /// one and they don't fit in it.
+    pub fn upload(
+        device: &Device,
+        queue: &Queue,
+        layout: &BindGroupLayout,
+        data: SceneData,
+        texture_budget: Option<u64>,
+    ) -> Result<Self, SceneError> {
+        let _span = tracing::info_span!(
+            "upload_scene",
+            primitives = data.primitives.len(),
+            nodes = data.bvh.nodes.len()
+        )
+        .entered();
+
+        // Devices fail to bind bigger buffers with an error that doesn't say which one it is.
+        let limit = device.limits().max_storage_buffer_binding_size as u64;
+        let labels = ["Primitives", "BVH nodes", "Materials", "Lights"];
+        for (label, contents) in labels.into_iter().zip(&data.contents()[1..]) {
+            if contents.len() as u64 > limit {
+                return Err(SceneError::TooLarge {
+                    what: label,
+                    size: contents.len() as u64,
+                    limit,
+                });
+            }
+        }
+
+        let uniforms = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Scene uniform buffer"),
+            contents: data.uniforms.as_bytes(),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        // Bindings can't be empty, scenes without primitives or lights get a single unused one.
+        let storage = |label, contents: &[u8], element_size| {
+            let padding = vec![0; element_size];
+            device.create_buffer_init(&BufferInitDescriptor {
+                label: Some(label),
+                contents: if contents.is_empty() {
+                    &padding
+                } else {
+                    contents
+                },
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            })
+        };
+
+        let buffers = vec![
+            uniforms,
+            storage(
+                "Primitive buffer",
+                data.primitives.as_bytes(),
+                std::mem::size_of::<PrimitiveRaw>(),
+            ),
+            storage(
+                "BVH node buffer",
+                data.bvh.nodes.as_bytes(),
+                std::mem::size_of::<BvhNode>(),
+            ),
+            storage(
+                "Material buffer",
+                data.materials.as_bytes(),
+                std::mem::size_of::<MaterialRaw>(),
+            ),
+            storage(
+                "Light buffer",
+                data.lights.as_bytes(),
+                std::mem::size_of::<LightRaw>(),
+            ),
+        ];
+
+        let textures = TextureCache::load(device, queue, &data.textures, texture_budget)?;
+        let density = DensityTexture::load(device, queue, data.density_grid.as_ref())?;
+        let light_profiles = LightProfileArray::load(device, queue, &data.light_profiles)?;
+        let heightfields = HeightfieldArray::load(device, queue, &data.heightmaps)?;
+
+        let bind_group = create_bind_group(
+            device,
+            layout,
+            &buffers.iter().collect::<Vec<_>>(),
+            &textures,
+            [&density.view, &light_profiles.view, &heightfields.view],
+        );
+
+        Ok(Self {
+            resources: Arc::new(SceneResources {
+                buffers,
+                textures,
+                density,
+                light_profiles,
+                heightfields,
+                bind_group,
+            }),
+            data,
+        })
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.resources.bind_group
+    }
+
+    /// Buffer of the `SceneUniforms` of the shaders, with the camera.
+    pub fn uniform_buffer(&self) -> &Buffer {
+        &self.resources.buffers[0]
+    }
+
+    /// Buffer of the primitives, in the order the leaves of the BVH refer to them.
+    pub fn primitive_buffer(&self) -> &Buffer {
+        &self.resources.buffers[1]
+    }
+
+    /// Bytes of GPU memory the cache of the textures takes.
+    pub fn texture_cache_size(&self) -> u64 {
+        self.resources.textures.size
+    }
+
+    /// Whether the textures don't all fit in their cache, so that renders have to read back
+    /// the pages they ask for and [stream](Self::stream_textures) them in.
+    pub fn streams_textures(&self) -> bool {
+        self.resources.textures.streams()
+    }
+
+    /// Streams the texture pages of the page table entries `requests` into the cache for the
+    /// work submitted next.
+    pub fn stream_textures(&self, queue: &Queue, requests: Vec<u32>) {
+        self.resources.textures.stream(queue, requests);
+    }
+
+    /// Whether every part of `data` fits in the current buffers, with the same textures.
+    pub fn fits(&self, data: &SceneData) -> bool {
+        data.textures == self.data.textures
+            && data.density_grid == self.data.density_grid
+            && data.light_profiles == self.data.light_profiles
+            && data
+                .heightmaps
+                .iter()
+                .map(|heightmap| &heightmap.path)
+                .eq(self.data.heightmaps.iter().map(|heightmap| &heightmap.path))
+            && data
+                .contents()
+                .iter()
+                .zip(&self.resources.buffers)
+                .all(|(contents, buffer)| contents.len() as u64 <= buffer.size())
+    }
+
+    /// Overwrites the buffers of the scene with `data` without allocating, which has to
+    /// [fit](Self::fits), returning the scene they hold now. Work submitted afterwards sees
+    /// `data` through either of them, so this one shouldn't be rendered anymore.
+    ///
+    /// Only the pieces of the primitives, nodes and lights that differ from the current data
+    /// are written, so moving an instance, giving an object another material or changing a
+    /// light uploads little more than what changed.
+    pub fn update(&self, queue: &Queue, data: SceneData) -> Self {
+        // The uniforms and materials can differ from the current data, with another camera or
+        // updated materials, so they're written whole.
+        let compared = [false, true, true, false, true];
+        let mut written = 0;
+        // Elements past the end of the new data are never referenced by it.
+        for (((contents, previous), buffer), compared) in data
+            .contents()
+            .iter()
+            .zip(self.data.contents())
+            .zip(&self.resources.buffers)
+            .zip(compared)
+        {
+            let previous = if compared { previous } else { &[] };
+            for range in changed_ranges(previous, contents) {
+                written += range.len();
+                queue.write_buffer(buffer, range.start as u64, &contents[range]);
+            }
+        }
+        tracing::debug!(bytes = written, "Updated scene in place");
+        Self {
+            resources: self.resources.clone(),
+            data,
+        }
+    }
+
+    /// Writes the parameters of the materials of `library` over the ones of the scene with the
+    /// same names, see [`SceneData::material_updates`]. Work submitted afterwards sees them.
+    pub fn update_materials(
+        &self,
+        queue: &Queue,
+        library: &MaterialLibrary,
+    ) -> Result<(), SceneError> {
+        let size = std::mem::size_of::<MaterialRaw>() as u64;
+        for (index, material) in self.data.material_updates(library)? {
+            queue.write_buffer(
+                &self.resources.buffers[3],
+                index as u64 * size,
+                material.as_bytes(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Makes later renders look all around `position` in the world on an octahedral map instead
+    /// of through the camera of the scene, or through it again without a position.
+    pub fn set_probe_camera(&self, queue: &Queue, position: Option<[f32; 3]>) {
+        let mut uniforms = self.data.uniforms;
+        if let Some(position) = position {
+            uniforms.camera_position = rebase(position, self.data.origin);
+            uniforms.camera_projection = PROJECTION_OCTAHEDRAL;
+        }
+        queue.write_buffer(&self.resources.buffers[0], 0, uniforms.as_bytes());
+    }
+
+    /// Bind group of the scene seen through `eye` of `stereo` instead of its camera, with a
+    /// uniform buffer of its own, for a render to bind in place of [`bind_group`](Self::bind_group) without
+    /// changing what other renders of the scene see. The bind group keeps the buffer alive.
+    pub fn eye_bind_group(
+        &self,
+        device: &Device,
+        layout: &BindGroupLayout,
+        stereo: Stereo,
+        eye: Eye,
+    ) -> BindGroup {
+        let mut uniforms = self.data.uniforms;
+        let camera = Vector3::from(uniforms.camera_position);
+        let forward = Vector3::from(uniforms.camera_forward);
+        let right = Vector3::from(uniforms.camera_right);
+        let position = camera + right * stereo.offset(eye);
+        uniforms.camera_position = position.into();
+        if stereo.convergence.is_finite() {
+            let forward = (camera + forward * stereo.convergence - position).normalize();
+            let right = forward.cross(Vector3::from(uniforms.camera_up)).normalize();
+            uniforms.camera_forward = forward.into();
+            uniforms.camera_right = right.into();
+            uniforms.camera_up = right.cross(forward).into();
+        }
+
+        let uniforms = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Eye uniform buffer"),
+            contents: uniforms.as_bytes(),
+            usage: BufferUsages::UNIFORM,
+        });
+        let resources = &self.resources;
+        let mut buffers: Vec<_> = resources.buffers.iter().collect();
+        buffers[0] = &uniforms;
+        create_bind_group(
+            device,
+            layout,
+            &buffers,
+            &resources.textures,
+            [
+                &resources.density.view,
+                &resources.light_profiles.view,
+                &resources.heightfields.view,
+            ],
+        )
+    }
+}
+
+impl SceneData {
+    /// Bytes of GPU memory the scene takes besides the cache of its textures, which takes what's
+    /// left of the budget, or what its textures need.
+    pub fn gpu_size(&self) -> u64 {
+        self.geometry_size() + self.bvh_size() + self.material_size() + self.texture_size()
+    }
+
+    /// Bytes of the primitives, with the programs of the signed distance fields.
+    pub fn geometry_size(&self) -> u64 {
+        self.primitives.as_bytes().len() as u64
+    }
+
+    /// Bytes of the nodes of the BVH.
+    pub fn bvh_size(&self) -> u64 {
+        self.bvh.nodes.as_bytes().len() as u64
+    }
+
+    /// Bytes of the materials, the lights and the uniforms.
+    pub fn material_size(&self) -> u64 {
+        (self.uniforms.as_bytes().len() + self.materials.as_bytes().len()) as u64
+            + self.lights.as_bytes().len() as u64
+    }
+
+    /// Bytes of the textures besides the cache of the material textures: the density grid, the
+    /// light profiles and the heightfields.
+    pub fn texture_size(&self) -> u64 {
+        let density = self.density_grid.as_ref().map_or(0, |grid| {
+            grid.resolution.iter().map(|&n| n as u64).product()
+        });
+        let light_profiles =
+            self.light_profiles.len() as u64 * PROFILE_WIDTH as u64 * PROFILE_HEIGHT as u64 * 4;
+        let heightfields = self.heightmaps.len() as u64
+            * self.heightmaps.iter().map(|h| h.width).max().unwrap_or(1) as u64
+            * self.heightmaps.iter().map(|h| h.height).max().unwrap_or(1) as u64
+            * 4;
+        density + light_profiles + heightfields
+    }
+
+    /// Contents of the buffers in binding order.
+    fn contents(&self) -> [&[u8]; 5] {
+        [
+            self.uniforms.as_bytes(),
+            self.primitives.as_bytes(),
+            self.bvh.nodes.as_bytes(),
+            self.materials.as_bytes(),
+            self.lights.as_bytes(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_triangle_indices() {
+        let positions = [[0.0; 3]; 3];
+        assert_eq!(check_triangles(&positions, &[0, 1, 2]), Ok(()));
+        assert_eq!(check_triangles(&positions, &[]), Ok(()));
+        assert_eq!(
+            check_triangles(&positions, &[0, 1]),
+            Err("2 indices don't make whole triangles".to_owned())
+        );
+        assert_eq!(
+            check_triangles(&positions, &[0, 1, 3]),
+            Err("index 3 refers to a missing vertex, there are 3".to_owned())
+        );
+    }
+}
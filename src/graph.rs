@@ -0,0 +1,297 @@
+//! Render graph of the passes every tile of a render goes through, set by
+//! [`RenderSettings::graph`](crate::settings::RenderSettings::graph).
+//!
+//! The passes of the [`sample`](RenderGraph::sample) list run for every sample of the tile, in
+//! order, then the ones of the [`tile`](RenderGraph::tile) list run once all of them have been
+//! accumulated. Every pass declares the [resources](Resource) it reads and writes, which lets
+//! [`RenderGraph::validate`] check that nothing is read before a pass wrote it, e.g. that the
+//! graph doesn't tonemap an accumulation no pass fills.
+//!
+//! Besides the built-in passes the graph can run [custom passes](PassDeclaration) of the
+//! application, added to the renderer with
+//! [`add_graph_pass`](crate::renderer::RaytracingRenderer::add_graph_pass), e.g. a denoiser
+//! reading and writing the accumulation between [`GraphPass::Accumulate`] and
+//! [`GraphPass::Tonemap`], or its own tonemapper writing the output in place of the built-in
+//! one.
+//!
+//! The graph only orders the passes. There's no built-in denoising pass, the resources live as
+//! long as the tile targets of the render, whatever the graph, and the barriers between the
+//! passes are left to wgpu.
+
+use std::{error::Error, fmt};
+
+use serde::{Deserialize, Serialize};
+
+/// What the passes of a tile hand over to each other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Resource {
+    /// The paths of the sample being traced and their hits, in the queues of the wavefront
+    /// integrator.
+    Paths,
+    /// Samples accumulated for the pixels of the tile.
+    Accumulation,
+    /// RGBA8 output texture of the tile.
+    Output,
+}
+
+/// Pass of a [`RenderGraph`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GraphPass {
+    /// Starts a path at every pixel, along with the photons or the paths from the lights of
+    /// the integrators tracing light from them.
+    RayGen,
+    /// Traces and shades the paths until all of them terminated.
+    Shade,
+    /// Adds the light the paths carried to the camera to the accumulation.
+    Accumulate,
+    /// Averages, exposes and tonemaps the accumulation into the output.
+    Tonemap,
+    /// Copies the output to where the render reads it back from, the host or the buffer of
+    /// [`render_into_buffer`](crate::renderer::RaytracingRenderer::render_into_buffer).
+    Readback,
+    /// Pass of the application with the name it was added to the renderer with.
+    Custom(String),
+}
+
+impl GraphPass {
+    /// Resources the built-in passes read and write, `None` for custom passes.
+    pub fn resources(&self) -> Option<(&'static [Resource], &'static [Resource])> {
+        Some(match self {
+            Self::RayGen => (&[], &[Resource::Paths]),
+            Self::Shade => (&[Resource::Paths], &[Resource::Paths]),
+            Self::Accumulate => (&[Resource::Paths], &[Resource::Accumulation]),
+            Self::Tonemap => (&[Resource::Accumulation], &[Resource::Output]),
+            Self::Readback => (&[Resource::Output], &[]),
+            Self::Custom(_) => return None,
+        })
+    }
+
+    /// Whether the pass runs for every sample rather than once for the tile, `None` for custom
+    /// passes, which can do either.
+    pub fn per_sample(&self) -> Option<bool> {
+        match self {
+            Self::RayGen | Self::Shade | Self::Accumulate => Some(true),
+            Self::Tonemap | Self::Readback => Some(false),
+            Self::Custom(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for GraphPass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Custom(name) => write!(f, "custom pass {name:?}"),
+            pass => write!(f, "{pass:?} pass"),
+        }
+    }
+}
+
+/// Name and resources of a custom pass of the application.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PassDeclaration {
+    /// What [`GraphPass::Custom`] refers to the pass by.
+    pub name: String,
+    pub reads: Vec<Resource>,
+    pub writes: Vec<Resource>,
+}
+
+/// Passes every tile of a render goes through, see the [module](self) documentation.
+///
+/// The default graph runs the built-in passes in their usual order, with no custom passes.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderGraph {
+    /// Passes of every sample, in order.
+    pub sample: Vec<GraphPass>,
+    /// Passes of the tile once its samples have been accumulated, in order.
+    pub tile: Vec<GraphPass>,
+}
+
+impl Default for RenderGraph {
+    fn default() -> Self {
+        Self {
+            sample: vec![GraphPass::RayGen, GraphPass::Shade, GraphPass::Accumulate],
+            tile: vec![GraphPass::Tonemap, GraphPass::Readback],
+        }
+    }
+}
+
+impl RenderGraph {
+    /// Checks that every built-in pass is in the list it belongs to at most once, that
+    /// `declaration` knows every custom pass, that every pass only reads resources a pass
+    /// before it wrote and that the tile gets [read back](GraphPass::Readback), as renders
+    /// would otherwise return the pixels of the previous tile. The passes of a tile see what the
+    /// passes of its samples wrote.
+    pub fn validate<'a>(
+        &self,
+        declaration: impl Fn(&str) -> Option<&'a PassDeclaration>,
+    ) -> Result<(), GraphError> {
+        let mut written = Vec::new();
+        for (passes, per_sample) in [(&self.sample, true), (&self.tile, false)] {
+            for (index, pass) in passes.iter().enumerate() {
+                if pass.per_sample().is_some_and(|sample| sample != per_sample) {
+                    return Err(GraphError::WrongList(pass.clone()));
+                }
+                if pass.resources().is_some() && passes[..index].contains(pass) {
+                    return Err(GraphError::Repeated(pass.clone()));
+                }
+
+                let (reads, writes) = match (pass.resources(), pass) {
+                    (Some(resources), _) => resources,
+                    (None, GraphPass::Custom(name)) => {
+                        let declaration = declaration(name)
+                            .ok_or_else(|| GraphError::UnknownPass(name.clone()))?;
+                        (&declaration.reads[..], &declaration.writes[..])
+                    }
+                    (None, _) => unreachable!("Only custom passes have no resources"),
+                };
+                if let Some(&resource) = reads.iter().find(|read| !written.contains(*read)) {
+                    return Err(GraphError::Unwritten {
+                        pass: pass.clone(),
+                        resource,
+                    });
+                }
+                written.extend_from_slice(writes);
+            }
+        }
+        if !self.tile.contains(&GraphPass::Readback) {
+            return Err(GraphError::NoReadback);
+        }
+        Ok(())
+    }
+}
+
+/// Why a [`RenderGraph`] can't be rendered with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GraphError {
+    /// A pass that runs for every sample is in the list of the tile, or the other way around.
+    WrongList(GraphPass),
+    /// A built-in pass is in its list more than once.
+    Repeated(GraphPass),
+    /// No custom pass of that name was added to the renderer.
+    UnknownPass(String),
+    /// `pass` reads `resource` before any pass wrote it.
+    Unwritten { pass: GraphPass, resource: Resource },
+    /// The tile list has no [`GraphPass::Readback`].
+    NoReadback,
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongList(pass) => write!(f, "The {pass} is in the wrong list"),
+            Self::Repeated(pass) => write!(f, "The {pass} is in the graph more than once"),
+            Self::UnknownPass(name) => write!(f, "No custom pass is called {name:?}"),
+            Self::Unwritten { pass, resource } => {
+                write!(f, "The {pass} reads {resource:?} before any pass writes it")
+            }
+            Self::NoReadback => write!(f, "The graph never reads the tiles back"),
+        }
+    }
+}
+
+impl Error for GraphError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn denoiser() -> PassDeclaration {
+        PassDeclaration {
+            name: "denoise".to_owned(),
+            reads: vec![Resource::Accumulation],
+            writes: vec![Resource::Accumulation],
+        }
+    }
+
+    fn validate(graph: &RenderGraph) -> Result<(), GraphError> {
+        let denoiser = denoiser();
+        graph.validate(|name| (name == denoiser.name).then_some(&denoiser))
+    }
+
+    #[test]
+    fn default_graph_is_valid() {
+        assert_eq!(validate(&RenderGraph::default()), Ok(()));
+    }
+
+    #[test]
+    fn custom_passes_are_valid_where_their_reads_are_written() {
+        let mut graph = RenderGraph::default();
+        graph
+            .tile
+            .insert(0, GraphPass::Custom("denoise".to_owned()));
+        assert_eq!(validate(&graph), Ok(()));
+
+        graph
+            .sample
+            .insert(0, GraphPass::Custom("denoise".to_owned()));
+        assert_eq!(
+            validate(&graph),
+            Err(GraphError::Unwritten {
+                pass: GraphPass::Custom("denoise".to_owned()),
+                resource: Resource::Accumulation,
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_custom_passes_are_rejected() {
+        let mut graph = RenderGraph::default();
+        graph.tile.insert(0, GraphPass::Custom("bloom".to_owned()));
+        assert_eq!(
+            validate(&graph),
+            Err(GraphError::UnknownPass("bloom".to_owned()))
+        );
+    }
+
+    #[test]
+    fn built_in_passes_stay_in_their_list() {
+        let mut graph = RenderGraph::default();
+        graph.sample.push(GraphPass::Tonemap);
+        assert_eq!(
+            validate(&graph),
+            Err(GraphError::WrongList(GraphPass::Tonemap))
+        );
+
+        let mut graph = RenderGraph::default();
+        graph.tile.insert(0, GraphPass::Shade);
+        assert_eq!(
+            validate(&graph),
+            Err(GraphError::WrongList(GraphPass::Shade))
+        );
+    }
+
+    #[test]
+    fn built_in_passes_run_once() {
+        let mut graph = RenderGraph::default();
+        graph.sample.push(GraphPass::Accumulate);
+        assert_eq!(
+            validate(&graph),
+            Err(GraphError::Repeated(GraphPass::Accumulate))
+        );
+    }
+
+    #[test]
+    fn passes_read_what_was_written_before_them() {
+        let graph = RenderGraph {
+            sample: vec![GraphPass::RayGen, GraphPass::Shade],
+            ..Default::default()
+        };
+        assert_eq!(
+            validate(&graph),
+            Err(GraphError::Unwritten {
+                pass: GraphPass::Tonemap,
+                resource: Resource::Accumulation,
+            })
+        );
+    }
+
+    #[test]
+    fn tiles_are_read_back() {
+        let graph = RenderGraph {
+            tile: vec![GraphPass::Tonemap],
+            ..Default::default()
+        };
+        assert_eq!(validate(&graph), Err(GraphError::NoReadback));
+    }
+}
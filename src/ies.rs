@@ -0,0 +1,226 @@
+//! Photometric data of real luminaires in the IES LM-63 format, giving point lights the shape
+//! of the light fixtures they stand for.
+
+use std::fmt;
+
+/// How the intensity of a luminaire varies around it, as measured on a goniometer.
+///
+/// Angles are in degrees, in the type C coordinate system of almost all files: vertical angles
+/// from 0 straight down the axis of the luminaire to 180 straight up, and horizontal angles
+/// around the axis.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IesProfile {
+    pub vertical_angles: Vec<f32>,
+    /// Runs up to 0 for luminaires symmetric around their axis, 90 for ones symmetric in each
+    /// quadrant, 180 for ones symmetric about a plane, or 360.
+    pub horizontal_angles: Vec<f32>,
+    /// Intensities in candela, a row of every vertical angle for each horizontal angle.
+    pub candela: Vec<Vec<f32>>,
+}
+
+/// Why an IES file can't be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IesError(String);
+
+impl fmt::Display for IesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for IesError {}
+
+impl IesProfile {
+    /// Parses the contents of an IES file of any version of the format.
+    pub fn parse(source: &str) -> Result<Self, IesError> {
+        let error = |message: &str| IesError(message.to_owned());
+
+        // Keywords run up to the line with the tilt of the lamps, numbers follow.
+        let mut lines = source.lines();
+        let tilt = lines
+            .by_ref()
+            .map(str::trim)
+            .find_map(|line| line.strip_prefix("TILT="))
+            .ok_or_else(|| error("missing TILT line"))?;
+        let numbers = lines
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|token| !token.is_empty())
+            .map(|token| {
+                token
+                    .parse::<f32>()
+                    .map_err(|_| IesError(format!("invalid number \"{token}\"")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut numbers = numbers.into_iter();
+        let mut next = || {
+            numbers
+                .next()
+                .ok_or_else(|| error("unexpected end of file"))
+        };
+
+        // Tilt tables only matter for lamps tilted away from how they were measured.
+        if tilt.trim() == "INCLUDE" {
+            let _geometry = next()?;
+            let angles = next()? as usize;
+            for _ in 0..2 * angles {
+                next()?;
+            }
+        }
+
+        let _lamps = next()?;
+        let _lumens = next()?;
+        let multiplier = next()?;
+        let vertical = next()? as usize;
+        let horizontal = next()? as usize;
+        let _photometric_type = next()?;
+        let _units = next()?;
+        for _dimension in 0..3 {
+            next()?;
+        }
+        let ballast = next()?;
+        let _ballast_lamp = next()?;
+        let _watts = next()?;
+        if vertical == 0 || horizontal == 0 {
+            return Err(error("no angles"));
+        }
+
+        let mut read = |count| (0..count).map(|_| next()).collect::<Result<Vec<_>, _>>();
+        let vertical_angles = read(vertical)?;
+        let horizontal_angles = read(horizontal)?;
+        let candela = (0..horizontal)
+            .map(|_| {
+                read(vertical)
+                    .map(|row| row.into_iter().map(|c| c * multiplier * ballast).collect())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let ascending = |angles: &[f32]| angles.windows(2).all(|pair| pair[0] < pair[1]);
+        if !ascending(&vertical_angles) || !ascending(&horizontal_angles) {
+            return Err(error("angles aren't in ascending order"));
+        }
+
+        Ok(Self {
+            vertical_angles,
+            horizontal_angles,
+            candela,
+        })
+    }
+
+    /// Intensity in candela towards `vertical` and `horizontal` degrees, interpolated between the
+    /// measured angles. Zero past the vertical angles measured, applying the symmetry of the
+    /// horizontal ones.
+    pub fn intensity(&self, vertical: f32, horizontal: f32) -> f32 {
+        let last = *self.horizontal_angles.last().unwrap_or(&0.0);
+        let horizontal = horizontal.rem_euclid(360.0);
+        let horizontal = if last <= 0.0 {
+            0.0
+        } else if last <= 90.0 {
+            let folded = horizontal % 180.0;
+            folded.min(180.0 - folded)
+        } else if last <= 180.0 {
+            horizontal.min(360.0 - horizontal)
+        } else {
+            horizontal
+        };
+
+        let Some((v0, v1, tv)) = bracket(&self.vertical_angles, vertical, false) else {
+            return 0.0;
+        };
+        let (h0, h1, th) =
+            bracket(&self.horizontal_angles, horizontal, true).unwrap_or((0, 0, 0.0));
+        let row = |h: usize| {
+            let row = &self.candela[h];
+            row[v0] + (row[v1] - row[v0]) * tv
+        };
+        row(h0) + (row(h1) - row(h0)) * th
+    }
+
+    /// The intensities on a grid of `width` horizontal angles from 0 and `height` vertical ones
+    /// from 0 to 180 degrees, rows by vertical angle, over the largest one.
+    pub(crate) fn bake(&self, width: u32, height: u32) -> Vec<f32> {
+        let mut table = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            let vertical = 180.0 * y as f32 / (height - 1).max(1) as f32;
+            for x in 0..width {
+                table.push(self.intensity(vertical, 360.0 * x as f32 / width as f32));
+            }
+        }
+
+        let max = table.iter().copied().fold(0.0, f32::max);
+        if max > 0.0 {
+            table.iter_mut().for_each(|value| *value /= max);
+        }
+        table
+    }
+}
+
+/// Indices of the angles around `angle` and how far it is between them, or none if it's outside
+/// of them. With `wrap`, angles past the last one blend back towards the first at 360 degrees.
+fn bracket(angles: &[f32], angle: f32, wrap: bool) -> Option<(usize, usize, f32)> {
+    let last = angles.len() - 1;
+    if angles.len() == 1 || angle <= angles[0] {
+        return (angle >= angles[0] - 1.0e-3 || wrap).then_some((0, 0, 0.0));
+    }
+    if angle >= angles[last] {
+        if wrap && angles[last] < 360.0 {
+            let t = (angle - angles[last]) / (360.0 + angles[0] - angles[last]);
+            return Some((last, 0, t));
+        }
+        return (angle <= angles[last] + 1.0e-3 || wrap).then_some((last, last, 0.0));
+    }
+
+    let upper = angles.partition_point(|&a| a <= angle);
+    let (a0, a1) = (angles[upper - 1], angles[upper]);
+    Some((upper - 1, upper, (angle - a0) / (a1 - a0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Three vertical angles and a symmetric horizontal one, with a multiplier of 2.
+    const PROFILE: &str = "IESNA:LM-63-2002
+[TEST] test
+TILT=NONE
+1 1000 2 3 1 1 2 0 0 0
+1 1 100
+0 45 90
+0
+100 50 0
+";
+
+    #[test]
+    fn parses_profiles() {
+        let profile = IesProfile::parse(PROFILE).unwrap();
+        assert_eq!(profile.vertical_angles, [0.0, 45.0, 90.0]);
+        assert_eq!(profile.horizontal_angles, [0.0]);
+        assert_eq!(profile.candela, [vec![200.0, 100.0, 0.0]]);
+        assert_eq!(profile.intensity(22.5, 123.0), 150.0);
+        assert_eq!(profile.intensity(120.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn rejects_malformed_profiles() {
+        let parse = |source: &str| IesProfile::parse(source).map_err(|err| err.to_string());
+        assert_eq!(
+            parse("IESNA:LM-63-2002\n1 1000 1"),
+            Err("missing TILT line".to_owned())
+        );
+        assert_eq!(
+            parse(&PROFILE.replace("100 50 0", "100 fifty 0")),
+            Err("invalid number \"fifty\"".to_owned())
+        );
+        assert_eq!(
+            parse(&PROFILE.replace("100 50 0", "100 50")),
+            Err("unexpected end of file".to_owned())
+        );
+        assert_eq!(
+            parse(&PROFILE.replace("0 45 90", "0 90 45")),
+            Err("angles aren't in ascending order".to_owned())
+        );
+        assert_eq!(
+            parse("TILT=NONE\n1 1000 1 0 1 1 2 0 0 0\n1 1 100\n"),
+            Err("no angles".to_owned())
+        );
+    }
+}
@@ -1 +1,49 @@
+pub mod analysis;
+pub mod animation;
+pub mod bench;
+#[cfg(feature = "fs")]
+mod binary;
+mod bvh;
+#[cfg(feature = "renderdoc")]
+mod capture;
+#[cfg(feature = "fs")]
+pub mod checkpoint;
+mod displacement;
+#[cfg(feature = "native")]
+pub mod farm;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod gpu_scene;
+pub mod graph;
+pub mod ies;
+pub mod lightmap;
+pub mod materials;
+#[cfg(feature = "native")]
+pub mod multi_gpu;
+#[cfg(feature = "fs")]
+pub mod obj;
+#[cfg(feature = "fs")]
+pub mod pbrt;
+pub mod present;
+pub mod probes;
+pub mod progress;
+pub mod queue;
+mod raster;
+pub mod rays;
 pub mod renderer;
+pub mod scene;
+pub mod scenes;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod settings;
+pub mod shader;
+pub mod sun;
+mod supersample;
+pub mod testing;
+mod texture;
+pub mod tile;
+pub mod tuning;
+#[cfg(feature = "native")]
+pub mod video;
+#[cfg(target_arch = "wasm32")]
+pub mod web;
@@ -0,0 +1,246 @@
+//! Lightmaps baked from the scene a [`RaytracingRenderer`] traces, e.g. to light static game
+//! assets with the global illumination of the path tracer.
+//!
+//! The texels of a lightmap are laid out over the texture coordinates of an object, which have
+//! to map its triangles onto separate parts of the unit square without overlapping. Paths start
+//! at the points of the surface the texels cover instead of the camera, so every texel gets the
+//! irradiance of the surface over pi: the light a white diffuse surface would reflect there.
+
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, Point3, SquareMatrix, Transform, Vector3};
+use zerocopy::AsBytes;
+
+use crate::{
+    renderer::RaytracingRenderer,
+    scene::{self, Scene, SceneError, Shape},
+    settings::RenderSettings,
+};
+
+/// How many texels the edges of the parts a surface covers are grown by, so that filtering the
+/// lightmap across them doesn't pull in the empty texels around.
+const DILATION: u32 = 4;
+
+/// Mirrors `LightmapTexel` in the shaders.
+#[derive(AsBytes, Clone, Copy, Default)]
+#[repr(C)]
+struct LightmapTexel {
+    position: [f32; 3],
+    covered: u32,
+    normal: [f32; 3],
+    _padding: u32,
+}
+
+/// Bakes the lightmap of the first object named `object` in `scene`, which has to be the scene
+/// of `renderer`, `resolution` texels wide and high with the samples, bounces and integrator
+/// of `settings`. The size of the image and the exposure in `settings` are ignored.
+///
+/// Returns tightly packed rows of linear RGBA32F texels from the top down, with (0, 0) of the
+/// texture coordinates at the bottom left like textures. Alpha is one where the surface covers
+/// the texels, or the edges were grown over them, and zero elsewhere.
+pub async fn bake(
+    renderer: &RaytracingRenderer,
+    scene: &Scene,
+    object: &str,
+    settings: &RenderSettings,
+    resolution: u32,
+) -> Result<Vec<f32>, SceneError> {
+    let error = |message: &str| SceneError::Lightmap {
+        object: object.to_owned(),
+        message: message.to_owned(),
+    };
+    let instance = scene
+        .instances()
+        .into_iter()
+        .find(|instance| instance.object.name == object)
+        .ok_or_else(|| error("not found in the scene"))?;
+
+    let mesh;
+    let (positions, normals, texcoords, indices) = match &instance.object.shape {
+        Shape::Triangles {
+            positions,
+            normals,
+            texcoords,
+            indices,
+        } => (positions, normals, texcoords, indices),
+        Shape::Mesh { path } => {
+            mesh = scene::load_mesh(path)?;
+            (
+                &mesh.positions,
+                &mesh.normals,
+                &mesh.texcoords,
+                &mesh.indices,
+            )
+        }
+        Shape::Sphere { .. }
+        | Shape::Quad { .. }
+        | Shape::Cuboid { .. }
+        | Shape::Custom { .. }
+        | Shape::Sdf { .. }
+        | Shape::Csg { .. }
+        | Shape::Heightfield { .. }
+        | Shape::Curves { .. }
+        | Shape::Points { .. } => return Err(error("only triangles can be lightmapped")),
+    };
+    if texcoords.len() != positions.len() {
+        return Err(error("the mesh has no texture coordinates"));
+    }
+    let normals = if instance.object.flat_shading || normals.len() != positions.len() {
+        &[][..]
+    } else {
+        normals
+    };
+
+    let texels = rasterize(
+        positions,
+        normals,
+        texcoords,
+        indices,
+        &instance.matrix,
+        resolution,
+    );
+    let settings = RenderSettings {
+        width: resolution,
+        height: resolution,
+        // Baked light stays in the units of the scene.
+        exposure: None,
+        ..settings.clone()
+    };
+    let mut pixels = renderer.render_lightmap(&settings, texels.as_bytes()).await;
+
+    for (pixel, texel) in pixels.chunks_exact_mut(4).zip(&texels) {
+        pixel[3] = texel.covered as f32;
+    }
+    dilate(&mut pixels, resolution);
+    Ok(pixels)
+}
+
+/// Points of the surface of the triangles at the centers of the texels they cover in texture
+/// space, in world space through `matrix`.
+fn rasterize(
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    texcoords: &[[f32; 2]],
+    indices: &[u32],
+    matrix: &Matrix4<f32>,
+    resolution: u32,
+) -> Vec<LightmapTexel> {
+    // Normals transform with the inverse transpose to stay perpendicular to the surface.
+    let normal_matrix = Matrix3::from_cols(
+        matrix.x.truncate(),
+        matrix.y.truncate(),
+        matrix.z.truncate(),
+    )
+    .invert()
+    .map(|inverse| inverse.transpose())
+    .unwrap_or_else(Matrix3::identity);
+
+    let size = resolution as f32;
+    let mut texels = vec![LightmapTexel::default(); (resolution * resolution) as usize];
+    let triangles: Vec<[usize; 3]> = if indices.is_empty() {
+        (0..positions.len() / 3)
+            .map(|i| [i * 3, i * 3 + 1, i * 3 + 2])
+            .collect()
+    } else {
+        indices
+            .chunks_exact(3)
+            .map(|t| [t[0] as usize, t[1] as usize, t[2] as usize])
+            .collect()
+    };
+
+    for triangle in triangles {
+        let uv = triangle.map(|i| [texcoords[i][0] * size, (1.0 - texcoords[i][1]) * size]);
+        let area = edge(uv[0], uv[1], uv[2]);
+        if area.abs() < 1.0e-12 {
+            continue;
+        }
+        let world = triangle.map(|i| matrix.transform_point(Point3::from(positions[i])));
+        let face_normal = (world[1] - world[0]).cross(world[2] - world[0]);
+
+        let min = |axis: usize| uv.iter().map(|p| p[axis]).fold(f32::MAX, f32::min);
+        let max = |axis: usize| uv.iter().map(|p| p[axis]).fold(f32::MIN, f32::max);
+        let range = |axis: usize| {
+            let start = min(axis).floor().max(0.0) as u32;
+            let end = (max(axis).ceil().max(0.0) as u32).min(resolution);
+            start..end
+        };
+        for y in range(1) {
+            for x in range(0) {
+                let p = [x as f32 + 0.5, y as f32 + 0.5];
+                let weights = [
+                    edge(uv[1], uv[2], p) / area,
+                    edge(uv[2], uv[0], p) / area,
+                    edge(uv[0], uv[1], p) / area,
+                ];
+                if weights.iter().any(|&w| w < -1.0e-4) {
+                    continue;
+                }
+
+                let position = (0..3).fold(Vector3::new(0.0, 0.0, 0.0), |sum, i| {
+                    sum + Vector3::new(world[i].x, world[i].y, world[i].z) * weights[i]
+                });
+                let normal = if normals.is_empty() {
+                    face_normal
+                } else {
+                    let normal = (0..3).fold(Vector3::new(0.0, 0.0, 0.0), |sum, i| {
+                        sum + Vector3::from(normals[triangle[i]]) * weights[i]
+                    });
+                    normal_matrix * normal
+                };
+                if normal.magnitude2() == 0.0 {
+                    continue;
+                }
+
+                texels[(y * resolution + x) as usize] = LightmapTexel {
+                    position: position.into(),
+                    covered: 1,
+                    normal: normal.normalize().into(),
+                    _padding: 0,
+                };
+            }
+        }
+    }
+    texels
+}
+
+/// Twice the signed area of the triangle `a`, `b`, `c`.
+fn edge(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+/// Grows the texels with alpha set into the empty ones next to them by [`DILATION`] texels,
+/// each taking the mean of its neighbours.
+fn dilate(pixels: &mut [f32], resolution: u32) {
+    let size = resolution as i64;
+    for _ in 0..DILATION {
+        let previous = pixels.to_vec();
+        for y in 0..size {
+            for x in 0..size {
+                let index = ((y * size + x) * 4) as usize;
+                if previous[index + 3] > 0.0 {
+                    continue;
+                }
+
+                let mut sum = [0.0; 3];
+                let mut count = 0;
+                for (dx, dy) in (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (dx, dy))) {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if nx < 0 || ny < 0 || nx >= size || ny >= size {
+                        continue;
+                    }
+                    let neighbour = ((ny * size + nx) * 4) as usize;
+                    if previous[neighbour + 3] > 0.0 {
+                        for channel in 0..3 {
+                            sum[channel] += previous[neighbour + channel];
+                        }
+                        count += 1;
+                    }
+                }
+                if count > 0 {
+                    for channel in 0..3 {
+                        pixels[index + channel] = sum[channel] / count as f32;
+                    }
+                    pixels[index + 3] = 1.0;
+                }
+            }
+        }
+    }
+}
@@ -1,13 +1,1211 @@
-use raytracing::renderer::RaytracingRenderer;
+use std::{
+    collections::HashSet,
+    error::Error,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::mpsc,
+    time::Duration,
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use notify::{EventKind, RecursiveMode, Watcher};
+use raytracing::{
+    analysis::{self, Histogram},
+    animation::{self, CameraPath},
+    bench::{self, BenchScene},
+    lightmap, probes,
+    progress::Progress,
+    renderer::RaytracingRenderer,
+    scene::{Scene, Shape},
+    scenes,
+    settings::{DebugView, Integrator, PixelFilter, RenderSettings, Stereo},
+    shader::ShaderSources,
+    tuning,
+    video::{self, VideoEncoder},
+};
+use tracing_subscriber::EnvFilter;
+
+/// How long file events have to stop coming in before a changed scene is re-rendered.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Range and bins of `--histogram`, half a stop wide.
+const HISTOGRAM_MIN_STOPS: f32 = -10.0;
+const HISTOGRAM_MAX_STOPS: f32 = 6.0;
+const HISTOGRAM_BINS: u32 = 32;
+
+/// Renders an image with the GPU path tracer.
+///
+/// Render settings default to the ones stored in the scene, flags override them.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Scene to render, a `.ron`, `.json`, `.pbrt` or `.obj` file. Renders a demo scene without one.
+    #[arg(long, value_name = "PATH")]
+    scene: Option<PathBuf>,
+
+    /// Render one of the procedural scenes built into the renderer instead of a scene file.
+    #[arg(long, value_name = "NAME", value_parser = scenes::NAMES, conflicts_with = "scene")]
+    builtin: Option<String>,
+
+    /// Width of the image in pixels.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    width: Option<u32>,
+
+    /// Height of the image in pixels.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    height: Option<u32>,
+
+    /// Samples per pixel.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    spp: Option<u32>,
+
+    /// Maximum number of bounces of every path.
+    #[arg(long)]
+    bounces: Option<u32>,
+
+    /// Seed of the random number generators.
+    #[arg(long)]
+    seed: Option<u32>,
+
+    /// Render only the ambient occlusion of the surfaces the camera sees, with this many
+    /// occlusion rays per sample.
+    #[arg(long, value_name = "RAYS", value_parser = clap::value_parser!(u32).range(1..))]
+    ao_rays: Option<u32>,
+
+    /// How far occlusion rays reach.
+    #[arg(
+        long,
+        value_name = "DISTANCE",
+        requires = "ao_rays",
+        default_value_t = 1.0
+    )]
+    ao_distance: f32,
+
+    /// Render caustics with progressive photon mapping, tracing this many photons per sample.
+    #[arg(
+        long,
+        value_name = "PHOTONS",
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with = "ao_rays"
+    )]
+    photons: Option<u32>,
+
+    /// Initial radius around surfaces photons are gathered from.
+    #[arg(
+        long,
+        value_name = "RADIUS",
+        requires = "photons",
+        default_value_t = 0.05
+    )]
+    photon_radius: f32,
+
+    /// Render with bidirectional path tracing, for scenes mostly lit through small openings or
+    /// by light bouncing off other surfaces.
+    #[arg(long, conflicts_with_all = ["ao_rays", "photons"])]
+    bidirectional: bool,
+
+    /// Sample the direct light of the surfaces the camera sees with ReSTIR, picking out of this
+    /// many lights for every sample.
+    #[arg(
+        long,
+        value_name = "CANDIDATES",
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with_all = ["ao_rays", "photons", "bidirectional"]
+    )]
+    restir: Option<u32>,
+
+    /// Resample the light of the next bounce off those surfaces with ReSTIR GI as well.
+    #[arg(long, requires = "restir")]
+    restir_indirect: bool,
+
+    /// Guide the diffuse bounces towards the light found at the surfaces by the earlier
+    /// samples, for scenes lit indirectly through a few bright spots.
+    #[arg(long, conflicts_with_all = ["ao_rays", "photons", "bidirectional", "restir"])]
+    path_guiding: bool,
+
+    /// Learn the light arriving at the diffuse surfaces in an irradiance cache over this many
+    /// samples of every tile, then end the paths of the rest there after a final gather
+    /// bounce, for diffuse interiors lit through many bounces.
+    #[arg(
+        long,
+        value_name = "CACHE_SAMPLES",
+        conflicts_with_all = ["ao_rays", "photons", "bidirectional", "restir", "path_guiding"]
+    )]
+    final_gather: Option<u32>,
+
+    /// Render a debug view of the surfaces the camera sees instead of shading them.
+    #[arg(
+        long,
+        value_name = "VIEW",
+        conflicts_with_all = [
+            "ao_rays",
+            "photons",
+            "bidirectional",
+            "restir",
+            "path_guiding",
+            "final_gather"
+        ]
+    )]
+    debug_view: Option<DebugViewArg>,
+
+    /// Depth shown as white by the depth debug view.
+    #[arg(long, value_name = "DISTANCE", default_value_t = 10.0)]
+    depth_distance: f32,
+
+    /// Tests of BVH nodes and primitives shown as red by the traversal heatmap debug view.
+    #[arg(long, value_name = "TESTS", default_value_t = 200)]
+    heatmap_max_tests: u32,
+
+    /// Accumulate samples in half precision for a faster, lower memory preview. Ignored for
+    /// `.exr` outputs and renders of more than 2048 samples per pixel.
+    #[arg(long)]
+    half_precision: bool,
+
+    /// Reconstruction filter of the pixels.
+    #[arg(long, value_enum)]
+    filter: Option<FilterArg>,
+
+    /// How far the filter reaches from the center of a pixel, in pixels. Half a pixel for
+    /// `box`, a pixel and a half for `gaussian` and two pixels for the others by default.
+    #[arg(long, value_name = "PIXELS", requires = "filter")]
+    filter_radius: Option<f32>,
+
+    /// Distance along rays within which hits are ignored, smaller for tiny scenes leaking light.
+    #[arg(long, value_name = "DISTANCE")]
+    ray_epsilon: Option<f32>,
+
+    /// Scale of how far rays leaving surfaces start off them, larger for shadow acne.
+    #[arg(long, value_name = "SCALE")]
+    ray_offset: Option<f32>,
+
+    /// Leave the background transparent where the camera sees the sky. The output is
+    /// premultiplied by its alpha.
+    #[arg(long)]
+    transparent: bool,
+
+    /// Side of the tiles the image is rendered in, in pixels.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    tile_size: Option<u32>,
+
+    /// Render on the first adapter whose name contains this, ignoring case.
+    #[arg(long, value_name = "NAME")]
+    adapter: Option<String>,
+
+    /// Output image, `.exr` files are written in linear floating point, anything else as RGBA8
+    /// in the format implied by the extension.
+    #[arg(short, long, default_value = "out.png")]
+    output: PathBuf,
+
+    /// Bake an irradiance probe at this position instead of rendering an image, can be given
+    /// more than once. The probes are written to the output as JSON.
+    #[arg(
+        long,
+        value_name = "X,Y,Z",
+        value_parser = parse_position,
+        conflicts_with_all = ["watch", "end"]
+    )]
+    probe: Vec<[f32; 3]>,
+
+    /// Side of the octahedral maps of the radiance arriving at the probes, in texels.
+    #[arg(
+        long,
+        value_name = "TEXELS",
+        value_parser = clap::value_parser!(u32).range(1..),
+        default_value_t = 32
+    )]
+    probe_resolution: u32,
+
+    /// Bake the lightmap of the object with this name over its texture coordinates instead of
+    /// rendering an image, written to the output, which has to be an `.exr` file.
+    #[arg(
+        long,
+        value_name = "OBJECT",
+        requires = "scene",
+        conflicts_with_all = ["watch", "end", "probe"]
+    )]
+    lightmap: Option<String>,
+
+    /// Side of the lightmap in texels.
+    #[arg(
+        long,
+        value_name = "TEXELS",
+        value_parser = clap::value_parser!(u32).range(1..),
+        default_value_t = 512
+    )]
+    lightmap_resolution: u32,
+
+    /// Write a false color image of the stops of the render around middle grey to the output
+    /// instead of the render itself, to judge its exposure and see what clips.
+    #[arg(
+        long,
+        conflicts_with_all = ["watch", "end", "probe", "lightmap", "stereo", "supersample"]
+    )]
+    false_color: bool,
+
+    /// Also write a histogram of the luminance of the render in stops around middle grey to
+    /// this JSON file.
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["watch", "end", "probe", "lightmap", "stereo", "supersample"]
+    )]
+    histogram: Option<PathBuf>,
+
+    /// Write a heatmap of how long every tile takes to render to the output instead of the
+    /// render, to find what's slow. Small `--tile-size`s make it finer.
+    #[arg(
+        long,
+        conflicts_with_all = ["watch", "end", "probe", "lightmap", "stereo", "supersample", "false_color", "histogram"]
+    )]
+    time_heatmap: bool,
+
+    /// Render every layer of the scene on its own instead of the whole scene, each to the
+    /// output with the name of the layer appended.
+    #[arg(
+        long,
+        requires = "scene",
+        conflicts_with_all = ["watch", "end", "probe", "lightmap", "stereo"]
+    )]
+    layers: bool,
+
+    /// Keep running, rendering the scene again to the same output every time it, one of its
+    /// meshes or one of the `--shaders` is saved.
+    #[arg(long, requires = "scene")]
+    watch: bool,
+
+    /// Serve renders over HTTP on this address, e.g. `0.0.0.0:8080`, instead of rendering an
+    /// image. The scene, if given, is rendered until a job submits another one.
+    #[cfg(feature = "server")]
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        conflicts_with_all = ["watch", "end", "turntable", "layers"]
+    )]
+    serve: Option<String>,
+
+    /// Build the shaders from the WGSL files in this directory, falling back to the built-in
+    /// ones for files it lacks. `src/shaders` if no directory is given.
+    #[arg(long, value_name = "DIR", num_args = 0..=1, default_missing_value = "src/shaders")]
+    shaders: Option<PathBuf>,
+
+    /// Side of the square workgroups the compute shaders run in.
+    #[arg(long, value_name = "SIZE", value_parser = clap::value_parser!(u32).range(1..))]
+    workgroup_size: Option<u32>,
+
+    /// Benchmark workgroup sizes on the adapter and use the fastest, caching it for later runs.
+    #[arg(long, conflicts_with = "workgroup_size")]
+    tune_workgroup_size: bool,
+
+    /// Sort paths by the material they hit before shading them, which can be faster on scenes
+    /// with many different materials.
+    #[arg(long)]
+    sort_paths: bool,
+
+    /// Trace paths with persistent threads taking them off a shared queue, which can be faster
+    /// when tracing some paths takes much longer than others.
+    #[arg(long)]
+    persistent_threads: bool,
+
+    /// Rasterize the triangles the camera sees instead of tracing the camera rays through the
+    /// BVH, which can be faster on scenes of many triangles.
+    #[arg(long)]
+    hybrid: bool,
+
+    /// Make renders bit for bit reproducible on the same adapter and driver, e.g. to compare
+    /// them in CI. ReSTIR doesn't reuse earlier renders and photon mapping loses some caustics.
+    #[arg(long)]
+    deterministic: bool,
+
+    /// Antialias by rendering at this many times the width and height and scaling the image
+    /// back down, only for RGBA8 outputs.
+    #[arg(long, value_name = "FACTOR", value_parser = clap::value_parser!(u32).range(2..=8))]
+    supersample: Option<u32>,
+
+    /// Render a stereo pair with the eyes this far apart in scene units, side by side in an RGBA8
+    /// image twice as wide.
+    #[arg(long, value_name = "IPD")]
+    stereo: Option<f32>,
+
+    /// Distance ahead of the camera the eyes of `--stereo` converge at, parallel by default.
+    #[arg(long, requires = "stereo")]
+    convergence: Option<f32>,
+
+    /// GPU memory in MiB the scene and the render should stay within, scaling textures down
+    /// and splitting the render into smaller tiles if they don't fit.
+    #[arg(long, value_name = "MIB")]
+    memory_budget: Option<u64>,
+
+    /// Define a shader preprocessor symbol, e.g. `MAX_BOUNCES=4u`.
+    #[arg(short = 'D', long = "define", value_name = "NAME[=VALUE]")]
+    defines: Vec<String>,
+
+    /// Remove a shader preprocessor symbol defined by default, e.g. `NEE` to turn off next
+    /// event estimation.
+    #[arg(short = 'U', long = "undefine", value_name = "NAME")]
+    undefines: Vec<String>,
+
+    /// Render the animation of the scene up to this time in seconds, writing numbered frames.
+    /// A run of `#` in the output name is replaced by the frame number, otherwise it's appended
+    /// to the file name. `.mp4`, `.mkv`, `.mov` and `.webm` outputs are encoded into a single
+    /// video with ffmpeg instead.
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        requires = "scene",
+        conflicts_with = "watch"
+    )]
+    end: Option<f32>,
+
+    /// Time of the first frame of an animation, in seconds.
+    #[arg(long, value_name = "SECONDS", default_value_t = 0.0)]
+    start: f32,
+
+    /// Frames per second of an animation.
+    #[arg(long, default_value_t = 24.0)]
+    fps: f32,
+
+    /// Render a turntable taking this many seconds instead of the animation of the camera, the
+    /// camera orbiting once around the point it looks at. Frames are written like with `--end`.
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        requires = "scene",
+        conflicts_with_all = ["watch", "end", "camera_path"]
+    )]
+    turntable: Option<f32>,
+
+    /// Animate the camera with the keyframes of a `.ron` or `.json` file instead of its own, e.g.
+    /// one written by `--save-camera-path`.
+    #[arg(long, value_name = "PATH", requires = "scene")]
+    camera_path: Option<PathBuf>,
+
+    /// Save the keyframes of the camera, including the ones of a `--turntable`, to a `.ron` or
+    /// `.json` file.
+    #[arg(long, value_name = "PATH", requires = "scene")]
+    save_camera_path: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Render the built-in benchmark scenes and report their frame times and camera rays per
+    /// second. Flags given before the sub-command set up the renderer and override the size,
+    /// samples and bounces of the renders.
+    Bench(BenchArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct BenchArgs {
+    /// Scene to benchmark, can be given more than once. All of them by default.
+    #[arg(long, value_enum)]
+    scene: Vec<BenchSceneArg>,
+
+    /// Untimed renders of every scene before the timed ones.
+    #[arg(long, default_value_t = 2)]
+    warmup: u32,
+
+    /// Timed renders of every scene.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), default_value_t = 5)]
+    iterations: u32,
+
+    /// Benchmark every discrete and integrated GPU in turn instead of a single adapter.
+    #[arg(long)]
+    all_adapters: bool,
+}
+
+/// Values of `bench --scene`.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum BenchSceneArg {
+    CornellBox,
+    SphereField,
+    HighPolyMesh,
+}
+
+/// Values of `--filter`.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum FilterArg {
+    Box,
+    Gaussian,
+    Mitchell,
+    BlackmanHarris,
+}
+
+/// Values of `--debug-view`.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum DebugViewArg {
+    Normals,
+    Uv,
+    Depth,
+    BvhBounds,
+    TraversalHeatmap,
+}
 
 #[async_std::main]
-async fn main() {
-    let dimension = 1024;
+async fn main() -> ExitCode {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(io::stderr)
+        .init();
+
+    match run(Args::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    if let Some(Command::Bench(bench_args)) = &args.command {
+        return run_bench(&args, bench_args).await;
+    }
+
+    let mut scene = match (&args.scene, &args.builtin) {
+        (Some(path), _) => Some(load_scene(path)?),
+        (None, Some(name)) => scenes::by_name(name),
+        (None, None) => None,
+    };
+    if let Some(scene) = &mut scene {
+        if let Some(path) = &args.camera_path {
+            scene.camera_animation = animation::load_camera_keyframes(path)?;
+        }
+        if let Some(duration) = args.turntable {
+            let path = CameraPath::Turntable {
+                duration,
+                turns: 1.0,
+            };
+            scene.camera_animation = path.keyframes(&scene.camera, args.start, args.fps);
+        }
+        if let Some(path) = &args.save_camera_path {
+            animation::save_camera_keyframes(path, &scene.camera_animation)?;
+            eprintln!("Saved {}", path.display());
+        }
+    }
+
+    let mut renderer = match &args.adapter {
+        Some(name) => RaytracingRenderer::with_adapter_name(name)
+            .await
+            .ok_or_else(|| format!("No adapter matching \"{name}\" found"))?,
+        None => RaytracingRenderer::try_new()
+            .await
+            .ok_or("No suitable adapter found")?,
+    };
+    eprintln!("Rendering on {}", renderer.adapter_info().name);
+    configure(&mut renderer, &args).await?;
+    renderer.validate_settings(&settings(&args, scene.as_ref()))?;
+
+    let end = args
+        .end
+        .or(args.turntable.map(|duration| args.start + duration));
+    if let (Some(end), Some(scene)) = (end, &scene) {
+        return render_sequence(&mut renderer, &args, scene, end).await;
+    }
+
+    if let (true, Some(scene)) = (args.layers, &scene) {
+        return render_layers(&mut renderer, &args, scene).await;
+    }
+
+    if let Some(scene) = &scene {
+        renderer.set_scene(scene)?;
+    }
+    #[cfg(feature = "server")]
+    if let Some(address) = &args.serve {
+        let listener = std::net::TcpListener::bind(address)?;
+        eprintln!("Serving on http://{}", listener.local_addr()?);
+        return Ok(raytracing::server::serve(&renderer, listener)?);
+    }
+    if let (Some(object), Some(scene)) = (&args.lightmap, &scene) {
+        if !is_exr(&args.output) {
+            return Err("Lightmaps can only be written as OpenEXR".into());
+        }
+        let settings = settings(&args, Some(scene));
+        let resolution = args.lightmap_resolution;
+        let pixels = lightmap::bake(&renderer, scene, object, &settings, resolution).await?;
+        let image = image::Rgba32FImage::from_raw(resolution, resolution, pixels)
+            .ok_or("Baked lightmap doesn't match its dimensions")?;
+        image::DynamicImage::ImageRgba32F(image).save(&args.output)?;
+        eprintln!("Saved {}", args.output.display());
+        return Ok(());
+    }
+    if !args.probe.is_empty() {
+        let settings = settings(&args, scene.as_ref());
+        let probes = probes::bake(&renderer, &settings, &args.probe, args.probe_resolution).await;
+        fs::write(&args.output, serde_json::to_string(&probes)?)?;
+        eprintln!("Saved {}", args.output.display());
+        return Ok(());
+    }
+    if let Some(ipd) = args.stereo {
+        let settings = settings(&args, scene.as_ref());
+        let stereo = Stereo {
+            ipd,
+            convergence: args.convergence.unwrap_or(f32::INFINITY),
+        };
+        let pixels = renderer.render_stereo_side_by_side(&settings, stereo).await;
+        image::save_buffer(
+            &args.output,
+            &pixels,
+            settings.width * 2,
+            settings.height,
+            image::ColorType::Rgba8,
+        )?;
+        eprintln!("Saved {}", args.output.display());
+        return Ok(());
+    }
+    if args.time_heatmap {
+        if is_exr(&args.output) {
+            return Err("Heatmaps can't be written as OpenEXR".into());
+        }
+        let settings = settings(&args, scene.as_ref());
+        let times = renderer.time_tiles(&settings).await;
+        let pixels = analysis::time_heatmap(&times, settings.width, settings.height);
+        image::save_buffer(
+            &args.output,
+            &pixels,
+            settings.width,
+            settings.height,
+            image::ColorType::Rgba8,
+        )?;
+        let slowest = times
+            .iter()
+            .map(|(_, time)| *time)
+            .max()
+            .unwrap_or_default();
+        eprintln!(
+            "Saved {}, slowest tile {:.1} ms",
+            args.output.display(),
+            slowest.as_secs_f64() * 1000.0
+        );
+        return Ok(());
+    }
+    if args.false_color || args.histogram.is_some() {
+        return analyze(&renderer, &args, &settings(&args, scene.as_ref())).await;
+    }
+    render(
+        &renderer,
+        &settings(&args, scene.as_ref()),
+        args.supersample,
+        &args.output,
+    )
+    .await?;
+
+    match (args.watch, &args.scene, scene) {
+        (true, Some(path), Some(scene)) => watch(&mut renderer, &args, path, scene).await,
+        _ => Ok(()),
+    }
+}
+
+/// Benchmarks the scenes of `bench_args` on the adapter of `args`, or on every GPU.
+async fn run_bench(args: &Args, bench_args: &BenchArgs) -> Result<(), Box<dyn Error>> {
+    let mut renderers = Vec::new();
+    if bench_args.all_adapters {
+        let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
+        // The same GPU can show up once per backend, only keep the first of them.
+        let mut seen = HashSet::new();
+        for adapter in instance.enumerate_adapters(wgpu::Backends::PRIMARY) {
+            let info = adapter.get_info();
+            if matches!(
+                info.device_type,
+                wgpu::DeviceType::DiscreteGpu | wgpu::DeviceType::IntegratedGpu
+            ) && seen.insert((info.vendor, info.device))
+            {
+                renderers.push(RaytracingRenderer::from_adapter(adapter).await);
+            }
+        }
+        if renderers.is_empty() {
+            return Err("No GPU found".into());
+        }
+    } else {
+        renderers.push(match &args.adapter {
+            Some(name) => RaytracingRenderer::with_adapter_name(name)
+                .await
+                .ok_or_else(|| format!("No adapter matching \"{name}\" found"))?,
+            None => RaytracingRenderer::try_new()
+                .await
+                .ok_or("No suitable adapter found")?,
+        });
+    }
+
+    let scenes: Vec<BenchScene> = if bench_args.scene.is_empty() {
+        BenchScene::ALL.to_vec()
+    } else {
+        bench_args
+            .scene
+            .iter()
+            .map(|scene| match scene {
+                BenchSceneArg::CornellBox => BenchScene::CornellBox,
+                BenchSceneArg::SphereField => BenchScene::SphereField,
+                BenchSceneArg::HighPolyMesh => BenchScene::HighPolyMesh,
+            })
+            .collect()
+    };
+    let settings = override_settings(args, bench::settings());
+    println!(
+        "{}x{} pixels, {} samples per pixel, {} bounces, {} warmup and {} timed renders",
+        settings.width,
+        settings.height,
+        settings.samples_per_pixel,
+        settings.max_bounces,
+        bench_args.warmup,
+        bench_args.iterations,
+    );
+
+    for renderer in &mut renderers {
+        configure(renderer, args).await?;
+        println!("{}", renderer.adapter_info().name);
+        for &scene in &scenes {
+            let result = bench::run(
+                renderer,
+                scene,
+                &settings,
+                bench_args.warmup,
+                bench_args.iterations,
+            )
+            .await;
+            let ms = |time: Duration| time.as_secs_f64() * 1000.0;
+            println!(
+                "  {:<16} mean {:8.2} ms | min {:8.2} ms | max {:8.2} ms | {:8.2} Mrays/s",
+                scene.name(),
+                ms(result.mean()),
+                ms(result.fastest()),
+                ms(result.slowest()),
+                result.mrays_per_second(),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Applies the shader and traversal flags of `args` to `renderer`.
+async fn configure(renderer: &mut RaytracingRenderer, args: &Args) -> Result<(), Box<dyn Error>> {
+    if !args.defines.is_empty() || !args.undefines.is_empty() {
+        let mut defines = renderer.shader_defines().clone();
+        for define in &args.defines {
+            let (name, value) = define.split_once('=').unwrap_or((define, ""));
+            defines.insert(name.to_owned(), value.to_owned());
+        }
+        for name in &args.undefines {
+            defines.remove(name);
+        }
+        renderer.set_shader_defines(defines).await?;
+    }
+    if args.deterministic {
+        renderer.set_deterministic(true).await?;
+    }
+    renderer.set_memory_budget(args.memory_budget.map(|mib| mib << 20));
+    renderer.set_path_sorting(args.sort_paths);
+    renderer.set_persistent_threads(args.persistent_threads);
+    renderer.set_hybrid_primary(args.hybrid);
+    if let Some(size) = args.workgroup_size {
+        renderer.set_workgroup_size(size).await?;
+    }
+    if args.tune_workgroup_size {
+        let size = match tuning_cache_path() {
+            Some(path) => tuning::tune_workgroup_size_cached(renderer, path).await?,
+            None => tuning::tune_workgroup_size(renderer).await?,
+        };
+        eprintln!("Using {size}x{size} workgroups");
+    }
+    if let Some(dir) = &args.shaders {
+        renderer.reload_shaders(dir).await?;
+    }
+    Ok(())
+}
+
+fn load_scene(path: &Path) -> Result<Scene, Box<dyn Error>> {
+    Ok(Scene::load(path).map_err(|err| format!("{}: {err}", path.display()))?)
+}
+
+/// Settings of `scene` with the ones passed on the command line replacing them.
+fn settings(args: &Args, scene: Option<&Scene>) -> RenderSettings {
+    let settings = scene
+        .map(|scene| scene.settings.clone())
+        .unwrap_or_default();
+    override_settings(args, settings)
+}
+
+/// `settings` with the ones passed on the command line replacing them.
+fn override_settings(args: &Args, mut settings: RenderSettings) -> RenderSettings {
+    let overrides = [
+        (args.width, &mut settings.width),
+        (args.height, &mut settings.height),
+        (args.spp, &mut settings.samples_per_pixel),
+        (args.tile_size, &mut settings.tile_size),
+        (args.seed, &mut settings.seed),
+        (args.bounces, &mut settings.max_bounces),
+    ];
+    for (value, setting) in overrides {
+        if let Some(value) = value {
+            *setting = value;
+        }
+    }
+    for (value, setting) in [
+        (args.ray_epsilon, &mut settings.ray_epsilon),
+        (args.ray_offset, &mut settings.ray_offset),
+    ] {
+        if let Some(value) = value {
+            *setting = value;
+        }
+    }
+    settings.half_precision_accumulation |= args.half_precision;
+    settings.transparent_background |= args.transparent;
+    if let Some(filter) = args.filter {
+        settings.filter = match filter {
+            FilterArg::Box => PixelFilter::Box {
+                radius: args.filter_radius.unwrap_or(0.5),
+            },
+            FilterArg::Gaussian => PixelFilter::Gaussian {
+                radius: args.filter_radius.unwrap_or(1.5),
+            },
+            FilterArg::Mitchell => PixelFilter::Mitchell {
+                radius: args.filter_radius.unwrap_or(2.0),
+            },
+            FilterArg::BlackmanHarris => PixelFilter::BlackmanHarris {
+                radius: args.filter_radius.unwrap_or(2.0),
+            },
+        };
+    }
+    if let Some(rays) = args.ao_rays {
+        settings.integrator = Integrator::AmbientOcclusion {
+            rays,
+            max_distance: args.ao_distance,
+        };
+    }
+    if let Some(photons) = args.photons {
+        settings.integrator = Integrator::PhotonMapping {
+            photons,
+            radius: args.photon_radius,
+        };
+    }
+    if args.bidirectional {
+        settings.integrator = Integrator::Bidirectional;
+    }
+    if let Some(candidates) = args.restir {
+        settings.integrator = Integrator::Restir {
+            candidates,
+            indirect: args.restir_indirect,
+        };
+    }
+    if args.path_guiding {
+        settings.integrator = Integrator::PathGuiding;
+    }
+    if let Some(cache_samples) = args.final_gather {
+        settings.integrator = Integrator::FinalGather { cache_samples };
+    }
+    if let Some(view) = args.debug_view {
+        settings.integrator = Integrator::Debug(match view {
+            DebugViewArg::Normals => DebugView::Normals,
+            DebugViewArg::Uv => DebugView::Uv,
+            DebugViewArg::Depth => DebugView::Depth {
+                max_distance: args.depth_distance,
+            },
+            DebugViewArg::BvhBounds => DebugView::BvhBounds,
+            DebugViewArg::TraversalHeatmap => DebugView::TraversalHeatmap {
+                max_tests: args.heatmap_max_tests,
+            },
+        });
+    }
+    settings
+}
+
+async fn render(
+    renderer: &RaytracingRenderer,
+    settings: &RenderSettings,
+    supersample: Option<u32>,
+    output: &Path,
+) -> Result<(), Box<dyn Error>> {
+    if is_exr(output) {
+        if supersample.is_some() {
+            return Err("Supersampling only applies to RGBA8 images, not OpenEXR".into());
+        }
+        let pixels = renderer
+            .render_hdr_with_progress(settings, print_progress)
+            .await;
+        finish_progress();
+
+        let image = image::Rgba32FImage::from_raw(settings.width, settings.height, pixels)
+            .ok_or("Rendered image doesn't match its dimensions")?;
+        image::DynamicImage::ImageRgba32F(image).save(output)?;
+    } else {
+        let pixels = match supersample {
+            Some(factor) => {
+                renderer
+                    .render_supersampled_with_progress(settings, factor, print_progress)
+                    .await?
+            }
+            None => {
+                renderer
+                    .render_with_progress(settings, print_progress)
+                    .await
+            }
+        };
+        finish_progress();
+
+        image::save_buffer(
+            output,
+            &pixels,
+            settings.width,
+            settings.height,
+            image::ColorType::Rgba8,
+        )?;
+    }
+
+    eprintln!("Saved {}", output.display());
+    Ok(())
+}
+
+async fn render_sequence(
+    renderer: &mut RaytracingRenderer,
+    args: &Args,
+    scene: &Scene,
+    end: f32,
+) -> Result<(), Box<dyn Error>> {
+    if is_exr(&args.output) {
+        return Err("Animations can't be written as OpenEXR".into());
+    }
+    if args.fps <= 0.0 {
+        return Err("Frames per second have to be positive".into());
+    }
+
+    let settings = settings(args, Some(scene));
+    let frames = animation::frame_times(args.start, end, args.fps).count();
+    let mut encoder = match video::is_video(&args.output) {
+        true => Some(VideoEncoder::new(
+            &args.output,
+            settings.width,
+            settings.height,
+            args.fps,
+        )?),
+        false => None,
+    };
+    let mut result: Result<(), Box<dyn Error>> = Ok(());
+
+    renderer
+        .render_sequence(
+            scene,
+            &settings,
+            args.start,
+            end,
+            args.fps,
+            |index, pixels| {
+                eprint!("\rframe {}/{frames}   ", index + 1);
+                let _ = io::stderr().flush();
+
+                if result.is_ok() {
+                    result = match &mut encoder {
+                        Some(encoder) => encoder.write_frame(pixels).map_err(Into::into),
+                        None => image::save_buffer(
+                            frame_path(&args.output, index),
+                            pixels,
+                            settings.width,
+                            settings.height,
+                            image::ColorType::Rgba8,
+                        )
+                        .map_err(Into::into),
+                    };
+                }
+            },
+        )
+        .await?;
+    finish_progress();
+    result?;
+
+    match encoder {
+        Some(encoder) => {
+            encoder.finish()?;
+            eprintln!("Saved {}", args.output.display());
+        }
+        None => eprintln!("Saved {frames} frames"),
+    }
+    Ok(())
+}
 
-    let raw_bytes = RaytracingRenderer::new()
-        .await
-        .render_as_rgba8unorm_slice(dimension, dimension)
+/// Renders an HDR image and writes the exposure analysis `args` asks for, with the render itself
+/// or its false color image to the output.
+async fn analyze(
+    renderer: &RaytracingRenderer,
+    args: &Args,
+    settings: &RenderSettings,
+) -> Result<(), Box<dyn Error>> {
+    let pixels = renderer
+        .render_hdr_with_progress(settings, print_progress)
         .await;
+    finish_progress();
+
+    if let Some(path) = &args.histogram {
+        let histogram = Histogram::new(
+            &pixels,
+            HISTOGRAM_MIN_STOPS,
+            HISTOGRAM_MAX_STOPS,
+            HISTOGRAM_BINS,
+        );
+        fs::write(path, serde_json::to_string(&histogram)?)?;
+        eprintln!(
+            "Saved {}, {} clipped pixels, mean {:+.2} stops",
+            path.display(),
+            histogram.clipped,
+            histogram.mean_stops
+        );
+    }
+
+    if args.false_color {
+        if is_exr(&args.output) {
+            return Err("False color images can't be written as OpenEXR".into());
+        }
+        image::save_buffer(
+            &args.output,
+            &analysis::false_color(&pixels),
+            settings.width,
+            settings.height,
+            image::ColorType::Rgba8,
+        )?;
+    } else if is_exr(&args.output) {
+        let image = image::Rgba32FImage::from_raw(settings.width, settings.height, pixels)
+            .ok_or("Rendered image doesn't match its dimensions")?;
+        image::DynamicImage::ImageRgba32F(image).save(&args.output)?;
+    } else {
+        let pixels: Vec<u8> = pixels
+            .iter()
+            .map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect();
+        image::save_buffer(
+            &args.output,
+            &pixels,
+            settings.width,
+            settings.height,
+            image::ColorType::Rgba8,
+        )?;
+    }
+    eprintln!("Saved {}", args.output.display());
+    Ok(())
+}
+
+/// Renders every layer of `scene` to `args.output` with the name of the layer appended.
+async fn render_layers(
+    renderer: &mut RaytracingRenderer,
+    args: &Args,
+    scene: &Scene,
+) -> Result<(), Box<dyn Error>> {
+    if scene.layers.is_empty() {
+        return Err("The scene has no layers".into());
+    }
+
+    let settings = settings(args, Some(scene));
+    for layer in &scene.layers {
+        let layer_scene = scene.layer(&layer.name).expect("Layers of the scene exist");
+        renderer.set_scene(&layer_scene)?;
+        render(
+            renderer,
+            &settings,
+            args.supersample,
+            &layer_path(&args.output, &layer.name),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Path of the image of the layer called `name` written to `output`.
+fn layer_path(output: &Path, name: &str) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match output.extension() {
+        Some(extension) => format!("{stem}_{name}.{}", extension.to_string_lossy()),
+        None => format!("{stem}_{name}"),
+    };
+    output.with_file_name(name)
+}
+
+/// Path of frame `index` of an animation written to `output`.
+fn frame_path(output: &Path, index: u32) -> PathBuf {
+    let name = output
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let name = match name.find('#') {
+        Some(start) => {
+            let width = name[start..].chars().take_while(|&c| c == '#').count();
+            format!(
+                "{}{index:0width$}{}",
+                &name[..start],
+                &name[start + width..]
+            )
+        }
+        None => {
+            let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+            match output.extension() {
+                Some(extension) => format!("{stem}_{index:04}.{}", extension.to_string_lossy()),
+                None => format!("{stem}_{index:04}"),
+            }
+        }
+    };
+
+    output.with_file_name(name)
+}
+
+/// Re-renders the scene at `path` every time it, one of its meshes or one of the shaders
+/// changes, forever.
+///
+/// Errors in the edited scene or shaders are reported and the previous render is kept until
+/// the next change.
+async fn watch(
+    renderer: &mut RaytracingRenderer,
+    args: &Args,
+    path: &Path,
+    mut scene: Scene,
+) -> Result<(), Box<dyn Error>> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+
+    loop {
+        // Editors often save by replacing files, which only directory watches survive.
+        let scene_files = watched_files(path, &scene);
+        let shader_files = shader_files(args);
+        let files: HashSet<_> = scene_files.union(&shader_files).cloned().collect();
+        let directories: HashSet<_> = files
+            .iter()
+            .map(|file| file.parent().unwrap_or(Path::new(".")).to_owned())
+            .collect();
+        for directory in &directories {
+            watcher.watch(directory, RecursiveMode::NonRecursive)?;
+        }
+
+        eprintln!("Watching {} for changes", path.display());
+        let changed = wait_for_change(&receiver, &files)?;
+
+        for directory in &directories {
+            let _ = watcher.unwatch(directory);
+        }
+
+        let result = async {
+            if let Some(dir) = &args.shaders {
+                if !changed.is_disjoint(&shader_files) {
+                    renderer.reload_shaders(dir).await?;
+                }
+            }
+            if !changed.is_disjoint(&scene_files) {
+                let new_scene = load_scene(path)?;
+                renderer.set_scene(&new_scene)?;
+                scene = new_scene;
+            }
+            render(
+                renderer,
+                &settings(args, Some(&scene)),
+                args.supersample,
+                &args.output,
+            )
+            .await?;
+            Ok::<_, Box<dyn Error>>(())
+        };
+        if let Err(err) = result.await {
+            eprintln!("error: {err}");
+        }
+    }
+}
+
+/// Files a render of `scene`, loaded from `path`, depends on.
+fn watched_files(path: &Path, scene: &Scene) -> HashSet<PathBuf> {
+    let instances = scene.instances();
+    let files = instances
+        .iter()
+        .filter_map(|instance| match &instance.object.shape {
+            Shape::Mesh { path } | Shape::Heightfield { path, .. } => Some(path.as_path()),
+            _ => None,
+        });
+
+    std::iter::once(path)
+        .chain(files)
+        .map(|file| fs::canonicalize(file).unwrap_or_else(|_| file.to_owned()))
+        .collect()
+}
+
+/// Shader files of the `--shaders` directory.
+fn shader_files(args: &Args) -> HashSet<PathBuf> {
+    let Some(dir) = &args.shaders else {
+        return HashSet::new();
+    };
+    ShaderSources::builtin()
+        .names()
+        .map(|name| {
+            let file = dir.join(name);
+            fs::canonicalize(&file).unwrap_or(file)
+        })
+        .collect()
+}
+
+/// Blocks until some of `files` change, waiting for the burst of events of a save to settle,
+/// and returns the ones that did.
+fn wait_for_change(
+    receiver: &mpsc::Receiver<notify::Result<notify::Event>>,
+    files: &HashSet<PathBuf>,
+) -> Result<HashSet<PathBuf>, Box<dyn Error>> {
+    let collect = |changed: &mut HashSet<PathBuf>, event: notify::Result<notify::Event>| {
+        let event = event?;
+        if matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+        ) {
+            changed.extend(event.paths.into_iter().filter(|path| files.contains(path)));
+        }
+        notify::Result::Ok(())
+    };
+
+    let mut changed = HashSet::new();
+    while changed.is_empty() {
+        collect(&mut changed, receiver.recv()?)?;
+    }
+    while let Ok(event) = receiver.recv_timeout(WATCH_DEBOUNCE) {
+        collect(&mut changed, event)?;
+    }
+
+    Ok(changed)
+}
+
+/// Where tuned workgroup sizes are cached, in the user's cache directory.
+fn tuning_cache_path() -> Option<PathBuf> {
+    let cache = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+    Some(cache.join("raytracing").join("workgroup_sizes.json"))
+}
+
+/// Parses `X,Y,Z` into a position.
+fn parse_position(value: &str) -> Result<[f32; 3], String> {
+    let coordinates = value
+        .split(',')
+        .map(|c| c.trim().parse::<f32>().map_err(|err| err.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    coordinates
+        .try_into()
+        .map_err(|_| "Expected three coordinates, X,Y,Z".to_owned())
+}
+
+fn is_exr(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("exr"))
+}
+
+fn print_progress(progress: Progress) {
+    let eta = progress.eta().map_or_else(
+        || "--".to_owned(),
+        |eta| format!("{:.0}s", eta.as_secs_f64()),
+    );
+
+    eprint!(
+        "\r{:5.1}% | tiles {}/{} | {:.1}s elapsed | eta {eta}   ",
+        progress.fraction() * 100.0,
+        progress.tiles_done,
+        progress.tiles_total,
+        progress.elapsed.as_secs_f64(),
+    );
+    let _ = io::stderr().flush();
+}
 
-    image::save_buffer("out.png", &raw_bytes, dimension, dimension, image::ColorType::Rgba8).unwrap();
+fn finish_progress() {
+    eprintln!();
 }
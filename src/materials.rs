@@ -0,0 +1,73 @@
+//! Materials registered by name whose parameters can change while a scene is rendered, e.g. to
+//! tweak them live in a viewer between progressive batches, see
+//! [`RaytracingRenderer::update_materials`](crate::renderer::RaytracingRenderer::update_materials).
+
+use crate::scene::{Material, Scene};
+
+/// Materials by name, replacing the materials of scenes with the same names.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MaterialLibrary {
+    materials: Vec<Material>,
+}
+
+impl MaterialLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Library of the materials of `scene`, to change them from there.
+    pub fn from_scene(scene: &Scene) -> Self {
+        Self {
+            materials: scene.materials.clone(),
+        }
+    }
+
+    /// Adds `material` under its name, returning the one it replaces.
+    pub fn register(&mut self, material: Material) -> Option<Material> {
+        match self.get_mut(&material.name) {
+            Some(registered) => Some(std::mem::replace(registered, material)),
+            None => {
+                self.materials.push(material);
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Material> {
+        let index = self
+            .materials
+            .iter()
+            .position(|material| material.name == name)?;
+        Some(self.materials.remove(index))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Material> {
+        self.materials.iter().find(|material| material.name == name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Material> {
+        self.materials
+            .iter_mut()
+            .find(|material| material.name == name)
+    }
+
+    /// Materials in the order they were registered.
+    pub fn iter(&self) -> impl Iterator<Item = &Material> {
+        self.materials.iter()
+    }
+
+    /// Replaces the materials of `scene` with the ones of the same names and adds the others,
+    /// e.g. to set the scene again with changes that can't be made in place.
+    pub fn apply_to(&self, scene: &mut Scene) {
+        for material in &self.materials {
+            match scene
+                .materials
+                .iter_mut()
+                .find(|scene_material| scene_material.name == material.name)
+            {
+                Some(scene_material) => *scene_material = material.clone(),
+                None => scene.materials.push(material.clone()),
+            }
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+use tracing::instrument;
+use wgpu::{Backends, DeviceType, Instance};
+
+use crate::{
+    renderer::RaytracingRenderer,
+    scene::{Scene, SceneError},
+    settings::RenderSettings,
+    tile,
+};
+
+/// Renders images on several GPUs at once.
+///
+/// Every renderer pulls the next tile from a shared queue as soon as it's done with the previous
+/// one, so faster GPUs naturally end up rendering more of the image. The tiles are merged on the
+/// host.
+pub struct MultiGpuRenderer {
+    renderers: Vec<RaytracingRenderer>,
+}
+
+impl MultiGpuRenderer {
+    /// Creates a renderer on every discrete and integrated GPU of the system.
+    ///
+    /// # Panics
+    ///
+    /// If no GPU is found.
+    #[instrument(name = "MultiGpuRenderer::new")]
+    pub async fn new() -> Self {
+        let instance = Instance::new(Backends::PRIMARY);
+
+        // The same GPU can show up once per backend, only keep the first of them.
+        let mut seen = HashSet::new();
+        let adapters: Vec<_> = instance
+            .enumerate_adapters(Backends::PRIMARY)
+            .filter(|adapter| {
+                let info = adapter.get_info();
+                matches!(
+                    info.device_type,
+                    DeviceType::DiscreteGpu | DeviceType::IntegratedGpu
+                ) && seen.insert((info.vendor, info.device))
+            })
+            .collect();
+
+        assert!(!adapters.is_empty(), "No suitable adapter found");
+
+        let mut renderers = Vec::with_capacity(adapters.len());
+        for adapter in adapters {
+            renderers.push(RaytracingRenderer::from_adapter(adapter).await);
+        }
+
+        Self::from_renderers(renderers)
+    }
+
+    /// # Panics
+    ///
+    /// If `renderers` is empty.
+    pub fn from_renderers(renderers: Vec<RaytracingRenderer>) -> Self {
+        assert!(!renderers.is_empty(), "At least one renderer is required");
+        Self { renderers }
+    }
+
+    pub fn renderers(&self) -> &[RaytracingRenderer] {
+        &self.renderers
+    }
+
+    /// Uploads `scene` to every GPU, see [`RaytracingRenderer::set_scene`].
+    pub fn set_scene(&mut self, scene: &Scene) -> Result<(), SceneError> {
+        for renderer in &mut self.renderers {
+            renderer.set_scene(scene)?;
+        }
+        Ok(())
+    }
+
+    /// Renders an image as tightly packed RGBA8 rows, splitting its tiles between all the GPUs.
+    #[instrument(skip(self))]
+    pub fn render(&self, settings: &RenderSettings) -> Vec<u8> {
+        let rects: Vec<_> =
+            tile::tiles(settings.width, settings.height, settings.tile_size).collect();
+        let next_rect = AtomicUsize::new(0);
+
+        let mut image = vec![0; settings.width as usize * settings.height as usize * 4];
+
+        // Waiting on a device blocks the calling thread, so every GPU is driven by its own
+        // thread rather than by tasks sharing one.
+        thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+
+            for renderer in &self.renderers {
+                let sender = sender.clone();
+                let (rects, next_rect) = (&rects, &next_rect);
+
+                scope.spawn(move || {
+                    let queue = std::iter::from_fn(|| {
+                        rects
+                            .get(next_rect.fetch_add(1, Ordering::Relaxed))
+                            .copied()
+                    });
+
+                    async_std::task::block_on(renderer.render_tile_rects(
+                        settings,
+                        queue,
+                        |tile| {
+                            // The receiver only goes away once every tile has arrived.
+                            sender.send(tile).unwrap();
+                        },
+                    ));
+                });
+            }
+            drop(sender);
+
+            for tile in receiver {
+                tile.copy_into(&mut image, settings.width);
+            }
+        });
+
+        image
+    }
+}
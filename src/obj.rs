@@ -0,0 +1,191 @@
+//! Importer for [Wavefront OBJ](https://paulbourke.net/dataformats/obj/) files as whole scenes,
+//! with the materials of the MTL files they refer to.
+//!
+//! Every model of the file becomes an object with its MTL material mapped onto [`Material`]:
+//! diffuse colors and maps become the base color, `Ke` the emission, `d` the opacity, `Ni` the
+//! IOR and `disp` maps the displacement. `illum` models with refraction make materials
+//! transmissive, ones with ray traced reflections make them metallic as far as their specular
+//! color goes and ones without highlights turn off the specular coat. The `Pm` and `Pr` values of the PBR extension take
+//! precedence over that, and its `Pc` and `Pcr` set the clear coat. The camera looks at the
+//! bounds of the models, which are lit by the sky.
+
+use std::path::Path;
+
+use cgmath::{InnerSpace, Point3, Vector3};
+
+use crate::scene::{Camera, Material, Object, Scene, SceneError, Shape};
+
+/// Vertical field of view of the camera of imported scenes, in degrees.
+const FOV: f32 = 40.0;
+
+/// Loads an `.obj` file and the `.mtl` files it refers to.
+pub fn load(path: impl AsRef<Path>) -> Result<Scene, SceneError> {
+    let path = path.as_ref();
+    let (models, materials) = tobj::load_obj(
+        path,
+        &tobj::LoadOptions {
+            triangulate: true,
+            single_index: true,
+            ..Default::default()
+        },
+    )
+    .map_err(|source| SceneError::Obj {
+        path: path.to_owned(),
+        source,
+    })?;
+
+    let base = path.parent().unwrap_or(Path::new(""));
+    let materials = materials.unwrap_or_else(|err| {
+        tracing::warn!(%err, "Could not load the materials, using the default one");
+        Vec::new()
+    });
+
+    let mut scene = Scene {
+        materials: materials.iter().map(|mtl| material(mtl, base)).collect(),
+        ..Default::default()
+    };
+
+    let (mut min, mut max) = ([f32::MAX; 3], [f32::MIN; 3]);
+    for model in models {
+        let mesh = model.mesh;
+        let vertices = mesh.positions.len() / 3;
+        let positions: Vec<_> = mesh
+            .positions
+            .chunks_exact(3)
+            .map(|p| [p[0], p[1], p[2]])
+            .collect();
+        for position in &positions {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(position[axis]);
+                max[axis] = max[axis].max(position[axis]);
+            }
+        }
+
+        let normals = if mesh.normals.len() == vertices * 3 {
+            mesh.normals
+                .chunks_exact(3)
+                .map(|n| [n[0], n[1], n[2]])
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let texcoords = if mesh.texcoords.len() == vertices * 2 {
+            mesh.texcoords
+                .chunks_exact(2)
+                .map(|t| [t[0], t[1]])
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        scene.objects.push(Object {
+            name: model.name,
+            material: mesh
+                .material_id
+                .and_then(|id| scene.materials.get(id))
+                .map(|material| material.name.clone()),
+            ..Object::new(Shape::Triangles {
+                positions,
+                normals,
+                texcoords,
+                indices: mesh.indices,
+            })
+        });
+    }
+
+    if min[0] <= max[0] {
+        scene.camera = framing(Point3::from(min), Point3::from(max));
+    }
+    Ok(scene)
+}
+
+/// Maps an MTL material onto the lobes of [`Material`], with textures relative to `base`.
+fn material(mtl: &tobj::Material, base: &Path) -> Material {
+    let param = |name: &str| {
+        mtl.unknown_param
+            .get(name)
+            .and_then(|value| value.split_whitespace().next()?.parse::<f32>().ok())
+    };
+    let rgb = |name: &str| {
+        let values: Vec<f32> = mtl
+            .unknown_param
+            .get(name)?
+            .split_whitespace()
+            .map_while(|value| value.parse().ok())
+            .collect();
+        match values[..] {
+            [r, g, b] => Some([r, g, b]),
+            [gray] => Some([gray; 3]),
+            _ => None,
+        }
+    };
+
+    // Glass, reflections from a ray traced specular color, and no highlights at all.
+    let illum = mtl.illumination_model.unwrap_or(2);
+    let specular = if illum < 2 {
+        0.0
+    } else {
+        Material::default().specular
+    };
+    let transmission = if matches!(illum, 4 | 6 | 7 | 9) {
+        1.0
+    } else {
+        0.0
+    };
+    let metallic = if matches!(illum, 3 | 5) {
+        mtl.specular.iter().copied().fold(0.0, f32::max)
+    } else {
+        0.0
+    };
+    // The Phong exponent as the roughness of the matching microfacet lobe, glass without one is
+    // clear.
+    let roughness = if mtl.shininess > 0.0 {
+        (2.0 / (mtl.shininess + 2.0)).sqrt()
+    } else if transmission > 0.0 {
+        0.0
+    } else {
+        Material::default().roughness
+    };
+
+    Material {
+        name: mtl.name.clone(),
+        base_color: mtl.diffuse,
+        base_color_texture: (!mtl.diffuse_texture.is_empty())
+            .then(|| base.join(mtl.diffuse_texture.replace('\\', "/"))),
+        metallic: param("Pm").unwrap_or(metallic).clamp(0.0, 1.0),
+        roughness: param("Pr").unwrap_or(roughness).clamp(0.0, 1.0),
+        specular,
+        clearcoat: param("Pc").unwrap_or_default().clamp(0.0, 1.0),
+        clearcoat_roughness: param("Pcr")
+            .unwrap_or(Material::default().clearcoat_roughness)
+            .clamp(0.0, 1.0),
+        ior: if mtl.optical_density > 1.0 {
+            mtl.optical_density
+        } else {
+            Material::default().ior
+        },
+        transmission,
+        emission: rgb("Ke").unwrap_or_default(),
+        opacity: mtl.dissolve.clamp(0.0, 1.0),
+        // Options like `-mm` come before the file name.
+        displacement_texture: mtl
+            .unknown_param
+            .get("disp")
+            .and_then(|value| value.split_whitespace().last())
+            .map(|path| base.join(path.replace('\\', "/"))),
+        ..Default::default()
+    }
+}
+
+/// Camera looking at the center of the bounds from the front, far enough to see all of them.
+fn framing(min: Point3<f32>, max: Point3<f32>) -> Camera {
+    let center = min + (max - min) * 0.5;
+    let radius = (max - center).magnitude().max(1.0e-3);
+    let distance = radius / (FOV.to_radians() * 0.5).sin();
+    Camera {
+        position: (center + Vector3::unit_z() * distance).into(),
+        look_at: center.into(),
+        fov: FOV,
+        ..Default::default()
+    }
+}
@@ -0,0 +1,1250 @@
+//! Importer for a subset of the [PBRT](https://pbrt.org) v3 and v4 scene formats.
+//!
+//! Supported are the perspective camera, film resolution, sampler sample counts, the box,
+//! Gaussian and Mitchell pixel filters, integrator path depths, the `bdpt` and `sppm`
+//! integrators, transforms and attribute blocks, object instancing, `Include` and `Import`,
+//! `sphere`, `trianglemesh`, `plymesh` and `curve` shapes, point, distant and diffuse area
+//! lights, and the common materials mapped onto [`Material`].
+//! Anything else is skipped with a warning.
+//!
+//! PBRT's coordinate system is left-handed, imported scenes are mirrored along the X axis so
+//! that they render like they do in PBRT.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use cgmath::{
+    Deg, InnerSpace, Matrix, Matrix4, Point3, SquareMatrix, Transform as _, Vector3, Vector4,
+};
+
+use crate::{
+    scene::{Camera, CurveBasis, Light, Material, Object, Scene, SceneError, Shape},
+    settings::{Integrator, PixelFilter},
+};
+
+/// Loads a `.pbrt` file, together with the files it includes and the meshes it refers to.
+pub fn load(path: impl AsRef<Path>) -> Result<Scene, SceneError> {
+    let path = path.as_ref();
+    let mut importer = Importer::new(path.parent().unwrap_or(Path::new("")));
+    importer.parse_file(path)?;
+    Ok(importer.finish())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Number(f32),
+    Open,
+    Close,
+}
+
+struct Tokens {
+    path: PathBuf,
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+}
+
+impl Tokens {
+    fn new(path: &Path, source: &str) -> Result<Self, SceneError> {
+        let mut tokens = Vec::new();
+        let mut chars = source.chars().peekable();
+        let mut line = 1;
+
+        while let Some(&c) = chars.peek() {
+            match c {
+                '\n' => {
+                    line += 1;
+                    chars.next();
+                }
+                c if c.is_whitespace() => {
+                    chars.next();
+                }
+                '#' => while chars.next_if(|&c| c != '\n').is_some() {},
+                '[' => {
+                    tokens.push((Token::Open, line));
+                    chars.next();
+                }
+                ']' => {
+                    tokens.push((Token::Close, line));
+                    chars.next();
+                }
+                '"' => {
+                    chars.next();
+                    let mut string = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some('\\') => match chars.next() {
+                                Some('n') => string.push('\n'),
+                                Some('t') => string.push('\t'),
+                                Some(c) => string.push(c),
+                                None => break,
+                            },
+                            Some('\n') | None => {
+                                return Err(syntax_error(path, line, "Unterminated string"));
+                            }
+                            Some(c) => string.push(c),
+                        }
+                    }
+                    tokens.push((Token::Str(string), line));
+                }
+                _ => {
+                    let mut word = String::new();
+                    while let Some(c) = chars
+                        .next_if(|&c| !c.is_whitespace() && !matches!(c, '[' | ']' | '"' | '#'))
+                    {
+                        word.push(c);
+                    }
+                    let token = match word.parse() {
+                        Ok(number) => Token::Number(number),
+                        Err(_) => Token::Word(word),
+                    };
+                    tokens.push((token, line));
+                }
+            }
+        }
+
+        Ok(Self {
+            path: path.to_owned(),
+            tokens,
+            position: 0,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .map(|(token, _)| token.clone());
+        self.position += 1;
+        token
+    }
+
+    fn error(&self, message: impl Into<String>) -> SceneError {
+        let line = self
+            .tokens
+            .get(self.position.saturating_sub(1))
+            .or(self.tokens.last())
+            .map_or(1, |&(_, line)| line);
+        syntax_error(&self.path, line, message)
+    }
+
+    fn string(&mut self) -> Result<String, SceneError> {
+        match self.next() {
+            Some(Token::Str(string)) => Ok(string),
+            _ => Err(self.error("Expected a string")),
+        }
+    }
+
+    fn number(&mut self) -> Result<f32, SceneError> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(number),
+            _ => Err(self.error("Expected a number")),
+        }
+    }
+
+    /// `N` numbers, optionally enclosed in brackets like the arguments of `Transform`.
+    fn numbers<const N: usize>(&mut self) -> Result<[f32; N], SceneError> {
+        let bracketed = self.peek() == Some(&Token::Open);
+        if bracketed {
+            self.next();
+        }
+        let mut numbers = [0.0; N];
+        for number in &mut numbers {
+            *number = self.number()?;
+        }
+        if bracketed && self.next() != Some(Token::Close) {
+            return Err(self.error("Expected `]`"));
+        }
+        Ok(numbers)
+    }
+
+    /// Parameters following the arguments of a directive, up to the next directive.
+    fn parameters(&mut self) -> Result<Parameters, SceneError> {
+        let mut parameters = Parameters::default();
+
+        while let Some(Token::Str(declaration)) = self.peek() {
+            let mut words = declaration.split_whitespace();
+            let (Some(ty), Some(name), None) = (words.next(), words.next(), words.next()) else {
+                return Err(self.error(format!("Invalid parameter \"{declaration}\"")));
+            };
+            let (ty, name) = (ty.to_owned(), name.to_owned());
+            self.next();
+
+            let mut values = Vec::new();
+            if self.peek() == Some(&Token::Open) {
+                self.next();
+                while let Some(token) = self.next() {
+                    match token {
+                        Token::Close => break,
+                        Token::Open => return Err(self.error("Unexpected `[`")),
+                        value => values.push(value),
+                    }
+                }
+            } else {
+                values.extend(self.next());
+            }
+
+            parameters.0.push(Parameter { ty, name, values });
+        }
+
+        Ok(parameters)
+    }
+}
+
+fn syntax_error(path: &Path, line: usize, message: impl Into<String>) -> SceneError {
+    SceneError::Pbrt {
+        path: path.to_owned(),
+        line,
+        message: message.into(),
+    }
+}
+
+struct Parameter {
+    ty: String,
+    name: String,
+    values: Vec<Token>,
+}
+
+#[derive(Default)]
+struct Parameters(Vec<Parameter>);
+
+impl Parameters {
+    fn get(&self, name: &str) -> Option<&Parameter> {
+        self.0.iter().find(|parameter| parameter.name == name)
+    }
+
+    fn numbers(&self, name: &str) -> Vec<f32> {
+        self.get(name)
+            .map(|parameter| {
+                parameter
+                    .values
+                    .iter()
+                    .filter_map(|value| match value {
+                        Token::Number(number) => Some(*number),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn float(&self, name: &str) -> Option<f32> {
+        self.numbers(name).first().copied()
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        self.get(name)?.values.iter().find_map(|value| match value {
+            Token::Str(string) => Some(string.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Colour of a spectrum parameter, only RGB and constant spectra are understood.
+    fn rgb(&self, name: &str) -> Option<[f32; 3]> {
+        let parameter = self.get(name)?;
+        let numbers = self.numbers(name);
+        match (parameter.ty.as_str(), numbers.as_slice()) {
+            ("rgb" | "color", &[r, g, b]) => Some([r, g, b]),
+            ("float", &[value]) => Some([value; 3]),
+            ("texture", _) => {
+                tracing::warn!(name, "Textures aren't supported, using a constant colour");
+                None
+            }
+            (ty, _) => {
+                tracing::warn!(name, ty, "Unsupported spectrum, using a constant colour");
+                None
+            }
+        }
+    }
+}
+
+/// Graphics state saved by `AttributeBegin`.
+#[derive(Clone)]
+struct Attributes {
+    transform: Matrix4<f32>,
+    material: Option<String>,
+    area_light: Option<[f32; 3]>,
+}
+
+struct Importer {
+    base: PathBuf,
+    scene: Scene,
+    attributes: Attributes,
+    stack: Vec<Attributes>,
+    coordinate_systems: HashMap<String, Matrix4<f32>>,
+    /// Field of view along the shorter side of the image, converted once the film is known.
+    fov: f32,
+    /// Objects of `ObjectBegin` blocks, in the space of their definition.
+    instances: HashMap<String, Vec<Object>>,
+    /// Instance currently being defined.
+    defining: Option<(String, Vec<Object>)>,
+    anonymous_materials: usize,
+}
+
+impl Importer {
+    fn new(base: &Path) -> Self {
+        let mut scene = Scene::default();
+        // PBRT's own defaults.
+        scene.settings.width = 1280;
+        scene.settings.height = 720;
+        scene.settings.max_bounces = 5;
+
+        Self {
+            base: base.to_owned(),
+            scene,
+            attributes: Attributes {
+                transform: Matrix4::identity(),
+                material: None,
+                area_light: None,
+            },
+            stack: Vec::new(),
+            coordinate_systems: HashMap::new(),
+            fov: 90.0,
+            instances: HashMap::new(),
+            defining: None,
+            anonymous_materials: 0,
+        }
+    }
+
+    fn finish(mut self) -> Scene {
+        let aspect = self.scene.settings.width as f32 / self.scene.settings.height as f32;
+        self.scene.camera.fov = if aspect < 1.0 {
+            let tan_half = (self.fov.to_radians() * 0.5).tan() / aspect;
+            2.0 * tan_half.atan().to_degrees()
+        } else {
+            self.fov
+        };
+        self.scene
+    }
+
+    fn resolve(&self, path: &str) -> PathBuf {
+        self.base.join(path)
+    }
+
+    fn parse_file(&mut self, path: &Path) -> Result<(), SceneError> {
+        let source = fs::read_to_string(path)?;
+        let mut tokens = Tokens::new(path, &source)?;
+
+        while let Some(token) = tokens.next() {
+            let Token::Word(directive) = token else {
+                return Err(tokens.error("Expected a directive"));
+            };
+            self.directive(&directive, &mut tokens)?;
+        }
+
+        Ok(())
+    }
+
+    fn directive(&mut self, directive: &str, tokens: &mut Tokens) -> Result<(), SceneError> {
+        let transform = &mut self.attributes.transform;
+        match directive {
+            "Identity" => *transform = Matrix4::identity(),
+            "Translate" => {
+                let [x, y, z] = tokens.numbers()?;
+                *transform = *transform * Matrix4::from_translation(Vector3::new(x, y, z));
+            }
+            "Scale" => {
+                let [x, y, z] = tokens.numbers()?;
+                *transform = *transform * Matrix4::from_nonuniform_scale(x, y, z);
+            }
+            "Rotate" => {
+                let [angle, x, y, z] = tokens.numbers()?;
+                let axis = Vector3::new(x, y, z).normalize();
+                *transform = *transform * Matrix4::from_axis_angle(axis, Deg(angle));
+            }
+            "LookAt" => {
+                let [ex, ey, ez, lx, ly, lz, ux, uy, uz] = tokens.numbers()?;
+                *transform = *transform * look_at([ex, ey, ez], [lx, ly, lz], [ux, uy, uz]);
+            }
+            "Transform" => *transform = matrix(tokens.numbers()?),
+            "ConcatTransform" => *transform = *transform * matrix(tokens.numbers()?),
+            "CoordinateSystem" => {
+                let name = tokens.string()?;
+                self.coordinate_systems.insert(name, *transform);
+            }
+            "CoordSysTransform" => {
+                let name = tokens.string()?;
+                match self.coordinate_systems.get(&name) {
+                    Some(system) => *transform = *system,
+                    None => tracing::warn!(name, "Unknown coordinate system"),
+                }
+            }
+            "AttributeBegin" | "TransformBegin" => self.stack.push(self.attributes.clone()),
+            "AttributeEnd" | "TransformEnd" => {
+                let Some(attributes) = self.stack.pop() else {
+                    return Err(tokens.error(format!("Unmatched {directive}")));
+                };
+                if directive == "TransformEnd" {
+                    self.attributes.transform = attributes.transform;
+                } else {
+                    self.attributes = attributes;
+                }
+            }
+            "WorldBegin" => {
+                self.attributes.transform = Matrix4::identity();
+                self.coordinate_systems
+                    .insert("world".to_owned(), Matrix4::identity());
+            }
+            "WorldEnd" | "ReverseOrientation" => {}
+            "Camera" => {
+                let ty = tokens.string()?;
+                let parameters = tokens.parameters()?;
+                self.camera(&ty, &parameters);
+            }
+            "Film" => {
+                tokens.string()?;
+                let parameters = tokens.parameters()?;
+                let settings = &mut self.scene.settings;
+                if let Some(width) = parameters.float("xresolution") {
+                    settings.width = width.max(1.0) as u32;
+                }
+                if let Some(height) = parameters.float("yresolution") {
+                    settings.height = height.max(1.0) as u32;
+                }
+            }
+            "Sampler" => {
+                tokens.string()?;
+                let parameters = tokens.parameters()?;
+                if let Some(samples) = parameters.float("pixelsamples") {
+                    self.scene.settings.samples_per_pixel = samples.max(1.0) as u32;
+                }
+            }
+            "PixelFilter" => {
+                let ty = tokens.string()?;
+                let parameters = tokens.parameters()?;
+                let radius = |default| parameters.float("xradius").unwrap_or(default);
+                self.scene.settings.filter = match &*ty {
+                    "box" => PixelFilter::Box {
+                        radius: radius(0.5),
+                    },
+                    "gaussian" => PixelFilter::Gaussian {
+                        radius: radius(1.5),
+                    },
+                    "mitchell" => PixelFilter::Mitchell {
+                        radius: radius(2.0),
+                    },
+                    _ => {
+                        tracing::warn!(ty, "Unsupported pixel filter, using a box filter");
+                        PixelFilter::default()
+                    }
+                };
+            }
+            "Integrator" => {
+                let ty = tokens.string()?;
+                let parameters = tokens.parameters()?;
+                if let Some(depth) = parameters.float("maxdepth") {
+                    self.scene.settings.max_bounces = depth.max(0.0) as u32;
+                }
+                match &*ty {
+                    "bdpt" => self.scene.settings.integrator = Integrator::Bidirectional,
+                    "sppm" => {
+                        self.scene.settings.integrator = Integrator::PhotonMapping {
+                            photons: parameters
+                                .float("photonsperiteration")
+                                .unwrap_or(100_000.0)
+                                .max(1.0) as u32,
+                            radius: parameters.float("radius").unwrap_or(1.0),
+                        };
+                    }
+                    _ => {}
+                }
+            }
+            "Material" => {
+                let ty = tokens.string()?;
+                let parameters = tokens.parameters()?;
+                let name = format!("pbrt material {}", self.anonymous_materials);
+                self.anonymous_materials += 1;
+                self.scene
+                    .materials
+                    .push(material(name.clone(), &ty, &parameters));
+                self.attributes.material = Some(name);
+            }
+            "MakeNamedMaterial" => {
+                let name = tokens.string()?;
+                let parameters = tokens.parameters()?;
+                let ty = parameters.string("type").unwrap_or("diffuse").to_owned();
+                self.scene.materials.push(material(name, &ty, &parameters));
+            }
+            "NamedMaterial" => self.attributes.material = Some(tokens.string()?),
+            "LightSource" => {
+                let ty = tokens.string()?;
+                let parameters = tokens.parameters()?;
+                self.light(&ty, &parameters);
+            }
+            "AreaLightSource" => {
+                let ty = tokens.string()?;
+                let parameters = tokens.parameters()?;
+                if ty != "diffuse" {
+                    tracing::warn!(ty, "Unsupported area light");
+                }
+                let [r, g, b] = parameters.rgb("L").unwrap_or([1.0; 3]);
+                let scale = parameters.float("scale").unwrap_or(1.0);
+                self.attributes.area_light = Some([r * scale, g * scale, b * scale]);
+            }
+            "Shape" => {
+                let ty = tokens.string()?;
+                let parameters = tokens.parameters()?;
+                self.shape(&ty, &parameters)?;
+            }
+            "ObjectBegin" => {
+                let name = tokens.string()?;
+                self.stack.push(self.attributes.clone());
+                self.defining = Some((name, Vec::new()));
+            }
+            "ObjectEnd" => {
+                if let Some((name, objects)) = self.defining.take() {
+                    self.instances.insert(name, objects);
+                }
+                if let Some(attributes) = self.stack.pop() {
+                    self.attributes = attributes;
+                }
+            }
+            "ObjectInstance" => {
+                let name = tokens.string()?;
+                match self.instances.get(&name) {
+                    Some(objects) => {
+                        let objects: Vec<_> = objects
+                            .iter()
+                            .map(|object| {
+                                bake(object.clone(), &(MIRROR * self.attributes.transform))
+                            })
+                            .collect();
+                        self.scene.objects.extend(objects);
+                    }
+                    None => tracing::warn!(name, "Unknown object instance"),
+                }
+            }
+            "Include" | "Import" => {
+                let path = self.resolve(&tokens.string()?);
+                self.parse_file(&path)?;
+            }
+            "Texture" => {
+                let name = tokens.string()?;
+                tokens.string()?;
+                tokens.string()?;
+                tokens.parameters()?;
+                tracing::warn!(name, "Textures aren't supported");
+            }
+            "Attribute" | "Option" | "ColorSpace" | "Accelerator" | "MakeNamedMedium"
+            | "MediumInterface" => {
+                while matches!(tokens.peek(), Some(Token::Str(_))) {
+                    tokens.next();
+                }
+                tokens.parameters()?;
+                tracing::debug!(directive, "Ignoring directive");
+            }
+            _ => return Err(tokens.error(format!("Unknown directive {directive}"))),
+        }
+        Ok(())
+    }
+
+    fn camera(&mut self, ty: &str, parameters: &Parameters) {
+        if ty != "perspective" {
+            tracing::warn!(ty, "Unsupported camera, using a perspective one");
+        }
+
+        let camera_to_world = self
+            .attributes
+            .transform
+            .invert()
+            .unwrap_or_else(Matrix4::identity);
+        self.coordinate_systems
+            .insert("camera".to_owned(), camera_to_world);
+
+        let world = MIRROR * camera_to_world;
+        let position = world.transform_point(Point3::new(0.0, 0.0, 0.0));
+        let forward = world.transform_vector(Vector3::unit_z());
+        let up = world.transform_vector(Vector3::unit_y());
+
+        self.scene.camera = Camera {
+            position: position.into(),
+            look_at: (position + forward).into(),
+            up: up.into(),
+            fov: self.scene.camera.fov,
+            shift: self.scene.camera.shift,
+        };
+        self.fov = parameters.float("fov").unwrap_or(90.0);
+    }
+
+    fn light(&mut self, ty: &str, parameters: &Parameters) {
+        let world = MIRROR * self.attributes.transform;
+        let scale = parameters.float("scale").unwrap_or(1.0);
+
+        let light = match ty {
+            "point" => {
+                let from = parameters.numbers("from");
+                let from = Point3::from(<[f32; 3]>::try_from(from).unwrap_or([0.0; 3]));
+                Light::Point {
+                    position: world.transform_point(from).into(),
+                    color: parameters.rgb("I").unwrap_or([1.0; 3]),
+                    intensity: scale,
+                    profile: None,
+                }
+            }
+            "distant" => {
+                let point = |name, default| {
+                    Point3::from(<[f32; 3]>::try_from(parameters.numbers(name)).unwrap_or(default))
+                };
+                let direction = point("to", [0.0, 0.0, 1.0]) - point("from", [0.0; 3]);
+                Light::Directional {
+                    direction: world.transform_vector(direction).into(),
+                    color: parameters.rgb("L").unwrap_or([1.0; 3]),
+                    intensity: scale,
+                    angular_diameter: 0.0,
+                }
+            }
+            _ => {
+                tracing::warn!(ty, "Unsupported light source");
+                return;
+            }
+        };
+
+        self.scene.lights.push(light);
+    }
+
+    fn shape(&mut self, ty: &str, parameters: &Parameters) -> Result<(), SceneError> {
+        let shape = match ty {
+            "sphere" => Shape::Sphere {
+                center: [0.0; 3],
+                radius: parameters.float("radius").unwrap_or(1.0),
+            },
+            "trianglemesh" => {
+                let positions = parameters
+                    .numbers("P")
+                    .chunks_exact(3)
+                    .map(|p| [p[0], p[1], p[2]])
+                    .collect();
+                let normals = parameters
+                    .numbers("N")
+                    .chunks_exact(3)
+                    .map(|n| [n[0], n[1], n[2]])
+                    .collect();
+                // PBRT v4 calls the texture coordinates `uv`, v3 `st`.
+                let mut texcoords = parameters.numbers("uv");
+                if texcoords.is_empty() {
+                    texcoords = parameters.numbers("st");
+                }
+                let texcoords = texcoords.chunks_exact(2).map(|t| [t[0], t[1]]).collect();
+                let indices = parameters
+                    .numbers("indices")
+                    .into_iter()
+                    .map(|index| index as u32)
+                    .collect();
+                Shape::Triangles {
+                    positions,
+                    normals,
+                    texcoords,
+                    indices,
+                }
+            }
+            "curve" => {
+                let points: Vec<_> = parameters
+                    .numbers("P")
+                    .chunks_exact(3)
+                    .map(|p| [p[0], p[1], p[2]])
+                    .collect();
+                // The width goes from one end to the other, control point by control point.
+                let width = parameters.float("width").unwrap_or(1.0);
+                let start = parameters.float("width0").unwrap_or(width);
+                let end = parameters.float("width1").unwrap_or(width);
+                let last = points.len().saturating_sub(1).max(1) as f32;
+                let widths = (0..points.len())
+                    .map(|i| start + (end - start) * i as f32 / last)
+                    .collect();
+                let basis = match parameters.string("basis") {
+                    Some("bspline") => CurveBasis::BSpline,
+                    _ => CurveBasis::Bezier,
+                };
+                // Ribbons with normals of their own are traced like flat ones facing the ray.
+                Shape::Curves {
+                    points,
+                    counts: Vec::new(),
+                    widths,
+                    basis,
+                    flat: parameters.string("type") != Some("cylinder"),
+                }
+            }
+            "plymesh" => {
+                let Some(filename) = parameters.string("filename") else {
+                    tracing::warn!("plymesh without a filename");
+                    return Ok(());
+                };
+                let (positions, indices) = read_ply(&self.resolve(filename))?;
+                Shape::Triangles {
+                    positions,
+                    normals: Vec::new(),
+                    texcoords: Vec::new(),
+                    indices,
+                }
+            }
+            _ => {
+                tracing::warn!(ty, "Unsupported shape");
+                return Ok(());
+            }
+        };
+
+        let mut material = self.attributes.material.clone();
+        if let Some(emission) = self.attributes.area_light {
+            let base = match &material {
+                Some(name) => self
+                    .scene
+                    .materials
+                    .iter()
+                    .find(|material| &material.name == name)
+                    .cloned()
+                    .unwrap_or_default(),
+                None => Material::default(),
+            };
+            let name = format!("pbrt material {}", self.anonymous_materials);
+            self.anonymous_materials += 1;
+            self.scene.materials.push(Material {
+                name: name.clone(),
+                emission,
+                ..base
+            });
+            material = Some(name);
+        }
+
+        let object = Object {
+            material,
+            ..Object::new(shape)
+        };
+
+        match &mut self.defining {
+            // Instanced objects get the rest of their transform with every instance.
+            Some((_, objects)) => objects.push(bake(object, &self.attributes.transform)),
+            None => {
+                let object = bake(object, &self.attributes.transform);
+                self.scene.objects.push(bake(object, &MIRROR));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reflection turning PBRT's left-handed world into a right-handed one.
+const MIRROR: Matrix4<f32> = Matrix4::new(
+    -1.0, 0.0, 0.0, 0.0, //
+    0.0, 1.0, 0.0, 0.0, //
+    0.0, 0.0, 1.0, 0.0, //
+    0.0, 0.0, 0.0, 1.0,
+);
+
+/// Applies `transform` to the geometry of `object`.
+fn bake(mut object: Object, transform: &Matrix4<f32>) -> Object {
+    match &mut object.shape {
+        Shape::Sphere { center, radius } => {
+            let scale = [0, 1, 2]
+                .map(|axis| transform[axis].truncate().magnitude())
+                .into_iter()
+                .fold(0.0, f32::max);
+            *center = transform.transform_point(Point3::from(*center)).into();
+            *radius *= scale;
+        }
+        Shape::Quad { corner, edges } => {
+            *corner = transform.transform_point(Point3::from(*corner)).into();
+            for edge in edges {
+                *edge = transform.transform_vector(Vector3::from(*edge)).into();
+            }
+        }
+        Shape::Triangles {
+            positions, normals, ..
+        } => {
+            for position in positions {
+                *position = transform.transform_point(Point3::from(*position)).into();
+            }
+            // Normals transform with the inverse transpose to stay perpendicular to the surface.
+            match transform.invert() {
+                Some(inverse) => {
+                    for normal in normals {
+                        let transformed = inverse.transpose() * Vector3::from(*normal).extend(0.0);
+                        *normal = transformed.truncate().into();
+                    }
+                }
+                None => normals.clear(),
+            }
+        }
+        Shape::Curves { points, widths, .. } => {
+            let scale = [0, 1, 2]
+                .map(|axis| transform[axis].truncate().magnitude())
+                .into_iter()
+                .fold(0.0, f32::max);
+            for point in points {
+                *point = transform.transform_point(Point3::from(*point)).into();
+            }
+            for width in widths {
+                *width *= scale;
+            }
+        }
+        Shape::Mesh { .. }
+        | Shape::Cuboid { .. }
+        | Shape::Points { .. }
+        | Shape::Custom { .. }
+        | Shape::Sdf { .. }
+        | Shape::Csg { .. }
+        | Shape::Heightfield { .. } => {}
+    }
+    object
+}
+
+/// Matrix from the 16 column-major values of `Transform` and `ConcatTransform`.
+fn matrix(m: [f32; 16]) -> Matrix4<f32> {
+    Matrix4::new(
+        m[0], m[1], m[2], m[3], m[4], m[5], m[6], m[7], m[8], m[9], m[10], m[11], m[12], m[13],
+        m[14], m[15],
+    )
+}
+
+/// World to camera transform of PBRT's `LookAt`.
+fn look_at(eye: [f32; 3], target: [f32; 3], up: [f32; 3]) -> Matrix4<f32> {
+    let eye = Point3::from(eye);
+    let direction = (Point3::from(target) - eye).normalize();
+    let right = Vector3::from(up).normalize().cross(direction).normalize();
+    let up = direction.cross(right);
+
+    let camera_to_world = Matrix4::from_cols(
+        right.extend(0.0),
+        up.extend(0.0),
+        direction.extend(0.0),
+        Vector4::new(eye.x, eye.y, eye.z, 1.0),
+    );
+    camera_to_world.invert().unwrap_or_else(Matrix4::identity)
+}
+
+fn material(name: String, ty: &str, parameters: &Parameters) -> Material {
+    let color = |names: &[&str], default| {
+        names
+            .iter()
+            .find_map(|name| parameters.rgb(name))
+            .unwrap_or(default)
+    };
+    let roughness = parameters
+        .float("roughness")
+        .or_else(|| parameters.float("uroughness"))
+        .unwrap_or(0.0)
+        .clamp(0.0, 1.0);
+    let ior = parameters
+        .float("eta")
+        .or_else(|| parameters.float("index"))
+        .unwrap_or(1.5);
+
+    let base = Material {
+        name,
+        ..Default::default()
+    };
+
+    match ty {
+        "matte" | "diffuse" => Material {
+            base_color: color(&["Kd", "reflectance"], [0.5; 3]),
+            specular: 0.0,
+            ..base
+        },
+        "plastic" | "coateddiffuse" | "substrate" | "uber" => Material {
+            base_color: color(&["Kd", "reflectance"], [0.5; 3]),
+            roughness,
+            ..base
+        },
+        "metal" | "conductor" => Material {
+            base_color: color(&["reflectance", "Kr"], [0.9; 3]),
+            metallic: 1.0,
+            roughness,
+            ..base
+        },
+        "mirror" => Material {
+            base_color: color(&["Kr", "reflectance"], [0.9; 3]),
+            metallic: 1.0,
+            roughness: 0.0,
+            ..base
+        },
+        "glass" | "dielectric" | "thindielectric" => Material {
+            base_color: color(&["Kt"], [1.0; 3]),
+            transmission: 1.0,
+            roughness,
+            ior,
+            ..base
+        },
+        "hair" => {
+            let azimuthal_roughness = parameters.float("beta_n").unwrap_or(0.3);
+            // Reflectance of the melanin concentrations PBRT defaults to without a color, by
+            // the inverse of the absorption the shaders derive from the color.
+            let melanin = || {
+                let eumelanin = parameters.float("eumelanin").unwrap_or(1.3);
+                let pheomelanin = parameters.float("pheomelanin").unwrap_or(0.0);
+                let b = azimuthal_roughness;
+                let scale = 5.969 - 0.215 * b + 2.532 * b.powi(2) - 10.73 * b.powi(3)
+                    + 5.574 * b.powi(4)
+                    + 0.245 * b.powi(5);
+                let absorption = [0usize, 1, 2].map(|i| {
+                    eumelanin * [0.419, 0.697, 1.37][i] + pheomelanin * [0.187, 0.4, 1.05][i]
+                });
+                absorption.map(|a| (-a.sqrt() * scale).exp())
+            };
+            Material {
+                base_color: parameters
+                    .rgb("color")
+                    .or_else(|| parameters.rgb("reflectance"))
+                    .unwrap_or_else(melanin),
+                roughness: parameters.float("beta_m").unwrap_or(0.3),
+                ior: parameters.float("eta").unwrap_or(1.55),
+                hair: 1.0,
+                hair_azimuthal_roughness: azimuthal_roughness,
+                hair_scale_angle: parameters.float("alpha").unwrap_or(2.0),
+                ..base
+            }
+        }
+        "" | "none" | "interface" => base,
+        _ => {
+            tracing::warn!(ty, "Unsupported material, using a diffuse one");
+            base
+        }
+    }
+}
+
+/// Reads the vertex positions and the triangulated faces of a PLY mesh.
+fn read_ply(path: &Path) -> Result<(Vec<[f32; 3]>, Vec<u32>), SceneError> {
+    let error = |message: &str| syntax_error(path, 0, message);
+    let mut reader = BufReader::new(fs::File::open(path)?);
+
+    // Header
+    let mut encoding = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(error("Unterminated PLY header"));
+        }
+        let words: Vec<_> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["ply"] | ["comment", ..] | ["obj_info", ..] | [] => {}
+            ["format", format, _] => encoding = Some(format.to_string()),
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| error("Invalid element count"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => elements
+                .last_mut()
+                .ok_or_else(|| error("Property outside of an element"))?
+                .properties
+                .push(PlyProperty {
+                    name: name.to_string(),
+                    ty: PlyType::parse(item).ok_or_else(|| error("Invalid property type"))?,
+                    list: Some(PlyType::parse(count).ok_or_else(|| error("Invalid list type"))?),
+                }),
+            ["property", ty, name] => elements
+                .last_mut()
+                .ok_or_else(|| error("Property outside of an element"))?
+                .properties
+                .push(PlyProperty {
+                    name: name.to_string(),
+                    ty: PlyType::parse(ty).ok_or_else(|| error("Invalid property type"))?,
+                    list: None,
+                }),
+            ["end_header"] => break,
+            _ => return Err(error("Invalid PLY header")),
+        }
+    }
+
+    let mut values = match encoding.as_deref() {
+        Some("ascii") => PlyValues::Ascii(String::new(), Vec::new()),
+        Some("binary_little_endian") => PlyValues::Binary { big_endian: false },
+        Some("binary_big_endian") => PlyValues::Binary { big_endian: true },
+        _ => return Err(error("Unsupported PLY format")),
+    };
+
+    let mut positions = Vec::new();
+    let mut indices = Vec::new();
+    for element in &elements {
+        for _ in 0..element.count {
+            let mut position = [0.0; 3];
+            for property in &element.properties {
+                match property.list {
+                    None => {
+                        let value = values.read(&mut reader, property.ty, path)?;
+                        if element.name == "vertex" {
+                            match property.name.as_str() {
+                                "x" => position[0] = value as f32,
+                                "y" => position[1] = value as f32,
+                                "z" => position[2] = value as f32,
+                                _ => {}
+                            }
+                        }
+                    }
+                    Some(count_ty) => {
+                        let count = values.read(&mut reader, count_ty, path)? as usize;
+                        let mut face = Vec::with_capacity(count);
+                        for _ in 0..count {
+                            face.push(values.read(&mut reader, property.ty, path)? as u32);
+                        }
+                        let is_face = element.name == "face"
+                            && matches!(property.name.as_str(), "vertex_indices" | "vertex_index");
+                        if is_face {
+                            // Triangulate polygons as fans.
+                            for i in 1..face.len().saturating_sub(1) {
+                                indices.extend([face[0], face[i], face[i + 1]]);
+                            }
+                        }
+                    }
+                }
+            }
+            if element.name == "vertex" {
+                positions.push(position);
+            }
+        }
+    }
+
+    if indices
+        .iter()
+        .any(|&index| index as usize >= positions.len())
+    {
+        return Err(error("PLY face refers to a missing vertex"));
+    }
+
+    Ok((positions, indices))
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+struct PlyProperty {
+    name: String,
+    ty: PlyType,
+    /// Type of the length of list properties.
+    list: Option<PlyType>,
+}
+
+#[derive(Clone, Copy)]
+enum PlyType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyType {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+}
+
+enum PlyValues {
+    /// Current line and its values not read yet, in reverse.
+    Ascii(String, Vec<f64>),
+    Binary {
+        big_endian: bool,
+    },
+}
+
+impl PlyValues {
+    fn read(
+        &mut self,
+        reader: &mut impl BufRead,
+        ty: PlyType,
+        path: &Path,
+    ) -> Result<f64, SceneError> {
+        match self {
+            Self::Ascii(line, pending) => {
+                while pending.is_empty() {
+                    line.clear();
+                    if reader.read_line(line)? == 0 {
+                        return Err(syntax_error(path, 0, "Truncated PLY data"));
+                    }
+                    for word in line.split_whitespace().rev() {
+                        pending.push(
+                            word.parse()
+                                .map_err(|_| syntax_error(path, 0, "Invalid PLY value"))?,
+                        );
+                    }
+                }
+                Ok(pending.pop().unwrap())
+            }
+            Self::Binary { big_endian } => {
+                // Little-endian from here on, zero padded to the largest type.
+                let mut b = [0; 8];
+                reader.read_exact(&mut b[..ty.size()])?;
+                if *big_endian {
+                    b[..ty.size()].reverse();
+                }
+                Ok(match ty {
+                    PlyType::I8 => b[0] as i8 as f64,
+                    PlyType::U8 => b[0] as f64,
+                    PlyType::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    PlyType::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    PlyType::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    PlyType::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    PlyType::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    PlyType::F64 => f64::from_le_bytes(b),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `files` into a directory of their own and loads the first of them.
+    fn load_files(test: &str, files: &[(&str, &str)]) -> Result<Scene, SceneError> {
+        let directory = std::env::temp_dir().join(format!("pbrt-{test}-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        for (name, contents) in files {
+            fs::write(directory.join(name), contents).unwrap();
+        }
+        let scene = load(directory.join(files[0].0));
+        fs::remove_dir_all(&directory).unwrap();
+        scene
+    }
+
+    fn syntax_message(result: Result<Scene, SceneError>) -> (usize, String) {
+        match result {
+            Err(SceneError::Pbrt { line, message, .. }) => (line, message),
+            other => panic!("expected a syntax error, got {other:?}"),
+        }
+    }
+
+    const QUAD: &str = "ply
+format ascii 1.0
+element vertex 4
+property float x
+property float y
+property float z
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0
+1 0 0
+1 1 0
+0 1 0
+4 0 1 2 3
+";
+
+    #[test]
+    fn loads_triangle_meshes() {
+        let scene = load_files(
+            "triangles",
+            &[(
+                "scene.pbrt",
+                "WorldBegin\n\
+                 Shape \"trianglemesh\" \"point3 P\" [0 0 0 1 0 0 0 1 0] \"integer indices\" [0 1 2]\n",
+            )],
+        )
+        .unwrap();
+        assert_eq!(scene.objects.len(), 1);
+        let Shape::Triangles { indices, .. } = &scene.objects[0].shape else {
+            panic!("expected triangles, got {:?}", scene.objects[0].shape);
+        };
+        assert_eq!(indices, &[0, 1, 2]);
+    }
+
+    #[test]
+    fn rejects_malformed_scenes() {
+        let (line, message) = syntax_message(load_files(
+            "unterminated",
+            &[("scene.pbrt", "WorldBegin\nShape \"sphere\n")],
+        ));
+        assert_eq!(line, 2);
+        assert_eq!(message, "Unterminated string");
+
+        let (line, message) = syntax_message(load_files(
+            "directive",
+            &[("scene.pbrt", "WorldBegin\n\nFrobnicate 1\n")],
+        ));
+        assert_eq!(line, 3);
+        assert_eq!(message, "Unknown directive Frobnicate");
+    }
+
+    #[test]
+    fn triangulates_ply_meshes() {
+        let scene = load_files(
+            "ply",
+            &[
+                (
+                    "scene.pbrt",
+                    "WorldBegin\nShape \"plymesh\" \"string filename\" \"quad.ply\"\n",
+                ),
+                ("quad.ply", QUAD),
+            ],
+        )
+        .unwrap();
+        let Shape::Triangles {
+            positions, indices, ..
+        } = &scene.objects[0].shape
+        else {
+            panic!("expected triangles, got {:?}", scene.objects[0].shape);
+        };
+        assert_eq!(positions.len(), 4);
+        assert_eq!(indices, &[0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn rejects_malformed_ply_meshes() {
+        let load_ply = |test: &str, ply: &str| {
+            syntax_message(load_files(
+                test,
+                &[
+                    (
+                        "scene.pbrt",
+                        "WorldBegin\nShape \"plymesh\" \"string filename\" \"mesh.ply\"\n",
+                    ),
+                    ("mesh.ply", ply),
+                ],
+            ))
+            .1
+        };
+        assert_eq!(
+            load_ply("missing-vertex", &QUAD.replace("4 0 1 2 3", "4 0 1 2 4")),
+            "PLY face refers to a missing vertex"
+        );
+        assert_eq!(
+            load_ply("header", &QUAD.replace("element face 1", "element face")),
+            "Invalid PLY header"
+        );
+        assert_eq!(
+            load_ply("unterminated-header", "ply\nformat ascii 1.0\n"),
+            "Unterminated PLY header"
+        );
+        assert_eq!(
+            load_ply("format", &QUAD.replace("ascii", "utf8")),
+            "Unsupported PLY format"
+        );
+    }
+}
@@ -0,0 +1,382 @@
+//! Images shown on a window or canvas instead of being read back to the host.
+//!
+//! Images smaller than the surface are scaled up to it with a spatial upscaler after AMD
+//! FidelityFX Super Resolution 1.0, so that interactive views can path trace fewer pixels and
+//! stay responsive on slower GPUs.
+
+use std::num::NonZeroU32;
+
+use wgpu::{
+    include_wgsl,
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages,
+    Color, ColorTargetState, ColorWrites, CommandEncoder, CommandEncoderDescriptor,
+    CompositeAlphaMode, Device, Extent3d, FragmentState, ImageCopyBuffer, ImageDataLayout, LoadOp,
+    MultisampleState, Operations, PipelineLayout, PipelineLayoutDescriptor, PresentMode,
+    PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, ShaderModule, ShaderStages, Surface, SurfaceConfiguration,
+    SurfaceError, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType,
+    TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension, VertexState,
+};
+use zerocopy::AsBytes;
+
+use crate::renderer::{buffer_bytes_per_row, RaytracingRenderer};
+
+/// Format of the upsampled image the upscaler sharpens onto the surface.
+const UPSAMPLED_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+
+/// Mirrors `UpscaleUniforms` in the shader.
+#[derive(AsBytes)]
+#[repr(C)]
+struct UpscaleUniforms {
+    output_wh: [f32; 2],
+    sharpness: f32,
+    _padding: f32,
+}
+
+/// Draws images rendered with [`RaytracingRenderer::render_into_buffer`] onto a [`Surface`].
+pub struct Presenter {
+    pipeline: RenderPipeline,
+    bind_group_layout: BindGroupLayout,
+    easu_pipeline: RenderPipeline,
+    rcas_pipeline: RenderPipeline,
+    upscale_bind_group_layout: BindGroupLayout,
+    format: TextureFormat,
+    surface_wh: [u32; 2],
+    sharpness: f32,
+}
+
+impl Presenter {
+    /// Creates a presenter for surfaces configured with `format`.
+    pub fn new(renderer: &RaytracingRenderer, format: TextureFormat) -> Self {
+        let device = renderer.device();
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Present bind group layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: false },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Present pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader = device.create_shader_module(include_wgsl!("shaders/present.wgsl"));
+        let pipeline = fullscreen_pipeline(
+            device,
+            "Present pipeline",
+            &pipeline_layout,
+            &shader,
+            "fs_main",
+            format,
+        );
+
+        let upscale_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("Upscale bind group layout"),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Float { filterable: false },
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let upscale_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Upscale pipeline layout"),
+            bind_group_layouts: &[&upscale_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let upscale_shader = device.create_shader_module(include_wgsl!("shaders/upscale.wgsl"));
+        let easu_pipeline = fullscreen_pipeline(
+            device,
+            "EASU pipeline",
+            &upscale_pipeline_layout,
+            &upscale_shader,
+            "fs_easu",
+            UPSAMPLED_FORMAT,
+        );
+        let rcas_pipeline = fullscreen_pipeline(
+            device,
+            "RCAS pipeline",
+            &upscale_pipeline_layout,
+            &upscale_shader,
+            "fs_rcas",
+            format,
+        );
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            easu_pipeline,
+            rcas_pipeline,
+            upscale_bind_group_layout,
+            format,
+            surface_wh: [0, 0],
+            sharpness: 0.2,
+        }
+    }
+
+    /// Sharpening of upscaled images, in stops below the strongest. 0.2 by default.
+    pub fn sharpness(&self) -> f32 {
+        self.sharpness
+    }
+
+    pub fn set_sharpness(&mut self, stops: f32) {
+        self.sharpness = stops.max(0.0);
+    }
+
+    /// Format to configure `surface` with on the adapter of `renderer`, if it supports any.
+    ///
+    /// Renders are already encoded for display, so formats without sRGB conversion come first.
+    pub fn preferred_format(
+        renderer: &RaytracingRenderer,
+        surface: &Surface,
+    ) -> Option<TextureFormat> {
+        let formats = surface.get_supported_formats(renderer.adapter());
+        formats
+            .iter()
+            .find(|format| !format.describe().srgb)
+            .or_else(|| formats.first())
+            .copied()
+    }
+
+    /// Configures `surface` to be `width` by `height` pixels large.
+    pub fn configure(
+        &mut self,
+        renderer: &RaytracingRenderer,
+        surface: &Surface,
+        width: u32,
+        height: u32,
+    ) {
+        surface.configure(
+            renderer.device(),
+            &SurfaceConfiguration {
+                usage: TextureUsages::RENDER_ATTACHMENT,
+                format: self.format,
+                width,
+                height,
+                present_mode: PresentMode::Fifo,
+                alpha_mode: CompositeAlphaMode::Auto,
+            },
+        );
+        self.surface_wh = [width, height];
+    }
+
+    /// Draws the `width` by `height` image `buffer` holds onto `surface` and presents it.
+    ///
+    /// `buffer` is laid out like [`RaytracingRenderer::render_into_buffer`] writes it and needs
+    /// `COPY_SRC` usage on top. Images of another size than the one the surface was last
+    /// [configured](Self::configure) for are upsampled to it along their edges and sharpened.
+    pub fn present(
+        &self,
+        renderer: &RaytracingRenderer,
+        surface: &Surface,
+        buffer: &Buffer,
+        width: u32,
+        height: u32,
+    ) -> Result<(), SurfaceError> {
+        let device = renderer.device();
+        let frame = surface.get_current_texture()?;
+
+        let size = Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let image = device.create_texture(&TextureDescriptor {
+            label: Some("Present texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8Unorm,
+            usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
+        });
+        let image_view = image.create_view(&TextureViewDescriptor::default());
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Present command encoder"),
+        });
+
+        encoder.copy_buffer_to_texture(
+            ImageCopyBuffer {
+                buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(buffer_bytes_per_row(width)),
+                    rows_per_image: NonZeroU32::new(height),
+                },
+            },
+            image.as_image_copy(),
+            size,
+        );
+
+        let frame_view = frame.texture.create_view(&TextureViewDescriptor::default());
+        if [width, height] == self.surface_wh {
+            let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                label: Some("Present bind group"),
+                layout: &self.bind_group_layout,
+                entries: &[BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&image_view),
+                }],
+            });
+            draw(&mut encoder, &frame_view, &self.pipeline, &bind_group);
+        } else {
+            self.upscale(device, &mut encoder, &image_view, &frame_view);
+        }
+
+        renderer.queue().submit(Some(encoder.finish()));
+        frame.present();
+
+        Ok(())
+    }
+
+    /// Records the upsampling of `image` to the size of the surface and its sharpening onto
+    /// `target`.
+    fn upscale(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        image: &TextureView,
+        target: &TextureView,
+    ) {
+        let [width, height] = self.surface_wh;
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Upscale uniform buffer"),
+            contents: UpscaleUniforms {
+                output_wh: [width as f32, height as f32],
+                sharpness: self.sharpness,
+                _padding: 0.0,
+            }
+            .as_bytes(),
+            usage: BufferUsages::UNIFORM,
+        });
+        let upsampled = device.create_texture(&TextureDescriptor {
+            label: Some("Upsampled texture"),
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: UPSAMPLED_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        });
+        let upsampled_view = upsampled.create_view(&TextureViewDescriptor::default());
+
+        let bind_group = |label, view| {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: Some(label),
+                layout: &self.upscale_bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(view),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        let easu_bind_group = bind_group("EASU bind group", image);
+        let rcas_bind_group = bind_group("RCAS bind group", &upsampled_view);
+
+        draw(
+            encoder,
+            &upsampled_view,
+            &self.easu_pipeline,
+            &easu_bind_group,
+        );
+        draw(encoder, target, &self.rcas_pipeline, &rcas_bind_group);
+    }
+}
+
+/// Pipeline drawing a triangle covering the whole of a `format` target with `fragment_entry`.
+fn fullscreen_pipeline(
+    device: &Device,
+    label: &str,
+    layout: &PipelineLayout,
+    shader: &ShaderModule,
+    fragment_entry: &str,
+    format: TextureFormat,
+) -> RenderPipeline {
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        primitive: PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: MultisampleState::default(),
+        fragment: Some(FragmentState {
+            module: shader,
+            entry_point: fragment_entry,
+            targets: &[Some(ColorTargetState {
+                format,
+                blend: None,
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        multiview: None,
+    })
+}
+
+/// Records a pass drawing `pipeline` over all of `target`.
+fn draw(
+    encoder: &mut CommandEncoder,
+    target: &TextureView,
+    pipeline: &RenderPipeline,
+    bind_group: &BindGroup,
+) {
+    let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+        label: Some("Present render pass"),
+        color_attachments: &[Some(RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: Operations {
+                load: LoadOp::Clear(Color::BLACK),
+                store: true,
+            },
+        })],
+        depth_stencil_attachment: None,
+    });
+
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, bind_group, &[]);
+    pass.draw(0..3, 0..1);
+}
@@ -0,0 +1,156 @@
+//! Irradiance probes baked from the scene a [`RaytracingRenderer`] traces, e.g. to light the
+//! same scene in real time with the global illumination of the path tracer.
+//!
+//! Every probe is rendered as an octahedral map of the radiance arriving at its position from
+//! all directions, and also projected onto second order spherical harmonics convolved with the
+//! cosine lobe, the usual representation of the irradiance of surfaces around it ("An Efficient
+//! Representation for Irradiance Environment Maps", Ramamoorthi and Hanrahan 2001).
+
+use serde::{Deserialize, Serialize};
+
+use crate::{renderer::RaytracingRenderer, settings::RenderSettings};
+
+/// Convolution of the bands of the spherical harmonics with the clamped cosine.
+const BAND_SCALES: [f32; 3] = [
+    std::f32::consts::PI,
+    std::f32::consts::PI * 2.0 / 3.0,
+    std::f32::consts::PI / 4.0,
+];
+
+/// Light at a point of the scene.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IrradianceProbe {
+    pub position: [f32; 3],
+    /// Side of `radiance` in texels.
+    pub resolution: u32,
+    /// Linear RGB radiance arriving from the directions of an octahedral map, rows from the top
+    /// down, see [`octahedral_direction`].
+    pub radiance: Vec<[f32; 3]>,
+    /// Linear RGB irradiance of surfaces facing any direction as the coefficients of the real
+    /// spherical harmonics `Y00, Y1-1, Y10, Y11, Y2-2, Y2-1, Y20, Y21, Y22`, see
+    /// [`irradiance`](Self::irradiance).
+    pub irradiance_sh: [[f32; 3]; 9],
+}
+
+impl IrradianceProbe {
+    /// Probe at `position` from an octahedral map of RGBA32F `pixels`, `resolution` wide and
+    /// high.
+    pub(crate) fn from_octahedral_map(position: [f32; 3], resolution: u32, pixels: &[f32]) -> Self {
+        let radiance: Vec<[f32; 3]> = pixels
+            .chunks_exact(4)
+            .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .collect();
+
+        // Texels cover solid angles falling off with the cube of the distance of their point
+        // on the octahedron to the center.
+        let mut sh = [[0.0; 3]; 9];
+        let mut total_weight = 0.0;
+        for (index, texel) in radiance.iter().enumerate() {
+            let (x, y) = (index as u32 % resolution, index as u32 / resolution);
+            let u = (x as f32 + 0.5) / resolution as f32;
+            let v = 1.0 - (y as f32 + 0.5) / resolution as f32;
+            let point = octahedron_point(2.0 * u - 1.0, 2.0 * v - 1.0);
+            let length = point.iter().map(|c| c * c).sum::<f32>().sqrt();
+            let weight = length.powi(-3);
+            total_weight += weight;
+
+            let basis = sh_basis(point.map(|c| c / length));
+            for (coefficient, y) in sh.iter_mut().zip(basis) {
+                for channel in 0..3 {
+                    coefficient[channel] += texel[channel] * y * weight;
+                }
+            }
+        }
+
+        let scale = 4.0 * std::f32::consts::PI / total_weight.max(f32::MIN_POSITIVE);
+        for (index, coefficient) in sh.iter_mut().enumerate() {
+            let band = match index {
+                0 => 0,
+                1..=3 => 1,
+                _ => 2,
+            };
+            *coefficient = coefficient.map(|c| c * scale * BAND_SCALES[band]);
+        }
+
+        Self {
+            position,
+            resolution,
+            radiance,
+            irradiance_sh: sh,
+        }
+    }
+
+    /// Linear RGB irradiance of a surface at the probe facing `normal`, which has to be unit
+    /// length.
+    pub fn irradiance(&self, normal: [f32; 3]) -> [f32; 3] {
+        let basis = sh_basis(normal);
+        let mut irradiance = [0.0; 3];
+        for (coefficient, y) in self.irradiance_sh.iter().zip(basis) {
+            for channel in 0..3 {
+                irradiance[channel] += coefficient[channel] * y;
+            }
+        }
+        irradiance.map(|c| c.max(0.0))
+    }
+}
+
+/// Unit vector at `(u, v)` of an octahedral map, both from -1 to 1 with `v` going up, the
+/// upper hemisphere in the middle. Texels of [`IrradianceProbe::radiance`] cover the square
+/// around their center.
+pub fn octahedral_direction(u: f32, v: f32) -> [f32; 3] {
+    let point = octahedron_point(u, v);
+    let length = point.iter().map(|c| c * c).sum::<f32>().sqrt();
+    point.map(|c| c / length)
+}
+
+/// Point of the unit octahedron `(u, v)` of an octahedral map is folded onto.
+fn octahedron_point(u: f32, v: f32) -> [f32; 3] {
+    let z = 1.0 - u.abs() - v.abs();
+    let t = (-z).max(0.0);
+    [u - t.copysign(u), v - t.copysign(v), z]
+}
+
+/// Real spherical harmonics of the first three bands at `direction`.
+fn sh_basis([x, y, z]: [f32; 3]) -> [f32; 9] {
+    [
+        0.282095,
+        0.488603 * y,
+        0.488603 * z,
+        0.488603 * x,
+        1.092548 * x * y,
+        1.092548 * y * z,
+        0.315392 * (3.0 * z * z - 1.0),
+        1.092548 * x * z,
+        0.546274 * (x * x - y * y),
+    ]
+}
+
+/// Bakes a probe at every one of `positions` from the scene of `renderer`, rendering octahedral
+/// maps `resolution` texels wide with the samples, bounces and integrator of `settings`. The
+/// size of the image and the exposure in `settings` are ignored.
+pub async fn bake(
+    renderer: &RaytracingRenderer,
+    settings: &RenderSettings,
+    positions: &[[f32; 3]],
+    resolution: u32,
+) -> Vec<IrradianceProbe> {
+    let settings = RenderSettings {
+        width: resolution,
+        height: resolution,
+        // Baked light stays in the units of the scene.
+        exposure: None,
+        ..settings.clone()
+    };
+
+    let mut probes = Vec::with_capacity(positions.len());
+    for &position in positions {
+        tracing::info!(?position, "Baking probe");
+        renderer.set_probe_camera(Some(position));
+        let pixels = renderer.render_hdr_with_progress(&settings, |_| {}).await;
+        probes.push(IrradianceProbe::from_octahedral_map(
+            position, resolution, &pixels,
+        ));
+    }
+    renderer.set_probe_camera(None);
+    probes
+}
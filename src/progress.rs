@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use crate::settings::RenderSettings;
+
+/// Snapshot of how far a render has come.
+///
+/// Reported through the callback of [`RaytracingRenderer::render_with_progress`] after every
+/// batch of samples. To consume it as a `Stream` instead, forward it into a channel:
+///
+/// ```ignore
+/// let (sender, receiver) = async_std::channel::unbounded();
+/// let render = renderer.render_with_progress(&settings, move |p| {
+///     let _ = sender.try_send(p);
+/// });
+/// ```
+///
+/// [`RaytracingRenderer::render_with_progress`]: crate::renderer::RaytracingRenderer::render_with_progress
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+    /// Pixel samples accumulated so far, over the whole image.
+    pub samples_done: u64,
+    /// Pixel samples the render will accumulate in total.
+    pub samples_total: u64,
+    /// Tiles whose samples have all been accumulated.
+    pub tiles_done: u32,
+    /// Tiles the image is split into.
+    pub tiles_total: u32,
+    /// Time spent rendering so far.
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Progress of a render that hasn't started yet.
+    pub(crate) fn new(settings: &RenderSettings, tiles_total: u32) -> Self {
+        Self {
+            samples_done: 0,
+            samples_total: settings.width as u64
+                * settings.height as u64
+                * settings.samples_per_pixel as u64,
+            tiles_done: 0,
+            tiles_total,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Completed fraction of the render, between `0.0` and `1.0`.
+    pub fn fraction(&self) -> f64 {
+        if self.samples_total == 0 {
+            1.0
+        } else {
+            self.samples_done as f64 / self.samples_total as f64
+        }
+    }
+
+    /// Estimated time left, extrapolated from the throughput so far.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.samples_done == 0 {
+            return None;
+        }
+
+        let remaining = self.samples_total.saturating_sub(self.samples_done);
+        Some(
+            self.elapsed
+                .mul_f64(remaining as f64 / self.samples_done as f64),
+        )
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.samples_done >= self.samples_total
+    }
+}
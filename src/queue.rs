@@ -0,0 +1,108 @@
+//! Renders of many images submitted back to back, e.g. thumbnails of a set of scenes or the
+//! frames of an animation, for more throughput than rendering them one at a time.
+
+use crate::{
+    renderer::RaytracingRenderer,
+    scene::{Scene, SceneError},
+    settings::RenderSettings,
+};
+
+/// An image for a [`RenderQueue`] to render.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderJob {
+    /// Replaces the scene of the renderer for this job and the following ones, or keeps the
+    /// current one if `None`.
+    pub scene: Option<Scene>,
+    pub settings: RenderSettings,
+}
+
+/// Render jobs, run in the order they were pushed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderQueue {
+    jobs: Vec<RenderJob>,
+}
+
+impl RenderQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a render of the current scene with `settings`, returning the index of the job.
+    pub fn push(&mut self, settings: RenderSettings) -> usize {
+        self.push_job(RenderJob {
+            scene: None,
+            settings,
+        })
+    }
+
+    /// Adds a render of `scene` with `settings`, returning the index of the job. The jobs
+    /// pushed after it render `scene` too, unless they have scenes of their own.
+    pub fn push_scene(&mut self, scene: Scene, settings: RenderSettings) -> usize {
+        self.push_job(RenderJob {
+            scene: Some(scene),
+            settings,
+        })
+    }
+
+    pub fn push_job(&mut self, job: RenderJob) -> usize {
+        self.jobs.push(job);
+        self.jobs.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Runs the jobs on `renderer`, calling `on_image` with the index and the tightly packed
+    /// RGBA8 pixels of every job, in order, as soon as they have been read back.
+    ///
+    /// Unlike separate renders, the samples of a tile are submitted without waiting for the
+    /// GPU in between, consecutive jobs of the same scene with the same tile size and integrator
+    /// share their tile targets, and the readback of every tile, the last one of a job included, overlaps with
+    /// rendering the next one. Fails if the scene of a job can't be set, once the images of the
+    /// jobs before it have been handed to `on_image`. The last scene stays set.
+    pub async fn run(
+        self,
+        renderer: &RaytracingRenderer,
+        on_image: impl FnMut(usize, Vec<u8>),
+    ) -> Result<(), SceneError> {
+        renderer.render_jobs(self.jobs, on_image).await
+    }
+}
+
+#[cfg(all(test, feature = "native"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jobs_render_their_own_scene() {
+        async_std::task::block_on(async {
+            // Without an adapter there's nothing to render with.
+            let Some(renderer) = RaytracingRenderer::try_new().await else {
+                return;
+            };
+            let settings = RenderSettings {
+                width: 32,
+                height: 32,
+                samples_per_pixel: 4,
+                ..Default::default()
+            };
+
+            let mut queue = RenderQueue::new();
+            queue.push_scene(Scene::demo(), settings.clone());
+            queue.push_scene(Scene::cornell_box(), settings);
+            let mut images = Vec::new();
+            queue
+                .run(&renderer, |_, image| images.push(image))
+                .await
+                .unwrap();
+
+            assert_eq!(images.len(), 2);
+            assert_ne!(images[0], images[1]);
+        });
+    }
+}
@@ -0,0 +1,275 @@
+//! Primary visibility of hybrid renders, rasterized instead of traced, see
+//! [`RaytracingRenderer::set_hybrid_primary`](crate::renderer::RaytracingRenderer::set_hybrid_primary).
+//!
+//! The triangles of the scene are drawn into a tile sized target holding the index of the
+//! primitive closest to the camera at the center of every pixel, which is copied into a buffer
+//! for the compute pipeline to intersect the camera rays with first.
+
+use std::num::NonZeroU32;
+
+use wgpu::{
+    include_wgsl, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferUsages,
+    Color, ColorTargetState, ColorWrites, CommandBuffer, CommandEncoderDescriptor, CompareFunction,
+    DepthStencilState, Device, Extent3d, FragmentState, ImageCopyBuffer, ImageDataLayout, LoadOp,
+    MultisampleState, Operations, PipelineLayoutDescriptor, PrimitiveState, Queue,
+    RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, ShaderStages, Texture, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor,
+    VertexState,
+};
+use zerocopy::AsBytes;
+
+use crate::{gpu_scene::GpuScene, renderer::padded_bytes_per_row, tile::TileRect};
+
+/// Bytes before the rows of the visibility buffer, where copies of textures into buffers can
+/// start. They begin with the entries in a row and whether the scene has primitives besides
+/// triangles, like `visibility` in the shaders.
+const VISIBILITY_HEADER_SIZE: u64 = 256;
+
+const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+/// Mirrors `RasterUniforms` in the shader.
+#[derive(AsBytes)]
+#[repr(C)]
+struct RasterUniforms {
+    image_wh: [f32; 2],
+    tile_origin: [f32; 2],
+    target_wh: [f32; 2],
+    camera_shift: [f32; 2],
+}
+
+/// Bytes of GPU memory the [`VisibilityTargets`] of tiles `tile_size` pixels a side take.
+pub(crate) fn visibility_size(tile_size: u32) -> u64 {
+    let pixels = tile_size as u64 * tile_size as u64;
+    // The primitives and depths of the pixels and the copy of the primitives.
+    pixels * 8
+        + VISIBILITY_HEADER_SIZE
+        + padded_bytes_per_row(tile_size * 4) as u64 * tile_size as u64
+}
+
+/// Draws the triangles of scenes into [`VisibilityTargets`].
+pub(crate) struct Rasterizer {
+    pipeline: RenderPipeline,
+    bind_group_layout: BindGroupLayout,
+}
+
+/// What a tile rasterizes into, kept with the targets of the tiles.
+pub(crate) struct VisibilityTargets {
+    primitives: Texture,
+    primitives_view: TextureView,
+    depth_view: TextureView,
+    uniform_buffer: Buffer,
+    /// Header and rows of the primitives seen at the pixels, bound for the compute pipeline.
+    pub buffer: Buffer,
+    tile_size: u32,
+}
+
+impl Rasterizer {
+    pub fn new(device: &Device) -> Self {
+        let entry = |binding, ty| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::VERTEX,
+            ty: BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Raster bind group layout"),
+            entries: &[
+                entry(0, BufferBindingType::Uniform),
+                entry(1, BufferBindingType::Storage { read_only: true }),
+                entry(2, BufferBindingType::Uniform),
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Raster pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(include_wgsl!("shaders/raster.wgsl"));
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Raster pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            // Rays hit triangles from both sides.
+            primitive: PrimitiveState::default(),
+            // Reverse Z, the closest surfaces have the greatest depths.
+            depth_stencil: Some(DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::Greater,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format: TextureFormat::R32Uint,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+        }
+    }
+
+    /// Targets for tiles up to `tile_size` pixels a side.
+    pub fn create_targets(&self, device: &Device, tile_size: u32) -> VisibilityTargets {
+        let size = Extent3d {
+            width: tile_size,
+            height: tile_size,
+            depth_or_array_layers: 1,
+        };
+        let texture = |label, format, usage| {
+            device.create_texture(&TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format,
+                usage,
+            })
+        };
+        let primitives = texture(
+            "Visible primitive texture",
+            TextureFormat::R32Uint,
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        );
+        let depth = texture(
+            "Visibility depth texture",
+            DEPTH_FORMAT,
+            TextureUsages::RENDER_ATTACHMENT,
+        );
+
+        VisibilityTargets {
+            primitives_view: primitives.create_view(&TextureViewDescriptor::default()),
+            primitives,
+            depth_view: depth.create_view(&TextureViewDescriptor::default()),
+            uniform_buffer: device.create_buffer(&BufferDescriptor {
+                label: Some("Raster uniform buffer"),
+                size: std::mem::size_of::<RasterUniforms>() as u64,
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            buffer: device.create_buffer(&BufferDescriptor {
+                label: Some("Visibility buffer"),
+                size: VISIBILITY_HEADER_SIZE
+                    + padded_bytes_per_row(tile_size * 4) as u64 * tile_size as u64,
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            tile_size,
+        }
+    }
+
+    /// Commands drawing `scene` into `targets` for the tile `rect` of an image `image_wh` large
+    /// and copying the primitives seen into their buffer. Their uniforms are written right
+    /// away, so the commands have to be submitted before the next tile is rasterized.
+    pub fn rasterize(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        targets: &VisibilityTargets,
+        scene: &GpuScene,
+        rect: TileRect,
+        image_wh: [u32; 2],
+    ) -> CommandBuffer {
+        let bytes_per_row = padded_bytes_per_row(targets.tile_size * 4);
+        let header = [bytes_per_row / 4, !scene.data.triangles_only() as u32];
+        queue.write_buffer(&targets.buffer, 0, header.as_bytes());
+        let uniforms = RasterUniforms {
+            image_wh: image_wh.map(|side| side as f32),
+            tile_origin: [rect.x as f32, rect.y as f32],
+            target_wh: [targets.tile_size as f32; 2],
+            camera_shift: scene.data.uniforms.camera_shift(),
+        };
+        queue.write_buffer(&targets.uniform_buffer, 0, uniforms.as_bytes());
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Raster bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: scene.uniform_buffer().as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: scene.primitive_buffer().as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: targets.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Raster command encoder"),
+        });
+        {
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Raster pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &targets.primitives_view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color {
+                            r: u32::MAX as f64,
+                            g: 0.0,
+                            b: 0.0,
+                            a: 0.0,
+                        }),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                    view: &targets.depth_view,
+                    depth_ops: Some(Operations {
+                        load: LoadOp::Clear(0.0),
+                        store: false,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..scene.data.primitive_count() * 3, 0..1);
+        }
+
+        encoder.copy_texture_to_buffer(
+            targets.primitives.as_image_copy(),
+            ImageCopyBuffer {
+                buffer: &targets.buffer,
+                layout: ImageDataLayout {
+                    offset: VISIBILITY_HEADER_SIZE,
+                    bytes_per_row: NonZeroU32::new(bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            Extent3d {
+                width: rect.width,
+                height: rect.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        encoder.finish()
+    }
+}
@@ -0,0 +1,71 @@
+//! Rays traced against the scene of a
+//! [`RaytracingRenderer`](crate::renderer::RaytracingRenderer) on their own, for integrators
+//! built outside of the crate on top of its BVH and traversal.
+//!
+//! [`trace_rays`](crate::renderer::RaytracingRenderer::trace_rays) takes [`TraceRay`]s and
+//! answers with the [`RayHit`] of every one of them,
+//! [`encode_trace_rays`](crate::renderer::RaytracingRenderer::encode_trace_rays) does the same
+//! with buffers that stay on the GPU, e.g. written and read by the application's own kernels. Both structs are
+//! laid out the way the buffers hold them, tightly packed one after the other.
+//!
+//! Rays see every object of the scene, whatever its
+//! [ray visibility](crate::scene::RayVisibility). Only the closest hit is found, none of the
+//! camera, film and shading of renders are involved.
+
+use zerocopy::{AsBytes, FromBytes};
+
+/// Ray to find the closest hit of, ignoring the ones closer to its origin than `t_min` or
+/// farther from it than `t_max`. Mirrors `TraceRay` in the shaders.
+#[derive(AsBytes, FromBytes, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct TraceRay {
+    pub origin: [f32; 3],
+    pub t_min: f32,
+    /// Of unit length, so that the distances along the ray are in scene units.
+    pub direction: [f32; 3],
+    pub t_max: f32,
+}
+
+impl TraceRay {
+    /// Ray from `origin` along `direction`, normalized, seeing everything in front of it.
+    pub fn new(origin: [f32; 3], direction: [f32; 3]) -> Self {
+        let length = direction.iter().map(|c| c * c).sum::<f32>().sqrt();
+        Self {
+            origin,
+            t_min: 0.0,
+            direction: direction.map(|c| c / length),
+            t_max: f32::MAX,
+        }
+    }
+}
+
+/// Closest hit of a [`TraceRay`]. Mirrors `RayHit` in the shaders.
+#[derive(AsBytes, FromBytes, Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct RayHit {
+    /// Point of the surface hit, in world space.
+    pub position: [f32; 3],
+    /// How far along the ray the hit is.
+    pub distance: f32,
+    /// Normal of the surface at the hit, facing the ray.
+    pub normal: [f32; 3],
+    /// One if the ray hit the outside of the surface, zero if it hit the inside.
+    pub front_face: u32,
+    /// Texture coordinates of the hit, the parametric ones on primitives without any.
+    pub uv: [f32; 2],
+    /// Index of the object hit among the [instances](crate::scene::Scene::instances) of the
+    /// scene, [`RayHit::MISS`] if the ray hit nothing, and the other fields are left as they
+    /// were.
+    pub object: u32,
+    /// Index of the material of the surface hit.
+    pub material: u32,
+}
+
+impl RayHit {
+    /// [`object`](Self::object) of the rays that hit nothing.
+    pub const MISS: u32 = u32::MAX;
+
+    pub fn is_hit(&self) -> bool {
+        self.object != Self::MISS
+    }
+}
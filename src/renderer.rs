@@ -1,15 +1,69 @@
-use std::num::{NonZeroU32, NonZeroU64};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, VecDeque},
+    num::{NonZeroU32, NonZeroU64},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
+#[cfg(feature = "fs")]
+use std::{fs, io, path::Path};
 
+use futures::{stream, FutureExt, Stream};
+use futures_intrusive::channel::shared::OneshotReceiver;
+// `std::time::Instant` panics in browsers.
+use instant::Instant;
+use tracing::{info_span, instrument, Instrument};
 use wgpu::{
-    include_wgsl,
     util::{BufferInitDescriptor, DeviceExt},
-    Adapter, Backends, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, BindingType, BufferBindingType, BufferDescriptor, BufferUsages,
-    CommandEncoderDescriptor, ComputePassDescriptor, ComputePipelineDescriptor,
-    Device, DeviceDescriptor, Instance, Maintain, PipelineLayoutDescriptor,
-    Queue, RequestAdapterOptions, ShaderStages, BindingResource, ImageCopyBuffer, ImageDataLayout,
+    Adapter, AdapterInfo, Backends, BindGroup, BindGroupDescriptor, BindGroupEntry,
+    BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType,
+    Buffer, BufferAsyncError, BufferBinding, BufferBindingType, BufferDescriptor, BufferSlice,
+    BufferUsages, CommandBuffer, CommandEncoder, CommandEncoderDescriptor, ComputePass,
+    ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device, DeviceDescriptor,
+    DownlevelFlags, ErrorFilter, Features, ImageCopyBuffer, ImageDataLayout, Instance, Limits,
+    Maintain, PipelineLayoutDescriptor, PushConstantRange, QuerySet, QuerySetDescriptor, QueryType,
+    Queue, RequestAdapterOptions, RequestDeviceError, ShaderModuleDescriptor, ShaderSource,
+    ShaderStages, Texture, QUERY_RESOLVE_BUFFER_ALIGNMENT,
+};
+use zerocopy::{AsBytes, FromBytes};
+
+use crate::{
+    animation::{self, CameraPath},
+    gpu_scene::{GpuScene, SceneData},
+    graph::{GraphError, GraphPass, PassDeclaration, RenderGraph},
+    materials::MaterialLibrary,
+    progress::Progress,
+    queue::RenderJob,
+    raster::{self, Rasterizer, VisibilityTargets},
+    rays::{RayHit, TraceRay},
+    scene::{Scene, SceneError},
+    settings::{
+        DebugView, DepthBuffer, Eye, Integrator, PixelFilter, RenderSettings, SettingsError, Stereo,
+    },
+    shader::{self, ShaderError, ShaderHook, ShaderSources},
+    supersample,
+    tile::{self, Tile, TileRect},
 };
-use zerocopy::AsBytes;
+#[cfg(feature = "fs")]
+use crate::{binary, checkpoint::Checkpoint};
+
+/// Largest sample count half precision floats represent exactly, more samples per pixel are
+/// always accumulated in full precision.
+const HALF_ACCUMULATION_MAX_SAMPLES: u32 = 2048;
+
+/// Samples taken for every pixel of a tile by a single dispatch.
+const SAMPLES_PER_DISPATCH: u32 = 8;
+
+/// Side of the square workgroups of the compute shaders unless
+/// [changed](RaytracingRenderer::set_workgroup_size).
+const DEFAULT_WORKGROUP_SIZE: u32 = 8;
+
+/// Smallest tiles renders are split into to fit the [memory
+/// budget](RaytracingRenderer::set_memory_budget).
+const MIN_TILE_SIZE: u32 = 16;
 
 #[derive(AsBytes)]
 #[repr(C)]
@@ -18,194 +72,3799 @@ struct RayRaw {
     direction: [f32; 3],
 }
 
+const FRAME_UNIFORMS_SIZE: u32 = std::mem::size_of::<FrameUniforms>() as u32;
+
+/// Mirrors `FrameUniforms` in the shaders.
+#[derive(AsBytes)]
+#[repr(C)]
+struct FrameUniforms {
+    image_wh: [u32; 2],
+    tile_origin: [u32; 2],
+    tile_wh: [u32; 2],
+    sample_index: u32,
+    seed: u32,
+    max_bounces: u32,
+    half_accumulation: u32,
+    ao_rays: u32,
+    ao_distance: f32,
+    debug_view: u32,
+    debug_scale: f32,
+    photon_count: u32,
+    photon_radius: f32,
+    restir_candidates: u32,
+    restir_frame: u32,
+    pixel_filter: u32,
+    filter_radius: f32,
+    transparent_background: u32,
+    exposure: f32,
+    ray_epsilon: f32,
+    ray_offset: f32,
+    cache_samples: u32,
+    _padding: u32,
+}
+
+impl FrameUniforms {
+    fn new(
+        settings: &RenderSettings,
+        targets: &TileTargets,
+        rect: TileRect,
+        sample_index: u32,
+    ) -> Self {
+        let (ao_rays, ao_distance) = match settings.integrator {
+            Integrator::AmbientOcclusion { rays, max_distance } => (rays, max_distance),
+            _ => (0, 0.0),
+        };
+        let (debug_view, debug_scale) = match settings.integrator {
+            Integrator::Debug(view) => match view {
+                DebugView::Normals => (0, 0.0),
+                DebugView::Uv => (1, 0.0),
+                DebugView::Depth { max_distance } => (2, max_distance),
+                DebugView::BvhBounds => (3, 0.0),
+                DebugView::TraversalHeatmap { max_tests } => (4, max_tests as f32),
+            },
+            _ => (0, 0.0),
+        };
+        // Shrinks by the schedule of "Progressive Photon Mapping: A Probabilistic Approach"
+        // (Knaus and Zwicker 2011) with alpha 2/3, balancing the noise against the blur.
+        let (photon_count, photon_radius) = match settings.integrator {
+            Integrator::PhotonMapping { radius, .. } => (
+                targets.photon_capacity,
+                radius * (sample_index as f32 + 1.0).powf(-1.0 / 6.0),
+            ),
+            _ => (0, 0.0),
+        };
+
+        Self {
+            image_wh: [settings.width, settings.height],
+            tile_origin: [rect.x, rect.y],
+            tile_wh: [rect.width, rect.height],
+            sample_index,
+            seed: settings.seed,
+            max_bounces: settings.max_bounces,
+            half_accumulation: targets.half_accumulation as u32,
+            ao_rays,
+            ao_distance,
+            debug_view,
+            debug_scale,
+            photon_count,
+            photon_radius,
+            restir_candidates: match settings.integrator {
+                Integrator::Restir { candidates, .. } => candidates,
+                _ => 0,
+            },
+            restir_frame: 0,
+            pixel_filter: match settings.filter {
+                PixelFilter::Box { .. } => 0,
+                PixelFilter::Gaussian { .. } => 1,
+                PixelFilter::Mitchell { .. } => 2,
+                PixelFilter::BlackmanHarris { .. } => 3,
+            },
+            filter_radius: settings.filter.radius(),
+            transparent_background: settings.transparent_background as u32,
+            exposure: match (settings.integrator, settings.exposure) {
+                (Integrator::AmbientOcclusion { .. } | Integrator::Debug(_), _) | (_, None) => 1.0,
+                (_, Some(exposure)) => exposure.scale(),
+            },
+            ray_epsilon: settings.ray_epsilon,
+            ray_offset: settings.ray_offset,
+            cache_samples: match settings.integrator {
+                Integrator::FinalGather { cache_samples } => cache_samples,
+                _ => 0,
+            },
+            _padding: 0,
+        }
+    }
+}
+
+/// Size of the count and the heads of the lists `PhotonMap` in the shaders starts with, its
+/// photons follow.
+const PHOTON_MAP_HEADER_SIZE: u64 = 16 + 262144 * 4;
+const PHOTON_SIZE: u64 = 32;
+
+/// Vertices of every path from the lights `light_vertices` in the shaders holds, and their
+/// size.
+const LIGHT_VERTICES: u64 = 4;
+const LIGHT_VERTEX_SIZE: u64 = 48;
+
+/// Size of both sets of histograms `PathGuide` in the shaders starts with, and of the record of
+/// every path of the tile that follows them.
+const GUIDE_HISTOGRAMS_SIZE: u64 = 524288 * 4;
+const GUIDE_RECORD_SIZE: u64 = 48;
+
+/// Size of the entries `IrradianceCache` in the shaders starts with, and of the record of every
+/// path of the tile that follows them.
+const CACHE_ENTRIES_SIZE: u64 = 786432 * 4;
+const CACHE_RECORD_SIZE: u64 = 32;
+
+/// Sizes of `Reservoir` and `GiReservoir` in the shaders.
+const RESERVOIR_SIZE: u64 = 48;
+const GI_RESERVOIR_SIZE: u64 = 64;
+
+/// Bytes of the accumulation of a pixel, see `accumulation_stride` in the shaders.
+pub(crate) fn accumulation_size(half_accumulation: bool) -> u64 {
+    if half_accumulation {
+        12
+    } else {
+        20
+    }
+}
+
+/// Sizes of `PathState` and `Hit` in the shaders, the entries of the queues the kernels of the
+/// integrator pass paths through.
+const PATH_STATE_SIZE: u64 = 176;
+const HIT_SIZE: u64 = 64;
+
+const TRACE_RAY_SIZE: u64 = std::mem::size_of::<TraceRay>() as u64;
+const RAY_HIT_SIZE: u64 = std::mem::size_of::<RayHit>() as u64;
+
+/// Size of the dispatch arguments, list, counts, persistent thread slot and sort buckets
+/// `LivePaths` in the shaders starts with, its two lists of path indices follow.
+const LIVE_PATHS_HEADER_SIZE: u64 = 28 + 64 * 4;
+
+/// Workgroups of persistent threads tracing the paths, enough to keep all the cores of a large
+/// GPU busy.
+const PERSISTENT_WORKGROUPS: u32 = 256;
+
+/// Object of the scene found under a pixel by [`RaytracingRenderer::pick`], its index in
+/// [`Scene::instances`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InstanceId(pub usize);
+
+/// Path traced for a single pixel by [`RaytracingRenderer::trace_pixel`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PixelPath {
+    /// Surfaces the path hit, in order.
+    pub vertices: Vec<PathVertex>,
+    /// Whether the path ended by escaping to the sky rather than at its last vertex.
+    pub escaped: bool,
+    /// Radiance the path carried back to the camera.
+    pub radiance: [f32; 3],
+}
+
+/// Surface hit by a [`PixelPath`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PathVertex {
+    pub position: [f32; 3],
+    /// Shading normal, facing the incoming ray.
+    pub normal: [f32; 3],
+    /// Index of the material in the scene.
+    pub material: u32,
+    /// How the path scattered off the surface.
+    pub event: ScatterEvent,
+    /// Throughput of the path leaving the surface.
+    pub throughput: [f32; 3],
+}
+
+/// BSDF lobe a path scattered through, mirroring the `EVENT` constants of the shaders.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ScatterEvent {
+    /// The path ended at the surface.
+    Absorbed,
+    Diffuse,
+    Metallic,
+    /// Reflection off a dielectric.
+    Reflection,
+    /// Refraction into or out of a dielectric.
+    Transmission,
+    /// Scattered by a [scatter hook](ShaderHook::Scatter).
+    Custom,
+    /// Entered, scattered inside or left the subsurface medium of a material.
+    Subsurface,
+    /// Scattered by the [fog](crate::scene::Fog) between surfaces.
+    Volume,
+}
+
 pub trait Render {
     fn render(&self);
     fn render_to_texture(&self, texture: &wgpu::Texture);
 }
 
+/// Path tracer running on a single device.
+///
+/// Rendering and replacing the scene only borrow it immutably, so one renderer can serve
+/// renders from many tasks at once behind an `Arc`, each with the scene current when it
+/// started. Changing shaders, workgroup sizes and the like borrows it mutably, for configuring
+/// it before it's shared.
 pub struct RaytracingRenderer {
-    _instance: Instance,
-    _adapter: Adapter,
-    device: Device,
-    queue: Queue,
+    /// Shared with the application if it created the device, see
+    /// [`from_device`](Self::from_device).
+    _adapter: Arc<Adapter>,
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    bind_group_layout: BindGroupLayout,
+    scene_bind_group_layout: BindGroupLayout,
+    queue_bind_group_layout: BindGroupLayout,
+    transport_bind_group_layout: BindGroupLayout,
+    ray_bind_group_layout: BindGroupLayout,
+    pipelines: Pipelines,
+    /// What the pipelines were built from.
+    shaders: ShaderConfig,
+    /// Whether paths are sorted by what they hit before shading them.
+    sort_paths: bool,
+    /// Whether paths are traced by persistent threads.
+    persistent_threads: bool,
+    /// Rasterizes the first hits of the paths, if they don't come from tracing camera rays.
+    rasterizer: Option<Rasterizer>,
+    /// Replaced as a whole by new scenes, renders hold on to the one they started with.
+    scene: RwLock<Arc<GpuScene>>,
+    /// Kept from one render to the next, for ReSTIR to reuse.
+    reservoirs: Mutex<Option<Reservoirs>>,
+    /// Bytes of GPU memory scenes and renders should stay within.
+    memory_budget: Option<u64>,
+    /// Record commands of the application between the passes of renders, in the order added.
+    pass_callbacks: Vec<(PassStage, PassCallback)>,
+    /// Custom passes of render graphs, see [`add_graph_pass`](Self::add_graph_pass).
+    graph_passes: Vec<(PassDeclaration, PassCallback)>,
+    #[cfg(feature = "renderdoc")]
+    renderdoc: Option<crate::capture::RenderDocCapture>,
+}
+
+// Renders only borrow the renderer immutably, so that tasks on different threads can share one
+// behind an `Arc`. Browsers run everything on a single thread, where wgpu isn't `Send`.
+#[cfg(not(target_arch = "wasm32"))]
+const _: () = {
+    fn assert_send_sync<T: Send + Sync>() {}
+    fn render_is_send<'a>(
+        renderer: &'a RaytracingRenderer,
+        settings: &'a RenderSettings,
+    ) -> impl std::future::Future<Output = Vec<u8>> + Send + 'a {
+        renderer.render(settings)
+    }
+    let _ = assert_send_sync::<RaytracingRenderer>;
+    let _ = render_is_send;
+};
+
+/// What the device of a renderer is requested with on top of what the renderer needs, see
+/// [`RaytracingRenderer::from_adapter_with`].
+#[derive(Clone, Debug, Default)]
+pub struct DeviceRequest {
+    /// Features the application uses on the device, e.g. in its
+    /// [pass callbacks](RaytracingRenderer::add_pass_callback).
+    pub features: Features,
+    /// Limits of the device, e.g. a larger `max_storage_buffer_binding_size` for scenes whose
+    /// geometry doesn't fit the default one.
+    pub limits: Limits,
+}
+
+/// GPU memory a render takes, see [`RaytracingRenderer::estimate_memory`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryEstimate {
+    /// Buffers and textures of the current scene.
+    pub scene: u64,
+    /// Targets the tiles are rendered into, shared by all of them.
+    pub tile_targets: u64,
+    /// Reservoirs of [`Integrator::Restir`], which span the whole image.
+    pub reservoirs: u64,
+    /// Largest buffer of the tile targets, which has to be within the limits of the device.
+    pub largest_tile_buffer: u64,
+}
+
+impl MemoryEstimate {
+    pub fn total(&self) -> u64 {
+        self.scene + self.tile_targets + self.reservoirs
+    }
+}
+
+/// GPU memory a render takes by what it's used for, see
+/// [`RaytracingRenderer::memory_report`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// Primitives of the scene, with the programs of signed distance fields.
+    pub geometry: u64,
+    /// Nodes of the BVH over the primitives.
+    pub bvh: u64,
+    /// Materials, lights and uniforms of the scene.
+    pub materials: u64,
+    /// Cache of the material textures, density grid, light profiles and heightfields.
+    pub textures: u64,
+    /// Samples accumulated for the pixels of a tile.
+    pub accumulation: u64,
+    /// States, hits and lists of the paths of a tile, and what the integrator traces from the
+    /// lights or learns about them.
+    pub paths: u64,
+    /// Output texture of a tile and the buffers it's read back through, and the texture
+    /// feedback and its buffer.
+    pub readback: u64,
+    /// Primary visibility of [hybrid](RaytracingRenderer::set_hybrid_primary) renders.
+    pub visibility: u64,
+    /// Reservoirs of [`Integrator::Restir`], which span the whole image.
+    pub reservoirs: u64,
+}
+
+impl MemoryReport {
+    /// Memory of the scene, shared by every render of it.
+    pub fn scene(&self) -> u64 {
+        self.geometry + self.bvh + self.materials + self.textures
+    }
+
+    /// Memory of the targets the tiles are rendered into, shared by all of them.
+    pub fn tile_targets(&self) -> u64 {
+        self.accumulation + self.paths + self.readback + self.visibility
+    }
+
+    pub fn total(&self) -> u64 {
+        self.scene() + self.tile_targets() + self.reservoirs
+    }
+}
+
+/// Point between the passes of a render where [pass
+/// callbacks](RaytracingRenderer::add_pass_callback) record their commands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PassStage {
+    /// After the passes of every sample of a tile, see [`RenderGraph::sample`].
+    AfterAccumulation,
+    /// Before the samples accumulated for a tile are averaged, exposed and tonemapped into its
+    /// output texture by [`GraphPass::Tonemap`], only if the graph has that pass.
+    BeforeResolve,
+    /// After the output texture of a tile has been written by [`GraphPass::Tonemap`], before
+    /// it's copied out.
+    AfterResolve,
+}
+
+/// What [pass callbacks](RaytracingRenderer::add_pass_callback) record their commands with.
+pub struct PassContext<'a> {
+    /// For [graph passes](RaytracingRenderer::add_graph_pass), where the render is at:
+    /// [`PassStage::AfterAccumulation`] while the samples are traced, then
+    /// [`PassStage::BeforeResolve`] until the tonemap pass and [`PassStage::AfterResolve`] after
+    /// it.
+    pub stage: PassStage,
+    /// Name of the graph pass being recorded, `None` for pass callbacks.
+    pub pass: Option<&'a str>,
+    pub device: &'a Device,
+    pub queue: &'a Queue,
+    /// Encoder of the passes around the stage, submitted after the callbacks return.
+    pub encoder: &'a mut CommandEncoder,
+    /// Samples accumulated for the pixels of the tile, row after row of `rect.width` pixels.
+    /// Every pixel is five 32-bit words, the RGBA32F sum of its samples weighted by the pixel
+    /// filter and the sum of the weights, or three if `half_accumulation` is set, the RGBA16F
+    /// mean of its samples and the sum of the weights. Has `STORAGE` usage.
+    pub accumulation: &'a Buffer,
+    pub half_accumulation: bool,
+    /// RGBA8 output texture of the tile, with the tile in its top left corner. Has
+    /// `STORAGE_BINDING` and `COPY_SRC` usage.
+    pub output: &'a Texture,
+    /// Tile of the image being rendered.
+    pub rect: TileRect,
+    pub settings: &'a RenderSettings,
+    /// Index of the sample just accumulated at [`PassStage::AfterAccumulation`].
+    pub sample: Option<u32>,
+}
+
+type PassCallback = Box<dyn Fn(&mut PassContext<'_>) + Send + Sync>;
+
+/// Reservoirs of [`Integrator::Restir`] for every pixel of an image, twice, and the ones of
+/// ReSTIR GI with room for the bounces of `tile_paths` paths if it's on.
+struct Reservoirs {
+    direct: Buffer,
+    indirect: Option<Buffer>,
+    pixels: u64,
+    tile_paths: u64,
+    /// Dispatches into them so far.
+    dispatches: u32,
+}
+
+/// Bind groups of the reservoirs for a dispatch, in place of the frame bind group.
+struct RestirBindGroups {
+    direct: BindGroup,
+    indirect: Option<BindGroup>,
+    /// Count of the dispatch, see `FrameUniforms::restir_frame`.
+    frame: u32,
+}
+
+/// GPU resources a tile is rendered into, sized for the largest tile of a render and reused
+/// for all of them.
+struct TileTargets {
+    /// Scene the tiles are rendered with, the current one when the targets were created.
+    scene: Arc<GpuScene>,
+    /// RGBA32F sums of the samples of every pixel and the sum of their weights, or RGBA16F means
+    /// if `half_accumulation` is set.
+    accumulation_buffer: Buffer,
+    half_accumulation: bool,
+    uniform_buffer: Buffer,
+    out_tex: Texture,
+    /// Staging buffers tiles are read back through, alternating between consecutive tiles so
+    /// that one can be copied out on the host while the next one renders.
+    out_buffers: [Buffer; 2],
+    padded_bytes_per_row: u32,
+    bind_group: BindGroup,
+    /// Binds the path and hit queues, with an entry for every pixel of a tile, and the lists of
+    /// live paths.
+    queue_bind_group: BindGroup,
+    /// Path and hit queues, read back when tracing a single pixel.
+    paths_buffer: Buffer,
+    hits_buffer: Buffer,
+    /// Holds the lists of live paths, starting with the arguments of the dispatches over them.
+    live_paths_buffer: Buffer,
+    /// Indirect dispatch arguments copied out of `live_paths_buffer`, which can't be read as
+    /// such while it's bound for writing.
+    dispatch_buffer: Buffer,
+    /// Photons of a sample when photon mapping, up to `photon_capacity` of them, the paths
+    /// from the lights of bidirectional path tracing, or the guide of path guiding or the
+    /// irradiance cache of the final gather, which learn over all the samples of the tiles.
+    transport_buffer: Buffer,
+    photon_capacity: u32,
+    /// Binds the frame uniforms and `transport_buffer` for the kernels tracing light from the
+    /// lights.
+    transport_bind_group: BindGroup,
+    /// Binds the frame uniforms and the texels of a lightmap the paths start at instead of the
+    /// camera.
+    lightmap_bind_group: Option<BindGroup>,
+    /// Binds the scene seen through a camera of its own in place of the bind group of `scene`,
+    /// e.g. an eye of [`render_stereo`](RaytracingRenderer::render_stereo).
+    camera_bind_group: Option<BindGroup>,
+    /// Two timestamps the GPU writes before and after the commands of every batch of samples,
+    /// for [`time_tiles`](RaytracingRenderer::time_tiles).
+    timestamps: Option<QuerySet>,
+    /// What hybrid renders rasterize the first hits into, and the bind group of the frame
+    /// uniforms and the primitives seen for the kernel finding them.
+    visibility: Option<(VisibilityTargets, BindGroup)>,
+    feedback: TextureFeedback,
+}
+
+/// Pages of the scene's textures the paths of a tile asked for, read back to
+/// [stream](GpuScene::stream_textures) them in.
+struct TextureFeedback {
+    /// Page table entry plus one of every path, zero if it didn't ask for one.
+    texture: Texture,
+    buffer: Buffer,
+    tile_size: u32,
+    /// Whether `buffer` is being mapped with the requests copied into it, and whether it has
+    /// been mapped already.
+    pending: AtomicBool,
+    mapped: Arc<AtomicBool>,
 }
 
 impl RaytracingRenderer {
+    /// Creates a renderer on the highest performance adapter available.
+    ///
+    /// # Panics
+    ///
+    /// If there's no adapter, see [`try_new`](Self::try_new).
     pub async fn new() -> Self {
-        let _instance = Instance::new(Backends::PRIMARY);
+        Self::try_new().await.expect("No suitable adapter found")
+    }
+
+    /// Creates a renderer on the highest performance adapter available, if there's any.
+    pub async fn try_new() -> Option<Self> {
+        Self::try_new_with(&DeviceRequest::default()).await
+    }
 
-        let _adapter = _instance
+    /// Creates a renderer on the highest performance adapter available with the features and
+    /// limits of `request`, if there's any adapter and it supports them.
+    #[instrument(name = "RaytracingRenderer::try_new_with")]
+    pub async fn try_new_with(request: &DeviceRequest) -> Option<Self> {
+        let instance = Instance::new(Backends::PRIMARY);
+
+        let adapter = instance
             .request_adapter(&RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::HighPerformance,
                 compatible_surface: None,
                 force_fallback_adapter: false,
             })
+            .await?;
+
+        match Self::from_adapter_with(adapter, request).await {
+            Ok(renderer) => Some(renderer),
+            Err(err) => {
+                tracing::warn!(%err, "The adapter can't create the device requested");
+                None
+            }
+        }
+    }
+
+    /// Creates a renderer on the first adapter whose name contains `name`, ignoring case.
+    ///
+    /// Browsers don't list their adapters, so this isn't available on the web.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[instrument(name = "RaytracingRenderer::with_adapter_name")]
+    pub async fn with_adapter_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        let adapter = Instance::new(Backends::PRIMARY)
+            .enumerate_adapters(Backends::PRIMARY)
+            .find(|adapter| adapter.get_info().name.to_lowercase().contains(&name))?;
+
+        Some(Self::from_adapter(adapter).await)
+    }
+
+    /// Creates a renderer on a specific adapter, e.g. one picked from
+    /// [`Instance::enumerate_adapters`].
+    ///
+    /// # Panics
+    ///
+    /// If the adapter fails to create a device, see
+    /// [`from_adapter_with`](Self::from_adapter_with).
+    pub async fn from_adapter(adapter: Adapter) -> Self {
+        Self::from_adapter_with(adapter, &DeviceRequest::default())
             .await
-            .expect("No suitable adapter found");
+            .expect("Failed to create device")
+    }
+
+    /// Creates a renderer on a specific adapter with the features and limits of `request`,
+    /// plus the push constants and timestamp queries the renderer uses if the adapter has them.
+    ///
+    /// Fails if the adapter doesn't support what's requested.
+    #[instrument(name = "RaytracingRenderer::from_adapter_with", skip(adapter), fields(adapter = %adapter.get_info().name))]
+    pub async fn from_adapter_with(
+        adapter: Adapter,
+        request: &DeviceRequest,
+    ) -> Result<Self, RequestDeviceError> {
+        // Must happen before the device gets created, otherwise RenderDoc can't hook it.
+        #[cfg(feature = "renderdoc")]
+        let renderdoc = crate::capture::RenderDocCapture::load();
+
+        let _adapter = adapter;
+
+        // Per-dispatch data is pushed instead of written to a buffer where that's possible.
+        let push_constants = _adapter.features().contains(Features::PUSH_CONSTANTS)
+            && _adapter.limits().max_push_constant_size >= FRAME_UNIFORMS_SIZE;
+
+        let mut features = request.features;
+        // The web backend can't write timestamps yet.
+        if !cfg!(target_arch = "wasm32") {
+            features |= _adapter.features() & Features::TIMESTAMP_QUERY;
+        }
+        if push_constants {
+            features |= Features::PUSH_CONSTANTS;
+        }
 
         let (device, queue) = _adapter
             .request_device(
                 &DeviceDescriptor {
                     label: Some("Main device"),
-                    ..Default::default()
+                    features,
+                    limits: Limits {
+                        max_push_constant_size: if push_constants {
+                            request
+                                .limits
+                                .max_push_constant_size
+                                .max(FRAME_UNIFORMS_SIZE)
+                        } else {
+                            request.limits.max_push_constant_size
+                        },
+                        ..request.limits.clone()
+                    },
                 },
                 None,
             )
-            .await
-            .expect("Failed to create device");
+            .await?;
 
-        Self {
-            _instance,
-            _adapter,
+        tracing::info!(adapter = ?_adapter.get_info(), "Created device");
+
+        Ok(Self::with_device(
+            Arc::new(_adapter),
+            Arc::new(device),
+            Arc::new(queue),
+            #[cfg(feature = "renderdoc")]
+            renderdoc,
+        ))
+    }
+
+    /// Creates a renderer on a device the application already created from `adapter`, so that
+    /// both share one device and its buffers and textures, e.g. to render into a texture bound
+    /// by the application's own passes without a copy through the host.
+    ///
+    /// The device needs at least the default [`Limits`], and push constants are only used if
+    /// it was created with [`Features::PUSH_CONSTANTS`] and room for the frame uniforms in
+    /// them. RenderDoc only hooks devices created after it was loaded.
+    #[instrument(name = "RaytracingRenderer::from_device", skip_all, fields(adapter = %adapter.get_info().name))]
+    pub fn from_device(adapter: Arc<Adapter>, device: Arc<Device>, queue: Arc<Queue>) -> Self {
+        Self::with_device(
+            adapter,
             device,
             queue,
-        }
+            #[cfg(feature = "renderdoc")]
+            crate::capture::RenderDocCapture::load(),
+        )
     }
 
-    pub async fn render_as_rgba8unorm_slice(&self, width: u32, height: u32) -> Vec<u8> {
-        let out_tex_extent = wgpu::Extent3d {
-            width,
-            height,
-            depth_or_array_layers: 1,
-        };
-
-        let out_tex = self.device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("Output texture"),
-            dimension: wgpu::TextureDimension::D2,
-            sample_count: 1,
-            mip_level_count: 1,
-            usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::STORAGE_BINDING,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            size: out_tex_extent,
-        });
-
-        let out_tex_view = out_tex.create_view(&wgpu::TextureViewDescriptor::default());
-
-        let out_buffer = self.device.create_buffer(&BufferDescriptor {
-            label: Some("Output buffer"),
-            size: (width * height * 4) as u64,
-            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
-            mapped_at_creation: false,
-        });
+    fn with_device(
+        _adapter: Arc<Adapter>,
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        #[cfg(feature = "renderdoc")] renderdoc: Option<crate::capture::RenderDocCapture>,
+    ) -> Self {
+        let push_constants = device.features().contains(Features::PUSH_CONSTANTS)
+            && device.limits().max_push_constant_size >= FRAME_UNIFORMS_SIZE;
 
-        let in_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Input buffer"),
-            contents: [width, height].as_bytes(),
-            usage: BufferUsages::UNIFORM,
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Frame bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(8),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(
+                            std::mem::size_of::<FrameUniforms>() as u64
+                        ),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::R32Uint,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ],
         });
 
-        let raytracing_shader = self
-            .device
-            .create_shader_module(include_wgsl!("shaders/ray_gen.wgsl"));
+        let scene_bind_group_layout = GpuScene::bind_group_layout(&device);
 
-        let compute_bind_group_layout = self
-            .device
-            .create_bind_group_layout(&BindGroupLayoutDescriptor {
-                label: Some("Ray generation bind group layout"),
+        // Stands in for the frame bind group layout, without the accumulation.
+        let transport_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("Light transport bind group layout"),
                 entries: &[
                     BindGroupLayoutEntry {
-                        binding: 0,
+                        binding: 1,
                         visibility: ShaderStages::COMPUTE,
-                        ty: BindingType::StorageTexture {
-                            access: wgpu::StorageTextureAccess::WriteOnly,
-                            format: wgpu::TextureFormat::Rgba8Unorm,
-                            view_dimension: wgpu::TextureViewDimension::D2,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: NonZeroU64::new(
+                                std::mem::size_of::<FrameUniforms>() as u64
+                            ),
                         },
                         count: None,
                     },
                     BindGroupLayoutEntry {
-                        binding: 1,
+                        binding: 3,
                         visibility: ShaderStages::COMPUTE,
                         ty: BindingType::Buffer {
-                            ty: BufferBindingType::Uniform,
+                            ty: BufferBindingType::Storage { read_only: false },
                             has_dynamic_offset: false,
-                            min_binding_size: NonZeroU64::new(8),
+                            min_binding_size: NonZeroU64::new(4),
                         },
                         count: None,
                     },
                 ],
             });
 
-        let compute_bind_group = self.device.create_bind_group(&BindGroupDescriptor {
-            label: Some("Ray generation bind group"),
-            layout: &compute_bind_group_layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(&out_tex_view),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: in_buffer.as_entire_binding(),
+        // Also stands in for the frame bind group, with the rays and hits of `trace_rays`.
+        let ray_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Ray bind group layout"),
+            entries: &[(3, true, TRACE_RAY_SIZE), (4, false, RAY_HIT_SIZE)].map(
+                |(binding, read_only, size)| BindGroupLayoutEntry {
+                    binding,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only },
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(size),
+                    },
+                    count: None,
                 },
-            ],
+            ),
         });
 
-        let pipeline_layout = self
-            .device
-            .create_pipeline_layout(&PipelineLayoutDescriptor {
-                label: Some("Ray generation pipeline layout"),
-                bind_group_layouts: &[&compute_bind_group_layout],
-                push_constant_ranges: &[],
-            });
+        let queue_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Path queue bind group layout"),
+            entries: &[PATH_STATE_SIZE, HIT_SIZE, LIVE_PATHS_HEADER_SIZE + 4]
+                .iter()
+                .enumerate()
+                .map(|(binding, &size)| BindGroupLayoutEntry {
+                    binding: binding as u32,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(size),
+                    },
+                    count: None,
+                })
+                .collect::<Vec<_>>(),
+        });
 
-        let raytracing_pipeline = self
-            .device
-            .create_compute_pipeline(&ComputePipelineDescriptor {
-                label: Some("Ray generation pipeline"),
-                layout: Some(&pipeline_layout),
-                module: &raytracing_shader,
-                entry_point: "main",
-            });
+        let shaders = ShaderConfig {
+            sources: ShaderSources::builtin(),
+            defines: shader::default_defines(),
+            hooks: BTreeMap::new(),
+            workgroup_size: DEFAULT_WORKGROUP_SIZE,
+            push_constants,
+        };
+        let pipelines = create_pipelines(
+            &device,
+            &bind_group_layout,
+            &scene_bind_group_layout,
+            &queue_bind_group_layout,
+            &transport_bind_group_layout,
+            &ray_bind_group_layout,
+            &shaders,
+        )
+        .expect("Built-in shaders preprocess");
 
-        let mut encoder = self
-            .device
-            .create_command_encoder(&CommandEncoderDescriptor {
-                label: Some("Ray generation command encoder"),
-            });
+        let scene_data = SceneData::build(&Scene::demo()).expect("Demo scene is valid");
+        let scene = GpuScene::upload(&device, &queue, &scene_bind_group_layout, scene_data, None)
+            .expect("Demo scene has no textures");
 
-        {
-            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
-                label: Some("Ray generation compute pass"),
-            });
+        Self {
+            _adapter,
+            device,
+            queue,
+            bind_group_layout,
+            scene_bind_group_layout,
+            queue_bind_group_layout,
+            transport_bind_group_layout,
+            ray_bind_group_layout,
+            pipelines,
+            shaders,
+            sort_paths: false,
+            persistent_threads: false,
+            rasterizer: None,
+            scene: RwLock::new(Arc::new(scene)),
+            reservoirs: Mutex::new(None),
+            memory_budget: None,
+            pass_callbacks: Vec::new(),
+            graph_passes: Vec::new(),
+            #[cfg(feature = "renderdoc")]
+            renderdoc,
+        }
+    }
+
+    /// Runs `render` inside a RenderDoc frame capture.
+    ///
+    /// ```ignore
+    /// let bytes = renderer.capture(|r| r.render_as_rgba8unorm_slice(512, 512)).await;
+    /// ```
+    ///
+    /// When the application isn't running under RenderDoc the closure is simply awaited.
+    #[cfg(feature = "renderdoc")]
+    pub async fn capture<'a, F, Fut, T>(&'a self, render: F) -> T
+    where
+        F: FnOnce(&'a Self) -> Fut,
+        Fut: std::future::Future<Output = T>,
+    {
+        let Some(renderdoc) = &self.renderdoc else {
+            tracing::warn!("RenderDoc is not attached, rendering without a capture");
+            return render(self).await;
+        };
+
+        renderdoc.start();
+        let result = render(self).await;
+        // Make sure all the work submitted by `render` ends up in the capture.
+        self.device.poll(Maintain::Wait);
+        renderdoc.end();
+
+        result
+    }
+
+    /// Information about the adapter the renderer runs on.
+    pub fn adapter_info(&self) -> AdapterInfo {
+        self._adapter.get_info()
+    }
+
+    /// Device the renderer runs on, e.g. to create the buffers passed to
+    /// [`render_into_buffer`](Self::render_into_buffer).
+    pub fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+
+    /// Queue the renderer submits its work to.
+    pub fn queue(&self) -> &Arc<Queue> {
+        &self.queue
+    }
+
+    /// Adapter the renderer runs on, e.g. to query the formats a surface supports on it.
+    pub fn adapter(&self) -> &Arc<Adapter> {
+        &self._adapter
+    }
+
+    /// Replaces the scene rendered from now on, [`Scene::demo`] until the first call. Renders
+    /// already going on, e.g. on other tasks, keep rendering the scene they started with.
+    ///
+    /// Fails if a mesh or texture can't be loaded or an object refers to a material the scene
+    /// lacks, in which case the previous scene is kept.
+    #[instrument(skip_all)]
+    pub fn set_scene(&self, scene: &Scene) -> Result<(), SceneError> {
+        let data = SceneData::build(scene)?;
+        self.upload_scene(data)
+    }
+
+    /// Like [`set_scene`](Self::set_scene), but if `scene` has the same primitives as the
+    /// current one, only moved, e.g. the next frame of an animation, the BVH of the current
+    /// one is refitted to them instead of building a new one.
+    ///
+    /// Refitting is much faster than building, but the BVH gets slower to trace the further
+    /// the primitives move, so it's still rebuilt once it has become too slow.
+    ///
+    /// Scenes updated in place only write the parts of their buffers that changed, so small
+    /// edits like moving an instance, swapping the material of an object or changing a light
+    /// between frames upload little of the scene.
+    #[instrument(skip_all)]
+    pub fn refit_scene(&self, scene: &Scene) -> Result<(), SceneError> {
+        let data = SceneData::refit(scene, &self.current_scene().data)?;
+        self.upload_scene(data)
+    }
 
-            pass.set_bind_group(0, &compute_bind_group, &[]);
-            pass.set_pipeline(&raytracing_pipeline);
-            pass.dispatch_workgroups(8192, 8192, 1);
+    /// Updates the parameters of the materials of the current scene with the same names as the
+    /// ones of `library` in place, without building or uploading the rest of the scene again,
+    /// e.g. to tweak them between progressive batches. Accumulated samples aren't reset.
+    ///
+    /// Materials can't get textures the scene doesn't have or be displaced differently this
+    /// way, which needs [`set_scene`](Self::set_scene) with the library
+    /// [applied](MaterialLibrary::apply_to).
+    pub fn update_materials(&self, library: &MaterialLibrary) -> Result<(), SceneError> {
+        self.current_scene().update_materials(&self.queue, library)
+    }
+
+    fn upload_scene(&self, data: SceneData) -> Result<(), SceneError> {
+        let mut scene = self.scene.write().unwrap();
+        // Renders still holding on to the current scene would see its buffers change.
+        let in_place = Arc::strong_count(&scene) == 1;
+        *scene = Arc::new(self.replace_scene(&scene, data, in_place)?);
+        Ok(())
+    }
+
+    /// The scene holding `data`, written over the buffers of `current` if `in_place` is set and
+    /// it [fits](GpuScene::fits) in them, as scenes changing from one frame to the next usually
+    /// do.
+    fn replace_scene(
+        &self,
+        current: &GpuScene,
+        data: SceneData,
+        in_place: bool,
+    ) -> Result<GpuScene, SceneError> {
+        if in_place && current.fits(&data) {
+            return Ok(current.update(&self.queue, data));
         }
+        let texture_budget = self.texture_budget(&data)?;
+        GpuScene::upload(
+            &self.device,
+            &self.queue,
+            &self.scene_bind_group_layout,
+            data,
+            texture_budget,
+        )
+    }
 
-        encoder.copy_texture_to_buffer(
-            out_tex.as_image_copy(),
-            ImageCopyBuffer {
-                buffer: &out_buffer,
-                layout: ImageDataLayout {
-                    bytes_per_row: NonZeroU32::new(4 * width),
-                    rows_per_image: NonZeroU32::new(height),
-                    offset: 0,
-                },
-            },
-            out_tex_extent,
-        );
+    /// The scene renders started now render.
+    fn current_scene(&self) -> Arc<GpuScene> {
+        self.scene.read().unwrap().clone()
+    }
 
-        self.queue.submit(Some(encoder.finish()));
+    /// Bytes of the memory budget left for the texture cache of `data`, `None` without a budget.
+    /// Fails if not even the rest of the scene fits.
+    fn texture_budget(&self, data: &SceneData) -> Result<Option<u64>, SceneError> {
+        let Some(budget) = self.memory_budget else {
+            return Ok(None);
+        };
+        let size = data.gpu_size();
+        if size > budget {
+            return Err(SceneError::TooLarge {
+                what: "The scene's buffers",
+                size,
+                limit: budget,
+            });
+        }
+        Ok(Some(budget - size))
+    }
 
-        let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
-        out_buffer
-            .slice(..)
-            .map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+    /// Bytes of GPU memory scenes and renders should stay within, see
+    /// [`set_memory_budget`](Self::set_memory_budget).
+    pub fn memory_budget(&self) -> Option<u64> {
+        self.memory_budget
+    }
 
-        self.device.poll(Maintain::Wait);
+    /// Keeps scenes and renders within `budget` bytes of GPU memory, or only within the limits
+    /// of the device if it's `None`, the default. wgpu can't tell how much memory an adapter
+    /// has, so it's up to the caller to pick a budget, e.g. from the adapter's specifications
+    /// minus what the rest of the application takes.
+    ///
+    /// Scenes whose textures don't fit stream them through a cache of what does, and
+    /// [`set_scene`](Self::set_scene) fails with [`SceneError::TooLarge`] if not even the
+    /// coarsest mip levels of the textures fit next to the rest of the scene. Renders whose tiles don't fit
+    /// next to the scene are split into smaller tiles, except for
+    /// [`render_tile_rects`](Self::render_tile_rects) and checkpointed renders, whose tiles
+    /// are given. Applies to scenes set from now on.
+    pub fn set_memory_budget(&mut self, budget: Option<u64>) {
+        self.memory_budget = budget;
+    }
 
-        if let Some(Ok(())) = receiver.receive().await {
-            let data = out_buffer.slice(..).get_mapped_range();
-            let vec = data.as_bytes().to_vec();
-            drop(data);
+    /// GPU memory a render with `settings` takes along with the current scene.
+    pub fn estimate_memory(&self, settings: &RenderSettings) -> MemoryEstimate {
+        self.estimate_memory_with_tiles(settings, settings.tile_size)
+    }
+
+    /// GPU memory a render with `settings` takes along with the current scene, by what it's
+    /// used for, with the tiles shrunk to fit the [memory budget](Self::set_memory_budget)
+    /// like renders shrink them.
+    pub fn memory_report(&self, settings: &RenderSettings) -> MemoryReport {
+        self.memory_report_with_tiles(settings, self.fit_tiles(settings).tile_size)
+    }
 
-            out_buffer.unmap();
+    fn estimate_memory_with_tiles(
+        &self,
+        settings: &RenderSettings,
+        tile_size: u32,
+    ) -> MemoryEstimate {
+        let report = self.memory_report_with_tiles(settings, tile_size);
+        let path_count = tile_size as u64 * tile_size as u64;
+        MemoryEstimate {
+            scene: report.scene(),
+            tile_targets: report.tile_targets(),
+            reservoirs: report.reservoirs,
+            largest_tile_buffer: [
+                report.accumulation,
+                path_count * PATH_STATE_SIZE,
+                path_count * HIT_SIZE,
+                LIVE_PATHS_HEADER_SIZE + 2 * path_count * 4,
+                transport_size(settings, path_count),
+            ]
+            .into_iter()
+            .max()
+            .unwrap_or(0),
+        }
+    }
 
-            vec
+    fn memory_report_with_tiles(&self, settings: &RenderSettings, tile_size: u32) -> MemoryReport {
+        let path_count = tile_size as u64 * tile_size as u64;
+        let paths = path_count * PATH_STATE_SIZE
+            + path_count * HIT_SIZE
+            + LIVE_PATHS_HEADER_SIZE
+            + 2 * path_count * 4
+            + transport_size(settings, path_count);
+        // The output texture and the two buffers it's read back through, and the texture
+        // feedback and its buffer.
+        let output = 5 * padded_bytes_per_row(tile_size * 4) as u64 * tile_size as u64;
+        let visibility = if self.hybrid_primary() {
+            raster::visibility_size(tile_size)
         } else {
-            panic!("Could not map buffer");
+            0
+        };
+
+        let pixels = settings.width as u64 * settings.height as u64;
+        let reservoirs = match settings.integrator {
+            Integrator::Restir { indirect, .. } => {
+                2 * pixels * RESERVOIR_SIZE
+                    + if indirect {
+                        (2 * pixels + path_count) * GI_RESERVOIR_SIZE
+                    } else {
+                        0
+                    }
+            }
+            _ => 0,
+        };
+
+        let scene = self.current_scene();
+        MemoryReport {
+            geometry: scene.data.geometry_size(),
+            bvh: scene.data.bvh_size(),
+            materials: scene.data.material_size(),
+            textures: scene.data.texture_size() + scene.texture_cache_size(),
+            accumulation: path_count * accumulation_size(half_accumulation(settings)),
+            paths,
+            readback: output,
+            visibility,
+            reservoirs,
+        }
+    }
+
+    /// `settings` with tiles small enough for their targets to fit in the memory budget and
+    /// the limits of the device, halving them down to [`MIN_TILE_SIZE`] at most.
+    fn fit_tiles<'a>(&self, settings: &'a RenderSettings) -> Cow<'a, RenderSettings> {
+        let settings = self.checked_graph(settings);
+        let limit = self.device.limits().max_storage_buffer_binding_size as u64;
+        let fits = |estimate: MemoryEstimate| {
+            estimate.largest_tile_buffer <= limit
+                && self
+                    .memory_budget
+                    .is_none_or(|budget| estimate.total() <= budget)
+        };
+
+        let mut tile_size = settings.tile_size;
+        let mut estimate = self.estimate_memory_with_tiles(&settings, tile_size);
+        while !fits(estimate) && tile_size > MIN_TILE_SIZE {
+            tile_size = (tile_size / 2).max(MIN_TILE_SIZE);
+            estimate = self.estimate_memory_with_tiles(&settings, tile_size);
+        }
+        if !fits(estimate) {
+            tracing::warn!(
+                needed = estimate.total(),
+                budget = self.memory_budget,
+                "Render doesn't fit the memory budget even with the smallest tiles"
+            );
+        }
+
+        if tile_size == settings.tile_size {
+            settings
+        } else {
+            tracing::warn!(
+                from = settings.tile_size,
+                to = tile_size,
+                "Shrank tiles to fit the memory budget"
+            );
+            Cow::Owned(RenderSettings {
+                tile_size,
+                ..settings.into_owned()
+            })
+        }
+    }
+
+    /// `settings` with the default graph if the renderer can't validate theirs, so that renders
+    /// that can't fail still render an image.
+    fn checked_graph<'a>(&self, settings: &'a RenderSettings) -> Cow<'a, RenderSettings> {
+        match self.validate_graph(&settings.graph) {
+            Ok(()) => Cow::Borrowed(settings),
+            Err(err) => {
+                tracing::error!(%err, "Invalid render graph, rendering with the default one");
+                Cow::Owned(RenderSettings {
+                    graph: RenderGraph::default(),
+                    ..settings.clone()
+                })
+            }
         }
     }
+
+    /// Rebuilds the pipelines from `sources`, so that renders started afterwards trace with
+    /// them from the first sample.
+    ///
+    /// The [hooks](Self::set_shader_hook) registered so far are kept. Fails if the shaders
+    /// don't compile, in which case the previous pipelines are kept.
+    pub async fn set_shaders(&mut self, sources: ShaderSources) -> Result<(), ShaderError> {
+        let config = ShaderConfig {
+            sources,
+            ..self.shaders.clone()
+        };
+        self.rebuild_pipelines(config).await
+    }
+
+    /// Rebuilds the pipelines from the shaders in `dir`, see [`ShaderSources::load`] and
+    /// [`set_shaders`](Self::set_shaders).
+    #[cfg(feature = "fs")]
+    pub async fn reload_shaders(&mut self, dir: impl AsRef<Path>) -> Result<(), ShaderError> {
+        self.set_shaders(ShaderSources::load(dir)?).await
+    }
+
+    /// Defines the shaders are preprocessed with, [`shader::default_defines`] unless
+    /// [replaced](Self::set_shader_defines).
+    pub fn shader_defines(&self) -> &BTreeMap<String, String> {
+        &self.shaders.defines
+    }
+
+    /// Rebuilds the pipelines with different preprocessor defines, e.g. to turn off next event
+    /// estimation by leaving out `NEE`.
+    ///
+    /// Fails if the shaders don't compile with them, in which case the previous pipelines are
+    /// kept.
+    pub async fn set_shader_defines(
+        &mut self,
+        defines: BTreeMap<String, String>,
+    ) -> Result<(), ShaderError> {
+        let config = ShaderConfig {
+            defines,
+            ..self.shaders.clone()
+        };
+        self.rebuild_pipelines(config).await
+    }
+
+    /// Splices custom WGSL into the integrator at `hook`, see [`ShaderHook`] for what it has to
+    /// define, or goes back to the built-in behavior with `None`.
+    ///
+    /// Fails if the shaders don't compile with it, in which case the previous pipelines are
+    /// kept.
+    pub async fn set_shader_hook(
+        &mut self,
+        hook: ShaderHook,
+        source: Option<&str>,
+    ) -> Result<(), ShaderError> {
+        let mut config = self.shaders.clone();
+        match source {
+            Some(source) => config.hooks.insert(hook, source.to_owned()),
+            None => config.hooks.remove(&hook),
+        };
+        self.rebuild_pipelines(config).await
+    }
+
+    /// Side of the square workgroups the compute shaders are dispatched in.
+    pub fn workgroup_size(&self) -> u32 {
+        self.shaders.workgroup_size
+    }
+
+    /// Rebuilds the pipelines for `size` by `size` workgroups, as the fastest size differs
+    /// between GPUs. The default is 8.
+    ///
+    /// Fails if the device doesn't support workgroups this large, in which case the previous
+    /// pipelines are kept.
+    pub async fn set_workgroup_size(&mut self, size: u32) -> Result<(), ShaderError> {
+        let config = ShaderConfig {
+            workgroup_size: size,
+            ..self.shaders.clone()
+        };
+        self.rebuild_pipelines(config).await
+    }
+
+    /// Whether the live paths are sorted by the material they hit before shading them.
+    pub fn path_sorting(&self) -> bool {
+        self.sort_paths
+    }
+
+    /// Sorts the live paths by the material they hit between tracing and shading them, so that
+    /// paths running the same shading code are dispatched together.
+    ///
+    /// Sorting costs a few passes over the paths every bounce, which pays off on scenes whose
+    /// materials make neighbouring paths diverge. Off by default.
+    pub fn set_path_sorting(&mut self, sort: bool) {
+        self.sort_paths = sort;
+    }
+
+    /// Whether paths are traced by persistent threads.
+    pub fn persistent_threads(&self) -> bool {
+        self.persistent_threads
+    }
+
+    /// Traces the paths with a fixed number of threads that keep taking the next path off a
+    /// shared queue, instead of one thread per path.
+    ///
+    /// Threads whose paths are cheap to trace move on to the next one rather than idling until
+    /// their workgroup is done, which pays off when the cost per path varies a lot, e.g. deep
+    /// parts of the BVH or glass. Off by default.
+    pub fn set_persistent_threads(&mut self, persistent: bool) {
+        self.persistent_threads = persistent;
+    }
+
+    /// Whether the first hits of the paths come from rasterizing the scene.
+    pub fn hybrid_primary(&self) -> bool {
+        self.rasterizer.is_some()
+    }
+
+    /// Rasterizes the triangles of the scene into every tile before its samples and starts the
+    /// camera rays from the triangle seen at the center of their pixel, traversing the BVH only
+    /// for the rays missing it, instead of tracing all of them from the root. Bounces are traced
+    /// as usual.
+    ///
+    /// Saves most of the traversal of camera rays in scenes of many triangles, which pays off
+    /// the most for interactive renders with few bounces. Other primitives aren't rasterized
+    /// and the rays are still traced up to the triangle in scenes with any, as they could be in
+    /// front of it. Off by default, and stays off on adapters whose vertex shaders can't read
+    /// storage buffers.
+    pub fn set_hybrid_primary(&mut self, hybrid: bool) {
+        let supported = self
+            ._adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(DownlevelFlags::VERTEX_STORAGE);
+        if hybrid && !supported {
+            tracing::warn!("The adapter can't rasterize the scene, tracing the camera rays");
+        }
+        if !hybrid || !supported {
+            self.rasterizer = None;
+        } else if self.rasterizer.is_none() {
+            self.rasterizer = Some(Rasterizer::new(&self.device));
+        }
+    }
+
+    /// Calls `callback` at `stage` of every tile of every render from now on, to record passes
+    /// of the application into the same encoder as the renderer's own, e.g. a denoiser reading
+    /// the accumulated samples or a grade of the tonemapped output, without forking the
+    /// pipeline.
+    ///
+    /// Callbacks at the same stage run in the order they were added. The renderer doesn't track
+    /// what they bind, so the bind groups and uniforms it set are gone once they return, and
+    /// whatever they write into the accumulation ends up in the samples after it.
+    pub fn add_pass_callback(
+        &mut self,
+        stage: PassStage,
+        callback: impl Fn(&mut PassContext<'_>) + Send + Sync + 'static,
+    ) {
+        self.pass_callbacks.push((stage, Box::new(callback)));
+    }
+
+    /// Removes the callbacks added with [`add_pass_callback`](Self::add_pass_callback).
+    pub fn clear_pass_callbacks(&mut self) {
+        self.pass_callbacks.clear();
+    }
+
+    /// Adds the custom pass `declaration` for [render graphs](RenderSettings::graph) to run
+    /// where they list it, replacing the one of the same name if there is one. `callback`
+    /// records its commands like [pass callbacks](Self::add_pass_callback) do.
+    pub fn add_graph_pass(
+        &mut self,
+        declaration: PassDeclaration,
+        callback: impl Fn(&mut PassContext<'_>) + Send + Sync + 'static,
+    ) {
+        self.remove_graph_pass(&declaration.name);
+        self.graph_passes.push((declaration, Box::new(callback)));
+    }
+
+    /// Removes the custom pass called `name`, graphs still listing it don't render anymore.
+    pub fn remove_graph_pass(&mut self, name: &str) {
+        self.graph_passes
+            .retain(|(declaration, _)| declaration.name != name);
+    }
+
+    /// Checks that `graph` can be rendered with the custom passes added to the renderer, see
+    /// [`RenderGraph::validate`].
+    pub fn validate_graph(&self, graph: &RenderGraph) -> Result<(), GraphError> {
+        graph.validate(|name| self.graph_pass(name))
+    }
+
+    /// Checks that `settings` can be rendered with the custom passes added to the renderer, see
+    /// [`RenderSettings::validate`].
+    pub fn validate_settings(&self, settings: &RenderSettings) -> Result<(), SettingsError> {
+        settings.validate(|name| self.graph_pass(name))
+    }
+
+    fn graph_pass(&self, name: &str) -> Option<&PassDeclaration> {
+        self.graph_passes
+            .iter()
+            .map(|(declaration, _)| declaration)
+            .find(|declaration| declaration.name == name)
+    }
+
+    /// What the passes recorded at `stage` of the tile `rect` rendered into `targets` record
+    /// their commands with.
+    fn pass_context<'a>(
+        &'a self,
+        stage: PassStage,
+        encoder: &'a mut CommandEncoder,
+        targets: &'a TileTargets,
+        settings: &'a RenderSettings,
+        rect: TileRect,
+        sample: Option<u32>,
+    ) -> PassContext<'a> {
+        PassContext {
+            stage,
+            pass: None,
+            device: &self.device,
+            queue: &self.queue,
+            encoder,
+            accumulation: &targets.accumulation_buffer,
+            half_accumulation: targets.half_accumulation,
+            output: &targets.out_tex,
+            rect,
+            settings,
+            sample,
+        }
+    }
+
+    /// Calls the pass callbacks of the stage of `context`.
+    fn run_pass_callbacks(&self, context: &mut PassContext<'_>) {
+        let stage = context.stage;
+        for (_, callback) in self.pass_callbacks.iter().filter(|(at, _)| *at == stage) {
+            callback(context);
+        }
+    }
+
+    /// Calls the custom graph pass named in `context`.
+    fn run_graph_pass(&self, context: &mut PassContext<'_>) {
+        let (_, callback) = self
+            .graph_passes
+            .iter()
+            .find(|(declaration, _)| Some(declaration.name.as_str()) == context.pass)
+            .expect("Validated graphs only have passes the renderer knows");
+        callback(context);
+    }
+
+    /// Whether renders are bit for bit reproducible, see
+    /// [`set_deterministic`](Self::set_deterministic).
+    pub fn deterministic(&self) -> bool {
+        self.shaders.defines.contains_key("DETERMINISTIC")
+    }
+
+    /// Makes renders of the same scene with the same settings come out bit for bit the same on
+    /// the same adapter, driver and shaders, e.g. to compare them in automated tests:
+    ///
+    /// - ReSTIR starts over in every render instead of reusing the reservoirs of earlier ones.
+    /// - Photon mapping sums the photons around a surface without depending on the order the GPU
+    ///   stored them in, and stores at most one for every photon traced, which drops the
+    ///   caustics of light going on off surfaces that also have a diffuse lobe.
+    ///
+    /// The rest already is: the random numbers of every pixel only depend on the seed of the
+    /// settings and the index of the sample, and every pixel sums its own samples in order.
+    /// Different adapters or drivers still round differently. Rebuilds the pipelines with the
+    /// `DETERMINISTIC` shader define. Off by default.
+    pub async fn set_deterministic(&mut self, deterministic: bool) -> Result<(), ShaderError> {
+        let mut defines = self.shaders.defines.clone();
+        if deterministic {
+            defines.insert("DETERMINISTIC".to_owned(), String::new());
+        } else {
+            defines.remove("DETERMINISTIC");
+        }
+        self.set_shader_defines(defines).await
+    }
+
+    /// See [`GpuScene::set_probe_camera`], for [`probes::bake`](crate::probes::bake).
+    pub(crate) fn set_probe_camera(&self, position: Option<[f32; 3]>) {
+        self.current_scene().set_probe_camera(&self.queue, position);
+    }
+
+    #[instrument(skip_all, fields(workgroup_size = config.workgroup_size))]
+    async fn rebuild_pipelines(&mut self, config: ShaderConfig) -> Result<(), ShaderError> {
+        self.device.push_error_scope(ErrorFilter::Validation);
+        let pipelines = create_pipelines(
+            &self.device,
+            &self.bind_group_layout,
+            &self.scene_bind_group_layout,
+            &self.queue_bind_group_layout,
+            &self.transport_bind_group_layout,
+            &self.ray_bind_group_layout,
+            &config,
+        );
+        let error = self.device.pop_error_scope().await;
+        let pipelines = pipelines?;
+        if let Some(err) = error {
+            return Err(ShaderError::Compile(err));
+        }
+
+        self.pipelines = pipelines;
+        self.shaders = config;
+        Ok(())
+    }
+
+    pub async fn render_as_rgba8unorm_slice(&self, width: u32, height: u32) -> Vec<u8> {
+        self.render(&RenderSettings::new(width, height)).await
+    }
+
+    /// Renders an image as tightly packed RGBA8 rows.
+    pub async fn render(&self, settings: &RenderSettings) -> Vec<u8> {
+        self.render_with_progress(settings, |_| {}).await
+    }
+
+    /// Like [`render`](Self::render), calling `on_progress` every time a batch of samples has
+    /// been accumulated on the GPU.
+    pub async fn render_with_progress(
+        &self,
+        settings: &RenderSettings,
+        on_progress: impl FnMut(Progress),
+    ) -> Vec<u8> {
+        let mut image = vec![0; settings.width as usize * settings.height as usize * 4];
+        self.render_into_with_progress(settings, &mut image, on_progress)
+            .await;
+        image
+    }
+
+    /// Renders an image into `out` as tightly packed RGBA8 rows, without allocating the whole
+    /// image on the host.
+    ///
+    /// # Panics
+    ///
+    /// If `out` isn't exactly `width * height * 4` bytes long.
+    pub async fn render_into(&self, settings: &RenderSettings, out: &mut [u8]) {
+        self.render_into_with_progress(settings, out, |_| {}).await
+    }
+
+    /// Like [`render_into`](Self::render_into), calling `on_progress` every time a batch of
+    /// samples has been accumulated on the GPU.
+    #[instrument(skip(self, out, on_progress))]
+    pub async fn render_into_with_progress(
+        &self,
+        settings: &RenderSettings,
+        out: &mut [u8],
+        on_progress: impl FnMut(Progress),
+    ) {
+        let settings = &*self.fit_tiles(settings);
+        let image_bytes = settings.width as usize * settings.height as usize * 4;
+        assert_eq!(
+            out.len(),
+            image_bytes,
+            "Output slice doesn't fit a {}x{} RGBA8 image",
+            settings.width,
+            settings.height
+        );
+
+        let targets = self.create_tile_targets(
+            settings.tile_size,
+            half_accumulation(settings),
+            settings.integrator,
+        );
+        self.render_into_targets(&targets, settings, out, on_progress)
+            .await;
+    }
+
+    /// Renders the animation of `scene` from `start` up to `end` seconds at `fps` frames per
+    /// second, calling `on_frame` with the index and the tightly packed RGBA8 pixels of every
+    /// frame.
+    ///
+    /// Tile targets and the host image are shared by all the frames, the BVH is
+    /// [refitted](Self::refit_scene) from one frame to the next for as long as the primitives
+    /// stay the same and scene buffers are rewritten in place whenever the next frame fits in
+    /// them. The scene stays set once the sequence is done, at its last frame.
+    #[instrument(skip(self, scene, on_frame))]
+    pub async fn render_sequence(
+        &mut self,
+        scene: &Scene,
+        settings: &RenderSettings,
+        start: f32,
+        end: f32,
+        fps: f32,
+        mut on_frame: impl FnMut(u32, &[u8]),
+    ) -> Result<(), SceneError> {
+        let settings = &*self.fit_tiles(settings);
+        let mut targets = self.create_tile_targets(
+            settings.tile_size,
+            half_accumulation(settings),
+            settings.integrator,
+        );
+        let mut image = vec![0; settings.width as usize * settings.height as usize * 4];
+
+        for (index, time) in animation::frame_times(start, end, fps).enumerate() {
+            // Nothing else renders while the renderer is borrowed mutably, so the buffers can be
+            // rewritten in place even though the targets hold on to the scene.
+            let data = SceneData::refit(&scene.at(time), &targets.scene.data)?;
+            targets.scene = Arc::new(self.replace_scene(&targets.scene, data, true)?);
+            *self.scene.get_mut().unwrap() = targets.scene.clone();
+            self.render_into_targets(&targets, settings, &mut image, |_| {})
+                .instrument(info_span!("frame", index, time))
+                .await;
+            on_frame(index as u32, &image);
+        }
+
+        Ok(())
+    }
+
+    /// Renders the camera of `scene` following `path` from zero up to the duration of the path
+    /// at `fps` frames per second like [`render_sequence`](Self::render_sequence) does, e.g. a
+    /// turntable in a single call. The rest of the animation of the scene plays along.
+    pub async fn render_camera_path(
+        &mut self,
+        scene: &Scene,
+        settings: &RenderSettings,
+        path: &CameraPath,
+        fps: f32,
+        on_frame: impl FnMut(u32, &[u8]),
+    ) -> Result<(), SceneError> {
+        let scene = Scene {
+            camera_animation: path.keyframes(&scene.camera, 0.0, fps),
+            ..scene.clone()
+        };
+        self.render_sequence(&scene, settings, 0.0, path.duration(), fps, on_frame)
+            .await
+    }
+
+    /// Renders an image a band of rows at a time, calling `on_band` with every band spanning the
+    /// whole width of the image as soon as all of its tiles have been read back.
+    ///
+    /// Bands are `tile_size` rows tall (the last one possibly shorter), so only one of them has
+    /// to be held in host memory when `on_band` writes them out, e.g. to a streaming encoder.
+    /// They're shorter if the tiles have to shrink to fit the
+    /// [memory budget](Self::set_memory_budget).
+    #[instrument(skip(self, on_band))]
+    pub async fn render_bands(&self, settings: &RenderSettings, mut on_band: impl FnMut(Tile)) {
+        let settings = &*self.fit_tiles(settings);
+        let tiles = tile::tiles(settings.width, settings.height, settings.tile_size);
+        let band_bytes = settings.width as usize * settings.tile_size as usize * 4;
+        let mut band = Vec::with_capacity(band_bytes);
+
+        let targets = self.create_tile_targets(
+            settings.tile_size,
+            half_accumulation(settings),
+            settings.integrator,
+        );
+        self.render_tiles_pipelined(&targets, settings, tiles, |event| match event {
+            TileEvent::Samples { .. } => {}
+            TileEvent::Row {
+                rect,
+                index,
+                pixels,
+            } => {
+                let offset = (index * settings.width as usize + rect.x as usize) * 4;
+                let end = offset + pixels.len();
+                if band.len() < end {
+                    band.resize(end, 0);
+                }
+                band[offset..end].copy_from_slice(pixels);
+            }
+            TileEvent::Finished(rect) => {
+                if rect.x + rect.width == settings.width {
+                    on_band(Tile {
+                        rect: TileRect {
+                            x: 0,
+                            y: rect.y,
+                            width: settings.width,
+                            height: rect.height,
+                        },
+                        data: std::mem::replace(&mut band, Vec::with_capacity(band_bytes)),
+                    });
+                }
+            }
+        })
+        .await;
+    }
+
+    /// Renders an image like [`render`](Self::render), antialiased by rendering it at `factor`
+    /// times its width and height and scaling that back down with a Mitchell-Netravali filter.
+    ///
+    /// Every pixel of the image gets `factor * factor` times the samples per pixel of
+    /// `settings`, taken at `factor * factor` spots spread over it, and costs as much more to
+    /// render. The image is held in host memory at the higher resolution in full precision, 16
+    /// bytes a pixel, before it's scaled down.
+    ///
+    /// Fails with the [`SettingsError`] of the settings at the higher resolution, e.g. when it's
+    /// too large to render.
+    pub async fn render_supersampled(
+        &self,
+        settings: &RenderSettings,
+        factor: u32,
+    ) -> Result<Vec<u8>, SettingsError> {
+        self.render_supersampled_with_progress(settings, factor, |_| {})
+            .await
+    }
+
+    /// Like [`render_supersampled`](Self::render_supersampled), calling `on_progress` with the
+    /// progress of the render at the higher resolution.
+    pub async fn render_supersampled_with_progress(
+        &self,
+        settings: &RenderSettings,
+        factor: u32,
+        on_progress: impl FnMut(Progress),
+    ) -> Result<Vec<u8>, SettingsError> {
+        let factor = factor.max(1);
+        let (Some(width), Some(height)) = (
+            settings.width.checked_mul(factor),
+            settings.height.checked_mul(factor),
+        ) else {
+            return Err(SettingsError::TooLarge);
+        };
+        let supersampled = RenderSettings {
+            width,
+            height,
+            ..settings.clone()
+        };
+        self.validate_settings(&supersampled)?;
+        let mut pixels = self
+            .render_hdr_with_progress(&supersampled, on_progress)
+            .await;
+        // Clamped like the RGBA8 output of renders, so that bright samples don't bleed into
+        // the pixels around them.
+        for value in &mut pixels {
+            *value = value.clamp(0.0, 1.0);
+        }
+        Ok(
+            supersample::downscale(&pixels, supersampled.width, supersampled.height, factor)
+                .into_iter()
+                .map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+                .collect(),
+        )
+    }
+
+    /// Renders an image as tightly packed rows of linear RGBA32F pixels, without the
+    /// quantization of the RGBA8 output, e.g. to save it as OpenEXR.
+    #[instrument(skip(self, on_progress))]
+    pub async fn render_hdr_with_progress(
+        &self,
+        settings: &RenderSettings,
+        on_progress: impl FnMut(Progress),
+    ) -> Vec<f32> {
+        let settings = &*self.fit_tiles(settings);
+        let targets = self.create_tile_targets(settings.tile_size, false, settings.integrator);
+        self.render_hdr_into_targets(&targets, settings, on_progress)
+            .await
+    }
+
+    /// Renders the depths of the surfaces at the centers of the pixels as tightly packed rows of
+    /// floats, following the convention of `depth_buffer` so that they can be copied into the
+    /// depth buffer of raster passes drawn with [`DepthBuffer::projection`] over the image.
+    pub async fn render_depth(
+        &self,
+        settings: &RenderSettings,
+        depth_buffer: DepthBuffer,
+    ) -> Vec<f32> {
+        // The linear depth view saturates at its scale, which has to stay finite and leave
+        // room for the precision of near surfaces.
+        let scale = depth_buffer.far.min(1.0e30);
+        let settings = RenderSettings {
+            samples_per_pixel: 1,
+            half_precision_accumulation: false,
+            integrator: Integrator::Debug(DebugView::Depth {
+                max_distance: scale,
+            }),
+            // Rays through the centers, as rasterization samples there
+            filter: PixelFilter::Box { radius: 0.0 },
+            ..settings.clone()
+        };
+        self.render_hdr_with_progress(&settings, |_| {})
+            .await
+            .chunks_exact(4)
+            .map(|pixel| match pixel[0] {
+                depth if depth >= 1.0 => depth_buffer.depth(f32::INFINITY),
+                depth => depth_buffer.depth(depth * scale),
+            })
+            .collect()
+    }
+
+    /// Renders the image tile by tile, measuring how long the GPU takes for every tile, to find
+    /// the geometry and materials that are slow to render, e.g. with
+    /// [`analysis::time_heatmap`](crate::analysis::time_heatmap). Smaller tiles locate them
+    /// more precisely, down to a tile per pixel. The image itself isn't read back.
+    ///
+    /// On native devices with [`Features::TIMESTAMP_QUERY`] the time is the one the GPU spent
+    /// on the batches of samples of the tile, between timestamps it wrote before and after each
+    /// of them. Without it the time is the wall time the host waited for the tile, which
+    /// includes recording and submitting its commands.
+    pub async fn time_tiles(&self, settings: &RenderSettings) -> Vec<(TileRect, Duration)> {
+        let settings = &*self.fit_tiles(settings);
+        let mut targets = self.create_tile_targets(
+            settings.tile_size,
+            half_accumulation(settings),
+            settings.integrator,
+        );
+        let tiles = tile::tiles(settings.width, settings.height, settings.tile_size);
+        if cfg!(target_arch = "wasm32")
+            || !self.device.features().contains(Features::TIMESTAMP_QUERY)
+        {
+            return tiles
+                .map(|rect| {
+                    let start = Instant::now();
+                    self.accumulate_tile(&targets, settings, rect, |_| {});
+                    (rect, start.elapsed())
+                })
+                .collect();
+        }
+
+        let timestamps = self.device.create_query_set(&QuerySetDescriptor {
+            label: Some("Tile timestamps"),
+            ty: QueryType::Timestamp,
+            count: 2,
+        });
+        // Every batch resolves its timestamps at an offset of its own.
+        let batches = settings
+            .samples_per_pixel
+            .div_ceil(SAMPLES_PER_DISPATCH)
+            .max(1) as u64;
+        let resolve_buffer = self.device.create_buffer(&BufferDescriptor {
+            label: Some("Timestamp resolve buffer"),
+            size: batches * QUERY_RESOLVE_BUFFER_ALIGNMENT,
+            usage: BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let period = self.queue.get_timestamp_period() as f64;
+        targets.timestamps = Some(timestamps);
+        let timestamps = targets.timestamps.as_ref().unwrap();
+
+        let mut times = Vec::new();
+        for rect in tiles {
+            let mut batch = 0;
+            self.accumulate_tile(&targets, settings, rect, |_| {
+                let mut encoder = self
+                    .device
+                    .create_command_encoder(&CommandEncoderDescriptor {
+                        label: Some("Timestamp resolve command encoder"),
+                    });
+                let offset = batch * QUERY_RESOLVE_BUFFER_ALIGNMENT;
+                encoder.resolve_query_set(timestamps, 0..2, &resolve_buffer, offset);
+                self.queue.submit(Some(encoder.finish()));
+                batch += 1;
+            });
+
+            let ticks: u64 = if batch == 0 {
+                0
+            } else {
+                self.read_buffer(&resolve_buffer, batch * QUERY_RESOLVE_BUFFER_ALIGNMENT)
+                    .await
+                    .chunks_exact(QUERY_RESOLVE_BUFFER_ALIGNMENT as usize)
+                    .map(|batch| {
+                        let [start, end]: [u64; 2] = bytemuck::pod_read_unaligned(&batch[..16]);
+                        end.saturating_sub(start)
+                    })
+                    .sum()
+            };
+            times.push((rect, Duration::from_nanos((ticks as f64 * period) as u64)));
+        }
+        times
+    }
+
+    /// Renders the views of the left and right eye of `stereo`, each like [`render`](Self::render)
+    /// renders the view of the camera, e.g. for the two displays of a headset.
+    ///
+    /// The eyes are bound for these renders only, other renders of the scene still look through
+    /// its camera.
+    pub async fn render_stereo(&self, settings: &RenderSettings, stereo: Stereo) -> [Vec<u8>; 2] {
+        let settings = &*self.fit_tiles(settings);
+        let mut images =
+            [(); 2].map(|_| vec![0; settings.width as usize * settings.height as usize * 4]);
+        for (eye, image) in [Eye::Left, Eye::Right].into_iter().zip(&mut images) {
+            let mut targets = self.create_tile_targets(
+                settings.tile_size,
+                half_accumulation(settings),
+                settings.integrator,
+            );
+            targets.camera_bind_group = Some(targets.scene.eye_bind_group(
+                &self.device,
+                &self.scene_bind_group_layout,
+                stereo,
+                eye,
+            ));
+            self.render_into_targets(&targets, settings, image, |_| {})
+                .await;
+        }
+        images
+    }
+
+    /// Like [`render_stereo`](Self::render_stereo), with the views side by side in one image
+    /// twice as wide, the left eye on the left.
+    pub async fn render_stereo_side_by_side(
+        &self,
+        settings: &RenderSettings,
+        stereo: Stereo,
+    ) -> Vec<u8> {
+        let [left, right] = self.render_stereo(settings, stereo).await;
+        let row = settings.width as usize * 4;
+        left.chunks_exact(row)
+            .zip(right.chunks_exact(row))
+            .flat_map(|(left, right)| left.iter().chain(right))
+            .copied()
+            .collect()
+    }
+
+    /// Starts the paths at the texels of a lightmap rather than the camera, see
+    /// [`lightmap::bake`](crate::lightmap::bake), and renders it like
+    /// [`render_hdr_with_progress`](Self::render_hdr_with_progress). `texels` are the
+    /// `LightmapTexel`s of the shaders, `settings.width` by `settings.height` of them.
+    pub(crate) async fn render_lightmap(
+        &self,
+        settings: &RenderSettings,
+        texels: &[u8],
+    ) -> Vec<f32> {
+        // Texels are sampled over their triangles rather than spread by a pixel filter.
+        let settings = &RenderSettings {
+            filter: PixelFilter::default(),
+            ..self.fit_tiles(settings).into_owned()
+        };
+        let mut targets = self.create_tile_targets(settings.tile_size, false, settings.integrator);
+        let texel_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Lightmap texel buffer"),
+            contents: texels,
+            usage: BufferUsages::STORAGE,
+        });
+        targets.lightmap_bind_group = Some(self.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Lightmap bind group"),
+            layout: &self.transport_bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 1,
+                    resource: targets.uniform_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: texel_buffer.as_entire_binding(),
+                },
+            ],
+        }));
+        self.render_hdr_into_targets(&targets, settings, |_| {})
+            .await
+    }
+
+    async fn render_hdr_into_targets(
+        &self,
+        targets: &TileTargets,
+        settings: &RenderSettings,
+        mut on_progress: impl FnMut(Progress),
+    ) -> Vec<f32> {
+        let start = Instant::now();
+        let tiles: Vec<_> =
+            tile::tiles(settings.width, settings.height, settings.tile_size).collect();
+
+        let mut progress = Progress::new(settings, tiles.len() as u32);
+
+        let mut image = vec![0.0; settings.width as usize * settings.height as usize * 4];
+
+        for rect in tiles {
+            self.accumulate_tile(targets, settings, rect, |count| {
+                progress.samples_done += rect.pixel_count() * count as u64;
+                progress.elapsed = start.elapsed();
+                on_progress(progress);
+            });
+
+            let accumulation = self
+                .read_buffer(
+                    &targets.accumulation_buffer,
+                    rect.pixel_count() * accumulation_size(false),
+                )
+                .await;
+            // The readback isn't guaranteed to be aligned for a cast to floats.
+            let sums: Vec<[f32; 5]> = accumulation
+                .chunks_exact(accumulation_size(false) as usize)
+                .map(bytemuck::pod_read_unaligned)
+                .collect();
+
+            for (row, row_sums) in sums.chunks_exact(rect.width as usize).enumerate() {
+                let offset =
+                    ((rect.y as usize + row) * settings.width as usize + rect.x as usize) * 4;
+                for (pixel, [r, g, b, a, weight]) in image[offset..offset + row_sums.len() * 4]
+                    .chunks_exact_mut(4)
+                    .zip(row_sums)
+                {
+                    // Filters with negative lobes can leave a pixel without any weight.
+                    let scale = if *weight == 0.0 { 0.0 } else { weight.recip() };
+                    pixel.copy_from_slice(&[r * scale, g * scale, b * scale, a * scale]);
+                }
+            }
+
+            progress.tiles_done += 1;
+            progress.elapsed = start.elapsed();
+            on_progress(progress);
+        }
+
+        image
+    }
+
+    /// Renders an image straight into `buffer` on the GPU, skipping the host readback.
+    ///
+    /// Rows are laid out [`buffer_bytes_per_row`] bytes apart, as texture to buffer copies
+    /// require. The buffer has to belong to this renderer's device, have `COPY_DST` usage and be
+    /// at least `buffer_bytes_per_row(width) * height` bytes long. The render is submitted but
+    /// not waited for.
+    #[instrument(skip(self, buffer))]
+    pub fn render_into_buffer(&self, settings: &RenderSettings, buffer: &Buffer) {
+        let settings = &*self.fit_tiles(settings);
+        let bytes_per_row = buffer_bytes_per_row(settings.width);
+        assert!(
+            buffer.size() >= bytes_per_row as u64 * settings.height as u64,
+            "Output buffer doesn't fit a {}x{} RGBA8 image",
+            settings.width,
+            settings.height
+        );
+
+        let targets = self.create_tile_targets(
+            settings.tile_size,
+            half_accumulation(settings),
+            settings.integrator,
+        );
+
+        for rect in tile::tiles(settings.width, settings.height, settings.tile_size) {
+            self.accumulate_tile(&targets, settings, rect, |_| {});
+
+            let mut encoder = self
+                .device
+                .create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("Resolve command encoder"),
+                });
+
+            let readback = ImageCopyBuffer {
+                buffer,
+                layout: ImageDataLayout {
+                    bytes_per_row: NonZeroU32::new(bytes_per_row),
+                    rows_per_image: NonZeroU32::new(rect.height),
+                    offset: rect.y as u64 * bytes_per_row as u64 + rect.x as u64 * 4,
+                },
+            };
+            self.encode_tile_passes(&mut encoder, &targets, settings, rect, readback);
+
+            self.queue.submit(Some(encoder.finish()));
+        }
+    }
+
+    /// Renders only `rects` out of the image described by `settings`, calling `on_tile` with
+    /// every one of them as soon as it has been read back.
+    ///
+    /// `rects` is consumed lazily, one tile at a time, so it can hand out work shared with
+    /// other renderers.
+    #[instrument(skip(self, rects, on_tile))]
+    pub async fn render_tile_rects(
+        &self,
+        settings: &RenderSettings,
+        rects: impl IntoIterator<Item = TileRect>,
+        mut on_tile: impl FnMut(Tile),
+    ) {
+        let settings = &*self.checked_graph(settings);
+        let mut data = Vec::new();
+
+        let targets = self.create_tile_targets(
+            settings.tile_size,
+            half_accumulation(settings),
+            settings.integrator,
+        );
+        self.render_tiles_pipelined(&targets, settings, rects, |event| match event {
+            TileEvent::Samples { .. } => {}
+            TileEvent::Row { pixels, .. } => data.extend_from_slice(pixels),
+            TileEvent::Finished(rect) => on_tile(Tile {
+                rect,
+                data: std::mem::take(&mut data),
+            }),
+        })
+        .await;
+    }
+
+    /// Renders the image as a stream of tiles, each one yielded as soon as it has been read
+    /// back.
+    ///
+    /// Tiles are only rendered while the stream is polled, so at most one of them is held in
+    /// host memory at any time.
+    pub fn render_tiles(&self, settings: &RenderSettings) -> impl Stream<Item = Tile> + '_ {
+        let settings = self.fit_tiles(settings).into_owned();
+        let tiles = tile::tiles(settings.width, settings.height, settings.tile_size);
+        let targets = self.create_tile_targets(
+            settings.tile_size,
+            half_accumulation(&settings),
+            settings.integrator,
+        );
+
+        stream::unfold(
+            (settings, tiles, targets),
+            move |(settings, mut tiles, targets)| async move {
+                let rect = tiles.next()?;
+                let tile = self.render_tile(&targets, &settings, rect).await;
+                Some((tile, (settings, tiles, targets)))
+            },
+        )
+    }
+
+    /// Runs the jobs of a [`RenderQueue`](crate::queue::RenderQueue), see
+    /// [`RenderQueue::run`](crate::queue::RenderQueue::run).
+    pub(crate) async fn render_jobs(
+        &self,
+        jobs: Vec<RenderJob>,
+        mut on_image: impl FnMut(usize, Vec<u8>),
+    ) -> Result<(), SceneError> {
+        let mut images = VecDeque::new();
+        let mut pending: Option<(PendingReadback, Arc<TileTargets>)> = None;
+        let mut cached_targets: Option<((u32, bool, Integrator), Arc<TileTargets>)> = None;
+        let mut slot = 0;
+
+        for (index, job) in jobs.into_iter().enumerate() {
+            if let Some(scene) = &job.scene {
+                // Work already submitted keeps the previous scene alive until it's done, and
+                // so do the cached targets, which bind it.
+                cached_targets = None;
+                if let Err(err) = self.set_scene(scene) {
+                    if let Some((readback, targets)) = pending {
+                        self.finish_queued_tile(&targets, readback, &mut images, &mut on_image)
+                            .await;
+                    }
+                    return Err(err);
+                }
+            }
+
+            let settings = self.fit_tiles(&job.settings).into_owned();
+            let key = (
+                settings.tile_size,
+                half_accumulation(&settings),
+                settings.integrator,
+            );
+            let targets = match &cached_targets {
+                Some((cached_key, targets)) if *cached_key == key => targets.clone(),
+                _ => {
+                    let targets = Arc::new(self.create_tile_targets(key.0, key.1, key.2));
+                    cached_targets.insert((key, targets)).1.clone()
+                }
+            };
+
+            let tiles: Vec<_> =
+                tile::tiles(settings.width, settings.height, settings.tile_size).collect();
+            images.push_back(QueuedImage {
+                index,
+                width: settings.width,
+                tiles_left: tiles.len(),
+                pixels: vec![0; settings.width as usize * settings.height as usize * 4],
+            });
+
+            for rect in tiles {
+                // Queued without waiting, the GPU only gets waited for by the readbacks.
+                let batches =
+                    (0..settings.samples_per_pixel).step_by(SAMPLES_PER_DISPATCH as usize);
+                for sample_index in batches {
+                    let sample_count =
+                        SAMPLES_PER_DISPATCH.min(settings.samples_per_pixel - sample_index);
+                    self.dispatch_samples(&targets, &settings, rect, sample_index, sample_count);
+                }
+                let readback = self.begin_read_tile(&targets, &settings, rect, slot);
+                slot ^= 1;
+                if let Some((previous, previous_targets)) =
+                    pending.replace((readback, targets.clone()))
+                {
+                    self.finish_queued_tile(
+                        &previous_targets,
+                        previous,
+                        &mut images,
+                        &mut on_image,
+                    )
+                    .await;
+                }
+            }
+        }
+
+        if let Some((readback, targets)) = pending {
+            self.finish_queued_tile(&targets, readback, &mut images, &mut on_image)
+                .await;
+        }
+        // Only empty images can be left.
+        for image in images {
+            on_image(image.index, image.pixels);
+        }
+        Ok(())
+    }
+
+    /// Copies a tile of [`render_jobs`](Self::render_jobs) into the first of `images` still
+    /// missing some, then hands the finished ones at the front to `on_image`.
+    async fn finish_queued_tile(
+        &self,
+        targets: &TileTargets,
+        readback: PendingReadback,
+        images: &mut VecDeque<QueuedImage>,
+        on_image: &mut impl FnMut(usize, Vec<u8>),
+    ) {
+        let image = images
+            .iter_mut()
+            .find(|image| image.tiles_left > 0)
+            .expect("Every readback belongs to an image");
+        self.finish_read_tile(targets, readback, |event| {
+            if let TileEvent::Row {
+                rect,
+                index,
+                pixels,
+            } = event
+            {
+                let offset =
+                    ((rect.y as usize + index) * image.width as usize + rect.x as usize) * 4;
+                image.pixels[offset..offset + pixels.len()].copy_from_slice(pixels);
+            }
+        })
+        .await;
+        image.tiles_left -= 1;
+
+        while images.front().is_some_and(|image| image.tiles_left == 0) {
+            let image = images.pop_front().unwrap();
+            on_image(image.index, image.pixels);
+        }
+    }
+
+    /// Renders like [`render`](Self::render), saving a [`Checkpoint`] to `path` at most every
+    /// `interval` so that an interrupted render can be resumed.
+    ///
+    /// Fails if the renderer can't [validate](Self::validate_graph) the graph of `settings`. If
+    /// `path` already holds a checkpoint of a render with the same settings, the render
+    /// continues from it. The checkpoint is removed once the render completes. Scene contents
+    /// aren't part of the checkpoint, resuming with a different scene mixes the two.
+    ///
+    /// The checkpoint keeps the settings after shrinking the tiles to the memory budget, a
+    /// renderer that shrinks them differently can't resume it.
+    #[cfg(feature = "fs")]
+    #[instrument(skip(self, path))]
+    pub async fn render_checkpointed(
+        &self,
+        settings: &RenderSettings,
+        path: impl AsRef<Path>,
+        interval: Duration,
+    ) -> io::Result<Vec<u8>> {
+        let path = path.as_ref();
+        self.validate_graph(&settings.graph)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let settings = &*self.fit_tiles(settings);
+        let half_accumulation = half_accumulation(settings);
+
+        let mut checkpoint = if path.exists() {
+            let checkpoint = Checkpoint::load(path)?;
+            if checkpoint.settings != *settings {
+                return Err(binary::invalid_data(
+                    "Checkpoint was saved with different render settings",
+                ));
+            }
+
+            tracing::info!(
+                tiles_done = checkpoint.tiles_done,
+                samples_done = checkpoint.samples_done,
+                "Resuming render from checkpoint"
+            );
+            checkpoint
+        } else {
+            Checkpoint::new(settings)
+        };
+
+        let targets =
+            self.create_tile_targets(settings.tile_size, half_accumulation, settings.integrator);
+        let mut last_save = Instant::now();
+
+        let tiles = tile::tiles(settings.width, settings.height, settings.tile_size)
+            .skip(checkpoint.tiles_done as usize);
+
+        for rect in tiles {
+            if checkpoint.samples_done > 0 {
+                self.queue
+                    .write_buffer(&targets.accumulation_buffer, 0, &checkpoint.accumulation);
+            }
+
+            while checkpoint.samples_done < settings.samples_per_pixel {
+                let sample_count =
+                    SAMPLES_PER_DISPATCH.min(settings.samples_per_pixel - checkpoint.samples_done);
+                self.dispatch_samples(
+                    &targets,
+                    settings,
+                    rect,
+                    checkpoint.samples_done,
+                    sample_count,
+                );
+                self.device.poll(Maintain::Wait);
+                checkpoint.samples_done += sample_count;
+
+                if last_save.elapsed() >= interval
+                    && checkpoint.samples_done < settings.samples_per_pixel
+                {
+                    checkpoint.accumulation = self
+                        .read_buffer(
+                            &targets.accumulation_buffer,
+                            rect.pixel_count() * accumulation_size(half_accumulation),
+                        )
+                        .await;
+                    checkpoint.save(path)?;
+                    last_save = Instant::now();
+                }
+            }
+
+            let image = &mut checkpoint.image;
+            self.read_tile(&targets, settings, rect, |row, src| {
+                let offset =
+                    ((rect.y as usize + row) * settings.width as usize + rect.x as usize) * 4;
+                image[offset..offset + src.len()].copy_from_slice(src);
+            })
+            .await;
+
+            checkpoint.tiles_done += 1;
+            checkpoint.samples_done = 0;
+            checkpoint.accumulation.clear();
+
+            if last_save.elapsed() >= interval {
+                checkpoint.save(path)?;
+                last_save = Instant::now();
+            }
+        }
+
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+
+        Ok(checkpoint.image)
+    }
+
+    /// Traces sample `sample_index` of pixel (`x`, `y`) of the image described by `settings`,
+    /// returning every bounce of its path, e.g. to find out what makes a pixel black or a
+    /// firefly.
+    ///
+    /// The path is always traced by the path tracing integrator, the way it's traced for the
+    /// same sample of a full render.
+    ///
+    /// # Panics
+    ///
+    /// If the pixel is outside the image.
+    #[instrument(skip(self))]
+    pub async fn trace_pixel(
+        &self,
+        settings: &RenderSettings,
+        x: u32,
+        y: u32,
+        sample_index: u32,
+    ) -> PixelPath {
+        assert!(
+            x < settings.width && y < settings.height,
+            "Pixel ({x}, {y}) is outside the {}x{} image",
+            settings.width,
+            settings.height
+        );
+
+        let targets = self.create_tile_targets(1, false, settings.integrator);
+        let rect = TileRect {
+            x,
+            y,
+            width: 1,
+            height: 1,
+        };
+        let uniforms = FrameUniforms::new(settings, &targets, rect, sample_index);
+        self.write_frame_uniforms(&targets, &uniforms);
+
+        // The hit and the state of the path after every bounce.
+        let record_size = HIT_SIZE + PATH_STATE_SIZE;
+        let bounces = settings.max_bounces as u64 + 1;
+        let records = self.device.create_buffer(&BufferDescriptor {
+            label: Some("Pixel path buffer"),
+            size: bounces * record_size,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let pipelines = &self.pipelines;
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Pixel path command encoder"),
+            });
+
+        let mut pass = self.begin_path_pass(&mut encoder, &targets, "Ray generation pass");
+        self.dispatch_kernel(&mut pass, &pipelines.ray_gen, &uniforms, rect);
+        drop(pass);
+        self.copy_dispatch_args(&mut encoder, &targets);
+
+        let shadow_rays = self.shaders.defines.contains_key("NEE");
+        for bounce in 0..bounces {
+            let mut pass = self.begin_path_pass(&mut encoder, &targets, "Bounce pass");
+            self.dispatch_kernel_live(&mut pass, &pipelines.trace, &uniforms, &targets);
+            self.dispatch_kernel_live(&mut pass, &pipelines.shade, &uniforms, &targets);
+            if shadow_rays {
+                self.dispatch_kernel_live(&mut pass, &pipelines.shadow, &uniforms, &targets);
+            }
+            drop(pass);
+
+            let offset = bounce * record_size;
+            encoder.copy_buffer_to_buffer(&targets.hits_buffer, 0, &records, offset, HIT_SIZE);
+            encoder.copy_buffer_to_buffer(
+                &targets.paths_buffer,
+                0,
+                &records,
+                offset + HIT_SIZE,
+                PATH_STATE_SIZE,
+            );
+
+            // Once the path is dead the copies of the later bounces repeat its last state.
+            let mut pass = self.begin_path_pass(&mut encoder, &targets, "Compaction pass");
+            self.dispatch_kernel_live(&mut pass, &pipelines.compact, &uniforms, &targets);
+            self.set_kernel(&mut pass, &pipelines.dispatch_args, &uniforms);
+            pass.dispatch_workgroups(1, 1, 1);
+            drop(pass);
+            self.copy_dispatch_args(&mut encoder, &targets);
+        }
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = records.slice(..);
+        self.map_read(slice).await;
+        let bytes = slice.get_mapped_range();
+
+        let word =
+            |offset: usize| -> u32 { bytemuck::pod_read_unaligned(&bytes[offset..offset + 4]) };
+        let vec3 = |offset: usize| [0, 4, 8].map(|i| f32::from_bits(word(offset + i)));
+
+        let mut path = PixelPath {
+            vertices: Vec::new(),
+            escaped: false,
+            radiance: [0.0; 3],
+        };
+        // Fields are read at their offsets in `Hit` and `PathState`.
+        for offset in (0..bytes.len()).step_by(record_size as usize) {
+            let state = offset + HIT_SIZE as usize;
+            path.radiance = vec3(state + 48);
+            if word(offset + 36) == 0 {
+                path.escaped = true;
+                break;
+            }
+
+            path.vertices.push(PathVertex {
+                position: vec3(offset),
+                normal: vec3(offset + 16),
+                material: word(offset + 12),
+                event: match word(state + 68) {
+                    1 => ScatterEvent::Diffuse,
+                    2 => ScatterEvent::Metallic,
+                    3 => ScatterEvent::Reflection,
+                    4 => ScatterEvent::Transmission,
+                    5 => ScatterEvent::Custom,
+                    6 => ScatterEvent::Subsurface,
+                    7 => ScatterEvent::Volume,
+                    _ => ScatterEvent::Absorbed,
+                },
+                throughput: vec3(state + 32),
+            });
+            if word(state + 60) == 0 {
+                break;
+            }
+        }
+        drop(bytes);
+        records.unmap();
+
+        path
+    }
+
+    /// Finds the object seen through pixel (`x`, `y`) of the image described by `settings` by
+    /// tracing a single camera ray, e.g. to select objects by clicking on them. `None` if the ray
+    /// escapes the scene.
+    ///
+    /// # Panics
+    ///
+    /// If the pixel is outside the image.
+    #[instrument(skip(self))]
+    pub async fn pick(&self, settings: &RenderSettings, x: u32, y: u32) -> Option<InstanceId> {
+        assert!(
+            x < settings.width && y < settings.height,
+            "Pixel ({x}, {y}) is outside the {}x{} image",
+            settings.width,
+            settings.height
+        );
+
+        let targets = self.create_tile_targets(1, false, settings.integrator);
+        let rect = TileRect {
+            x,
+            y,
+            width: 1,
+            height: 1,
+        };
+        let uniforms = FrameUniforms::new(settings, &targets, rect, 0);
+        self.write_frame_uniforms(&targets, &uniforms);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Pick command encoder"),
+            });
+        let mut pass = self.begin_path_pass(&mut encoder, &targets, "Ray generation pass");
+        self.dispatch_kernel(&mut pass, &self.pipelines.ray_gen, &uniforms, rect);
+        drop(pass);
+        self.copy_dispatch_args(&mut encoder, &targets);
+        let mut pass = self.begin_path_pass(&mut encoder, &targets, "Pick pass");
+        self.dispatch_kernel_live(&mut pass, &self.pipelines.trace, &uniforms, &targets);
+        drop(pass);
+        self.queue.submit(Some(encoder.finish()));
+
+        // `hit` and `object` of the `Hit`.
+        let hit = self.read_buffer(&targets.hits_buffer, HIT_SIZE).await;
+        let word =
+            |offset: usize| -> u32 { bytemuck::pod_read_unaligned(&hit[offset..offset + 4]) };
+        (word(36) != 0).then(|| InstanceId(word(48) as usize))
+    }
+
+    /// Finds the closest hits of `rays` in the scene, see [`rays`](crate::rays).
+    #[instrument(skip(self, rays), fields(count = rays.len()))]
+    pub async fn trace_rays(&self, rays: &[TraceRay]) -> Vec<RayHit> {
+        if rays.is_empty() {
+            return Vec::new();
+        }
+
+        let ray_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Ray buffer"),
+            contents: rays.as_bytes(),
+            usage: BufferUsages::STORAGE,
+        });
+        let size = rays.len() as u64 * RAY_HIT_SIZE;
+        let hit_buffer = self.device.create_buffer(&BufferDescriptor {
+            label: Some("Ray hit buffer"),
+            size,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Trace rays command encoder"),
+            });
+        self.encode_trace_rays(&mut encoder, &ray_buffer, &hit_buffer, rays.len() as u32);
+        self.queue.submit(Some(encoder.finish()));
+
+        let bytes = self.read_buffer(&hit_buffer, size).await;
+        bytes
+            .chunks_exact(RAY_HIT_SIZE as usize)
+            .map(|hit| RayHit::read_from(hit).expect("Chunks are the size of a hit"))
+            .collect()
+    }
+
+    /// Records the tracing of the first `count` [`TraceRay`]s in `rays` into `encoder`, writing
+    /// their [`RayHit`]s to the start of `hits`, e.g. in between the application's own passes
+    /// generating the rays and shading the hits. Nothing is submitted.
+    ///
+    /// Both buffers have to belong to this renderer's device, have `STORAGE` usage and hold
+    /// `count` rays or hits. The hits are found in the scene set when the commands are
+    /// recorded.
+    pub fn encode_trace_rays(
+        &self,
+        encoder: &mut CommandEncoder,
+        rays: &Buffer,
+        hits: &Buffer,
+        count: u32,
+    ) {
+        if count == 0 {
+            return;
+        }
+        let (rays_size, hits_size) = (count as u64 * TRACE_RAY_SIZE, count as u64 * RAY_HIT_SIZE);
+        assert!(
+            rays.size() >= rays_size,
+            "Ray buffer doesn't fit {count} rays"
+        );
+        assert!(
+            hits.size() >= hits_size,
+            "Hit buffer doesn't fit {count} hits"
+        );
+        let bind_group = self.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Ray bind group"),
+            layout: &self.ray_bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: rays,
+                        offset: 0,
+                        size: NonZeroU64::new(rays_size),
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: hits,
+                        offset: 0,
+                        size: NonZeroU64::new(hits_size),
+                    }),
+                },
+            ],
+        });
+
+        // Spread over a second dimension past the workgroups a dispatch has in one.
+        let invocations = self.shaders.workgroup_size * self.shaders.workgroup_size;
+        let workgroups = count.div_ceil(invocations);
+        let max_x = self.device.limits().max_compute_workgroups_per_dimension;
+        let x = workgroups.min(max_x);
+
+        let scene = self.current_scene();
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Trace rays pass"),
+        });
+        pass.set_pipeline(&self.pipelines.trace_rays);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.set_bind_group(1, scene.bind_group(), &[]);
+        pass.dispatch_workgroups(x, workgroups.div_ceil(x), 1);
+    }
+
+    /// Renders an image into `out` through `targets`, which have to fit the tile size of
+    /// `settings`.
+    async fn render_into_targets(
+        &self,
+        targets: &TileTargets,
+        settings: &RenderSettings,
+        out: &mut [u8],
+        mut on_progress: impl FnMut(Progress),
+    ) {
+        let start = Instant::now();
+        let tiles: Vec<_> =
+            tile::tiles(settings.width, settings.height, settings.tile_size).collect();
+
+        let mut progress = Progress::new(settings, tiles.len() as u32);
+
+        self.render_tiles_pipelined(targets, settings, tiles, |event| match event {
+            TileEvent::Samples { rect, count } => {
+                progress.samples_done += rect.pixel_count() * count as u64;
+                progress.elapsed = start.elapsed();
+                on_progress(progress);
+            }
+            TileEvent::Row {
+                rect,
+                index,
+                pixels,
+            } => {
+                let offset =
+                    ((rect.y as usize + index) * settings.width as usize + rect.x as usize) * 4;
+                out[offset..offset + pixels.len()].copy_from_slice(pixels);
+            }
+            TileEvent::Finished(_) => {
+                progress.tiles_done += 1;
+                progress.elapsed = start.elapsed();
+                on_progress(progress);
+            }
+        })
+        .await;
+    }
+
+    /// Renders `tiles` in order, reporting what happens to them through `on_event`.
+    ///
+    /// The readback of every tile is only waited for once the next one has been rendered, so
+    /// the GPU keeps working while the previous tile is mapped and copied out on the host.
+    async fn render_tiles_pipelined(
+        &self,
+        targets: &TileTargets,
+        settings: &RenderSettings,
+        tiles: impl IntoIterator<Item = TileRect>,
+        mut on_event: impl FnMut(TileEvent<'_>),
+    ) {
+        let mut pending: Option<PendingReadback> = None;
+
+        for (index, rect) in tiles.into_iter().enumerate() {
+            self.accumulate_tile(targets, settings, rect, |count| {
+                on_event(TileEvent::Samples { rect, count })
+            });
+
+            let readback = self.begin_read_tile(targets, settings, rect, index % 2);
+            if let Some(previous) = pending.replace(readback) {
+                self.finish_read_tile(targets, previous, &mut on_event)
+                    .await;
+            }
+        }
+
+        if let Some(last) = pending {
+            self.finish_read_tile(targets, last, &mut on_event).await;
+        }
+    }
+
+    async fn render_tile(
+        &self,
+        targets: &TileTargets,
+        settings: &RenderSettings,
+        rect: TileRect,
+    ) -> Tile {
+        self.accumulate_tile(targets, settings, rect, |_| {});
+
+        let mut data = Vec::with_capacity(rect.pixel_count() as usize * 4);
+        self.read_tile(targets, settings, rect, |_, row| {
+            data.extend_from_slice(row)
+        })
+        .await;
+
+        Tile { rect, data }
+    }
+
+    /// Accumulates all the samples of `rect`, calling `on_samples` with the number of samples
+    /// per pixel of every finished batch.
+    #[instrument(skip(self, targets, settings, on_samples))]
+    fn accumulate_tile(
+        &self,
+        targets: &TileTargets,
+        settings: &RenderSettings,
+        rect: TileRect,
+        mut on_samples: impl FnMut(u32),
+    ) {
+        for sample_index in (0..settings.samples_per_pixel).step_by(SAMPLES_PER_DISPATCH as usize) {
+            let sample_count = SAMPLES_PER_DISPATCH.min(settings.samples_per_pixel - sample_index);
+            self.dispatch_samples(targets, settings, rect, sample_index, sample_count);
+            self.device.poll(Maintain::Wait);
+            on_samples(sample_count);
+        }
+    }
+
+    /// Creates the targets for tiles of up to `tile_size` pixels a side, accumulating in half
+    /// precision if `half_accumulation` is set, with room for what `integrator` traces from the
+    /// lights.
+    fn create_tile_targets(
+        &self,
+        tile_size: u32,
+        half_accumulation: bool,
+        integrator: Integrator,
+    ) -> TileTargets {
+        let _span = info_span!("create_tile_targets", tile_size, half_accumulation).entered();
+
+        // Every render creates its targets, so deterministic ones start ReSTIR over here.
+        if self.deterministic() {
+            *self.reservoirs.lock().unwrap() = None;
+        }
+
+        let out_tex_extent = wgpu::Extent3d {
+            width: tile_size,
+            height: tile_size,
+            depth_or_array_layers: 1,
+        };
+
+        let out_tex = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Output texture"),
+            dimension: wgpu::TextureDimension::D2,
+            sample_count: 1,
+            mip_level_count: 1,
+            usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::STORAGE_BINDING,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            size: out_tex_extent,
+        });
+
+        let out_tex_view = out_tex.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let padded_bytes_per_row = padded_bytes_per_row(tile_size * 4);
+        let out_buffers = ["Output buffer 0", "Output buffer 1"].map(|label| {
+            self.device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size: (padded_bytes_per_row * tile_size) as u64,
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            })
+        });
+
+        let accumulation_buffer = self.device.create_buffer(&BufferDescriptor {
+            label: Some("Accumulation buffer"),
+            size: tile_size as u64 * tile_size as u64 * accumulation_size(half_accumulation),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let uniform_buffer = self.device.create_buffer(&BufferDescriptor {
+            label: Some("Frame uniform buffer"),
+            size: std::mem::size_of::<FrameUniforms>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let feedback = TextureFeedback {
+            texture: self.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Texture feedback texture"),
+                dimension: wgpu::TextureDimension::D2,
+                sample_count: 1,
+                mip_level_count: 1,
+                usage: wgpu::TextureUsages::COPY_SRC
+                    | wgpu::TextureUsages::COPY_DST
+                    | wgpu::TextureUsages::STORAGE_BINDING,
+                format: wgpu::TextureFormat::R32Uint,
+                size: out_tex_extent,
+            }),
+            buffer: self.device.create_buffer(&BufferDescriptor {
+                label: Some("Texture feedback buffer"),
+                size: (padded_bytes_per_row * tile_size) as u64,
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            tile_size,
+            pending: AtomicBool::new(false),
+            mapped: Arc::new(AtomicBool::new(false)),
+        };
+        let feedback_view = feedback
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = self.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Frame bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: accumulation_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(&out_tex_view),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: BindingResource::TextureView(&feedback_view),
+                },
+            ],
+        });
+
+        let path_count = tile_size as u64 * tile_size as u64;
+        let queue_buffers = [
+            ("Path state buffer", path_count * PATH_STATE_SIZE),
+            ("Hit buffer", path_count * HIT_SIZE),
+            (
+                "Live path buffer",
+                LIVE_PATHS_HEADER_SIZE + 2 * path_count * 4,
+            ),
+        ]
+        .map(|(label, size)| {
+            self.device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            })
+        });
+
+        let queue_bind_group = self.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Path queue bind group"),
+            layout: &self.queue_bind_group_layout,
+            entries: &[0, 1, 2].map(|binding| BindGroupEntry {
+                binding,
+                resource: queue_buffers[binding as usize].as_entire_binding(),
+            }),
+        });
+        let [paths_buffer, hits_buffer, live_paths_buffer] = queue_buffers;
+
+        let dispatch_buffer = self.device.create_buffer(&BufferDescriptor {
+            label: Some("Indirect dispatch buffer"),
+            size: 12,
+            usage: BufferUsages::INDIRECT | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // Bindings can't be empty, other renders get a buffer that's never used.
+        let photons = match integrator {
+            Integrator::PhotonMapping { photons, .. } => photons,
+            _ => 0,
+        };
+        let transport_buffer = self.device.create_buffer(&BufferDescriptor {
+            label: Some("Light transport buffer"),
+            size: match integrator {
+                Integrator::PhotonMapping { .. } if photons > 0 => {
+                    PHOTON_MAP_HEADER_SIZE + photons as u64 * PHOTON_SIZE
+                }
+                Integrator::Bidirectional => path_count * LIGHT_VERTICES * LIGHT_VERTEX_SIZE,
+                Integrator::PathGuiding => GUIDE_HISTOGRAMS_SIZE + path_count * GUIDE_RECORD_SIZE,
+                Integrator::FinalGather { .. } => {
+                    CACHE_ENTRIES_SIZE + path_count * CACHE_RECORD_SIZE
+                }
+                _ => 4,
+            },
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let transport_bind_group = self.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Light transport bind group"),
+            layout: &self.transport_bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: transport_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let visibility = self.rasterizer.as_ref().map(|rasterizer| {
+            let visibility = rasterizer.create_targets(&self.device, tile_size);
+            let bind_group = self.device.create_bind_group(&BindGroupDescriptor {
+                label: Some("Visibility bind group"),
+                layout: &self.transport_bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: visibility.buffer.as_entire_binding(),
+                    },
+                ],
+            });
+            (visibility, bind_group)
+        });
+
+        TileTargets {
+            scene: self.current_scene(),
+            accumulation_buffer,
+            half_accumulation,
+            uniform_buffer,
+            out_tex,
+            out_buffers,
+            padded_bytes_per_row,
+            bind_group,
+            queue_bind_group,
+            paths_buffer,
+            hits_buffer,
+            live_paths_buffer,
+            dispatch_buffer,
+            transport_buffer,
+            photon_capacity: photons,
+            transport_bind_group,
+            lightmap_bind_group: None,
+            camera_bind_group: None,
+            timestamps: None,
+            visibility,
+            feedback,
+        }
+    }
+
+    /// Makes `uniforms` visible to the dispatches of the next submission if the shaders read
+    /// them from a buffer, otherwise [`dispatch_kernel`](Self::dispatch_kernel) pushes them.
+    fn write_frame_uniforms(&self, targets: &TileTargets, uniforms: &FrameUniforms) {
+        if !self.shaders.push_constants {
+            self.queue
+                .write_buffer(&targets.uniform_buffer, 0, uniforms.as_bytes());
+        }
+    }
+
+    /// Sets `pipeline` for the dispatches recorded next in `pass`, along with `uniforms` if the
+    /// shaders read them from push constants.
+    fn set_kernel<'a>(
+        &self,
+        pass: &mut ComputePass<'a>,
+        pipeline: &'a ComputePipeline,
+        uniforms: &FrameUniforms,
+    ) {
+        pass.set_pipeline(pipeline);
+        if self.shaders.push_constants {
+            pass.set_push_constants(0, uniforms.as_bytes());
+        }
+    }
+
+    /// Records a dispatch of `pipeline` with an invocation for every pixel of `rect`.
+    fn dispatch_kernel<'a>(
+        &self,
+        pass: &mut ComputePass<'a>,
+        pipeline: &'a ComputePipeline,
+        uniforms: &FrameUniforms,
+        rect: TileRect,
+    ) {
+        self.set_kernel(pass, pipeline, uniforms);
+        pass.dispatch_workgroups(
+            rect.width.div_ceil(self.shaders.workgroup_size),
+            rect.height.div_ceil(self.shaders.workgroup_size),
+            1,
+        );
+    }
+
+    /// Records a dispatch of `pipeline` with an invocation for every path in the current list
+    /// of live paths, as of the last [`copy_dispatch_args`](Self::copy_dispatch_args).
+    fn dispatch_kernel_live<'a>(
+        &self,
+        pass: &mut ComputePass<'a>,
+        pipeline: &'a ComputePipeline,
+        uniforms: &FrameUniforms,
+        targets: &'a TileTargets,
+    ) {
+        self.set_kernel(pass, pipeline, uniforms);
+        pass.dispatch_workgroups_indirect(&targets.dispatch_buffer, 0);
+    }
+
+    /// Binds the ReSTIR reservoirs of `settings`' image in place of the accumulation of
+    /// `targets`, recreating them if the size of the image or the tiles changed, and counts the
+    /// dispatch into them.
+    fn restir_bind_groups(
+        &self,
+        targets: &TileTargets,
+        settings: &RenderSettings,
+        indirect: bool,
+    ) -> RestirBindGroups {
+        let pixels = settings.width as u64 * settings.height as u64;
+        let tile_paths = settings.tile_size as u64 * settings.tile_size as u64;
+        // Zeroed, so they start out empty.
+        let create_buffer = |label, size| {
+            self.device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            })
+        };
+        let mut reservoirs = self.reservoirs.lock().unwrap();
+        let reservoirs = match &mut *reservoirs {
+            Some(reservoirs)
+                if reservoirs.pixels == pixels
+                    && reservoirs.tile_paths == tile_paths
+                    && reservoirs.indirect.is_some() == indirect =>
+            {
+                reservoirs
+            }
+            reservoirs => reservoirs.insert(Reservoirs {
+                direct: create_buffer("Reservoir buffer", 2 * pixels * RESERVOIR_SIZE),
+                indirect: indirect.then(|| {
+                    create_buffer(
+                        "GI reservoir buffer",
+                        (2 * pixels + tile_paths) * GI_RESERVOIR_SIZE,
+                    )
+                }),
+                pixels,
+                tile_paths,
+                dispatches: 0,
+            }),
+        };
+        reservoirs.dispatches = reservoirs.dispatches.wrapping_add(1);
+
+        let bind_group = |label, buffer: &Buffer| {
+            self.device.create_bind_group(&BindGroupDescriptor {
+                label: Some(label),
+                layout: &self.transport_bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: targets.uniform_buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        RestirBindGroups {
+            direct: bind_group("Reservoir bind group", &reservoirs.direct),
+            indirect: reservoirs
+                .indirect
+                .as_ref()
+                .map(|buffer| bind_group("GI reservoir bind group", buffer)),
+            frame: reservoirs.dispatches,
+        }
+    }
+
+    /// Begins a pass with the bind groups of the path tracing kernels set.
+    fn begin_path_pass<'a>(
+        &'a self,
+        encoder: &'a mut CommandEncoder,
+        targets: &'a TileTargets,
+        label: &str,
+    ) -> ComputePass<'a> {
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor { label: Some(label) });
+        pass.set_bind_group(0, &targets.bind_group, &[]);
+        let scene = targets.camera_bind_group.as_ref();
+        pass.set_bind_group(1, scene.unwrap_or(targets.scene.bind_group()), &[]);
+        pass.set_bind_group(2, &targets.queue_bind_group, &[]);
+        pass
+    }
+
+    /// Records the copy of the arguments of the dispatches over the live paths to where
+    /// indirect dispatches read them.
+    fn copy_dispatch_args(&self, encoder: &mut CommandEncoder, targets: &TileTargets) {
+        encoder.copy_buffer_to_buffer(
+            &targets.live_paths_buffer,
+            0,
+            &targets.dispatch_buffer,
+            0,
+            targets.dispatch_buffer.size(),
+        );
+    }
+
+    /// Accumulates `sample_count` more samples for every pixel of `rect`, without waiting for
+    /// the GPU to finish them. With push constants all the samples go in a single submission,
+    /// otherwise each needs its own to see its uniforms.
+    ///
+    /// Every sample is a wave of kernels, the ones of the passes of the
+    /// [graph](RenderSettings::graph): ray generation starts a path per pixel, then every
+    /// bounce traces the live paths, shades their hits and traces the shadow rays of the
+    /// diffuse ones, until accumulation adds the finished paths to the tile. In between
+    /// bounces stream compaction drops the paths that terminated, so that later bounces only
+    /// dispatch work for the ones still alive.
+    #[instrument(skip(self, targets, settings))]
+    fn dispatch_samples(
+        &self,
+        targets: &TileTargets,
+        settings: &RenderSettings,
+        rect: TileRect,
+        sample_index: u32,
+        sample_count: u32,
+    ) {
+        self.stream_textures(targets);
+        let pipelines = &self.pipelines;
+        let nee = self.shaders.defines.contains_key("NEE");
+        let photons = matches!(settings.integrator, Integrator::PhotonMapping { .. })
+            && targets.photon_capacity > 0;
+        let bidirectional = settings.integrator == Integrator::Bidirectional;
+        let guiding = settings.integrator == Integrator::PathGuiding && nee;
+        let final_gather = matches!(settings.integrator, Integrator::FinalGather { .. }) && nee;
+        // Both find the diffuse hits through the shadow rays queued at them.
+        let light_paths = if photons && nee {
+            Some(&pipelines.gather)
+        } else if bidirectional && nee {
+            Some(&pipelines.connect)
+        } else {
+            None
+        };
+        let restir = match settings.integrator {
+            // Paths only bounce off the surfaces the camera sees with bounces to spare.
+            Integrator::Restir { indirect, .. } if nee => Some(self.restir_bind_groups(
+                targets,
+                settings,
+                indirect && settings.max_bounces > 0,
+            )),
+            _ => None,
+        };
+        let restir_indirect = restir.as_ref().and_then(|restir| restir.indirect.as_ref());
+        let (max_bounces, shade, shadow_rays) = match settings.integrator {
+            // Without next event estimation nothing ever queues shadow rays.
+            Integrator::PathTracing
+            | Integrator::PhotonMapping { .. }
+            | Integrator::Bidirectional
+            | Integrator::Restir { .. }
+            | Integrator::PathGuiding
+            | Integrator::FinalGather { .. } => (settings.max_bounces, &pipelines.shade, nee),
+            // Only the occlusion of the first hits is computed.
+            Integrator::AmbientOcclusion { .. } => (0, &pipelines.ambient_occlusion, false),
+            Integrator::Debug(_) => (0, &pipelines.debug, false),
+        };
+
+        let mut command_buffers = Vec::new();
+        if let Some(timestamps) = &targets.timestamps {
+            command_buffers.push(self.timestamp_commands(timestamps, 0));
+        }
+        // Paths from lightmaps don't start at the camera, and the rasterizer only draws through
+        // the one of the scene.
+        let visibility = targets.visibility.as_ref().filter(|_| {
+            targets.lightmap_bind_group.is_none() && targets.camera_bind_group.is_none()
+        });
+        if let (Some((visibility, _)), Some(rasterizer)) = (visibility, &self.rasterizer) {
+            command_buffers.push(rasterizer.rasterize(
+                &self.device,
+                &self.queue,
+                visibility,
+                &targets.scene,
+                rect,
+                [settings.width, settings.height],
+            ));
+        }
+        for sample in sample_index..sample_index + sample_count {
+            let mut uniforms = FrameUniforms::new(settings, targets, rect, sample);
+            if let Some(restir) = &restir {
+                uniforms.restir_frame = restir.frame;
+            }
+            self.write_frame_uniforms(targets, &uniforms);
+
+            let mut encoder = self
+                .device
+                .create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("Path tracing command encoder"),
+                });
+
+            encoder.push_debug_group("Path tracing");
+
+            for node in &settings.graph.sample {
+                match node {
+                    GraphPass::RayGen => {
+                        if photons {
+                            encoder.clear_buffer(
+                                &targets.transport_buffer,
+                                0,
+                                NonZeroU64::new(PHOTON_MAP_HEADER_SIZE),
+                            );
+                            let mut pass =
+                                self.begin_path_pass(&mut encoder, targets, "Photon pass");
+                            pass.set_bind_group(0, &targets.transport_bind_group, &[]);
+                            self.set_kernel(&mut pass, &pipelines.photon_trace, &uniforms);
+                            let invocations =
+                                self.shaders.workgroup_size * self.shaders.workgroup_size;
+                            pass.dispatch_workgroups(
+                                targets
+                                    .photon_capacity
+                                    .div_ceil(invocations)
+                                    .min(self.device.limits().max_compute_workgroups_per_dimension),
+                                1,
+                                1,
+                            );
+                        }
+
+                        let mut pass =
+                            self.begin_path_pass(&mut encoder, targets, "Ray generation pass");
+                        if let Some(bind_group) = &targets.lightmap_bind_group {
+                            pass.set_bind_group(0, bind_group, &[]);
+                            self.dispatch_kernel(
+                                &mut pass,
+                                &pipelines.lightmap_gen,
+                                &uniforms,
+                                rect,
+                            );
+                            pass.set_bind_group(0, &targets.bind_group, &[]);
+                        } else {
+                            self.dispatch_kernel(&mut pass, &pipelines.ray_gen, &uniforms, rect);
+                        }
+                        if bidirectional && nee {
+                            // At the wavelengths of the camera paths.
+                            pass.set_bind_group(0, &targets.transport_bind_group, &[]);
+                            self.dispatch_kernel(
+                                &mut pass,
+                                &pipelines.light_trace,
+                                &uniforms,
+                                rect,
+                            );
+                        }
+                        drop(pass);
+                        self.copy_dispatch_args(&mut encoder, targets);
+                    }
+                    GraphPass::Shade => {
+                        for bounce in 0..=max_bounces {
+                            // Paths terminating at the last bounce don't have to be compacted away.
+                            let compact = bounce < max_bounces;
+
+                            let mut pass =
+                                self.begin_path_pass(&mut encoder, targets, "Bounce pass");
+                            if let (Some((_, bind_group)), 0) = (visibility, bounce) {
+                                pass.set_bind_group(0, bind_group, &[]);
+                                let kernel = &pipelines.primary_hit;
+                                self.dispatch_kernel_live(&mut pass, kernel, &uniforms, targets);
+                                pass.set_bind_group(0, &targets.bind_group, &[]);
+                            } else if self.persistent_threads {
+                                self.set_kernel(&mut pass, &pipelines.trace_persistent, &uniforms);
+                                pass.dispatch_workgroups(PERSISTENT_WORKGROUPS, 1, 1);
+                            } else {
+                                self.dispatch_kernel_live(
+                                    &mut pass,
+                                    &pipelines.trace,
+                                    &uniforms,
+                                    targets,
+                                );
+                            }
+                            if self.sort_paths {
+                                // Sorting keeps the count, so the dispatch arguments stay valid.
+                                self.dispatch_kernel_live(
+                                    &mut pass,
+                                    &pipelines.sort_count,
+                                    &uniforms,
+                                    targets,
+                                );
+                                self.set_kernel(&mut pass, &pipelines.sort_scan, &uniforms);
+                                pass.dispatch_workgroups(1, 1, 1);
+                                self.dispatch_kernel_live(
+                                    &mut pass,
+                                    &pipelines.sort_scatter,
+                                    &uniforms,
+                                    targets,
+                                );
+                                self.set_kernel(&mut pass, &pipelines.dispatch_args, &uniforms);
+                                pass.dispatch_workgroups(1, 1, 1);
+                            }
+                            if let (Some(bind_group), 1) = (restir_indirect, bounce) {
+                                pass.set_bind_group(0, bind_group, &[]);
+                                let kernel = &pipelines.restir_gi_sample;
+                                self.dispatch_kernel_live(&mut pass, kernel, &uniforms, targets);
+                                pass.set_bind_group(0, &targets.bind_group, &[]);
+                            }
+                            self.dispatch_kernel_live(&mut pass, shade, &uniforms, targets);
+                            if let Some(light_paths) = light_paths {
+                                pass.set_bind_group(0, &targets.transport_bind_group, &[]);
+                                self.dispatch_kernel_live(
+                                    &mut pass,
+                                    light_paths,
+                                    &uniforms,
+                                    targets,
+                                );
+                                pass.set_bind_group(0, &targets.bind_group, &[]);
+                            }
+                            // Only the surfaces the camera sees are resampled.
+                            if let (Some(restir), 0) = (&restir, bounce) {
+                                pass.set_bind_group(0, &restir.direct, &[]);
+                                for kernel in
+                                    [&pipelines.restir_temporal, &pipelines.restir_spatial]
+                                {
+                                    self.dispatch_kernel_live(
+                                        &mut pass, kernel, &uniforms, targets,
+                                    );
+                                }
+                                pass.set_bind_group(0, &targets.bind_group, &[]);
+                            }
+                            if shadow_rays {
+                                self.dispatch_kernel_live(
+                                    &mut pass,
+                                    &pipelines.shadow,
+                                    &uniforms,
+                                    targets,
+                                );
+                            }
+                            if guiding || final_gather {
+                                let kernel = if guiding {
+                                    &pipelines.guide
+                                } else {
+                                    &pipelines.final_gather
+                                };
+                                pass.set_bind_group(0, &targets.transport_bind_group, &[]);
+                                self.dispatch_kernel_live(&mut pass, kernel, &uniforms, targets);
+                                pass.set_bind_group(0, &targets.bind_group, &[]);
+                            }
+                            if compact {
+                                self.dispatch_kernel_live(
+                                    &mut pass,
+                                    &pipelines.compact,
+                                    &uniforms,
+                                    targets,
+                                );
+                                self.set_kernel(&mut pass, &pipelines.dispatch_args, &uniforms);
+                                pass.dispatch_workgroups(1, 1, 1);
+                            }
+                            drop(pass);
+
+                            if compact {
+                                self.copy_dispatch_args(&mut encoder, targets);
+                            }
+                        }
+                    }
+                    GraphPass::Accumulate => {
+                        let mut pass =
+                            self.begin_path_pass(&mut encoder, targets, "Accumulation pass");
+                        if let Some(bind_group) = restir_indirect {
+                            pass.set_bind_group(0, bind_group, &[]);
+                            for kernel in
+                                [&pipelines.restir_gi_temporal, &pipelines.restir_gi_spatial]
+                            {
+                                self.dispatch_kernel(&mut pass, kernel, &uniforms, rect);
+                            }
+                            pass.set_bind_group(0, &targets.bind_group, &[]);
+                        }
+                        self.dispatch_kernel(&mut pass, &pipelines.accumulate, &uniforms, rect);
+                        drop(pass);
+                    }
+                    GraphPass::Custom(name) => {
+                        let mut context = self.pass_context(
+                            PassStage::AfterAccumulation,
+                            &mut encoder,
+                            targets,
+                            settings,
+                            rect,
+                            Some(sample),
+                        );
+                        context.pass = Some(name);
+                        self.run_graph_pass(&mut context);
+                    }
+                    GraphPass::Tonemap | GraphPass::Readback => {
+                        unreachable!("Validated graphs only tonemap and read back tiles")
+                    }
+                }
+            }
+
+            encoder.pop_debug_group();
+            self.run_pass_callbacks(&mut self.pass_context(
+                PassStage::AfterAccumulation,
+                &mut encoder,
+                targets,
+                settings,
+                rect,
+                Some(sample),
+            ));
+
+            command_buffers.push(encoder.finish());
+            // Uniforms written to a buffer only reach the submissions after the write.
+            if !self.shaders.push_constants {
+                self.queue.submit(command_buffers.drain(..));
+            }
+        }
+
+        if let Some(timestamps) = &targets.timestamps {
+            command_buffers.push(self.timestamp_commands(timestamps, 1));
+        }
+        if !command_buffers.is_empty() {
+            self.queue.submit(command_buffers);
+        }
+        self.read_texture_feedback(targets);
+    }
+
+    /// Commands writing the time the GPU gets to them into timestamp `index` of `query_set`.
+    fn timestamp_commands(&self, query_set: &QuerySet, index: u32) -> CommandBuffer {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Timestamp command encoder"),
+            });
+        encoder.write_timestamp(query_set, index);
+        encoder.finish()
+    }
+
+    /// Starts reading back the texture pages the samples dispatched so far asked for, unless
+    /// the previous ones are still being read back or the textures all fit in their cache.
+    fn read_texture_feedback(&self, targets: &TileTargets) {
+        let feedback = &targets.feedback;
+        if !targets.scene.streams_textures() || feedback.pending.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Texture feedback command encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            feedback.texture.as_image_copy(),
+            ImageCopyBuffer {
+                buffer: &feedback.buffer,
+                layout: ImageDataLayout {
+                    bytes_per_row: NonZeroU32::new(targets.padded_bytes_per_row),
+                    rows_per_image: None,
+                    offset: 0,
+                },
+            },
+            wgpu::Extent3d {
+                width: feedback.tile_size,
+                height: feedback.tile_size,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let mapped = feedback.mapped.clone();
+        feedback
+            .buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                mapped.store(result.is_ok(), Ordering::Release)
+            });
+    }
+
+    /// Streams in the texture pages of the last [feedback](Self::read_texture_feedback) once
+    /// it's been read back, and clears the requests for the samples dispatched next.
+    fn stream_textures(&self, targets: &TileTargets) {
+        let feedback = &targets.feedback;
+        if !feedback.mapped.swap(false, Ordering::AcqRel) {
+            return;
+        }
+
+        let row_bytes = feedback.tile_size as usize * 4;
+        let requests = feedback
+            .buffer
+            .slice(..)
+            .get_mapped_range()
+            .chunks_exact(targets.padded_bytes_per_row as usize)
+            .flat_map(|row| row[..row_bytes].chunks_exact(4))
+            .map(|request| u32::from_le_bytes([request[0], request[1], request[2], request[3]]))
+            .filter_map(|request| request.checked_sub(1))
+            .collect();
+        feedback.buffer.unmap();
+        feedback.pending.store(false, Ordering::Release);
+
+        targets.scene.stream_textures(&self.queue, requests);
+        self.queue.write_texture(
+            feedback.texture.as_image_copy(),
+            &vec![0; row_bytes * feedback.tile_size as usize],
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(row_bytes as u32),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: feedback.tile_size,
+                height: feedback.tile_size,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Records the passes of the [graph](RenderSettings::graph) that run once the samples of
+    /// `rect` have been accumulated, resolving them into the output texture and copying that
+    /// to `readback`.
+    fn encode_tile_passes(
+        &self,
+        encoder: &mut CommandEncoder,
+        targets: &TileTargets,
+        settings: &RenderSettings,
+        rect: TileRect,
+        readback: ImageCopyBuffer<'_>,
+    ) {
+        let uniforms = FrameUniforms::new(settings, targets, rect, 0);
+        self.write_frame_uniforms(targets, &uniforms);
+
+        let mut stage = PassStage::BeforeResolve;
+        for node in &settings.graph.tile {
+            match node {
+                GraphPass::Tonemap => {
+                    self.run_pass_callbacks(&mut self.pass_context(
+                        PassStage::BeforeResolve,
+                        encoder,
+                        targets,
+                        settings,
+                        rect,
+                        None,
+                    ));
+
+                    encoder.push_debug_group("Resolve");
+                    {
+                        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                            label: Some("Resolve compute pass"),
+                        });
+
+                        pass.set_bind_group(0, &targets.bind_group, &[]);
+                        self.dispatch_kernel(&mut pass, &self.pipelines.resolve, &uniforms, rect);
+                    }
+                    encoder.pop_debug_group();
+
+                    stage = PassStage::AfterResolve;
+                    self.run_pass_callbacks(
+                        &mut self.pass_context(stage, encoder, targets, settings, rect, None),
+                    );
+                }
+                GraphPass::Readback => {
+                    encoder.push_debug_group("Readback");
+                    encoder.copy_texture_to_buffer(
+                        targets.out_tex.as_image_copy(),
+                        readback.clone(),
+                        rect.extent(),
+                    );
+                    encoder.pop_debug_group();
+                }
+                GraphPass::Custom(name) => {
+                    let mut context =
+                        self.pass_context(stage, encoder, targets, settings, rect, None);
+                    context.pass = Some(name);
+                    self.run_graph_pass(&mut context);
+                }
+                GraphPass::RayGen | GraphPass::Shade | GraphPass::Accumulate => {
+                    unreachable!("Validated graphs only trace samples before the tile passes")
+                }
+            }
+        }
+    }
+
+    /// Resolves the samples accumulated for `rect` and reads the result back, calling `on_row`
+    /// with the index and the tightly packed RGBA8 pixels of every row of the tile.
+    async fn read_tile(
+        &self,
+        targets: &TileTargets,
+        settings: &RenderSettings,
+        rect: TileRect,
+        mut on_row: impl FnMut(usize, &[u8]),
+    ) {
+        let readback = self.begin_read_tile(targets, settings, rect, 0);
+        self.finish_read_tile(targets, readback, |event| {
+            if let TileEvent::Row { index, pixels, .. } = event {
+                on_row(index, pixels);
+            }
+        })
+        .await;
+    }
+
+    /// Resolves the samples accumulated for `rect` and starts copying the result into the
+    /// staging buffer `slot`, without waiting for it.
+    #[instrument(skip(self, targets, settings))]
+    fn begin_read_tile(
+        &self,
+        targets: &TileTargets,
+        settings: &RenderSettings,
+        rect: TileRect,
+        slot: usize,
+    ) -> PendingReadback {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Resolve command encoder"),
+            });
+
+        let readback = ImageCopyBuffer {
+            buffer: &targets.out_buffers[slot],
+            layout: ImageDataLayout {
+                bytes_per_row: NonZeroU32::new(targets.padded_bytes_per_row),
+                rows_per_image: NonZeroU32::new(rect.height),
+                offset: 0,
+            },
+        };
+        self.encode_tile_passes(&mut encoder, targets, settings, rect, readback);
+
+        info_span!("submit").in_scope(|| self.queue.submit(Some(encoder.finish())));
+
+        let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+        targets.out_buffers[slot]
+            .slice(..targets.padded_bytes_per_row as u64 * rect.height as u64)
+            .map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+
+        PendingReadback {
+            rect,
+            slot,
+            receiver,
+        }
+    }
+
+    /// Waits for a readback started by [`begin_read_tile`](Self::begin_read_tile) and hands its
+    /// rows to `on_event`, followed by [`TileEvent::Finished`].
+    async fn finish_read_tile(
+        &self,
+        targets: &TileTargets,
+        readback: PendingReadback,
+        mut on_event: impl FnMut(TileEvent<'_>),
+    ) {
+        let PendingReadback {
+            rect,
+            slot,
+            receiver,
+        } = readback;
+
+        async {
+            // Usually the mapping already completed while the following tile was rendering,
+            // only block on the GPU if it didn't.
+            self.device.poll(Maintain::Poll);
+            let result = match receiver.receive().now_or_never() {
+                Some(result) => result,
+                None => {
+                    self.device.poll(Maintain::Wait);
+                    receiver.receive().await
+                }
+            };
+            let Some(Ok(())) = result else {
+                panic!("Could not map buffer");
+            };
+
+            let buffer = &targets.out_buffers[slot];
+            let data = buffer
+                .slice(..targets.padded_bytes_per_row as u64 * rect.height as u64)
+                .get_mapped_range();
+            let row_bytes = rect.width as usize * 4;
+            for (index, row) in data
+                .chunks_exact(targets.padded_bytes_per_row as usize)
+                .enumerate()
+            {
+                on_event(TileEvent::Row {
+                    rect,
+                    index,
+                    pixels: &row[..row_bytes],
+                });
+            }
+            drop(data);
+
+            buffer.unmap();
+        }
+        .instrument(info_span!("readback", ?rect))
+        .await;
+
+        on_event(TileEvent::Finished(rect));
+    }
+
+    /// Copies the first `size` bytes of `buffer` back to the host.
+    #[instrument(skip(self, buffer))]
+    async fn read_buffer(&self, buffer: &Buffer, size: u64) -> Vec<u8> {
+        let staging_buffer = self.device.create_buffer(&BufferDescriptor {
+            label: Some("Staging buffer"),
+            size,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Buffer readback command encoder"),
+            });
+        encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = staging_buffer.slice(..);
+        self.map_read(slice).await;
+        let vec = slice.get_mapped_range().to_vec();
+        staging_buffer.unmap();
+
+        vec
+    }
+
+    /// Maps `slice` for reading, waiting for the GPU to be done with it.
+    async fn map_read(&self, slice: BufferSlice<'_>) {
+        let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+        slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+
+        self.device.poll(Maintain::Wait);
+
+        let Some(Ok(())) = receiver.receive().await else {
+            panic!("Could not map buffer");
+        };
+    }
+}
+
+/// What happened to a tile while rendering it with
+/// [`RaytracingRenderer::render_tiles_pipelined`].
+enum TileEvent<'a> {
+    /// `count` more samples per pixel have been accumulated.
+    Samples { rect: TileRect, count: u32 },
+    /// Row `index` of the tile has been read back.
+    Row {
+        rect: TileRect,
+        index: usize,
+        pixels: &'a [u8],
+    },
+    /// All the rows of the tile have been read back.
+    Finished(TileRect),
+}
+
+/// Image of a job of [`RaytracingRenderer::render_jobs`] waiting for its tiles.
+struct QueuedImage {
+    index: usize,
+    width: u32,
+    tiles_left: usize,
+    pixels: Vec<u8>,
+}
+
+/// Tile readback submitted to the GPU but not yet copied out.
+struct PendingReadback {
+    rect: TileRect,
+    slot: usize,
+    receiver: OneshotReceiver<Result<(), BufferAsyncError>>,
+}
+
+/// Everything the pipelines of a renderer are built from.
+#[derive(Clone)]
+struct ShaderConfig {
+    sources: ShaderSources,
+    defines: BTreeMap<String, String>,
+    hooks: BTreeMap<ShaderHook, String>,
+    /// Side of the square workgroups of the compute shaders.
+    workgroup_size: u32,
+    /// Whether the shaders read `FrameUniforms` from push constants rather than a buffer.
+    push_constants: bool,
+}
+
+impl ShaderConfig {
+    /// Source of file `name` with the hooks spliced in and the directives expanded.
+    fn preprocess(&self, name: &str) -> Result<String, ShaderError> {
+        let mut sources = self.sources.clone();
+        for (&hook, source) in &self.hooks {
+            sources.set_hook(hook, source);
+        }
+
+        // Dispatches are sized on the host, so the shaders can't pick their own.
+        let mut defines = self.defines.clone();
+        defines.insert(
+            "WORKGROUP_SIZE".to_owned(),
+            format!("{}u", self.workgroup_size),
+        );
+        defines.insert(
+            "WORKGROUP_INVOCATIONS".to_owned(),
+            format!("{}u", self.workgroup_size * self.workgroup_size),
+        );
+        if self.push_constants {
+            defines.insert("PUSH_CONSTANTS".to_owned(), String::new());
+        }
+
+        sources.preprocess(name, &defines)
+    }
+}
+
+/// Compute pipelines of a renderer, one for every kernel.
+struct Pipelines {
+    ray_gen: ComputePipeline,
+    trace: ComputePipeline,
+    trace_persistent: ComputePipeline,
+    primary_hit: ComputePipeline,
+    sort_count: ComputePipeline,
+    sort_scan: ComputePipeline,
+    sort_scatter: ComputePipeline,
+    shade: ComputePipeline,
+    shadow: ComputePipeline,
+    photon_trace: ComputePipeline,
+    gather: ComputePipeline,
+    light_trace: ComputePipeline,
+    connect: ComputePipeline,
+    restir_temporal: ComputePipeline,
+    restir_spatial: ComputePipeline,
+    restir_gi_sample: ComputePipeline,
+    restir_gi_temporal: ComputePipeline,
+    restir_gi_spatial: ComputePipeline,
+    guide: ComputePipeline,
+    final_gather: ComputePipeline,
+    lightmap_gen: ComputePipeline,
+    trace_rays: ComputePipeline,
+    ambient_occlusion: ComputePipeline,
+    debug: ComputePipeline,
+    compact: ComputePipeline,
+    dispatch_args: ComputePipeline,
+    accumulate: ComputePipeline,
+    resolve: ComputePipeline,
+}
+
+/// Builds the pipelines of all the kernels.
+fn create_pipelines(
+    device: &Device,
+    bind_group_layout: &BindGroupLayout,
+    scene_bind_group_layout: &BindGroupLayout,
+    queue_bind_group_layout: &BindGroupLayout,
+    transport_bind_group_layout: &BindGroupLayout,
+    ray_bind_group_layout: &BindGroupLayout,
+    config: &ShaderConfig,
+) -> Result<Pipelines, ShaderError> {
+    let push_constant_ranges: &[_] = if config.push_constants {
+        &[PushConstantRange {
+            stages: ShaderStages::COMPUTE,
+            range: 0..FRAME_UNIFORMS_SIZE,
+        }]
+    } else {
+        &[]
+    };
+
+    // Shared by all the path tracing kernels, so that bind groups and push constants carry
+    // over from one dispatch to the next.
+    let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("Path tracing pipeline layout"),
+        bind_group_layouts: &[
+            bind_group_layout,
+            scene_bind_group_layout,
+            queue_bind_group_layout,
+        ],
+        push_constant_ranges,
+    });
+
+    // The photon map or the paths from the lights take the place of the frame bind group,
+    // keeping the push constants.
+    let transport_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("Photon mapping pipeline layout"),
+        bind_group_layouts: &[
+            transport_bind_group_layout,
+            scene_bind_group_layout,
+            queue_bind_group_layout,
+        ],
+        push_constant_ranges,
+    });
+
+    // Rays from the application need neither the frame uniforms nor the paths.
+    let ray_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("Ray pipeline layout"),
+        bind_group_layouts: &[ray_bind_group_layout, scene_bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    let resolve_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("Resolve pipeline layout"),
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges,
+    });
+
+    let create_pipeline = |name: &str, layout| -> Result<ComputePipeline, ShaderError> {
+        let source = config.preprocess(name)?;
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some(name),
+            source: ShaderSource::Wgsl(source.into()),
+        });
+        Ok(device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some(name),
+            layout: Some(layout),
+            module: &module,
+            entry_point: "main",
+        }))
+    };
+
+    Ok(Pipelines {
+        ray_gen: create_pipeline(ShaderSources::RAY_GEN, &pipeline_layout)?,
+        trace: create_pipeline(ShaderSources::TRACE, &pipeline_layout)?,
+        trace_persistent: create_pipeline(ShaderSources::TRACE_PERSISTENT, &pipeline_layout)?,
+        primary_hit: create_pipeline(ShaderSources::PRIMARY_HIT, &transport_pipeline_layout)?,
+        sort_count: create_pipeline(ShaderSources::SORT_COUNT, &pipeline_layout)?,
+        sort_scan: create_pipeline(ShaderSources::SORT_SCAN, &pipeline_layout)?,
+        sort_scatter: create_pipeline(ShaderSources::SORT_SCATTER, &pipeline_layout)?,
+        shade: create_pipeline(ShaderSources::SHADE, &pipeline_layout)?,
+        shadow: create_pipeline(ShaderSources::SHADOW, &pipeline_layout)?,
+        photon_trace: create_pipeline(ShaderSources::PHOTON_TRACE, &transport_pipeline_layout)?,
+        gather: create_pipeline(ShaderSources::GATHER, &transport_pipeline_layout)?,
+        light_trace: create_pipeline(ShaderSources::LIGHT_TRACE, &transport_pipeline_layout)?,
+        connect: create_pipeline(ShaderSources::CONNECT, &transport_pipeline_layout)?,
+        restir_temporal: create_pipeline(
+            ShaderSources::RESTIR_TEMPORAL,
+            &transport_pipeline_layout,
+        )?,
+        restir_spatial: create_pipeline(ShaderSources::RESTIR_SPATIAL, &transport_pipeline_layout)?,
+        restir_gi_sample: create_pipeline(
+            ShaderSources::RESTIR_GI_SAMPLE,
+            &transport_pipeline_layout,
+        )?,
+        restir_gi_temporal: create_pipeline(
+            ShaderSources::RESTIR_GI_TEMPORAL,
+            &transport_pipeline_layout,
+        )?,
+        restir_gi_spatial: create_pipeline(
+            ShaderSources::RESTIR_GI_SPATIAL,
+            &transport_pipeline_layout,
+        )?,
+        guide: create_pipeline(ShaderSources::GUIDE, &transport_pipeline_layout)?,
+        final_gather: create_pipeline(ShaderSources::FINAL_GATHER, &transport_pipeline_layout)?,
+        lightmap_gen: create_pipeline(ShaderSources::LIGHTMAP_GEN, &transport_pipeline_layout)?,
+        trace_rays: create_pipeline(ShaderSources::TRACE_RAYS, &ray_pipeline_layout)?,
+        ambient_occlusion: create_pipeline(ShaderSources::AMBIENT_OCCLUSION, &pipeline_layout)?,
+        debug: create_pipeline(ShaderSources::DEBUG, &pipeline_layout)?,
+        compact: create_pipeline(ShaderSources::COMPACT, &pipeline_layout)?,
+        dispatch_args: create_pipeline(ShaderSources::DISPATCH_ARGS, &pipeline_layout)?,
+        accumulate: create_pipeline(ShaderSources::ACCUMULATE, &pipeline_layout)?,
+        resolve: create_pipeline(ShaderSources::RESOLVE, &resolve_pipeline_layout)?,
+    })
+}
+
+/// Bytes of what `settings` trace from the lights or learn about them for `path_count`
+/// paths.
+fn transport_size(settings: &RenderSettings, path_count: u64) -> u64 {
+    match settings.integrator {
+        Integrator::PhotonMapping { photons, .. } if photons > 0 => {
+            PHOTON_MAP_HEADER_SIZE + photons as u64 * PHOTON_SIZE
+        }
+        Integrator::Bidirectional => path_count * LIGHT_VERTICES * LIGHT_VERTEX_SIZE,
+        Integrator::PathGuiding => GUIDE_HISTOGRAMS_SIZE + path_count * GUIDE_RECORD_SIZE,
+        Integrator::FinalGather { .. } => CACHE_ENTRIES_SIZE + path_count * CACHE_RECORD_SIZE,
+        _ => 4,
+    }
+}
+
+/// Whether renders with `settings` accumulate in half precision, which is only asked for with
+/// sample counts half precision can still count exactly.
+pub(crate) fn half_accumulation(settings: &RenderSettings) -> bool {
+    settings.half_precision_accumulation
+        && settings.samples_per_pixel <= HALF_ACCUMULATION_MAX_SAMPLES
+}
+
+/// Distance in bytes between the rows of an image `width` pixels wide written by
+/// [`RaytracingRenderer::render_into_buffer`].
+pub fn buffer_bytes_per_row(width: u32) -> u32 {
+    padded_bytes_per_row(width * 4)
+}
+
+/// Rounds `bytes_per_row` up to the alignment buffer copies of textures require.
+pub(crate) fn padded_bytes_per_row(bytes_per_row: u32) -> u32 {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    bytes_per_row.div_ceil(align) * align
 }
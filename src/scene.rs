@@ -0,0 +1,1845 @@
+//! Scene descriptions.
+//!
+//! Scenes are plain data, loaded from [RON](https://github.com/ron-rs/ron) or JSON files so that
+//! they can be written by hand or exported by other tools:
+//!
+//! ```ron
+//! (
+//!     camera: (position: (0.0, 1.0, 3.0), look_at: (0.0, 0.5, 0.0), fov: 40.0),
+//!     materials: [
+//!         (name: "red", base_color: (0.8, 0.1, 0.1)),
+//!         (name: "mirror", metallic: 1.0, roughness: 0.0),
+//!     ],
+//!     objects: [
+//!         (shape: Sphere(radius: 0.5), material: Some("red"), transform: (translation: (0.0, 0.5, 0.0))),
+//!         (shape: Mesh(path: "floor.obj"), material: Some("mirror")),
+//!         (shape: Sphere(radius: 0.2), material: Some("red"), overrides: (base_color: Some((0.1, 0.1, 0.8)))),
+//!     ],
+//!     nodes: [(
+//!         transform: (translation: (1.0, 0.0, 0.0), rotation: (0.0, 30.0, 0.0)),
+//!         objects: [(shape: Mesh(path: "table.obj"))],
+//!         children: [(transform: (translation: (0.0, 0.8, 0.0)), objects: [(shape: Mesh(path: "lamp.obj"))])],
+//!     )],
+//!     lights: [Point(position: (2.0, 4.0, 2.0), intensity: 20.0)],
+//!     fog: Some((scattering: (0.02, 0.02, 0.02), anisotropy: 0.6)),
+//!     sky: Texture((path: "studio.png", intensity: 2.0)),
+//!     background: Some(Color((0.05, 0.05, 0.05))),
+//!     settings: (width: 1280, height: 720, samples_per_pixel: 64),
+//! )
+//! ```
+//!
+//! Every field has a default, so only what differs from it has to be written out.
+
+#[cfg(feature = "fs")]
+use std::fs;
+use std::{
+    error::Error,
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use cgmath::{Deg, InnerSpace, Matrix3, Matrix4, Point3, Quaternion, Rotation3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    animation::{self, Keyframe, TransformChannels},
+    settings::RenderSettings,
+    sun,
+};
+
+/// Everything needed to render an image.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scene {
+    pub camera: Camera,
+    /// Renders the scene around the camera instead of around the origin of the world, to stay
+    /// precise at the camera in scenes too large for it otherwise, see [`Scene::origin`].
+    pub camera_relative: bool,
+    pub materials: Vec<Material>,
+    pub objects: Vec<Object>,
+    /// Hierarchies of objects placed relative to each other, as imported from other tools.
+    pub nodes: Vec<Node>,
+    pub lights: Vec<Light>,
+    /// Objects some of the `lights` are limited to or kept off of, see [`LightLink`].
+    pub light_links: Vec<LightLink>,
+    /// Participating medium between the surfaces, without one rays travel through vacuum.
+    pub fog: Option<Fog>,
+    /// Light arriving from outside the scene, along the rays that don't hit anything.
+    pub sky: Sky,
+    /// What camera rays that don't hit anything see instead of `sky`, which still lights the
+    /// scene and shows in reflections and refractions. A physical background is seen with the
+    /// atmosphere of a physical `sky` if there's one.
+    pub background: Option<Sky>,
+    /// Openings the sky is seen through, see [`Portal`].
+    pub portals: Vec<Portal>,
+    /// Planes cutting away everything behind them, see [`ClipPlane`].
+    pub clip_planes: Vec<ClipPlane>,
+    /// Sun at its position for a place and time, added to `lights` and setting the direction
+    /// of the sun of a physical `sky`.
+    pub sun: Option<Sun>,
+    /// Settings the scene is meant to be rendered with.
+    pub settings: RenderSettings,
+    /// Keyframes overriding `camera`, see [`Scene::at`].
+    pub camera_animation: Vec<Keyframe<Camera>>,
+    /// Parts of the scene rendered on their own for compositing, see [`RenderLayer`].
+    pub layers: Vec<RenderLayer>,
+}
+
+/// Pinhole camera.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Camera {
+    pub position: [f32; 3],
+    /// Point the camera is aimed at.
+    pub look_at: [f32; 3],
+    /// Direction that's up in the image, it doesn't have to be perpendicular to the view.
+    pub up: [f32; 3],
+    /// Vertical field of view in degrees.
+    pub fov: f32,
+    /// Shift of the lens parallel to the image, right and up in heights of the image. It moves
+    /// the image without turning the camera, e.g. up to frame a tall building from a level
+    /// camera while keeping its verticals parallel.
+    pub shift: [f32; 2],
+}
+
+impl Camera {
+    /// View matrix of a raster camera looking the same way, into a right-handed view space
+    /// looking down -Z. See [`DepthBuffer::projection`](crate::settings::DepthBuffer::projection)
+    /// for its projection.
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(
+            Point3::from(self.position),
+            Point3::from(self.look_at),
+            Vector3::from(self.up),
+        )
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            position: [0.0, 0.0, 0.0],
+            look_at: [0.0, 0.0, -1.0],
+            up: [0.0, 1.0, 0.0],
+            fov: 90.0,
+            shift: [0.0, 0.0],
+        }
+    }
+}
+
+/// Surface appearance, referenced by objects through its name.
+///
+/// Materials are principled BSDFs in the spirit of Disney's and glTF's metallic-roughness
+/// model: a blend of a metal with a `base_color` tint by `metallic`, and of what's left, glass by
+/// `transmission` and otherwise a dielectric with a specular coat over diffuse reflection.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Material {
+    pub name: String,
+    /// Linear RGB albedo of diffuse reflection, or tint of metallic reflection and refraction.
+    pub base_color: [f32; 3],
+    /// Image `base_color` is multiplied with, mapped onto meshes by their texture
+    /// coordinates. Relative paths are relative to the scene file.
+    pub base_color_texture: Option<PathBuf>,
+    /// How the texels of `base_color_texture` are encoded, sRGB like most color images unless
+    /// it was baked in linear space.
+    pub base_color_texture_space: ColorSpace,
+    /// Offset added to the texture coordinates `base_color_texture` is looked up at.
+    pub texture_offset: [f32; 2],
+    /// Turns the texture coordinates are rotated by around the center of the texture,
+    /// counterclockwise, before the offset is added.
+    pub texture_rotation: f32,
+    /// How `base_color_texture` changes over the animation of the scene, see [`Scene::at`].
+    pub texture_animation: Option<TextureAnimation>,
+    pub metallic: f32,
+    /// Blurriness of specular reflection and refraction, from mirror-like at zero up to one.
+    pub roughness: f32,
+    /// How much more `roughness` blurs specular reflection and refraction across the tangent
+    /// than along it, from none at zero up to one, e.g. for brushed metal. Tangents follow the
+    /// first texture coordinate of meshes and the longitude of spheres.
+    pub anisotropic: f32,
+    /// Rotation of the tangent around the normal in turns.
+    pub anisotropic_rotation: f32,
+    /// Strength of the specular coat of dielectrics, the default of 0.5 reflecting 4% of the light
+    /// head-on like most of them do.
+    pub specular: f32,
+    /// Index of refraction of transmissive materials.
+    pub ior: f32,
+    /// Abbe number of transmissive materials, the lower the more their index of refraction
+    /// varies with wavelength, splitting white light into rainbows when rendered with the
+    /// `SPECTRAL` shader define. Zero for none, crown glass has around 60 and flint glass 35.
+    pub abbe_number: f32,
+    pub transmission: f32,
+    /// Retro-reflective sheen of cloth at grazing angles, on top of diffuse reflection.
+    pub sheen: f32,
+    /// How much the sheen takes on the hue of `base_color` rather than being white.
+    pub sheen_tint: f32,
+    /// Weight of a clear dielectric layer over the rest of the material, like the lacquer of car
+    /// paint or varnished wood.
+    pub clearcoat: f32,
+    /// Blurriness of the reflections of the clear coat.
+    pub clearcoat_roughness: f32,
+    /// Index of refraction of the clear coat, setting how much it reflects.
+    pub clearcoat_ior: f32,
+    /// Thickness in nanometers of a film over the surface whose interference colors specular
+    /// reflection, like soap bubbles or oil slicks, zero for none. Films of a few hundred
+    /// nanometers show the most vivid colors.
+    pub thin_film_thickness: f32,
+    /// Index of refraction of the thin film.
+    pub thin_film_ior: f32,
+    /// Fraction of the diffuse light that enters the surface and scatters around below it
+    /// before leaving again, e.g. for skin, wax or marble. Needs a closed mesh to walk in.
+    pub subsurface: f32,
+    /// Linear RGB scattering coefficient of the medium below the surface, the chance per unit
+    /// length of light bouncing off a particle.
+    pub subsurface_scattering: [f32; 3],
+    /// Linear RGB absorption coefficient of the medium below the surface, the chance per unit
+    /// length of light getting absorbed, which tints the light coming back out.
+    pub subsurface_absorption: [f32; 3],
+    /// Linear RGB radiance emitted by the surface.
+    pub emission: [f32; 3],
+    /// Probability of light scattering off the fibers of [curves](Shape::Curves) like off hair
+    /// rather than through the lobes above, after "A Practical and Controllable Hair and Fur
+    /// Model for Production Path Tracing" (Chiang et al. 2016). Hair takes its color from
+    /// `base_color`, its longitudinal roughness from `roughness` and its index of refraction
+    /// from `ior`, 1.55 for human hair.
+    pub hair: f32,
+    /// How much hair blurs light around its fibers, from 0 to 1.
+    pub hair_azimuthal_roughness: f32,
+    /// Tilt in degrees of the scales on the surface of hair fibers, which shifts its
+    /// highlights along the strands.
+    pub hair_scale_angle: f32,
+    /// Fraction of the surface that's there, the rest of it is cut out: rays pass through it,
+    /// camera and shadow rays alike, e.g. to render foliage or fences with a single quad.
+    pub opacity: f32,
+    /// Makes the surface a stand-in for the ground or walls of a photographic backplate: the
+    /// camera only sees the shadows and reflections of the rest of the scene on it, with the
+    /// shadows in the alpha of the image and the reflections as light added over the backplate.
+    /// Other rays see it like any other surface, so that it still bounces light onto the scene.
+    ///
+    /// Shadows are those of the lights and portals, sampled by `NEE` when path tracing.
+    pub shadow_catcher: bool,
+    /// Which of the transmissive materials of overlapping objects fills the space they share,
+    /// the one of the highest priority, or of the object entered last of those. The surfaces of
+    /// the others are skipped there, and the ones left refract relative to the material on
+    /// their other side, e.g. for liquid in a glass modeled a little into its walls with a higher
+    /// priority than the glass. Skipped surfaces still count as bounces.
+    pub priority: u32,
+    /// Grayscale image of the heights the surface of meshes is moved out to along their normals,
+    /// mapped onto them by their texture coordinates. Meshes are tessellated finer to follow it
+    /// when the scene is built. Relative paths are relative to the scene file.
+    pub displacement_texture: Option<PathBuf>,
+    /// Distance in object space white moves the surface out by, black leaves it in place.
+    pub displacement_scale: f32,
+    /// How many times every triangle of displaced meshes is split into four.
+    pub displacement_subdivisions: u32,
+    /// Id handed to the [scatter hook](crate::shader::ShaderHook::Scatter), which replaces the
+    /// lobes above when one is registered.
+    pub custom: Option<u32>,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            base_color: [0.5, 0.5, 0.5],
+            base_color_texture: None,
+            base_color_texture_space: ColorSpace::Srgb,
+            texture_offset: [0.0, 0.0],
+            texture_rotation: 0.0,
+            texture_animation: None,
+            metallic: 0.0,
+            roughness: 0.5,
+            anisotropic: 0.0,
+            anisotropic_rotation: 0.0,
+            specular: 0.5,
+            ior: 1.5,
+            abbe_number: 0.0,
+            transmission: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.03,
+            clearcoat_ior: 1.5,
+            thin_film_thickness: 0.0,
+            thin_film_ior: 1.33,
+            subsurface: 0.0,
+            subsurface_scattering: [10.0, 10.0, 10.0],
+            subsurface_absorption: [0.1, 0.1, 0.1],
+            emission: [0.0, 0.0, 0.0],
+            hair: 0.0,
+            hair_azimuthal_roughness: 0.3,
+            hair_scale_angle: 2.0,
+            opacity: 1.0,
+            shadow_catcher: false,
+            priority: 0,
+            displacement_texture: None,
+            displacement_scale: 0.1,
+            displacement_subdivisions: 3,
+            custom: None,
+        }
+    }
+}
+
+/// Base color texture of a [`Material`] changing over time, e.g. for screens, fire cards or
+/// conveyor belts. Textures are only animated in the scenes [`Scene::at`] returns, which
+/// [sequences](crate::renderer::RaytracingRenderer::render_sequence) are rendered from.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextureAnimation {
+    /// Images shown one after the other in place of the texture, if there are any.
+    pub sequence: Option<ImageSequence>,
+    /// Texture coordinates the texture moves by every second, added to the offset of the
+    /// material.
+    pub scroll: [f32; 2],
+    /// Turns the texture rotates by every second, added to the rotation of the material.
+    pub rotation: f32,
+}
+
+/// Numbered images played back as a texture, looping.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImageSequence {
+    /// Path of the images with the frame number in place of its last run of `#`, zero padded to
+    /// the length of the run, e.g. `fire_###.png` for `fire_001.png` and on. Relative paths are
+    /// relative to the scene file.
+    pub pattern: PathBuf,
+    /// Number of the first image.
+    pub first: u32,
+    /// Images in the sequence.
+    pub count: u32,
+    /// Images shown per second.
+    pub fps: f32,
+}
+
+impl Default for ImageSequence {
+    fn default() -> Self {
+        Self {
+            pattern: PathBuf::new(),
+            first: 1,
+            count: 1,
+            fps: 24.0,
+        }
+    }
+}
+
+impl ImageSequence {
+    /// Path of the image shown at `time` seconds into the animation.
+    pub fn path_at(&self, time: f32) -> PathBuf {
+        let frame = (time * self.fps).floor() as i64;
+        let number = self.first as i64 + frame.rem_euclid(self.count.max(1) as i64);
+
+        let pattern = self.pattern.to_string_lossy();
+        let Some(end) = pattern.rfind('#') else {
+            return self.pattern.clone();
+        };
+        let start = pattern[..end].trim_end_matches('#').len();
+        let width = end + 1 - start;
+        format!(
+            "{}{number:0width$}{}",
+            &pattern[..start],
+            &pattern[end + 1..]
+        )
+        .into()
+    }
+}
+
+impl TextureAnimation {
+    /// `material` with its base color texture as it is at `time` seconds into the animation.
+    fn apply(&self, material: &mut Material, time: f32) {
+        if let Some(sequence) = &self.sequence {
+            material.base_color_texture = Some(sequence.path_at(time));
+        }
+        // Textures repeat, whole turns and offsets don't change anything.
+        for (offset, scroll) in material.texture_offset.iter_mut().zip(self.scroll) {
+            *offset = (*offset + scroll * time).rem_euclid(1.0);
+        }
+        material.texture_rotation =
+            (material.texture_rotation + self.rotation * time).rem_euclid(1.0);
+    }
+}
+
+/// How the texels of a texture encode what it holds, telling whether they're decoded from sRGB
+/// when the texture is sampled.
+///
+/// Textures are kept sRGB encoded on the GPU, so linear ones lose a little of their precision
+/// in the highlights.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ColorSpace {
+    /// sRGB encoded color, decoded to linear RGB, what painted and photographed images hold.
+    #[default]
+    Srgb,
+    /// Values sampled as they are, e.g. colors baked in linear space, masks or other data.
+    Linear,
+    /// Unit vectors mapped from -1..1 to 0..1 per channel, sampled as they are like linear
+    /// data. Their mip levels are renormalized rather than just averaged, so they stay unit
+    /// vectors.
+    NormalMap,
+}
+
+/// Parameters of a [`Material`] replaced for a single object, e.g. to give instances of the same
+/// mesh their own colors without a material for each of them.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialOverrides {
+    pub base_color: Option<[f32; 3]>,
+    pub metallic: Option<f32>,
+    pub roughness: Option<f32>,
+    pub anisotropic: Option<f32>,
+    pub anisotropic_rotation: Option<f32>,
+    pub specular: Option<f32>,
+    pub ior: Option<f32>,
+    pub abbe_number: Option<f32>,
+    pub transmission: Option<f32>,
+    pub sheen: Option<f32>,
+    pub sheen_tint: Option<f32>,
+    pub clearcoat: Option<f32>,
+    pub clearcoat_roughness: Option<f32>,
+    pub clearcoat_ior: Option<f32>,
+    pub thin_film_thickness: Option<f32>,
+    pub thin_film_ior: Option<f32>,
+    pub subsurface: Option<f32>,
+    pub subsurface_scattering: Option<[f32; 3]>,
+    pub subsurface_absorption: Option<[f32; 3]>,
+    pub emission: Option<[f32; 3]>,
+    pub hair: Option<f32>,
+    pub hair_azimuthal_roughness: Option<f32>,
+    pub hair_scale_angle: Option<f32>,
+    pub opacity: Option<f32>,
+}
+
+impl MaterialOverrides {
+    /// Whether no parameter is overridden.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// `material` with the overridden parameters replaced.
+    pub fn apply(&self, material: Material) -> Material {
+        Material {
+            base_color: self.base_color.unwrap_or(material.base_color),
+            metallic: self.metallic.unwrap_or(material.metallic),
+            roughness: self.roughness.unwrap_or(material.roughness),
+            anisotropic: self.anisotropic.unwrap_or(material.anisotropic),
+            anisotropic_rotation: self
+                .anisotropic_rotation
+                .unwrap_or(material.anisotropic_rotation),
+            specular: self.specular.unwrap_or(material.specular),
+            ior: self.ior.unwrap_or(material.ior),
+            abbe_number: self.abbe_number.unwrap_or(material.abbe_number),
+            transmission: self.transmission.unwrap_or(material.transmission),
+            sheen: self.sheen.unwrap_or(material.sheen),
+            sheen_tint: self.sheen_tint.unwrap_or(material.sheen_tint),
+            clearcoat: self.clearcoat.unwrap_or(material.clearcoat),
+            clearcoat_roughness: self
+                .clearcoat_roughness
+                .unwrap_or(material.clearcoat_roughness),
+            clearcoat_ior: self.clearcoat_ior.unwrap_or(material.clearcoat_ior),
+            thin_film_thickness: self
+                .thin_film_thickness
+                .unwrap_or(material.thin_film_thickness),
+            thin_film_ior: self.thin_film_ior.unwrap_or(material.thin_film_ior),
+            subsurface: self.subsurface.unwrap_or(material.subsurface),
+            subsurface_scattering: self
+                .subsurface_scattering
+                .unwrap_or(material.subsurface_scattering),
+            subsurface_absorption: self
+                .subsurface_absorption
+                .unwrap_or(material.subsurface_absorption),
+            emission: self.emission.unwrap_or(material.emission),
+            hair: self.hair.unwrap_or(material.hair),
+            hair_azimuthal_roughness: self
+                .hair_azimuthal_roughness
+                .unwrap_or(material.hair_azimuthal_roughness),
+            hair_scale_angle: self.hair_scale_angle.unwrap_or(material.hair_scale_angle),
+            opacity: self.opacity.unwrap_or(material.opacity),
+            ..material
+        }
+    }
+}
+
+/// Shape placed in the scene.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Object {
+    #[serde(default)]
+    pub name: String,
+    pub shape: Shape,
+    /// Name of the material, objects without one get the default [`Material`].
+    #[serde(default)]
+    pub material: Option<String>,
+    /// Tweaks to the material that only apply to this object.
+    #[serde(default)]
+    pub overrides: MaterialOverrides,
+    /// Shade triangles with their face normals even if the mesh has vertex normals.
+    #[serde(default)]
+    pub flat_shading: bool,
+    #[serde(default)]
+    pub transform: Transform,
+    /// Keyframes overriding `transform`, see [`Scene::at`].
+    #[serde(default)]
+    pub animation: Vec<Keyframe<Transform>>,
+    /// Keyframes of the parts of `transform`, applied after `animation`.
+    #[serde(default)]
+    pub channels: TransformChannels,
+    /// Makes the object a stand-in for something in the footage the render is composited into:
+    /// camera rays see a hole with zero alpha where it is, while it still casts shadows and
+    /// bounces light like any other object.
+    #[serde(default)]
+    pub holdout: bool,
+    /// Rays that see the object, the others pass through it.
+    #[serde(default)]
+    pub visibility: RayVisibility,
+}
+
+/// Kinds of rays that see an [object](Object), e.g. to block light with a card the camera
+/// doesn't see or show something only in reflections.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RayVisibility {
+    /// Rays from the camera.
+    pub camera: bool,
+    /// Shadow rays towards the lights, without them the object casts no shadows.
+    pub shadow: bool,
+    /// Rays reflected or refracted by specular surfaces.
+    pub reflection: bool,
+    /// Rays scattered diffusely or by subsurface and volume scattering, without them the object
+    /// bounces no light onto the others.
+    pub indirect: bool,
+}
+
+impl Default for RayVisibility {
+    fn default() -> Self {
+        Self {
+            camera: true,
+            shadow: true,
+            reflection: true,
+            indirect: true,
+        }
+    }
+}
+
+impl Object {
+    /// Object with the default material and transform.
+    pub fn new(shape: Shape) -> Self {
+        Self {
+            name: String::new(),
+            shape,
+            material: None,
+            overrides: MaterialOverrides::default(),
+            flat_shading: false,
+            transform: Transform::default(),
+            animation: Vec::new(),
+            channels: TransformChannels::default(),
+            holdout: false,
+            visibility: RayVisibility::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Shape {
+    Sphere {
+        #[serde(default)]
+        center: [f32; 3],
+        radius: f32,
+    },
+    /// Parallelogram from `corner` along both `edges`, intersected exactly rather than as two
+    /// triangles, e.g. for the walls and light of a Cornell box. Its front faces the cross
+    /// product of the edges and its texture coordinates go from 0 at the corner to 1 along
+    /// either edge.
+    Quad {
+        #[serde(default)]
+        corner: [f32; 3],
+        edges: [[f32; 3]; 2],
+    },
+    /// Box intersected as a whole rather than as twelve triangles. The texture coordinates span
+    /// every one of its faces.
+    Cuboid {
+        #[serde(default)]
+        center: [f32; 3],
+        half_extents: [f32; 3],
+    },
+    /// Triangles of a Wavefront OBJ file. Relative paths are relative to the scene file.
+    Mesh { path: PathBuf },
+    /// Triangles written out in the scene itself.
+    Triangles {
+        positions: Vec<[f32; 3]>,
+        /// Normals of the vertices in `positions`, interpolated across the triangles to shade
+        /// them smoothly. Triangles are shaded flat without them.
+        #[serde(default)]
+        normals: Vec<[f32; 3]>,
+        /// Texture coordinates of the vertices in `positions`, with (0, 0) at the bottom left of
+        /// textures like in OBJ files. Without them triangles get the barycentric coordinates of
+        /// their second and third vertex.
+        #[serde(default)]
+        texcoords: Vec<[f32; 2]>,
+        /// Three vertex indices per triangle, consecutive triples of `positions` when empty.
+        /// Scenes with indices of missing vertices, or not a multiple of three of them, fail to
+        /// upload.
+        #[serde(default)]
+        indices: Vec<u32>,
+    },
+    /// Primitive intersected by the [intersect hook](crate::shader::ShaderHook::Intersect),
+    /// never hit without one.
+    ///
+    /// The hook gets the values as they are, custom shapes live in world space and ignore the
+    /// transform of their object.
+    Custom {
+        /// Corners of the box the shape fits in.
+        min: [f32; 3],
+        max: [f32; 3],
+        /// Parameters for the hook to interpret.
+        #[serde(default)]
+        data: [f32; 4],
+    },
+    /// Solid bounded by the zero level set of a signed distance field, traced by sphere tracing,
+    /// e.g. for procedural shapes blended smoothly into each other.
+    Sdf { expression: Sdf },
+    /// Solid made of simpler ones by constructive solid geometry, with exact surfaces and sharp
+    /// seams, see [`Csg`].
+    Csg { expression: Csg },
+    /// Terrain over a grid of the pixels of a grayscale image, traced cell by cell instead of
+    /// as triangles so that large ones stay cheap to load. Relative paths are relative to the
+    /// scene file.
+    ///
+    /// The terrain is centered on the origin, `size[0]` wide along x and `size[2]` deep along
+    /// z with the top row of the image at -z, and rises from 0 for black to `size[1]` for white.
+    /// Its texture coordinates span the image.
+    Heightfield { path: PathBuf, size: [f32; 3] },
+    /// Strands of hair or fur, tubes along cubic curves with the [hair](Material::hair) lobe of
+    /// their material in mind. Their tangent runs along the strands and their texture
+    /// coordinates from the root of every strand to its tip across and from one side of it to
+    /// the other up.
+    Curves {
+        /// Control points of all the strands, one after the other.
+        points: Vec<[f32; 3]>,
+        /// How many of `points` every strand has, all of them for a single one when empty.
+        #[serde(default)]
+        counts: Vec<u32>,
+        /// Width of the strands at every control point, or everywhere for a single one.
+        widths: Vec<f32>,
+        #[serde(default)]
+        basis: CurveBasis,
+        /// Ribbons always facing the ray and shaded as if they were round, quicker to trace than
+        /// the round tubes strands are otherwise.
+        #[serde(default)]
+        flat: bool,
+    },
+    /// Small disks around points, e.g. to show scanned point clouds among the rest of a scene.
+    Points {
+        positions: Vec<[f32; 3]>,
+        /// Radius of every point, or of all of them for a single one.
+        radii: Vec<f32>,
+        /// Directions the disks face, or none for disks facing every ray.
+        #[serde(default)]
+        normals: Vec<[f32; 3]>,
+        /// Linear RGB color of every point, multiplying the base color of the material. White
+        /// when empty.
+        #[serde(default)]
+        colors: Vec<[f32; 3]>,
+        /// Gaussian splats fading out towards their radius, three standard deviations from their
+        /// center, rather than opaque disks. Their transparency cuts them out like
+        /// [`Material::opacity`].
+        #[serde(default)]
+        gaussian: bool,
+    },
+}
+
+/// How the control points of [`Shape::Curves`] shape the strands.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CurveBasis {
+    /// Cubic Bézier segments sharing their end points, three more points for every one after the
+    /// first, going through the first and last point of each.
+    #[default]
+    Bezier,
+    /// Uniform cubic B-spline, a segment for every four consecutive points, only going near them
+    /// but smooth at the joins.
+    BSpline,
+}
+
+/// Signed distance field of a [`Shape::Sdf`] in the space of its object: solids combined by
+/// constructive solid geometry, negative inside of them.
+///
+/// The operations blend their operands over a `smoothness` wide seam, or join them with a
+/// sharp one at zero. At most eight distances are kept while evaluating the expression, which
+/// only limits operations whose both operands are operations themselves.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Sdf {
+    Sphere {
+        #[serde(default)]
+        center: [f32; 3],
+        radius: f32,
+    },
+    /// Box with its edges rounded off by `rounding`, which grows it by that much.
+    Cuboid {
+        #[serde(default)]
+        center: [f32; 3],
+        half_extents: [f32; 3],
+        #[serde(default)]
+        rounding: f32,
+    },
+    /// Ring around the y axis, `major_radius` from its center to the center of its tube.
+    Torus {
+        #[serde(default)]
+        center: [f32; 3],
+        major_radius: f32,
+        minor_radius: f32,
+    },
+    /// Capped cylinder along the y axis.
+    Cylinder {
+        #[serde(default)]
+        center: [f32; 3],
+        radius: f32,
+        half_height: f32,
+    },
+    Union {
+        a: Box<Sdf>,
+        b: Box<Sdf>,
+        #[serde(default)]
+        smoothness: f32,
+    },
+    Intersection {
+        a: Box<Sdf>,
+        b: Box<Sdf>,
+        #[serde(default)]
+        smoothness: f32,
+    },
+    /// `a` with `b` cut out of it.
+    Subtraction {
+        a: Box<Sdf>,
+        b: Box<Sdf>,
+        #[serde(default)]
+        smoothness: f32,
+    },
+}
+
+impl Sdf {
+    /// Corners of a box containing the solid, empty if the minimum exceeds the maximum.
+    pub fn bounds(&self) -> [[f32; 3]; 2] {
+        let around = |center: &[f32; 3], extents: [f32; 3]| {
+            [
+                std::array::from_fn(|i| center[i] - extents[i]),
+                std::array::from_fn(|i| center[i] + extents[i]),
+            ]
+        };
+        match self {
+            Self::Sphere { center, radius } => around(center, [*radius; 3]),
+            Self::Cuboid {
+                center,
+                half_extents,
+                rounding,
+            } => around(center, half_extents.map(|h| h + rounding)),
+            Self::Torus {
+                center,
+                major_radius,
+                minor_radius,
+            } => {
+                let radius = major_radius + minor_radius;
+                around(center, [radius, *minor_radius, radius])
+            }
+            Self::Cylinder {
+                center,
+                radius,
+                half_height,
+            } => around(center, [*radius, *half_height, *radius]),
+            // Smooth unions bulge out by up to a quarter of the smoothness between operands,
+            // the other operations only ever shrink.
+            Self::Union { a, b, smoothness } => {
+                let ([a_min, a_max], [b_min, b_max]) = (a.bounds(), b.bounds());
+                let grow = smoothness.max(0.0) / 4.0;
+                [
+                    std::array::from_fn(|i| a_min[i].min(b_min[i]) - grow),
+                    std::array::from_fn(|i| a_max[i].max(b_max[i]) + grow),
+                ]
+            }
+            Self::Intersection { a, b, .. } => {
+                let ([a_min, a_max], [b_min, b_max]) = (a.bounds(), b.bounds());
+                [
+                    std::array::from_fn(|i| a_min[i].max(b_min[i])),
+                    std::array::from_fn(|i| a_max[i].min(b_max[i])),
+                ]
+            }
+            Self::Subtraction { a, .. } => a.bounds(),
+        }
+    }
+}
+
+/// Solid of a [`Shape::Csg`] in the space of its object: convex solids combined by boolean
+/// operations, traced exactly by following where the ray enters and leaves each one of them,
+/// e.g. for mechanical parts with holes drilled into them. Unlike [signed distance fields](Sdf)
+/// they can't be blended, but their surfaces are the exact ones of the solids.
+///
+/// The shaders have room for the intervals of up to [`Csg::MAX_SOLIDS`] solids.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Csg {
+    Sphere {
+        #[serde(default)]
+        center: [f32; 3],
+        radius: f32,
+    },
+    Cuboid {
+        #[serde(default)]
+        center: [f32; 3],
+        half_extents: [f32; 3],
+    },
+    /// Capped cylinder along the y axis.
+    Cylinder {
+        #[serde(default)]
+        center: [f32; 3],
+        radius: f32,
+        half_height: f32,
+    },
+    Union {
+        a: Box<Csg>,
+        b: Box<Csg>,
+    },
+    Intersection {
+        a: Box<Csg>,
+        b: Box<Csg>,
+    },
+    /// `a` with `b` cut out of it.
+    Difference {
+        a: Box<Csg>,
+        b: Box<Csg>,
+    },
+}
+
+impl Csg {
+    /// Solids an expression can combine, `CSG_SOLIDS` in the shaders.
+    pub const MAX_SOLIDS: usize = 16;
+
+    /// Corners of a box containing the solid, empty if the minimum exceeds the maximum.
+    pub fn bounds(&self) -> [[f32; 3]; 2] {
+        let around = |center: &[f32; 3], extents: [f32; 3]| {
+            [
+                std::array::from_fn(|i| center[i] - extents[i]),
+                std::array::from_fn(|i| center[i] + extents[i]),
+            ]
+        };
+        match self {
+            Self::Sphere { center, radius } => around(center, [*radius; 3]),
+            Self::Cuboid {
+                center,
+                half_extents,
+            } => around(center, *half_extents),
+            Self::Cylinder {
+                center,
+                radius,
+                half_height,
+            } => around(center, [*radius, *half_height, *radius]),
+            Self::Union { a, b } => {
+                let ([a_min, a_max], [b_min, b_max]) = (a.bounds(), b.bounds());
+                [
+                    std::array::from_fn(|i| a_min[i].min(b_min[i])),
+                    std::array::from_fn(|i| a_max[i].max(b_max[i])),
+                ]
+            }
+            Self::Intersection { a, b } => {
+                let ([a_min, a_max], [b_min, b_max]) = (a.bounds(), b.bounds());
+                [
+                    std::array::from_fn(|i| a_min[i].max(b_min[i])),
+                    std::array::from_fn(|i| a_max[i].min(b_max[i])),
+                ]
+            }
+            Self::Difference { a, .. } => a.bounds(),
+        }
+    }
+
+    /// Number of solids the expression combines.
+    pub fn solids(&self) -> usize {
+        match self {
+            Self::Sphere { .. } | Self::Cuboid { .. } | Self::Cylinder { .. } => 1,
+            Self::Union { a, b } | Self::Intersection { a, b } | Self::Difference { a, b } => {
+                a.solids() + b.solids()
+            }
+        }
+    }
+}
+
+/// Node of the scene graph, grouping objects and other nodes under a transform relative to the
+/// node above it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Node {
+    pub name: String,
+    pub transform: Transform,
+    /// Keyframes overriding `transform`, see [`Scene::at`].
+    pub animation: Vec<Keyframe<Transform>>,
+    /// Keyframes of the parts of `transform`, applied after `animation`.
+    pub channels: TransformChannels,
+    /// Hidden nodes are left out of renders along with everything below them.
+    pub visible: bool,
+    /// Objects placed relative to the node, their transforms applied before the node's.
+    pub objects: Vec<Object>,
+    pub children: Vec<Node>,
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            transform: Transform::default(),
+            animation: Vec::new(),
+            channels: TransformChannels::default(),
+            visible: true,
+            objects: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+}
+
+/// Object as it's placed in the world, with the transforms of the nodes above it applied, see
+/// [`Scene::instances`].
+#[derive(Clone, Debug)]
+pub struct Instance<'a> {
+    pub object: &'a Object,
+    /// Transform from the space of the object to world space, including its own transform,
+    /// moved to the [origin](Scene::origin) of the scene.
+    pub matrix: Matrix4<f32>,
+}
+
+/// Placement of an object, applied as scale, then rotation, then translation.
+///
+/// Spheres only support uniform scales, otherwise their radius is scaled by the largest
+/// component.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Transform {
+    pub translation: [f32; 3],
+    /// Rotations around the X, Y and Z axes in degrees, applied in that order.
+    pub rotation: [f32; 3],
+    /// Rotation as a unit quaternion `[x, y, z, w]` used instead of `rotation`, e.g. one
+    /// imported from glTF.
+    pub orientation: Option<[f32; 4]>,
+    pub scale: [f32; 3],
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: [0.0; 3],
+            rotation: [0.0; 3],
+            orientation: None,
+            scale: [1.0; 3],
+        }
+    }
+}
+
+impl Transform {
+    /// Rotation of the transform, from its `orientation` if it has one, otherwise its Euler
+    /// angles.
+    pub fn quaternion(&self) -> Quaternion<f32> {
+        match self.orientation {
+            Some([x, y, z, w]) => Quaternion::new(w, x, y, z).normalize(),
+            None => {
+                let [rx, ry, rz] = self.rotation;
+                Quaternion::from_angle_z(Deg(rz))
+                    * Quaternion::from_angle_y(Deg(ry))
+                    * Quaternion::from_angle_x(Deg(rx))
+            }
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        let rotation = Matrix3::from(self.quaternion());
+        let [sx, sy, sz] = self.scale;
+
+        Matrix4::from_translation(Vector3::from(self.translation))
+            * Matrix4::from(rotation)
+            * Matrix4::from_nonuniform_scale(sx, sy, sz)
+    }
+}
+
+/// Light without a surface, so never hit by rays and only reached by light sampling.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Light {
+    /// Light emitted equally in all directions from a point.
+    Point {
+        position: [f32; 3],
+        #[serde(default = "white")]
+        color: [f32; 3],
+        /// Radiant intensity, in watts per steradian, in the brightest direction of `profile`.
+        #[serde(default = "one")]
+        intensity: f32,
+        /// Measured distribution of the light of a luminaire, which otherwise emits equally in
+        /// all directions.
+        #[serde(default)]
+        profile: Option<LightProfile>,
+    },
+    /// Infinitely far light, like the sun.
+    Directional {
+        /// Direction the light travels in.
+        direction: [f32; 3],
+        #[serde(default = "white")]
+        color: [f32; 3],
+        /// Irradiance on surfaces facing the light, in watts per square meter.
+        #[serde(default = "one")]
+        intensity: f32,
+        /// Angle the light arrives from in degrees, softening the shadows it casts, e.g. 0.53
+        /// for the sun. Zero for light from a single direction and hard shadows.
+        #[serde(default)]
+        angular_diameter: f32,
+    },
+}
+
+/// Limits the light of one of the [lights](Scene::lights) to some objects, e.g. to light a
+/// single character or keep a fill light off the background. Every object with one of the
+/// names gets the link, including instances of it and objects of the same name in nodes.
+///
+/// Links only apply to the light sampled by `NEE` when path tracing, light reaching objects
+/// any other way isn't linked.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LightLink {
+    /// Index of the light in `lights`.
+    pub light: usize,
+    /// Names of the objects the light illuminates, all of them if empty.
+    pub objects: Vec<String>,
+    /// Names of the objects the light ignores.
+    pub excluded: Vec<String>,
+}
+
+impl LightLink {
+    /// Whether the light of the link illuminates the object called `name`.
+    pub fn illuminates(&self, name: &str) -> bool {
+        (self.objects.is_empty() || self.objects.iter().any(|object| object == name))
+            && !self.excluded.iter().any(|object| object == name)
+    }
+}
+
+/// Named part of a scene rendered on its own, with every other object as a
+/// [holdout](Object::holdout) so that the images of the layers can be composited over each
+/// other. Like [light links](LightLink), objects are matched by name, including instances and
+/// objects of the same name in nodes.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderLayer {
+    pub name: String,
+    /// Names of the objects in the layer, all of them if empty.
+    pub objects: Vec<String>,
+    /// Names of the objects left out of the layer.
+    pub excluded: Vec<String>,
+}
+
+impl RenderLayer {
+    /// Whether the object called `name` is in the layer.
+    pub fn contains(&self, name: &str) -> bool {
+        (self.objects.is_empty() || self.objects.iter().any(|object| object == name))
+            && !self.excluded.iter().any(|object| object == name)
+    }
+}
+
+/// How the intensity of a [point light](Light::Point) varies with direction, from an
+/// [IES file](crate::ies).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LightProfile {
+    /// Relative paths are relative to the scene file.
+    pub path: PathBuf,
+    /// Direction of the vertical angle of 0 degrees, down the axis of the luminaire.
+    #[serde(default = "down")]
+    pub axis: [f32; 3],
+    /// Direction of the horizontal angle of 0 degrees, with angles increasing towards
+    /// `axis × reference`. It doesn't have to be perpendicular to `axis`.
+    #[serde(default = "east")]
+    pub reference: [f32; 3],
+}
+
+/// Homogeneous medium filling the space between surfaces, scattering light into haze and shafts of
+/// light through gaps in the geometry.
+///
+/// The coefficients are per unit of distance, so half the light is gone after `ln(2)` over their
+/// sum. Lights only shine through it with `NEE` enabled, like they only shine on surfaces with it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Fog {
+    /// Fraction of the light scattered per unit of distance travelled, for each channel.
+    pub scattering: [f32; 3],
+    /// Fraction of the light absorbed per unit of distance travelled, for each channel.
+    pub absorption: [f32; 3],
+    /// Henyey-Greenstein asymmetry of the scattering, from -1 for all of it going back the way it
+    /// came over 0 for isotropic to 1 for all of it going on.
+    pub anisotropy: f32,
+    /// Minimum and maximum corners of the box the fog fills, the bounds of the scene if not
+    /// given.
+    pub bounds: Option<[[f32; 3]; 2]>,
+    /// Density varying over the box, scaling the coefficients, which makes them the densest the
+    /// fog gets.
+    pub density: Option<DensityGrid>,
+}
+
+/// Densities on a regular grid spanning the box of the [`Fog`], like smoke or clouds exported
+/// from a simulation.
+///
+/// The file holds the raw little-endian `f32` densities of the cells, from 0 to 1, with x
+/// changing fastest and z slowest. They're interpolated between the centers of the cells and kept
+/// with 8 bits of precision.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DensityGrid {
+    pub path: PathBuf,
+    /// Number of cells along each axis.
+    pub resolution: [u32; 3],
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self {
+            scattering: [0.05; 3],
+            absorption: [0.0; 3],
+            anisotropy: 0.0,
+            bounds: None,
+            density: None,
+        }
+    }
+}
+
+/// What rays escaping the scene see.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Sky {
+    /// Gradient from white at the horizon to light blue overhead, and back to white below.
+    #[default]
+    Gradient,
+    /// Clear daylight sky of an analytic model of the atmosphere.
+    Physical(PhysicalSky),
+    /// The same linear RGB radiance from every direction.
+    Color([f32; 3]),
+    /// Linear RGB radiance blended from `bottom` straight down to `top` straight up.
+    VerticalGradient { bottom: [f32; 3], top: [f32; 3] },
+    /// Image all around the scene, see [`EnvironmentTexture`].
+    Texture(EnvironmentTexture),
+}
+
+/// Image in the equirectangular layout seen all around the scene, with the horizon across the
+/// middle of it and -Z at its center. It's streamed through the same cache as the textures of
+/// materials, and like them resampled to a square power of two.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvironmentTexture {
+    pub path: PathBuf,
+    /// Scale from the colors of the image to the radiance the paths carry.
+    pub intensity: f32,
+    /// Degrees the image is turned around the vertical axis, counterclockwise seen from above.
+    pub rotation: f32,
+    /// How the texels of the image are encoded, see [`Material::base_color_texture_space`].
+    pub color_space: ColorSpace,
+}
+
+impl Default for EnvironmentTexture {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::new(),
+            intensity: 1.0,
+            rotation: 0.0,
+            color_space: ColorSpace::Srgb,
+        }
+    }
+}
+
+/// Sky of the analytic daylight model of "A Practical Analytic Model for Daylight" (Preetham,
+/// Shirley and Smits 1999), which gives the luminance and chromaticity of every direction for
+/// the position of the sun and the haze of the atmosphere.
+///
+/// Only the light the atmosphere scatters is included, the sun itself takes a
+/// [directional light](Light::Directional) shining along `-sun_direction` that next event
+/// estimation samples.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhysicalSky {
+    /// Direction towards the sun, with y up, which doesn't have to be unit length. The model
+    /// doesn't cover the sun below the horizon, lower suns are treated as setting.
+    pub sun_direction: [f32; 3],
+    /// Haze of the atmosphere, from 2 for a very clear sky over 3 for a clear one to 10 for a
+    /// hazy one.
+    pub turbidity: f32,
+    /// Scale from the luminance of the model in kcd/m² to the radiance the paths carry.
+    pub intensity: f32,
+    /// Fraction of the light of the sky the ground below the horizon reflects, seen as the
+    /// sky mirrored at the horizon.
+    pub ground_albedo: f32,
+}
+
+impl Default for PhysicalSky {
+    fn default() -> Self {
+        Self {
+            sun_direction: [0.0, 1.0, -1.0],
+            turbidity: 3.0,
+            intensity: 0.1,
+            ground_albedo: 0.3,
+        }
+    }
+}
+
+/// Rectangle the sky lights the scene through, like a window of an interior.
+///
+/// With `NEE` enabled, diffuse surfaces sample the sky by picking points on the portals rather
+/// than only finding it by bouncing around, which is far less noisy when it's only seen through
+/// small openings. Light through the rest of the sky still arrives as before. Portals aren't
+/// surfaces, anything in them like window panes blocks the sky.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Portal {
+    pub corner: [f32; 3],
+    /// Sides of the rectangle from `corner`, which have to be perpendicular.
+    pub edges: [[f32; 3]; 2],
+}
+
+/// Plane through `point` cutting away the geometry on the side `normal` points away from, for
+/// sections through buildings or parts. Rays pass through what's cut away as if it weren't
+/// there, shadow rays included.
+///
+/// With a `cap_material`, closed surfaces cut open are filled with it where the plane goes
+/// through them, like the hatched faces of section drawings. Caps are only there for rays coming
+/// through the plane, rays inside the surfaces still leave through the opening.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClipPlane {
+    pub point: [f32; 3],
+    pub normal: [f32; 3],
+    #[serde(default)]
+    pub cap_material: Option<String>,
+}
+
+/// The sun as seen from a place on Earth at a time, see [`sun::direction`].
+///
+/// The scene is laid out with y up, x pointing east and -z pointing north.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sun {
+    /// Degrees north of the equator, negative to the south.
+    pub latitude: f32,
+    /// Degrees east of Greenwich, negative to the west.
+    pub longitude: f32,
+    /// Year, month and day of the month, from 1.
+    pub date: (i32, u32, u32),
+    /// Local time of day in hours, e.g. 14.5 for half past two in the afternoon.
+    pub time: f32,
+    /// Hours the local time is ahead of UTC, e.g. 1 for central European time.
+    pub utc_offset: f32,
+    pub color: [f32; 3],
+    /// Irradiance on surfaces facing the sun, in watts per square meter.
+    pub intensity: f32,
+    /// Angle the sun covers in the sky in degrees, see [`Light::Directional`].
+    pub angular_diameter: f32,
+}
+
+impl Default for Sun {
+    fn default() -> Self {
+        Self {
+            latitude: 51.48,
+            longitude: 0.0,
+            date: (2000, 6, 21),
+            time: 12.0,
+            utc_offset: 0.0,
+            color: white(),
+            intensity: 1.0,
+            angular_diameter: 0.53,
+        }
+    }
+}
+
+impl Sun {
+    /// Unit vector from the scene towards the sun.
+    pub fn direction(&self) -> [f32; 3] {
+        let (year, month, day) = self.date;
+        sun::direction(
+            self.latitude,
+            self.longitude,
+            year,
+            month,
+            day,
+            self.time - self.utc_offset,
+        )
+    }
+
+    /// Directional light of the sun, shining along `-direction`.
+    pub fn light(&self) -> Light {
+        let [x, y, z] = self.direction();
+        Light::Directional {
+            direction: [-x, -y, -z],
+            color: self.color,
+            intensity: self.intensity,
+            angular_diameter: self.angular_diameter,
+        }
+    }
+}
+
+fn white() -> [f32; 3] {
+    [1.0; 3]
+}
+
+fn down() -> [f32; 3] {
+    [0.0, -1.0, 0.0]
+}
+
+fn east() -> [f32; 3] {
+    [1.0, 0.0, 0.0]
+}
+
+pub(crate) fn one() -> f32 {
+    1.0
+}
+
+/// File formats scenes can be read from and written to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SceneFormat {
+    Ron,
+    Json,
+}
+
+impl SceneFormat {
+    /// Format implied by the extension of `path`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ron" => Some(Self::Ron),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+impl Scene {
+    /// The scene rendered until another one is set: a diffuse sphere resting on a much larger
+    /// one serving as the ground, lit by the sky.
+    pub fn demo() -> Self {
+        let sphere = |center, radius| Object::new(Shape::Sphere { center, radius });
+
+        Self {
+            objects: vec![
+                sphere([0.0, 0.0, -1.0], 0.5),
+                sphere([0.0, -100.5, -1.0], 100.0),
+            ],
+            ..Default::default()
+        }
+    }
+
+    /// The Cornell box, see [`scenes::cornell_box`](crate::scenes::cornell_box).
+    pub fn cornell_box() -> Self {
+        crate::scenes::cornell_box()
+    }
+
+    /// The scene as it is at `time` seconds into its animation, with the camera and the
+    /// transforms of animated objects and nodes replaced by their interpolated keyframes, then
+    /// the parts their [channels](TransformChannels) animate replaced by those, and
+    /// the [animated textures](TextureAnimation) of materials advanced to it.
+    pub fn at(&self, time: f32) -> Self {
+        fn animate_nodes(nodes: &mut [Node], time: f32) {
+            for node in nodes {
+                if let Some(transform) = animation::sample(&node.animation, time) {
+                    node.transform = transform;
+                }
+                node.channels.apply(&mut node.transform, time);
+                animate_nodes(&mut node.children, time);
+            }
+        }
+
+        let mut scene = self.clone();
+        if let Some(camera) = animation::sample(&self.camera_animation, time) {
+            scene.camera = camera;
+        }
+        animate_nodes(&mut scene.nodes, time);
+        for material in &mut scene.materials {
+            if let Some(animation) = material.texture_animation.take() {
+                animation.apply(material, time);
+            }
+        }
+        scene
+            .try_for_each_object_mut(|object| {
+                if let Some(transform) = animation::sample(&object.animation, time) {
+                    object.transform = transform;
+                }
+                object.channels.apply(&mut object.transform, time);
+                Ok(())
+            })
+            .expect("Animating objects can't fail");
+        scene
+    }
+
+    /// The scene as it's rendered for the layer called `name`, with the objects outside of it
+    /// turned into holdouts, or `None` if the scene has no such layer.
+    pub fn layer(&self, name: &str) -> Option<Self> {
+        let layer = self.layers.iter().find(|layer| layer.name == name)?;
+        let mut scene = self.clone();
+        scene
+            .try_for_each_object_mut(|object| {
+                object.holdout |= !layer.contains(&object.name);
+                Ok(())
+            })
+            .expect("Holding out objects can't fail");
+        Some(scene)
+    }
+
+    /// Whether the camera, any object, any node or the texture of any material is animated.
+    pub fn is_animated(&self) -> bool {
+        fn is_animated(objects: &[Object], nodes: &[Node]) -> bool {
+            objects
+                .iter()
+                .any(|object| !object.animation.is_empty() || !object.channels.is_empty())
+                || nodes.iter().any(|node| {
+                    !node.animation.is_empty()
+                        || !node.channels.is_empty()
+                        || is_animated(&node.objects, &node.children)
+                })
+        }
+
+        !self.camera_animation.is_empty()
+            || is_animated(&self.objects, &self.nodes)
+            || self
+                .materials
+                .iter()
+                .any(|material| material.texture_animation.is_some())
+    }
+
+    /// Point of the world the scene is rendered around: the position of the camera if it's
+    /// [camera relative](Self::camera_relative), otherwise the origin.
+    ///
+    /// The world transforms of the [instances](Self::instances) are composed in double
+    /// precision and moved by it before they're rounded to single precision, and so are the
+    /// lights, portals, clip planes, fog and camera when the scene is built for the GPU. The
+    /// shaders then only see small coordinates close to the camera, where f32 is precise, so
+    /// surfaces thousands of kilometers from the origin of planetary scale scenes don't jitter
+    /// or tear. Positions in the scene are still only as precise as f32 stores them, which
+    /// places them less precisely the further out they are but doesn't make them jitter.
+    pub fn origin(&self) -> [f64; 3] {
+        if self.camera_relative {
+            self.camera.position.map(f64::from)
+        } else {
+            [0.0; 3]
+        }
+    }
+
+    /// The objects that are rendered, with the transforms of the nodes above them flattened
+    /// into their world transforms around the [origin](Self::origin): the top level objects,
+    /// then the ones of visible nodes, depth first.
+    pub fn instances(&self) -> Vec<Instance<'_>> {
+        fn flatten<'a>(
+            objects: &'a [Object],
+            nodes: &'a [Node],
+            parent: Matrix4<f64>,
+            instances: &mut Vec<Instance<'a>>,
+        ) {
+            let matrix = |transform: &Transform| {
+                parent * transform.matrix().cast().expect("f32 converts to f64")
+            };
+            instances.extend(objects.iter().map(|object| {
+                Instance {
+                    object,
+                    matrix: matrix(&object.transform)
+                        .cast()
+                        .expect("f64 converts to f32"),
+                }
+            }));
+            for node in nodes.iter().filter(|node| node.visible) {
+                flatten(
+                    &node.objects,
+                    &node.children,
+                    matrix(&node.transform),
+                    instances,
+                );
+            }
+        }
+
+        let [x, y, z] = self.origin();
+        let mut instances = Vec::new();
+        flatten(
+            &self.objects,
+            &self.nodes,
+            Matrix4::from_translation(Vector3::new(-x, -y, -z)),
+            &mut instances,
+        );
+        instances
+    }
+
+    /// Calls `f` with every object of the scene, including the ones of hidden nodes, stopping
+    /// at the first error.
+    fn try_for_each_object_mut(
+        &mut self,
+        mut f: impl FnMut(&mut Object) -> Result<(), SceneError>,
+    ) -> Result<(), SceneError> {
+        fn visit(
+            objects: &mut [Object],
+            nodes: &mut [Node],
+            f: &mut impl FnMut(&mut Object) -> Result<(), SceneError>,
+        ) -> Result<(), SceneError> {
+            objects.iter_mut().try_for_each(&mut *f)?;
+            nodes
+                .iter_mut()
+                .try_for_each(|node| visit(&mut node.objects, &mut node.children, f))
+        }
+
+        visit(&mut self.objects, &mut self.nodes, &mut f)
+    }
+
+    /// Loads a scene from a file in the format implied by its extension.
+    ///
+    /// Relative mesh paths are resolved against the directory of the scene file, the meshes
+    /// themselves are only read when the scene is rendered or [inlined](Self::inline_meshes).
+    /// `.pbrt` files are [imported](crate::pbrt) as well, and so are `.obj` files with their
+    /// [MTL materials](crate::obj).
+    #[cfg(feature = "fs")]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SceneError> {
+        let path = path.as_ref();
+        let has_extension = |ext: &str| {
+            path.extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case(ext))
+        };
+        if has_extension("pbrt") {
+            return crate::pbrt::load(path);
+        }
+        if has_extension("obj") {
+            return crate::obj::load(path);
+        }
+
+        let format = SceneFormat::from_path(path)
+            .ok_or_else(|| SceneError::UnknownFormat(path.to_owned()))?;
+
+        let mut scene = Self::parse(&fs::read_to_string(path)?, format)?;
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        for material in &mut scene.materials {
+            let sequence = material
+                .texture_animation
+                .as_mut()
+                .and_then(|animation| animation.sequence.as_mut())
+                .map(|sequence| &mut sequence.pattern);
+            for texture in [
+                material.base_color_texture.as_mut(),
+                material.displacement_texture.as_mut(),
+                sequence,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                if texture.is_relative() {
+                    *texture = base.join(&*texture);
+                }
+            }
+        }
+        for light in &mut scene.lights {
+            if let Light::Point {
+                profile: Some(profile),
+                ..
+            } = light
+            {
+                if profile.path.is_relative() {
+                    profile.path = base.join(&profile.path);
+                }
+            }
+        }
+        if let Some(grid) = scene.fog.as_mut().and_then(|fog| fog.density.as_mut()) {
+            if grid.path.is_relative() {
+                grid.path = base.join(&grid.path);
+            }
+        }
+        for sky in std::iter::once(&mut scene.sky).chain(&mut scene.background) {
+            if let Sky::Texture(texture) = sky {
+                if texture.path.is_relative() {
+                    texture.path = base.join(&texture.path);
+                }
+            }
+        }
+        scene.try_for_each_object_mut(|object| {
+            if let Shape::Mesh { path } | Shape::Heightfield { path, .. } = &mut object.shape {
+                if path.is_relative() {
+                    *path = base.join(&*path);
+                }
+            }
+            Ok(())
+        })?;
+
+        Ok(scene)
+    }
+
+    /// Saves the scene to a file in the format implied by its extension.
+    #[cfg(feature = "fs")]
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SceneError> {
+        let path = path.as_ref();
+        let format = SceneFormat::from_path(path)
+            .ok_or_else(|| SceneError::UnknownFormat(path.to_owned()))?;
+
+        fs::write(path, self.to_string(format)?)?;
+        Ok(())
+    }
+
+    pub fn parse(source: &str, format: SceneFormat) -> Result<Self, SceneError> {
+        Ok(match format {
+            SceneFormat::Ron => ron::from_str(source)?,
+            SceneFormat::Json => serde_json::from_str(source)?,
+        })
+    }
+
+    pub fn to_string(&self, format: SceneFormat) -> Result<String, SceneError> {
+        Ok(match format {
+            SceneFormat::Ron => ron::ser::to_string_pretty(self, Default::default())?,
+            SceneFormat::Json => serde_json::to_string_pretty(self)?,
+        })
+    }
+
+    /// Replaces every [`Shape::Mesh`] with the [`Shape::Triangles`] of its file, so that the
+    /// scene no longer depends on mesh files, e.g. before sending it to another machine.
+    /// Textures are still read from their files.
+    pub fn inline_meshes(&mut self) -> Result<(), SceneError> {
+        self.try_for_each_object_mut(|object| {
+            if let Shape::Mesh { path } = &object.shape {
+                let mesh = load_mesh(path)?;
+                object.shape = Shape::Triangles {
+                    positions: mesh.positions,
+                    normals: mesh.normals,
+                    texcoords: mesh.texcoords,
+                    indices: mesh.indices,
+                };
+            }
+            Ok(())
+        })
+    }
+
+    /// Index of the material called `name`.
+    pub fn material_index(&self, name: &str) -> Result<usize, SceneError> {
+        self.materials
+            .iter()
+            .position(|material| material.name == name)
+            .ok_or_else(|| SceneError::UnknownMaterial(name.to_owned()))
+    }
+}
+
+/// Vertices and triangles of a mesh file, laid out like [`Shape::Triangles`].
+pub(crate) struct MeshData {
+    pub positions: Vec<[f32; 3]>,
+    /// Empty unless every vertex has one, just like `texcoords`.
+    pub normals: Vec<[f32; 3]>,
+    pub texcoords: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+}
+
+/// Reads the vertex positions, normals, texture coordinates and triangle indices of all the
+/// models of an OBJ file.
+#[cfg(feature = "fs")]
+pub(crate) fn load_mesh(path: &Path) -> Result<MeshData, SceneError> {
+    let (models, _materials) = tobj::load_obj(
+        path,
+        &tobj::LoadOptions {
+            triangulate: true,
+            single_index: true,
+            ..Default::default()
+        },
+    )
+    .map_err(|source| SceneError::Obj {
+        path: path.to_owned(),
+        source,
+    })?;
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut texcoords = Vec::new();
+    let mut indices = Vec::new();
+    // Models without normals make the whole mesh flat shaded, ones without texture coordinates
+    // leave all of it untextured.
+    let (mut smooth, mut textured) = (true, true);
+    for model in models {
+        let offset = positions.len() as u32;
+        let vertices = model.mesh.positions.len() / 3;
+        positions.extend(
+            model
+                .mesh
+                .positions
+                .chunks_exact(3)
+                .map(|p| [p[0], p[1], p[2]]),
+        );
+        smooth &= model.mesh.normals.len() == vertices * 3;
+        normals.extend(
+            model
+                .mesh
+                .normals
+                .chunks_exact(3)
+                .map(|n| [n[0], n[1], n[2]]),
+        );
+        textured &= model.mesh.texcoords.len() == vertices * 2;
+        texcoords.extend(model.mesh.texcoords.chunks_exact(2).map(|t| [t[0], t[1]]));
+        indices.extend(model.mesh.indices.iter().map(|index| index + offset));
+    }
+    if !smooth {
+        normals.clear();
+    }
+    if !textured {
+        texcoords.clear();
+    }
+
+    Ok(MeshData {
+        positions,
+        normals,
+        texcoords,
+        indices,
+    })
+}
+
+/// Without file access meshes can only be given as [`Shape::Triangles`].
+#[cfg(not(feature = "fs"))]
+pub(crate) fn load_mesh(path: &Path) -> Result<MeshData, SceneError> {
+    Err(SceneError::Io(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "{}: meshes can't be read without the fs feature",
+            path.display()
+        ),
+    )))
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Ron(ron::Error),
+    RonSyntax(ron::error::SpannedError),
+    Json(serde_json::Error),
+    Obj {
+        path: PathBuf,
+        source: tobj::LoadError,
+    },
+    /// Malformed [PBRT](crate::pbrt) scene or PLY mesh, `line` is zero for PLY files.
+    Pbrt {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    /// The extension of the file doesn't match any [`SceneFormat`].
+    UnknownFormat(PathBuf),
+    /// An object refers to a material that isn't part of the scene.
+    UnknownMaterial(String),
+    /// A texture, density grid, light profile or heightfield can't be read or doesn't fit on
+    /// the GPU.
+    Texture {
+        path: PathBuf,
+        message: String,
+    },
+    /// The [signed distance field](Sdf) of an object needs more distances kept while
+    /// evaluating it than the shaders have room for.
+    Sdf {
+        object: String,
+        message: String,
+    },
+    /// The [CSG expression](Csg) of an object combines more solids than the shaders have room
+    /// for.
+    Csg {
+        object: String,
+        message: String,
+    },
+    /// The [curves](Shape::Curves) or [points](Shape::Points) of an object have attributes that
+    /// don't go with their control points or positions.
+    Geometry {
+        object: String,
+        message: String,
+    },
+    /// The object to bake a [lightmap](crate::lightmap) of isn't part of the scene or has no
+    /// texture coordinates to lay it out.
+    Lightmap {
+        object: String,
+        message: String,
+    },
+    /// The scene takes more GPU memory than the
+    /// [budget](crate::renderer::RaytracingRenderer::set_memory_budget) even with its textures
+    /// streamed, or one of its buffers or textures is bigger than the device can bind.
+    TooLarge {
+        what: &'static str,
+        size: u64,
+        limit: u64,
+    },
+    /// A material of a [`MaterialLibrary`](crate::materials::MaterialLibrary) changed in a way
+    /// only setting the scene again can, see
+    /// [`update_materials`](crate::renderer::RaytracingRenderer::update_materials).
+    MaterialUpdate {
+        material: String,
+        message: String,
+    },
+    /// A [light link](LightLink) refers to a light that isn't part of the scene, or there are
+    /// more of them than the shaders have room for.
+    LightLink {
+        light: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Ron(err) => write!(f, "{err}"),
+            Self::RonSyntax(err) => write!(f, "{err}"),
+            Self::Json(err) => write!(f, "{err}"),
+            Self::Obj { path, source } => write!(f, "{}: {source}", path.display()),
+            Self::Pbrt {
+                path,
+                line: 0,
+                message,
+            } => write!(f, "{}: {message}", path.display()),
+            Self::Pbrt {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{line}: {message}", path.display()),
+            Self::UnknownFormat(path) => {
+                write!(f, "{}: unknown scene format", path.display())
+            }
+            Self::UnknownMaterial(name) => write!(f, "unknown material \"{name}\""),
+            Self::Texture { path, message } => write!(f, "{}: {message}", path.display()),
+            Self::Sdf { object, message }
+            | Self::Csg { object, message }
+            | Self::Geometry { object, message }
+            | Self::Lightmap { object, message } => {
+                write!(f, "object \"{object}\": {message}")
+            }
+            Self::TooLarge { what, size, limit } => write!(
+                f,
+                "{what} take {:.1} MiB of GPU memory, more than the {:.1} MiB available",
+                *size as f64 / 1048576.0,
+                *limit as f64 / 1048576.0
+            ),
+            Self::MaterialUpdate { material, message } => {
+                write!(f, "material \"{material}\": {message}")
+            }
+            Self::LightLink { light, message } => write!(f, "link of light {light}: {message}"),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Ron(err) => Some(err),
+            Self::RonSyntax(err) => Some(err),
+            Self::Json(err) => Some(err),
+            Self::Obj { source, .. } => Some(source),
+            Self::Pbrt { .. }
+            | Self::UnknownFormat(_)
+            | Self::UnknownMaterial(_)
+            | Self::Texture { .. }
+            | Self::Sdf { .. }
+            | Self::Csg { .. }
+            | Self::Geometry { .. }
+            | Self::Lightmap { .. }
+            | Self::TooLarge { .. }
+            | Self::MaterialUpdate { .. }
+            | Self::LightLink { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for SceneError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<ron::Error> for SceneError {
+    fn from(err: ron::Error) -> Self {
+        Self::Ron(err)
+    }
+}
+
+impl From<ron::error::SpannedError> for SceneError {
+    fn from(err: ron::error::SpannedError) -> Self {
+        Self::RonSyntax(err)
+    }
+}
+
+impl From<serde_json::Error> for SceneError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
@@ -0,0 +1,392 @@
+//! Procedural scenes built into the crate, to render something without writing a scene file
+//! and to have content that stays the same for tests and benchmarks.
+
+use crate::scene::{Camera, Material, MaterialOverrides, Object, Scene, Shape};
+
+/// Names of the scenes [`by_name`] knows.
+pub const NAMES: [&str; 4] = ["demo", "cornell-box", "random-spheres", "material-grid"];
+
+/// The scene called `name`, one of [`NAMES`], with [`random_spheres`] at seed zero.
+pub fn by_name(name: &str) -> Option<Scene> {
+    match name {
+        "demo" => Some(Scene::demo()),
+        "cornell-box" => Some(cornell_box()),
+        "random-spheres" => Some(random_spheres(0)),
+        "material-grid" => Some(material_grid()),
+        _ => None,
+    }
+}
+
+/// Uniform number from 0 to 1 for `i`, so that the scenes are the same every time.
+pub(crate) fn hash(i: u32) -> f32 {
+    let mut x = i.wrapping_mul(0x9e37_79b9) ^ 0x85eb_ca6b;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    (x >> 8) as f32 / 16_777_216.0
+}
+
+/// The Cornell box: a white room with a red wall on the left and a green one on the right,
+/// lit by a square light under the ceiling, open towards the camera. A mirror sphere and a
+/// glass one stand on the floor.
+pub fn cornell_box() -> Scene {
+    let quad = |name: &str, material: &str, corners: [[f32; 3]; 4]| Object {
+        name: name.to_owned(),
+        material: Some(material.to_owned()),
+        ..Object::new(Shape::Triangles {
+            positions: corners.to_vec(),
+            normals: Vec::new(),
+            texcoords: Vec::new(),
+            indices: vec![0, 1, 2, 0, 2, 3],
+        })
+    };
+    let sphere = |name: &str, material: &str, center, radius| Object {
+        name: name.to_owned(),
+        material: Some(material.to_owned()),
+        ..Object::new(Shape::Sphere { center, radius })
+    };
+    let diffuse = |name: &str, base_color| Material {
+        name: name.to_owned(),
+        base_color,
+        specular: 0.0,
+        ..Default::default()
+    };
+
+    Scene {
+        camera: Camera {
+            position: [0.0, 1.0, 3.9],
+            look_at: [0.0, 1.0, 0.0],
+            fov: 40.0,
+            ..Default::default()
+        },
+        materials: vec![
+            diffuse("white", [0.73, 0.73, 0.73]),
+            diffuse("red", [0.65, 0.05, 0.05]),
+            diffuse("green", [0.12, 0.45, 0.15]),
+            Material {
+                name: "light".to_owned(),
+                base_color: [0.0, 0.0, 0.0],
+                emission: [17.0, 12.0, 4.0],
+                ..Default::default()
+            },
+            Material {
+                name: "mirror".to_owned(),
+                base_color: [0.9, 0.9, 0.9],
+                metallic: 1.0,
+                roughness: 0.0,
+                ..Default::default()
+            },
+            Material {
+                name: "glass".to_owned(),
+                base_color: [1.0, 1.0, 1.0],
+                roughness: 0.0,
+                transmission: 1.0,
+                ..Default::default()
+            },
+        ],
+        objects: vec![
+            quad(
+                "floor",
+                "white",
+                [
+                    [-1.0, 0.0, 1.0],
+                    [1.0, 0.0, 1.0],
+                    [1.0, 0.0, -1.0],
+                    [-1.0, 0.0, -1.0],
+                ],
+            ),
+            quad(
+                "ceiling",
+                "white",
+                [
+                    [-1.0, 2.0, 1.0],
+                    [-1.0, 2.0, -1.0],
+                    [1.0, 2.0, -1.0],
+                    [1.0, 2.0, 1.0],
+                ],
+            ),
+            quad(
+                "back",
+                "white",
+                [
+                    [-1.0, 0.0, -1.0],
+                    [1.0, 0.0, -1.0],
+                    [1.0, 2.0, -1.0],
+                    [-1.0, 2.0, -1.0],
+                ],
+            ),
+            quad(
+                "left",
+                "red",
+                [
+                    [-1.0, 0.0, 1.0],
+                    [-1.0, 0.0, -1.0],
+                    [-1.0, 2.0, -1.0],
+                    [-1.0, 2.0, 1.0],
+                ],
+            ),
+            quad(
+                "right",
+                "green",
+                [
+                    [1.0, 0.0, -1.0],
+                    [1.0, 0.0, 1.0],
+                    [1.0, 2.0, 1.0],
+                    [1.0, 2.0, -1.0],
+                ],
+            ),
+            quad(
+                "light",
+                "light",
+                [
+                    [-0.25, 1.99, 0.25],
+                    [-0.25, 1.99, -0.25],
+                    [0.25, 1.99, -0.25],
+                    [0.25, 1.99, 0.25],
+                ],
+            ),
+            sphere("mirror sphere", "mirror", [-0.45, 0.35, -0.4], 0.35),
+            sphere("glass sphere", "glass", [0.45, 0.35, 0.2], 0.35),
+        ],
+        ..Default::default()
+    }
+}
+
+/// The final scene of "Ray Tracing in One Weekend" (Shirley 2016): a field of small spheres,
+/// mostly diffuse with some metal and glass ones of random colors and roughnesses, around a
+/// large glass, diffuse and metal sphere each, on a grey ground. `seed` picks the small
+/// spheres.
+pub fn random_spheres(seed: u32) -> Scene {
+    let material = |name: &str, material: Material| Material {
+        name: name.to_owned(),
+        ..material
+    };
+    let materials = vec![
+        material(
+            "ground",
+            Material {
+                base_color: [0.5, 0.5, 0.5],
+                specular: 0.0,
+                ..Default::default()
+            },
+        ),
+        material(
+            "diffuse",
+            Material {
+                specular: 0.0,
+                ..Default::default()
+            },
+        ),
+        material(
+            "metal",
+            Material {
+                metallic: 1.0,
+                ..Default::default()
+            },
+        ),
+        material(
+            "glass",
+            Material {
+                base_color: [1.0, 1.0, 1.0],
+                roughness: 0.0,
+                transmission: 1.0,
+                ..Default::default()
+            },
+        ),
+    ];
+    let sphere = |name: String, material: &str, center, radius, overrides| Object {
+        name,
+        material: Some(material.to_owned()),
+        overrides,
+        ..Object::new(Shape::Sphere { center, radius })
+    };
+
+    let mut objects = vec![sphere(
+        "ground".to_owned(),
+        "ground",
+        [0.0, -1000.0, 0.0],
+        1000.0,
+        MaterialOverrides::default(),
+    )];
+    let mut index = seed.wrapping_mul(0x2c1b_3c6d);
+    let mut random = || {
+        index = index.wrapping_add(1);
+        hash(index)
+    };
+    for a in -11..11 {
+        for b in -11..11 {
+            let choice = random();
+            let center = [a as f32 + 0.9 * random(), 0.2, b as f32 + 0.9 * random()];
+            let [dx, dz] = [center[0] - 4.0, center[2]];
+            if dx * dx + dz * dz <= 0.81 {
+                continue;
+            }
+
+            let name = format!("sphere {a} {b}");
+            objects.push(if choice < 0.8 {
+                let color = [0; 3].map(|_| random() * random());
+                let overrides = MaterialOverrides {
+                    base_color: Some(color),
+                    ..Default::default()
+                };
+                sphere(name, "diffuse", center, 0.2, overrides)
+            } else if choice < 0.95 {
+                let color = [0; 3].map(|_| 0.5 + 0.5 * random());
+                let overrides = MaterialOverrides {
+                    base_color: Some(color),
+                    roughness: Some(0.5 * random()),
+                    ..Default::default()
+                };
+                sphere(name, "metal", center, 0.2, overrides)
+            } else {
+                sphere(name, "glass", center, 0.2, MaterialOverrides::default())
+            });
+        }
+    }
+    objects.extend([
+        sphere(
+            "glass sphere".to_owned(),
+            "glass",
+            [0.0, 1.0, 0.0],
+            1.0,
+            MaterialOverrides::default(),
+        ),
+        sphere(
+            "diffuse sphere".to_owned(),
+            "diffuse",
+            [-4.0, 1.0, 0.0],
+            1.0,
+            MaterialOverrides {
+                base_color: Some([0.4, 0.2, 0.1]),
+                ..Default::default()
+            },
+        ),
+        sphere(
+            "metal sphere".to_owned(),
+            "metal",
+            [4.0, 1.0, 0.0],
+            1.0,
+            MaterialOverrides {
+                base_color: Some([0.7, 0.6, 0.5]),
+                roughness: Some(0.0),
+                ..Default::default()
+            },
+        ),
+    ]);
+
+    Scene {
+        camera: Camera {
+            position: [13.0, 2.0, 3.0],
+            look_at: [0.0, 0.0, 0.0],
+            fov: 20.0,
+            ..Default::default()
+        },
+        materials,
+        objects,
+        ..Default::default()
+    }
+}
+
+/// Spheres showing how the parameters of materials combine, on a grey ground lit by the sky:
+/// the roughness goes from zero on the left to one on the right, and the rows from the top
+/// are metal, plastic, glass and clear coat over a rough base.
+pub fn material_grid() -> Scene {
+    const COLUMNS: usize = 6;
+    const ROWS: usize = 4;
+    let rows: [_; ROWS] = [
+        (
+            "metal",
+            Material {
+                base_color: [0.95, 0.64, 0.54],
+                metallic: 1.0,
+                ..Default::default()
+            },
+        ),
+        (
+            "plastic",
+            Material {
+                base_color: [0.7, 0.1, 0.1],
+                ..Default::default()
+            },
+        ),
+        (
+            "glass",
+            Material {
+                base_color: [1.0, 1.0, 1.0],
+                transmission: 1.0,
+                ..Default::default()
+            },
+        ),
+        (
+            "clear coat",
+            Material {
+                base_color: [0.1, 0.2, 0.6],
+                roughness: 0.8,
+                clearcoat: 1.0,
+                ..Default::default()
+            },
+        ),
+    ];
+
+    let mut materials = vec![Material {
+        name: "ground".to_owned(),
+        base_color: [0.4, 0.4, 0.4],
+        specular: 0.0,
+        ..Default::default()
+    }];
+    let mut objects = vec![Object {
+        name: "ground".to_owned(),
+        material: Some("ground".to_owned()),
+        ..Object::new(Shape::Sphere {
+            center: [0.0, -1000.0, 0.0],
+            radius: 1000.0,
+        })
+    }];
+    for (row, (name, material)) in rows.into_iter().enumerate() {
+        // Clear coats vary the roughness of the coat, the others the one of the surface.
+        let roughness = |roughness| match name {
+            "clear coat" => MaterialOverrides {
+                clearcoat_roughness: Some(roughness),
+                ..Default::default()
+            },
+            _ => MaterialOverrides {
+                roughness: Some(roughness),
+                ..Default::default()
+            },
+        };
+        for column in 0..COLUMNS {
+            let t = column as f32 / (COLUMNS - 1) as f32;
+            objects.push(Object {
+                name: format!("{name} {t}"),
+                material: Some(name.to_owned()),
+                overrides: roughness(t),
+                ..Object::new(Shape::Sphere {
+                    center: [
+                        column as f32 * 1.1 - (COLUMNS - 1) as f32 * 0.55,
+                        0.5 + (ROWS - 1 - row) as f32 * 1.1,
+                        0.0,
+                    ],
+                    radius: 0.5,
+                })
+            });
+        }
+        materials.push(Material {
+            name: name.to_owned(),
+            ..material
+        });
+    }
+
+    Scene {
+        camera: Camera {
+            position: [0.0, 2.2, 9.0],
+            look_at: [0.0, 2.2, 0.0],
+            fov: 40.0,
+            ..Default::default()
+        },
+        materials,
+        objects,
+        ..Default::default()
+    }
+}
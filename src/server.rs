@@ -0,0 +1,362 @@
+//! Render service over HTTP, e.g. to run headless render boxes that other machines submit jobs
+//! to.
+//!
+//! [`serve`] answers a small JSON API while rendering the submitted jobs one at a time, in the
+//! order they came in:
+//!
+//! - `POST /jobs` with an object holding the [`RenderSettings`] of a render as `settings` and
+//!   optionally the [`Scene`] to render as `scene` submits a job, answering with its `id`.
+//!   Jobs without a scene render the one of the job before them.
+//! - `GET /jobs/<id>` answers with the `state` of the job, `queued`, `rendering`, `done`,
+//!   `failed` or `expired`, the `progress` of its render from 0 to 1 and the `error` it failed
+//!   with.
+//! - `GET /jobs/<id>/image` answers with the PNG image of a job that's done.
+//! - `DELETE /jobs/<id>` forgets a job along with its image.
+//!
+//! The images of the last 32 finished jobs are kept until they're deleted, older
+//! jobs expire and lose their image as newer ones finish.
+//!
+//! Paths to meshes and textures in scenes are read on the machine of the server,
+//! [inline](Scene::inline_meshes) the meshes of scenes to submit them from elsewhere.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use image::{codecs::png::PngEncoder, ImageEncoder};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    binary::invalid_data, renderer::RaytracingRenderer, scene::Scene, settings::RenderSettings,
+};
+
+/// Largest request body accepted, enough for scenes with a few million triangles inlined.
+const MAX_BODY_SIZE: u64 = 64 << 20;
+/// Largest request line and headers accepted, together.
+const MAX_HEAD_SIZE: u64 = 64 << 10;
+/// Most headers accepted in a request.
+const MAX_HEADERS: usize = 64;
+/// How long a connection may wait on the client to send or receive more before it's dropped.
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// Most images of finished jobs kept at once.
+const MAX_IMAGES: usize = 32;
+
+/// Body of `POST /jobs`.
+#[derive(Deserialize)]
+struct JobRequest {
+    #[serde(default)]
+    scene: Option<Scene>,
+    #[serde(default)]
+    settings: RenderSettings,
+}
+
+enum JobState {
+    Queued,
+    /// Fraction of the render done so far.
+    Rendering(f64),
+    /// PNG image of the render.
+    Done(Vec<u8>),
+    Failed(String),
+    /// Done, but the image was dropped to make room for the ones of newer jobs.
+    Expired,
+}
+
+/// States of the jobs by id, `None` once deleted.
+type Jobs = Mutex<Vec<Option<JobState>>>;
+
+/// Answers HTTP requests on `listener` forever, rendering the jobs submitted through them with
+/// `renderer`.
+///
+/// Every connection is answered on a thread of its own while the jobs render on the calling
+/// thread, one request per connection. A failing request, or a client that doesn't send or
+/// receive anything for 30 seconds, is logged and dropped.
+pub fn serve(renderer: &RaytracingRenderer, listener: TcpListener) -> io::Result<()> {
+    let jobs = Mutex::new(Vec::new());
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        let jobs = &jobs;
+        let listener = &listener;
+        let requests = scope.spawn(move || -> io::Result<()> {
+            for stream in listener.incoming() {
+                let (stream, sender) = (stream?, sender.clone());
+                scope.spawn(move || {
+                    if let Err(err) = handle_connection(renderer, stream, jobs, &sender) {
+                        tracing::warn!(%err, "Request failed");
+                    }
+                });
+            }
+            Ok(())
+        });
+
+        // Ends once the request thread does, dropping the sender.
+        for (id, request) in receiver {
+            render_job(renderer, jobs, id, request);
+        }
+        requests
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+/// Renders the job `id` unless it was deleted, keeping its state up to date.
+fn render_job(renderer: &RaytracingRenderer, jobs: &Jobs, id: usize, request: JobRequest) {
+    let update = |state| {
+        if let Some(job @ Some(_)) = jobs.lock().unwrap().get_mut(id) {
+            *job = Some(state);
+        }
+    };
+    if jobs.lock().unwrap()[id].is_none() {
+        return;
+    }
+    let _span = tracing::info_span!("server_job", id).entered();
+    update(JobState::Rendering(0.0));
+
+    if let Some(scene) = &request.scene {
+        if let Err(err) = renderer.set_scene(scene) {
+            tracing::warn!(%err, "Setting the scene failed");
+            return update(JobState::Failed(err.to_string()));
+        }
+    }
+    let settings = &request.settings;
+    let pixels = async_std::task::block_on(renderer.render_with_progress(settings, |progress| {
+        update(JobState::Rendering(progress.fraction()))
+    }));
+
+    let mut png = Vec::new();
+    let encoded = PngEncoder::new(&mut png).write_image(
+        &pixels,
+        settings.width,
+        settings.height,
+        image::ColorType::Rgba8,
+    );
+    update(match encoded {
+        Ok(()) => JobState::Done(png),
+        Err(err) => JobState::Failed(err.to_string()),
+    });
+
+    // Jobs finish in the order of their ids, the newest images come last.
+    let mut jobs = jobs.lock().unwrap();
+    let done = jobs
+        .iter_mut()
+        .rev()
+        .flatten()
+        .filter(|job| matches!(job, JobState::Done(_)));
+    for job in done.skip(MAX_IMAGES) {
+        *job = JobState::Expired;
+    }
+}
+
+/// HTTP request, only as much of it as the API needs.
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+fn handle_connection(
+    renderer: &RaytracingRenderer,
+    stream: TcpStream,
+    jobs: &Jobs,
+    sender: &mpsc::Sender<(usize, JobRequest)>,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let request = read_request(&mut reader)?;
+    tracing::debug!(method = request.method, path = request.path, "Request");
+
+    let (status, content_type, body) = respond(renderer, &request, jobs, sender);
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    stream.flush()
+}
+
+fn read_request(reader: &mut impl BufRead) -> io::Result<Request> {
+    let mut head = reader.by_ref().take(MAX_HEAD_SIZE);
+    let mut read_line = || {
+        let mut line = String::new();
+        head.read_line(&mut line)?;
+        if line.ends_with('\n') {
+            Ok(line)
+        } else if head.limit() == 0 {
+            Err(invalid_data("Request head too large"))
+        } else {
+            Err(io::Error::from(io::ErrorKind::UnexpectedEof))
+        }
+    };
+
+    let line = read_line()?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(invalid_data("Malformed request line"));
+    };
+
+    let mut length = 0;
+    for headers in 0.. {
+        let header = read_line()?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if headers == MAX_HEADERS {
+            return Err(invalid_data("Too many request headers"));
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid_data("Malformed Content-Length"))?;
+            }
+        }
+    }
+    if length > MAX_BODY_SIZE {
+        return Err(invalid_data("Request body too large"));
+    }
+
+    // Grows as the body comes in rather than trusting the length with the allocation.
+    let mut body = Vec::new();
+    reader.take(length).read_to_end(&mut body)?;
+    if body.len() as u64 != length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(Request {
+        method: method.to_owned(),
+        path: path.to_owned(),
+        body,
+    })
+}
+
+/// Status, content type and body of the response to `request`.
+fn respond(
+    renderer: &RaytracingRenderer,
+    request: &Request,
+    jobs: &Jobs,
+    sender: &mpsc::Sender<(usize, JobRequest)>,
+) -> (&'static str, &'static str, Vec<u8>) {
+    let json = |status, value: serde_json::Value| {
+        (status, "application/json", value.to_string().into_bytes())
+    };
+    let error = |status, message: &str| json(status, json!({ "error": message }));
+    let not_found = || error("404 Not Found", "No such job");
+
+    let segments: Vec<_> = request.path.trim_matches('/').split('/').collect();
+    let id = match segments.get(1).map(|id| id.parse::<usize>()) {
+        Some(Ok(id)) => Some(id),
+        Some(Err(_)) => return not_found(),
+        None => None,
+    };
+    // Parses and validates new jobs before locking, to not hold up the progress of renders.
+    match (request.method.as_str(), segments.as_slice()) {
+        ("POST", ["jobs"]) => {
+            let job: JobRequest = match serde_json::from_slice(&request.body) {
+                Ok(job) => job,
+                Err(err) => return error("400 Bad Request", &err.to_string()),
+            };
+            if let Err(err) = renderer.validate_settings(&job.settings) {
+                return error("400 Bad Request", &err.to_string());
+            }
+            let mut jobs = jobs.lock().unwrap();
+            jobs.push(Some(JobState::Queued));
+            let id = jobs.len() - 1;
+            // The renders only stop once requests aren't answered anymore.
+            let _ = sender.send((id, job));
+            json("202 Accepted", json!({ "id": id }))
+        }
+        ("GET", ["jobs", _]) => {
+            let jobs = jobs.lock().unwrap();
+            let Some(Some(job)) = id.and_then(|id| jobs.get(id)) else {
+                return not_found();
+            };
+            let (state, progress, error) = match job {
+                JobState::Queued => ("queued", 0.0, None),
+                JobState::Rendering(progress) => ("rendering", *progress, None),
+                JobState::Done(_) => ("done", 1.0, None),
+                JobState::Failed(error) => ("failed", 0.0, Some(error)),
+                JobState::Expired => ("expired", 1.0, None),
+            };
+            json(
+                "200 OK",
+                json!({ "state": state, "progress": progress, "error": error }),
+            )
+        }
+        ("GET", ["jobs", _, "image"]) => {
+            let jobs = jobs.lock().unwrap();
+            match id.and_then(|id| jobs.get(id)) {
+                Some(Some(JobState::Done(png))) => ("200 OK", "image/png", png.clone()),
+                Some(Some(JobState::Expired)) => error("410 Gone", "The image of the job expired"),
+                Some(Some(_)) => error("409 Conflict", "The job isn't done"),
+                _ => not_found(),
+            }
+        }
+        ("DELETE", ["jobs", _]) => {
+            let mut jobs = jobs.lock().unwrap();
+            match id.and_then(|id| jobs.get_mut(id)) {
+                Some(job @ Some(_)) => {
+                    *job = None;
+                    ("204 No Content", "application/json", Vec::new())
+                }
+                _ => not_found(),
+            }
+        }
+        _ => error("404 Not Found", "No such endpoint"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(request: &[u8]) -> io::Result<Request> {
+        read_request(&mut BufReader::new(request))
+    }
+
+    #[test]
+    fn reads_requests() {
+        let request = read(b"POST /jobs HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}").unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/jobs");
+        assert_eq!(request.body, b"{}");
+    }
+
+    #[test]
+    fn rejects_oversized_requests() {
+        let kind = |request: &[u8]| read(request).err().map(|err| err.kind());
+
+        let long_line = [b'a'; MAX_HEAD_SIZE as usize];
+        assert_eq!(kind(&long_line), Some(io::ErrorKind::InvalidData));
+
+        let mut headers = b"GET /jobs/0 HTTP/1.1\r\n".to_vec();
+        for _ in 0..=MAX_HEADERS {
+            headers.extend(b"X-Header: 1\r\n");
+        }
+        headers.extend(b"\r\n");
+        assert_eq!(kind(&headers), Some(io::ErrorKind::InvalidData));
+
+        let body = format!(
+            "POST /jobs HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_SIZE + 1
+        );
+        assert_eq!(kind(body.as_bytes()), Some(io::ErrorKind::InvalidData));
+        assert_eq!(
+            kind(b"POST /jobs HTTP/1.1\r\nContent-Length: 4\r\n\r\n{}"),
+            Some(io::ErrorKind::UnexpectedEof)
+        );
+        assert_eq!(
+            kind(b"GET /jobs HTTP/1.1\r\n"),
+            Some(io::ErrorKind::UnexpectedEof)
+        );
+    }
+}
@@ -0,0 +1,479 @@
+use std::{error::Error, fmt};
+
+use cgmath::Matrix4;
+use serde::{Deserialize, Serialize};
+
+use crate::graph::{GraphError, PassDeclaration, RenderGraph};
+
+/// Parameters of a single render.
+///
+/// Fields missing from a [scene file](crate::scene::Scene) keep their default values.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    /// Width of the output image in pixels.
+    pub width: u32,
+    /// Height of the output image in pixels.
+    pub height: u32,
+    /// Number of samples accumulated for every pixel.
+    pub samples_per_pixel: u32,
+    /// Side of the square tiles the image is rendered in, in pixels.
+    pub tile_size: u32,
+    /// Seed of the per-pixel random number generators.
+    pub seed: u32,
+    /// Maximum number of times a path bounces off surfaces before it's terminated.
+    pub max_bounces: u32,
+    /// Accumulate samples as RGBA16F instead of RGBA32F, halving the memory and bandwidth of
+    /// the accumulation, e.g. for interactive previews.
+    ///
+    /// Final renders are promoted to full precision regardless: HDR and checkpointed ones, and
+    /// ones taking more samples per pixel than half precision can count.
+    pub half_precision_accumulation: bool,
+    /// How the light reaching the camera is computed.
+    pub integrator: Integrator,
+    /// How the samples of a pixel are spread over and around it and weighted.
+    pub filter: PixelFilter,
+    /// Leave the pixels camera rays see the environment through transparent instead of
+    /// showing the sky there, to composite the render over other imagery.
+    ///
+    /// Images are premultiplied by their alpha either way, and holdouts and shadow catchers
+    /// make pixels transparent regardless.
+    pub transparent_background: bool,
+    /// Photographic exposure scaling the light reaching the camera, for scenes lit in physical
+    /// units. Without one the radiance is written out as it is.
+    pub exposure: Option<Exposure>,
+    /// Distance in scene units along rays within which hits are ignored, which keeps rays from
+    /// hitting the surfaces they leave. Scenes much smaller than a unit need a smaller one to
+    /// keep light from leaking through thin walls and corners.
+    pub ray_epsilon: f32,
+    /// How far rays leaving surfaces start off them, in multiples of an offset that grows with
+    /// the magnitude of the coordinates of the surfaces so that it holds at any scale of the
+    /// scene. Raise it for shadow acne on large scenes, zero starts rays on the surfaces.
+    pub ray_offset: f32,
+    /// Passes every tile goes through, to reorder, leave out or add to the built-in ones.
+    /// Renders that can fail return the error if the renderer can't
+    /// [validate](crate::renderer::RaytracingRenderer::validate_graph) it, the others log it and
+    /// render with the default graph.
+    pub graph: RenderGraph,
+}
+
+/// Exposure of a camera from its photographic parameters, which brings scenes lit in physical
+/// units, like a sun of around 100000 lux, to the brightness of a photograph taken with the
+/// same settings. The defaults are the "sunny 16" rule for daylight.
+///
+/// Renders have neither depth of field nor motion blur, so the aperture and shutter only
+/// change the brightness. Ambient occlusion and debug views aren't exposed.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Exposure {
+    /// Sensitivity of the sensor.
+    pub iso: f32,
+    /// Focal length over the diameter of the aperture.
+    pub f_number: f32,
+    /// How long the shutter is open, in seconds.
+    pub shutter: f32,
+}
+
+impl Exposure {
+    /// Exposure value at ISO 100 of the settings.
+    pub fn ev100(self) -> f32 {
+        (self.f_number * self.f_number / self.shutter * 100.0 / self.iso).log2()
+    }
+
+    /// Factor the radiance reaching the camera is scaled by: the luminance that just saturates
+    /// the sensor (Lagarde and de Rousiers 2014) becomes one.
+    pub fn scale(self) -> f32 {
+        1.0 / (1.2 * self.ev100().exp2())
+    }
+}
+
+impl Default for Exposure {
+    fn default() -> Self {
+        Self {
+            iso: 100.0,
+            f_number: 16.0,
+            shutter: 1.0 / 100.0,
+        }
+    }
+}
+
+/// Reconstruction filter of the pixels of an image.
+///
+/// Every sample of a pixel is taken at a uniformly random spot within `radius` pixels of its
+/// center along both axes and weighted by the filter there, so wider filters blend in light
+/// from the neighbouring pixels without any sample being shared between pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PixelFilter {
+    /// Weighs all the samples the same. With the default radius of half a pixel every pixel
+    /// is the plain mean of the light arriving over its area.
+    Box { radius: f32 },
+    /// A Gaussian with a standard deviation of a third of the radius, shifted down to reach
+    /// zero there. Softer than the others, with no ringing.
+    Gaussian { radius: f32 },
+    /// The cubic of "Reconstruction Filters in Computer Graphics" (Mitchell and Netravali
+    /// 1988) with B = C = 1/3, stretched over the radius. Sharp, with slightly negative lobes
+    /// that ring faintly around high contrast edges.
+    Mitchell { radius: f32 },
+    /// The four term Blackman-Harris window, close to a Gaussian but dropping to zero more
+    /// smoothly.
+    BlackmanHarris { radius: f32 },
+}
+
+impl PixelFilter {
+    pub fn radius(self) -> f32 {
+        match self {
+            Self::Box { radius }
+            | Self::Gaussian { radius }
+            | Self::Mitchell { radius }
+            | Self::BlackmanHarris { radius } => radius,
+        }
+    }
+}
+
+impl Default for PixelFilter {
+    fn default() -> Self {
+        Self::Box { radius: 0.5 }
+    }
+}
+
+/// Light transport a render computes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Integrator {
+    /// Full global illumination, bouncing paths up to
+    /// [`max_bounces`](RenderSettings::max_bounces) times.
+    #[default]
+    PathTracing,
+    /// Only the ambient occlusion of the surfaces the camera sees: the fraction of `rays`
+    /// cosine distributed rays leaving them that don't hit anything within `max_distance`.
+    /// Materials, lights and the sky are ignored, which makes it a quick preview of the
+    /// geometry or a pass to composite.
+    AmbientOcclusion { rays: u32, max_distance: f32 },
+    /// Path tracing plus the caustics point and directional lights cast through specular
+    /// surfaces, which paths can't find on their own, by progressive photon mapping. Every
+    /// sample traces `photons` photons from the lights and gathers the ones landing on diffuse
+    /// surfaces within `radius` of where the camera paths hit them. The radius shrinks with
+    /// every sample, so the blur of the estimates converges away.
+    ///
+    /// Photons are gathered where next event estimation samples the lights, so the `NEE`
+    /// shader define has to stay on.
+    PhotonMapping { photons: u32, radius: f32 },
+    /// Bidirectional path tracing: every sample also traces a path from the point and
+    /// directional lights, and the diffuse surfaces the camera paths hit connect to the diffuse
+    /// ones it reached. That finds light coming through small openings or bouncing off lit
+    /// surfaces where next event estimation alone mostly lands in shadow.
+    ///
+    /// The connections are made along with next event estimation, so the `NEE` shader define
+    /// has to stay on, and they're skipped in scenes with fog.
+    Bidirectional,
+    /// Path tracing with the direct light of the surfaces the camera sees sampled by
+    /// reservoir-based spatiotemporal importance resampling (ReSTIR, Bitterli et al. 2020): one
+    /// shadow ray picked out of `candidates` lights, reweighted by the picks of the previous
+    /// renders at the same pixel and of the neighbouring pixels. That keeps scenes with many
+    /// lights interactive at a single sample per pixel, when rendering the same image over and
+    /// over.
+    ///
+    /// With `indirect` set the light those surfaces get from the next bounce is resampled the
+    /// same way (ReSTIR GI, Ouyang et al. 2021), reconnecting them to the hits of the bounces
+    /// of the neighbouring and previous paths. That part is skipped in spectral renders and
+    /// scenes with fog.
+    ///
+    /// It takes the place of next event estimation at those surfaces, so the `NEE` shader
+    /// define has to stay on.
+    Restir { candidates: u32, indirect: bool },
+    /// Path tracing with the diffuse bounces guided towards the light arriving at the surfaces
+    /// (after "Practical Path Guiding for Efficient Light-Transport Simulation", Müller et al.
+    /// 2017): a grid over the scene holds a histogram of the directions light came from in
+    /// every cell, learned from the paths of the previous samples, and half of the bounces pick
+    /// from it instead of the diffuse lobe. That helps scenes lit indirectly through a few
+    /// bright spots, which paths bouncing at random rarely find.
+    ///
+    /// The guide is learned along with next event estimation, so the `NEE` shader define has to
+    /// stay on.
+    PathGuiding,
+    /// Path tracing in two passes over every tile: the first `cache_samples` samples trace the
+    /// paths as usual while learning the light arriving at the diffuse surfaces they bounce off
+    /// in an irradiance cache, a grid over the scene split by the direction the surfaces face.
+    /// The samples after trace a bounce off the diffuse surfaces the camera sees, the final
+    /// gather, and end the paths at the next diffuse surface with the light of the cache,
+    /// interpolated between its cells. The cache is kept from one tile to the next.
+    ///
+    /// That trades a little blur and bias in the indirect light for far fewer bounces per
+    /// sample, which pays off in diffuse interiors lit through many bounces. Paths go on
+    /// bouncing where the cache hasn't learned anything and in spectral renders.
+    ///
+    /// The cache is learned along with next event estimation, so the `NEE` shader define has
+    /// to stay on.
+    FinalGather { cache_samples: u32 },
+    /// A debug view of the surfaces the camera sees instead of their shading.
+    Debug(DebugView),
+}
+
+/// What [`Integrator::Debug`] shows, e.g. to diagnose broken imports or acceleration
+/// structures.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DebugView {
+    /// Shading normals, mapped from -1..1 to 0..1 per channel.
+    Normals,
+    /// A checker over the texture coordinates of meshes, or the parametric coordinates of
+    /// primitives without any, barycentric ones for triangles and longitude and latitude for
+    /// spheres.
+    Uv,
+    /// Linear depth, from black at the camera to white at `max_distance` and beyond.
+    Depth { max_distance: f32 },
+    /// Edges of the bounds of the BVH nodes in front of the surfaces.
+    BvhBounds,
+    /// Heatmap of how many BVH nodes and primitives the camera rays are tested against, from
+    /// blue for none to red for `max_tests` and more. Hot spots show where the BVH separates
+    /// the primitives poorly.
+    TraversalHeatmap { max_tests: u32 },
+}
+
+/// Depth convention of a raster depth buffer, for
+/// [`render_depth`](crate::renderer::RaytracingRenderer::render_depth) to write depths that ray
+/// traced images can be composited with raster passes by.
+///
+/// Depths are those of a perspective projection onto a zero to one depth range, like Direct3D,
+/// Metal, Vulkan and WebGPU use, from `near` to `far` along the forward axis of the camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthBuffer {
+    pub near: f32,
+    /// Distance of the far plane, which can be infinite.
+    pub far: f32,
+    /// Puts the near plane at one and the far plane at zero, spreading the precision of float
+    /// depth buffers much more evenly along the view.
+    pub reverse_z: bool,
+}
+
+impl DepthBuffer {
+    /// Depth buffer value of a surface `distance` along the forward axis of the camera, that
+    /// of the far plane for distances beyond it. Surfaces before the near plane are clamped to
+    /// it rather than clipped.
+    pub fn depth(self, distance: f32) -> f32 {
+        let Self { near, far, .. } = self;
+        let far_depth = if self.reverse_z { 0.0 } else { 1.0 };
+        if distance >= far {
+            return far_depth;
+        }
+        let depth = if far.is_infinite() {
+            near / distance
+        } else {
+            near * (far - distance) / (distance * (far - near))
+        };
+        let depth = depth.clamp(0.0, 1.0);
+        if self.reverse_z {
+            depth
+        } else {
+            1.0 - depth
+        }
+    }
+
+    /// Projection matrix of a raster camera producing the same depths, for a right-handed view
+    /// space looking down -Z like [`Camera::view_matrix`](crate::scene::Camera::view_matrix),
+    /// with a vertical field of view of `fov` degrees and `aspect_ratio` width over height.
+    #[rustfmt::skip]
+    pub fn projection(self, fov: f32, aspect_ratio: f32) -> Matrix4<f32> {
+        let Self { near, far, .. } = self;
+        let y = 1.0 / (fov.to_radians() * 0.5).tan();
+        let x = y / aspect_ratio;
+        let (z, w) = match (self.reverse_z, far.is_infinite()) {
+            (false, false) => (far / (near - far), near * far / (near - far)),
+            (false, true) => (-1.0, -near),
+            (true, false) => (near / (far - near), near * far / (far - near)),
+            (true, true) => (0.0, near),
+        };
+        // Columns, which cgmath takes one after the other
+        Matrix4::new(
+            x, 0.0, 0.0, 0.0,
+            0.0, y, 0.0, 0.0,
+            0.0, 0.0, z, -1.0,
+            0.0, 0.0, w, 0.0,
+        )
+    }
+}
+
+/// Eyes of a stereo pair rendered by
+/// [`render_stereo`](crate::renderer::RaytracingRenderer::render_stereo), on either side of the
+/// camera of the scene.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stereo {
+    /// Interpupillary distance, how far apart the eyes are along the right of the camera, in
+    /// the units of the scene.
+    pub ipd: f32,
+    /// Distance ahead of the camera the eyes turn in to look at, where objects appear at the
+    /// depth of the screen. At infinity the eyes look straight ahead, parallel like the views of
+    /// head-mounted displays.
+    pub convergence: f32,
+}
+
+impl Stereo {
+    /// Offset of the position of `eye` from the camera along its right.
+    pub fn offset(self, eye: Eye) -> f32 {
+        match eye {
+            Eye::Left => -0.5 * self.ipd,
+            Eye::Right => 0.5 * self.ipd,
+        }
+    }
+}
+
+impl Default for Stereo {
+    /// Average adult eyes in meters, looking straight ahead.
+    fn default() -> Self {
+        Self {
+            ipd: 0.063,
+            convergence: f32::INFINITY,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+impl RenderSettings {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            ..Default::default()
+        }
+    }
+
+    /// Checks that the image and its tiles have pixels, that the image isn't larger than
+    /// [`MAX_IMAGE_SIZE`], that pixels get samples and that the graph is valid with the custom
+    /// passes `declaration` knows, see [`RenderGraph::validate`]. Settings from outside
+    /// the application, e.g. the ones submitted to render servers, are checked with
+    /// [`RaytracingRenderer::validate_settings`](crate::renderer::RaytracingRenderer::validate_settings)
+    /// before rendering them.
+    pub fn validate<'a>(
+        &self,
+        declaration: impl Fn(&str) -> Option<&'a PassDeclaration>,
+    ) -> Result<(), SettingsError> {
+        if self.width == 0 || self.height == 0 {
+            return Err(SettingsError::NoPixels);
+        }
+        let size = u64::from(self.width)
+            .checked_mul(u64::from(self.height))
+            .and_then(|pixels| pixels.checked_mul(4));
+        if size.is_none_or(|size| size > MAX_IMAGE_SIZE) {
+            return Err(SettingsError::TooLarge);
+        }
+        if self.samples_per_pixel == 0 {
+            return Err(SettingsError::NoSamples);
+        }
+        if self.tile_size == 0 {
+            return Err(SettingsError::NoTileSize);
+        }
+        self.graph
+            .validate(declaration)
+            .map_err(SettingsError::Graph)
+    }
+}
+
+/// Largest RGBA8 image [`RenderSettings::validate`] accepts in bytes, e.g. 16384 by 16384 pixels.
+pub const MAX_IMAGE_SIZE: u64 = 1 << 30;
+
+/// Why [`RenderSettings`] can't be rendered with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SettingsError {
+    /// The image is zero pixels wide or high.
+    NoPixels,
+    /// The image takes more than [`MAX_IMAGE_SIZE`] bytes.
+    TooLarge,
+    /// The pixels get zero samples.
+    NoSamples,
+    /// The tiles are zero pixels a side.
+    NoTileSize,
+    Graph(GraphError),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoPixels => write!(f, "The image has no pixels"),
+            Self::TooLarge => write!(f, "The image is larger than {MAX_IMAGE_SIZE} bytes"),
+            Self::NoSamples => write!(f, "The pixels get no samples"),
+            Self::NoTileSize => write!(f, "The tiles have no pixels"),
+            Self::Graph(err) => write!(f, "Invalid render graph: {err}"),
+        }
+    }
+}
+
+impl Error for SettingsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Graph(err) => Some(err),
+            Self::NoPixels | Self::TooLarge | Self::NoSamples | Self::NoTileSize => None,
+        }
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            width: 1024,
+            height: 1024,
+            samples_per_pixel: 16,
+            tile_size: 256,
+            seed: 0,
+            max_bounces: 8,
+            half_precision_accumulation: false,
+            integrator: Integrator::PathTracing,
+            filter: PixelFilter::default(),
+            transparent_background: false,
+            exposure: None,
+            ray_epsilon: 1.0e-3,
+            ray_offset: 1.0,
+            graph: RenderGraph::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::GraphPass;
+
+    #[test]
+    fn validate_rejects_settings_without_pixels() {
+        let validate = |settings: RenderSettings| settings.validate(|_| None);
+        assert_eq!(validate(RenderSettings::default()), Ok(()));
+        assert_eq!(
+            validate(RenderSettings::new(0, 16)),
+            Err(SettingsError::NoPixels)
+        );
+        assert_eq!(
+            validate(RenderSettings {
+                samples_per_pixel: 0,
+                ..Default::default()
+            }),
+            Err(SettingsError::NoSamples)
+        );
+        assert_eq!(
+            validate(RenderSettings {
+                tile_size: 0,
+                ..Default::default()
+            }),
+            Err(SettingsError::NoTileSize)
+        );
+        assert_eq!(
+            validate(RenderSettings {
+                graph: RenderGraph {
+                    tile: vec![GraphPass::Tonemap],
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+            Err(SettingsError::Graph(GraphError::NoReadback))
+        );
+    }
+
+    #[test]
+    fn validate_rejects_images_too_large() {
+        let validate = |width, height| RenderSettings::new(width, height).validate(|_| None);
+        assert_eq!(validate(16384, 16384), Ok(()));
+        assert_eq!(validate(16384, 16385), Err(SettingsError::TooLarge));
+        assert_eq!(validate(u32::MAX, u32::MAX), Err(SettingsError::TooLarge));
+    }
+}
@@ -0,0 +1,531 @@
+//! WGSL sources the compute pipelines are built from, either the ones built into the crate or
+//! ones loaded at runtime to iterate on them without rebuilding.
+//!
+//! Sources go through a small preprocessor before they're compiled, understanding these
+//! directives on lines of their own:
+//!
+//! - `#include "file.wgsl"` pastes another file of the same [`ShaderSources`], every file is
+//!   only included once
+//! - `#define NAME value` replaces the identifier `NAME` with `value` from then on, `#define
+//!   NAME` only defines it
+//! - `#undef NAME` removes a definition
+//! - `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif` keep lines depending on whether `NAME`
+//!   is defined
+//!
+//! Defines passed in from the host act as feature flags, e.g. `NEE` enables next event
+//! estimation, see `shade.wgsl` for all of them.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fmt, io,
+    path::PathBuf,
+};
+
+const BUILTIN: [(&str, &str); 50] = [
+    ("accumulate.wgsl", include_str!("shaders/accumulate.wgsl")),
+    (
+        "ambient_occlusion.wgsl",
+        include_str!("shaders/ambient_occlusion.wgsl"),
+    ),
+    ("bsdf.wgsl", include_str!("shaders/bsdf.wgsl")),
+    ("compact.wgsl", include_str!("shaders/compact.wgsl")),
+    ("connect.wgsl", include_str!("shaders/connect.wgsl")),
+    ("debug.wgsl", include_str!("shaders/debug.wgsl")),
+    (
+        "dispatch_args.wgsl",
+        include_str!("shaders/dispatch_args.wgsl"),
+    ),
+    ("filter.wgsl", include_str!("shaders/filter.wgsl")),
+    (
+        "final_gather.wgsl",
+        include_str!("shaders/final_gather.wgsl"),
+    ),
+    ("fog.wgsl", include_str!("shaders/fog.wgsl")),
+    ("frame.wgsl", include_str!("shaders/frame.wgsl")),
+    ("gather.wgsl", include_str!("shaders/gather.wgsl")),
+    (
+        "gi_reservoirs.wgsl",
+        include_str!("shaders/gi_reservoirs.wgsl"),
+    ),
+    ("guide.wgsl", include_str!("shaders/guide.wgsl")),
+    (
+        "hook_intersect.wgsl",
+        include_str!("shaders/hook_intersect.wgsl"),
+    ),
+    (
+        "hook_scatter.wgsl",
+        include_str!("shaders/hook_scatter.wgsl"),
+    ),
+    (
+        "irradiance_cache.wgsl",
+        include_str!("shaders/irradiance_cache.wgsl"),
+    ),
+    ("light_paths.wgsl", include_str!("shaders/light_paths.wgsl")),
+    ("light_trace.wgsl", include_str!("shaders/light_trace.wgsl")),
+    (
+        "lightmap_gen.wgsl",
+        include_str!("shaders/lightmap_gen.wgsl"),
+    ),
+    ("path_guide.wgsl", include_str!("shaders/path_guide.wgsl")),
+    ("paths.wgsl", include_str!("shaders/paths.wgsl")),
+    (
+        "photon_trace.wgsl",
+        include_str!("shaders/photon_trace.wgsl"),
+    ),
+    ("photons.wgsl", include_str!("shaders/photons.wgsl")),
+    ("primary_hit.wgsl", include_str!("shaders/primary_hit.wgsl")),
+    ("random.wgsl", include_str!("shaders/random.wgsl")),
+    ("ray_ahit.wgsl", include_str!("shaders/ray_ahit.wgsl")),
+    ("ray_chit.wgsl", include_str!("shaders/ray_chit.wgsl")),
+    ("ray_gen.wgsl", include_str!("shaders/ray_gen.wgsl")),
+    (
+        "ray_intersect.wgsl",
+        include_str!("shaders/ray_intersect.wgsl"),
+    ),
+    ("ray_miss.wgsl", include_str!("shaders/ray_miss.wgsl")),
+    ("reservoirs.wgsl", include_str!("shaders/reservoirs.wgsl")),
+    ("resolve.wgsl", include_str!("shaders/resolve.wgsl")),
+    (
+        "restir_gi_sample.wgsl",
+        include_str!("shaders/restir_gi_sample.wgsl"),
+    ),
+    (
+        "restir_gi_spatial.wgsl",
+        include_str!("shaders/restir_gi_spatial.wgsl"),
+    ),
+    (
+        "restir_gi_temporal.wgsl",
+        include_str!("shaders/restir_gi_temporal.wgsl"),
+    ),
+    (
+        "restir_spatial.wgsl",
+        include_str!("shaders/restir_spatial.wgsl"),
+    ),
+    (
+        "restir_temporal.wgsl",
+        include_str!("shaders/restir_temporal.wgsl"),
+    ),
+    ("scene.wgsl", include_str!("shaders/scene.wgsl")),
+    ("shade.wgsl", include_str!("shaders/shade.wgsl")),
+    ("shadow.wgsl", include_str!("shaders/shadow.wgsl")),
+    ("sort.wgsl", include_str!("shaders/sort.wgsl")),
+    ("sort_count.wgsl", include_str!("shaders/sort_count.wgsl")),
+    ("sort_scan.wgsl", include_str!("shaders/sort_scan.wgsl")),
+    (
+        "sort_scatter.wgsl",
+        include_str!("shaders/sort_scatter.wgsl"),
+    ),
+    ("spectral.wgsl", include_str!("shaders/spectral.wgsl")),
+    ("trace.wgsl", include_str!("shaders/trace.wgsl")),
+    ("trace_path.wgsl", include_str!("shaders/trace_path.wgsl")),
+    (
+        "trace_persistent.wgsl",
+        include_str!("shaders/trace_persistent.wgsl"),
+    ),
+    ("trace_rays.wgsl", include_str!("shaders/trace_rays.wgsl")),
+];
+
+/// Defines the built-in shaders are compiled with unless told otherwise.
+pub fn default_defines() -> BTreeMap<String, String> {
+    BTreeMap::from([("NEE".to_owned(), String::new())])
+}
+
+/// Extension points of the integrator that WGSL from the host can be spliced into, see
+/// [`RaytracingRenderer::set_shader_hook`](crate::renderer::RaytracingRenderer::set_shader_hook).
+///
+/// Hooks can use the structs, bindings and functions of `scene.wgsl`, `random.wgsl` and
+/// `ray_intersect.wgsl`, scatter hooks also the ones of `ray_chit.wgsl`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ShaderHook {
+    /// Intersects [`Shape::Custom`](crate::scene::Shape::Custom) primitives, defining
+    ///
+    /// ```wgsl
+    /// fn hook_intersect(primitive: Primitive, ray: Ray, dist_min: f32, dist_max: f32, rec: ptr<function, HitRecord>) -> bool
+    /// ```
+    ///
+    /// `primitive.v0.xyz` and `primitive.v1.xyz` are the bounds of the shape and `primitive.v2`
+    /// its data. Hits between `dist_min` and `dist_max` return true after filling in the
+    /// `distance`, `hit_point` and, through `set_face_normal`, the normal of `rec`, and
+    /// optionally its `uv` and the `texel_scale` of texture coordinates to world units.
+    Intersect,
+    /// Scatters paths off materials with a [`custom`](crate::scene::Material::custom) id,
+    /// defining
+    ///
+    /// ```wgsl
+    /// fn hook_scatter(material: Material, ray: Ray, rec: HitRecord, attenuation: ptr<function, vec3<f32>>, direction: ptr<function, vec3<f32>>) -> bool
+    /// ```
+    ///
+    /// Returning false absorbs the path, otherwise it continues in `direction` with its
+    /// throughput multiplied by `attenuation`. The emission of the material has already been
+    /// added.
+    Scatter,
+}
+
+impl ShaderHook {
+    /// File of the [`ShaderSources`] holding the hook.
+    pub fn file(self) -> &'static str {
+        match self {
+            Self::Intersect => "hook_intersect.wgsl",
+            Self::Scatter => "hook_scatter.wgsl",
+        }
+    }
+
+    /// Define telling the integrator the hook is there.
+    fn define(self) -> &'static str {
+        match self {
+            Self::Intersect => "HOOK_INTERSECT",
+            Self::Scatter => "HOOK_SCATTER",
+        }
+    }
+}
+
+/// Shader files of a [`RaytracingRenderer`](crate::renderer::RaytracingRenderer), by name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShaderSources {
+    files: BTreeMap<String, String>,
+}
+
+impl ShaderSources {
+    /// Kernel starting the paths at the camera.
+    pub const RAY_GEN: &'static str = "ray_gen.wgsl";
+
+    /// Kernel starting the paths at the texels of a lightmap instead, see
+    /// [`lightmap::bake`](crate::lightmap::bake).
+    pub const LIGHTMAP_GEN: &'static str = "lightmap_gen.wgsl";
+
+    /// Kernel finding the closest hits of the paths.
+    pub const TRACE: &'static str = "trace.wgsl";
+
+    /// Kernel finding the closest hits of the paths with persistent threads.
+    pub const TRACE_PERSISTENT: &'static str = "trace_persistent.wgsl";
+
+    /// Kernel finding the closest hits of rays from the application, see
+    /// [`trace_rays`](crate::renderer::RaytracingRenderer::trace_rays).
+    pub const TRACE_RAYS: &'static str = "trace_rays.wgsl";
+
+    /// Kernel finding the closest hits of the camera rays from the primitives rasterized in
+    /// their pixels instead, see
+    /// [`set_hybrid_primary`](crate::renderer::RaytracingRenderer::set_hybrid_primary).
+    pub const PRIMARY_HIT: &'static str = "primary_hit.wgsl";
+
+    /// Kernel shading the hits and extending the paths by a bounce.
+    pub const SHADE: &'static str = "shade.wgsl";
+
+    /// Kernel shading the hits with their ambient occlusion instead, see
+    /// [`Integrator::AmbientOcclusion`](crate::settings::Integrator::AmbientOcclusion).
+    pub const AMBIENT_OCCLUSION: &'static str = "ambient_occlusion.wgsl";
+
+    /// Kernel writing debug views of the hits instead, see
+    /// [`Integrator::Debug`](crate::settings::Integrator::Debug).
+    pub const DEBUG: &'static str = "debug.wgsl";
+
+    /// Kernel tracing the shadow rays of next event estimation.
+    pub const SHADOW: &'static str = "shadow.wgsl";
+
+    /// Kernels tracing the photons of a sample and gathering them at the diffuse hits, see
+    /// [`Integrator::PhotonMapping`](crate::settings::Integrator::PhotonMapping).
+    pub const PHOTON_TRACE: &'static str = "photon_trace.wgsl";
+    pub const GATHER: &'static str = "gather.wgsl";
+
+    /// Kernels tracing the paths from the lights of a sample and connecting the diffuse hits to
+    /// them, see [`Integrator::Bidirectional`](crate::settings::Integrator::Bidirectional).
+    pub const LIGHT_TRACE: &'static str = "light_trace.wgsl";
+    pub const CONNECT: &'static str = "connect.wgsl";
+
+    /// Kernels sampling the direct light of the first hits with ReSTIR, reusing the picks of
+    /// earlier samples and then of neighbouring pixels, see
+    /// [`Integrator::Restir`](crate::settings::Integrator::Restir).
+    pub const RESTIR_TEMPORAL: &'static str = "restir_temporal.wgsl";
+    pub const RESTIR_SPATIAL: &'static str = "restir_spatial.wgsl";
+
+    /// Kernels resampling the light the first hits get from the next bounce with ReSTIR GI:
+    /// storing the bounces before shading their hits, then reusing the ones of earlier samples
+    /// and of neighbouring pixels once the paths are done.
+    pub const RESTIR_GI_SAMPLE: &'static str = "restir_gi_sample.wgsl";
+    pub const RESTIR_GI_TEMPORAL: &'static str = "restir_gi_temporal.wgsl";
+    pub const RESTIR_GI_SPATIAL: &'static str = "restir_gi_spatial.wgsl";
+
+    /// Kernel guiding the diffuse bounces by the light found at them so far and training the
+    /// guide, see [`Integrator::PathGuiding`](crate::settings::Integrator::PathGuiding).
+    pub const GUIDE: &'static str = "guide.wgsl";
+
+    /// Kernel training the irradiance cache with the diffuse bounces and ending the paths past
+    /// the first one at the cache, see
+    /// [`Integrator::FinalGather`](crate::settings::Integrator::FinalGather).
+    pub const FINAL_GATHER: &'static str = "final_gather.wgsl";
+
+    /// Kernel moving the paths still alive to the other list of live paths.
+    pub const COMPACT: &'static str = "compact.wgsl";
+
+    /// Kernels sorting the live paths by what they hit: counting the paths in every bucket,
+    /// placing the buckets and moving the paths to them.
+    pub const SORT_COUNT: &'static str = "sort_count.wgsl";
+    pub const SORT_SCAN: &'static str = "sort_scan.wgsl";
+    pub const SORT_SCATTER: &'static str = "sort_scatter.wgsl";
+
+    /// Kernel switching to the list the last compaction or sort wrote and sizing the
+    /// dispatches over it.
+    pub const DISPATCH_ARGS: &'static str = "dispatch_args.wgsl";
+
+    /// Kernel adding the finished paths to the accumulated samples.
+    pub const ACCUMULATE: &'static str = "accumulate.wgsl";
+
+    /// File the pipeline averaging accumulated samples is built from.
+    pub const RESOLVE: &'static str = "resolve.wgsl";
+
+    /// Shaders built into the crate.
+    pub fn builtin() -> Self {
+        Self {
+            files: BUILTIN
+                .iter()
+                .map(|&(name, source)| (name.to_owned(), source.to_owned()))
+                .collect(),
+        }
+    }
+
+    /// The built-in shaders with the files of the same names found in `dir` replacing them, e.g.
+    /// `src/shaders` of the crate.
+    #[cfg(feature = "fs")]
+    pub fn load(dir: impl AsRef<std::path::Path>) -> Result<Self, ShaderError> {
+        let dir = dir.as_ref();
+        let mut sources = Self::builtin();
+        for (name, _) in BUILTIN {
+            let path = dir.join(name);
+            match std::fs::read_to_string(&path) {
+                Ok(source) => sources.insert(name, source),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(source) => return Err(ShaderError::Io { path, source }),
+            }
+        }
+        Ok(sources)
+    }
+
+    /// Names of all the files.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(String::as_str)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.files.get(name).map(String::as_str)
+    }
+
+    /// Adds or replaces the file `name`.
+    pub fn insert(&mut self, name: impl Into<String>, source: impl Into<String>) {
+        self.files.insert(name.into(), source.into());
+    }
+
+    /// Splices `source` into the integrator at `hook`.
+    pub fn set_hook(&mut self, hook: ShaderHook, source: &str) {
+        self.insert(hook.file(), format!("#define {}\n{source}", hook.define()));
+    }
+
+    /// Expands the directives of file `name`, starting out with `defines`.
+    pub fn preprocess(
+        &self,
+        name: &str,
+        defines: &BTreeMap<String, String>,
+    ) -> Result<String, ShaderError> {
+        if self.get(name).is_none() {
+            return Err(ShaderError::Preprocess {
+                file: name.to_owned(),
+                line: 0,
+                message: "no such file".to_owned(),
+            });
+        }
+
+        let mut preprocessor = Preprocessor {
+            sources: self,
+            defines: defines.clone(),
+            included: BTreeSet::new(),
+            output: String::new(),
+        };
+        preprocessor.include(name)?;
+        Ok(preprocessor.output)
+    }
+}
+
+impl Default for ShaderSources {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+struct Preprocessor<'a> {
+    sources: &'a ShaderSources,
+    defines: BTreeMap<String, String>,
+    included: BTreeSet<String>,
+    output: String,
+}
+
+/// `#ifdef` or `#ifndef` block the preprocessor is inside of.
+struct Conditional {
+    active: bool,
+    seen_else: bool,
+}
+
+impl Preprocessor<'_> {
+    /// Appends file `name` to the output, unless it already has been.
+    fn include(&mut self, name: &str) -> Result<(), ShaderError> {
+        if !self.included.insert(name.to_owned()) {
+            return Ok(());
+        }
+        let source = self.sources.get(name).unwrap_or_default();
+
+        let mut conditionals: Vec<Conditional> = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let error = |message: &str| ShaderError::Preprocess {
+                file: name.to_owned(),
+                line: index + 1,
+                message: message.to_owned(),
+            };
+            let active = conditionals.iter().all(|conditional| conditional.active);
+
+            let Some(directive) = line.trim_start().strip_prefix('#') else {
+                if active {
+                    let line = self.substitute(line);
+                    self.output.push_str(&line);
+                    self.output.push('\n');
+                }
+                continue;
+            };
+
+            let (keyword, argument) = directive
+                .trim()
+                .split_once(char::is_whitespace)
+                .map_or((directive.trim(), ""), |(keyword, argument)| {
+                    (keyword, argument.trim())
+                });
+
+            match keyword {
+                "ifdef" | "ifndef" => {
+                    let defined = self.defines.contains_key(argument);
+                    conditionals.push(Conditional {
+                        active: defined == (keyword == "ifdef"),
+                        seen_else: false,
+                    });
+                }
+                "else" => {
+                    let conditional = conditionals
+                        .last_mut()
+                        .filter(|conditional| !conditional.seen_else)
+                        .ok_or_else(|| error("#else without #ifdef"))?;
+                    conditional.active = !conditional.active;
+                    conditional.seen_else = true;
+                }
+                "endif" => {
+                    conditionals
+                        .pop()
+                        .ok_or_else(|| error("#endif without #ifdef"))?;
+                }
+                _ if !active => {}
+                "include" => {
+                    let file = argument
+                        .strip_prefix('"')
+                        .and_then(|file| file.strip_suffix('"'))
+                        .ok_or_else(|| error("expected a quoted file name"))?;
+                    if self.sources.get(file).is_none() {
+                        return Err(error(&format!("no such file \"{file}\"")));
+                    }
+                    self.include(file)?;
+                }
+                "define" => {
+                    let (define, value) = argument
+                        .split_once(char::is_whitespace)
+                        .map_or((argument, ""), |(define, value)| (define, value.trim()));
+                    if define.is_empty() {
+                        return Err(error("expected a name to define"));
+                    }
+                    let value = self.substitute(value);
+                    self.defines.insert(define.to_owned(), value);
+                }
+                "undef" => {
+                    self.defines.remove(argument);
+                }
+                _ => return Err(error(&format!("unknown directive #{keyword}"))),
+            }
+        }
+
+        if !conditionals.is_empty() {
+            return Err(ShaderError::Preprocess {
+                file: name.to_owned(),
+                line: source.lines().count(),
+                message: "#ifdef without #endif".to_owned(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Replaces the identifiers of `line` that have been defined with a value.
+    fn substitute(&self, line: &str) -> String {
+        let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+        let mut output = String::with_capacity(line.len());
+        let mut rest = line;
+        while let Some(start) = rest.find(is_word) {
+            let end = rest[start..]
+                .find(|c| !is_word(c))
+                .map_or(rest.len(), |len| start + len);
+            let word = &rest[start..end];
+
+            output.push_str(&rest[..start]);
+            // Words starting with a digit are number literals.
+            match self.defines.get(word) {
+                Some(value)
+                    if !value.is_empty() && !word.starts_with(|c: char| c.is_ascii_digit()) =>
+                {
+                    output.push_str(value)
+                }
+                _ => output.push_str(word),
+            }
+            rest = &rest[end..];
+        }
+        output.push_str(rest);
+        output
+    }
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// Malformed preprocessor directive, `line` is zero if `file` doesn't exist.
+    Preprocess {
+        file: String,
+        line: usize,
+        message: String,
+    },
+    /// The shaders don't compile or don't match the layout the renderer binds.
+    Compile(wgpu::Error),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            Self::Preprocess {
+                file,
+                line: 0,
+                message,
+            } => write!(f, "{file}: {message}"),
+            Self::Preprocess {
+                file,
+                line,
+                message,
+            } => write!(f, "{file}:{line}: {message}"),
+            Self::Compile(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error for ShaderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Preprocess { .. } => None,
+            Self::Compile(err) => Some(err),
+        }
+    }
+}
@@ -0,0 +1,25 @@
+// Adds the radiance and alpha of the finished paths to the samples of their pixels
+#include "filter.wgsl"
+#include "frame.wgsl"
+#include "paths.wgsl"
+#include "spectral.wgsl"
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    if (!in_tile(global_invocation_id)) {
+        return;
+    }
+
+    let index = path_index(global_invocation_id);
+    var radiance = paths[index].radiance;
+#ifdef SPECTRAL
+    let wavelength = paths[index].wavelength;
+    if (wavelength > 0.0) {
+        radiance = spectrum_to_rgb(radiance, hero_wavelengths(wavelength));
+    }
+#endif
+    // Draws the same offset as the camera ray of the path did
+    let offset = start_pixel_sample(frame.tile_origin + global_invocation_id.xy);
+    accumulate_sample(index, vec4<f32>(radiance * frame.exposure, paths[index].alpha), filter_weight(offset));
+}
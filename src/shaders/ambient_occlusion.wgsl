@@ -0,0 +1,47 @@
+// Ambient occlusion of the first hits of the paths, which is all they return
+#include "frame.wgsl"
+#include "paths.wgsl"
+#include "random.wgsl"
+#include "ray_ahit.wgsl"
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>, @builtin(local_invocation_index) local_invocation_index: u32) {
+    let index = live_path(live_slot(workgroup_id, local_invocation_index));
+    if (index == NO_PATH) {
+        return;
+    }
+
+    var path = paths[index];
+    path.alive = 0u;
+    path.wavelength = 0.0;
+
+    let hit = hits[index];
+    if (hit.hit == 0u) {
+        // Nothing to occlude the sky
+        path.radiance = vec3<f32>(1.0, 1.0, 1.0);
+        if (frame.transparent_background != 0u) {
+            path.radiance = vec3<f32>(0.0, 0.0, 0.0);
+            path.alpha = 0.0;
+        }
+        paths[index] = path;
+        return;
+    }
+
+    rng_state = path.rng_state;
+    var visible = 0u;
+    for (var i = 0u; i < frame.ao_rays; i += 1u) {
+        // Cosine distributed around the normal, falling back to it when the sum degenerates
+        var direction = hit.normal + random_unit_vector();
+        if (dot(direction, direction) < 1.0e-8) {
+            direction = hit.normal;
+        }
+        if (!occluded(offset_origin(hit.hit_point, hit.normal, hit.normal), normalize(direction), frame.ao_distance)) {
+            visible += 1u;
+        }
+    }
+    path.rng_state = rng_state;
+
+    path.radiance = vec3<f32>(f32(visible) / f32(max(frame.ao_rays, 1u)));
+    paths[index] = path;
+}
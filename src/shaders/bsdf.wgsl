@@ -0,0 +1,429 @@
+// Principled BSDF of the built-in materials, after "Physically Based Shading at Disney" (Burley
+// 2012): a metallic lobe, a glass lobe and a dielectric one layering a specular coat over diffuse
+// reflection with sheen or subsurface scattering, blended by `metallic` and `transmission`, all under an optional clear
+// coat. The specular lobes use anisotropic GGX, sampled through its distribution of microfacet
+// normals, with Fresnel terms optionally replaced by the interference of a thin film. Hair replaces
+// all of them with a model of its own
+#include "scene.wgsl"
+#include "paths.wgsl"
+#include "random.wgsl"
+#include "ray_chit.wgsl"
+#include "spectral.wgsl"
+
+struct BsdfSample {
+    direction: vec3<f32>,
+    // BSDF times the cosine of `direction` over its probability density
+    weight: vec3<f32>,
+    // One of the EVENT constants, EVENT_ABSORBED if the sample got absorbed
+    event: u32,
+}
+
+// Orthonormal basis with `n` as its last column, see "Building an Orthonormal Basis, Revisited"
+// (Duff et al.)
+fn tangent_frame(n: vec3<f32>) -> mat3x3<f32> {
+    let s = select(-1.0, 1.0, n.z >= 0.0);
+    let a = -1.0 / (s + n.z);
+    let b = n.x * n.y * a;
+    return mat3x3<f32>(
+        vec3<f32>(1.0 + s * n.x * n.x * a, s * b, -s * n.x),
+        vec3<f32>(b, s + n.y * n.y * a, -n.y),
+        n,
+    );
+}
+
+// Tangent space at a hit, with `tangent` rotated around `normal` by `rotation` turns as its first
+// column, or any one if it's parallel to the normal
+fn shading_frame(normal: vec3<f32>, tangent: vec3<f32>, rotation: f32) -> mat3x3<f32> {
+    let projected = tangent - normal * dot(normal, tangent);
+    if (dot(projected, projected) < 1.0e-8) {
+        return tangent_frame(normal);
+    }
+    let angle = 2.0 * PI * rotation;
+    let t = normalize(projected);
+    let rotated = cos(angle) * t + sin(angle) * cross(normal, t);
+    return mat3x3<f32>(rotated, cross(normal, rotated), normal);
+}
+
+// GGX roughness along the tangent and the bitangent
+fn ggx_alpha(material: Material) -> vec2<f32> {
+    let aspect = sqrt(1.0 - 0.9 * clamp(material.anisotropic, 0.0, 1.0));
+    let alpha = material.roughness * material.roughness;
+    return max(vec2<f32>(alpha / aspect, alpha * aspect), vec2<f32>(1.0e-4, 1.0e-4));
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn schlick_weight(cosine: f32) -> f32 {
+    return pow(clamp(1.0 - cosine, 0.0, 1.0), 5.0);
+}
+
+// Unpolarized Fresnel reflectance of a dielectric, `eta` being the ratio of the index of
+// refraction behind the surface to the one in front of it
+fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let sin_t2 = (1.0 - cos_i * cos_i) / (eta * eta);
+    if (sin_t2 >= 1.0) {
+        // Total internal reflection
+        return 1.0;
+    }
+    let cos_t = sqrt(1.0 - sin_t2);
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    return 0.5 * (rs * rs + rp * rp);
+}
+
+// Real index of refraction of a dielectric reflecting `f0` head-on
+fn ior_from_f0(f0: vec3<f32>) -> vec3<f32> {
+    let r = sqrt(clamp(f0, vec3<f32>(0.0), vec3<f32>(0.99)));
+    return (1.0 + r) / (1.0 - r);
+}
+
+// Reflectance at the wavelengths of the channels of a surface of index `substrate` under a film
+// `thickness` nanometers thick with index `film_ior`, from the Airy summation of the light
+// bouncing between its two interfaces, averaged over both polarizations. Metals are treated as
+// dielectrics of the same normal reflectance, ignoring the phase shift they add
+fn thin_film_fresnel(cos_i: f32, film_ior: f32, thickness: f32, substrate: vec3<f32>) -> vec3<f32> {
+    let sin2_i = 1.0 - cos_i * cos_i;
+    let sin2_film = sin2_i / (film_ior * film_ior);
+    if (sin2_film >= 1.0) {
+        // Total internal reflection on the film
+        return vec3<f32>(1.0);
+    }
+    let cos_film = sqrt(1.0 - sin2_film);
+    let cos_substrate = sqrt(max(1.0 - sin2_i / (substrate * substrate), vec3<f32>(0.0)));
+
+    // Amplitudes reflected by the top and the bottom interface
+    let rs12 = (cos_i - film_ior * cos_film) / (cos_i + film_ior * cos_film);
+    let rp12 = (film_ior * cos_i - cos_film) / (film_ior * cos_i + cos_film);
+    let rs23 = (film_ior * cos_film - substrate * cos_substrate) / (film_ior * cos_film + substrate * cos_substrate);
+    let rp23 = (substrate * cos_film - film_ior * cos_substrate) / (substrate * cos_film + film_ior * cos_substrate);
+
+    // Phase difference of the light crossing the film and back
+    let cos_delta = cos(4.0 * PI * film_ior * thickness * cos_film / channel_wavelengths());
+
+    let rs = (rs12 * rs12 + rs23 * rs23 + 2.0 * rs12 * rs23 * cos_delta) / (1.0 + rs12 * rs12 * rs23 * rs23 + 2.0 * rs12 * rs23 * cos_delta);
+    let rp = (rp12 * rp12 + rp23 * rp23 + 2.0 * rp12 * rp23 * cos_delta) / (1.0 + rp12 * rp12 * rp23 * rp23 + 2.0 * rp12 * rp23 * cos_delta);
+    return clamp(0.5 * (rs + rp), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Microfacet normal in tangent space, drawn proportionally to the GGX distribution times its cosine
+fn sample_ggx(alpha: vec2<f32>) -> vec3<f32> {
+    let u = rand_f32();
+    let v = 2.0 * PI * rand_f32();
+    let phi = atan2(alpha.y * sin(v), alpha.x * cos(v));
+    let cos_phi = cos(phi);
+    let sin_phi = sin(phi);
+    // Roughness along phi
+    let alpha2 = 1.0 / (cos_phi * cos_phi / (alpha.x * alpha.x) + sin_phi * sin_phi / (alpha.y * alpha.y));
+    let cos_theta = inverseSqrt(1.0 + alpha2 * u / (1.0 - u));
+    let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+    return vec3<f32>(sin_theta * cos_phi, sin_theta * sin_phi, cos_theta);
+}
+
+// Smith masking of GGX microfacets seen from tangent space direction `v`
+fn smith_g1(v: vec3<f32>, alpha: vec2<f32>) -> f32 {
+    let cos2 = v.z * v.z;
+    if (cos2 == 0.0) {
+        return 0.0;
+    }
+    let alpha_tan2 = (alpha.x * alpha.x * v.x * v.x + alpha.y * alpha.y * v.y * v.y) / cos2;
+    return 2.0 / (1.0 + sqrt(1.0 + alpha_tan2));
+}
+
+// Weight of a direction scattered off microfacet normal `h` sampled by sample_ggx, without the Fresnel
+// term, which holds for reflection and refraction alike
+fn microfacet_weight(frame: mat3x3<f32>, wo: vec3<f32>, wi: vec3<f32>, h: vec3<f32>, alpha: vec2<f32>) -> f32 {
+    let to_local = transpose(frame);
+    let local_wo = to_local * wo;
+    let n_h = max(dot(frame[2], h), 1.0e-6);
+    return smith_g1(local_wo, alpha) * smith_g1(to_local * wi, alpha) * abs(dot(wo, h)) / max(abs(local_wo.z) * n_h, 1.0e-6);
+}
+
+fn logistic_cdf(x: f32, s: f32) -> f32 {
+    return 1.0 / (1.0 + exp(-x / s));
+}
+
+// Logistic distribution of scale `s` restricted to the range from `a` to `b`, sampled by `u`
+fn sample_trimmed_logistic(u: f32, s: f32, a: f32, b: f32) -> f32 {
+    let k = logistic_cdf(b, s) - logistic_cdf(a, s);
+    let x = -s * log(1.0 / (u * k + logistic_cdf(a, s)) - 1.0);
+    return clamp(x, a, b);
+}
+
+// Absorption per unit of diameter of hair fibers of `color` reflected off many of them, whose
+// roughness around the fibers is `beta_n`
+fn hair_absorption(color: vec3<f32>, beta_n: f32) -> vec3<f32> {
+    let b = beta_n;
+    let scale = 5.969 - 0.215 * b + 2.532 * b * b - 10.73 * pow(b, 3.0) + 5.574 * pow(b, 4.0) + 0.245 * pow(b, 5.0);
+    let root = log(clamp(color, vec3<f32>(1.0e-4), vec3<f32>(1.0))) / scale;
+    return root * root;
+}
+
+// Hair fiber model of "A Practical and Controllable Hair and Fur Model for Production Path Tracing"
+// (Chiang et al. 2016) around `tangent`, with the light reflected off the fiber, transmitted through
+// it, reflected inside it once and the rest of it as separate lobes. One of them is picked by its
+// attenuation and sampled exactly, so its weight is the ratio of the two. How far across the fiber
+// light arrives comes from `normal`, whose part facing away from `wo` grows towards its edges
+fn sample_hair(material: Material, base_color: vec3<f32>, wo: vec3<f32>, normal: vec3<f32>, tangent: vec3<f32>) -> BsdfSample {
+    var sample = BsdfSample(vec3<f32>(0.0, 0.0, 0.0), vec3<f32>(0.0, 0.0, 0.0), EVENT_ABSORBED);
+    var u = tangent_frame(normal)[0];
+    if (dot(tangent, tangent) > 1.0e-12) {
+        u = normalize(tangent);
+    }
+    let sin_theta_o = clamp(dot(wo, u), -1.0, 1.0);
+    let cos_theta_o = sqrt(1.0 - sin_theta_o * sin_theta_o);
+    var z = normal;
+    if (cos_theta_o > 1.0e-4) {
+        z = normalize(wo - u * sin_theta_o);
+    }
+    let y = cross(u, z);
+    let h = clamp(dot(normal, y), -1.0, 1.0);
+    let gamma_o = asin(h);
+
+    // Attenuations of the lobes, from the path refracted through the fiber
+    let eta = material.ior;
+    let sin_theta_t = sin_theta_o / eta;
+    let cos_theta_t = sqrt(max(1.0 - sin_theta_t * sin_theta_t, 0.0));
+    let etap = sqrt(max(eta * eta - sin_theta_o * sin_theta_o, 0.0)) / max(cos_theta_o, 1.0e-4);
+    let sin_gamma_t = clamp(h / etap, -1.0, 1.0);
+    let cos_gamma_t = sqrt(1.0 - sin_gamma_t * sin_gamma_t);
+    let gamma_t = asin(sin_gamma_t);
+    let beta_n = max(material.hair_azimuthal_roughness, 0.01);
+    let absorption = hair_absorption(base_color, beta_n);
+    let transmittance = exp(-absorption * (2.0 * cos_gamma_t / max(cos_theta_t, 1.0e-4)));
+    let f = fresnel_dielectric(cos_theta_o * sqrt(1.0 - h * h), eta);
+    var attenuation: array<vec3<f32>, 4>;
+    attenuation[0] = vec3<f32>(f);
+    attenuation[1] = (1.0 - f) * (1.0 - f) * transmittance;
+    attenuation[2] = attenuation[1] * transmittance * f;
+    attenuation[3] = attenuation[2] * f * transmittance / max(1.0 - transmittance * f, vec3<f32>(1.0e-4));
+
+    var total = 0.0;
+    for (var p = 0u; p < 4u; p += 1u) {
+        total += luminance(attenuation[p]);
+    }
+    if (total <= 0.0) {
+        return sample;
+    }
+    let pick = rand_f32() * total;
+    var p = 0u;
+    var below = luminance(attenuation[0]);
+    while (p < 3u && below <= pick) {
+        p += 1u;
+        below += luminance(attenuation[p]);
+    }
+    let probability = luminance(attenuation[p]) / total;
+    if (probability <= 0.0) {
+        return sample;
+    }
+
+    // Longitudinal variance of the lobes, and the tilt of the scales shifting them along the fiber
+    let beta_m = max(material.roughness, 0.01);
+    let v0 = pow(0.726 * beta_m + 0.812 * beta_m * beta_m + 3.7 * pow(beta_m, 20.0), 2.0);
+    var variance = v0;
+    var sin_theta_op = sin_theta_o;
+    var cos_theta_op = cos_theta_o;
+    let alpha = radians(material.hair_scale_angle);
+    let sin_2k0 = sin(2.0 * alpha);
+    let cos_2k0 = cos(2.0 * alpha);
+    let sin_2k1 = 2.0 * cos_2k0 * sin_2k0;
+    let cos_2k1 = cos_2k0 * cos_2k0 - sin_2k0 * sin_2k0;
+    let sin_2k2 = 2.0 * cos_2k1 * sin_2k1;
+    let cos_2k2 = cos_2k1 * cos_2k1 - sin_2k1 * sin_2k1;
+    if (p == 0u) {
+        sin_theta_op = sin_theta_o * cos_2k1 - cos_theta_o * sin_2k1;
+        cos_theta_op = cos_theta_o * cos_2k1 + sin_theta_o * sin_2k1;
+    } else if (p == 1u) {
+        variance = 0.25 * v0;
+        sin_theta_op = sin_theta_o * cos_2k0 + cos_theta_o * sin_2k0;
+        cos_theta_op = cos_theta_o * cos_2k0 - sin_theta_o * sin_2k0;
+    } else if (p == 2u) {
+        variance = 4.0 * v0;
+        sin_theta_op = sin_theta_o * cos_2k2 + cos_theta_o * sin_2k2;
+        cos_theta_op = cos_theta_o * cos_2k2 - sin_theta_o * sin_2k2;
+    } else {
+        variance = 4.0 * v0;
+    }
+    cos_theta_op = abs(cos_theta_op);
+
+    // Longitudinal scattering, "Importance Sampling for Physically-Based Hair Fiber Models"
+    // (d'Eon et al. 2013)
+    let u0 = max(rand_f32(), 1.0e-5);
+    let cos_theta = 1.0 + variance * log(u0 + (1.0 - u0) * exp(-2.0 / variance));
+    let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+    let cos_phi = cos(2.0 * PI * rand_f32());
+    let sin_theta_i = clamp(-cos_theta * sin_theta_op + sin_theta * cos_phi * cos_theta_op, -1.0, 1.0);
+    let cos_theta_i = sqrt(1.0 - sin_theta_i * sin_theta_i);
+
+    // Azimuthal scattering around the deflection of the lobe, uniform for the rest of the light
+    var phi = 2.0 * PI * rand_f32();
+    if (p < 3u) {
+        let s = sqrt(PI / 8.0) * (0.265 * beta_n + 1.194 * beta_n * beta_n + 5.372 * pow(beta_n, 22.0));
+        let deflection = 2.0 * f32(p) * gamma_t - 2.0 * gamma_o + f32(p) * PI;
+        phi = deflection + sample_trimmed_logistic(rand_f32(), s, -PI, PI);
+    }
+    // `wo` is at a right angle in the plane of `y` and `z`
+    let phi_i = 0.5 * PI + phi;
+
+    sample.direction = normalize(sin_theta_i * u + cos_theta_i * (cos(phi_i) * y + sin(phi_i) * z));
+    sample.weight = attenuation[p] / probability;
+    sample.event = select(EVENT_TRANSMISSION, EVENT_REFLECTION, p == 0u);
+    return sample;
+}
+
+// Draws a direction scattering light from `wo`, pointing away from the surface, with `normal` on
+// the side of it and anisotropic reflection stretched along `tangent`
+fn sample_bsdf(material: Material, base_color: vec3<f32>, wo: vec3<f32>, normal: vec3<f32>, tangent: vec3<f32>, front_face: bool) -> BsdfSample {
+    if (material.hair > 0.0 && rand_f32() < material.hair) {
+        return sample_hair(material, base_color, wo, normal, tangent);
+    }
+
+    var sample = BsdfSample(vec3<f32>(0.0, 0.0, 0.0), vec3<f32>(0.0, 0.0, 0.0), EVENT_ABSORBED);
+    let frame = shading_frame(normal, tangent, material.anisotropic_rotation);
+    let alpha = ggx_alpha(material);
+    let n_wo = dot(normal, wo);
+
+    // The clear coat reflects according to its Fresnel reflectance, which is also how often it's
+    // sampled, and passes the rest of the light on to the lobes below
+    if (front_face && material.clearcoat > 0.0) {
+        let coat = material.clearcoat * fresnel_dielectric(n_wo, material.clearcoat_ior);
+        if (rand_f32() < coat) {
+            let coat_alpha = vec2<f32>(max(material.clearcoat_roughness * material.clearcoat_roughness, 1.0e-4));
+            let h = frame * sample_ggx(coat_alpha);
+            sample.direction = reflect(-wo, h);
+            if (dot(sample.direction, normal) <= 0.0) {
+                return sample;
+            }
+            let fresnel = material.clearcoat * fresnel_dielectric(dot(wo, h), material.clearcoat_ior);
+            sample.weight = vec3<f32>(fresnel / coat * microfacet_weight(frame, wo, sample.direction, h, coat_alpha));
+            sample.event = EVENT_REFLECTION;
+            return sample;
+        }
+    }
+
+    let glass = (1.0 - material.metallic) * material.transmission;
+    let lobe = rand_f32();
+    if (lobe < material.metallic) {
+        let h = frame * sample_ggx(alpha);
+        sample.direction = reflect(-wo, h);
+        if (dot(sample.direction, normal) <= 0.0) {
+            // Absorbed below the surface
+            return sample;
+        }
+        var fresnel = base_color + (1.0 - base_color) * schlick_weight(dot(wo, h));
+        if (material.thin_film_thickness > 0.0) {
+            fresnel = thin_film_fresnel(dot(wo, h), material.thin_film_ior, material.thin_film_thickness, ior_from_f0(base_color));
+        }
+        sample.weight = fresnel * microfacet_weight(frame, wo, sample.direction, h, alpha);
+        sample.event = EVENT_METALLIC;
+    } else if (lobe < material.metallic + glass) {
+        var ior = material.ior;
+        var dispersion = vec3<f32>(1.0);
+#ifdef SPECTRAL
+        // Every wavelength refracts its own way, so only the hero one goes on, standing in for all
+        if (material.abbe_number > 0.0) {
+            ior = dispersive_ior(material.ior, material.abbe_number, wavelengths.x);
+            if (!hero_only) {
+                dispersion = vec3<f32>(3.0, 0.0, 0.0);
+                hero_only = true;
+            }
+        }
+#endif
+        let h = frame * sample_ggx(alpha);
+        let eta = select(1.0 / ior, ior, front_face);
+        let wo_h = dot(wo, h);
+        if (wo_h <= 0.0) {
+            return sample;
+        }
+        // Reflected as often as the mean of the reflectances, which only differ with a film
+        var fresnel = vec3<f32>(fresnel_dielectric(wo_h, eta));
+        if (front_face && material.thin_film_thickness > 0.0) {
+            fresnel = thin_film_fresnel(wo_h, material.thin_film_ior, material.thin_film_thickness, vec3<f32>(ior));
+        }
+        let reflect_probability = (fresnel.x + fresnel.y + fresnel.z) / 3.0;
+        if (rand_f32() < reflect_probability) {
+            sample.direction = reflect(-wo, h);
+            sample.weight = fresnel / reflect_probability;
+            sample.event = EVENT_REFLECTION;
+            if (dot(sample.direction, normal) <= 0.0) {
+                sample.event = EVENT_ABSORBED;
+                return sample;
+            }
+        } else {
+            sample.direction = refract_direction(-wo, h, 1.0 / eta);
+            sample.weight = base_color * (1.0 - fresnel) / (1.0 - reflect_probability);
+            sample.event = EVENT_TRANSMISSION;
+            if (dot(sample.direction, normal) >= 0.0) {
+                sample.event = EVENT_ABSORBED;
+                return sample;
+            }
+        }
+        sample.weight *= dispersion * microfacet_weight(frame, wo, sample.direction, h, alpha);
+    } else {
+        // The coat reflects according to its Fresnel reflectance, which is also how often it's sampled
+        let f0 = 0.08 * material.specular;
+        let coat = f0 + (1.0 - f0) * schlick_weight(n_wo);
+        if (rand_f32() < coat) {
+            let h = frame * sample_ggx(alpha);
+            sample.direction = reflect(-wo, h);
+            if (dot(sample.direction, normal) <= 0.0) {
+                return sample;
+            }
+            var fresnel = vec3<f32>(f0 + (1.0 - f0) * schlick_weight(dot(wo, h)));
+            if (material.thin_film_thickness > 0.0) {
+                fresnel = thin_film_fresnel(dot(wo, h), material.thin_film_ior, material.thin_film_thickness, ior_from_f0(vec3<f32>(f0)));
+            }
+            sample.weight = fresnel / coat * microfacet_weight(frame, wo, sample.direction, h, alpha);
+            sample.event = EVENT_REFLECTION;
+        } else if (front_face && rand_f32() < material.subsurface) {
+            // Diffuse transmission into the medium below the surface, which colors the light
+            var direction = random_unit_vector() - normal;
+            if (dot(direction, direction) < 1.0e-8) {
+                direction = -normal;
+            }
+            sample.direction = normalize(direction);
+            sample.weight = vec3<f32>(1.0, 1.0, 1.0);
+            sample.event = EVENT_SUBSURFACE;
+        } else {
+            // Cosine weighted, falling back to the normal when the sum degenerates
+            sample.direction = normal + random_unit_vector();
+            if (dot(sample.direction, sample.direction) < 1.0e-8) {
+                sample.direction = normal;
+            }
+            sample.direction = normalize(sample.direction);
+
+            // Retro-reflection at grazing angles and sheen
+            let cos_d = dot(sample.direction, normalize(sample.direction + wo));
+            let fd90 = 0.5 + 2.0 * material.roughness * cos_d * cos_d;
+            let retro = (1.0 + (fd90 - 1.0) * schlick_weight(dot(normal, sample.direction)))
+                * (1.0 + (fd90 - 1.0) * schlick_weight(n_wo));
+            let lum = luminance(base_color);
+            let tint = select(vec3<f32>(1.0, 1.0, 1.0), base_color / lum, lum > 0.0);
+            let sheen = material.sheen * mix(vec3<f32>(1.0, 1.0, 1.0), tint, material.sheen_tint);
+            sample.weight = base_color * retro + PI * sheen * schlick_weight(cos_d);
+            sample.event = EVENT_DIFFUSE;
+        }
+    }
+    return sample;
+}
+
+// Ray leaving `light` in a random direction, and the power it carries over the probability of
+// the ray, for tracing light from the lights
+fn emit_light(light: Light, ray: ptr<function, Ray>) -> vec3<f32> {
+    if (light.kind != LIGHT_DIRECTIONAL) {
+        *ray = Ray(light.position, random_unit_vector());
+        return light.intensity * light_profile(light, (*ray).direction) * 4.0 * PI;
+    }
+
+    // From a disk facing the light that covers the bounds of the scene
+    let root = bvh_nodes[0];
+    let center = 0.5 * (root.aabb_min + root.aabb_max);
+    let radius = 0.5 * length(root.aabb_max - root.aabb_min);
+    let direction = directional_light_direction(light);
+    let r = radius * sqrt(rand_f32());
+    let phi = 2.0 * PI * rand_f32();
+    let origin = center + tangent_frame(direction) * vec3<f32>(r * cos(phi), r * sin(phi), -radius);
+    *ray = Ray(origin, direction);
+    return light.intensity * PI * radius * radius;
+}
@@ -0,0 +1,45 @@
+// Stream compaction of the live paths, moving the ones still alive after the last bounce to the
+// other list so that the next bounce only dispatches work for them
+#include "frame.wgsl"
+#include "paths.wgsl"
+
+// Inclusive prefix sum of the alive flags of the workgroup
+var<workgroup> scan: array<u32, WORKGROUP_INVOCATIONS>;
+// Where the survivors of the workgroup start in the other list
+var<workgroup> base: u32;
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>, @builtin(local_invocation_index) local_invocation_index: u32) {
+    let next = 1u - live.current;
+
+    // No early returns, every invocation has to reach the barriers
+    let index = live_path(live_slot(workgroup_id, local_invocation_index));
+    var alive = 0u;
+    if (index != NO_PATH) {
+        alive = paths[index].alive;
+    }
+
+    // Hillis-Steele scan in workgroup memory, subgroup ballots would save the barriers but
+    // neither wgpu 0.14 nor naga 0.10 has subgroup operations
+    scan[local_invocation_index] = alive;
+    workgroupBarrier();
+    for (var offset = 1u; offset < WORKGROUP_INVOCATIONS; offset *= 2u) {
+        var sum = scan[local_invocation_index];
+        if (local_invocation_index >= offset) {
+            sum += scan[local_invocation_index - offset];
+        }
+        workgroupBarrier();
+        scan[local_invocation_index] = sum;
+        workgroupBarrier();
+    }
+
+    if (local_invocation_index == WORKGROUP_INVOCATIONS - 1u) {
+        base = atomicAdd(&live.counts[next], scan[local_invocation_index]);
+    }
+    workgroupBarrier();
+
+    if (alive != 0u) {
+        live.indices[next * path_count() + base + scan[local_invocation_index] - 1u] = index;
+    }
+}
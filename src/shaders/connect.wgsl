@@ -0,0 +1,100 @@
+// Connections of the diffuse hits of the last bounce to the path from the lights of the same
+// index when rendering with bidirectional path tracing. Runs between shading and the shadow rays,
+// at the hits those are queued at
+//
+// Every path of light the camera path and the path from the lights can make between them is
+// weighted by one over the number of ways the two could have made it: connecting any two
+// consecutive diffuse surfaces, or sampling the light directly from the last one. The ways are
+// counted from the labels of the surfaces of both paths, which are all known once they meet
+#include "light_paths.wgsl"
+#include "paths.wgsl"
+#include "ray_chit.wgsl"
+#include "spectral.wgsl"
+
+// Whether surface `i` of a path of light connected at surface `camera` of the camera path, `end`
+// edges long, was scattered off diffusely
+fn diffuse_vertex(i: u32, camera: u32, end: u32, camera_labels: u32, light_labels: u32) -> bool {
+    if (i <= camera) {
+        return camera - i < 16u && ((camera_labels >> (camera - i)) & 1u) != 0u;
+    }
+    return ((light_labels >> (end - i - 1u)) & 1u) != 0u;
+}
+
+// Number of ways a path of light connected at surface `camera` of the camera path to surface
+// `light_depth` of the path from the lights could have been made, zero for the light itself
+fn connections(camera: u32, camera_labels: u32, light_depth: u32, light_labels: u32, max_bounces: u32) -> f32 {
+    let end = camera + light_depth + 1u;
+    var count = 0u;
+    // Never more than LIGHT_VERTICES surfaces on the light's side
+    for (var i = max(end, LIGHT_VERTICES + 2u) - LIGHT_VERTICES - 1u; i < end; i += 1u) {
+        if (i > max_bounces + 1u || !diffuse_vertex(i, camera, end, camera_labels, light_labels)) {
+            continue;
+        }
+        if (i + 1u < end && !diffuse_vertex(i + 1u, camera, end, camera_labels, light_labels)) {
+            continue;
+        }
+        // Surfaces moving over to the light's side have to be ones it can trace through
+        var traceable = true;
+        for (var j = i + 1u; j <= camera; j += 1u) {
+            traceable = traceable && ((camera_labels >> (16u + camera - j)) & 1u) != 0u;
+        }
+        count += select(0u, 1u, traceable);
+    }
+    return f32(max(count, 1u));
+}
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>, @builtin(local_invocation_index) local_invocation_index: u32) {
+    let index = live_path(live_slot(workgroup_id, local_invocation_index));
+    if (index == NO_PATH) {
+        return;
+    }
+    let path = paths[index];
+    let shadow_ray = path.shadow_ray;
+    // Paths from the lights don't go through the fog
+    if (shadow_ray.pending != SHADOW_SURFACE || fog_enabled()) {
+        return;
+    }
+
+#ifdef MAX_BOUNCES
+    let max_bounces = min(MAX_BOUNCES, frame.max_bounces);
+#else
+    let max_bounces = frame.max_bounces;
+#endif
+
+    // Shading counted the surface already
+    let camera = path.bounce;
+    var radiance = vec3<f32>(0.0, 0.0, 0.0);
+    for (var i = 0u; i < LIGHT_VERTICES; i += 1u) {
+        let vertex = light_vertices[index * LIGHT_VERTICES + i];
+        if (vertex.depth == 0u) {
+            break;
+        }
+        let to_vertex = vertex.position - shadow_ray.origin;
+        let distance = length(to_vertex);
+        let direction = to_vertex / distance;
+        let cos_camera = dot(shadow_ray.normal, direction);
+        let cos_light = dot(vertex.normal, -direction);
+        if (cos_camera <= 0.0 || cos_light <= 0.0 || occluded(shadow_ray.origin, direction, distance - frame.ray_epsilon)) {
+            continue;
+        }
+        // The weight includes the albedo, the 1/pi of the BRDF is left
+        var contribution = shadow_ray.weight / PI * vertex.power * cos_camera * cos_light / (distance * distance);
+        // Both sides scaled the hero wavelength up for the ones they dropped
+        if (path.hero_only != 0u && vertex.hero_only != 0u) {
+            contribution /= 3.0;
+        }
+        radiance += contribution / connections(camera, path.vertex_labels, vertex.depth, vertex.labels, max_bounces);
+    }
+
+    // Light from the sky through the portals isn't one of the ways counted, so it isn't weighted
+    rng_state = path.rng_state;
+    wavelengths = hero_wavelengths(path.wavelength);
+    radiance += shadow_ray.weight * spectrum(portal_light(shadow_ray.origin, shadow_ray.normal));
+
+    paths[index].radiance += radiance;
+    paths[index].rng_state = rng_state;
+    paths[index].shadow_ray.weight = shadow_ray.weight / connections(camera, path.vertex_labels, 0u, 0u, max_bounces);
+    paths[index].shadow_ray.pending = SHADOW_LIGHTS;
+}
@@ -0,0 +1,143 @@
+// Debug views of the first hits of the paths, written instead of their shading
+#include "frame.wgsl"
+#include "paths.wgsl"
+#include "ray_intersect.wgsl"
+
+let DEBUG_NORMALS: u32 = 0u;
+let DEBUG_UV: u32 = 1u;
+let DEBUG_DEPTH: u32 = 2u;
+let DEBUG_BVH_BOUNDS: u32 = 3u;
+
+// Checks of the UV checker along either axis
+let UV_CHECKS: f32 = 8.0;
+
+// Nodes and primitives the closest hit search of `hit_world` tests the ray against
+fn traversal_tests(ray: Ray) -> vec2<u32> {
+    let inv_direction = 1.0 / ray.direction;
+
+    var stack: array<u32, 64>;
+    var stack_size = 1u;
+    stack[0] = 0u;
+
+    var tests = vec2<u32>(0u, 0u);
+    var closest = T_MAX;
+    while (stack_size > 0u) {
+        stack_size -= 1u;
+        let node = bvh_nodes[stack[stack_size]];
+        tests.x += 1u;
+        if (hit_aabb(node.aabb_min, node.aabb_max, ray, inv_direction, closest) == T_MAX) {
+            continue;
+        }
+
+        if (node.count > 0u) {
+            tests.y += node.count;
+            for (var i = node.left_or_first; i < node.left_or_first + node.count; i += 1u) {
+                var rec: HitRecord;
+                if (hit_primitive(i, ray, frame.ray_epsilon, closest, &rec)) {
+                    closest = rec.distance;
+                }
+            }
+        } else {
+            stack[stack_size] = node.left_or_first;
+            stack[stack_size + 1u] = node.left_or_first + 1u;
+            stack_size += 2u;
+        }
+    }
+
+    return tests;
+}
+
+// Blue through green and yellow to red as `t` goes from 0 to 1
+fn heat(t: f32) -> vec3<f32> {
+    let x = clamp(t, 0.0, 1.0) * 3.0;
+    return clamp(vec3<f32>(x - 1.0, min(x, 3.0 - x), 1.0 - x), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// How many edges of the BVH nodes pass through the ray before `dist_max`, which overlapping
+// nodes make more than one
+fn bvh_edges(ray: Ray, dist_max: f32) -> u32 {
+    let inv_direction = 1.0 / ray.direction;
+
+    var stack: array<u32, 64>;
+    var stack_size = 1u;
+    stack[0] = 0u;
+
+    var edges = 0u;
+    while (stack_size > 0u) {
+        stack_size -= 1u;
+        let node = bvh_nodes[stack[stack_size]];
+        let entry = hit_aabb(node.aabb_min, node.aabb_max, ray, inv_direction, dist_max);
+        if (entry == T_MAX) {
+            continue;
+        }
+
+        // On an edge where the entry point is close to the faces along two axes, with the lines
+        // widening with the distance to stay about as thick on screen
+        if (entry > 0.0) {
+            let position = ray_at(ray, entry);
+            let to_faces = min(abs(position - node.aabb_min), abs(node.aabb_max - position));
+            let near = to_faces < vec3<f32>(0.002 * entry);
+            if (u32(near.x) + u32(near.y) + u32(near.z) >= 2u) {
+                edges += 1u;
+            }
+        }
+
+        if (node.count == 0u) {
+            stack[stack_size] = node.left_or_first;
+            stack[stack_size + 1u] = node.left_or_first + 1u;
+            stack_size += 2u;
+        }
+    }
+
+    return edges;
+}
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>, @builtin(local_invocation_index) local_invocation_index: u32) {
+    let index = live_path(live_slot(workgroup_id, local_invocation_index));
+    if (index == NO_PATH) {
+        return;
+    }
+
+    var path = paths[index];
+    path.alive = 0u;
+    path.wavelength = 0.0;
+
+    let hit = hits[index];
+    var color = vec3<f32>(0.0, 0.0, 0.0);
+    if (frame.debug_view == DEBUG_NORMALS) {
+        if (hit.hit != 0u) {
+            color = hit.normal * 0.5 + 0.5;
+        }
+    } else if (frame.debug_view == DEBUG_UV) {
+        if (hit.hit != 0u) {
+            let checks = vec2<i32>(floor(hit.uv * UV_CHECKS));
+            let shade = select(0.25, 1.0, (checks.x + checks.y) % 2 == 0);
+            color = vec3<f32>(fract(hit.uv), 1.0) * shade;
+        }
+    } else if (frame.debug_view == DEBUG_DEPTH) {
+        // Camera rays have a unit component along the forward axis, which makes the distance
+        // along them the linear depth
+        var depth = 1.0;
+        if (hit.hit != 0u) {
+            depth = min(hit.distance / frame.debug_scale, 1.0);
+        }
+        color = vec3<f32>(depth);
+    } else if (frame.debug_view == DEBUG_BVH_BOUNDS) {
+        // Bounds up to the closest hit over its dimmed normal
+        var dist_max = T_MAX;
+        if (hit.hit != 0u) {
+            color = (hit.normal * 0.5 + 0.5) * 0.25;
+            dist_max = hit.distance;
+        }
+        let edges = f32(bvh_edges(path.ray, dist_max));
+        color = mix(color, vec3<f32>(1.0, 0.6, 0.1), min(edges * 0.5, 1.0));
+    } else {
+        let tests = traversal_tests(path.ray);
+        color = heat(f32(tests.x + tests.y) / frame.debug_scale);
+    }
+
+    path.radiance = color;
+    paths[index] = path;
+}
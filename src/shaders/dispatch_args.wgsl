@@ -0,0 +1,17 @@
+// Makes the list the last compaction or sort wrote to current and sizes the dispatches over it
+#include "frame.wgsl"
+#include "paths.wgsl"
+
+@compute
+@workgroup_size(1)
+fn main() {
+    let current = live.current;
+    let next = 1u - current;
+    live.dispatch_x = (atomicLoad(&live.counts[next]) + WORKGROUP_INVOCATIONS - 1u) / WORKGROUP_INVOCATIONS;
+    atomicStore(&live.counts[current], 0u);
+    atomicStore(&live.next_slot, 0u);
+    live.current = next;
+    for (var bucket = 0u; bucket < SORT_BUCKETS; bucket += 1u) {
+        atomicStore(&live.buckets[bucket], 0u);
+    }
+}
@@ -0,0 +1,45 @@
+// Pixel reconstruction filters: the camera rays of a pixel are spread over the support of its
+// filter and their samples weighted by it when they're accumulated
+#include "frame.wgsl"
+#include "random.wgsl"
+
+let FILTER_BOX: u32 = 0u;
+let FILTER_GAUSSIAN: u32 = 1u;
+let FILTER_MITCHELL: u32 = 2u;
+let FILTER_BLACKMAN_HARRIS: u32 = 3u;
+
+// Seeds the random numbers of this sample of `pixel` and draws the offset from the center of
+// the pixel its camera ray goes through, in pixels
+fn start_pixel_sample(pixel: vec2<u32>) -> vec2<f32> {
+    let pixel_seed = pcg_hash(pixel.y * frame.image_wh.x + pixel.x) ^ pcg_hash(frame.seed);
+    rng_state = pcg_hash(pixel_seed ^ pcg_hash(frame.sample_index));
+    let u = vec2<f32>(rand_f32(), rand_f32());
+    return (2.0 * u - 1.0) * frame.filter_radius;
+}
+
+// Filter along one axis `x` pixels away from the center of the pixel
+fn filter_1d(x: f32) -> f32 {
+    let t = min(abs(x) / frame.filter_radius, 1.0);
+    if (frame.pixel_filter == FILTER_GAUSSIAN) {
+        // Standard deviation of a third of the radius, shifted down to reach zero at its edge
+        return max(exp(-4.5 * t * t) - exp(-4.5), 0.0);
+    }
+    if (frame.pixel_filter == FILTER_MITCHELL) {
+        // Mitchell-Netravali with B = C = 1/3, whose support is two units on each side
+        let m = 2.0 * t;
+        if (m < 1.0) {
+            return (7.0 * m * m * m - 12.0 * m * m + 16.0 / 3.0) / 6.0;
+        }
+        return (-7.0 / 3.0 * m * m * m + 12.0 * m * m - 20.0 * m + 32.0 / 3.0) / 6.0;
+    }
+    if (frame.pixel_filter == FILTER_BLACKMAN_HARRIS) {
+        let p = 6.2831853 * (0.5 + 0.5 * t);
+        return 0.35875 - 0.48829 * cos(p) + 0.14128 * cos(2.0 * p) - 0.01168 * cos(3.0 * p);
+    }
+    return 1.0;
+}
+
+// Weight of a sample whose camera ray went `offset` pixels away from the center of its pixel
+fn filter_weight(offset: vec2<f32>) -> f32 {
+    return filter_1d(offset.x) * filter_1d(offset.y);
+}
@@ -0,0 +1,54 @@
+// Final gather from the irradiance cache: the diffuse bounces of the samples learning it add the
+// light found since the previous diffuse bounce of every path to the cache, the ones of the
+// samples after end their paths past the first diffuse bounce with the light of the cache
+// instead of tracing them further. Runs after the shadow rays, once all the light of the bounce
+// reached the paths
+//
+// The first diffuse hits still trace their bounces, the gather rays, so the blur of the cache only
+// shows in the light they find. Spectral paths carry wavelengths the cache doesn't and bounce on
+// as usual. Diffuse hits only keep their normals with NEE defined, which gathering needs
+#include "irradiance_cache.wgsl"
+#include "paths.wgsl"
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>, @builtin(local_invocation_index) local_invocation_index: u32) {
+    let index = live_path(live_slot(workgroup_id, local_invocation_index));
+    if (index == NO_PATH) {
+        return;
+    }
+    var path = paths[index];
+    let diffuse = path.alive != 0u && path.event == EVENT_DIFFUSE && path.wavelength == 0.0;
+    // Light found through specular bounces still counts for the diffuse one before them
+    if (path.alive != 0u && !diffuse) {
+        return;
+    }
+
+    // Records of the first hits are left over from the previous sample
+    let record = cache.records[index];
+    if (record.entry != 0u && path.bounce > 1u) {
+        let incident = (path.radiance - record.radiance) / max(record.throughput, vec3<f32>(1.0e-30));
+        if (all(record.throughput > vec3<f32>(0.0))) {
+            cache_deposit(record.entry - 1u, incident);
+        }
+    }
+    cache.records[index].entry = 0u;
+    if (!diffuse) {
+        return;
+    }
+
+    let position = path.ray.origin;
+    let normal = path.shadow_ray.normal;
+    if (!gathering()) {
+        let entry = cache_entry(vec3<i32>(round(cache_grid(position))), cache_axis(normal));
+        cache.records[index] = CacheRecord(path.radiance, entry + 1u, path.throughput);
+        return;
+    }
+    // Paths the cache doesn't know anything about yet go on bouncing
+    let cached = cache_lookup(position, normal);
+    if (path.bounce > 1u && cached.a > 0.0) {
+        path.radiance += path.throughput * cached.rgb;
+        path.alive = 0u;
+        paths[index] = path;
+    }
+}
@@ -0,0 +1,68 @@
+// Fog filling a box of the scene, with coefficients per unit of distance
+#include "scene.wgsl"
+#include "random.wgsl"
+
+// Cap on the tentative collisions tracked through a density grid along a ray
+let MAX_FOG_STEPS: u32 = 256u;
+
+fn fog_enabled() -> bool {
+    return any(scene.fog_scattering + scene.fog_absorption > vec3<f32>(0.0));
+}
+
+// Distances along a ray at which it enters and leaves the fog before `distance`, the second not
+// above the first if it misses it
+fn fog_segment(ray: Ray, distance: f32) -> vec2<f32> {
+    let inv_direction = 1.0 / ray.direction;
+    let t0 = (scene.fog_min - ray.origin) * inv_direction;
+    let t1 = (scene.fog_max - ray.origin) * inv_direction;
+    let near = min(t0, t1);
+    let far = max(t0, t1);
+    return vec2<f32>(
+        max(max(near.x, near.y), max(near.z, 0.0)),
+        min(min(far.x, far.y), min(far.z, distance)),
+    );
+}
+
+// Density of the grid at `position` inside the box, interpolated between the centers of its cells
+fn fog_density(position: vec3<f32>) -> f32 {
+    let size = vec3<f32>(textureDimensions(density_grid));
+    let uvw = clamp((position - scene.fog_min) / (scene.fog_max - scene.fog_min), 0.5 / size, 1.0 - 0.5 / size);
+    return textureSampleLevel(density_grid, texture_sampler, uvw, 0.0).r;
+}
+
+// Densest the fog gets, for tracking collisions against
+fn fog_majorant(sigma_t: vec3<f32>) -> f32 {
+    return max(max(sigma_t.x, sigma_t.y), sigma_t.z);
+}
+
+// Fraction of the light that gets through the fog along a ray up to `distance`, estimated by
+// ratio tracking through a density grid
+fn fog_transmittance(ray: Ray, distance: f32) -> vec3<f32> {
+    if (!fog_enabled()) {
+        return vec3<f32>(1.0);
+    }
+    let segment = fog_segment(ray, distance);
+    let sigma_t = (scene.fog_scattering + scene.fog_absorption) * length(ray.direction);
+    if (scene.fog_density_grid == 0u) {
+        return exp(-sigma_t * max(segment.y - segment.x, 0.0));
+    }
+
+    let majorant = fog_majorant(sigma_t);
+    var transmittance = vec3<f32>(1.0);
+    var t = segment.x;
+    for (var step = 0u; step < MAX_FOG_STEPS; step += 1u) {
+        t -= log(1.0 - rand_f32()) / majorant;
+        if (t >= segment.y) {
+            break;
+        }
+        transmittance *= 1.0 - sigma_t * fog_density(ray_at(ray, t)) / majorant;
+    }
+    return transmittance;
+}
+
+// Henyey-Greenstein phase function, with `cos_theta` between the direction light travelled in and
+// the one it's scattered to
+fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    return (1.0 - g * g) / (4.0 * PI * denominator * sqrt(denominator));
+}
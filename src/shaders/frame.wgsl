@@ -0,0 +1,129 @@
+// Per-dispatch data shared by all the kernels of a render
+
+// Side of the square workgroups and the invocations in them, always set by the renderer
+#ifndef WORKGROUP_SIZE
+#define WORKGROUP_SIZE 8u
+#endif
+#ifndef WORKGROUP_INVOCATIONS
+#define WORKGROUP_INVOCATIONS 64u
+#endif
+
+struct FrameUniforms {
+    image_wh: vec2<u32>,
+    tile_origin: vec2<u32>,
+    tile_wh: vec2<u32>,
+    // Samples already accumulated in this tile, zero means the accumulation has to be reset
+    sample_index: u32,
+    seed: u32,
+    max_bounces: u32,
+    // Accumulation holds RGBA16F running means rather than RGBA32F sums
+    half_accumulation: u32,
+    // Occlusion rays of every hit and how far they reach when rendering ambient occlusion
+    ao_rays: u32,
+    ao_distance: f32,
+    // Which debug view to render and the value at the top of its scale, if it has one
+    debug_view: u32,
+    debug_scale: f32,
+    // Photons traced for every sample and the radius they're gathered in when photon mapping
+    photon_count: u32,
+    photon_radius: f32,
+    // Lights every ReSTIR reservoir starts from, and a count of the dispatches into the
+    // reservoirs that keeps the candidates of consecutive renders apart
+    restir_candidates: u32,
+    restir_frame: u32,
+    // Reconstruction filter of the pixels and how far it reaches from their centers, in pixels
+    pixel_filter: u32,
+    filter_radius: f32,
+    // Camera rays missing the scene leave their pixels transparent rather than show the sky
+    transparent_background: u32,
+    // Factor the radiance of the paths is scaled by as they're accumulated
+    exposure: f32,
+    // Distance along rays within which hits are ignored, and the scale of the offset of the
+    // origins of rays leaving surfaces, see offset_origin
+    ray_epsilon: f32,
+    ray_offset: f32,
+    // Samples of every tile learning the irradiance cache of the final gather before the rest
+    // look it up
+    cache_samples: u32,
+}
+
+// Samples of the pixels of the tile, read and written through the functions below
+@group(0) @binding(0)
+var<storage, read_write> accumulation: array<u32>;
+
+#ifdef PUSH_CONSTANTS
+var<push_constant> frame: FrameUniforms;
+#else
+@group(0) @binding(1)
+var<uniform> frame: FrameUniforms;
+#endif
+
+// Entries of the accumulation of a pixel: RGBA32F sums of the samples weighted by the pixel filter
+// and the sum of their weights, or RGBA16F means and the sum of their weights
+fn accumulation_stride() -> u32 {
+    return select(5u, 3u, frame.half_accumulation != 0u);
+}
+
+// Weighted sum of the samples of pixel `index` of a full precision accumulation
+fn load_sum(index: u32) -> vec4<f32> {
+    return bitcast<vec4<f32>>(vec4<u32>(
+        accumulation[5u * index],
+        accumulation[5u * index + 1u],
+        accumulation[5u * index + 2u],
+        accumulation[5u * index + 3u]
+    ));
+}
+
+// Sum of the weights of the samples accumulated for pixel `index` of the tile
+fn load_weight(index: u32) -> f32 {
+    let stride = accumulation_stride();
+    return bitcast<f32>(accumulation[stride * index + stride - 1u]);
+}
+
+// Weighted mean of the samples accumulated for pixel `index` of the tile, the radiance with
+// alpha premultiplied and the alpha
+fn load_accumulation(index: u32) -> vec4<f32> {
+    if (frame.half_accumulation != 0u) {
+        return vec4<f32>(unpack2x16float(accumulation[3u * index]), unpack2x16float(accumulation[3u * index + 1u]));
+    }
+    let weight = load_weight(index);
+    if (weight == 0.0) {
+        return vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    }
+    return load_sum(index) / weight;
+}
+
+// Adds a sample of premultiplied radiance and alpha weighted by the pixel filter to pixel `index`
+// of the tile, replacing the ones there for the first sample
+fn accumulate_sample(index: u32, color: vec4<f32>, weight: f32) {
+    if (frame.half_accumulation != 0u) {
+        // Running means keep the values small enough for half precision to stay accurate
+        var mean = color;
+        var total = weight;
+        if (frame.sample_index != 0u) {
+            let previous = load_accumulation(index);
+            total += load_weight(index);
+            mean = previous;
+            if (total != 0.0) {
+                mean = previous + (color - previous) * weight / total;
+            }
+        }
+        accumulation[3u * index] = pack2x16float(mean.rg);
+        accumulation[3u * index + 1u] = pack2x16float(mean.ba);
+        accumulation[3u * index + 2u] = bitcast<u32>(total);
+        return;
+    }
+
+    var sum = color * weight;
+    var total = weight;
+    if (frame.sample_index != 0u) {
+        sum += load_sum(index);
+        total += load_weight(index);
+    }
+    let bits = bitcast<vec4<u32>>(sum);
+    accumulation[5u * index] = bits.x;
+    accumulation[5u * index + 1u] = bits.y;
+    accumulation[5u * index + 2u] = bits.z;
+    accumulation[5u * index + 3u] = bits.w;
+    accumulation[5u * index + 4u] = bitcast<u32>(total);
+}
@@ -0,0 +1,22 @@
+// Caustics at the diffuse hits of the last bounce, from the photons around them, when rendering
+// with photon mapping. Runs between shading and the shadow rays, at the hits those are queued at
+#include "photons.wgsl"
+#include "paths.wgsl"
+#include "spectral.wgsl"
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>, @builtin(local_invocation_index) local_invocation_index: u32) {
+    let index = live_path(live_slot(workgroup_id, local_invocation_index));
+    if (index == NO_PATH) {
+        return;
+    }
+    let shadow_ray = paths[index].shadow_ray;
+    if (shadow_ray.pending != SHADOW_SURFACE) {
+        return;
+    }
+
+    wavelengths = hero_wavelengths(paths[index].wavelength);
+    // The weight includes the albedo, the 1/pi of the BRDF is left
+    paths[index].radiance += shadow_ray.weight * spectrum(gather_photons(shadow_ray.origin, shadow_ray.normal)) / PI;
+}
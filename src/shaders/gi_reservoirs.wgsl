@@ -0,0 +1,113 @@
+// Reservoirs of ReSTIR GI, resampling the light the surfaces the camera sees get from a single
+// bounce. They hold, for every pixel of the image, the ones of the current sample after temporal
+// reuse, then the ones after spatial reuse, which become the previous ones of the next sample and
+// render, and last for every path of the tile the bounce it took
+//
+// Kernels using them are laid out with them taking the place of the accumulation, like the photon
+// map
+#include "frame.wgsl"
+#include "paths.wgsl"
+#include "bsdf.wgsl"
+
+struct GiReservoir {
+    // Surface the camera sees
+    position: vec3<f32>,
+    // Bounces the pick was made out of, zero for an empty reservoir
+    count: f32,
+    // Surface the picked bounce got to, which any other surface can reconnect to
+    sample_position: vec3<f32>,
+    // Sum of the resampling weights while streaming bounces in, then the weight of the pick for
+    // the estimate. Bounces of the paths themselves start out with their probability density
+    weight: f32,
+    // Encoded by encode_direction
+    normal: u32,
+    sample_normal: u32,
+    // Radiance leaving the sample towards the surface it was traced from, packed by pack_color
+    radiance: vec2<u32>,
+    // Only kept for the bounces of the paths: the light they got before it and their throughput
+    // up to the albedo of the surface the camera sees, packed alike
+    direct: vec2<u32>,
+    albedo: vec2<u32>,
+}
+
+@group(0) @binding(3)
+var<storage, read_write> gi_reservoirs: array<GiReservoir>;
+
+// Oldest reservoirs are trusted to stand for at most this many bounces
+let GI_MAX_HISTORY: f32 = 30.0;
+
+fn gi_half() -> u32 {
+    return frame.image_wh.x * frame.image_wh.y;
+}
+
+// Bounce of path `index` of the tile
+fn gi_bounce_index(index: u32) -> u32 {
+    return 2u * gi_half() + index;
+}
+
+fn pack_color(color: vec3<f32>) -> vec2<u32> {
+    let c = min(color, vec3<f32>(65504.0));
+    return vec2<u32>(pack2x16float(c.rg), pack2x16float(vec2<f32>(c.b, 0.0)));
+}
+
+fn unpack_color(packed: vec2<u32>) -> vec3<f32> {
+    return vec3<f32>(unpack2x16float(packed.x), unpack2x16float(packed.y).x);
+}
+
+fn empty_gi_reservoir(position: vec3<f32>, normal: u32) -> GiReservoir {
+    return GiReservoir(position, 0.0, vec3<f32>(0.0), 0.0, normal, 0u, vec2<u32>(0u), vec2<u32>(0u), vec2<u32>(0u));
+}
+
+// Luminance of the radiance of the pick, which the picks are distributed by
+fn gi_target_density(reservoir: GiReservoir) -> f32 {
+    return luminance(unpack_color(reservoir.radiance));
+}
+
+// Streams the bounce of `sample` into `reservoir`, standing for `count` of them with resampling
+// weight `weight`
+fn update_gi_reservoir(reservoir: ptr<function, GiReservoir>, sample: GiReservoir, weight: f32, count: f32) {
+    (*reservoir).weight += weight;
+    (*reservoir).count += count;
+    if (weight > 0.0 && rand_f32() * (*reservoir).weight < weight) {
+        (*reservoir).sample_position = sample.sample_position;
+        (*reservoir).sample_normal = sample.sample_normal;
+        (*reservoir).radiance = sample.radiance;
+    }
+}
+
+// Turns the sum of the resampling weights into the weight of the pick
+fn finish_gi_reservoir(reservoir: ptr<function, GiReservoir>) {
+    let density = gi_target_density(*reservoir);
+    if (density > 0.0 && (*reservoir).count > 0.0) {
+        (*reservoir).weight /= (*reservoir).count * density;
+    } else {
+        (*reservoir).weight = 0.0;
+    }
+}
+
+// Streams the pick of a reservoir of another sample or pixel into `reservoir`, reconnecting the
+// surface of `reservoir` to it. The pick is weighted by the Jacobian of the change in solid angle,
+// reservoirs of surfaces too far away or facing elsewhere and reconnections distorting it too much
+// aren't reused
+fn merge_gi_reservoir(reservoir: ptr<function, GiReservoir>, other: GiReservoir) {
+    let position = (*reservoir).position;
+    let normal = decode_normal(bitcast<f32>((*reservoir).normal));
+    let tolerance = 0.05 * length(position - scene.camera_position);
+    if (other.count == 0.0 || dot(decode_normal(bitcast<f32>(other.normal)), normal) < 0.9 || length(other.position - position) > tolerance) {
+        return;
+    }
+
+    let sample_normal = decode_normal(bitcast<f32>(other.sample_normal));
+    let from_old = other.position - other.sample_position;
+    let from_new = position - other.sample_position;
+    let cos_old = abs(dot(sample_normal, normalize(from_old)));
+    let cos_new = dot(sample_normal, normalize(from_new));
+    if (cos_new <= 0.0 || dot(normal, from_new) >= 0.0) {
+        return;
+    }
+    let jacobian = cos_new / max(cos_old, 1.0e-6) * dot(from_old, from_old) / max(dot(from_new, from_new), 1.0e-12);
+    if (jacobian < 0.1 || jacobian > 10.0) {
+        return;
+    }
+    update_gi_reservoir(reservoir, other, gi_target_density(other) * other.weight * other.count * jacobian, other.count);
+}
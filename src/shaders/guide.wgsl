@@ -0,0 +1,83 @@
+// Diffuse bounces of the last hits guided towards the light arriving at them when rendering with
+// path guiding, and the light found since the previous diffuse bounce of every path added to the
+// guide. Runs after the shadow rays, once all the light of the bounce reached the paths
+//
+// The bounces pick from the guide or the diffuse lobe they sampled, weighted by the density of
+// both. Only the Lambertian part of the lobe is reevaluated for the directions of the guide
+#include "path_guide.wgsl"
+#include "bsdf.wgsl"
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>, @builtin(local_invocation_index) local_invocation_index: u32) {
+    let index = live_path(live_slot(workgroup_id, local_invocation_index));
+    if (index == NO_PATH) {
+        return;
+    }
+    var path = paths[index];
+    let diffuse = path.alive != 0u && path.event == EVENT_DIFFUSE;
+    // Light found through specular bounces still counts for the diffuse one before them
+    if (path.alive != 0u && !diffuse) {
+        return;
+    }
+
+    let record = guide.records[index];
+    if (record.cell != 0u) {
+        let throughput = luminance(record.throughput);
+        let incident = luminance(path.radiance - record.radiance) / max(throughput, 1.0e-30);
+        if (throughput > 0.0 && incident > 0.0) {
+            let deposit = min(incident / record.pdf * GUIDE_SCALE, GUIDE_MAX_DEPOSIT);
+            let bin = guide_histogram(1u - guiding_set(), record.cell - 1u) + record.bin;
+            atomicAdd(&guide.bins[bin], u32(deposit));
+        }
+    }
+    if (!diffuse) {
+        guide.records[index].cell = 0u;
+        return;
+    }
+
+    rng_state = path.rng_state;
+    let cell = guide_cell(path.ray.origin);
+    let histogram = guide_histogram(guiding_set(), cell);
+    var total = 0.0;
+    for (var i = 0u; i < GUIDE_BINS; i += 1u) {
+        total += f32(atomicLoad(&guide.bins[histogram + i]));
+    }
+
+    var direction = normalize(path.ray.direction);
+    if (total > 0.0 && rand_f32() < GUIDE_FRACTION) {
+        let threshold = rand_f32() * total;
+        var sum = 0.0;
+        var bin = GUIDE_BINS - 1u;
+        for (var i = 0u; i < GUIDE_BINS; i += 1u) {
+            sum += f32(atomicLoad(&guide.bins[histogram + i]));
+            if (sum > threshold) {
+                bin = i;
+                break;
+            }
+        }
+        direction = sample_guide_bin(bin);
+    }
+    path.rng_state = rng_state;
+
+    let normal = path.shadow_ray.normal;
+    let bsdf_pdf = max(dot(normal, direction), 0.0) / PI;
+    var pdf = bsdf_pdf;
+    if (total > 0.0) {
+        let bin = guide_bin(direction);
+        let guide_pdf = f32(atomicLoad(&guide.bins[histogram + bin])) / total * f32(GUIDE_BINS) / (4.0 * PI);
+        pdf = mix(bsdf_pdf, guide_pdf, GUIDE_FRACTION);
+    }
+    // Directions of the guide below the surface don't get any light
+    if (bsdf_pdf <= 0.0 || pdf <= 0.0) {
+        path.alive = 0u;
+        paths[index] = path;
+        guide.records[index].cell = 0u;
+        return;
+    }
+
+    path.ray.direction = direction;
+    path.throughput *= bsdf_pdf / pdf;
+    paths[index] = path;
+    guide.records[index] = GuideRecord(path.radiance, cell + 1u, path.throughput, guide_bin(direction), pdf);
+}
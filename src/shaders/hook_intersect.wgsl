@@ -0,0 +1 @@
+// Replaced through ShaderHook::Intersect
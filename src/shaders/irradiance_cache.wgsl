@@ -0,0 +1,105 @@
+// Irradiance cache of the final gather: for every cell of a grid over the scene and every axis
+// the normals of the surfaces in it face along, the sum of the light the diffuse bounces off
+// them found, as the cosine weighted radiance arriving at them, and how many bounces it came
+// from. Learned by the samples before FrameUniforms::cache_samples and looked up by the ones
+// after. Kernels using it are laid out with it taking the place of the accumulation, like the
+// photon map
+#include "frame.wgsl"
+#include "scene.wgsl"
+
+// Cells along every axis of the bounds of the scene
+let CACHE_RESOLUTION: u32 = 32u;
+let CACHE_CELLS: u32 = 32768u;
+// Fixed point scale of the sums, and the cap of what a single bounce adds so they don't overflow
+let CACHE_SCALE: f32 = 256.0;
+let CACHE_MAX_DEPOSIT: f32 = 65536.0;
+
+// Diffuse bounce of a path whose light hasn't been added to the cache yet
+struct CacheRecord {
+    // Radiance of the path when it bounced
+    radiance: vec3<f32>,
+    // Index of the entry of the cache plus one, zero without a pending bounce
+    entry: u32,
+    // Throughput of the path from then on
+    throughput: vec3<f32>,
+}
+
+struct IrradianceCache {
+    // RGB sums and the count of every entry, for the six axes of every cell in turn
+    entries: array<atomic<u32>, 786432>,
+    // One for every path of the tile
+    records: array<CacheRecord>,
+}
+
+@group(0) @binding(3)
+var<storage, read_write> cache: IrradianceCache;
+
+// Whether paths look the light arriving at their bounces up in the cache rather than learn it
+fn gathering() -> bool {
+    return frame.sample_index >= frame.cache_samples;
+}
+
+// Position of `position` on the grid, cell centers at whole numbers
+fn cache_grid(position: vec3<f32>) -> vec3<f32> {
+    let root = bvh_nodes[0];
+    let extent = max(root.aabb_max - root.aabb_min, vec3<f32>(1.0e-6));
+    return (position - root.aabb_min) / extent * f32(CACHE_RESOLUTION) - 0.5;
+}
+
+// Axis and direction along it `normal` faces the most, from 0 to 5
+fn cache_axis(normal: vec3<f32>) -> u32 {
+    let a = abs(normal);
+    var axis = 2u;
+    if (a.x >= a.y && a.x >= a.z) {
+        axis = 0u;
+    } else if (a.y >= a.z) {
+        axis = 1u;
+    }
+    return axis * 2u + select(0u, 1u, normal[axis] < 0.0);
+}
+
+// Entry of the cell at `cell` on the grid, clamped to its border, for surfaces facing `axis`
+fn cache_entry(cell: vec3<i32>, axis: u32) -> u32 {
+    let c = vec3<u32>(clamp(cell, vec3<i32>(0), vec3<i32>(i32(CACHE_RESOLUTION) - 1)));
+    return axis * CACHE_CELLS + (c.z * CACHE_RESOLUTION + c.y) * CACHE_RESOLUTION + c.x;
+}
+
+// Adds the cosine weighted radiance `incident` arriving at a surface to `entry`
+fn cache_deposit(entry: u32, incident: vec3<f32>) {
+    let deposit = min(max(incident, vec3<f32>(0.0)) * CACHE_SCALE, vec3<f32>(CACHE_MAX_DEPOSIT));
+    atomicAdd(&cache.entries[4u * entry], u32(deposit.r));
+    atomicAdd(&cache.entries[4u * entry + 1u], u32(deposit.g));
+    atomicAdd(&cache.entries[4u * entry + 2u], u32(deposit.b));
+    atomicAdd(&cache.entries[4u * entry + 3u], 1u);
+}
+
+// Cosine weighted radiance arriving at a surface at `position` facing `normal`, interpolated
+// trilinearly between the cells around it that learned anything, with the sum of the weights of
+// those in the alpha channel, zero if none did
+fn cache_lookup(position: vec3<f32>, normal: vec3<f32>) -> vec4<f32> {
+    let grid = cache_grid(position);
+    let base = vec3<i32>(floor(grid));
+    let t = grid - floor(grid);
+    let axis = cache_axis(normal);
+    var sum = vec4<f32>(0.0);
+    for (var corner = 0u; corner < 8u; corner += 1u) {
+        let offset = vec3<u32>(corner & 1u, (corner >> 1u) & 1u, corner >> 2u);
+        let entry = cache_entry(base + vec3<i32>(offset), axis);
+        let count = f32(atomicLoad(&cache.entries[4u * entry + 3u]));
+        if (count == 0.0) {
+            continue;
+        }
+        let weights = select(1.0 - t, t, offset == vec3<u32>(1u));
+        let weight = weights.x * weights.y * weights.z;
+        let total = vec3<f32>(
+            f32(atomicLoad(&cache.entries[4u * entry])),
+            f32(atomicLoad(&cache.entries[4u * entry + 1u])),
+            f32(atomicLoad(&cache.entries[4u * entry + 2u]))
+        );
+        sum += vec4<f32>(total / (count * CACHE_SCALE), 1.0) * weight;
+    }
+    if (sum.a > 0.0) {
+        return vec4<f32>(sum.rgb / sum.a, sum.a);
+    }
+    return vec4<f32>(0.0);
+}
@@ -0,0 +1,25 @@
+// Paths from the lights when rendering with bidirectional path tracing, one for every path of the
+// tile made of the diffuse surfaces it reached, which the camera path connects to
+//
+// Kernels using it are laid out with it taking the place of the accumulation, like the photon map
+#include "frame.wgsl"
+
+// Surfaces a path from the lights bounces off at most
+let LIGHT_VERTICES: u32 = 4u;
+
+struct LightVertex {
+    position: vec3<f32>,
+    // Surfaces hit from the light up to this one, zero past the last vertex of a path
+    depth: u32,
+    // Facing the side the light came from
+    normal: vec3<f32>,
+    // Bit `i` is set if the light scattered diffusely off the surface `i + 1` bounces from it
+    labels: u32,
+    // Power arriving times the BRDF of the surface, over the probability of the path
+    power: vec3<f32>,
+    // Whether only the hero wavelength is left
+    hero_only: u32,
+}
+
+@group(0) @binding(3)
+var<storage, read_write> light_vertices: array<LightVertex>;
@@ -0,0 +1,56 @@
+// Paths from the lights for bidirectional path tracing, one for every camera path of the tile at
+// its wavelengths, traced through all the lobes of the surfaces they hit. Runs after ray
+// generation, storing where it scattered diffusely for the camera paths to connect to
+#include "light_paths.wgsl"
+#include "paths.wgsl"
+#include "bsdf.wgsl"
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    if (!in_tile(global_invocation_id)) {
+        return;
+    }
+    let index = path_index(global_invocation_id);
+    let first = index * LIGHT_VERTICES;
+    // Decorrelated from the camera path, which starts from the same state
+    rng_state = pcg_hash(paths[index].rng_state ^ 0x5bd1e995u);
+    wavelengths = hero_wavelengths(paths[index].wavelength);
+    hero_only = false;
+
+    var stored = 0u;
+    if (scene.light_count > 0u) {
+        let light = pick_light(rand_f32());
+        var ray: Ray;
+        var power = spectrum(emit_light(lights[light], &ray)) / light_probability(light);
+        var labels = 0u;
+
+        for (var depth = 1u; depth <= LIGHT_VERTICES; depth += 1u) {
+            var rec: HitRecord;
+            if (!hit_world(ray, frame.ray_epsilon, T_MAX, &rec)) {
+                break;
+            }
+            let material = materials[rec.material];
+#ifdef HOOK_SCATTER
+            if (material.custom != MATERIAL_BUILTIN) {
+                break;
+            }
+#endif
+            let base_color = spectrum(base_color(material, rec.uv, 0.0) * rec.color);
+            let bsdf = sample_bsdf(material, base_color, -normalize(ray.direction), rec.normal, rec.tangent, rec.front_face);
+            if (bsdf.event == EVENT_DIFFUSE) {
+                labels |= 1u << (depth - 1u);
+                light_vertices[first + stored] = LightVertex(rec.hit_point, depth, rec.normal, labels, power * base_color / PI, u32(hero_only));
+                stored += 1u;
+            }
+            if (bsdf.event == EVENT_ABSORBED || bsdf.event == EVENT_SUBSURFACE) {
+                break;
+            }
+            power *= bsdf.weight;
+            ray = Ray(offset_origin(rec.hit_point, rec.normal, bsdf.direction), bsdf.direction);
+        }
+    }
+    if (stored < LIGHT_VERTICES) {
+        light_vertices[first + stored].depth = 0u;
+    }
+}
@@ -0,0 +1,57 @@
+// Paths starting at the texels of a lightmap instead of the camera, leaving the surface of every
+// texel in a cosine weighted direction, so that they bring back its irradiance over pi. The
+// direct light of the texels is sampled right away, like at the diffuse hits. Texels the surface
+// doesn't cover start paths that carry nothing
+//
+// Laid out with the texels taking the place of the accumulation, like the photon map
+#include "frame.wgsl"
+#include "paths.wgsl"
+#include "random.wgsl"
+#include "ray_chit.wgsl"
+#include "spectral.wgsl"
+
+struct LightmapTexel {
+    position: vec3<f32>,
+    covered: u32,
+    normal: vec3<f32>,
+}
+
+// Every texel of the lightmap, rows from the top down
+@group(0) @binding(3)
+var<storage, read_write> lightmap_texels: array<LightmapTexel>;
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    if (!in_tile(global_invocation_id)) {
+        return;
+    }
+
+    let pixel = frame.tile_origin + global_invocation_id.xy;
+    let pixel_seed = pcg_hash(pixel.y * frame.image_wh.x + pixel.x) ^ pcg_hash(frame.seed);
+    rng_state = pcg_hash(pixel_seed ^ pcg_hash(frame.sample_index));
+
+    let texel = lightmap_texels[pixel.y * frame.image_wh.x + pixel.x];
+    var direction = texel.normal + random_unit_vector();
+    if (dot(direction, direction) < 1.0e-8) {
+        direction = texel.normal;
+    }
+    let ray = Ray(offset_origin(texel.position, texel.normal, direction), normalize(direction));
+    let throughput = select(vec3<f32>(0.0, 0.0, 0.0), vec3<f32>(1.0, 1.0, 1.0), texel.covered != 0u);
+
+    var shadow_ray: ShadowRay;
+    shadow_ray.pending = 0u;
+#ifdef SPECTRAL
+    let wavelength = sample_hero_wavelength();
+#else
+    let wavelength = 0.0;
+#endif
+    var radiance = vec3<f32>(0.0, 0.0, 0.0);
+#ifdef NEE
+    if (texel.covered != 0u) {
+        wavelengths = hero_wavelengths(wavelength);
+        radiance = spectrum(direct_light(texel.position, texel.normal));
+    }
+#endif
+    start_path(path_index(global_invocation_id), PathState(ray, throughput, rng_state, radiance, 1u, 0u, EVENT_ABSORBED, 0.0, NO_MEDIUM, shadow_ray, 0u, wavelength, 0u, 0u, 1.0, 0u, vec4<u32>(NO_MEDIUM)));
+}
@@ -0,0 +1,83 @@
+// Guide of the diffuse bounces when rendering with path guiding: for every cell of a grid over
+// the scene, a histogram of the light arriving from the directions of equal solid angle bins,
+// learned from the light the paths bouncing off the surfaces in it found
+//
+// There are two sets of histograms, every sample guides the bounces by one and trains the
+// other, alternating, so the one paths are guided by stays the same throughout a sample. Kernels
+// using it are laid out with it taking the place of the accumulation, like the photon map
+#include "frame.wgsl"
+#include "random.wgsl"
+#include "scene.wgsl"
+
+// Cells along every axis of the bounds of the scene
+let GUIDE_RESOLUTION: u32 = 16u;
+let GUIDE_CELLS: u32 = 4096u;
+// Bins along the cosine to the z axis and the angle around it, the cosine makes them equal area
+let GUIDE_SIDE: u32 = 8u;
+let GUIDE_BINS: u32 = 64u;
+// Both sets of histograms
+let GUIDE_HISTOGRAM_BINS: u32 = 524288u;
+// Fixed point scale of the bins, and the cap of what a single path adds so they don't overflow
+let GUIDE_SCALE: f32 = 256.0;
+let GUIDE_MAX_DEPOSIT: f32 = 65536.0;
+// Fraction of diffuse bounces sampled from the guide rather than the BSDF once it learned
+// anything about a cell
+let GUIDE_FRACTION: f32 = 0.5;
+
+// Diffuse bounce of a path whose light hasn't been added to the guide yet
+struct GuideRecord {
+    // Radiance of the path when it bounced
+    radiance: vec3<f32>,
+    // Index of the cell plus one, zero without a pending bounce
+    cell: u32,
+    // Throughput of the path from then on
+    throughput: vec3<f32>,
+    bin: u32,
+    // Probability density of the direction it bounced in
+    pdf: f32,
+}
+
+struct PathGuide {
+    bins: array<atomic<u32>, GUIDE_HISTOGRAM_BINS>,
+    // One for every path of the tile
+    records: array<GuideRecord>,
+}
+
+@group(0) @binding(3)
+var<storage, read_write> guide: PathGuide;
+
+// Cell of the grid `position` is in, the ones outside the bounds are clamped to the border
+fn guide_cell(position: vec3<f32>) -> u32 {
+    let root = bvh_nodes[0];
+    let extent = max(root.aabb_max - root.aabb_min, vec3<f32>(1.0e-6));
+    let cell = clamp(
+        vec3<i32>((position - root.aabb_min) / extent * f32(GUIDE_RESOLUTION)),
+        vec3<i32>(0),
+        vec3<i32>(i32(GUIDE_RESOLUTION) - 1)
+    );
+    return (u32(cell.z) * GUIDE_RESOLUTION + u32(cell.y)) * GUIDE_RESOLUTION + u32(cell.x);
+}
+
+fn guide_bin(direction: vec3<f32>) -> u32 {
+    let cosine = min(u32((direction.z * 0.5 + 0.5) * f32(GUIDE_SIDE)), GUIDE_SIDE - 1u);
+    let angle = min(u32((atan2(direction.y, direction.x) / 6.2831853 + 0.5) * f32(GUIDE_SIDE)), GUIDE_SIDE - 1u);
+    return cosine * GUIDE_SIDE + angle;
+}
+
+// Uniformly distributed direction within `bin`
+fn sample_guide_bin(bin: u32) -> vec3<f32> {
+    let z = (f32(bin / GUIDE_SIDE) + rand_f32()) / f32(GUIDE_SIDE) * 2.0 - 1.0;
+    let a = ((f32(bin % GUIDE_SIDE) + rand_f32()) / f32(GUIDE_SIDE) - 0.5) * 6.2831853;
+    let r = sqrt(max(1.0 - z * z, 0.0));
+    return vec3<f32>(r * cos(a), r * sin(a), z);
+}
+
+// Index of the first bin of the histogram of `cell` of set `histograms`
+fn guide_histogram(histograms: u32, cell: u32) -> u32 {
+    return (histograms * GUIDE_CELLS + cell) * GUIDE_BINS;
+}
+
+// Set of histograms the paths of this sample are guided by, the other one is trained
+fn guiding_set() -> u32 {
+    return frame.sample_index & 1u;
+}
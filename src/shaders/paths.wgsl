@@ -0,0 +1,205 @@
+// Queues the kernels of the wavefront integrator hand their work over through, with an entry for
+// every path of the tile
+#include "frame.wgsl"
+#include "scene.wgsl"
+
+// Light a diffuse hit or scattering in the fog receives straight from the lights, which reaches the
+// camera scaled by `weight` unless the lights are occluded. `pending` is one of the SHADOW
+// constants, zero once traced
+struct ShadowRay {
+    origin: vec3<f32>,
+    pending: u32,
+    // Normal of the surface, or direction the path travelled in to the fog
+    normal: vec3<f32>,
+    // Light links of the lights that don't illuminate the surface, see `unlinked_lights`
+    unlinked: u32,
+    weight: vec3<f32>,
+}
+
+// A path being traced, which is only extended while `alive` is set
+struct PathState {
+    ray: Ray,
+    throughput: vec3<f32>,
+    rng_state: u32,
+    radiance: vec3<f32>,
+    alive: u32,
+    // Surfaces hit so far
+    bounce: u32,
+    // How the path scattered off the last one, one of the EVENT constants
+    event: u32,
+    // Width of the cone of rays around `ray` at its origin, widening by pixel_spread per unit
+    // of distance, to filter textures over what the pixel sees of them
+    cone_width: f32,
+    // Material whose subsurface medium the path is inside of, NO_MEDIUM outside of any
+    medium: u32,
+    // Queued at the last bounce, part of the path to stay within the storage buffers WebGPU
+    // guarantees
+    shadow_ray: ShadowRay,
+    // Scattering events of the random walk through the current medium
+    walk_steps: u32,
+    // Hero wavelength of the channels with SPECTRAL defined, zero for radiance in RGB
+    wavelength: f32,
+    // Whether only the hero wavelength is left
+    hero_only: u32,
+    // Labels of the last surfaces hit, for bidirectional path tracing: bit `i` is set if the one
+    // `i` bounces back scattered diffusely and bit `16 + i` if a path from the lights could have
+    // gone through it, see `push_vertex_label`
+    vertex_labels: u32,
+    // Coverage of the pixel, less than one where the camera sees a shadow catcher
+    alpha: f32,
+    // Whether the camera ray hit a shadow catcher, whose shadows then make up the alpha
+    catcher: u32,
+    // Transmissive materials the path went into and hasn't left yet, in the order it entered
+    // them, followed by NO_MEDIUM. The one of the highest priority fills the space they overlap
+    dielectrics: vec4<u32>,
+}
+
+// Closest hit of the ray of a path, `front_face` is zero or one and `hit` zero for misses, or else
+// one and the OBJECT flags of what was hit above it
+struct Hit {
+    hit_point: vec3<f32>,
+    material: u32,
+    normal: vec3<f32>,
+    front_face: u32,
+    distance: f32,
+    hit: u32,
+    uv: vec2<f32>,
+    object: u32,
+    texel_scale: f32,
+    // Encoded by encode_direction
+    tangent: u32,
+    // Square root of the color of the hit packed by pack4x8unorm
+    color: u32,
+}
+
+// Buckets paths are sorted into by what they hit, the last one holds the misses
+#define SORT_BUCKETS 64u
+
+// Two lists of the indices of the live paths, stream compaction moves the survivors of the
+// `current` one to the other, which in turn becomes current, and sorting all of them
+struct LivePaths {
+    // Arguments of the indirect dispatches over the current list, a workgroup for every
+    // WORKGROUP_INVOCATIONS paths
+    dispatch_x: u32,
+    dispatch_y: u32,
+    dispatch_z: u32,
+    current: u32,
+    counts: array<atomic<u32>, 2>,
+    // Next slot of the current list for persistent threads to take
+    next_slot: atomic<u32>,
+    // Sizes of the buckets while sorting, then where the next path of each goes
+    buckets: array<atomic<u32>, SORT_BUCKETS>,
+    // List `l` starts at `l` times the number of pixels in the tile
+    indices: array<u32>,
+}
+
+let NO_PATH: u32 = 0xffffffffu;
+
+fn hit_flags(hit: Hit) -> u32 {
+    return hit.hit >> 1u;
+}
+
+let EVENT_ABSORBED: u32 = 0u;
+let EVENT_DIFFUSE: u32 = 1u;
+let EVENT_METALLIC: u32 = 2u;
+let EVENT_REFLECTION: u32 = 3u;
+let EVENT_TRANSMISSION: u32 = 4u;
+let EVENT_CUSTOM: u32 = 5u;
+let EVENT_SUBSURFACE: u32 = 6u;
+let EVENT_VOLUME: u32 = 7u;
+
+let SHADOW_SURFACE: u32 = 1u;
+let SHADOW_VOLUME: u32 = 2u;
+// A surface whose light through the portals was added already, only the lights are left
+let SHADOW_LIGHTS: u32 = 3u;
+
+let NO_MEDIUM: u32 = 0xffffffffu;
+// Scattering events after which random walks through subsurface media are cut short
+let MAX_WALK_STEPS: u32 = 256u;
+
+@group(2) @binding(0)
+var<storage, read_write> paths: array<PathState>;
+
+@group(2) @binding(1)
+var<storage, read_write> hits: array<Hit>;
+
+@group(2) @binding(2)
+var<storage, read_write> live: LivePaths;
+
+// Labels of a path that just scattered off a surface with `event`
+fn push_vertex_label(labels: u32, event: u32) -> u32 {
+    let diffuse = select(0u, 1u, event == EVENT_DIFFUSE);
+    let traceable = select(0u, 0x10000u, event >= EVENT_DIFFUSE && event <= EVENT_TRANSMISSION);
+    return ((labels << 1u) & 0xfffefffeu) | diffuse | traceable;
+}
+
+fn path_index(global_invocation_id: vec3<u32>) -> u32 {
+    return global_invocation_id.y * frame.tile_wh.x + global_invocation_id.x;
+}
+
+fn path_count() -> u32 {
+    return frame.tile_wh.x * frame.tile_wh.y;
+}
+
+// Index in the whole image of the pixel path `index` of the tile traces
+fn pixel_index(index: u32) -> u32 {
+    let pixel = frame.tile_origin + vec2<u32>(index % frame.tile_wh.x, index / frame.tile_wh.x);
+    return pixel.y * frame.image_wh.x + pixel.x;
+}
+
+// Starts `path` as path `index` of the tile and lists it as live, the first one also resets the
+// lists for a new sample
+fn start_path(index: u32, path: PathState) {
+    paths[index] = path;
+    live.indices[index] = index;
+
+    if (index == 0u) {
+        let count = path_count();
+        live.dispatch_x = (count + WORKGROUP_INVOCATIONS - 1u) / WORKGROUP_INVOCATIONS;
+        live.dispatch_y = 1u;
+        live.dispatch_z = 1u;
+        live.current = 0u;
+        atomicStore(&live.counts[0], count);
+        atomicStore(&live.counts[1], 0u);
+        atomicStore(&live.next_slot, 0u);
+        for (var bucket = 0u; bucket < SORT_BUCKETS; bucket += 1u) {
+            atomicStore(&live.buckets[bucket], 0u);
+        }
+    }
+}
+
+// Slot in the current list of live paths of an invocation of an indirect dispatch
+fn live_slot(workgroup_id: vec3<u32>, local_invocation_index: u32) -> u32 {
+    return workgroup_id.x * WORKGROUP_INVOCATIONS + local_invocation_index;
+}
+
+// Index of the path in `slot` of the current list, NO_PATH past its end
+fn live_path(slot: u32) -> u32 {
+    let current = live.current;
+    if (slot >= atomicLoad(&live.counts[current])) {
+        return NO_PATH;
+    }
+    return live.indices[current * path_count() + slot];
+}
+
+fn in_tile(global_invocation_id: vec3<u32>) -> bool {
+    return global_invocation_id.x < frame.tile_wh.x && global_invocation_id.y < frame.tile_wh.y;
+}
+
+// Origin of a ray leaving the surface at `position` with `normal` to the side of it `direction`
+// is on, moved off the surface by units in the last place of its coordinates so that their
+// rounding doesn't put it back behind the surface at any scale of the scene ("A Fast and Robust
+// Method for Avoiding Self-Intersection", Wächter and Binder 2019)
+fn offset_origin(position: vec3<f32>, normal: vec3<f32>, direction: vec3<f32>) -> vec3<f32> {
+    let offset = select(-normal, normal, dot(normal, direction) >= 0.0) * frame.ray_offset;
+    let ulps = vec3<i32>(256.0 * offset);
+    let bits = bitcast<vec3<i32>>(position) + select(ulps, -ulps, position < vec3<f32>(0.0));
+    // Close to the origin floats are too dense for the units in the last place to get far
+    let near_origin = abs(position) < vec3<f32>(1.0 / 32.0);
+    return select(bitcast<vec3<f32>>(bits), position + offset / 65536.0, near_origin);
+}
+
+// Angle between the camera rays through neighbouring pixels
+fn pixel_spread() -> f32 {
+    return 2.0 * scene.tan_half_fov / f32(frame.image_wh.y);
+}
@@ -0,0 +1,62 @@
+// Photons of a sample, emitted from the point and directional lights and traced through the
+// specular lobes of the surfaces they hit. Every one that bounced at least once is stored where
+// it next meets a surface with a diffuse lobe, which is where it stops, because the camera paths
+// find the light scattered further by diffuse surfaces themselves
+#include "photons.wgsl"
+#include "paths.wgsl"
+#include "bsdf.wgsl"
+
+fn trace_photon(index: u32) {
+    rng_state = pcg_hash(index ^ pcg_hash(frame.seed ^ pcg_hash(frame.sample_index ^ pcg_hash(frame.tile_origin.x ^ pcg_hash(frame.tile_origin.y)))));
+    // Photons carry RGB, with dispersion at the wavelength of red
+    wavelengths = channel_wavelengths();
+    hero_only = true;
+
+    let light = pick_light(rand_f32());
+    var ray: Ray;
+    // Every photon stands for its share of the power of all the lights
+    var power = emit_light(lights[light], &ray) / (light_probability(light) * f32(frame.photon_count));
+
+    for (var bounce = 0u; bounce <= frame.max_bounces; bounce += 1u) {
+        var rec: HitRecord;
+        if (!hit_world(ray, frame.ray_epsilon, T_MAX, &rec)) {
+            return;
+        }
+        let material = materials[rec.material];
+#ifdef HOOK_SCATTER
+        if (material.custom != MATERIAL_BUILTIN) {
+            return;
+        }
+#endif
+        let diffuse = (1.0 - material.metallic) * (1.0 - material.transmission);
+        if (bounce > 0u && diffuse > 0.0) {
+            store_photon(rec.hit_point, power, ray.direction);
+#ifdef DETERMINISTIC
+            // At most one photon each never overflows the map, which would keep the ones stored
+            // first
+            return;
+#endif
+        }
+
+        let base_color = base_color(material, rec.uv, 0.0) * rec.color;
+        let bsdf = sample_bsdf(material, base_color, -normalize(ray.direction), rec.normal, rec.tangent, rec.front_face);
+        if (bsdf.event == EVENT_ABSORBED || bsdf.event == EVENT_DIFFUSE || bsdf.event == EVENT_SUBSURFACE) {
+            return;
+        }
+        power *= bsdf.weight;
+        ray = Ray(offset_origin(rec.hit_point, rec.normal, bsdf.direction), bsdf.direction);
+    }
+}
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>, @builtin(num_workgroups) num_workgroups: vec3<u32>, @builtin(local_invocation_index) local_invocation_index: u32) {
+    if (scene.light_count == 0u) {
+        return;
+    }
+    // Dispatches are capped in size, so invocations may trace more than one photon
+    let stride = num_workgroups.x * WORKGROUP_INVOCATIONS;
+    for (var index = workgroup_id.x * WORKGROUP_INVOCATIONS + local_invocation_index; index < frame.photon_count; index += stride) {
+        trace_photon(index);
+    }
+}
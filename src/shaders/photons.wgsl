@@ -0,0 +1,122 @@
+// Photon map of a sample when rendering with photon mapping: the photons that reached diffuse
+// surfaces after bouncing off specular ones, linked into lists per cell of a grid twice the
+// gather radius wide, which are hashed into a fixed number of heads
+//
+// Kernels using it are laid out with it taking the place of the accumulation, to stay within
+// the storage buffers WebGPU guarantees
+#include "frame.wgsl"
+#include "random.wgsl"
+#include "ray_intersect.wgsl"
+
+let PHOTON_CELLS: u32 = 262144u;
+// Cap on the photons of a list looked at, in case many more than expected land in one cell
+let MAX_GATHER_STEPS: u32 = 4096u;
+
+struct Photon {
+    position: vec3<f32>,
+    // Index of the next photon of the same list plus one, zero at the end of it
+    next: u32,
+    power: vec3<f32>,
+    // Direction the photon arrived in, as encoded by encode_direction
+    direction: u32,
+}
+
+struct PhotonMap {
+    count: atomic<u32>,
+    // Index of the last photon of every list plus one, zero for empty lists
+    heads: array<atomic<u32>, PHOTON_CELLS>,
+    photons: array<Photon>,
+}
+
+@group(0) @binding(3)
+var<storage, read_write> photon_map: PhotonMap;
+
+fn photon_cell(position: vec3<f32>) -> vec3<i32> {
+    return vec3<i32>(floor(position / (2.0 * frame.photon_radius)));
+}
+
+fn photon_head(cell: vec3<i32>) -> u32 {
+    let c = bitcast<vec3<u32>>(cell);
+    return pcg_hash(c.x ^ pcg_hash(c.y ^ pcg_hash(c.z))) % PHOTON_CELLS;
+}
+
+fn store_photon(position: vec3<f32>, power: vec3<f32>, direction: vec3<f32>) {
+    let slot = atomicAdd(&photon_map.count, 1u);
+    if (slot >= frame.photon_count) {
+        return;
+    }
+    let next = atomicExchange(&photon_map.heads[photon_head(photon_cell(position))], slot + 1u);
+    photon_map.photons[slot] = Photon(position, next, power, encode_direction(normalize(direction)));
+}
+
+// Whether `photon` of the list of `cell` lies within the radius around `position` and arrived
+// from the side of a surface facing `normal`
+fn gathers_photon(photon: Photon, cell: vec3<i32>, position: vec3<f32>, normal: vec3<f32>) -> bool {
+    // Other cells hashed to the same head are gathered on their own
+    let offset = photon.position - position;
+    let radius = frame.photon_radius;
+    return all(photon_cell(photon.position) == cell) && dot(offset, offset) <= radius * radius
+        && dot(decode_normal(bitcast<f32>(photon.direction)), normal) < 0.0;
+}
+
+// Cell of the corner of the cells around `position`, from 0 to 7. Cells are as wide as the sphere
+// the photons are gathered from, which overlaps at most two of them along every axis
+fn gather_cell(position: vec3<f32>, corner: u32) -> vec3<i32> {
+    let lower = photon_cell(position - frame.photon_radius);
+    return lower + vec3<i32>(vec3<u32>(corner & 1u, (corner >> 1u) & 1u, corner >> 2u));
+}
+
+// Irradiance at `position` on a surface facing `normal` from the photons within the radius
+// around it, arriving from its side
+fn gather_photons(position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    let radius = frame.photon_radius;
+#ifdef DETERMINISTIC
+    // Photons are linked into the lists in whatever order the GPU stores them, so their powers
+    // are summed as integers, in steps of a power of two below the largest one, for the sum not
+    // to depend on it
+    var largest = 0.0;
+    for (var corner = 0u; corner < 8u; corner += 1u) {
+        let cell = gather_cell(position, corner);
+        var next = atomicLoad(&photon_map.heads[photon_head(cell)]);
+        for (var steps = 0u; next != 0u && steps < MAX_GATHER_STEPS; steps += 1u) {
+            let photon = photon_map.photons[next - 1u];
+            next = photon.next;
+            if (gathers_photon(photon, cell, position, normal)) {
+                largest = max(largest, max(photon.power.x, max(photon.power.y, photon.power.z)));
+            }
+        }
+    }
+    if (largest <= 0.0) {
+        return vec3<f32>(0.0, 0.0, 0.0);
+    }
+    // Leaves room for all the photons the lists can hold without overflowing
+    let step = exp2(floor(log2(largest)) - 15.0);
+    var sum = vec3<u32>(0u, 0u, 0u);
+    for (var corner = 0u; corner < 8u; corner += 1u) {
+        let cell = gather_cell(position, corner);
+        var next = atomicLoad(&photon_map.heads[photon_head(cell)]);
+        for (var steps = 0u; next != 0u && steps < MAX_GATHER_STEPS; steps += 1u) {
+            let photon = photon_map.photons[next - 1u];
+            next = photon.next;
+            if (gathers_photon(photon, cell, position, normal)) {
+                sum += vec3<u32>(round(max(photon.power, vec3<f32>(0.0)) / step));
+            }
+        }
+    }
+    let power = vec3<f32>(sum) * step;
+#else
+    var power = vec3<f32>(0.0, 0.0, 0.0);
+    for (var corner = 0u; corner < 8u; corner += 1u) {
+        let cell = gather_cell(position, corner);
+        var next = atomicLoad(&photon_map.heads[photon_head(cell)]);
+        for (var steps = 0u; next != 0u && steps < MAX_GATHER_STEPS; steps += 1u) {
+            let photon = photon_map.photons[next - 1u];
+            next = photon.next;
+            if (gathers_photon(photon, cell, position, normal)) {
+                power += photon.power;
+            }
+        }
+    }
+#endif
+    return power / (PI * radius * radius);
+}
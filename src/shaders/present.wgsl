@@ -0,0 +1,15 @@
+@group(0) @binding(0)
+var image: texture_2d<f32>;
+
+// Fullscreen triangle covering the surface
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Copies the pixel of the image under the fragment, the image is as large as the surface
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    return textureLoad(image, vec2<i32>(position.xy), 0);
+}
@@ -0,0 +1,42 @@
+// Closest hits of the camera rays when rendering hybrid, starting from the primitives rasterized
+// at the centers of their pixels instead of traversing the BVH
+#include "trace_path.wgsl"
+
+let NO_PRIMITIVE: u32 = 0xffffffffu;
+// Entries before the rows of the visibility, whose copy has to start 256 bytes in
+let VISIBILITY_HEADER: u32 = 64u;
+
+// Starts with the entries in a row of the tile and whether the scene has primitives besides
+// triangles, which aren't rasterized, followed by the primitive seen at every pixel
+@group(0) @binding(3)
+var<storage, read_write> visibility: array<u32>;
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>, @builtin(local_invocation_index) local_invocation_index: u32) {
+    let index = live_path(live_slot(workgroup_id, local_invocation_index));
+    if (index == NO_PATH) {
+        return;
+    }
+
+    let pixel = vec2<u32>(index % frame.tile_wh.x, index / frame.tile_wh.x);
+    let primitive = visibility[VISIBILITY_HEADER + pixel.y * visibility[0] + pixel.x];
+    let ray = paths[index].ray;
+    hidden_flags = OBJECT_HIDDEN_CAMERA;
+    var rec: HitRecord;
+    // Rays through other points of the pixel than its center can miss what was rasterized there,
+    // like those through its edges or holes cut out of it, or what clipping planes cut away
+    let range = clip_range(ray, frame.ray_epsilon, T_MAX);
+    if (primitive == NO_PRIMITIVE || !hit_primitive(primitive, ray, range.start, range.end, &rec)) {
+        trace_path(index);
+        return;
+    }
+    // Other primitives and the caps of clipping planes can be in front
+    if (visibility[1] != 0u || scene.clip_plane_count > 0u) {
+        var closer: HitRecord;
+        if (hit_world(ray, frame.ray_epsilon, rec.distance, &closer)) {
+            rec = closer;
+        }
+    }
+    store_hit(index, rec);
+}
@@ -0,0 +1,23 @@
+// Per-invocation random numbers
+
+var<private> rng_state: u32;
+
+// PCG hash, see "Hash Functions for GPU Rendering" (Jarzynski, Olano)
+fn pcg_hash(input: u32) -> u32 {
+    let state = input * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Uniform float in [0, 1)
+fn rand_f32() -> f32 {
+    rng_state = pcg_hash(rng_state);
+    return f32(rng_state >> 8u) / 16777216.0;
+}
+
+fn random_unit_vector() -> vec3<f32> {
+    let z = rand_f32() * 2.0 - 1.0;
+    let a = rand_f32() * 6.2831853;
+    let r = sqrt(1.0 - z * z);
+    return vec3<f32>(r * cos(a), r * sin(a), z);
+}
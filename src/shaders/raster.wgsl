@@ -0,0 +1,96 @@
+// Primary visibility of hybrid rendering: rasterizes the triangles of the scene into a tile,
+// keeping the index of the one closest to the camera at the center of every pixel
+
+// Mirrors the start of `SceneUniforms` in scene.wgsl
+struct Camera {
+    position: vec3<f32>,
+    tan_half_fov: f32,
+    forward: vec3<f32>,
+    light_count: u32,
+    right: vec3<f32>,
+    projection: u32,
+    up: vec3<f32>,
+}
+
+// Mirrors `Primitive` in scene.wgsl
+struct Primitive {
+    v0: vec4<f32>,
+    v1: vec4<f32>,
+    v2: vec4<f32>,
+    kind: u32,
+    material: u32,
+    object: u32,
+    flags: u32,
+    texcoords: array<vec2<f32>, 3>,
+}
+
+struct RasterUniforms {
+    image_wh: vec2<f32>,
+    tile_origin: vec2<f32>,
+    // Size of the target, which the tile starts at the top left of
+    target_wh: vec2<f32>,
+    // Shift of the lens in heights of the image, like in `SceneUniforms`
+    camera_shift: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+@group(0) @binding(1)
+var<storage, read> primitives: array<Primitive>;
+
+@group(0) @binding(2)
+var<uniform> raster: RasterUniforms;
+
+let PRIMITIVE_TRIANGLE: u32 = 0u;
+let PRIMITIVE_SMOOTH_TRIANGLE: u32 = 3u;
+let PROJECTION_PERSPECTIVE: u32 = 0u;
+// Distance of the near plane, surfaces closer than that are left to the compute pipeline
+let NEAR: f32 = 0.001;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) @interpolate(flat) primitive: u32,
+}
+
+// Three vertices for every primitive, with reverse Z depths to an infinite far plane
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let index = vertex_index / 3u;
+    let primitive = primitives[index];
+
+    var out: VertexOutput;
+    out.primitive = index;
+    // Everything that isn't a triangle collapses to a point, which covers no pixels
+    out.position = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    if ((primitive.kind != PRIMITIVE_TRIANGLE && primitive.kind != PRIMITIVE_SMOOTH_TRIANGLE) || camera.projection != PROJECTION_PERSPECTIVE) {
+        return out;
+    }
+
+    var vertex = primitive.v0.xyz;
+    if (vertex_index % 3u == 1u) {
+        vertex = primitive.v1.xyz;
+    } else if (vertex_index % 3u == 2u) {
+        vertex = primitive.v2.xyz;
+    }
+
+    // Through the image like the camera rays of ray_gen, whose forward component is a unit
+    let relative = vertex - camera.position;
+    let depth = dot(relative, camera.forward);
+    let half_width = camera.tan_half_fov * raster.image_wh.x / raster.image_wh.y;
+    let shift = 2.0 * raster.camera_shift * vec2<f32>(raster.image_wh.y / raster.image_wh.x, 1.0);
+    let image = vec2<f32>(dot(relative, camera.right) / half_width, dot(relative, camera.up) / camera.tan_half_fov) - shift * depth;
+    // From the image onto the tile, rows going down both
+    let scale = raster.image_wh / raster.target_wh;
+    let offset = vec2<f32>(
+        (raster.image_wh.x - 2.0 * raster.tile_origin.x) / raster.target_wh.x - 1.0,
+        1.0 - (raster.image_wh.y - 2.0 * raster.tile_origin.y) / raster.target_wh.y
+    );
+    out.position = vec4<f32>(image * scale + offset * depth, NEAR, depth);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) u32 {
+    return in.primitive;
+}
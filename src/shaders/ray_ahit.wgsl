@@ -1,5 +1,11 @@
-@compute
-@workgroup_size(1)
-fn main() {
-    
+// Shadow rays, which only need to know whether anything is hit at all
+#include "ray_intersect.wgsl"
+
+fn occluded(origin: vec3<f32>, direction: vec3<f32>, dist_max: f32) -> bool {
+    let flags = hidden_flags;
+    hidden_flags = OBJECT_HIDDEN_SHADOW;
+    var rec: HitRecord;
+    let hit = hit_world(Ray(origin, direction), frame.ray_epsilon, dist_max, &rec);
+    hidden_flags = flags;
+    return hit;
 }
@@ -1,5 +1,219 @@
-@compute
-@workgroup_size(1)
-fn main() {
-    
+// Shading of the closest surface a ray hits
+#include "scene.wgsl"
+#include "random.wgsl"
+#include "ray_miss.wgsl"
+#include "ray_ahit.wgsl"
+#include "fog.wgsl"
+
+// Lights up to which shadow rays sample every one of them rather than picking one, beyond it the
+// cost of a shadow ray stays the same however many there are
+let SUMMED_LIGHTS: u32 = 8u;
+
+// Light arriving at a point from one of the lights, ignoring occluders
+struct LightSample {
+    // Direction towards the light
+    direction: vec3<f32>,
+    distance: f32,
+    intensity: vec3<f32>,
+}
+
+// Direction light from a directional light travels in, uniformly over its cone
+fn directional_light_direction(light: Light) -> vec3<f32> {
+    let axis = light.position;
+    if (light.cos_half_angle >= 1.0) {
+        return axis;
+    }
+    let cos_theta = 1.0 - rand_f32() * (1.0 - light.cos_half_angle);
+    let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+    let phi = 2.0 * PI * rand_f32();
+    let helper = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 1.0, 0.0), abs(axis.x) > 0.9);
+    let tangent = normalize(cross(helper, axis));
+    let bitangent = cross(axis, tangent);
+    return normalize(axis * cos_theta + (tangent * cos(phi) + bitangent * sin(phi)) * sin_theta);
+}
+
+// Fraction of the intensity of a point light its profile emits along unit `direction`
+fn light_profile(light: Light, direction: vec3<f32>) -> f32 {
+    if (light.profile == NO_TEXTURE) {
+        return 1.0;
+    }
+    let bitangent = cross(light.profile_axis, light.profile_reference);
+    let vertical = acos(clamp(dot(direction, light.profile_axis), -1.0, 1.0)) / PI;
+    let horizontal = atan2(dot(direction, bitangent), dot(direction, light.profile_reference)) / (2.0 * PI);
+
+    // Bilinear between the angles, wrapping around horizontally
+    let size = textureDimensions(light_profiles);
+    let x = fract(horizontal) * f32(size.x);
+    let y = vertical * f32(size.y - 1);
+    let x0 = min(i32(x), size.x - 1);
+    let y0 = min(i32(y), size.y - 1);
+    let x1 = (x0 + 1) % size.x;
+    let y1 = min(y0 + 1, size.y - 1);
+    let layer = i32(light.profile);
+    let top = mix(textureLoad(light_profiles, vec2<i32>(x0, y0), layer, 0).r, textureLoad(light_profiles, vec2<i32>(x1, y0), layer, 0).r, fract(x));
+    let bottom = mix(textureLoad(light_profiles, vec2<i32>(x0, y1), layer, 0).r, textureLoad(light_profiles, vec2<i32>(x1, y1), layer, 0).r, fract(x));
+    return mix(top, bottom, fract(y));
+}
+
+fn sample_light(light: Light, position: vec3<f32>) -> LightSample {
+    if (light.kind == LIGHT_DIRECTIONAL) {
+        return LightSample(-directional_light_direction(light), T_MAX, light.intensity);
+    }
+    let to_light = light.position - position;
+    let distance = length(to_light);
+    let direction = to_light / distance;
+    let intensity = light.intensity * light_profile(light, -direction);
+    return LightSample(direction, distance, intensity / (distance * distance));
+}
+
+// Lets all the light of the samples through, for the light shadow catchers would receive without
+// the shadows on them
+var<private> ignore_occluders: bool;
+
+// Bits of the links of the lights that don't illuminate the surface being lit, light_to_surface
+// leaves those out
+var<private> unlinked_lights: u32;
+
+// Light from a sample that gets to its point, through any fog on the way
+fn unoccluded_light(position: vec3<f32>, sample: LightSample) -> vec3<f32> {
+    if (!ignore_occluders && occluded(position, sample.direction, sample.distance)) {
+        return vec3<f32>(0.0);
+    }
+    return sample.intensity * fog_transmittance(Ray(position, sample.direction), sample.distance);
+}
+
+// Light `u` from 0 to 1 picks in proportion to the power of the lights, by a binary search of the
+// running fractions of the total in their `power_cdf`
+fn pick_light(u: f32) -> u32 {
+    var low = 0u;
+    var high = scene.light_count - 1u;
+    while (low < high) {
+        let middle = (low + high) / 2u;
+        if (lights[middle].power_cdf <= u) {
+            low = middle + 1u;
+        } else {
+            high = middle;
+        }
+    }
+    return low;
+}
+
+// Probability of `pick_light` picking `light`
+fn light_probability(light: u32) -> f32 {
+    var below = 0.0;
+    if (light > 0u) {
+        below = lights[light - 1u].power_cdf;
+    }
+    return lights[light].power_cdf - below;
+}
+
+// Radiance reaching a diffuse surface straight from `light`, times the 1/pi of the BRDF
+fn light_to_surface(light: u32, position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    if ((lights[light].link & unlinked_lights) != 0u) {
+        return vec3<f32>(0.0, 0.0, 0.0);
+    }
+    let sample = sample_light(lights[light], position);
+    let cos_theta = dot(normal, sample.direction);
+    if (cos_theta <= 0.0) {
+        return vec3<f32>(0.0, 0.0, 0.0);
+    }
+    return unoccluded_light(position, sample) * cos_theta / PI;
+}
+
+// Radiance reaching a diffuse surface straight from the lights, times the 1/pi of the BRDF. Beyond
+// SUMMED_LIGHTS a single one is picked by its power
+fn direct_light(position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    if (scene.light_count > SUMMED_LIGHTS) {
+        let light = pick_light(rand_f32());
+        return light_to_surface(light, position, normal) / light_probability(light);
+    }
+
+    var radiance = vec3<f32>(0.0, 0.0, 0.0);
+    for (var i = 0u; i < scene.light_count; i += 1u) {
+        radiance += light_to_surface(i, position, normal);
+    }
+    return radiance;
+}
+
+// Radiance scattered by the fog at `position` straight from `light` into the opposite of
+// `direction`, the one light travelled in to get there
+fn light_to_volume(light: u32, position: vec3<f32>, direction: vec3<f32>) -> vec3<f32> {
+    let sample = sample_light(lights[light], position);
+    let phase = henyey_greenstein(dot(-sample.direction, -direction), scene.fog_anisotropy);
+    return unoccluded_light(position, sample) * phase;
+}
+
+// Radiance scattered by the fog straight from the lights, see `light_to_volume`, picking one by
+// its power beyond SUMMED_LIGHTS like `direct_light`
+fn direct_light_volume(position: vec3<f32>, direction: vec3<f32>) -> vec3<f32> {
+    if (scene.light_count > SUMMED_LIGHTS) {
+        let light = pick_light(rand_f32());
+        return light_to_volume(light, position, direction) / light_probability(light);
+    }
+
+    var radiance = vec3<f32>(0.0, 0.0, 0.0);
+    for (var i = 0u; i < scene.light_count; i += 1u) {
+        radiance += light_to_volume(i, position, direction);
+    }
+    return radiance;
+}
+
+// Light from the sky reaching a diffuse surface through the portals, times the 1/pi of the BRDF,
+// from a point picked uniformly on each of them
+fn portal_light(position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    var radiance = vec3<f32>(0.0, 0.0, 0.0);
+
+    for (var i = 0u; i < scene.portal_count; i += 1u) {
+        let portal = lights[scene.light_count + i];
+        let sampled = portal.position + rand_f32() * portal.profile_axis + rand_f32() * portal.profile_reference;
+        let to_portal = sampled - position;
+        let distance_squared = dot(to_portal, to_portal);
+        let direction = to_portal * inverseSqrt(distance_squared);
+        let cos_theta = dot(normal, direction);
+        // The cross product is as long as the portal is large
+        let projected_area = abs(dot(cross(portal.profile_axis, portal.profile_reference), direction));
+        if (cos_theta > 0.0 && projected_area > 0.0) {
+            let sample = LightSample(direction, T_MAX, sky_color(direction) * projected_area / distance_squared);
+            radiance += unoccluded_light(position, sample) * cos_theta / PI;
+        }
+    }
+
+    return radiance;
+}
+
+// Whether a ray escaping the scene leaves through one of the portals
+fn through_portal(ray: Ray) -> bool {
+    for (var i = 0u; i < scene.portal_count; i += 1u) {
+        let portal = lights[scene.light_count + i];
+        let u = portal.profile_axis;
+        let v = portal.profile_reference;
+        let normal = cross(u, v);
+        let denominator = dot(ray.direction, normal);
+        if (denominator == 0.0) {
+            continue;
+        }
+        let t = dot(portal.position - ray.origin, normal) / denominator;
+        let local = ray_at(ray, t) - portal.position;
+        let a = dot(local, u) / dot(u, u);
+        let b = dot(local, v) / dot(v, v);
+        if (t > 0.0 && a >= 0.0 && a <= 1.0 && b >= 0.0 && b <= 1.0) {
+            return true;
+        }
+    }
+    return false;
+}
+
+// Schlick's approximation of the Fresnel reflectance
+fn reflectance(cosine: f32, ior_ratio: f32) -> f32 {
+    var r0 = (1.0 - ior_ratio) / (1.0 + ior_ratio);
+    r0 = r0 * r0;
+    return r0 + (1.0 - r0) * pow(1.0 - cosine, 5.0);
+}
+
+// Refraction of a unit direction through a surface with the given ratio of indices of refraction
+fn refract_direction(direction: vec3<f32>, normal: vec3<f32>, ior_ratio: f32) -> vec3<f32> {
+    let cos_theta = min(dot(-direction, normal), 1.0);
+    let perpendicular = ior_ratio * (direction + cos_theta * normal);
+    let parallel = -sqrt(abs(1.0 - dot(perpendicular, perpendicular))) * normal;
+    return perpendicular + parallel;
 }
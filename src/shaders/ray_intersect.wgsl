@@ -1,5 +1,919 @@
-@compute
-@workgroup_size(1)
-fn main() {
-    
+#include "scene.wgsl"
+#include "random.wgsl"
+
+fn set_face_normal(rec: ptr<function, HitRecord>, ray: Ray, outward_normal: vec3<f32>) {
+    (*rec).front_face = dot(ray.direction, outward_normal) < 0.0;
+    if ((*rec).front_face) {
+        (*rec).normal = outward_normal
+    } else {
+        (*rec).normal = -outward_normal
+    };
+}
+
+fn hit_sphere(sphere: Sphere, ray: Ray, dist_min: f32, dist_max: f32, rec:  ptr<function, HitRecord>) -> bool {
+    let oc = ray.origin - sphere.center;
+    let a = pow(length(ray.direction), 2.0);
+    let half_b = dot(oc, ray.direction);
+    let c = pow(length(oc), 2.0) - pow(sphere.radius, 2.0);
+    let discriminant = half_b*half_b - a*c;
+    if (discriminant < 0.0) {
+        return false;
+    }
+    let sqrtd = sqrt(discriminant);
+    var root = (-half_b - sqrtd) / a;
+    if (root < dist_min || dist_max < root) {
+        root = (-half_b + sqrtd) / a;
+        if (root < dist_min || dist_max < root) {
+            return false;
+        }
+    }
+
+    (*rec).distance = root;
+    (*rec).hit_point = ray_at(ray, (*rec).distance);
+    let outward_normal = ((*rec).hit_point - sphere.center) / sphere.radius;
+    set_face_normal(rec, ray, outward_normal);
+    // Longitude and latitude, with the poles on the y axis
+    (*rec).uv = vec2<f32>(atan2(-outward_normal.z, outward_normal.x) / (2.0 * PI) + 0.5, acos(-outward_normal.y) / PI);
+
+    return true;
+}
+
+// Möller-Trumbore ray/triangle intersection, of the parallelogram v1 and v2 span from v0 with a
+// `uv_sum_max` of 2 rather than 1
+fn hit_parallelogram(v0: vec3<f32>, v1: vec3<f32>, v2: vec3<f32>, ray: Ray, dist_min: f32, dist_max: f32, uv_sum_max: f32, rec: ptr<function, HitRecord>) -> bool {
+    let edge1 = v1 - v0;
+    let edge2 = v2 - v0;
+    let p = cross(ray.direction, edge2);
+    let det = dot(edge1, p);
+    if (abs(det) < 1.0e-12) {
+        return false;
+    }
+
+    let inv_det = 1.0 / det;
+    let s = ray.origin - v0;
+    let u = dot(s, p) * inv_det;
+    if (u < 0.0 || u > 1.0) {
+        return false;
+    }
+    let q = cross(s, edge1);
+    let v = dot(ray.direction, q) * inv_det;
+    if (v < 0.0 || v > 1.0 || u + v > uv_sum_max) {
+        return false;
+    }
+
+    let dist = dot(edge2, q) * inv_det;
+    if (dist < dist_min || dist_max < dist) {
+        return false;
+    }
+
+    (*rec).distance = dist;
+    (*rec).hit_point = ray_at(ray, dist);
+    set_face_normal(rec, ray, normalize(cross(edge1, edge2)));
+    (*rec).uv = vec2<f32>(u, v);
+
+    return true;
+}
+
+fn hit_triangle(v0: vec3<f32>, v1: vec3<f32>, v2: vec3<f32>, ray: Ray, dist_min: f32, dist_max: f32, rec: ptr<function, HitRecord>) -> bool {
+    return hit_parallelogram(v0, v1, v2, ray, dist_min, dist_max, 1.0, rec);
+}
+
+// Cells along either parametric axis of a primitive that are cut out as a whole
+let CUTOUT_CELLS: f32 = 4096.0;
+
+// Unit vector from the octahedral encoding in the bits of `encoded`
+fn decode_normal(encoded: f32) -> vec3<f32> {
+    return octahedral_direction(unpack2x16snorm(bitcast<u32>(encoded)));
+}
+
+// Octahedral encoding of a direction, the inverse of decode_normal with the bits as a u32
+fn encode_direction(direction: vec3<f32>) -> u32 {
+    let n = direction / max(abs(direction.x) + abs(direction.y) + abs(direction.z), 1.0e-30);
+    var e = n.xy;
+    if (n.z < 0.0) {
+        e = (1.0 - abs(n.yx)) * select(vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), n.xy >= vec2<f32>(0.0, 0.0));
+    }
+    return pack2x16snorm(e);
+}
+
+// Replaces the face normal of a hit on a smooth shaded triangle by the interpolated normals of its
+// vertices, on the same side of the surface
+fn smooth_normal(primitive: Primitive, ray: Ray, rec: ptr<function, HitRecord>) {
+    let uv = (*rec).uv;
+    var normal = normalize(
+        (1.0 - uv.x - uv.y) * decode_normal(primitive.v0.w)
+        + uv.x * decode_normal(primitive.v1.w)
+        + uv.y * decode_normal(primitive.v2.w)
+    );
+    if (dot(normal, (*rec).normal) < 0.0) {
+        normal = -normal;
+    }
+
+    // Interpolated normals can face away from rays grazing the surface, bend them back towards
+    // the ray so that reflections stay above it
+    let to_origin = -normalize(ray.direction);
+    let cos_theta = dot(normal, to_origin);
+    if (cos_theta < 0.01) {
+        normal = normalize(normal + (0.01 - cos_theta) * to_origin);
+    }
+    (*rec).normal = normal;
+}
+
+// Distances kept at once while evaluating a signed distance field, SDF_STACK on the host
+let SDF_STACK: u32 = 8u;
+let SDF_MAX_STEPS: u32 = 256u;
+// Distance to the surface counting as a hit, relative to the one travelled along the ray
+let SDF_EPSILON: f32 = 1.0e-4;
+
+// Minimum blending `a` and `b` over a `k` wide seam, with a quadratic polynomial
+fn smooth_min(a: f32, b: f32, k: f32) -> f32 {
+    if (k <= 0.0) {
+        return min(a, b);
+    }
+    let h = max(k - abs(a - b), 0.0) / k;
+    return min(a, b) - h * h * k * 0.25;
+}
+
+// Signed distance in object space from `p` to the field with the program at `first`, `count`
+// nodes long including its header
+fn sdf_distance(first: u32, count: u32, p: vec3<f32>) -> f32 {
+    var stack: array<f32, SDF_STACK>;
+    var size = 0u;
+    for (var i = first + 1u; i < first + count; i += 1u) {
+        let node = primitives[i];
+        let q = p - node.v0.xyz;
+        let extents = node.v1;
+        if (node.kind == SDF_SPHERE) {
+            stack[size] = length(q) - extents.x;
+            size += 1u;
+        } else if (node.kind == SDF_CUBOID) {
+            let d = abs(q) - extents.xyz;
+            stack[size] = length(max(d, vec3<f32>(0.0))) + min(max(d.x, max(d.y, d.z)), 0.0) - extents.w;
+            size += 1u;
+        } else if (node.kind == SDF_TORUS) {
+            stack[size] = length(vec2<f32>(length(q.xz) - extents.x, q.y)) - extents.y;
+            size += 1u;
+        } else if (node.kind == SDF_CYLINDER) {
+            let d = abs(vec2<f32>(length(q.xz), q.y)) - extents.xy;
+            stack[size] = min(max(d.x, d.y), 0.0) + length(max(d, vec2<f32>(0.0)));
+            size += 1u;
+        } else {
+            let a = stack[size - 2u];
+            let b = stack[size - 1u];
+            let k = node.v0.x;
+            size -= 1u;
+            if (node.kind == SDF_UNION) {
+                stack[size - 1u] = smooth_min(a, b, k);
+            } else if (node.kind == SDF_INTERSECTION) {
+                stack[size - 1u] = -smooth_min(-a, -b, k);
+            } else {
+                stack[size - 1u] = -smooth_min(-a, b, k);
+            }
+        }
+    }
+    return stack[0];
+}
+
+// The ray in the space whose transform from world space is in the first three rows of `header`
+fn object_ray(header: Primitive, ray: Ray) -> Ray {
+    var local = ray;
+    local.origin = vec3<f32>(
+        dot(header.v0, vec4<f32>(ray.origin, 1.0)),
+        dot(header.v1, vec4<f32>(ray.origin, 1.0)),
+        dot(header.v2, vec4<f32>(ray.origin, 1.0)),
+    );
+    local.direction = vec3<f32>(
+        dot(header.v0.xyz, ray.direction),
+        dot(header.v1.xyz, ray.direction),
+        dot(header.v2.xyz, ray.direction),
+    );
+    return local;
+}
+
+// Normal in world space of one in the space of `object_ray`, by the transpose of the transform
+fn world_normal(header: Primitive, normal: vec3<f32>) -> vec3<f32> {
+    return normalize(normal.x * header.v0.xyz + normal.y * header.v1.xyz + normal.z * header.v2.xyz);
+}
+
+// Sphere tracing of a signed distance field inside its bounds, from the side of the surface the
+// ray starts on
+fn hit_sdf(primitive: Primitive, ray: Ray, dist_min: f32, dist_max: f32, rec: ptr<function, HitRecord>) -> bool {
+    let inv_direction = 1.0 / ray.direction;
+    let t0 = (primitive.v0.xyz - ray.origin) * inv_direction;
+    let t1 = (primitive.v1.xyz - ray.origin) * inv_direction;
+    let t_near = min(t0, t1);
+    let t_far = max(t0, t1);
+    let exit = min(min(t_far.x, t_far.y), min(t_far.z, dist_max));
+    var t = max(max(t_near.x, t_near.y), max(t_near.z, dist_min));
+    if (t > exit) {
+        return false;
+    }
+
+    let first = bitcast<u32>(primitive.v2.x);
+    let count = bitcast<u32>(primitive.v2.y);
+    let header = primitives[first];
+    let local = object_ray(header, ray);
+    let origin = local.origin;
+    let direction = local.direction;
+    // World space distance of a step in object space, and length of the ray per unit of t
+    let scale = primitive.v2.z;
+    let speed = length(ray.direction);
+
+    var side = 1.0;
+    var found = false;
+    for (var step = 0u; step < SDF_MAX_STEPS; step += 1u) {
+        let distance = sdf_distance(first, count, origin + t * direction) * scale;
+        if (step == 0u && distance < 0.0) {
+            side = -1.0;
+        }
+        if (abs(distance) < SDF_EPSILON * max(t * speed, 1.0)) {
+            found = true;
+            break;
+        }
+        t += side * distance / speed;
+        if (t > exit) {
+            break;
+        }
+    }
+    if (!found) {
+        return false;
+    }
+
+    // Gradient from the tetrahedral differences, to world space by the transpose of the inverse
+    let p = origin + t * direction;
+    let h = SDF_EPSILON * max(t * speed, 1.0) / scale;
+    let k = vec2<f32>(1.0, -1.0);
+    let gradient = k.xyy * sdf_distance(first, count, p + k.xyy * h)
+        + k.yyx * sdf_distance(first, count, p + k.yyx * h)
+        + k.yxy * sdf_distance(first, count, p + k.yxy * h)
+        + k.xxx * sdf_distance(first, count, p + k.xxx * h);
+
+    (*rec).distance = t;
+    (*rec).hit_point = ray_at(ray, t);
+    set_face_normal(rec, ray, world_normal(header, gradient));
+    (*rec).uv = vec2<f32>(0.0);
+    return true;
+}
+
+// Solids of a CSG expression at most, Csg::MAX_SOLIDS on the host
+let CSG_SOLIDS: u32 = 16u;
+
+// Span of the ray in object space inside the convex solid of `node`, empty if the start is past
+// the end
+fn csg_interval(node: Primitive, ray: Ray) -> vec2<f32> {
+    let oc = ray.origin - node.v0.xyz;
+    let d = ray.direction;
+    let size = node.v1;
+    let empty = vec2<f32>(T_MAX, -T_MAX);
+    if (node.kind == CSG_SPHERE) {
+        let a = dot(d, d);
+        let b = dot(oc, d);
+        let h = b * b - a * (dot(oc, oc) - size.x * size.x);
+        if (h < 0.0) {
+            return empty;
+        }
+        return (-b + vec2<f32>(-sqrt(h), sqrt(h))) / a;
+    }
+    if (node.kind == CSG_CUBOID) {
+        let t0 = (-size.xyz - oc) / d;
+        let t1 = (size.xyz - oc) / d;
+        let t_near = min(t0, t1);
+        let t_far = max(t0, t1);
+        return vec2<f32>(max(max(t_near.x, t_near.y), t_near.z), min(min(t_far.x, t_far.y), t_far.z));
+    }
+
+    // Cylinders between their caps, inside the infinite one around their axis
+    let ty0 = (-size.y - oc.y) / d.y;
+    let ty1 = (size.y - oc.y) / d.y;
+    let span = vec2<f32>(min(ty0, ty1), max(ty0, ty1));
+    let a = dot(d.xz, d.xz);
+    let b = dot(oc.xz, d.xz);
+    let c = dot(oc.xz, oc.xz) - size.x * size.x;
+    if (a < 1.0e-12) {
+        if (c > 0.0) {
+            return empty;
+        }
+        return span;
+    }
+    let h = b * b - a * c;
+    if (h < 0.0) {
+        return empty;
+    }
+    let radial = (-b + vec2<f32>(-sqrt(h), sqrt(h))) / a;
+    return vec2<f32>(max(span.x, radial.x), min(span.y, radial.y));
+}
+
+// Outward normal in object space of the solid of `node` at `p` on its surface
+fn csg_normal(node: Primitive, p: vec3<f32>) -> vec3<f32> {
+    let q = p - node.v0.xyz;
+    let size = node.v1;
+    if (node.kind == CSG_SPHERE) {
+        return q;
+    }
+    if (node.kind == CSG_CUBOID) {
+        let r = abs(q) / size.xyz;
+        if (r.x >= r.y && r.x >= r.z) {
+            return vec3<f32>(sign(q.x), 0.0, 0.0);
+        } else if (r.y >= r.z) {
+            return vec3<f32>(0.0, sign(q.y), 0.0);
+        }
+        return vec3<f32>(0.0, 0.0, sign(q.z));
+    }
+    if (abs(q.y) / size.y >= length(q.xz) / size.x) {
+        return vec3<f32>(0.0, sign(q.y), 0.0);
+    }
+    return vec3<f32>(q.x, 0.0, q.z);
+}
+
+// Whether a point inside the solids with the bits of `inside` is inside the expression with the
+// program at `first`, `count` nodes long including its header, evaluated on a stack of bits
+fn csg_inside(first: u32, count: u32, inside: u32) -> bool {
+    var stack = 0u;
+    var solid = 0u;
+    for (var i = first + 1u; i < first + count; i += 1u) {
+        let kind = primitives[i].kind;
+        if (kind < CSG_UNION) {
+            stack = (stack << 1u) | ((inside >> solid) & 1u);
+            solid += 1u;
+        } else {
+            let b = stack & 1u;
+            let a = (stack >> 1u) & 1u;
+            var result = a | b;
+            if (kind == CSG_INTERSECTION) {
+                result = a & b;
+            } else if (kind == CSG_DIFFERENCE) {
+                result = a & (b ^ 1u);
+            }
+            stack = ((stack >> 2u) << 1u) | result;
+        }
+    }
+    return (stack & 1u) != 0u;
+}
+
+// Constructive solid geometry by the spans of the ray inside every solid: the surface is the first
+// of their ends past the start of the ray going into or out of the expression
+fn hit_csg(primitive: Primitive, ray: Ray, dist_min: f32, dist_max: f32, rec: ptr<function, HitRecord>) -> bool {
+    let first = bitcast<u32>(primitive.v2.x);
+    let count = bitcast<u32>(primitive.v2.y);
+    let header = primitives[first];
+    let local = object_ray(header, ray);
+
+    var starts: array<f32, CSG_SOLIDS>;
+    var ends: array<f32, CSG_SOLIDS>;
+    var nodes: array<u32, CSG_SOLIDS>;
+    var solids = 0u;
+    for (var i = first + 1u; i < first + count; i += 1u) {
+        let node = primitives[i];
+        if (node.kind < CSG_UNION) {
+            let span = csg_interval(node, local);
+            nodes[solids] = i;
+            starts[solids] = span.x;
+            ends[solids] = span.y;
+            solids += 1u;
+        }
+    }
+
+    var t = dist_min;
+    for (var crossing = 0u; crossing < 2u * solids; crossing += 1u) {
+        // The next end of a span
+        var next = T_MAX;
+        for (var i = 0u; i < solids; i += 1u) {
+            if (starts[i] > ends[i]) {
+                continue;
+            }
+            if (starts[i] > t) {
+                next = min(next, starts[i]);
+            }
+            if (ends[i] > t) {
+                next = min(next, ends[i]);
+            }
+        }
+        if (next > dist_max) {
+            return false;
+        }
+        t = next;
+
+        // The solids just before and after it, and the one it's an end of
+        var before = 0u;
+        var after = 0u;
+        var solid = 0u;
+        for (var i = 0u; i < solids; i += 1u) {
+            before |= u32(starts[i] < t && t <= ends[i]) << i;
+            after |= u32(starts[i] <= t && t < ends[i]) << i;
+            if (starts[i] <= ends[i] && (starts[i] == t || ends[i] == t)) {
+                solid = i;
+            }
+        }
+        let entering = csg_inside(first, count, after);
+        if (entering == csg_inside(first, count, before)) {
+            continue;
+        }
+
+        // The normal of the solid, facing out of the expression
+        var normal = csg_normal(primitives[nodes[solid]], local.origin + t * local.direction);
+        if ((dot(local.direction, normal) < 0.0) != entering) {
+            normal = -normal;
+        }
+        (*rec).distance = t;
+        (*rec).hit_point = ray_at(ray, t);
+        set_face_normal(rec, ray, world_normal(header, normal));
+        (*rec).uv = vec2<f32>(0.0);
+        return true;
+    }
+    return false;
+}
+
+// Cells along either side of the tiles of heightfields, HEIGHTFIELD_TILE on the host
+let HEIGHTFIELD_TILE: u32 = 32u;
+
+// Point of the grid of a heightfield in `layer` at `texel`, in the space of the grid
+fn heightfield_vertex(layer: i32, cells: vec2<f32>, texel: vec2<i32>) -> vec3<f32> {
+    let height = textureLoad(heightfields, texel, layer, 0).r;
+    let position = vec2<f32>(texel) / cells;
+    return vec3<f32>(position.x, height, position.y);
+}
+
+// Traversal of the cells of a tile of a heightfield along the ray with a 2D DDA, intersecting the
+// two triangles either one is split into along the diagonal from its first corner
+fn hit_heightfield(primitive: Primitive, ray: Ray, dist_min: f32, dist_max: f32, rec: ptr<function, HitRecord>) -> bool {
+    let header = primitives[bitcast<u32>(primitive.v2.x)];
+    let tile = bitcast<u32>(primitive.v2.y);
+    let layer = i32(header.kind);
+    let cells = header.texcoords[2];
+    let local = object_ray(header, ray);
+
+    // Bounds of the tile in the space of the grid
+    let first_cell = vec2<u32>(tile & 0xffffu, tile >> 16u) * HEIGHTFIELD_TILE;
+    let last_cell = min(first_cell + HEIGHTFIELD_TILE, vec2<u32>(cells)) - 1u;
+    let tile_min = vec3<f32>(f32(first_cell.x) / cells.x, primitive.v2.z, f32(first_cell.y) / cells.y);
+    let tile_max = vec3<f32>(f32(last_cell.x + 1u) / cells.x, primitive.v2.w, f32(last_cell.y + 1u) / cells.y);
+    let inv_direction = 1.0 / local.direction;
+    let t0 = (tile_min - local.origin) * inv_direction;
+    let t1 = (tile_max - local.origin) * inv_direction;
+    let t_near = min(t0, t1);
+    let t_far = max(t0, t1);
+    let entry = max(max(t_near.x, t_near.y), max(t_near.z, dist_min));
+    let exit = min(min(t_far.x, t_far.y), min(t_far.z, dist_max));
+    if (entry > exit) {
+        return false;
+    }
+
+    // Cells crossed in grid units, stepping over whichever border of the current one is closer
+    let start = (local.origin.xz + entry * local.direction.xz) * cells;
+    let direction = local.direction.xz * cells;
+    var cell = clamp(vec2<i32>(floor(start)), vec2<i32>(first_cell), vec2<i32>(last_cell));
+    let step = vec2<i32>(sign(direction));
+    let t_delta = abs(1.0 / direction);
+    let border = vec2<f32>(cell) + select(vec2<f32>(0.0), vec2<f32>(1.0), direction > vec2<f32>(0.0));
+    var t_next = select(vec2<f32>(T_MAX), entry + (border - start) / direction, direction != vec2<f32>(0.0));
+
+    var found = false;
+    for (var i = 0u; i < 2u * HEIGHTFIELD_TILE; i += 1u) {
+        let v00 = heightfield_vertex(layer, cells, cell);
+        let v10 = heightfield_vertex(layer, cells, cell + vec2<i32>(1, 0));
+        let v01 = heightfield_vertex(layer, cells, cell + vec2<i32>(0, 1));
+        let v11 = heightfield_vertex(layer, cells, cell + vec2<i32>(1, 1));
+        var closest = exit;
+        if (hit_triangle(v00, v11, v10, local, dist_min, closest, rec)) {
+            found = true;
+            closest = (*rec).distance;
+        }
+        if (hit_triangle(v00, v01, v11, local, dist_min, closest, rec)) {
+            found = true;
+        }
+        // Cells are crossed front to back, nothing behind a hit can be closer
+        if (found || min(t_next.x, t_next.y) > exit) {
+            break;
+        }
+
+        if (t_next.x < t_next.y) {
+            cell.x += step.x;
+            t_next.x += t_delta.x;
+        } else {
+            cell.y += step.y;
+            t_next.y += t_delta.y;
+        }
+        if (any(cell < vec2<i32>(first_cell)) || any(cell > vec2<i32>(last_cell))) {
+            break;
+        }
+    }
+    if (!found) {
+        return false;
+    }
+
+    // Texture coordinates span the image, with its top row at the far end of the grid
+    let position = (*rec).hit_point;
+    (*rec).hit_point = ray_at(ray, (*rec).distance);
+    (*rec).normal = world_normal(header, (*rec).normal);
+    (*rec).uv = vec2<f32>(position.x, 1.0 - position.z);
+    return true;
+}
+
+// Intersection of a piece of a curve, a capsule of the mean radius of its ends or a ribbon facing the
+// ray, only from the outside so that rays scattered through a fiber leave it. The normal is the one
+// of the capsule either way and the second texture coordinate goes across it
+fn hit_curve(primitive: Primitive, ray: Ray, dist_min: f32, dist_max: f32, rec: ptr<function, HitRecord>) -> bool {
+    let a = primitive.v0.xyz;
+    let b = primitive.v1.xyz;
+    let axis = b - a;
+    let ba_ba = dot(axis, axis);
+    let speed = length(ray.direction);
+    let direction = ray.direction / speed;
+    let oa = ray.origin - a;
+    let ba_rd = dot(axis, direction);
+    let ba_oa = dot(axis, oa);
+
+    var t: f32;
+    var along: f32;
+    if (primitive.v2.z != 0.0) {
+        // Closest approach of the ray to the axis
+        let denominator = ba_ba - ba_rd * ba_rd;
+        if (denominator <= 1.0e-12 * ba_ba) {
+            return false;
+        }
+        let t_line = (ba_rd * ba_oa - ba_ba * dot(direction, oa)) / denominator;
+        along = clamp((ba_oa + t_line * ba_rd) / ba_ba, 0.0, 1.0);
+        t = dot(a + along * axis - ray.origin, direction);
+        let offset = ray.origin + t * direction - (a + along * axis);
+        let radius = mix(primitive.v0.w, primitive.v1.w, along);
+        if (dot(offset, offset) > radius * radius) {
+            return false;
+        }
+    } else {
+        // "Intersectors" (Quilez), the side of the capsule and otherwise the sphere at either end
+        let radius = 0.5 * (primitive.v0.w + primitive.v1.w);
+        let qa = ba_ba - ba_rd * ba_rd;
+        let qb = ba_ba * dot(direction, oa) - ba_oa * ba_rd;
+        let qc = ba_ba * dot(oa, oa) - ba_oa * ba_oa - radius * radius * ba_ba;
+        let h = qb * qb - qa * qc;
+        if (h < 0.0) {
+            return false;
+        }
+        // Rays along the axis can only hit the end they move towards
+        var y = -ba_rd * ba_ba;
+        if (qa > 1.0e-12 * ba_ba) {
+            t = (-qb - sqrt(h)) / qa;
+            y = ba_oa + t * ba_rd;
+        }
+        if (qa > 1.0e-12 * ba_ba && y > 0.0 && y < ba_ba) {
+            along = y / ba_ba;
+        } else {
+            let oc = select(ray.origin - b, oa, y <= 0.0);
+            let cap_b = dot(direction, oc);
+            let cap_h = cap_b * cap_b - dot(oc, oc) + radius * radius;
+            if (cap_h < 0.0) {
+                return false;
+            }
+            t = -cap_b - sqrt(cap_h);
+            along = select(1.0, 0.0, y <= 0.0);
+        }
+    }
+    t /= speed;
+    if (t < dist_min || dist_max < t) {
+        return false;
+    }
+
+    // Offset across the fiber as seen from the ray, facing it
+    let tangent = axis / sqrt(ba_ba);
+    let facing = normalize(tangent * dot(direction, tangent) - direction);
+    let side = cross(tangent, facing);
+    let position = ray_at(ray, t);
+    let center = a + along * axis;
+    var across: f32;
+    if (primitive.v2.z != 0.0) {
+        across = clamp(dot(position - center, side) / mix(primitive.v0.w, primitive.v1.w, along), -1.0, 1.0);
+        (*rec).normal = sqrt(1.0 - across * across) * facing + across * side;
+    } else {
+        let outward = position - center;
+        (*rec).normal = normalize(outward - tangent * dot(outward, tangent));
+        across = clamp(dot((*rec).normal, side), -1.0, 1.0);
+    }
+    (*rec).distance = t;
+    (*rec).hit_point = position;
+    (*rec).front_face = true;
+    (*rec).uv = vec2<f32>(mix(primitive.v2.x, primitive.v2.y, along), 0.5 + 0.5 * across);
+    return true;
+}
+
+// Direction perpendicular to `normal`
+fn perpendicular(normal: vec3<f32>) -> vec3<f32> {
+    return select(vec3<f32>(normal.z, 0.0, -normal.x), vec3<f32>(0.0, -normal.z, normal.y), abs(normal.x) < abs(normal.y));
+}
+
+// Intersection of the disk of a point, facing its normal or otherwise the ray. Texture coordinates
+// span the square around it
+fn hit_point_disk(primitive: Primitive, ray: Ray, dist_min: f32, dist_max: f32, rec: ptr<function, HitRecord>) -> bool {
+    let center = primitive.v0.xyz;
+    let radius = primitive.v0.w;
+    var normal = primitive.v1.xyz;
+    if (dot(normal, normal) == 0.0) {
+        normal = -normalize(ray.direction);
+    }
+    let denominator = dot(ray.direction, normal);
+    if (abs(denominator) < 1.0e-12) {
+        return false;
+    }
+    let t = dot(center - ray.origin, normal) / denominator;
+    if (t < dist_min || dist_max < t) {
+        return false;
+    }
+    let position = ray_at(ray, t);
+    let offset = position - center;
+    if (dot(offset, offset) > radius * radius) {
+        return false;
+    }
+
+    (*rec).distance = t;
+    (*rec).hit_point = position;
+    set_face_normal(rec, ray, normal);
+    let tangent = normalize(perpendicular(normal));
+    let bitangent = cross(normal, tangent);
+    (*rec).uv = 0.5 + 0.5 * vec2<f32>(dot(offset, tangent), dot(offset, bitangent)) / radius;
+    return true;
+}
+
+// Slab test of a box against the ray in the space of its unit cube, hitting it where the ray leaves
+// it from inside. The texture coordinates of every face are the next two coordinates of the cube
+// after the one across it, and the tangent and texel scale follow them
+fn hit_box(primitive: Primitive, ray: Ray, dist_min: f32, dist_max: f32, rec: ptr<function, HitRecord>) -> bool {
+    let local = object_ray(primitive, ray);
+    let inv_direction = 1.0 / local.direction;
+    let t0 = -local.origin * inv_direction;
+    let t1 = (1.0 - local.origin) * inv_direction;
+    let t_near = min(t0, t1);
+    let t_far = max(t0, t1);
+    let entry = max(max(t_near.x, t_near.y), t_near.z);
+    let exit = min(min(t_far.x, t_far.y), t_far.z);
+    if (entry > exit) {
+        return false;
+    }
+    var t = entry;
+    var planes = t_near;
+    if (t < dist_min) {
+        t = exit;
+        planes = t_far;
+    }
+    if (t < dist_min || dist_max < t) {
+        return false;
+    }
+
+    let p = clamp(local.origin + t * local.direction, vec3<f32>(0.0), vec3<f32>(1.0));
+    var axis = 2u;
+    if (planes.x == t) {
+        axis = 0u;
+    } else if (planes.y == t) {
+        axis = 1u;
+    }
+    let across = vec3<f32>(f32(axis == 0u), f32(axis == 1u), f32(axis == 2u));
+    let outward = across * select(1.0, -1.0, dot(p, across) < 0.5);
+    (*rec).distance = t;
+    (*rec).hit_point = ray_at(ray, t);
+    set_face_normal(rec, ray, world_normal(primitive, outward));
+
+    // Edges of the cube in world space, the columns of the inverse of the transform to it
+    let r0 = primitive.v0.xyz;
+    let r1 = primitive.v1.xyz;
+    let r2 = primitive.v2.xyz;
+    let det = dot(r0, cross(r1, r2));
+    let e0 = cross(r1, r2) / det;
+    let e1 = cross(r2, r0) / det;
+    let e2 = cross(r0, r1) / det;
+    var u_edge = e1;
+    var v_edge = e2;
+    (*rec).uv = p.yz;
+    if (axis == 1u) {
+        u_edge = e2;
+        v_edge = e0;
+        (*rec).uv = p.zx;
+    } else if (axis == 2u) {
+        u_edge = e0;
+        v_edge = e1;
+        (*rec).uv = p.xy;
+    }
+    (*rec).tangent = u_edge;
+    (*rec).texel_scale = inverseSqrt(max(length(cross(u_edge, v_edge)), 1.0e-12));
+    return true;
+}
+
+// Intersector of custom primitives registered by the host, defining HOOK_INTERSECT
+#include "hook_intersect.wgsl"
+
+// OBJECT_HIDDEN flags of the objects the rays traced now pass through
+var<private> hidden_flags: u32;
+
+fn hit_primitive(index: u32, ray: Ray, dist_min: f32, dist_max: f32, rec: ptr<function, HitRecord>) -> bool {
+    let primitive = primitives[index];
+    if ((primitive.flags & hidden_flags) != 0u) {
+        return false;
+    }
+
+    var hit: bool;
+    if (primitive.kind == PRIMITIVE_SPHERE) {
+        hit = hit_sphere(Sphere(primitive.v0.xyz, primitive.v0.w), ray, dist_min, dist_max, rec);
+    } else if (primitive.kind == PRIMITIVE_CUSTOM) {
+#ifdef HOOK_INTERSECT
+        hit = hook_intersect(primitive, ray, dist_min, dist_max, rec);
+#else
+        hit = false;
+#endif
+    } else if (primitive.kind == PRIMITIVE_SDF) {
+        hit = hit_sdf(primitive, ray, dist_min, dist_max, rec);
+    } else if (primitive.kind == PRIMITIVE_HEIGHTFIELD) {
+        hit = hit_heightfield(primitive, ray, dist_min, dist_max, rec);
+    } else if (primitive.kind == PRIMITIVE_CURVE) {
+        hit = hit_curve(primitive, ray, dist_min, dist_max, rec);
+    } else if (primitive.kind == PRIMITIVE_POINT) {
+        hit = hit_point_disk(primitive, ray, dist_min, dist_max, rec);
+    } else if (primitive.kind == PRIMITIVE_QUAD) {
+        hit = hit_parallelogram(primitive.v0.xyz, primitive.v1.xyz, primitive.v2.xyz, ray, dist_min, dist_max, 2.0, rec);
+    } else if (primitive.kind == PRIMITIVE_BOX) {
+        hit = hit_box(primitive, ray, dist_min, dist_max, rec);
+    } else if (primitive.kind == PRIMITIVE_CSG) {
+        hit = hit_csg(primitive, ray, dist_min, dist_max, rec);
+    } else {
+        hit = hit_triangle(primitive.v0.xyz, primitive.v1.xyz, primitive.v2.xyz, ray, dist_min, dist_max, rec);
+        if (hit && primitive.kind == PRIMITIVE_SMOOTH_TRIANGLE) {
+            smooth_normal(primitive, ray, rec);
+        }
+    }
+
+    // Any-hit test of cut out materials, hashing cells of the surface rather than drawing a random
+    // number so that camera and shadow rays agree on which parts of it are there
+    var opacity = materials[primitive.material].opacity;
+    if (hit && primitive.kind == PRIMITIVE_POINT && primitive.v1.w != 0.0) {
+        // Gaussian splats fall off to 3 standard deviations at their radius
+        let offset = 2.0 * (*rec).uv - 1.0;
+        opacity *= exp(-4.5 * dot(offset, offset));
+    }
+    if (hit && opacity < 1.0) {
+        let uv = vec2<u32>(clamp((*rec).uv, vec2<f32>(0.0), vec2<f32>(1.0)) * CUTOUT_CELLS);
+        let cutout = pcg_hash(index ^ pcg_hash(uv.x ^ pcg_hash(uv.y)));
+        if (f32(cutout >> 8u) / 16777216.0 >= opacity) {
+            return false;
+        }
+    }
+
+    if (hit) {
+        (*rec).material = primitive.material;
+        (*rec).object = primitive.object;
+        (*rec).flags = primitive.flags;
+        (*rec).color = vec3<f32>(1.0);
+        if (primitive.kind == PRIMITIVE_SPHERE) {
+            // Latitude spans half the circumference, longitude runs around the y axis
+            (*rec).texel_scale = 1.0 / (PI * primitive.v0.w);
+            let outward = (*rec).hit_point - primitive.v0.xyz;
+            (*rec).tangent = vec3<f32>(outward.z, 0.0, -outward.x);
+        } else if (primitive.kind == PRIMITIVE_SDF || primitive.kind == PRIMITIVE_CSG) {
+            // Signed distance fields and CSG solids have no texture coordinates
+            (*rec).texel_scale = 0.0;
+            (*rec).tangent = perpendicular((*rec).normal);
+        } else if (primitive.kind == PRIMITIVE_POINT) {
+            // Points are too small to map textures onto
+            (*rec).texel_scale = 0.0;
+            (*rec).tangent = perpendicular((*rec).normal);
+            (*rec).color = primitive.v2.xyz;
+        } else if (primitive.kind == PRIMITIVE_CURVE) {
+            // Strands have no surface to map textures onto, only a direction for hair to follow
+            (*rec).texel_scale = 0.0;
+            (*rec).tangent = primitive.v1.xyz - primitive.v0.xyz;
+        } else if (primitive.kind == PRIMITIVE_HEIGHTFIELD) {
+            let header = primitives[bitcast<u32>(primitive.v2.x)];
+            (*rec).texel_scale = header.texcoords[1].y;
+            (*rec).tangent = vec3<f32>(header.texcoords[0], header.texcoords[1].x);
+        } else if (primitive.kind != PRIMITIVE_CUSTOM && primitive.kind != PRIMITIVE_BOX) {
+            // Quads are mapped like the triangle of their corner and the ends of their edges
+            let uv = (*rec).uv;
+            let t = primitive.texcoords;
+            (*rec).uv = (1.0 - uv.x - uv.y) * t[0] + uv.x * t[1] + uv.y * t[2];
+
+            // Ratio of the areas the triangle covers in texture and world space
+            let st1 = t[1] - t[0];
+            let st2 = t[2] - t[0];
+            let edge1 = primitive.v1.xyz - primitive.v0.xyz;
+            let edge2 = primitive.v2.xyz - primitive.v0.xyz;
+            let det = st1.x * st2.y - st1.y * st2.x;
+            let world_area = length(cross(edge1, edge2));
+            (*rec).texel_scale = sqrt(abs(det) / max(world_area, 1.0e-12));
+            // Derivative of the position along the first texture coordinate
+            (*rec).tangent = (edge1 * st2.y - edge2 * st1.y) * sign(det);
+        }
+    }
+    return hit;
+}
+
+// Distance at which the ray enters the box, or T_MAX if it misses it
+fn hit_aabb(aabb_min: vec3<f32>, aabb_max: vec3<f32>, ray: Ray, inv_direction: vec3<f32>, dist_max: f32) -> f32 {
+    let t0 = (aabb_min - ray.origin) * inv_direction;
+    let t1 = (aabb_max - ray.origin) * inv_direction;
+    let t_near = min(t0, t1);
+    let t_far = max(t0, t1);
+    let entry = max(max(t_near.x, t_near.y), max(t_near.z, 0.0));
+    let exit = min(min(t_far.x, t_far.y), min(t_far.z, dist_max));
+    if (entry <= exit) {
+        return entry;
+    }
+    return T_MAX;
+}
+
+// Part of a ray the clipping planes keep, empty when `start` is past `end`. `plane` is the one
+// the ray enters the kept part through after the start of the ray, NO_CAP if none
+struct ClipRange {
+    start: f32,
+    end: f32,
+    plane: u32,
+}
+
+fn clip_range(ray: Ray, dist_min: f32, dist_max: f32) -> ClipRange {
+    var range = ClipRange(dist_min, dist_max, NO_CAP);
+    let first = scene.light_count + scene.portal_count;
+    for (var i = first; i < first + scene.clip_plane_count; i += 1u) {
+        let plane = lights[i];
+        let height = dot(ray.origin - plane.position, plane.profile_axis);
+        let rate = dot(ray.direction, plane.profile_axis);
+        if (rate == 0.0) {
+            if (height < 0.0) {
+                range.end = -1.0;
+            }
+            continue;
+        }
+        let t = -height / rate;
+        if (rate > 0.0 && t > range.start) {
+            range.start = t;
+            range.plane = i;
+        } else if (rate < 0.0) {
+            range.end = min(range.end, t);
+        }
+    }
+    return range;
+}
+
+// Closest hit of everything in the BVH, clipping planes aside
+fn hit_bvh(ray: Ray, dist_min: f32, dist_max: f32, rec: ptr<function, HitRecord>) -> bool {
+    let inv_direction = 1.0 / ray.direction;
+
+    // Deep enough for the maximum depth of the BVH built on the host
+    var stack: array<u32, 64>;
+    var stack_size = 1u;
+    stack[0] = 0u;
+
+    var hit_anything = false;
+    var closest = dist_max;
+    while (stack_size > 0u) {
+        stack_size -= 1u;
+        let node = bvh_nodes[stack[stack_size]];
+        if (hit_aabb(node.aabb_min, node.aabb_max, ray, inv_direction, closest) == T_MAX) {
+            continue;
+        }
+
+        if (node.count > 0u) {
+            for (var i = node.left_or_first; i < node.left_or_first + node.count; i += 1u) {
+                var temp_rec: HitRecord;
+                if (hit_primitive(i, ray, dist_min, closest, &temp_rec)) {
+                    hit_anything = true;
+                    closest = temp_rec.distance;
+                    *rec = temp_rec;
+                }
+            }
+        } else {
+            stack[stack_size] = node.left_or_first;
+            stack[stack_size + 1u] = node.left_or_first + 1u;
+            stack_size += 2u;
+        }
+    }
+
+    return hit_anything;
+}
+
+// Closest hit within the part of the ray the clipping planes keep. Rays that enter the part
+// through a plane and hit the back of a surface first were inside of it at the plane, and hit
+// its cap there
+fn hit_clipped_world(ray: Ray, dist_min: f32, dist_max: f32, rec: ptr<function, HitRecord>) -> bool {
+    let range = clip_range(ray, dist_min, dist_max);
+    if (range.start > range.end) {
+        return false;
+    }
+    if (!hit_bvh(ray, range.start, range.end, rec)) {
+        return false;
+    }
+    if (range.plane == NO_CAP || (*rec).front_face || lights[range.plane].profile == NO_CAP) {
+        return true;
+    }
+
+    let plane = lights[range.plane];
+    (*rec).distance = range.start;
+    (*rec).hit_point = ray_at(ray, range.start);
+    set_face_normal(rec, ray, -plane.profile_axis);
+    (*rec).material = plane.profile;
+    (*rec).uv = vec2<f32>(0.0, 0.0);
+    (*rec).texel_scale = 0.0;
+    (*rec).tangent = perpendicular((*rec).normal);
+    (*rec).color = vec3<f32>(1.0);
+    return true;
+}
+
+fn hit_world(ray: Ray, dist_min: f32, dist_max: f32, rec: ptr<function, HitRecord>) -> bool {
+    if (scene.clip_plane_count > 0u) {
+        return hit_clipped_world(ray, dist_min, dist_max, rec);
+    }
+    return hit_bvh(ray, dist_min, dist_max, rec);
 }
@@ -1,5 +1,58 @@
-@compute
-@workgroup_size(1)
-fn main() {
+// Light reaching rays escaping the scene
+#include "scene.wgsl"
 
+// Luminance and chromaticity of the physical sky in `direction`, by the Perez formula
+fn physical_sky(direction: vec3<f32>) -> vec3<f32> {
+    let cos_theta = max(direction.y, 1.0e-3);
+    let cos_gamma = clamp(dot(direction, scene.sun_direction), -1.0, 1.0);
+    let gamma = acos(cos_gamma);
+    let perez = (1.0 + scene.sky_a * exp(scene.sky_b / cos_theta))
+        * (1.0 + scene.sky_c * exp(scene.sky_d * gamma) + scene.sky_e * cos_gamma * cos_gamma);
+    let xy_luminance = scene.sky_zenith * perez;
+
+    // xyY to linear sRGB through XYZ
+    let luminance = xy_luminance.x;
+    let x = xy_luminance.y;
+    let y = max(xy_luminance.z, 1.0e-6);
+    let xyz = vec3<f32>(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+    let rgb = mat3x3<f32>(
+        vec3<f32>(3.2406, -0.9689, 0.0557),
+        vec3<f32>(-1.5372, 1.8758, -0.2040),
+        vec3<f32>(-0.4986, 0.0415, 1.0570)
+    ) * xyz;
+    return max(rgb, vec3<f32>(0.0, 0.0, 0.0));
+}
+
+// Light from `direction` of a sky of `kind`, one of the SKY constants, with the gradient from
+// `bottom` to `top` or the scaled and turned texture of the uniforms
+fn environment_color(kind: u32, bottom: vec3<f32>, top: vec3<f32>, texture: u32, intensity: f32, rotation: f32, direction: vec3<f32>) -> vec3<f32> {
+    let unit_direction = normalize(direction);
+    if (kind == SKY_PHYSICAL) {
+        // The ground reflects the sky above it
+        if (unit_direction.y < 0.0) {
+            let mirrored = vec3<f32>(unit_direction.x, -unit_direction.y, unit_direction.z);
+            return scene.sky_ground_albedo * physical_sky(mirrored);
+        }
+        return physical_sky(unit_direction);
+    }
+    if (kind == SKY_TEXTURE) {
+        // Equirectangular, -Z at the center
+        let u = atan2(unit_direction.x, -unit_direction.z) / (2.0 * PI) + 0.5 + rotation;
+        let v = asin(clamp(unit_direction.y, -1.0, 1.0)) / PI + 0.5;
+        return intensity * texture_color(texture, vec2<f32>(u, v), 0.0);
+    }
+    let t = 0.5 * (unit_direction.y + 1.0); // 0.0 to 1.0 to -1.0 to 1.0
+    return mix(bottom, top, t);
+}
+
+fn sky_color(direction: vec3<f32>) -> vec3<f32> {
+    return environment_color(scene.sky_kind, scene.sky_bottom, scene.sky_top, scene.sky_texture, scene.sky_intensity, scene.sky_rotation, direction);
+}
+
+// What camera rays escaping the scene in `direction` see
+fn background_color(direction: vec3<f32>) -> vec3<f32> {
+    if (scene.background_kind == BACKGROUND_SKY) {
+        return sky_color(direction);
+    }
+    return environment_color(scene.background_kind, scene.background_bottom, scene.background_top, scene.background_texture, scene.background_intensity, scene.background_rotation, direction);
 }
@@ -0,0 +1,72 @@
+// Reservoirs of ReSTIR, one for every pixel of the image in each of two halves: the first holds
+// the ones of the current sample after temporal reuse, the second the ones after spatial reuse,
+// which become the previous ones of the next sample and render
+//
+// Kernels using them are laid out with them taking the place of the accumulation, like the photon
+// map
+#include "frame.wgsl"
+#include "paths.wgsl"
+#include "bsdf.wgsl"
+
+struct Reservoir {
+    // Surface the reservoir samples the lights of
+    position: vec3<f32>,
+    // Light picked so far
+    light: u32,
+    normal: vec3<f32>,
+    // Candidates the pick was made out of, zero for an empty reservoir
+    count: f32,
+    // Sum of the resampling weights while streaming candidates in, then the weight of the pick
+    // for the estimate
+    weight: f32,
+}
+
+@group(0) @binding(3)
+var<storage, read_write> reservoirs: array<Reservoir>;
+
+fn reservoir_half() -> u32 {
+    return frame.image_wh.x * frame.image_wh.y;
+}
+
+// Luminance of the light from `light` reaching a surface, ignoring occluders, which the picks are
+// distributed by
+fn target_density(light: u32, position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    // Picks of earlier renders may be of lights that are gone
+    if (light >= scene.light_count) {
+        return 0.0;
+    }
+    let sample = sample_light(lights[light], position);
+    return luminance(sample.intensity) * max(dot(normal, sample.direction), 0.0);
+}
+
+// Streams a candidate into `reservoir`, standing for `count` of them with resampling weight
+// `weight`
+fn update_reservoir(reservoir: ptr<function, Reservoir>, light: u32, weight: f32, count: f32) {
+    (*reservoir).weight += weight;
+    (*reservoir).count += count;
+    if (weight > 0.0 && rand_f32() * (*reservoir).weight < weight) {
+        (*reservoir).light = light;
+    }
+}
+
+// Turns the sum of the resampling weights into the weight of the pick
+fn finish_reservoir(reservoir: ptr<function, Reservoir>) {
+    let density = target_density((*reservoir).light, (*reservoir).position, (*reservoir).normal);
+    if (density > 0.0 && (*reservoir).count > 0.0) {
+        (*reservoir).weight /= (*reservoir).count * density;
+    } else {
+        (*reservoir).weight = 0.0;
+    }
+}
+
+// Streams the pick of a reservoir of another sample or pixel into `reservoir`, with the density
+// of its light at the surface of `reservoir`. Reservoirs of surfaces too far away or facing
+// elsewhere aren't reused
+fn merge_reservoir(reservoir: ptr<function, Reservoir>, other: Reservoir) {
+    let tolerance = 0.05 * length((*reservoir).position - scene.camera_position);
+    if (other.count == 0.0 || dot(other.normal, (*reservoir).normal) < 0.9 || length(other.position - (*reservoir).position) > tolerance) {
+        return;
+    }
+    let density = target_density(other.light, (*reservoir).position, (*reservoir).normal);
+    update_reservoir(reservoir, other.light, density * other.weight * other.count, other.count);
+}
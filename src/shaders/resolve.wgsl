@@ -0,0 +1,18 @@
+#include "frame.wgsl"
+
+@group(0) @binding(2)
+var out_image: texture_storage_2d<rgba8unorm, write>;
+
+// Averages the accumulated samples of the tile into the output texture
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    if (global_invocation_id.x >= frame.tile_wh.x || global_invocation_id.y >= frame.tile_wh.y) {
+        return;
+    }
+
+    let index = global_invocation_id.y * frame.tile_wh.x + global_invocation_id.x;
+    let mean = load_accumulation(index);
+
+    textureStore(out_image, vec2<i32>(global_invocation_id.xy), mean);
+}
@@ -0,0 +1,40 @@
+// Bounces off the diffuse surfaces the camera sees for ReSTIR GI, stored before shading their
+// hits. The paths carry on from there with a throughput of one, so that what they gather from
+// then on is the radiance leaving the hit towards the surface
+#include "gi_reservoirs.wgsl"
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>, @builtin(local_invocation_index) local_invocation_index: u32) {
+    let index = live_path(live_slot(workgroup_id, local_invocation_index));
+    if (index == NO_PATH) {
+        return;
+    }
+    let path = paths[index];
+    // Radiance of single wavelengths can't be reused by other paths, nor light scattered by fog
+    // on the way
+    if (path.bounce != 1u || path.event != EVENT_DIFFUSE || path.wavelength != 0.0 || fog_enabled()) {
+        return;
+    }
+
+    let hit = hits[index];
+    let direction = normalize(path.ray.direction);
+    let normal = path.shadow_ray.normal;
+    var bounce = empty_gi_reservoir(path.ray.origin, encode_direction(normal));
+    bounce.count = 1.0;
+    // Misses reconnect to a point far away in the same direction, which stands for the sky
+    if (hit.hit != 0u) {
+        bounce.sample_position = hit.hit_point;
+        bounce.sample_normal = encode_direction(hit.normal);
+    } else {
+        bounce.sample_position = path.ray.origin + direction * 1.0e6;
+        bounce.sample_normal = encode_direction(-direction);
+    }
+    bounce.weight = max(dot(normal, direction), 1.0e-6) / PI;
+    bounce.direct = pack_color(path.radiance);
+    bounce.albedo = pack_color(path.shadow_ray.weight);
+    gi_reservoirs[gi_bounce_index(index)] = bounce;
+
+    paths[index].radiance = vec3<f32>(0.0, 0.0, 0.0);
+    paths[index].throughput = vec3<f32>(1.0, 1.0, 1.0);
+}
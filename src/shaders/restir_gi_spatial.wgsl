@@ -0,0 +1,53 @@
+// Second half of ReSTIR GI: reuses the picks of neighbouring pixels of the tile and replaces the
+// light the paths got from their bounce with the one of the bounce picked in the end. Runs after
+// `restir_gi_temporal.wgsl`
+#include "gi_reservoirs.wgsl"
+
+// Neighbours reused and how far away they are at most, in pixels
+let SPATIAL_NEIGHBOURS: u32 = 4u;
+let SPATIAL_RADIUS: f32 = 24.0;
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    if (!in_tile(global_invocation_id)) {
+        return;
+    }
+    let index = path_index(global_invocation_id);
+    let pixel = pixel_index(index);
+    let bounce = gi_reservoirs[gi_bounce_index(index)];
+    if (bounce.count == 0.0) {
+        gi_reservoirs[gi_half() + pixel] = empty_gi_reservoir(bounce.position, bounce.normal);
+        return;
+    }
+    rng_state = pcg_hash(paths[index].rng_state ^ pcg_hash(~frame.restir_frame));
+
+    let current = gi_reservoirs[pixel];
+    var reservoir = empty_gi_reservoir(bounce.position, bounce.normal);
+    update_gi_reservoir(&reservoir, current, gi_target_density(current) * current.weight * current.count, current.count);
+    let local = vec2<f32>(global_invocation_id.xy);
+    let tile_max = vec2<f32>(frame.tile_wh - 1u);
+    for (var i = 0u; i < SPATIAL_NEIGHBOURS; i += 1u) {
+        let radius = SPATIAL_RADIUS * sqrt(rand_f32());
+        let angle = 2.0 * PI * rand_f32();
+        let neighbour = vec2<u32>(clamp(local + radius * vec2<f32>(cos(angle), sin(angle)), vec2<f32>(0.0), tile_max));
+        let other = neighbour.y * frame.tile_wh.x + neighbour.x;
+        if (other != index) {
+            merge_gi_reservoir(&reservoir, gi_reservoirs[pixel_index(other)]);
+        }
+    }
+    finish_gi_reservoir(&reservoir);
+    gi_reservoirs[gi_half() + pixel] = reservoir;
+    gi_reservoirs[gi_bounce_index(index)].count = 0.0;
+
+    var radiance = unpack_color(bounce.direct);
+    let to_sample = reservoir.sample_position - reservoir.position;
+    let distance = length(to_sample);
+    let direction = to_sample / distance;
+    let cos_theta = dot(decode_normal(bitcast<f32>(reservoir.normal)), direction);
+    if (reservoir.weight > 0.0 && cos_theta > 0.0 && !occluded(reservoir.position, direction, distance * (1.0 - 1.0e-4))) {
+        // The albedo is part of the throughput, the 1/pi of the BRDF is left
+        radiance += unpack_color(bounce.albedo) / PI * cos_theta * unpack_color(reservoir.radiance) * reservoir.weight;
+    }
+    paths[index].radiance = radiance;
+}
@@ -0,0 +1,28 @@
+// First half of ReSTIR GI once the paths of a sample are done: turns the bounce of every path into
+// a reservoir and reuses the pick of the same pixel from the previous sample
+#include "gi_reservoirs.wgsl"
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    if (!in_tile(global_invocation_id)) {
+        return;
+    }
+    let index = path_index(global_invocation_id);
+    let pixel = pixel_index(index);
+    var bounce = gi_reservoirs[gi_bounce_index(index)];
+    if (bounce.count == 0.0) {
+        gi_reservoirs[pixel] = empty_gi_reservoir(bounce.position, bounce.normal);
+        return;
+    }
+    rng_state = pcg_hash(paths[index].rng_state ^ pcg_hash(frame.restir_frame));
+    bounce.radiance = pack_color(paths[index].radiance);
+
+    var reservoir = empty_gi_reservoir(bounce.position, bounce.normal);
+    update_gi_reservoir(&reservoir, bounce, gi_target_density(bounce) / bounce.weight, 1.0);
+    var previous = gi_reservoirs[gi_half() + pixel];
+    previous.count = min(previous.count, GI_MAX_HISTORY);
+    merge_gi_reservoir(&reservoir, previous);
+    finish_gi_reservoir(&reservoir);
+    gi_reservoirs[pixel] = reservoir;
+}
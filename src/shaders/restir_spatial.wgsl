@@ -0,0 +1,54 @@
+// Second half of ReSTIR: reuses the picks of neighbouring pixels of the tile and shades the
+// surface with the light picked in the end, in place of the shadow ray. Runs after
+// `restir_temporal.wgsl`
+#include "reservoirs.wgsl"
+#include "spectral.wgsl"
+
+// Neighbours reused and how far away they are at most, in pixels
+let SPATIAL_NEIGHBOURS: u32 = 4u;
+let SPATIAL_RADIUS: f32 = 16.0;
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>, @builtin(local_invocation_index) local_invocation_index: u32) {
+    let index = live_path(live_slot(workgroup_id, local_invocation_index));
+    if (index == NO_PATH) {
+        return;
+    }
+    let pixel = pixel_index(index);
+    let current = reservoirs[pixel];
+    let shadow_ray = paths[index].shadow_ray;
+    if (shadow_ray.pending != SHADOW_SURFACE || scene.light_count == 0u) {
+        reservoirs[reservoir_half() + pixel] = current;
+        return;
+    }
+    rng_state = paths[index].rng_state;
+    wavelengths = hero_wavelengths(paths[index].wavelength);
+
+    var reservoir = Reservoir(current.position, current.light, current.normal, 0.0, 0.0);
+    update_reservoir(&reservoir, current.light, target_density(current.light, current.position, current.normal) * current.weight * current.count, current.count);
+    let local = vec2<f32>(f32(index % frame.tile_wh.x), f32(index / frame.tile_wh.x));
+    let tile_max = vec2<f32>(frame.tile_wh - 1u);
+    for (var i = 0u; i < SPATIAL_NEIGHBOURS; i += 1u) {
+        let radius = SPATIAL_RADIUS * sqrt(rand_f32());
+        let angle = 2.0 * PI * rand_f32();
+        let neighbour = vec2<u32>(clamp(local + radius * vec2<f32>(cos(angle), sin(angle)), vec2<f32>(0.0), tile_max));
+        let other = neighbour.y * frame.tile_wh.x + neighbour.x;
+        if (other != index) {
+            merge_reservoir(&reservoir, reservoirs[pixel_index(other)]);
+        }
+    }
+    finish_reservoir(&reservoir);
+    reservoirs[reservoir_half() + pixel] = reservoir;
+
+    paths[index].radiance += shadow_ray.weight * spectrum(portal_light(shadow_ray.origin, shadow_ray.normal));
+    if (reservoir.weight > 0.0) {
+        let sample = sample_light(lights[reservoir.light], reservoir.position);
+        let cos_theta = max(dot(reservoir.normal, sample.direction), 0.0);
+        // The weight includes the albedo, the 1/pi of the BRDF is left
+        let light = unoccluded_light(reservoir.position, sample) * cos_theta / PI;
+        paths[index].radiance += shadow_ray.weight * spectrum(light) * reservoir.weight;
+    }
+    paths[index].shadow_ray.pending = 0u;
+    paths[index].rng_state = rng_state;
+}
@@ -0,0 +1,43 @@
+// First half of ReSTIR at the diffuse surfaces the camera paths hit first: picks a light out of
+// candidates drawn uniformly, drops it if it's occluded and reuses the pick of the same pixel from
+// the previous sample. Runs after shading the first bounce
+#include "reservoirs.wgsl"
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>, @builtin(local_invocation_index) local_invocation_index: u32) {
+    let index = live_path(live_slot(workgroup_id, local_invocation_index));
+    if (index == NO_PATH) {
+        return;
+    }
+    let pixel = pixel_index(index);
+    let shadow_ray = paths[index].shadow_ray;
+    var reservoir = Reservoir(shadow_ray.origin, 0u, shadow_ray.normal, 0.0, 0.0);
+    if (shadow_ray.pending != SHADOW_SURFACE || scene.light_count == 0u) {
+        reservoirs[pixel] = reservoir;
+        return;
+    }
+    rng_state = pcg_hash(paths[index].rng_state ^ pcg_hash(frame.restir_frame));
+
+    for (var i = 0u; i < frame.restir_candidates; i += 1u) {
+        let light = pick_light(rand_f32());
+        let density = target_density(light, reservoir.position, reservoir.normal);
+        update_reservoir(&reservoir, light, density / light_probability(light), 1.0);
+    }
+    finish_reservoir(&reservoir);
+    let sample = sample_light(lights[reservoir.light], reservoir.position);
+    if (occluded(reservoir.position, sample.direction, sample.distance)) {
+        reservoir.weight = 0.0;
+    }
+
+    // The previous pick can't stand for more than twenty times the fresh candidates
+    var previous = reservoirs[reservoir_half() + pixel];
+    previous.count = min(previous.count, 20.0 * f32(frame.restir_candidates));
+    let candidates = reservoir;
+    reservoir.weight = 0.0;
+    reservoir.count = 0.0;
+    update_reservoir(&reservoir, candidates.light, target_density(candidates.light, candidates.position, candidates.normal) * candidates.weight * candidates.count, candidates.count);
+    merge_reservoir(&reservoir, previous);
+    finish_reservoir(&reservoir);
+    reservoirs[pixel] = reservoir;
+}
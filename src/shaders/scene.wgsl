@@ -0,0 +1,360 @@
+// Scene the rays are traced against
+
+struct Ray {
+    origin: vec3<f32>,
+    direction: vec3<f32>,
+}
+
+struct HitRecord {
+    hit_point: vec3<f32>,
+    normal: vec3<f32>,
+    distance: f32,
+    front_face: bool,
+    material: u32,
+    // Texture coordinates of the hit, the parametric ones on primitives without any
+    uv: vec2<f32>,
+    // Index of the scene object the primitive belongs to
+    object: u32,
+    // Texture coordinate units per world unit around the hit, to pick the mip level of textures
+    texel_scale: f32,
+    // Direction the first texture coordinate increases in, along which anisotropic materials are
+    // stretched, not necessarily unit length or perpendicular to the normal
+    tangent: vec3<f32>,
+    // Linear RGB multiplying the base color, the color of points and white elsewhere
+    color: vec3<f32>,
+    // OBJECT flags of the object
+    flags: u32,
+}
+
+struct Sphere {
+    center: vec3<f32>,
+    radius: f32,
+}
+
+struct SceneUniforms {
+    camera_position: vec3<f32>,
+    tan_half_fov: f32,
+    camera_forward: vec3<f32>,
+    light_count: u32,
+    camera_right: vec3<f32>,
+    // One of the PROJECTION constants
+    camera_projection: u32,
+    camera_up: vec3<f32>,
+    // Portals follow the lights in `lights`
+    portal_count: u32,
+    // Fog fills the box from `fog_min` to `fog_max`, there's none if both coefficients are zero.
+    // With `fog_density_grid` set they're scaled by `density_grid` over the box
+    fog_scattering: vec3<f32>,
+    fog_anisotropy: f32,
+    fog_absorption: vec3<f32>,
+    fog_density_grid: u32,
+    fog_min: vec3<f32>,
+    // Clipping planes follow the portals in `lights`
+    clip_plane_count: u32,
+    fog_max: vec3<f32>,
+    // One of the SKY constants, the physical sky has the coefficients of the Perez formula of
+    // its luminance and chromaticity in `sky_a` to `sky_e`, their values at the zenith over the
+    // formula there in `sky_zenith` and the direction towards the sun. Gradients go from
+    // `sky_bottom` to `sky_top`, textures are scaled by `sky_intensity` and turned by
+    // `sky_rotation` turns
+    sky_kind: u32,
+    sky_a: vec3<f32>,
+    sky_ground_albedo: f32,
+    sky_b: vec3<f32>,
+    sky_texture: u32,
+    sky_c: vec3<f32>,
+    sky_intensity: f32,
+    sky_d: vec3<f32>,
+    sky_rotation: f32,
+    sky_e: vec3<f32>,
+    // What camera rays escaping the scene see like the sky, BACKGROUND_SKY for the sky itself.
+    // A physical one has the coefficients of the sky
+    background_kind: u32,
+    sky_zenith: vec3<f32>,
+    background_texture: u32,
+    sun_direction: vec3<f32>,
+    background_intensity: f32,
+    sky_bottom: vec3<f32>,
+    background_rotation: f32,
+    sky_top: vec3<f32>,
+    // Shift of the lens in heights of the image, right and up
+    camera_shift_x: f32,
+    background_bottom: vec3<f32>,
+    camera_shift_y: f32,
+    background_top: vec3<f32>,
+}
+
+// Triangles keep their world space vertices in v0, v1 and v2, smooth shaded ones the encoded normals
+// of the vertices in their w, spheres their center and radius in v0, custom shapes their bounds in v0
+// and v1 and their data in v2. Signed distance fields have their bounds in v0 and v1 too, and in v2
+// the index and length of their program among the primitives past the BVH and the smallest scale
+// of their transform. Tiles of heightfields have their bounds in v0 and v1 as well, and in v2 the
+// index of the header of their heightfield past the BVH, the column and row of the tile in the low
+// and high half of the bits and their lowest and highest height. Pieces of curves have the centers
+// and radii of their ends in v0 and v1, and in v2 the parameters along their strand at those ends
+// and whether they're flat ribbons. `texcoords` are the texture coordinates of the vertices of
+// triangles
+struct Primitive {
+    v0: vec4<f32>,
+    v1: vec4<f32>,
+    v2: vec4<f32>,
+    kind: u32,
+    material: u32,
+    object: u32,
+    // OBJECT flags of the object
+    flags: u32,
+    texcoords: array<vec2<f32>, 3>,
+}
+
+// Leaves hold `count` primitives starting at `left_or_first`, inner nodes have a zero count and
+// their children at `left_or_first` and `left_or_first + 1`
+struct BvhNode {
+    aabb_min: vec3<f32>,
+    left_or_first: u32,
+    aabb_max: vec3<f32>,
+    count: u32,
+}
+
+struct Material {
+    base_color: vec3<f32>,
+    metallic: f32,
+    emission: vec3<f32>,
+    roughness: f32,
+    ior: f32,
+    transmission: f32,
+    // Id for the scatter hook, MATERIAL_BUILTIN for the built-in lobes
+    custom: u32,
+    // Fraction of the surface that's there, the points that aren't are skipped by all rays
+    opacity: f32,
+    // Texture of `page_table` multiplying base_color, NO_TEXTURE for none
+    base_color_texture: u32,
+    specular: f32,
+    sheen: f32,
+    sheen_tint: f32,
+    // Stretch of specular highlights along the tangent and its rotation around the normal in turns
+    anisotropic: f32,
+    anisotropic_rotation: f32,
+    // Weight of the clear coat layer over the other lobes
+    clearcoat: f32,
+    clearcoat_roughness: f32,
+    clearcoat_ior: f32,
+    // Thin film over the specular lobes in nanometers, none at zero, and its index of refraction
+    thin_film_thickness: f32,
+    thin_film_ior: f32,
+    // Probability of diffuse light entering the surface for a random walk through the medium of
+    // these coefficients per unit length, rather than reflecting off it
+    subsurface: f32,
+    subsurface_scattering: vec3<f32>,
+    abbe_number: f32,
+    subsurface_absorption: vec3<f32>,
+    // Probability of scattering like hair around the tangent, with the azimuthal roughness and the
+    // tilt of the scales of the fibers in degrees
+    hair: f32,
+    hair_azimuthal_roughness: f32,
+    hair_scale_angle: f32,
+    // Whether camera rays only see the shadows and reflections other surfaces cast onto it
+    shadow_catcher: u32,
+    // Which of the transmissive materials overlapping each other fills the space they share, the
+    // one of the highest priority
+    priority: u32,
+    // Transform of the texture coordinates base_color_texture is looked up at
+    texture_transform: mat3x2<f32>,
+}
+
+// `position` is the direction light travels in for directional lights, which arrive from a cone
+// `cos_half_angle` wide around it, a single direction at 1. Point lights with a layer of
+// `light_profiles` as `profile` scale their intensity by it, with its vertical angles starting at
+// `profile_axis` and its horizontal ones at `profile_reference`. Portals are the rectangles at
+// `position` spanned by those two. Clipping planes go through `position`, keep the side
+// `profile_axis` points to and cap surfaces with the material `profile`, NO_CAP for none.
+// `power_cdf` is the fraction of the power of all lights up to and including this one
+struct Light {
+    position: vec3<f32>,
+    kind: u32,
+    intensity: vec3<f32>,
+    cos_half_angle: f32,
+    profile_axis: vec3<f32>,
+    profile: u32,
+    profile_reference: vec3<f32>,
+    power_cdf: f32,
+    // Bits of the light links of the light, set in the OBJECT_LIGHT_LINKS of the objects it
+    // doesn't illuminate
+    link: u32,
+}
+
+let PRIMITIVE_SPHERE: u32 = 1u;
+let PRIMITIVE_CUSTOM: u32 = 2u;
+let PRIMITIVE_SMOOTH_TRIANGLE: u32 = 3u;
+let PRIMITIVE_SDF: u32 = 4u;
+// Headers of heightfields hold the rows of the transform from world space to the one of the grid,
+// which spans the unit square with heights from 0 to 1, in v0, v1 and v2 and their layer of
+// `heightfields` as `kind`. Their `texcoords` are the derivative of the position along the first
+// texture coordinate, the texel scale and the columns and rows of cells
+let PRIMITIVE_HEIGHTFIELD: u32 = 5u;
+let PRIMITIVE_CURVE: u32 = 6u;
+// Disks of points hold their center and radius in v0, the normal they face, zero to face the ray,
+// and 1 for Gaussian splats in v1 and their color in v2
+let PRIMITIVE_POINT: u32 = 7u;
+// Quads hold their corner and the ends of its edges in v0, v1 and v2, like the vertices of the
+// triangle they're twice the size of
+let PRIMITIVE_QUAD: u32 = 8u;
+// Boxes hold the rows of the transform from world space to the unit cube they span in v0, v1 and v2
+let PRIMITIVE_BOX: u32 = 9u;
+// Instructions of the programs of signed distance fields after their header, which holds the rows
+// of the transform from world to object space in v0, v1 and v2. Shapes push their distance with
+// their center in v0 and their size in v1, operations combine the last two with the smoothness in
+// v0.x
+let SDF_SPHERE: u32 = 0u;
+let SDF_CUBOID: u32 = 1u;
+let SDF_TORUS: u32 = 2u;
+let SDF_CYLINDER: u32 = 3u;
+let SDF_UNION: u32 = 4u;
+let SDF_INTERSECTION: u32 = 5u;
+let SDF_SUBTRACTION: u32 = 6u;
+// Solids of constructive solid geometry hold the same as PRIMITIVE_SDF, in programs of solids
+// with their center in v0 and their size in v1 and boolean operations on the last two
+let PRIMITIVE_CSG: u32 = 10u;
+let CSG_SPHERE: u32 = 0u;
+let CSG_CUBOID: u32 = 1u;
+let CSG_CYLINDER: u32 = 2u;
+let CSG_UNION: u32 = 3u;
+let CSG_INTERSECTION: u32 = 4u;
+let CSG_DIFFERENCE: u32 = 5u;
+// Objects camera rays see through to zero alpha, the footage the render is composited into
+let OBJECT_HOLDOUT: u32 = 1u;
+// Objects the rays of a kind pass through
+let OBJECT_HIDDEN_CAMERA: u32 = 2u;
+let OBJECT_HIDDEN_SHADOW: u32 = 4u;
+let OBJECT_HIDDEN_REFLECTION: u32 = 8u;
+let OBJECT_HIDDEN_INDIRECT: u32 = 16u;
+// First of the bits of the links of the lights that don't illuminate the object
+let OBJECT_LIGHT_LINKS_SHIFT: u32 = 8u;
+let MATERIAL_BUILTIN: u32 = 0xffffffffu;
+let NO_TEXTURE: u32 = 0xffffffffu;
+// Texels on a side of the pages of the textures, and of the slots of `texture_cache` holding them
+// with a border of their neighbours for filtering
+let PAGE_SIZE_LOG2: u32 = 7u;
+let PAGE_BORDER: u32 = 1u;
+let PAGE_SLOT_SIZE: u32 = 130u;
+let NOT_RESIDENT: u32 = 0xffffffffu;
+let LIGHT_DIRECTIONAL: u32 = 1u;
+let LIGHT_PORTAL: u32 = 2u;
+let LIGHT_CLIP_PLANE: u32 = 3u;
+let NO_CAP: u32 = 0xffffffffu;
+let PROJECTION_PERSPECTIVE: u32 = 0u;
+// All directions around the camera position on an octahedral map, for irradiance probes
+let PROJECTION_OCTAHEDRAL: u32 = 1u;
+let SKY_GRADIENT: u32 = 0u;
+let SKY_PHYSICAL: u32 = 1u;
+let SKY_TEXTURE: u32 = 2u;
+let BACKGROUND_SKY: u32 = 3u;
+let PI: f32 = 3.14159265;
+let T_MAX: f32 = 1.0e30;
+
+@group(1) @binding(0)
+var<uniform> scene: SceneUniforms;
+
+@group(1) @binding(1)
+var<storage, read> primitives: array<Primitive>;
+
+@group(1) @binding(2)
+var<storage, read> bvh_nodes: array<BvhNode>;
+
+@group(1) @binding(3)
+var<storage, read> materials: array<Material>;
+
+@group(1) @binding(4)
+var<storage, read> lights: array<Light>;
+
+// Pages of the textures, in slots looked up in `page_table`
+@group(1) @binding(5)
+var texture_cache: texture_2d<f32>;
+
+@group(1) @binding(6)
+var texture_sampler: sampler;
+
+@group(1) @binding(7)
+var density_grid: texture_3d<f32>;
+
+// Intensities of the light profiles over their brightest, horizontal angles from 0 to 360 degrees
+// across and vertical ones from 0 to 180 degrees down
+@group(1) @binding(8)
+var light_profiles: texture_2d_array<f32>;
+
+// Heights of the terrains from 0 to 1, in the top left corner of their layer
+@group(1) @binding(9)
+var heightfields: texture_2d_array<f32>;
+
+// The first entry of every texture shifted up by 8 bits over the log2 of its size, then the slot
+// of every page in `texture_cache`, level after level and row after row, NOT_RESIDENT if it's not
+// in there
+@group(1) @binding(10)
+var page_table: texture_2d<u32>;
+
+// Entry of `page_table` plus one of the page the last texture lookup wanted, zero for none, which
+// shading reports back to the host to stream in
+var<private> texture_request: u32;
+
+fn ray_at(ray: Ray, dist: f32) -> vec3<f32> {
+    return ray.origin + ray.direction * dist;
+}
+
+// Unit vector at `e` on an octahedral map from -1 to 1, the upper hemisphere in the middle
+fn octahedral_direction(e: vec2<f32>) -> vec3<f32> {
+    var n = vec3<f32>(e.x, e.y, 1.0 - abs(e.x) - abs(e.y));
+    let t = max(-n.z, 0.0);
+    n.x += select(t, -t, n.x >= 0.0);
+    n.y += select(t, -t, n.y >= 0.0);
+    return normalize(n);
+}
+
+fn page_table_entry(index: u32) -> u32 {
+    let width = u32(textureDimensions(page_table).x);
+    return textureLoad(page_table, vec2<i32>(i32(index % width), i32(index / width)), 0).r;
+}
+
+// Color of `texture` at texture coordinates `uv`, which start at the bottom left, filtered over a
+// `footprint` that wide in texture coordinates
+fn texture_color(texture: u32, uv: vec2<f32>, footprint: f32) -> vec3<f32> {
+    let descriptor = page_table_entry(texture);
+    let size_log2 = descriptor & 0xffu;
+    let size = 1u << size_log2;
+    // Levels smaller than a page aren't kept, the coarsest one is always in the cache
+    let levels = max(size_log2, PAGE_SIZE_LOG2) - PAGE_SIZE_LOG2 + 1u;
+    let wanted = min(u32(log2(max(footprint * f32(size), 1.0)) + 0.5), levels - 1u);
+    let st = fract(vec2<f32>(uv.x, 1.0 - uv.y));
+
+    // Falls back to coarser levels than the one wanted until one has the page in the cache
+    var entry = descriptor >> 8u;
+    var texel = vec4<f32>(1.0, 1.0, 1.0, 1.0);
+    for (var level = 0u; level < levels; level += 1u) {
+        let level_size = size >> level;
+        let pages = max(level_size >> PAGE_SIZE_LOG2, 1u);
+        if (level >= wanted) {
+            let page = min(vec2<u32>(st * f32(pages)), vec2<u32>(pages - 1u));
+            let index = entry + page.y * pages + page.x;
+            if (level == wanted) {
+                texture_request = index + 1u;
+            }
+            let slot = page_table_entry(index);
+            if (slot != NOT_RESIDENT) {
+                let slots_across = u32(textureDimensions(texture_cache).x) / PAGE_SLOT_SIZE;
+                let origin = vec2<u32>(slot % slots_across, slot / slots_across) * PAGE_SLOT_SIZE + PAGE_BORDER;
+                let position = vec2<f32>(origin) + st * f32(level_size) - vec2<f32>(page << vec2<u32>(PAGE_SIZE_LOG2));
+                texel = textureSampleLevel(texture_cache, texture_sampler, position / vec2<f32>(textureDimensions(texture_cache)), 0.0);
+                break;
+            }
+        }
+        entry += pages * pages;
+    }
+    return texel.rgb;
+}
+
+// Base color of the material at texture coordinates `uv`, filtered over a `footprint` that wide
+fn base_color(material: Material, uv: vec2<f32>, footprint: f32) -> vec3<f32> {
+    if (material.base_color_texture == NO_TEXTURE) {
+        return material.base_color;
+    }
+    let st = material.texture_transform * vec3<f32>(uv, 1.0);
+    return material.base_color * texture_color(material.base_color_texture, st, footprint);
+}
@@ -0,0 +1,388 @@
+// Shading of the hits of the live paths, extending them by a bounce
+//
+// Feature flags:
+// - NEE: sample the lights directly at diffuse surfaces and in the fog through shadow rays, without
+//   it point and directional lights don't contribute
+// - MAX_BOUNCES: compile in a cap on the bounce limit of the frame uniforms
+// - SPECTRAL: trace hero wavelengths instead of RGB, dispersing light through glass with an Abbe
+//   number, see `spectral.wgsl`
+//
+// Shading runs in f32 throughout, wgpu 0.14 knows SHADER_FLOAT16 but naga 0.10 can't compile
+// WGSL using f16 yet
+#include "frame.wgsl"
+#include "paths.wgsl"
+#include "random.wgsl"
+#include "ray_miss.wgsl"
+#include "ray_chit.wgsl"
+#include "bsdf.wgsl"
+
+// BSDF of custom materials registered by the host, defining HOOK_SCATTER
+#include "hook_scatter.wgsl"
+
+// Page of the textures every path last wanted plus one, zero for none, read back by the host to
+// stream them in when they don't all fit in the texture cache
+@group(0) @binding(4)
+var texture_feedback: texture_storage_2d<r32uint, write>;
+
+// Leaves the page the path at `index` wanted last, if any, for the host to read back
+fn store_texture_request(index: u32) {
+    if (texture_request != 0u) {
+        let width = u32(textureDimensions(texture_feedback).x);
+        textureStore(texture_feedback, vec2<i32>(i32(index % width), i32(index / width)), vec4<u32>(texture_request, 0u, 0u, 0u));
+    }
+}
+
+// Slot of the last entry of `material` in a stack of dielectrics, 4 if there's none
+fn find_dielectric(stack: vec4<u32>, material: u32) -> u32 {
+    var slot = 4u;
+    for (var i = 0u; i < 4u; i += 1u) {
+        if (stack[i] == material) {
+            slot = i;
+        }
+    }
+    return slot;
+}
+
+// Slot of the dielectric filling the space inside the ones of a stack besides the one at `skip`:
+// the last entered of those of the highest priority, 4 outside of all of them
+fn enclosing_dielectric(stack: vec4<u32>, skip: u32) -> u32 {
+    var slot = 4u;
+    for (var i = 0u; i < 4u; i += 1u) {
+        if (i != skip && stack[i] != NO_MEDIUM && (slot == 4u || materials[stack[i]].priority >= materials[stack[slot]].priority)) {
+            slot = i;
+        }
+    }
+    return slot;
+}
+
+// `stack` with `material` entered, forgetting the first one entered when it's full
+fn push_dielectric(stack: vec4<u32>, material: u32) -> vec4<u32> {
+    let slot = find_dielectric(stack, NO_MEDIUM);
+    if (slot == 4u) {
+        return vec4<u32>(stack.yzw, material);
+    }
+    var pushed = stack;
+    pushed[slot] = material;
+    return pushed;
+}
+
+// `stack` with the entry at `slot` left
+fn pop_dielectric(stack: vec4<u32>, slot: u32) -> vec4<u32> {
+    var popped = stack;
+    for (var i = slot; i < 3u; i += 1u) {
+        popped[i] = popped[i + 1u];
+    }
+    popped.w = NO_MEDIUM;
+    return popped;
+}
+
+// Continues `path` off the surface of `rec`, returning false if it got absorbed
+fn scatter(path: ptr<function, PathState>, rec: HitRecord) -> bool {
+    var material = materials[rec.material];
+    // Ray cone footprint, stretched along surfaces seen at grazing angles
+    let cos_theta = max(abs(dot(normalize((*path).ray.direction), rec.normal)), 0.05);
+    let base_color = spectrum(base_color(material, rec.uv, (*path).cone_width * rec.texel_scale / cos_theta) * rec.color);
+    (*path).event = EVENT_ABSORBED;
+
+#ifdef HOOK_SCATTER
+    if (material.custom != MATERIAL_BUILTIN) {
+        var attenuation = vec3<f32>(1.0, 1.0, 1.0);
+        var direction: vec3<f32>;
+        if (!hook_scatter(material, (*path).ray, rec, &attenuation, &direction)) {
+            return false;
+        }
+        (*path).ray = Ray(offset_origin(rec.hit_point, rec.normal, direction), direction);
+        (*path).throughput *= spectrum(attenuation);
+        (*path).event = EVENT_CUSTOM;
+        return true;
+    }
+#endif
+
+    // Transmissive surfaces are only there where the space they bound isn't filled by a
+    // dielectric of a higher priority, and refract relative to the one on their other side
+    let nested = material.transmission > 0.0;
+    var own = 4u;
+    if (nested) {
+        let dielectrics = (*path).dielectrics;
+        if (!rec.front_face) {
+            own = find_dielectric(dielectrics, rec.material);
+        }
+        let outside = enclosing_dielectric(dielectrics, own);
+        var boundary = outside == 4u || material.priority >= materials[dielectrics[outside]].priority;
+        if (!rec.front_face) {
+            boundary = own == 4u || enclosing_dielectric(dielectrics, 4u) == own;
+        }
+        if (!boundary) {
+            (*path).ray = Ray(offset_origin(rec.hit_point, rec.normal, (*path).ray.direction), (*path).ray.direction);
+            (*path).event = EVENT_TRANSMISSION;
+            if (rec.front_face) {
+                (*path).dielectrics = push_dielectric(dielectrics, rec.material);
+            } else {
+                (*path).dielectrics = pop_dielectric(dielectrics, own);
+            }
+            return true;
+        }
+        if (outside != 4u) {
+            material.ior /= materials[dielectrics[outside]].ior;
+        }
+    }
+
+    let bsdf = sample_bsdf(material, base_color, -normalize((*path).ray.direction), rec.normal, rec.tangent, rec.front_face);
+    (*path).event = bsdf.event;
+    if (bsdf.event == EVENT_ABSORBED) {
+        return false;
+    }
+    if (nested && bsdf.event == EVENT_TRANSMISSION) {
+        if (rec.front_face) {
+            (*path).dielectrics = push_dielectric((*path).dielectrics, rec.material);
+        } else if (own != 4u) {
+            (*path).dielectrics = pop_dielectric((*path).dielectrics, own);
+        }
+    }
+#ifdef NEE
+    if (bsdf.event == EVENT_DIFFUSE) {
+        (*path).shadow_ray = ShadowRay(offset_origin(rec.hit_point, rec.normal, rec.normal), SHADOW_SURFACE, rec.normal, rec.flags >> OBJECT_LIGHT_LINKS_SHIFT, (*path).throughput * base_color);
+    }
+#endif
+
+    (*path).ray = Ray(offset_origin(rec.hit_point, rec.normal, bsdf.direction), bsdf.direction);
+    (*path).throughput *= bsdf.weight;
+    if (bsdf.event == EVENT_SUBSURFACE) {
+        (*path).medium = rec.material;
+        (*path).walk_steps = 0u;
+    }
+    return true;
+}
+
+// Random walk step of a path inside the subsurface medium of a material, scattering it before it
+// reaches `hit` with the probability of a collision on the way there. Returns false if it got to
+// the surface, its throughput scaled by the transmittance up to it
+//
+// Distances are sampled along a channel picked at random, weighted by the mean of the
+// probabilities of all channels so that colored media stay unbiased
+fn walk_medium(path: ptr<function, PathState>, hit: Hit) -> bool {
+    let material = materials[(*path).medium];
+    let sigma_s = spectrum(material.subsurface_scattering);
+    let sigma_t = sigma_s + spectrum(material.subsurface_absorption);
+
+    let channel = min(u32(rand_f32() * 3.0), 2u);
+    let distance = -log(1.0 - rand_f32()) / max(sigma_t[channel], 1.0e-6);
+    if (hit.hit != 0u && distance >= hit.distance) {
+        let transmittance = exp(-sigma_t * hit.distance);
+        (*path).throughput *= transmittance / max(dot(transmittance, vec3<f32>(1.0 / 3.0)), 1.0e-30);
+        return false;
+    }
+
+    let transmittance = exp(-sigma_t * distance);
+    let pdf = dot(sigma_t * transmittance, vec3<f32>(1.0 / 3.0));
+    (*path).throughput *= sigma_s * transmittance / max(pdf, 1.0e-30);
+    // Isotropic scattering
+    (*path).ray = Ray(ray_at((*path).ray, distance), random_unit_vector());
+    (*path).event = EVENT_SUBSURFACE;
+    (*path).walk_steps += 1u;
+    (*path).alive = select(0u, 1u, (*path).walk_steps < MAX_WALK_STEPS && any((*path).throughput > vec3<f32>(0.0)));
+    return true;
+}
+
+// Direction light travelling in `direction` is scattered to by the fog
+fn sample_fog_phase(direction: vec3<f32>) -> vec3<f32> {
+    let g = scene.fog_anisotropy;
+    let u = rand_f32();
+    var cos_theta = 1.0 - 2.0 * u;
+    if (abs(g) > 1.0e-3) {
+        let s = (1.0 - g * g) / (1.0 + g - 2.0 * g * u);
+        cos_theta = clamp((1.0 + g * g - s * s) / (2.0 * g), -1.0, 1.0);
+    }
+    let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+    let phi = 2.0 * PI * rand_f32();
+    return tangent_frame(direction) * vec3<f32>(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+}
+
+// Scatters a path off the fog `distance` along its ray
+fn scatter_fog_at(path: ptr<function, PathState>, distance: f32) {
+    let ray = (*path).ray;
+    let position = ray_at(ray, distance);
+    let direction = normalize(ray.direction);
+#ifdef NEE
+    (*path).shadow_ray = ShadowRay(position, SHADOW_VOLUME, direction, 0u, (*path).throughput);
+#endif
+
+    (*path).cone_width += pixel_spread() * distance * length(ray.direction);
+    (*path).ray = Ray(position, sample_fog_phase(direction));
+    (*path).event = EVENT_VOLUME;
+}
+
+// Delta tracking of a path through the density grid over `segment` of its ray, with the densest
+// fog as the majorant. Tentative collisions are real with the probability of the mean density of
+// the channels, weighting the path for the ones it doesn't pick
+fn track_fog(path: ptr<function, PathState>, segment: vec2<f32>) -> bool {
+    let ray = (*path).ray;
+    let speed = length(ray.direction);
+    let sigma_s = spectrum(scene.fog_scattering) * speed;
+    let sigma_t = sigma_s + spectrum(scene.fog_absorption) * speed;
+    let majorant = fog_majorant(sigma_t);
+
+    var t = segment.x;
+    for (var step = 0u; step < MAX_FOG_STEPS; step += 1u) {
+        t -= log(1.0 - rand_f32()) / majorant;
+        if (t >= segment.y) {
+            return false;
+        }
+        let density = fog_density(ray_at(ray, t));
+        let collision = dot(sigma_t * density, vec3<f32>(1.0 / 3.0));
+        let real = collision / majorant;
+        if (rand_f32() < real) {
+            (*path).throughput *= sigma_s * density / max(collision, 1.0e-30);
+            scatter_fog_at(path, t);
+            return true;
+        }
+        (*path).throughput *= (majorant - sigma_t * density) / max(majorant - collision, 1.0e-30);
+    }
+    return false;
+}
+
+// Collision of a path with the fog before it reaches `hit`, scattering it with the probability of
+// one on the way there like `walk_medium`. Returns false if it got through, its throughput scaled
+// by the transmittance
+fn scatter_in_fog(path: ptr<function, PathState>, hit: Hit) -> bool {
+    let ray = (*path).ray;
+    let segment = fog_segment(ray, select(T_MAX, hit.distance, hit.hit != 0u));
+    if (segment.x >= segment.y) {
+        return false;
+    }
+    if (scene.fog_density_grid != 0u) {
+        return track_fog(path, segment);
+    }
+
+    // Distances are in units of the ray's direction
+    let speed = length(ray.direction);
+    let sigma_s = spectrum(scene.fog_scattering) * speed;
+    let sigma_t = sigma_s + spectrum(scene.fog_absorption) * speed;
+    let channel = min(u32(rand_f32() * 3.0), 2u);
+    let distance = -log(1.0 - rand_f32()) / max(sigma_t[channel], 1.0e-6);
+    if (distance >= segment.y - segment.x) {
+        let transmittance = exp(-sigma_t * (segment.y - segment.x));
+        (*path).throughput *= transmittance / max(dot(transmittance, vec3<f32>(1.0 / 3.0)), 1.0e-30);
+        return false;
+    }
+
+    let transmittance = exp(-sigma_t * distance);
+    let pdf = dot(sigma_t * transmittance, vec3<f32>(1.0 / 3.0));
+    (*path).throughput *= sigma_s * transmittance / max(pdf, 1.0e-30);
+    scatter_fog_at(path, segment.x + distance);
+    return true;
+}
+
+// Diffuse transmission of a path out of the subsurface medium it reached the surface of
+fn leave_medium(path: ptr<function, PathState>, rec: HitRecord) {
+    // The normal faces the inside the path comes from
+    var direction = random_unit_vector() - rec.normal;
+    if (dot(direction, direction) < 1.0e-8) {
+        direction = -rec.normal;
+    }
+    (*path).ray = Ray(offset_origin(rec.hit_point, rec.normal, direction), normalize(direction));
+    (*path).medium = NO_MEDIUM;
+    (*path).event = EVENT_SUBSURFACE;
+}
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>, @builtin(local_invocation_index) local_invocation_index: u32) {
+    let index = live_path(live_slot(workgroup_id, local_invocation_index));
+    if (index == NO_PATH) {
+        return;
+    }
+    var path = paths[index];
+    rng_state = path.rng_state;
+    wavelengths = hero_wavelengths(path.wavelength);
+    hero_only = path.hero_only != 0u;
+
+#ifdef MAX_BOUNCES
+    let max_bounces = min(MAX_BOUNCES, frame.max_bounces);
+#else
+    let max_bounces = frame.max_bounces;
+#endif
+
+    let hit = hits[index];
+    if (path.medium != NO_MEDIUM && walk_medium(&path, hit)) {
+        path.rng_state = rng_state;
+        paths[index] = path;
+        return;
+    }
+    if (path.medium == NO_MEDIUM && fog_enabled() && scatter_in_fog(&path, hit)) {
+        path.alive = select(0u, 1u, path.bounce < max_bounces && any(path.throughput > vec3<f32>(0.0)));
+        path.rng_state = rng_state;
+        path.vertex_labels = push_vertex_label(path.vertex_labels, EVENT_VOLUME);
+        path.bounce += 1u;
+        paths[index] = path;
+        return;
+    }
+    if (hit.hit == 0u) {
+        var sky: vec3<f32>;
+        if (path.bounce == 0u) {
+            sky = spectrum(background_color(path.ray.direction));
+        } else {
+            sky = spectrum(sky_color(path.ray.direction));
+        }
+        store_texture_request(index);
+#ifdef NEE
+        // The shadow rays of diffuse hits brought in the sky through the portals already
+        if (path.event == EVENT_DIFFUSE && through_portal(path.ray)) {
+            sky = vec3<f32>(0.0, 0.0, 0.0);
+        }
+#endif
+        // Shadow catchers only reflect the other surfaces, the backplate has the sky on it
+        if (path.catcher != 0u && path.bounce == 1u) {
+            sky = vec3<f32>(0.0, 0.0, 0.0);
+        }
+        if (path.bounce == 0u && frame.transparent_background != 0u) {
+            sky = vec3<f32>(0.0, 0.0, 0.0);
+            path.alpha = 0.0;
+        }
+        path.radiance += path.throughput * sky;
+        path.alive = 0u;
+        paths[index] = path;
+        return;
+    }
+
+    let rec = HitRecord(hit.hit_point, hit.normal, hit.distance, hit.front_face != 0u, hit.material, hit.uv, hit.object, hit.texel_scale, decode_normal(bitcast<f32>(hit.tangent)), pow(unpack4x8unorm(hit.color).rgb, vec3<f32>(2.0)), hit_flags(hit));
+    // Camera rays see through holdouts, to nothing
+    if (path.bounce == 0u && path.medium == NO_MEDIUM && (rec.flags & OBJECT_HOLDOUT) != 0u) {
+        path.alpha = 0.0;
+        path.alive = 0u;
+        paths[index] = path;
+        return;
+    }
+    // Camera rays see through shadow catchers, only the shadows cast onto them make up the alpha
+    let catcher = path.bounce == 0u && path.medium == NO_MEDIUM && materials[rec.material].shadow_catcher != 0u;
+    if (catcher) {
+        path.catcher = 1u;
+        path.alpha = 0.0;
+    } else {
+        path.radiance += path.throughput * spectrum(materials[rec.material].emission);
+    }
+
+    path.cone_width += pixel_spread() * rec.distance;
+    // Past the last bounce the rest of the path doesn't contribute
+    var scattered: bool;
+    if (path.medium != NO_MEDIUM) {
+        leave_medium(&path, rec);
+        scattered = true;
+    } else {
+        scattered = scatter(&path, rec);
+        store_texture_request(index);
+    }
+#ifdef NEE
+    // Shadows are sampled whichever lobe reflects the rest of the path
+    if (catcher) {
+        path.shadow_ray = ShadowRay(offset_origin(rec.hit_point, rec.normal, rec.normal), SHADOW_SURFACE, rec.normal, rec.flags >> OBJECT_LIGHT_LINKS_SHIFT, vec3<f32>(0.0, 0.0, 0.0));
+    }
+#endif
+    path.alive = select(0u, 1u, scattered && path.bounce < max_bounces);
+    path.rng_state = rng_state;
+    path.hero_only = u32(hero_only);
+    path.vertex_labels = push_vertex_label(path.vertex_labels, path.event);
+    path.bounce += 1u;
+
+    paths[index] = path;
+}
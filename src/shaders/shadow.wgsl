@@ -0,0 +1,53 @@
+// Shadow rays of the diffuse hits and scattering in the fog of the last bounce
+#include "frame.wgsl"
+#include "paths.wgsl"
+#include "ray_chit.wgsl"
+#include "spectral.wgsl"
+#include "bsdf.wgsl"
+
+// Fraction of the light arriving at a shadow catcher that other surfaces block, from the same
+// samples of the lights with and without occluders
+fn shadow_fraction(position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    let state = rng_state;
+    ignore_occluders = true;
+    let unshadowed = luminance(direct_light(position, normal) + portal_light(position, normal));
+    ignore_occluders = false;
+    rng_state = state;
+    let lit = luminance(direct_light(position, normal) + portal_light(position, normal));
+    if (unshadowed <= 0.0) {
+        return 0.0;
+    }
+    return clamp(1.0 - lit / unshadowed, 0.0, 1.0);
+}
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>, @builtin(local_invocation_index) local_invocation_index: u32) {
+    let index = live_path(live_slot(workgroup_id, local_invocation_index));
+    if (index == NO_PATH) {
+        return;
+    }
+    let shadow_ray = paths[index].shadow_ray;
+    if (shadow_ray.pending == 0u) {
+        return;
+    }
+    // Transmittance through density grids is estimated stochastically
+    rng_state = paths[index].rng_state;
+    wavelengths = hero_wavelengths(paths[index].wavelength);
+    unlinked_lights = shadow_ray.unlinked;
+
+    if (paths[index].catcher != 0u && paths[index].bounce == 1u) {
+        // The shadow catcher the camera sees doesn't show its own lighting
+        paths[index].alpha = shadow_fraction(shadow_ray.origin, shadow_ray.normal);
+    } else if (shadow_ray.pending == SHADOW_VOLUME) {
+        paths[index].radiance += shadow_ray.weight * spectrum(direct_light_volume(shadow_ray.origin, shadow_ray.normal));
+    } else {
+        var light = direct_light(shadow_ray.origin, shadow_ray.normal);
+        if (shadow_ray.pending == SHADOW_SURFACE) {
+            light += portal_light(shadow_ray.origin, shadow_ray.normal);
+        }
+        paths[index].radiance += shadow_ray.weight * spectrum(light);
+    }
+    paths[index].shadow_ray.pending = 0u;
+    paths[index].rng_state = rng_state;
+}
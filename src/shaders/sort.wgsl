@@ -0,0 +1,12 @@
+// Counting sort of the live paths by what they hit, so that paths shading the same material run
+// next to each other
+#include "frame.wgsl"
+#include "paths.wgsl"
+
+fn sort_key(index: u32) -> u32 {
+    let hit = hits[index];
+    if (hit.hit == 0u) {
+        return SORT_BUCKETS - 1u;
+    }
+    return hit.material % (SORT_BUCKETS - 1u);
+}
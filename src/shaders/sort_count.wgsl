@@ -0,0 +1,13 @@
+// Sizes of the buckets of the sort
+#include "sort.wgsl"
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>, @builtin(local_invocation_index) local_invocation_index: u32) {
+    let index = live_path(live_slot(workgroup_id, local_invocation_index));
+    if (index == NO_PATH) {
+        return;
+    }
+
+    atomicAdd(&live.buckets[sort_key(index)], 1u);
+}
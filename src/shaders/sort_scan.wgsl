@@ -0,0 +1,12 @@
+// Turns the sizes of the buckets into where they start in the other list
+#include "sort.wgsl"
+
+@compute
+@workgroup_size(1)
+fn main() {
+    var offset = 0u;
+    for (var bucket = 0u; bucket < SORT_BUCKETS; bucket += 1u) {
+        offset += atomicExchange(&live.buckets[bucket], offset);
+    }
+    atomicStore(&live.counts[1u - live.current], offset);
+}
@@ -0,0 +1,85 @@
+// Spectral rendering with hero wavelength sampling, see "Hero Wavelength Spectral Sampling"
+// (Wilkie et al. 2014). With SPECTRAL defined the three channels of the colors of a path carry the
+// values of spectra at three wavelengths spread over the visible range instead of red, green and
+// blue, converted back to RGB when the path is accumulated. Colors of the scene are turned into
+// spectra where they're used, to the same RGB again for every material, light and the sky
+#include "random.wgsl"
+
+let WAVELENGTH_MIN: f32 = 380.0;
+let WAVELENGTH_MAX: f32 = 780.0;
+
+// Wavelengths in nanometers the channels of the path being shaded carry
+var<private> wavelengths: vec3<f32>;
+// Whether only the first of them is left, after refracting through dispersive glass
+var<private> hero_only: bool;
+
+fn sample_hero_wavelength() -> f32 {
+    return mix(WAVELENGTH_MIN, WAVELENGTH_MAX, rand_f32());
+}
+
+// `hero` and two more wavelengths a third of the range apart from each other, wrapping around
+fn hero_wavelengths(hero: f32) -> vec3<f32> {
+    let range = WAVELENGTH_MAX - WAVELENGTH_MIN;
+    let offsets = vec3<f32>(0.0, range / 3.0, 2.0 * range / 3.0);
+    return WAVELENGTH_MIN + (hero - WAVELENGTH_MIN + offsets) % range;
+}
+
+// Wavelengths in nanometers the channels of colors stand for
+fn channel_wavelengths() -> vec3<f32> {
+#ifdef SPECTRAL
+    return wavelengths;
+#else
+    return vec3<f32>(650.0, 510.0, 475.0);
+#endif
+}
+
+// Values at `wavelengths` of a spectrum converting back to `rgb`, constant over the blue, green
+// and red bands of the visible range so that white stays a constant spectrum
+fn spectrum(rgb: vec3<f32>) -> vec3<f32> {
+#ifdef SPECTRAL
+    let red = max(dot(vec3<f32>(1.0132, -0.0046, -0.0086), rgb), 0.0);
+    let green = max(dot(vec3<f32>(0.0153, 0.9458, 0.0390), rgb), 0.0);
+    let blue = max(dot(vec3<f32>(0.0238, 0.0456, 0.9305), rgb), 0.0);
+    return select(select(vec3<f32>(blue), vec3<f32>(green), wavelengths >= vec3<f32>(490.0)), vec3<f32>(red), wavelengths >= vec3<f32>(590.0));
+#else
+    return rgb;
+#endif
+}
+
+// Index of refraction at `wavelength` of glass with index `ior` at the Fraunhofer d line, from the
+// Cauchy equation fit to its Abbe number
+fn dispersive_ior(ior: f32, abbe_number: f32, wavelength: f32) -> f32 {
+    let micrometers = wavelength * 1.0e-3;
+    let b = (ior - 1.0) / (abbe_number * (1.0 / (0.4861 * 0.4861) - 1.0 / (0.6563 * 0.6563)));
+    return ior + b * (1.0 / (micrometers * micrometers) - 1.0 / (0.5893 * 0.5893));
+}
+
+// Lobe of the CIE matching functions, a Gaussian that's `below` wide before `peak` and `above` after
+fn cie_lobe(wavelength: f32, peak: f32, below: f32, above: f32) -> f32 {
+    let t = (wavelength - peak) / select(above, below, wavelength < peak);
+    return exp(-0.5 * t * t);
+}
+
+// CIE 1931 matching functions, after "Simple Analytic Approximations to the CIE XYZ Color
+// Matching Functions" (Wyman et al. 2013)
+fn cie_xyz(wavelength: f32) -> vec3<f32> {
+    return vec3<f32>(
+        1.056 * cie_lobe(wavelength, 599.8, 37.9, 31.0) + 0.362 * cie_lobe(wavelength, 442.0, 16.0, 26.7) - 0.065 * cie_lobe(wavelength, 501.1, 20.4, 26.2),
+        0.821 * cie_lobe(wavelength, 568.8, 46.9, 40.5) + 0.286 * cie_lobe(wavelength, 530.9, 16.3, 31.1),
+        1.217 * cie_lobe(wavelength, 437.0, 11.8, 36.0) + 0.681 * cie_lobe(wavelength, 459.0, 26.0, 13.8),
+    );
+}
+
+// Linear sRGB of a spectrum sampled at `samples`, estimating its integral against the matching
+// functions and scaled for a constant spectrum of one to be white
+fn spectrum_to_rgb(values: vec3<f32>, samples: vec3<f32>) -> vec3<f32> {
+    let xyz = cie_xyz(samples.x) * values.x + cie_xyz(samples.y) * values.y + cie_xyz(samples.z) * values.z;
+    let xyz_to_rgb = mat3x3<f32>(
+        vec3<f32>(3.2406, -0.9689, 0.0557),
+        vec3<f32>(-1.5372, 1.8758, -0.2040),
+        vec3<f32>(-0.4986, 0.0415, 1.0570),
+    );
+    // Integrals of the matching functions over the range, in sRGB
+    let white = vec3<f32>(128.3627, 101.5486, 97.0496);
+    return xyz_to_rgb * xyz * ((WAVELENGTH_MAX - WAVELENGTH_MIN) / 3.0) / white;
+}
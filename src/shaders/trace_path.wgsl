@@ -0,0 +1,33 @@
+// Closest hit of the ray of a live path
+//
+// Always traverses the BVH in software: wgpu 0.14 exposes neither acceleration structures nor
+// ray queries, so there's no hardware path to take where the backend would have one
+#include "frame.wgsl"
+#include "paths.wgsl"
+#include "ray_intersect.wgsl"
+
+fn store_hit(index: u32, rec: HitRecord) {
+    hits[index] = Hit(rec.hit_point, rec.material, rec.normal, select(0u, 1u, rec.front_face), rec.distance, 1u | (rec.flags << 1u), rec.uv, rec.object, rec.texel_scale, encode_direction(rec.tangent), pack4x8unorm(vec4<f32>(sqrt(clamp(rec.color, vec3<f32>(0.0), vec3<f32>(1.0))), 1.0)));
+}
+
+// OBJECT_HIDDEN flag of the objects the ray of `path` passes through, by how it got there
+fn path_hidden_flags(path: PathState) -> u32 {
+    if (path.bounce == 0u) {
+        return OBJECT_HIDDEN_CAMERA;
+    }
+    if (path.event == EVENT_METALLIC || path.event == EVENT_REFLECTION || path.event == EVENT_TRANSMISSION) {
+        return OBJECT_HIDDEN_REFLECTION;
+    }
+    return OBJECT_HIDDEN_INDIRECT;
+}
+
+fn trace_path(index: u32) {
+    let path = paths[index];
+    hidden_flags = path_hidden_flags(path);
+    var rec: HitRecord;
+    if (hit_world(path.ray, frame.ray_epsilon, T_MAX, &rec)) {
+        store_hit(index, rec);
+    } else {
+        hits[index].hit = 0u;
+    }
+}
@@ -0,0 +1,17 @@
+// Closest hits of the rays of the live paths, with a fixed number of invocations each taking the
+// next path off the list until it's empty, so that none idle while others traverse expensive
+// parts of the BVH
+#include "trace_path.wgsl"
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main() {
+    loop {
+        let index = live_path(atomicAdd(&live.next_slot, 1u));
+        if (index == NO_PATH) {
+            break;
+        }
+
+        trace_path(index);
+    }
+}
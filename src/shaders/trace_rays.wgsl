@@ -0,0 +1,51 @@
+// Closest hits of rays handed in by the application rather than the paths of a render, an
+// invocation per ray
+#include "frame.wgsl"
+#include "ray_intersect.wgsl"
+
+// Mirrors `TraceRay` in the crate, hits closer than `t_min` or farther than `t_max` are ignored
+struct TraceRay {
+    origin: vec3<f32>,
+    t_min: f32,
+    direction: vec3<f32>,
+    t_max: f32,
+}
+
+// Mirrors `RayHit` in the crate, `object` is NO_RAY_HIT for misses and the rest left as it was
+struct RayHit {
+    position: vec3<f32>,
+    distance: f32,
+    normal: vec3<f32>,
+    front_face: u32,
+    uv: vec2<f32>,
+    object: u32,
+    material: u32,
+}
+
+let NO_RAY_HIT: u32 = 0xffffffffu;
+
+// In place of the frame bind group, a hit for every ray
+@group(0) @binding(3)
+var<storage, read> trace_rays: array<TraceRay>;
+
+@group(0) @binding(4)
+var<storage, read_write> ray_hits: array<RayHit>;
+
+@compute
+@workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE)
+fn main(@builtin(workgroup_id) workgroup_id: vec3<u32>, @builtin(num_workgroups) num_workgroups: vec3<u32>, @builtin(local_invocation_index) local_invocation_index: u32) {
+    let index = (workgroup_id.y * num_workgroups.x + workgroup_id.x) * WORKGROUP_INVOCATIONS + local_invocation_index;
+    if (index >= arrayLength(&trace_rays)) {
+        return;
+    }
+
+    let ray = trace_rays[index];
+    // Every object is seen, whatever its ray visibility.
+    hidden_flags = 0u;
+    var rec: HitRecord;
+    if (hit_world(Ray(ray.origin, ray.direction), ray.t_min, ray.t_max, &rec)) {
+        ray_hits[index] = RayHit(rec.hit_point, rec.distance, rec.normal, select(0u, 1u, rec.front_face), rec.uv, rec.object, rec.material);
+    } else {
+        ray_hits[index].object = NO_RAY_HIT;
+    }
+}
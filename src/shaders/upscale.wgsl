@@ -0,0 +1,116 @@
+// Spatial upscaling of images rendered smaller than the surface, after AMD FidelityFX Super
+// Resolution 1.0: edge adaptive Lanczos upsampling (EASU) to the size of the surface, then
+// robust contrast adaptive sharpening (RCAS) of that onto the surface
+
+struct UpscaleUniforms {
+    output_wh: vec2<f32>,
+    // Stops below the strongest sharpening, zero sharpens the most
+    sharpness: f32,
+}
+
+@group(0) @binding(0)
+var image: texture_2d<f32>;
+
+@group(0) @binding(1)
+var<uniform> upscale: UpscaleUniforms;
+
+// Fullscreen triangle covering the surface
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Pixel of the image, repeating the ones on its edges outside of it
+fn load(pixel: vec2<i32>) -> vec3<f32> {
+    let size = textureDimensions(image);
+    return textureLoad(image, clamp(pixel, vec2<i32>(0, 0), size - 1), 0).rgb;
+}
+
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// Lanczos 2 approximated by polynomials like EASU does, at squared distance `d2`. `lobe` goes
+// from 1/4 for the plain kernel up to 1/2 for a sharper one with a narrower window.
+fn lanczos2(d2: f32, lobe: f32) -> f32 {
+    let x2 = min(d2, 1.0 / lobe);
+    let base = 25.0 / 16.0 * (0.4 * x2 - 1.0) * (0.4 * x2 - 1.0) - (25.0 / 16.0 - 1.0);
+    let window = (lobe * x2 - 1.0) * (lobe * x2 - 1.0);
+    return base * window;
+}
+
+// Upsamples the 4 by 4 pixels of the image around the fragment with a kernel stretched along
+// the edges there, clamped to the nearest 2 by 2 to keep it from ringing
+@fragment
+fn fs_easu(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let input_wh = vec2<f32>(textureDimensions(image));
+    // Centers of the pixels of the image at whole numbers
+    let p = position.xy * input_wh / upscale.output_wh - 0.5;
+    let base = vec2<i32>(floor(p));
+    let f = p - floor(p);
+
+    // Gradient of the luma between the nearest 2 by 2 pixels
+    let l00 = luma(load(base));
+    let l10 = luma(load(base + vec2<i32>(1, 0)));
+    let l01 = luma(load(base + vec2<i32>(0, 1)));
+    let l11 = luma(load(base + vec2<i32>(1, 1)));
+    let gradient = vec2<f32>(mix(l10 - l00, l11 - l01, f.y), mix(l01 - l00, l11 - l10, f.x));
+    let length2 = dot(gradient, gradient);
+    var direction = vec2<f32>(1.0, 0.0);
+    if (length2 > 1.0e-8) {
+        direction = gradient * inverseSqrt(length2);
+    }
+    // How clear the edge is, against the contrast between the pixels
+    let range = max(max(l00, l10), max(l01, l11)) - min(min(l00, l10), min(l01, l11));
+    let edge = clamp(sqrt(length2) / max(range, 1.0e-4), 0.0, 1.0);
+    // Diagonal edges stretch the kernel further, as the pixels along them are further apart
+    let stretch = 1.0 / max(abs(direction.x), abs(direction.y));
+    let along = 1.0 / (1.0 + (stretch - 1.0) * edge);
+    let lobe = 0.25 + 0.25 * edge;
+
+    var sum = vec3<f32>(0.0, 0.0, 0.0);
+    var weights = 0.0;
+    for (var y = -1; y <= 2; y += 1) {
+        for (var x = -1; x <= 2; x += 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) - f;
+            // Across the edge the kernel stays sharp, along it it's widened
+            let rotated = vec2<f32>(dot(offset, direction), dot(offset, vec2<f32>(-direction.y, direction.x)) * along);
+            let weight = lanczos2(dot(rotated, rotated), lobe);
+            sum += weight * load(base + vec2<i32>(x, y));
+            weights += weight;
+        }
+    }
+
+    let c00 = load(base);
+    let c10 = load(base + vec2<i32>(1, 0));
+    let c01 = load(base + vec2<i32>(0, 1));
+    let c11 = load(base + vec2<i32>(1, 1));
+    let lowest = min(min(c00, c10), min(c01, c11));
+    let highest = max(max(c00, c10), max(c01, c11));
+    return vec4<f32>(clamp(sum / max(weights, 1.0e-4), lowest, highest), 1.0);
+}
+
+// Sharpens the pixel under the fragment against its four neighbours, as much as they allow
+// without leaving the range of the image
+@fragment
+fn fs_rcas(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    let center = load(pixel);
+    let north = load(pixel + vec2<i32>(0, -1));
+    let south = load(pixel + vec2<i32>(0, 1));
+    let west = load(pixel + vec2<i32>(-1, 0));
+    let east = load(pixel + vec2<i32>(1, 0));
+
+    let lowest = min(min(min(north, south), min(west, east)), center);
+    let highest = max(max(max(north, south), max(west, east)), center);
+    // Most negative weight of the neighbours that keeps every channel within [0, 1]
+    let hit_min = lowest / max(4.0 * highest, vec3<f32>(1.0e-4));
+    let hit_max = (1.0 - highest) / min(4.0 * lowest - 4.0, vec3<f32>(-1.0e-4));
+    let lobes = max(-hit_min, hit_max);
+    let limit = 0.25 - 1.0 / 16.0;
+    let lobe = max(-limit, min(max(max(lobes.r, lobes.g), lobes.b), 0.0)) * exp2(-upscale.sharpness);
+
+    let color = (lobe * (north + south + west + east) + center) / (4.0 * lobe + 1.0);
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,59 @@
+//! Position of the sun in the sky, for lighting scenes by a place on Earth and a time of day.
+
+/// Unit vector towards the sun from the point `latitude` degrees north and `longitude` degrees
+/// east on `year`-`month`-`day` at `utc_hours` UTC, with y up, x pointing east and -z pointing
+/// north. Below the horizon for night times.
+///
+/// Uses the fractional year approximation of the NOAA general solar position calculations, good
+/// to a few hundredths of a degree, which is far below what shading shows.
+pub fn direction(
+    latitude: f32,
+    longitude: f32,
+    year: i32,
+    month: u32,
+    day: u32,
+    utc_hours: f32,
+) -> [f32; 3] {
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let days_in_year = if leap { 366.0 } else { 365.0 };
+    let day_of_year = day_of_year(month, day, leap) as f32;
+
+    // Angle of the orbit of the Earth over the year, in radians.
+    let gamma =
+        2.0 * std::f32::consts::PI / days_in_year * (day_of_year - 1.0 + (utc_hours - 12.0) / 24.0);
+    let harmonics = |coefficients: [f32; 7]| {
+        let [c0, c1, s1, c2, s2, c3, s3] = coefficients;
+        c0 + c1 * gamma.cos()
+            + s1 * gamma.sin()
+            + c2 * (2.0 * gamma).cos()
+            + s2 * (2.0 * gamma).sin()
+            + c3 * (3.0 * gamma).cos()
+            + s3 * (3.0 * gamma).sin()
+    };
+    // In minutes and radians.
+    let equation_of_time = 229.18
+        * harmonics([
+            0.000075, 0.001868, -0.032077, -0.014615, -0.040849, 0.0, 0.0,
+        ]);
+    let declination = harmonics([
+        0.006918, -0.399912, 0.070257, -0.006758, 0.000907, -0.002697, 0.00148,
+    ]);
+
+    let solar_minutes = utc_hours * 60.0 + equation_of_time + 4.0 * longitude;
+    let hour_angle = (solar_minutes / 4.0 - 180.0).to_radians();
+    let latitude = latitude.to_radians();
+
+    let east = -declination.cos() * hour_angle.sin();
+    let north =
+        latitude.cos() * declination.sin() - latitude.sin() * declination.cos() * hour_angle.cos();
+    let up =
+        latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos();
+    [east, up, -north]
+}
+
+/// Day of the year of `month` and `day`, from 1 for the first of January.
+fn day_of_year(month: u32, day: u32, leap: bool) -> u32 {
+    const DAYS_BEFORE: [u32; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+    let month = month.clamp(1, 12);
+    DAYS_BEFORE[month as usize - 1] + day + u32::from(leap && month > 2)
+}
@@ -0,0 +1,85 @@
+//! Antialiasing by rendering at a multiple of the resolution of an image and filtering it back
+//! down, see [`render_supersampled`](crate::renderer::RaytracingRenderer::render_supersampled).
+
+/// Radius of [`mitchell`] in pixels of the downscaled image.
+const FILTER_RADIUS: f32 = 2.0;
+
+/// Scales `pixels`, tightly packed rows of RGBA values `width` by `height` of them, down
+/// `factor` times along both axes with a Mitchell-Netravali filter. `width` and `height` have
+/// to be multiples of `factor`. The filter has small negative lobes, so the results can
+/// overshoot the range of the input a little.
+pub(crate) fn downscale(pixels: &[f32], width: u32, height: u32, factor: u32) -> Vec<f32> {
+    let (width, height) = (width as usize, height as usize);
+    let (out_width, out_height) = (width / factor as usize, height / factor as usize);
+
+    // Separable, rows first.
+    let mut rows = vec![0.0; out_width * height * 4];
+    let horizontal = weights(out_width, width, factor);
+    for y in 0..height {
+        for (x, (start, weights)) in horizontal.iter().enumerate() {
+            let out = &mut rows[(y * out_width + x) * 4..][..4];
+            for (i, weight) in weights.iter().enumerate() {
+                let pixel = &pixels[(y * width + start + i) * 4..][..4];
+                for (out, value) in out.iter_mut().zip(pixel) {
+                    *out += weight * value;
+                }
+            }
+        }
+    }
+
+    let mut image = vec![0.0; out_width * out_height * 4];
+    let vertical = weights(out_height, height, factor);
+    for (y, (start, weights)) in vertical.iter().enumerate() {
+        for (i, weight) in weights.iter().enumerate() {
+            let row = &rows[(start + i) * out_width * 4..][..out_width * 4];
+            let out = &mut image[y * out_width * 4..][..out_width * 4];
+            for (out, value) in out.iter_mut().zip(row) {
+                *out += weight * value;
+            }
+        }
+    }
+    image
+}
+
+/// First input pixel and normalized weights of the input pixels of every one of `out_size`
+/// output pixels along an axis `size` input pixels long. The filter is cut off at the edges
+/// of the image.
+fn weights(out_size: usize, size: usize, factor: u32) -> Vec<(usize, Vec<f32>)> {
+    let factor = factor as f32;
+    (0..out_size)
+        .map(|out| {
+            let center = (out as f32 + 0.5) * factor;
+            let start = (center - FILTER_RADIUS * factor).floor().max(0.0) as usize;
+            let end = ((center + FILTER_RADIUS * factor).ceil() as usize).min(size);
+            let mut weights: Vec<f32> = (start..end)
+                .map(|i| mitchell((i as f32 + 0.5 - center) / factor))
+                .collect();
+            let sum: f32 = weights.iter().sum();
+            for weight in &mut weights {
+                *weight /= sum;
+            }
+            (start, weights)
+        })
+        .collect()
+}
+
+/// Mitchell-Netravali filter with B = C = 1/3 ("Reconstruction Filters in Computer Graphics",
+/// Mitchell and Netravali 1988), the balance they recommend between blurring and ringing.
+fn mitchell(x: f32) -> f32 {
+    const B: f32 = 1.0 / 3.0;
+    const C: f32 = 1.0 / 3.0;
+    let x = x.abs();
+    let value = if x < 1.0 {
+        (12.0 - 9.0 * B - 6.0 * C) * x.powi(3)
+            + (-18.0 + 12.0 * B + 6.0 * C) * x.powi(2)
+            + (6.0 - 2.0 * B)
+    } else if x < 2.0 {
+        (-B - 6.0 * C) * x.powi(3)
+            + (6.0 * B + 30.0 * C) * x.powi(2)
+            + (-12.0 * B - 48.0 * C) * x
+            + (8.0 * B + 24.0 * C)
+    } else {
+        0.0
+    };
+    value / 6.0
+}
@@ -0,0 +1,540 @@
+//! Golden image tests: canonical scenes rendered at fixed seeds and compared against reference
+//! images, so that changes to the renderer, its shaders or the scenes of downstream users that
+//! alter what comes out get caught.
+//!
+//! Renders on different adapters and drivers differ a little in floating point, so images are
+//! compared by their structural similarity (SSIM, "Image Quality Assessment: From Error
+//! Visibility to Structural Similarity", Wang et al. 2004) rather than byte for byte. Noise and
+//! slight shifts in brightness barely lower it, while moved edges, missing objects or wrong
+//! materials do.
+
+use std::fmt;
+#[cfg(feature = "native")]
+use std::{
+    error::Error,
+    io,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "native")]
+use crate::scene::SceneError;
+use crate::{
+    renderer::RaytracingRenderer,
+    scene::{Camera, Material, Object, Scene, Shape},
+    settings::RenderSettings,
+};
+
+/// Side of the windows SSIM compares the images in, in pixels.
+const WINDOW: usize = 8;
+
+/// Built-in scene rendered by golden image tests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TestScene {
+    /// [`Scene::demo`].
+    Demo,
+    /// [`Scene::cornell_box`], for global illumination, caustics and color bleeding.
+    CornellBox,
+    /// A row of spheres of different materials on a ground lit by the sky: diffuse, rough and
+    /// polished metal, glass and clear coated.
+    Materials,
+}
+
+impl TestScene {
+    pub const ALL: [Self; 3] = [Self::Demo, Self::CornellBox, Self::Materials];
+
+    /// Name of the scene, which its reference images are named after.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Demo => "demo",
+            Self::CornellBox => "cornell_box",
+            Self::Materials => "materials",
+        }
+    }
+
+    pub fn scene(self) -> Scene {
+        match self {
+            Self::Demo => Scene::demo(),
+            Self::CornellBox => Scene::cornell_box(),
+            Self::Materials => materials(),
+        }
+    }
+
+    /// Settings the scene is rendered with: a small image with enough samples for the noise not
+    /// to dominate the comparison, at seed zero.
+    pub fn settings(self) -> RenderSettings {
+        RenderSettings {
+            width: 128,
+            height: 128,
+            samples_per_pixel: 64,
+            seed: 0,
+            max_bounces: 8,
+            ..Default::default()
+        }
+    }
+
+    /// Renders the scene as tightly packed RGBA8 rows with its [settings](Self::settings),
+    /// replacing the scene of `renderer`.
+    pub async fn render(self, renderer: &mut RaytracingRenderer) -> Vec<u8> {
+        renderer
+            .set_scene(&self.scene())
+            .expect("Built-in scenes are valid");
+        renderer.render(&self.settings()).await
+    }
+}
+
+fn materials() -> Scene {
+    let material = |name: &str, material: Material| Material {
+        name: name.to_owned(),
+        ..material
+    };
+    let materials = vec![
+        material("diffuse", Material::default()),
+        material(
+            "rough metal",
+            Material {
+                base_color: [0.9, 0.6, 0.3],
+                metallic: 1.0,
+                roughness: 0.4,
+                ..Default::default()
+            },
+        ),
+        material(
+            "mirror",
+            Material {
+                base_color: [0.9, 0.9, 0.9],
+                metallic: 1.0,
+                roughness: 0.0,
+                ..Default::default()
+            },
+        ),
+        material(
+            "glass",
+            Material {
+                base_color: [1.0, 1.0, 1.0],
+                roughness: 0.0,
+                transmission: 1.0,
+                ..Default::default()
+            },
+        ),
+        material(
+            "clear coat",
+            Material {
+                base_color: [0.1, 0.2, 0.6],
+                clearcoat: 1.0,
+                ..Default::default()
+            },
+        ),
+    ];
+
+    let mut objects: Vec<Object> = materials
+        .iter()
+        .enumerate()
+        .map(|(i, material)| Object {
+            name: material.name.clone(),
+            material: Some(material.name.clone()),
+            ..Object::new(Shape::Sphere {
+                center: [i as f32 * 1.1 - 2.2, 0.0, -3.0],
+                radius: 0.5,
+            })
+        })
+        .collect();
+    objects.push(Object {
+        name: "ground".to_owned(),
+        ..Object::new(Shape::Sphere {
+            center: [0.0, -100.5, -3.0],
+            radius: 100.0,
+        })
+    });
+
+    Scene {
+        camera: Camera {
+            position: [0.0, 1.0, 1.0],
+            look_at: [0.0, 0.0, -3.0],
+            fov: 50.0,
+            ..Default::default()
+        },
+        materials,
+        objects,
+        ..Default::default()
+    }
+}
+
+/// How far an image may be from its reference for [`Comparison::passes`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tolerance {
+    /// Lowest mean SSIM of the images, one for identical ones.
+    pub min_ssim: f32,
+    /// Highest fraction of the pixels that may differ by more than `pixel_threshold` in any
+    /// channel, e.g. fireflies.
+    pub max_outliers: f32,
+    pub pixel_threshold: u8,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            min_ssim: 0.97,
+            max_outliers: 0.01,
+            pixel_threshold: 64,
+        }
+    }
+}
+
+/// How an image differs from its reference.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Comparison {
+    /// Mean SSIM of the luma of the images.
+    pub ssim: f32,
+    /// Largest difference of any channel of any pixel.
+    pub max_difference: u8,
+    /// Fraction of the pixels differing by more than the threshold of the tolerance in any
+    /// channel.
+    pub outliers: f32,
+    tolerance: Tolerance,
+}
+
+impl Comparison {
+    /// Whether the image is within the tolerance it was compared with.
+    pub fn passes(&self) -> bool {
+        self.ssim >= self.tolerance.min_ssim && self.outliers <= self.tolerance.max_outliers
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SSIM {:.4} (at least {}), {:.2}% of the pixels off by more than {} (at most {:.2}%), \
+             largest difference {}",
+            self.ssim,
+            self.tolerance.min_ssim,
+            self.outliers * 100.0,
+            self.tolerance.pixel_threshold,
+            self.tolerance.max_outliers * 100.0,
+            self.max_difference,
+        )
+    }
+}
+
+/// Compares two images of tightly packed RGBA8 rows, `width` by `height` pixels, ignoring
+/// alpha.
+///
+/// # Panics
+///
+/// If either image isn't exactly `width * height * 4` bytes long.
+pub fn compare(
+    reference: &[u8],
+    image: &[u8],
+    width: u32,
+    height: u32,
+    tolerance: Tolerance,
+) -> Comparison {
+    let (width, height) = (width as usize, height as usize);
+    assert_eq!(reference.len(), width * height * 4);
+    assert_eq!(image.len(), width * height * 4);
+
+    let mut max_difference = 0;
+    let mut outliers = 0;
+    for (a, b) in reference.chunks_exact(4).zip(image.chunks_exact(4)) {
+        let difference = (0..3).map(|c| a[c].abs_diff(b[c])).max().unwrap_or(0);
+        max_difference = max_difference.max(difference);
+        if difference > tolerance.pixel_threshold {
+            outliers += 1;
+        }
+    }
+
+    Comparison {
+        ssim: ssim(&luma(reference), &luma(image), width, height),
+        max_difference,
+        outliers: outliers as f32 / (width * height).max(1) as f32,
+        tolerance,
+    }
+}
+
+/// Rec. 709 luma of the gamma encoded channels, from 0 to 1.
+fn luma(image: &[u8]) -> Vec<f32> {
+    image
+        .chunks_exact(4)
+        .map(|p| (0.2126 * p[0] as f32 + 0.7152 * p[1] as f32 + 0.0722 * p[2] as f32) / 255.0)
+        .collect()
+}
+
+/// Mean SSIM of the windows of [`WINDOW`] pixels, overlapping by half, shrunk to the image if
+/// it's smaller.
+fn ssim(a: &[f32], b: &[f32], width: usize, height: usize) -> f32 {
+    const C1: f32 = 0.01 * 0.01;
+    const C2: f32 = 0.03 * 0.03;
+    let (window_x, window_y) = (WINDOW.min(width), WINDOW.min(height));
+    let starts = |size: usize, window: usize| {
+        (0..=size.saturating_sub(window))
+            .step_by((window / 2).max(1))
+            .collect::<Vec<_>>()
+    };
+
+    let mut sum = 0.0;
+    let mut windows = 0;
+    for &y0 in &starts(height, window_y) {
+        for &x0 in &starts(width, window_x) {
+            let pixels = || {
+                (y0..y0 + window_y)
+                    .flat_map(move |y| (x0..x0 + window_x).map(move |x| y * width + x))
+            };
+            let n = (window_x * window_y) as f32;
+            let mean_a = pixels().map(|i| a[i]).sum::<f32>() / n;
+            let mean_b = pixels().map(|i| b[i]).sum::<f32>() / n;
+            let (mut var_a, mut var_b, mut covariance) = (0.0, 0.0, 0.0);
+            for i in pixels() {
+                let (da, db) = (a[i] - mean_a, b[i] - mean_b);
+                var_a += da * da;
+                var_b += db * db;
+                covariance += da * db;
+            }
+            let (var_a, var_b, covariance) = (var_a / n, var_b / n, covariance / n);
+            sum += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            windows += 1;
+        }
+    }
+    if windows == 0 {
+        1.0
+    } else {
+        sum / windows as f32
+    }
+}
+
+/// Why [`check`] failed.
+#[cfg(feature = "native")]
+#[derive(Debug)]
+pub enum GoldenError {
+    /// The reference image doesn't exist yet, see [`bless`].
+    MissingReference(PathBuf),
+    /// The reference image has a different size than the render.
+    SizeMismatch {
+        path: PathBuf,
+        width: u32,
+        height: u32,
+    },
+    /// The render is too far from the reference, it was written to `actual` for inspection.
+    Mismatch {
+        comparison: Comparison,
+        actual: PathBuf,
+    },
+    Scene(SceneError),
+    Image(image::ImageError),
+    Io(io::Error),
+}
+
+#[cfg(feature = "native")]
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingReference(path) => {
+                write!(f, "{}: reference image not found", path.display())
+            }
+            Self::SizeMismatch {
+                path,
+                width,
+                height,
+            } => write!(
+                f,
+                "{}: reference image is {width}x{height} pixels, the render isn't",
+                path.display()
+            ),
+            Self::Mismatch { comparison, actual } => write!(
+                f,
+                "render differs from the reference, written to {}: {comparison}",
+                actual.display()
+            ),
+            Self::Scene(err) => err.fmt(f),
+            Self::Image(err) => err.fmt(f),
+            Self::Io(err) => err.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "native")]
+impl Error for GoldenError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Scene(err) => Some(err),
+            Self::Image(err) => Some(err),
+            Self::Io(err) => Some(err),
+            Self::MissingReference(_) | Self::SizeMismatch { .. } | Self::Mismatch { .. } => None,
+        }
+    }
+}
+
+#[cfg(feature = "native")]
+impl From<SceneError> for GoldenError {
+    fn from(err: SceneError) -> Self {
+        Self::Scene(err)
+    }
+}
+
+#[cfg(feature = "native")]
+impl From<image::ImageError> for GoldenError {
+    fn from(err: image::ImageError) -> Self {
+        Self::Image(err)
+    }
+}
+
+#[cfg(feature = "native")]
+impl From<io::Error> for GoldenError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Renders `scene` with `settings` and compares it with the reference PNG at `reference`,
+/// replacing the scene of `renderer`. On failure the render is written next to the reference
+/// with `.actual` before the extension.
+#[cfg(feature = "native")]
+pub async fn check(
+    renderer: &mut RaytracingRenderer,
+    scene: &Scene,
+    settings: &RenderSettings,
+    reference: impl AsRef<Path>,
+    tolerance: Tolerance,
+) -> Result<Comparison, GoldenError> {
+    let reference = reference.as_ref();
+    if !reference.exists() {
+        return Err(GoldenError::MissingReference(reference.to_owned()));
+    }
+    let expected = image::open(reference)?.into_rgba8();
+    if expected.dimensions() != (settings.width, settings.height) {
+        return Err(GoldenError::SizeMismatch {
+            path: reference.to_owned(),
+            width: expected.width(),
+            height: expected.height(),
+        });
+    }
+
+    renderer.set_scene(scene)?;
+    let image = renderer.render(settings).await;
+    let comparison = compare(
+        expected.as_raw(),
+        &image,
+        settings.width,
+        settings.height,
+        tolerance,
+    );
+    tracing::info!(reference = %reference.display(), %comparison, "Compared with reference");
+    if comparison.passes() {
+        return Ok(comparison);
+    }
+
+    let actual = reference.with_extension("actual.png");
+    save(&actual, &image, settings)?;
+    Err(GoldenError::Mismatch { comparison, actual })
+}
+
+/// [`check`] of a built-in scene against `<name>.png` in `dir`.
+#[cfg(feature = "native")]
+pub async fn check_builtin(
+    renderer: &mut RaytracingRenderer,
+    scene: TestScene,
+    dir: impl AsRef<Path>,
+    tolerance: Tolerance,
+) -> Result<Comparison, GoldenError> {
+    let reference = dir.as_ref().join(format!("{}.png", scene.name()));
+    check(
+        renderer,
+        &scene.scene(),
+        &scene.settings(),
+        reference,
+        tolerance,
+    )
+    .await
+}
+
+/// Renders `scene` with `settings` as the new reference PNG at `reference`, after an intended
+/// change of what it looks like.
+#[cfg(feature = "native")]
+pub async fn bless(
+    renderer: &mut RaytracingRenderer,
+    scene: &Scene,
+    settings: &RenderSettings,
+    reference: impl AsRef<Path>,
+) -> Result<(), GoldenError> {
+    renderer.set_scene(scene)?;
+    let image = renderer.render(settings).await;
+    save(reference.as_ref(), &image, settings)
+}
+
+#[cfg(feature = "native")]
+fn save(path: &Path, image: &[u8], settings: &RenderSettings) -> Result<(), GoldenError> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    image::save_buffer(
+        path,
+        image,
+        settings.width,
+        settings.height,
+        image::ColorType::Rgba8,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Horizontal gradient of `width` by `height` opaque pixels.
+    fn gradient(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let value = (i % width * 255 / (width - 1)) as u8;
+                [value, value, value, 255]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn identical_images_pass() {
+        let image = gradient(16, 16);
+        let comparison = compare(&image, &image, 16, 16, Tolerance::default());
+        assert_eq!(comparison.ssim, 1.0);
+        assert_eq!(comparison.max_difference, 0);
+        assert_eq!(comparison.outliers, 0.0);
+        assert!(comparison.passes());
+    }
+
+    #[test]
+    fn inverted_images_fail() {
+        let reference = gradient(16, 16);
+        let inverted: Vec<_> = reference
+            .chunks_exact(4)
+            .flat_map(|p| [255 - p[0], 255 - p[1], 255 - p[2], p[3]])
+            .collect();
+        let comparison = compare(&reference, &inverted, 16, 16, Tolerance::default());
+        assert!(comparison.ssim < 0.0, "{comparison}");
+        assert_eq!(comparison.max_difference, 255);
+        assert!(!comparison.passes());
+    }
+
+    #[test]
+    fn counts_outliers_above_the_threshold() {
+        let reference = gradient(10, 10);
+        let mut image = reference.clone();
+        // One firefly and one pixel just within the threshold, alpha is ignored.
+        image[0] = 255;
+        image[4 * 50 + 1] = image[4 * 50 + 1].saturating_add(64);
+        image[4 * 99 + 3] = 0;
+        let tolerance = Tolerance {
+            min_ssim: 0.0,
+            max_outliers: 0.01,
+            pixel_threshold: 64,
+        };
+        let comparison = compare(&reference, &image, 10, 10, tolerance);
+        assert_eq!(comparison.outliers, 0.01);
+        assert_eq!(comparison.max_difference, 255);
+        assert!(comparison.passes());
+
+        let strict = Tolerance {
+            max_outliers: 0.0,
+            ..tolerance
+        };
+        assert!(!compare(&reference, &image, 10, 10, strict).passes());
+    }
+}
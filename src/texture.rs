@@ -0,0 +1,879 @@
+//! Textures of a scene's materials, streamed through a cache of their pages, the density grid
+//! of its fog, the profiles of its lights and the heights of its terrains.
+
+use std::{
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use wgpu::{
+    AddressMode, Device, Extent3d, FilterMode, ImageCopyTexture, ImageDataLayout, Origin3d, Queue,
+    Sampler, SamplerDescriptor, Texture, TextureAspect, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension,
+};
+use zerocopy::AsBytes;
+
+use crate::{
+    ies::IesProfile,
+    scene::{ColorSpace, DensityGrid, SceneError},
+};
+
+/// Horizontal angles of the baked light profiles, every 2.5 degrees.
+pub(crate) const PROFILE_WIDTH: u32 = 144;
+/// Vertical angles of the baked light profiles, every degree from 0 to 180.
+pub(crate) const PROFILE_HEIGHT: u32 = 181;
+
+/// Texels on a side of the pages textures are streamed into the [`TextureCache`] in.
+const PAGE_SIZE: u32 = 128;
+/// Texels of the neighbouring pages repeated around every page in the cache, enough for
+/// bilinear filtering not to reach past them.
+const PAGE_BORDER: u32 = 1;
+/// Side of the slots of the cache, which hold a page and its border.
+const SLOT_SIZE: u32 = PAGE_SIZE + 2 * PAGE_BORDER;
+/// Entries of a row of the page table.
+const PAGE_TABLE_WIDTH: u32 = 1024;
+/// Page table entry of pages that aren't in the cache.
+const NOT_RESIDENT: u32 = u32::MAX;
+/// Pages streamed in by a single [update](TextureCache::stream) at most, keeping the uploads
+/// between two batches of samples short.
+const MAX_UPLOADS: usize = 256;
+/// Bytes of GPU memory a slot of the cache takes.
+const SLOT_BYTES: u64 = SLOT_SIZE as u64 * SLOT_SIZE as u64 * 4;
+
+/// Largest side of textures, bigger ones are scaled down to it.
+pub(crate) const MAX_TEXTURE_SIZE: u32 = 8192;
+
+/// Textures uploaded to the GPU, as pages in the slots of a single cache texture that's looked
+/// up through a page table, so any number of materials can be shaded by the same bind group.
+///
+/// Textures with more pages than the cache has slots are streamed: shading writes the pages it
+/// would have wanted into a feedback texture, whose requests are [streamed](Self::stream) in
+/// over the least recently requested pages in between batches of samples. Until then lookups
+/// fall back to the finest coarser mip level in the cache, the coarsest one of every texture
+/// always is.
+pub(crate) struct TextureCache {
+    texture: Texture,
+    pub view: TextureView,
+    page_table: Texture,
+    pub page_table_view: TextureView,
+    pub sampler: Sampler,
+    /// Slots in a row of the cache texture.
+    slots_across: u32,
+    /// Bytes of GPU memory the cache and its page table take.
+    pub size: u64,
+    /// Pages that didn't all fit in the cache, if some didn't.
+    streamed: Option<Mutex<StreamedPages>>,
+}
+
+/// Texture kept on the host to stream its pages from.
+struct StreamedTexture {
+    /// Side in texels, a power of two.
+    size: u32,
+    /// RGBA8 sRGB texels of the mip levels down to the first one fitting in a page.
+    levels: Vec<Vec<u8>>,
+    /// Page table entry of the first page of its first level.
+    first_entry: u32,
+}
+
+struct StreamedPages {
+    textures: Vec<StreamedTexture>,
+    /// What the page table holds, padded to whole rows.
+    table: Vec<u32>,
+    /// Page table entry of the page in every slot, if there's one.
+    slots: Vec<Option<u32>>,
+    /// Update every slot was last requested by, `u64::MAX` for the coarsest levels that are
+    /// never evicted.
+    last_requested: Vec<u64>,
+    updates: u64,
+}
+
+/// Mip levels of a texture `size` texels a side kept in the cache, down to the first one
+/// fitting in a page. Smaller ones would only be sampled by pixels seeing all of it.
+fn level_count(size: u32) -> u32 {
+    (size / PAGE_SIZE).max(1).trailing_zeros() + 1
+}
+
+/// Pages across `level` of a texture `size` texels a side.
+fn pages_across(size: u32, level: u32) -> u32 {
+    ((size >> level) / PAGE_SIZE).max(1)
+}
+
+impl TextureCache {
+    /// Loads the images at `paths` encoded in their color spaces as textures in that order, each
+    /// scaled to a square power of two up to [`MAX_TEXTURE_SIZE`] texels a side with a chain of
+    /// mipmaps. The cache has room for all of their pages if the device allows, and `budget`
+    /// bytes of them if there's one. Without any textures it only has an unused white slot,
+    /// since bindings can't be empty.
+    pub fn load(
+        device: &Device,
+        queue: &Queue,
+        paths: &[(PathBuf, ColorSpace)],
+        budget: Option<u64>,
+    ) -> Result<Self, SceneError> {
+        let _span = tracing::info_span!("load_textures", textures = paths.len()).entered();
+
+        let mut textures = Vec::with_capacity(paths.len());
+        let mut first_entry = paths.len() as u32;
+        for (path, space) in paths {
+            let image = decode(path)?;
+            let size = image
+                .width
+                .max(image.height)
+                .next_power_of_two()
+                .min(MAX_TEXTURE_SIZE);
+            let mut levels = vec![image.resized(size)];
+            for level in 1..level_count(size) {
+                let finer = &levels[level as usize - 1];
+                levels.push(downsample(finer, size >> (level - 1), *space));
+            }
+            // The cache decodes every texture from sRGB when it's sampled.
+            if *space != ColorSpace::Srgb {
+                for texel in levels.iter_mut().flatten() {
+                    *texel = linear_to_srgb(*texel as f32 / 255.0);
+                }
+            }
+            textures.push(StreamedTexture {
+                size,
+                levels,
+                first_entry,
+            });
+            first_entry += (0..level_count(size))
+                .map(|level| pages_across(size, level).pow(2))
+                .sum::<u32>();
+        }
+        let page_count = first_entry - paths.len() as u32;
+
+        let max_size = device.limits().max_texture_dimension_2d;
+        let table_rows = first_entry.max(1).div_ceil(PAGE_TABLE_WIDTH);
+        if table_rows > max_size {
+            return Err(SceneError::TooLarge {
+                what: "The page table of the scene's textures",
+                size: first_entry as u64 * 4,
+                limit: max_size as u64 * PAGE_TABLE_WIDTH as u64 * 4,
+            });
+        }
+        let max_slots = (max_size / SLOT_SIZE).pow(2);
+        let mut slot_count = page_count.min(max_slots);
+        if let Some(budget) = budget {
+            slot_count = slot_count.min((budget / SLOT_BYTES).min(u32::MAX as u64) as u32);
+        }
+        if slot_count < textures.len() as u32 {
+            return Err(SceneError::TooLarge {
+                what: "The coarsest mip levels of the scene's textures",
+                size: textures.len() as u64 * SLOT_BYTES,
+                limit: budget.unwrap_or(max_slots as u64 * SLOT_BYTES),
+            });
+        }
+        if slot_count < page_count {
+            tracing::info!(
+                pages = page_count,
+                slots = slot_count,
+                "Streaming scene textures through a cache smaller than them"
+            );
+        }
+
+        let slot_count = slot_count.max(1);
+        let slots_across = slot_count.min(max_size / SLOT_SIZE);
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("Scene texture cache"),
+            size: Extent3d {
+                width: slots_across * SLOT_SIZE,
+                height: slot_count.div_ceil(slots_across) * SLOT_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8UnormSrgb,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        });
+        let page_table = device.create_texture(&TextureDescriptor {
+            label: Some("Scene texture page table"),
+            size: Extent3d {
+                width: PAGE_TABLE_WIDTH,
+                height: table_rows,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::R32Uint,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        });
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Scene texture sampler"),
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::Repeat,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        // Textures are described by their first entry and the log2 of their size.
+        let mut table = vec![NOT_RESIDENT; (table_rows * PAGE_TABLE_WIDTH) as usize];
+        for (index, texture) in textures.iter().enumerate() {
+            table[index] = texture.first_entry << 8 | texture.size.trailing_zeros();
+        }
+        let mut pages = StreamedPages {
+            textures,
+            table,
+            slots: vec![None; slot_count as usize],
+            last_requested: vec![0; slot_count as usize],
+            updates: 0,
+        };
+        let mut cache = Self {
+            view: texture.create_view(&TextureViewDescriptor::default()),
+            texture,
+            page_table_view: page_table.create_view(&TextureViewDescriptor::default()),
+            page_table,
+            sampler,
+            slots_across,
+            size: slots_across as u64 * slot_count.div_ceil(slots_across) as u64 * SLOT_BYTES
+                + table_rows as u64 * PAGE_TABLE_WIDTH as u64 * 4,
+            streamed: None,
+        };
+
+        if pages.textures.is_empty() {
+            let white = vec![255; SLOT_BYTES as usize];
+            cache.write_slot(queue, 0, &white);
+        }
+        // Fills the cache from the coarsest levels of all the textures up, pinning those.
+        let mut slot = 0;
+        let deepest = pages.textures.iter().map(|t| level_count(t.size)).max();
+        'fill: for depth in 0..deepest.unwrap_or(0) {
+            for texture in 0..pages.textures.len() {
+                let levels = level_count(pages.textures[texture].size);
+                let Some(level) = (levels - 1).checked_sub(depth) else {
+                    continue;
+                };
+                let pages_across = pages_across(pages.textures[texture].size, level);
+                for page in 0..pages_across * pages_across {
+                    if slot == slot_count {
+                        break 'fill;
+                    }
+                    let entry =
+                        pages.entry(texture, level, page % pages_across, page / pages_across);
+                    cache.upload(queue, &mut pages, entry, slot);
+                    if depth == 0 {
+                        pages.last_requested[slot as usize] = u64::MAX;
+                    }
+                    slot += 1;
+                }
+            }
+        }
+        cache.write_table(queue, &pages.table);
+
+        // The textures are only kept around if they have more to stream in.
+        if slot_count < page_count {
+            cache.streamed = Some(Mutex::new(pages));
+        }
+        Ok(cache)
+    }
+
+    /// Whether the textures have more pages than the cache has slots, so that they're streamed.
+    pub fn streams(&self) -> bool {
+        self.streamed.is_some()
+    }
+
+    /// Streams in the pages of the page table entries `requests` missing from the cache, coarse
+    /// levels first, over the ones requested the longest ago.
+    ///
+    /// Only the pages of a single update are evicted, so if there's more than it frees or than
+    /// [`MAX_UPLOADS`] the rest has to be requested again.
+    pub fn stream(&self, queue: &Queue, mut requests: Vec<u32>) {
+        let Some(streamed) = &self.streamed else {
+            return;
+        };
+        let mut pages = streamed.lock().unwrap();
+        pages.updates += 1;
+
+        requests.sort_unstable();
+        requests.dedup();
+        let mut missing = Vec::new();
+        for entry in requests {
+            match pages.table.get(entry as usize) {
+                Some(&NOT_RESIDENT) => {
+                    if let Some((texture, level, ..)) = pages.locate(entry) {
+                        let depth = level_count(pages.textures[texture].size) - level;
+                        missing.push((depth, entry));
+                    }
+                }
+                Some(&slot) if entry >= pages.textures.len() as u32 => {
+                    let updates = pages.updates;
+                    let last_requested = &mut pages.last_requested[slot as usize];
+                    *last_requested = (*last_requested).max(updates);
+                }
+                _ => {}
+            }
+        }
+        if missing.is_empty() {
+            return;
+        }
+
+        missing.sort_unstable();
+        for &(_, entry) in missing.iter().take(MAX_UPLOADS) {
+            // Slots that were never used have been requested the longest ago.
+            let Some((slot, _)) = pages
+                .last_requested
+                .iter()
+                .enumerate()
+                .filter(|&(_, &last_requested)| last_requested < pages.updates)
+                .min_by_key(|&(_, &last_requested)| last_requested)
+            else {
+                break;
+            };
+            if let Some(evicted) = pages.slots[slot] {
+                pages.table[evicted as usize] = NOT_RESIDENT;
+            }
+            self.upload(queue, &mut pages, entry, slot as u32);
+            pages.last_requested[slot] = pages.updates;
+        }
+        self.write_table(queue, &pages.table);
+    }
+
+    /// Writes the page of page table entry `entry` into `slot`.
+    fn upload(&self, queue: &Queue, pages: &mut StreamedPages, entry: u32, slot: u32) {
+        let Some((texture, level, x, y)) = pages.locate(entry) else {
+            return;
+        };
+        let texture = &pages.textures[texture];
+        let texels = page_texels(&texture.levels[level as usize], texture.size >> level, x, y);
+        self.write_slot(queue, slot, &texels);
+        pages.table[entry as usize] = slot;
+        pages.slots[slot as usize] = Some(entry);
+    }
+
+    fn write_slot(&self, queue: &Queue, slot: u32, texels: &[u8]) {
+        queue.write_texture(
+            ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: Origin3d {
+                    x: slot % self.slots_across * SLOT_SIZE,
+                    y: slot / self.slots_across * SLOT_SIZE,
+                    z: 0,
+                },
+                aspect: TextureAspect::All,
+            },
+            texels,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(SLOT_SIZE * 4),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: SLOT_SIZE,
+                height: SLOT_SIZE,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    fn write_table(&self, queue: &Queue, table: &[u32]) {
+        queue.write_texture(
+            self.page_table.as_image_copy(),
+            table.as_bytes(),
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(PAGE_TABLE_WIDTH * 4),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: PAGE_TABLE_WIDTH,
+                height: table.len() as u32 / PAGE_TABLE_WIDTH,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+impl StreamedPages {
+    /// Page table entry of page (`x`, `y`) of `level` of `texture`.
+    fn entry(&self, texture: usize, level: u32, x: u32, y: u32) -> u32 {
+        let size = self.textures[texture].size;
+        let level_start: u32 = (0..level).map(|l| pages_across(size, l).pow(2)).sum();
+        self.textures[texture].first_entry + level_start + y * pages_across(size, level) + x
+    }
+
+    /// Texture, level and page of page table entry `entry`, if it's one of a page.
+    fn locate(&self, entry: u32) -> Option<(usize, u32, u32, u32)> {
+        let texture = self
+            .textures
+            .partition_point(|texture| texture.first_entry <= entry)
+            .checked_sub(1)?;
+        let size = self.textures[texture].size;
+        let mut offset = entry - self.textures[texture].first_entry;
+        for level in 0..level_count(size) {
+            let pages = pages_across(size, level);
+            if offset < pages * pages {
+                return Some((texture, level, offset % pages, offset / pages));
+            }
+            offset -= pages * pages;
+        }
+        None
+    }
+}
+
+/// Texels of the slot of page (`x`, `y`) of a mip level `size` texels a side, its border
+/// wrapping around the level like the sampler repeats textures. Levels smaller than a page
+/// repeat within it.
+fn page_texels(level: &[u8], size: u32, x: u32, y: u32) -> Vec<u8> {
+    let mut texels = Vec::with_capacity(SLOT_BYTES as usize);
+    for row in 0..SLOT_SIZE {
+        let v = (y * PAGE_SIZE + row + size - PAGE_BORDER % size) % size;
+        for column in 0..SLOT_SIZE {
+            let u = (x * PAGE_SIZE + column + size - PAGE_BORDER % size) % size;
+            let offset = ((v * size + u) * 4) as usize;
+            texels.extend_from_slice(&level[offset..offset + 4]);
+        }
+    }
+    texels
+}
+
+/// Density grid of the fog uploaded to the GPU, sampled with the sampler of the
+/// [`TextureCache`].
+pub(crate) struct DensityTexture {
+    _texture: Texture,
+    pub view: TextureView,
+}
+
+impl DensityTexture {
+    /// Loads the densities of `grid`, or a single full cell without one since bindings can't be
+    /// empty.
+    pub fn load(
+        device: &Device,
+        queue: &Queue,
+        grid: Option<&DensityGrid>,
+    ) -> Result<Self, SceneError> {
+        let (size, cells) = match grid {
+            Some(grid) => {
+                let _span =
+                    tracing::info_span!("load_density_grid", path = %grid.path.display()).entered();
+                let max_size = device.limits().max_texture_dimension_3d;
+                if grid.resolution.iter().any(|&n| n == 0 || n > max_size) {
+                    return Err(SceneError::Texture {
+                        path: grid.path.clone(),
+                        message: format!(
+                            "The resolution has to be between 1 and {max_size} along every axis"
+                        ),
+                    });
+                }
+                (grid.resolution, read_densities(grid)?)
+            }
+            None => ([1; 3], vec![255]),
+        };
+
+        let [width, height, depth] = size;
+        let extent = Extent3d {
+            width,
+            height,
+            depth_or_array_layers: depth,
+        };
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("Fog density texture"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D3,
+            format: TextureFormat::R8Unorm,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        });
+        queue.write_texture(
+            texture.as_image_copy(),
+            &cells,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(width),
+                rows_per_image: NonZeroU32::new(height),
+            },
+            extent,
+        );
+
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        Ok(Self {
+            _texture: texture,
+            view,
+        })
+    }
+}
+
+/// Profiles of the point lights of a scene, baked into the layers of a texture array of their
+/// intensities relative to the brightest direction, read without a sampler since they're
+/// 32 bit floats.
+pub(crate) struct LightProfileArray {
+    _texture: Texture,
+    pub view: TextureView,
+}
+
+impl LightProfileArray {
+    /// Loads the IES files at `paths` into consecutive layers, or a single unused layer without
+    /// any since bindings can't be empty.
+    pub fn load(device: &Device, queue: &Queue, paths: &[PathBuf]) -> Result<Self, SceneError> {
+        let _span = tracing::info_span!("load_light_profiles", profiles = paths.len()).entered();
+
+        let max_layers = device.limits().max_texture_array_layers;
+        if paths.len() > max_layers as usize {
+            return Err(SceneError::Texture {
+                path: paths[max_layers as usize].clone(),
+                message: format!("The device supports at most {max_layers} light profiles"),
+            });
+        }
+
+        let layers = paths.len().max(1) as u32;
+        let extent = Extent3d {
+            width: PROFILE_WIDTH,
+            height: PROFILE_HEIGHT,
+            depth_or_array_layers: layers,
+        };
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("Light profile texture array"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::R32Float,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        });
+
+        for (layer, path) in paths.iter().enumerate() {
+            let table = read_profile(path)?.bake(PROFILE_WIDTH, PROFILE_HEIGHT);
+            let bytes: Vec<u8> = table.iter().flat_map(|value| value.to_le_bytes()).collect();
+            queue.write_texture(
+                ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                    aspect: TextureAspect::All,
+                },
+                &bytes,
+                ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(PROFILE_WIDTH * 4),
+                    rows_per_image: None,
+                },
+                Extent3d {
+                    depth_or_array_layers: 1,
+                    ..extent
+                },
+            );
+        }
+
+        let view = texture.create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        Ok(Self {
+            _texture: texture,
+            view,
+        })
+    }
+}
+
+/// Heights of the pixels of a grayscale image from 0 to 1, rows from the top down.
+pub(crate) struct Heightmap {
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub heights: Vec<f32>,
+}
+
+impl Heightmap {
+    #[cfg(feature = "native")]
+    pub fn read(path: &Path) -> Result<Self, SceneError> {
+        let _span = tracing::info_span!("load_heightmap", path = %path.display()).entered();
+        let image = image::open(path).map_err(|err| SceneError::Texture {
+            path: path.to_owned(),
+            message: err.to_string(),
+        })?;
+        // 16 bit images keep their precision, terrains are often stored as those.
+        let image = image.into_luma16();
+        if image.width() < 2 || image.height() < 2 {
+            return Err(SceneError::Texture {
+                path: path.to_owned(),
+                message: "Heightmaps have to be at least 2 pixels wide and high".to_owned(),
+            });
+        }
+        Ok(Self {
+            path: path.to_owned(),
+            width: image.width(),
+            height: image.height(),
+            heights: image
+                .pixels()
+                .map(|pixel| pixel.0[0] as f32 / 65535.0)
+                .collect(),
+        })
+    }
+
+    #[cfg(not(feature = "native"))]
+    pub fn read(path: &Path) -> Result<Self, SceneError> {
+        Err(SceneError::Texture {
+            path: path.to_owned(),
+            message: "Heightmaps can only be loaded with the native feature".to_owned(),
+        })
+    }
+
+    /// Height of the pixel in column `x` and row `y`.
+    pub fn at(&self, x: u32, y: u32) -> f32 {
+        self.heights[(y * self.width + x) as usize]
+    }
+}
+
+/// Heights of the terrains of a scene, each in the top left corner of a layer of a texture array
+/// as big as the biggest of them, read without a sampler since they're 32 bit floats.
+pub(crate) struct HeightfieldArray {
+    _texture: Texture,
+    pub view: TextureView,
+}
+
+impl HeightfieldArray {
+    /// Uploads `heightmaps` into consecutive layers, or a single unused layer without any since
+    /// bindings can't be empty.
+    pub fn load(
+        device: &Device,
+        queue: &Queue,
+        heightmaps: &[Heightmap],
+    ) -> Result<Self, SceneError> {
+        let limits = device.limits();
+        if let Some(heightmap) = heightmaps.get(limits.max_texture_array_layers as usize) {
+            return Err(SceneError::Texture {
+                path: heightmap.path.clone(),
+                message: format!(
+                    "The device supports at most {} heightfields",
+                    limits.max_texture_array_layers
+                ),
+            });
+        }
+        let max_size = limits.max_texture_dimension_2d;
+        if let Some(heightmap) = heightmaps
+            .iter()
+            .find(|heightmap| heightmap.width.max(heightmap.height) > max_size)
+        {
+            return Err(SceneError::Texture {
+                path: heightmap.path.clone(),
+                message: format!("The device supports heightmaps of at most {max_size} pixels"),
+            });
+        }
+
+        let extent = Extent3d {
+            width: heightmaps.iter().map(|h| h.width).max().unwrap_or(1),
+            height: heightmaps.iter().map(|h| h.height).max().unwrap_or(1),
+            depth_or_array_layers: heightmaps.len().max(1) as u32,
+        };
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("Heightfield texture array"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::R32Float,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        });
+
+        for (layer, heightmap) in heightmaps.iter().enumerate() {
+            let bytes: Vec<u8> = heightmap
+                .heights
+                .iter()
+                .flat_map(|height| height.to_le_bytes())
+                .collect();
+            queue.write_texture(
+                ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                    aspect: TextureAspect::All,
+                },
+                &bytes,
+                ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(heightmap.width * 4),
+                    rows_per_image: None,
+                },
+                Extent3d {
+                    width: heightmap.width,
+                    height: heightmap.height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        let view = texture.create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        Ok(Self {
+            _texture: texture,
+            view,
+        })
+    }
+}
+
+#[cfg(feature = "fs")]
+fn read_profile(path: &Path) -> Result<IesProfile, SceneError> {
+    let error = |message: String| SceneError::Texture {
+        path: path.to_owned(),
+        message,
+    };
+    // Older files are often Latin-1 rather than UTF-8, only the keywords would be garbled.
+    let bytes = std::fs::read(path).map_err(|err| error(err.to_string()))?;
+    IesProfile::parse(&String::from_utf8_lossy(&bytes)).map_err(|err| error(err.to_string()))
+}
+
+#[cfg(not(feature = "fs"))]
+fn read_profile(path: &Path) -> Result<IesProfile, SceneError> {
+    Err(SceneError::Texture {
+        path: path.to_owned(),
+        message: "Light profiles can only be loaded with the fs feature".to_owned(),
+    })
+}
+
+/// The densities of `grid` as normalized bytes.
+#[cfg(feature = "fs")]
+fn read_densities(grid: &DensityGrid) -> Result<Vec<u8>, SceneError> {
+    let error = |message: String| SceneError::Texture {
+        path: grid.path.clone(),
+        message,
+    };
+    let bytes = std::fs::read(&grid.path).map_err(|err| error(err.to_string()))?;
+    let [x, y, z] = grid.resolution.map(|n| n as usize);
+    if bytes.len() != x * y * z * 4 {
+        return Err(error(format!(
+            "Expected {} bytes for {x}x{y}x{z} cells, found {}",
+            x * y * z * 4,
+            bytes.len()
+        )));
+    }
+
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|cell| {
+            let density = f32::from_le_bytes([cell[0], cell[1], cell[2], cell[3]]);
+            (density.clamp(0.0, 1.0) * 255.0).round() as u8
+        })
+        .collect())
+}
+
+#[cfg(not(feature = "fs"))]
+fn read_densities(grid: &DensityGrid) -> Result<Vec<u8>, SceneError> {
+    Err(SceneError::Texture {
+        path: grid.path.clone(),
+        message: "Density grids can only be loaded with the fs feature".to_owned(),
+    })
+}
+
+/// Decoded RGBA8 image.
+struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    /// The pixels scaled to a `size` by `size` square.
+    fn resized(&self, size: u32) -> Vec<u8> {
+        if self.width == size && self.height == size {
+            return self.pixels.clone();
+        }
+
+        // Nearest neighbour, textures are filtered when sampled anyway.
+        let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+        for y in 0..size {
+            let y = (y as u64 * self.height as u64 / size as u64) as usize;
+            for x in 0..size {
+                let x = (x as u64 * self.width as u64 / size as u64) as usize;
+                let offset = (y * self.width as usize + x) * 4;
+                pixels.extend_from_slice(&self.pixels[offset..offset + 4]);
+            }
+        }
+        pixels
+    }
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> u8 {
+    let c = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+/// Averages every two by two block of `pixels` of a `size` by `size` square encoded in `space`,
+/// making the next mip level. sRGB colors are averaged in linear space and normals renormalized.
+fn downsample(pixels: &[u8], size: u32, space: ColorSpace) -> Vec<u8> {
+    let size = size as usize;
+    let half = size / 2;
+    let mut mip = Vec::with_capacity(half * half * 4);
+    for y in 0..half {
+        for x in 0..half {
+            let texel = |dx, dy, channel| pixels[((2 * y + dy) * size + 2 * x + dx) * 4 + channel];
+            let mut mean = [0.0; 4];
+            for (channel, mean) in mean.iter_mut().enumerate() {
+                let texels =
+                    [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(dx, dy)| texel(dx, dy, channel));
+                // Alpha is linear already.
+                *mean = if channel < 3 && space == ColorSpace::Srgb {
+                    texels.map(srgb_to_linear).iter().sum::<f32>() / 4.0
+                } else {
+                    texels.iter().map(|&c| c as f32 / 255.0).sum::<f32>() / 4.0
+                };
+            }
+            if space == ColorSpace::NormalMap {
+                let normal = [0, 1, 2].map(|channel| mean[channel] * 2.0 - 1.0);
+                let length = normal.iter().map(|c| c * c).sum::<f32>().sqrt();
+                if length > 0.0 {
+                    for channel in 0..3 {
+                        mean[channel] = normal[channel] / length * 0.5 + 0.5;
+                    }
+                }
+            }
+            for (channel, mean) in mean.into_iter().enumerate() {
+                mip.push(if channel < 3 && space == ColorSpace::Srgb {
+                    linear_to_srgb(mean)
+                } else {
+                    (mean * 255.0).round() as u8
+                });
+            }
+        }
+    }
+    mip
+}
+
+#[cfg(feature = "native")]
+fn decode(path: &Path) -> Result<Image, SceneError> {
+    let image = image::open(path).map_err(|err| SceneError::Texture {
+        path: path.to_owned(),
+        message: err.to_string(),
+    })?;
+    let image = image.into_rgba8();
+    Ok(Image {
+        width: image.width(),
+        height: image.height(),
+        pixels: image.into_raw(),
+    })
+}
+
+#[cfg(not(feature = "native"))]
+fn decode(path: &Path) -> Result<Image, SceneError> {
+    Err(SceneError::Texture {
+        path: path.to_owned(),
+        message: "Textures can only be loaded with the native feature".to_owned(),
+    })
+}
@@ -0,0 +1,112 @@
+/// Rectangular region of the output image, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TileRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TileRect {
+    pub fn pixel_count(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    pub(crate) fn extent(&self) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: 1,
+        }
+    }
+}
+
+/// Rendered region of the output image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub rect: TileRect,
+    /// Tightly packed RGBA8 rows of the tile.
+    pub data: Vec<u8>,
+}
+
+impl Tile {
+    /// Copies the tile into its place in `image`, an RGBA8 image `image_width` pixels wide.
+    pub fn copy_into(&self, image: &mut [u8], image_width: u32) {
+        let row_bytes = self.rect.width as usize * 4;
+        for (row, src) in self.data.chunks_exact(row_bytes).enumerate() {
+            let offset =
+                ((self.rect.y as usize + row) * image_width as usize + self.rect.x as usize) * 4;
+            image[offset..offset + row_bytes].copy_from_slice(src);
+        }
+    }
+}
+
+/// Splits a `width`×`height` image into row-major tiles of at most `tile_size`×`tile_size` pixels.
+pub fn tiles(width: u32, height: u32, tile_size: u32) -> impl Iterator<Item = TileRect> {
+    assert!(tile_size > 0, "Tile size must be greater than zero");
+
+    (0..height).step_by(tile_size as usize).flat_map(move |y| {
+        (0..width)
+            .step_by(tile_size as usize)
+            .map(move |x| TileRect {
+                x,
+                y,
+                width: tile_size.min(width - x),
+                height: tile_size.min(height - y),
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_cover_every_pixel_once() {
+        for (width, height, tile_size) in [(1, 1, 1), (7, 5, 2), (64, 64, 16), (100, 30, 64)] {
+            let mut covered = vec![0; (width * height) as usize];
+            for rect in tiles(width, height, tile_size) {
+                assert!(rect.width > 0 && rect.width <= tile_size);
+                assert!(rect.height > 0 && rect.height <= tile_size);
+                for y in rect.y..rect.y + rect.height {
+                    for x in rect.x..rect.x + rect.width {
+                        covered[(y * width + x) as usize] += 1;
+                    }
+                }
+            }
+            assert!(
+                covered.iter().all(|&count| count == 1),
+                "{width}x{height} in tiles of {tile_size}"
+            );
+        }
+    }
+
+    #[test]
+    fn tiles_are_row_major() {
+        let origins: Vec<_> = tiles(5, 3, 2).map(|rect| (rect.x, rect.y)).collect();
+        assert_eq!(origins, [(0, 0), (2, 0), (4, 0), (0, 2), (2, 2), (4, 2)]);
+    }
+
+    #[test]
+    fn empty_images_have_no_tiles() {
+        assert_eq!(tiles(0, 16, 8).count(), 0);
+        assert_eq!(tiles(16, 0, 8).count(), 0);
+    }
+
+    #[test]
+    fn tiles_copy_into_their_place() {
+        let tile = Tile {
+            rect: TileRect {
+                x: 1,
+                y: 1,
+                width: 2,
+                height: 1,
+            },
+            data: vec![1, 2, 3, 4, 5, 6, 7, 8],
+        };
+        let mut image = vec![0; 3 * 2 * 4];
+        tile.copy_into(&mut image, 3);
+        assert_eq!(&image[..16], &[0; 16]);
+        assert_eq!(&image[16..], &[1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+}
@@ -0,0 +1,151 @@
+//! Finding the workgroup size an adapter renders fastest with, as it differs between vendors.
+
+use std::collections::BTreeMap;
+#[cfg(feature = "fs")]
+use std::{fs, io, path::Path};
+
+use instant::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use wgpu::AdapterInfo;
+
+use crate::{renderer::RaytracingRenderer, settings::RenderSettings, shader::ShaderError};
+
+/// Workgroup sides tried by [`tune_workgroup_size`], those the device can't run are skipped.
+pub const CANDIDATES: [u32; 5] = [4, 8, 12, 16, 32];
+
+/// Render timed for every candidate, small enough to finish in a fraction of a second.
+fn benchmark_settings() -> RenderSettings {
+    RenderSettings {
+        width: 256,
+        height: 256,
+        samples_per_pixel: 16,
+        tile_size: 256,
+        seed: 0,
+        max_bounces: 4,
+        ..Default::default()
+    }
+}
+
+/// Renders a small image of the current scene with every [candidate](CANDIDATES) workgroup
+/// size and keeps the fastest, which is returned.
+///
+/// Every size is rendered once to warm up and then timed twice, taking the faster run.
+pub async fn tune_workgroup_size(renderer: &mut RaytracingRenderer) -> Result<u32, ShaderError> {
+    let limits = renderer.device().limits();
+    let settings = benchmark_settings();
+
+    let mut fastest: Option<(u32, Duration)> = None;
+    for size in CANDIDATES {
+        if size
+            > limits
+                .max_compute_workgroup_size_x
+                .min(limits.max_compute_workgroup_size_y)
+            || size * size > limits.max_compute_invocations_per_workgroup
+        {
+            continue;
+        }
+        renderer.set_workgroup_size(size).await?;
+
+        renderer.render(&settings).await;
+        let mut best = Duration::MAX;
+        for _ in 0..2 {
+            let start = Instant::now();
+            renderer.render(&settings).await;
+            best = best.min(start.elapsed());
+        }
+        tracing::debug!(size, time = ?best, "Timed workgroup size");
+
+        if fastest.is_none_or(|(_, time)| best < time) {
+            fastest = Some((size, best));
+        }
+    }
+
+    let (size, _) = fastest.expect("Devices support at least 4x4 workgroups");
+    renderer.set_workgroup_size(size).await?;
+    tracing::info!(size, "Picked workgroup size");
+    Ok(size)
+}
+
+/// Workgroup sizes found by [`tune_workgroup_size`], by adapter, so that tuning only runs once
+/// per device.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkgroupSizeCache {
+    sizes: BTreeMap<String, u32>,
+}
+
+impl WorkgroupSizeCache {
+    pub fn get(&self, adapter: &AdapterInfo) -> Option<u32> {
+        self.sizes.get(&adapter_key(adapter)).copied()
+    }
+
+    pub fn insert(&mut self, adapter: &AdapterInfo, size: u32) {
+        self.sizes.insert(adapter_key(adapter), size);
+    }
+
+    /// Reads a cache saved by [`save`](Self::save), an empty one if `path` doesn't exist.
+    #[cfg(feature = "fs")]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ShaderError> {
+        let path = path.as_ref();
+        let io_error = |source| ShaderError::Io {
+            path: path.to_owned(),
+            source,
+        };
+
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|err| io_error(io::Error::new(io::ErrorKind::InvalidData, err))),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(io_error(err)),
+        }
+    }
+
+    /// Writes the cache as JSON, creating the directories leading to `path`.
+    #[cfg(feature = "fs")]
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ShaderError> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self).expect("Cache serializes");
+        path.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(path, json))
+            .map_err(|source| ShaderError::Io {
+                path: path.to_owned(),
+                source,
+            })
+    }
+}
+
+/// Uses the workgroup size cached at `path` for the adapter of `renderer`, tuning and caching
+/// one first if there's none.
+#[cfg(feature = "fs")]
+pub async fn tune_workgroup_size_cached(
+    renderer: &mut RaytracingRenderer,
+    path: impl AsRef<Path>,
+) -> Result<u32, ShaderError> {
+    let path = path.as_ref();
+    let adapter = renderer.adapter_info();
+    let mut cache = WorkgroupSizeCache::load(path)?;
+
+    if let Some(size) = cache.get(&adapter) {
+        renderer.set_workgroup_size(size).await?;
+        return Ok(size);
+    }
+
+    let size = tune_workgroup_size(renderer).await?;
+    cache.insert(&adapter, size);
+    cache.save(path)?;
+    Ok(size)
+}
+
+/// Identifies an adapter together with the driver running it, as updates can change what's
+/// fastest.
+fn adapter_key(adapter: &AdapterInfo) -> String {
+    format!(
+        "{} ({:04x}:{:04x}, {:?}, {} {})",
+        adapter.name,
+        adapter.vendor,
+        adapter.device,
+        adapter.backend,
+        adapter.driver,
+        adapter.driver_info
+    )
+}
@@ -0,0 +1,111 @@
+//! Video files encoded by piping frames into [ffmpeg](https://ffmpeg.org), which has to be on
+//! the `PATH`.
+
+use std::{
+    ffi::OsStr,
+    io::{self, Write},
+    path::Path,
+    process::{Child, ChildStdin, Command, Stdio},
+};
+
+/// Extensions of the containers [`VideoEncoder`] picks a codec for.
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mkv", "mov", "webm"];
+
+/// Whether `path` names a video file [`VideoEncoder`] can write, judging by its extension.
+pub fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|extension| {
+            VIDEO_EXTENSIONS
+                .iter()
+                .any(|video| extension.eq_ignore_ascii_case(video))
+        })
+}
+
+/// Encodes tightly packed RGBA8 frames into a video file.
+///
+/// The codec follows from the extension of the file: ProRes 422 HQ for `.mov`, VP9 for `.webm`
+/// and H.264 for anything else.
+pub struct VideoEncoder {
+    ffmpeg: Child,
+    stdin: Option<ChildStdin>,
+    frame_bytes: usize,
+}
+
+impl VideoEncoder {
+    /// Starts ffmpeg writing to `path`, replacing it if it exists.
+    pub fn new(path: impl AsRef<Path>, width: u32, height: u32, fps: f32) -> io::Result<Self> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        let codec: &[&str] = match extension.as_str() {
+            "mov" => &[
+                "-c:v",
+                "prores_ks",
+                "-profile:v",
+                "3",
+                "-pix_fmt",
+                "yuv422p10le",
+            ],
+            "webm" => &["-c:v", "libvpx-vp9", "-pix_fmt", "yuv420p"],
+            _ => &["-c:v", "libx264", "-pix_fmt", "yuv420p", "-crf", "18"],
+        };
+
+        let mut ffmpeg = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error", "-f", "rawvideo"])
+            .args(["-pixel_format", "rgba"])
+            .arg("-video_size")
+            .arg(format!("{width}x{height}"))
+            .arg("-framerate")
+            .arg(fps.to_string())
+            .args(["-i", "-"])
+            .args(codec)
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|err| io::Error::new(err.kind(), format!("Could not start ffmpeg: {err}")))?;
+
+        let stdin = ffmpeg.stdin.take();
+        Ok(Self {
+            ffmpeg,
+            stdin,
+            frame_bytes: width as usize * height as usize * 4,
+        })
+    }
+
+    /// Appends a frame to the video.
+    ///
+    /// # Panics
+    ///
+    /// If `pixels` doesn't match the size the encoder was created with.
+    pub fn write_frame(&mut self, pixels: &[u8]) -> io::Result<()> {
+        assert_eq!(pixels.len(), self.frame_bytes, "Frame size mismatch");
+        self.stdin
+            .as_mut()
+            .expect("Stdin is only taken by finish")
+            .write_all(pixels)
+    }
+
+    /// Waits for ffmpeg to write out the rest of the video.
+    pub fn finish(mut self) -> io::Result<()> {
+        // Closing the pipe ends the input.
+        drop(self.stdin.take());
+        let status = self.ffmpeg.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("ffmpeg failed with {status}")));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for VideoEncoder {
+    fn drop(&mut self) {
+        if self.stdin.take().is_some() {
+            let _ = self.ffmpeg.wait();
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Rendering in the browser through WebGPU.
+//!
+//! Build with `--target wasm32-unknown-unknown --no-default-features`. `web-sys` only binds
+//! WebGPU when compiled with `--cfg=web_sys_unstable_apis`, which `.cargo/config.toml` sets
+//! for this target. With the bindings generated by `wasm-bindgen`:
+//!
+//! ```js
+//! const renderer = await CanvasRenderer.create(document.querySelector("canvas"));
+//! renderer.setScene(sceneJson);
+//! renderer.render();
+//! ```
+//!
+//! `renderer.setRenderScale(0.5)` path traces a quarter of the pixels of the canvas and
+//! upscales them to it, to keep navigating responsive on slower GPUs.
+//!
+//! `renderer.setMaterial(materialJson)` changes the parameters of the material of the scene
+//! with the same name in place, e.g. from the sliders of an editor.
+
+use wasm_bindgen::prelude::*;
+use web_sys::HtmlCanvasElement;
+use wgpu::{
+    Backends, BufferDescriptor, BufferUsages, Instance, PowerPreference, RequestAdapterOptions,
+    Surface,
+};
+
+use crate::{
+    materials::MaterialLibrary,
+    present::Presenter,
+    renderer::{buffer_bytes_per_row, RaytracingRenderer},
+    scene::{Material, Scene, SceneFormat},
+    settings::RenderSettings,
+};
+
+/// Renderer drawing onto a `<canvas>`.
+#[wasm_bindgen]
+pub struct CanvasRenderer {
+    renderer: RaytracingRenderer,
+    presenter: Presenter,
+    surface: Surface,
+    canvas: HtmlCanvasElement,
+    settings: RenderSettings,
+    render_scale: f32,
+    /// Materials of the scene as they were last set.
+    materials: MaterialLibrary,
+}
+
+#[wasm_bindgen]
+impl CanvasRenderer {
+    /// Creates a renderer drawing onto `canvas`, showing the demo scene until
+    /// [`set_scene`](Self::set_scene) replaces it.
+    pub async fn create(canvas: HtmlCanvasElement) -> Result<CanvasRenderer, JsValue> {
+        console_error_panic_hook::set_once();
+
+        let instance = Instance::new(Backends::BROWSER_WEBGPU);
+        let surface = instance.create_surface_from_canvas(&canvas);
+        let adapter = instance
+            .request_adapter(&RequestAdapterOptions {
+                power_preference: PowerPreference::HighPerformance,
+                compatible_surface: Some(&surface),
+                force_fallback_adapter: false,
+            })
+            .await
+            .ok_or("No WebGPU adapter found")?;
+
+        let renderer = RaytracingRenderer::from_adapter(adapter).await;
+        let format = Presenter::preferred_format(&renderer, &surface)
+            .ok_or("The canvas can't be drawn onto with this adapter")?;
+        let presenter = Presenter::new(&renderer, format);
+
+        Ok(Self {
+            renderer,
+            presenter,
+            surface,
+            canvas,
+            settings: RenderSettings::default(),
+            render_scale: 1.0,
+            materials: MaterialLibrary::new(),
+        })
+    }
+
+    /// Replaces the scene with one in the JSON [scene format](crate::scene).
+    ///
+    /// Meshes have to be inlined as triangles, there are no files to load them from. The render
+    /// settings of the scene apply to the following renders, except for the size, which always
+    /// follows the canvas.
+    #[wasm_bindgen(js_name = setScene)]
+    pub fn set_scene(&mut self, json: &str) -> Result<(), JsValue> {
+        let scene = Scene::parse(json, SceneFormat::Json).map_err(to_js)?;
+        self.renderer.set_scene(&scene).map_err(to_js)?;
+        self.materials = MaterialLibrary::from_scene(&scene);
+        self.settings = scene.settings;
+        Ok(())
+    }
+
+    /// Replaces the material of the scene with the same name as a material in the JSON scene
+    /// format in place, without setting the scene again. It can't refer to textures the scene
+    /// doesn't have or be displaced differently.
+    #[wasm_bindgen(js_name = setMaterial)]
+    pub fn set_material(&mut self, json: &str) -> Result<(), JsValue> {
+        let material: Material = serde_json::from_str(json).map_err(to_js)?;
+        let name = material.name.clone();
+        let previous = self.materials.register(material);
+        self.renderer
+            .update_materials(&self.materials)
+            .map_err(|error| {
+                // Keep the library matching what's rendered.
+                match previous {
+                    Some(previous) => self.materials.register(previous),
+                    None => self.materials.remove(&name),
+                };
+                to_js(error)
+            })
+    }
+
+    /// Sets the size renders are made at relative to the canvas, from 0 to 1. Smaller renders
+    /// are upscaled to the canvas.
+    #[wasm_bindgen(js_name = setRenderScale)]
+    pub fn set_render_scale(&mut self, scale: f32) {
+        self.render_scale = scale.clamp(0.01, 1.0);
+    }
+
+    /// Sets the sharpening of upscaled renders, in stops below the strongest.
+    #[wasm_bindgen(js_name = setSharpness)]
+    pub fn set_sharpness(&mut self, stops: f32) {
+        self.presenter.set_sharpness(stops);
+    }
+
+    /// Renders the scene at the current size of the canvas times the render scale and draws
+    /// it.
+    ///
+    /// The work is only submitted, the browser shows the image once the GPU is done with it.
+    pub fn render(&mut self) -> Result<(), JsValue> {
+        let scaled = |size: u32| ((size as f32 * self.render_scale).ceil() as u32).max(1);
+        let settings = RenderSettings {
+            width: scaled(self.canvas.width()),
+            height: scaled(self.canvas.height()),
+            ..self.settings.clone()
+        };
+
+        let buffer = self.renderer.device().create_buffer(&BufferDescriptor {
+            label: Some("Canvas image buffer"),
+            size: buffer_bytes_per_row(settings.width) as u64 * settings.height as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        self.renderer.render_into_buffer(&settings, &buffer);
+
+        self.presenter.configure(
+            &self.renderer,
+            &self.surface,
+            self.canvas.width(),
+            self.canvas.height(),
+        );
+        self.presenter
+            .present(
+                &self.renderer,
+                &self.surface,
+                &buffer,
+                settings.width,
+                settings.height,
+            )
+            .map_err(to_js)
+    }
+}
+
+fn to_js(err: impl std::fmt::Display) -> JsValue {
+    JsValue::from_str(&err.to_string())
+}